@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+/// How long the pointer has to stay put before it clicks, unless --dwell says otherwise
+pub const DEFAULT_DWELL: Duration = Duration::from_millis(1200);
+/// How far a destination's hit area reaches past its square on each side at most, in squares
+pub const MAX_EXPANSION: f32 = 0.5;
+/// How far a destination's hit area reaches unless --target-expansion says otherwise
+pub const DEFAULT_EXPANSION: f32 = 0.3;
+/// How far past their edges buttons still take a click, in pixels
+pub const CLICK_SLOP: f32 = 12.0;
+/// How far the pointer may wobble and still be dwelling on the same spot, in pixels
+pub const WOBBLE_RADIUS: f32 = 8.0;
+// After a dwell click the pointer has to leave the spot, and this long has to pass,
+// before it can dwell again, so resting on a piece doesn't select and deselect it forever
+const COOLDOWN: Duration = Duration::from_millis(500);
+
+/// Input for players who struggle with precise clicking, all off without --accessible-input
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct AccessibleInput {
+    // How far the legal destinations of the selected piece take clicks past their squares
+    pub expansion: f32,
+    // How long hovering takes to click, None for no dwell clicking
+    pub dwell: Option<Duration>,
+}
+
+impl Default for AccessibleInput {
+    fn default() -> Self {
+        Self {
+            expansion: DEFAULT_EXPANSION,
+            dwell: Some(DEFAULT_DWELL),
+        }
+    }
+}
+
+/// The destination a click at `point` goes to, with each destination's square grown by
+/// `expansion` squares on every side. Where the grown squares overlap the nearest centre
+/// wins, and an exact tie goes to the first square from the top left.
+/// `point` and the destinations are as drawn, in squares from the board's top left corner.
+pub fn nearest_destination(
+    point: (f32, f32),
+    destinations: &[(usize, usize)],
+    expansion: f32,
+) -> Option<(usize, usize)> {
+    let reach = 0.5 + expansion.clamp(0.0, MAX_EXPANSION);
+    let (x, y) = point;
+    destinations
+        .iter()
+        .copied()
+        .map(|(row, col)| {
+            let (dx, dy) = (x - (col as f32 + 0.5), y - (row as f32 + 0.5));
+            ((row, col), dx, dy)
+        })
+        .filter(|(_, dx, dy)| dx.abs() <= reach && dy.abs() <= reach)
+        .min_by(|(a, ax, ay), (b, bx, by)| {
+            (ax * ax + ay * ay)
+                .total_cmp(&(bx * bx + by * by))
+                .then(a.cmp(b))
+        })
+        .map(|(square, _, _)| square)
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum Phase {
+    Idle,
+    // The pointer came to rest here
+    Dwelling { anchor: (f32, f32), since: Duration },
+    // Clicked here, waiting for the pointer to leave and the cooldown to pass
+    Clicked { anchor: (f32, f32), at: Duration },
+}
+
+/// Clicks where the pointer rests long enough, so nothing needs a button press
+pub struct Dwell {
+    delay: Duration,
+    phase: Phase,
+}
+
+#[inline]
+fn near(a: (f32, f32), b: (f32, f32)) -> bool {
+    (a.0 - b.0).hypot(a.1 - b.1) <= WOBBLE_RADIUS
+}
+
+impl Dwell {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            phase: Phase::Idle,
+        }
+    }
+
+    /// Follow the pointer at `now`, the spot to click once it rested there long enough.
+    /// `pointer` is None while dwelling is suppressed, which starts the wait over but never
+    /// lets the last click's spot click again.
+    pub fn update(&mut self, now: Duration, pointer: Option<(f32, f32)>) -> Option<(f32, f32)> {
+        let Some(pointer) = pointer else {
+            if let Phase::Dwelling { .. } = self.phase {
+                self.phase = Phase::Idle;
+            }
+            return None;
+        };
+        match self.phase {
+            Phase::Clicked { anchor, at } if near(anchor, pointer) || now < at + COOLDOWN => None,
+            Phase::Dwelling { anchor, since } if near(anchor, pointer) => {
+                if now.saturating_sub(since) < self.delay {
+                    return None;
+                }
+                self.phase = Phase::Clicked { anchor, at: now };
+                Some(anchor)
+            }
+            _ => {
+                self.phase = Phase::Dwelling {
+                    anchor: pointer,
+                    since: now,
+                };
+                None
+            }
+        }
+    }
+
+    /// Where the pointer is resting and how much of the wait is left, from 1 down to 0
+    pub fn progress(&self, now: Duration) -> Option<((f32, f32), f32)> {
+        match self.phase {
+            Phase::Dwelling { anchor, since } => {
+                let waited = now.saturating_sub(since).as_secs_f32();
+                Some((
+                    anchor,
+                    (1.0 - waited / self.delay.as_secs_f32()).clamp(0.0, 1.0),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn grown_destinations_take_clicks_next_to_them() {
+        let destinations = [(2, 3), (5, 5)];
+        // On the square itself, and a bit past its edge only once it is grown
+        assert_eq!(
+            nearest_destination((3.5, 2.5), &destinations, 0.0),
+            Some((2, 3))
+        );
+        assert_eq!(nearest_destination((3.5, 3.2), &destinations, 0.0), None);
+        assert_eq!(
+            nearest_destination((3.5, 3.2), &destinations, 0.3),
+            Some((2, 3))
+        );
+        assert_eq!(nearest_destination((3.5, 3.9), &destinations, 0.3), None);
+        // Never more than half a square
+        assert_eq!(nearest_destination((3.5, 3.9), &destinations, 5.0), None);
+    }
+
+    #[test]
+    fn overlapping_destinations_go_to_the_nearest() {
+        // Two squares apart on the same rank, both grown by half a square meet at the middle
+        let destinations = [(4, 4), (4, 2)];
+        assert_eq!(
+            nearest_destination((3.4, 4.5), &destinations, 0.5),
+            Some((4, 2))
+        );
+        assert_eq!(
+            nearest_destination((3.6, 4.5), &destinations, 0.5),
+            Some((4, 4))
+        );
+        // Right in the middle is a tie, the square further left wins whatever the order
+        assert_eq!(
+            nearest_destination((3.5, 4.5), &destinations, 0.5),
+            Some((4, 2))
+        );
+        assert_eq!(
+            nearest_destination((3.5, 4.5), &[(4, 2), (4, 4)], 0.5),
+            Some((4, 2))
+        );
+    }
+
+    #[test]
+    fn resting_clicks_once_and_wobbling_is_tolerated() {
+        let mut dwell = Dwell::new(DEFAULT_DWELL);
+        let start = Duration::from_secs(10);
+        assert_eq!(dwell.update(start, Some((100.0, 100.0))), None);
+        // Just inside the radius it is still the same spot
+        assert_eq!(
+            dwell.update(start + 600 * MS, Some((100.0 + WOBBLE_RADIUS - 0.1, 100.0))),
+            None
+        );
+        let (anchor, left) = dwell.progress(start + 600 * MS).unwrap();
+        assert_eq!(anchor, (100.0, 100.0));
+        assert!((left - 0.5).abs() < 1e-3);
+        assert_eq!(
+            dwell.update(start + 1200 * MS, Some((101.0, 101.0))),
+            Some((100.0, 100.0))
+        );
+        // Staying put doesn't click again, not even after the cooldown
+        assert_eq!(dwell.update(start + 5000 * MS, Some((100.0, 100.0))), None);
+        assert_eq!(dwell.progress(start + 5000 * MS), None);
+    }
+
+    #[test]
+    fn wobbling_too_far_starts_over() {
+        let mut dwell = Dwell::new(DEFAULT_DWELL);
+        dwell.update(Duration::ZERO, Some((100.0, 100.0)));
+        // Just outside the radius is another spot, waited for from the start
+        dwell.update(1000 * MS, Some((100.0, 100.0 + WOBBLE_RADIUS + 0.1)));
+        assert_eq!(
+            dwell.update(1300 * MS, Some((100.0, 100.0 + WOBBLE_RADIUS + 0.1))),
+            None
+        );
+        assert_eq!(
+            dwell.update(2200 * MS, Some((100.0, 100.0 + WOBBLE_RADIUS + 0.1))),
+            Some((100.0, 100.0 + WOBBLE_RADIUS + 0.1))
+        );
+    }
+
+    #[test]
+    fn a_click_cools_down_and_suppression_resets_the_wait() {
+        let mut dwell = Dwell::new(DEFAULT_DWELL);
+        dwell.update(Duration::ZERO, Some((10.0, 10.0)));
+        assert!(dwell.update(1200 * MS, Some((10.0, 10.0))).is_some());
+        // Moving on right away waits out the cooldown first
+        assert_eq!(dwell.update(1300 * MS, Some((50.0, 50.0))), None);
+        assert_eq!(dwell.update(1600 * MS, Some((50.0, 50.0))), None);
+        dwell.update(1800 * MS, Some((50.0, 50.0)));
+        assert_eq!(dwell.update(2900 * MS, Some((50.0, 50.0))), None);
+        assert!(dwell.update(3000 * MS, Some((50.0, 50.0))).is_some());
+
+        // Suppressed halfway, like during a drag, the wait starts over once it ends
+        dwell.update(4000 * MS, Some((90.0, 90.0)));
+        dwell.update(4600 * MS, None);
+        assert_eq!(dwell.update(4700 * MS, Some((90.0, 90.0))), None);
+        assert_eq!(dwell.update(5300 * MS, Some((90.0, 90.0))), None);
+        assert!(dwell.update(5900 * MS, Some((90.0, 90.0))).is_some());
+        // A suppression after a click doesn't let the same spot click again
+        dwell.update(6000 * MS, None);
+        assert_eq!(dwell.update(9000 * MS, Some((90.0, 90.0))), None);
+    }
+}
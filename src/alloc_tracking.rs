@@ -0,0 +1,161 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fmt::Write;
+
+thread_local! {
+    // Kept by each thread for itself, a frame counts what the window's thread allocated and
+    // not what the engine or the network did meanwhile
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static ALLOCATED_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+#[inline]
+fn count(bytes: usize) {
+    // Gone while the thread is torn down, what it frees then isn't a frame's
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+    let _ = ALLOCATED_BYTES.try_with(|allocated| allocated.set(allocated.get() + bytes));
+}
+
+/// Forwards to the system allocator while counting every allocation
+pub(crate) struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Returns the allocation count and byte count of this thread since the last call, and
+/// resets both
+pub(crate) fn take_counts() -> (usize, usize) {
+    (
+        ALLOCATIONS.with(|allocations| allocations.replace(0)),
+        ALLOCATED_BYTES.with(|allocated| allocated.replace(0)),
+    )
+}
+
+/// What the frames allocated since the counts were reset, shown over the board
+#[derive(Default)]
+pub(crate) struct FrameStats {
+    // Allocations and bytes of the last frame
+    last: (usize, usize),
+    peak: usize,
+    total: usize,
+    frames: usize,
+    // Text cache hits and misses of the last frame
+    text_cache: (usize, usize),
+    line: String,
+}
+
+impl FrameStats {
+    /// Called once per frame with the text cache's lookups. Misses are only expected while
+    /// texts change, a steady frame should be all hits and allocate nothing.
+    pub(crate) fn frame(&mut self, text_cache: (usize, usize)) {
+        let last = take_counts();
+        self.peak = self.peak.max(last.0);
+        self.total += last.0;
+        self.frames += 1;
+        if (last, text_cache) == (self.last, self.text_cache) && !self.line.is_empty() {
+            return;
+        }
+        self.last = last;
+        self.text_cache = text_cache;
+        self.line.clear();
+        let _ = write!(
+            self.line,
+            "{} allocations ({} bytes) a frame, at most {}, {} in {} frames, text cache {} hits {} misses, F12 resets",
+            last.0, last.1, self.peak, self.total, self.frames, text_cache.0, text_cache.1
+        );
+        // What the line took to write down isn't the next frame's
+        take_counts();
+    }
+
+    /// Start counting the peak and the total again
+    pub(crate) fn reset(&mut self) {
+        self.peak = 0;
+        self.total = 0;
+        self.frames = 0;
+        self.line.clear();
+    }
+
+    #[inline]
+    pub(crate) fn line(&self) -> &str {
+        &self.line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_gui::layout::{LayoutPlan, Orientation};
+    use chess_gui::parse_fen;
+    use chess_gui::scene::{compose, BoardScene, Command, Overlay, Sink};
+    use chess_gui::text_cache::TextCache;
+    use chess_gui::toasts::Toasts;
+    use std::time::Duration;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    // Draws nothing, like a canvas it only looks at what it is given
+    struct Discard(usize);
+
+    impl Sink for Discard {
+        fn paint(&mut self, _: Command<'_>) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn steady_frames_allocate_nothing() {
+        let squares = parse_fen(START).unwrap();
+        let mut texts: TextCache<usize> = TextCache::default();
+        let mut toasts = Toasts::default();
+        toasts.push("The hook vetoed the move", Duration::from_secs(4));
+        let destinations = [(5, 4), (4, 4)];
+        let mut stats = FrameStats::default();
+
+        let mut frame = |now: Duration, stats: &mut FrameStats| {
+            let plan = LayoutPlan::new(800.0, 600.0, 0.2, 0.0, Orientation::Landscape);
+            toasts.advance(now);
+            let mut scene = BoardScene::new(plan.board, &squares);
+            scene.coordinates = true;
+            scene.waiting = squares[7][4].color();
+            scene.overlay = Overlay::Selection {
+                from: (6, 4),
+                destinations: &destinations,
+            };
+            let mut sink = Discard(0);
+            compose(&scene, &mut sink);
+            for line in ["1. e4 e5", "White to move", toasts.current().unwrap_or("")] {
+                texts.get(line, plan.line_height, || line.len());
+            }
+            assert!(sink.0 > 32);
+            stats.frame(texts.take_counts());
+        };
+        // The first frames lay out the texts and write down what they cost
+        for second in 0..2 {
+            frame(Duration::from_secs(second), &mut stats);
+        }
+        take_counts();
+        for second in 2..10 {
+            frame(Duration::from_millis(second * 16), &mut stats);
+            assert_eq!(stats.last.0, 0, "frame {}: {}", second, stats.line());
+        }
+        assert!(stats.line().starts_with("0 allocations (0 bytes) a frame"));
+
+        stats.reset();
+        assert!(stats.line().is_empty());
+        frame(Duration::from_millis(500), &mut stats);
+        assert_eq!((stats.peak, stats.frames), (0, 1));
+    }
+}
@@ -0,0 +1,368 @@
+use crate::config::{ComputerConfig, Opponent};
+use crate::{board_from_fen, pgn};
+use jonathan_hallstrom_chess::Color;
+use serde_json::{Map, Value};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Kept in ggez's user data directory unless --save-file says otherwise
+pub const FILE_NAME: &str = "autosave.json";
+// Moves appended to the journal before the whole game is written again, so a save takes
+// about as long at move 500 as at move 5
+const SNAPSHOT_INTERVAL: usize = 64;
+
+/// An unfinished game played on this computer, networked games are never saved
+pub struct SavedGame {
+    // Position the game started from, None for the standard one
+    pub start_fen: Option<String>,
+    // Every move played so far in algebraic notation like "e2e4"
+    pub moves: Vec<String>,
+    // Position after the moves, compared with the replayed board when resuming
+    pub fen: String,
+    // None when both sides are played here
+    pub computer: Option<ComputerConfig>,
+}
+
+impl SavedGame {
+    pub fn opponent(&self) -> Opponent {
+        match &self.computer {
+            Some(computer) => Opponent::Computer(ComputerConfig {
+                depth: computer.depth,
+                color: computer.color,
+            }),
+            None => Opponent::Local,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert(
+            "start_fen".to_owned(),
+            self.start_fen.clone().map_or(Value::Null, Value::String),
+        );
+        object.insert(
+            "moves".to_owned(),
+            Value::Array(self.moves.iter().cloned().map(Value::String).collect()),
+        );
+        object.insert("fen".to_owned(), Value::String(self.fen.clone()));
+        // Only for telling saves apart, resuming replays the moves
+        object.insert(
+            "hash".to_owned(),
+            Value::String(pgn::game_hash(self.start_fen.as_deref(), &self.moves)),
+        );
+        if let Some(computer) = &self.computer {
+            let mut config = Map::new();
+            config.insert("depth".to_owned(), Value::Number(computer.depth.into()));
+            config.insert(
+                "color".to_owned(),
+                Value::String(
+                    match computer.color {
+                        Color::White => "white",
+                        Color::Black => "black",
+                    }
+                    .to_owned(),
+                ),
+            );
+            object.insert("computer".to_owned(), Value::Object(config));
+        }
+        Value::Object(object)
+    }
+
+    /// Only the shape is checked here, the moves are checked by replaying them
+    fn from_json(value: &Value) -> Result<Self, String> {
+        let string = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+                .ok_or_else(|| format!("\"{}\" is missing or not a string", key))
+        };
+
+        let start_fen = match value.get("start_fen") {
+            None | Some(Value::Null) => None,
+            Some(_) => Some(string("start_fen")?),
+        };
+        // Starting a game from it unwraps, so it has to be usable
+        if let Some(fen) = &start_fen {
+            board_from_fen(fen)?;
+        }
+
+        let moves = value
+            .get("moves")
+            .and_then(Value::as_array)
+            .ok_or("\"moves\" is missing or not a list")?
+            .iter()
+            .map(|mv| mv.as_str().map(str::to_owned))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("every move must be a string")?;
+
+        let computer = match value.get("computer") {
+            None | Some(Value::Null) => None,
+            Some(computer) => Some(ComputerConfig {
+                depth: computer
+                    .get("depth")
+                    .and_then(Value::as_u64)
+                    .and_then(|depth| u32::try_from(depth).ok())
+                    .filter(|depth| *depth > 0)
+                    .ok_or("the computer's depth must be a positive number")?,
+                color: match computer.get("color").and_then(Value::as_str) {
+                    Some("white") => Color::White,
+                    Some("black") => Color::Black,
+                    _ => return Err("the computer's color must be white or black".to_owned()),
+                },
+            }),
+        };
+
+        Ok(Self {
+            start_fen,
+            moves,
+            fen: string("fen")?,
+            computer,
+        })
+    }
+}
+
+/// Keeps the save of a game in step with it as it is played. A move is only appended to a
+/// journal next to the save, the last full write of the game. Every 64 moves, or once a move
+/// was taken back, the game is written in full again and the journal starts over.
+pub struct Autosave {
+    path: PathBuf,
+    // Moves in the last full write and in it and the journal together, None before the first
+    written: Option<(usize, usize)>,
+}
+
+impl Autosave {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            written: None,
+        }
+    }
+
+    /// Bring the save up to `game`, only its last move is written while the journal keeps up
+    pub fn write(&mut self, game: &SavedGame) {
+        let played = game.moves.len();
+        match self.written {
+            Some((_, saved)) if saved == played => {}
+            Some((full, saved)) if saved + 1 == played && played - full < SNAPSHOT_INTERVAL => {
+                match append(&self.path, saved, &game.moves[saved], &game.fen) {
+                    Ok(()) => self.written = Some((full, played)),
+                    Err(err) => {
+                        println!("Can't save the game to {}: {}", self.path.display(), err);
+                        // Written in full with the next move
+                        self.written = None;
+                    }
+                }
+            }
+            _ => {
+                // The old journal goes first so it is never read against the new save
+                remove_journal(&self.path);
+                write(&self.path, game);
+                self.written = Some((played, played));
+            }
+        }
+    }
+
+    /// Forget the save, the next write starts a new one
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn remove(&mut self) {
+        remove(&self.path);
+        self.written = None;
+    }
+}
+
+/// The moves played since the save was last written in full, a line "<ply> <move> <fen>" each
+fn journal_path(path: &Path) -> PathBuf {
+    path.with_extension("json.moves")
+}
+
+fn append(path: &Path, ply: usize, mv: &str, fen: &str) -> std::io::Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path(path))?
+        .write_all(format!("{} {} {}\n", ply, mv, fen).as_bytes())
+}
+
+/// The moves in the journal that follow on from a save of `played` moves, and the position
+/// after the last of them. A line cut short by a crash ends it.
+fn read_journal(text: &str, played: usize) -> (Vec<String>, Option<String>) {
+    let mut moves = Vec::new();
+    let mut fen = None;
+    for line in text.split_inclusive('\n') {
+        let Some(line) = line.strip_suffix('\n') else {
+            break;
+        };
+        let mut fields = line.splitn(3, ' ');
+        let (Some(ply), Some(mv), Some(after)) = (fields.next(), fields.next(), fields.next())
+        else {
+            break;
+        };
+        if ply.parse::<usize>().ok() != Some(played + moves.len()) {
+            break;
+        }
+        moves.push(mv.to_owned());
+        fen = Some(after.to_owned());
+    }
+    (moves, fen)
+}
+
+fn remove_journal(path: &Path) {
+    if let Err(err) = std::fs::remove_file(journal_path(path)) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            println!("Can't remove the save {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// Replace the save, written next to it first so a crash can't leave half a file behind
+pub fn write(path: &Path, game: &SavedGame) {
+    let written = (|| -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_string(&game.to_json())?)?;
+        std::fs::rename(&partial, path)
+    })();
+    if let Err(err) = written {
+        println!("Can't save the game to {}: {}", path.display(), err);
+    }
+}
+
+/// The saved game if there is one with the moves from its journal, a damaged save is
+/// reported and ignored
+pub fn read(path: &Path) -> Option<SavedGame> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let parsed = serde_json::from_str::<Value>(&text)
+        .map_err(|err| err.to_string())
+        .and_then(|value| SavedGame::from_json(&value));
+    match parsed {
+        Ok(mut game) => {
+            if let Ok(journal) = std::fs::read_to_string(journal_path(path)) {
+                let (moves, fen) = read_journal(&journal, game.moves.len());
+                game.moves.extend(moves);
+                game.fen = fen.unwrap_or(game.fen);
+            }
+            Some(game)
+        }
+        Err(message) => {
+            println!("Ignoring the damaged save {}: {}", path.display(), message);
+            None
+        }
+    }
+}
+
+/// Forget the save once its game is over, there is nothing left to resume
+pub fn remove(path: &Path) {
+    remove_journal(path);
+    if let Err(err) = std::fs::remove_file(path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            println!("Can't remove the save {}: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::visible_range;
+    use crate::scratch::Scratch;
+    use crate::session::tests::play;
+    use crate::session::Session;
+    use std::time::{Duration, Instant};
+
+    const SHUFFLE: [&str; 4] = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    fn saved(session: &Session) -> SavedGame {
+        SavedGame {
+            start_fen: session.start_fen.clone(),
+            moves: session.move_notations(),
+            fen: session.fen(),
+            computer: None,
+        }
+    }
+
+    #[test]
+    fn the_journal_is_read_as_far_as_it_follows_the_save() {
+        let fen = "8/8/8/8/8/8/8/K6k w - - 0 1";
+        let journal = format!("3 e2e4 {}\n4 e7e5 {}\n", fen, fen);
+        let (moves, last) = read_journal(&journal, 3);
+        assert_eq!(moves, ["e2e4", "e7e5"]);
+        assert_eq!(last.as_deref(), Some(fen));
+        // Left over from another save
+        assert_eq!(read_journal(&journal, 5), (Vec::new(), None));
+        // Cut short by a crash, the whole lines before it still count
+        let cut = format!("3 e2e4 {}\n4 e7e5 8/8/8", fen);
+        assert_eq!(read_journal(&cut, 3).0, ["e2e4"]);
+    }
+
+    #[test]
+    fn moves_are_appended_and_taken_back_moves_rewrite_the_save() {
+        let scratch = Scratch::new("journal");
+        let path = scratch.join(FILE_NAME);
+        let mut autosave = Autosave::new(path.clone());
+        let mut session = Session::new(None).unwrap();
+        for notation in ["e2e4", "e7e5", "g1f3"] {
+            play(&mut session, notation);
+            autosave.write(&saved(&session));
+        }
+        assert!(journal_path(&path).exists());
+        let loaded = read(&path).unwrap();
+        assert_eq!(loaded.moves, ["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(loaded.fen, session.fen());
+
+        session.undo();
+        play(&mut session, "b1c3");
+        autosave.write(&saved(&session));
+        assert_eq!(read(&path).unwrap().moves, ["e2e4", "e7e5", "b1c3"]);
+
+        autosave.remove();
+        assert!(!path.exists() && !journal_path(&path).exists());
+    }
+
+    #[test]
+    #[ignore = "plays a thousand plies"]
+    fn a_thousand_ply_game_stays_fast_and_resumes() {
+        let scratch = Scratch::new("long-game");
+        let path = scratch.join(FILE_NAME);
+        let mut autosave = Autosave::new(path.clone());
+        let mut session = Session::new(None).unwrap();
+        // The shuffle repeats forever unless it is claimed
+        session.claim_draws = true;
+        let budget = Duration::from_millis(20);
+        for ply in 0..1000 {
+            let started = Instant::now();
+            play(&mut session, SHUFFLE[ply % SHUFFLE.len()]);
+            // As much of the move list as a frame draws
+            let lines = visible_range(session.history_lines.len(), 0, 32);
+            assert!(lines.len() <= 32);
+            let played = started.elapsed();
+
+            let started = Instant::now();
+            autosave.write(&saved(&session));
+            let written = started.elapsed();
+            assert!(played < budget, "ply {} took {:?}", ply, played);
+            assert!(written < budget, "saving ply {} took {:?}", ply, written);
+        }
+        // One FEN and a few numbers a ply, not copies of the whole history
+        assert_eq!(session.snapshots.len(), 1001);
+        assert_eq!(session.repetitions.len(), 4);
+
+        let loaded = read(&path).unwrap();
+        assert_eq!(loaded.moves.len(), 1000);
+        let mut resumed = Session::new(loaded.start_fen.clone()).unwrap();
+        resumed.claim_draws = true;
+        for notation in &loaded.moves {
+            play(&mut resumed, notation);
+        }
+        assert_eq!(resumed.fen(), session.fen());
+        assert_eq!(loaded.fen, session.fen());
+        remove(&path);
+    }
+}
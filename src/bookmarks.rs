@@ -0,0 +1,197 @@
+/// What marks a bookmark in the move list and starts its comment in PGN, like {[#] blunder}
+pub const GLYPH: &str = "[#]";
+
+/// A position marked during play to come back to in review
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bookmark {
+    // The position after this many plies of the game's moves
+    pub ply: usize,
+    pub label: Option<String>,
+}
+
+/// The bookmarks of a game, at most one for each ply and in the order of their plies.
+/// They are only ever kept locally, nothing about them is sent to the peer.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Bookmarks {
+    marks: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// Bookmark the position after `ply`, a bookmark already there keeps its label
+    pub fn add(&mut self, ply: usize) {
+        if let Err(at) = self.marks.binary_search_by_key(&ply, |mark| mark.ply) {
+            self.marks.insert(at, Bookmark { ply, label: None });
+        }
+    }
+
+    /// Label the bookmark at `ply`, a blank label takes the label away
+    pub fn set_label(&mut self, ply: usize, label: &str) {
+        let label = label.trim();
+        if let Some(mark) = self.marks.iter_mut().find(|mark| mark.ply == ply) {
+            mark.label = (!label.is_empty()).then(|| label.to_owned());
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, ply: usize) -> Option<&Bookmark> {
+        self.marks.iter().find(|mark| mark.ply == ply)
+    }
+
+    /// The first bookmarked ply after `ply`
+    #[inline]
+    pub fn next(&self, ply: usize) -> Option<usize> {
+        self.marks.iter().map(|mark| mark.ply).find(|at| *at > ply)
+    }
+
+    /// The last bookmarked ply before `ply`
+    #[inline]
+    pub fn previous(&self, ply: usize) -> Option<usize> {
+        self.marks.iter().map(|mark| mark.ply).rfind(|at| *at < ply)
+    }
+
+    /// Forget the bookmarks past `ply`, once the moves they were set on are replaced
+    #[inline]
+    pub fn forget_after(&mut self, ply: usize) {
+        self.marks.retain(|mark| mark.ply <= ply);
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Bookmark> {
+        self.marks.iter()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.marks.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty()
+    }
+}
+
+/// The PGN comment of a bookmark without its braces, like "[#] blunder"
+pub fn comment(bookmark: &Bookmark) -> String {
+    match &bookmark.label {
+        // A brace would end the comment early
+        Some(label) => format!("{} {}", GLYPH, label.replace(['{', '}'], "")),
+        None => GLYPH.to_owned(),
+    }
+}
+
+/// The label of a bookmark's comment, Some(None) for one without a label and None for any
+/// other comment
+pub fn parse_comment(comment: &str) -> Option<Option<String>> {
+    let label = comment.trim().strip_prefix(GLYPH)?.trim();
+    Some((!label.is_empty()).then(|| label.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::practice::Fork;
+    use crate::session::tests::play;
+    use crate::session::Session;
+    use jonathan_hallstrom_chess::Color;
+
+    fn plies(bookmarks: &Bookmarks) -> Vec<usize> {
+        bookmarks.iter().map(|mark| mark.ply).collect()
+    }
+
+    #[test]
+    fn bookmarks_are_kept_in_ply_order_and_labeled() {
+        let mut bookmarks = Bookmarks::default();
+        for ply in [12, 3, 30, 3] {
+            bookmarks.add(ply);
+        }
+        assert_eq!(plies(&bookmarks), [3, 12, 30]);
+
+        bookmarks.set_label(12, "  went wrong here ");
+        assert_eq!(
+            bookmarks.get(12).unwrap().label.as_deref(),
+            Some("went wrong here")
+        );
+        // Adding it again keeps the label, a blank one takes it away
+        bookmarks.add(12);
+        assert!(bookmarks.get(12).unwrap().label.is_some());
+        bookmarks.set_label(12, " ");
+        assert_eq!(bookmarks.get(12).unwrap().label, None);
+        // Only bookmarks get labels
+        bookmarks.set_label(13, "nothing");
+        assert_eq!(bookmarks.get(13), None);
+        assert_eq!(bookmarks.len(), 3);
+    }
+
+    #[test]
+    fn jumping_goes_through_the_bookmarks_in_order() {
+        let mut bookmarks = Bookmarks::default();
+        for ply in [30, 3, 12] {
+            bookmarks.add(ply);
+        }
+        assert_eq!(bookmarks.next(0), Some(3));
+        assert_eq!(bookmarks.next(3), Some(12));
+        assert_eq!(bookmarks.next(20), Some(30));
+        assert_eq!(bookmarks.next(30), None);
+        assert_eq!(bookmarks.previous(31), Some(30));
+        assert_eq!(bookmarks.previous(12), Some(3));
+        assert_eq!(bookmarks.previous(3), None);
+        assert_eq!(Bookmarks::default().next(0), None);
+    }
+
+    #[test]
+    fn comments_read_back_as_the_same_bookmark() {
+        let labeled = Bookmark {
+            ply: 4,
+            label: Some("the {knight} hangs".to_owned()),
+        };
+        assert_eq!(comment(&labeled), "[#] the knight hangs");
+        assert_eq!(
+            parse_comment(&comment(&labeled)),
+            Some(Some("the knight hangs".to_owned()))
+        );
+        let bare = Bookmark {
+            ply: 4,
+            label: None,
+        };
+        assert_eq!(parse_comment(&comment(&bare)), Some(None));
+        assert_eq!(parse_comment("Practice from game 123 after ply 4"), None);
+    }
+
+    #[test]
+    fn bookmarks_stay_on_their_mainline_ply_through_a_branch_and_back() {
+        let mut game = Session::new(None).unwrap();
+        for mv in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+            play(&mut game, mv);
+        }
+        game.bookmarks.add(2);
+        game.bookmarks.set_label(2, "symmetric");
+        game.bookmarks.add(4);
+        let before = game.bookmarks.clone();
+
+        // Practice from ply 2 plays a line of its own, the bookmarks stay with the game
+        let fork = Fork::new(&game, Some(2), Color::White, 3).unwrap();
+        let mut practice = fork.session().unwrap();
+        play(&mut practice, "d2d4");
+        assert!(practice.bookmarks.is_empty());
+        drop(practice);
+        assert_eq!(game.bookmarks, before);
+
+        // Taking moves back and playing them again comes back to the same mainline
+        game.undo();
+        game.undo();
+        play(&mut game, "g1f3");
+        play(&mut game, "b8c6");
+        assert_eq!(game.bookmarks, before);
+
+        // Another move replaces the mainline past it, and its bookmarks with it
+        game.undo();
+        game.undo();
+        play(&mut game, "f1c4");
+        assert_eq!(plies(&game.bookmarks), [2]);
+        assert_eq!(
+            game.bookmarks.get(2).unwrap().label.as_deref(),
+            Some("symmetric")
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use crate::menu::grown;
+use crate::{unit_mesh, Graphics, Restore};
+use chess_gui::bookmarks;
+use chess_gui::game_list::{
+    self, list_order, max_scroll, row_at, scroll_to, visible_rows, ListedGame, Scan, SortKey,
+};
+use chess_gui::pgn::ReadGame;
+use ggez::graphics::{self, Canvas, Drawable, Mesh, Rect, Text};
+use ggez::input::keyboard::KeyCode;
+use ggez::Context;
+use mint::{Point2, Vector2};
+use std::path::PathBuf;
+
+const FIELD_COLOR: graphics::Color = graphics::Color::new(1.0, 0.9, 0.9, 1.0);
+const BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const CHOSEN_BUTTON_COLOR: graphics::Color = graphics::Color::new(0.0, 0.5, 0.0, 0.75);
+const ROW_COLOR: graphics::Color = graphics::Color::new(0.95, 0.95, 0.95, 1.0);
+const SELECTED_ROW_COLOR: graphics::Color = graphics::Color::new(0.7, 0.85, 0.7, 1.0);
+const TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const BROKEN_TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.35);
+const ERROR_TEXT_COLOR: graphics::Color = graphics::Color::new(0.7, 0.0, 0.0, 1.0);
+// Part of the window's height each row of the list takes
+const ROW_FRACTION: f32 = 0.04;
+const PAGE_ROWS: usize = 10;
+
+/// Where everything in the list of saved games is drawn, relative to the window size
+struct BrowserLayout {
+    filter: Rect,
+    sorts: [(SortKey, Rect); 3],
+    list: Rect,
+    row_height: f32,
+    hint: Rect,
+}
+
+impl BrowserLayout {
+    fn new(width: f32, height: f32) -> Self {
+        let rect = |x: f32, y: f32, w: f32, h: f32| {
+            Rect::new(x * width, y * height, w * width, h * height)
+        };
+        Self {
+            filter: rect(0.05, 0.1, 0.5, 0.06),
+            sorts: [
+                (SortKey::Date, rect(0.58, 0.1, 0.11, 0.06)),
+                (SortKey::Players, rect(0.71, 0.1, 0.11, 0.06)),
+                (SortKey::Result, rect(0.84, 0.1, 0.11, 0.06)),
+            ],
+            list: rect(0.05, 0.19, 0.9, 0.72),
+            row_height: ROW_FRACTION * height,
+            hint: rect(0.05, 0.93, 0.9, 0.04),
+        }
+    }
+}
+
+/// What the player did in the list of saved games
+pub(crate) enum BrowserAction {
+    Open(ListedGame),
+    Back,
+}
+
+/// The games saved in the save directory, read in the background and opened for review
+pub(crate) struct Browser {
+    dir: PathBuf,
+    scan: Option<Scan>,
+    // Why the directory couldn't be read
+    scan_error: Option<String>,
+    games: Vec<ListedGame>,
+    // Indices into `games` of the rows, sorted and filtered
+    order: Vec<usize>,
+    sort: SortKey,
+    filter: String,
+    // How far down the list is scrolled, in pixels
+    scroll: f32,
+    // The chosen game by its index into `games`, so it stays chosen while the order changes
+    selected: Option<usize>,
+    mouse: Point2<f32>,
+    // Unit square tinted and stretched into every row and button
+    rect_mesh: Mesh,
+}
+
+impl Restore for Browser {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::UnitMesh(&mut self.rect_mesh)
+    }
+}
+
+impl Browser {
+    pub(crate) fn new(ctx: &Context, dir: PathBuf) -> Self {
+        Self {
+            scan: Some(Scan::start(dir.clone())),
+            dir,
+            scan_error: None,
+            games: Vec::new(),
+            order: Vec::new(),
+            sort: SortKey::Date,
+            filter: String::new(),
+            scroll: 0.0,
+            selected: None,
+            mouse: Point2 { x: 0.0, y: 0.0 },
+            rect_mesh: unit_mesh(ctx),
+        }
+    }
+
+    #[inline]
+    fn reorder(&mut self) {
+        self.order = list_order(&self.games, self.sort, &self.filter);
+    }
+
+    /// Take in the files read since the last frame
+    pub(crate) fn update(&mut self) {
+        let Some(scan) = &mut self.scan else {
+            return;
+        };
+        let read = scan.poll();
+        if scan.is_done() {
+            self.scan = None;
+        }
+        match read {
+            Ok(games) if games.is_empty() => {}
+            Ok(games) => {
+                self.games.extend(games);
+                self.reorder();
+            }
+            Err(message) => self.scan_error = Some(message),
+        }
+    }
+
+    /// The row of the chosen game, None when it is filtered out
+    #[inline]
+    fn selected_row(&self) -> Option<usize> {
+        let selected = self.selected?;
+        self.order.iter().position(|&index| index == selected)
+    }
+
+    fn select_row(&mut self, row: usize, width: f32, height: f32) {
+        let layout = BrowserLayout::new(width, height);
+        let Some(&index) = self.order.get(row) else {
+            return;
+        };
+        self.selected = Some(index);
+        self.scroll = scroll_to(row, self.scroll, layout.row_height, layout.list.h);
+    }
+
+    /// The chosen game, if it could be read
+    fn open(&self) -> Option<BrowserAction> {
+        let game = &self.games[self.selected?];
+        match &game.headers {
+            Ok(_) => Some(BrowserAction::Open(game.clone())),
+            Err(_) => None,
+        }
+    }
+
+    fn draw_rect(&self, canvas: &mut Canvas, rect: Rect, color: graphics::Color) {
+        canvas.draw(
+            &self.rect_mesh,
+            graphics::DrawParam::default().dest_rect(rect).color(color),
+        );
+    }
+
+    fn draw_label(&self, canvas: &mut Canvas, rect: Rect, label: &str, color: graphics::Color) {
+        let mut text = Text::new(label);
+        text.set_scale(rect.h * 0.6);
+        text.set_bounds(Vector2 {
+            x: rect.w,
+            y: rect.h,
+        });
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: rect.x + rect.h * 0.2,
+                    y: rect.y + rect.h * 0.2,
+                })
+                .color(color),
+        );
+    }
+
+    fn row_label(game: &ListedGame) -> String {
+        match &game.headers {
+            Ok(headers) => format!(
+                "{}   {} - {}   {}   {} moves{}   {}",
+                headers.date,
+                headers.white,
+                headers.black,
+                headers.result,
+                headers.plies.div_ceil(2),
+                match headers.bookmarks {
+                    0 => String::new(),
+                    count => format!("   {} {}", bookmarks::GLYPH, count),
+                },
+                headers.opening.as_deref().unwrap_or(""),
+            ),
+            Err(_) => format!("{}   can't be read", game.file_name()),
+        }
+    }
+
+    pub(crate) fn draw(&self, ctx: &Context, canvas: &mut Canvas) {
+        let (width, height) = {
+            let cords = canvas.screen_coordinates().unwrap();
+            (cords.w, cords.h)
+        };
+        let layout = BrowserLayout::new(width, height);
+
+        let status = match (&self.scan_error, &self.scan) {
+            (Some(message), _) => message.clone(),
+            (None, Some(scan)) => format!("Reading {}... {}%", self.dir.display(), scan.percent()),
+            (None, None) => format!("{} games in {}", self.games.len(), self.dir.display()),
+        };
+        let title = Rect::new(layout.list.x, 0.02 * height, layout.list.w, 0.06 * height);
+        let status_color = match self.scan_error {
+            Some(_) => ERROR_TEXT_COLOR,
+            None => TEXT_COLOR,
+        };
+        self.draw_label(canvas, title, &status, status_color);
+
+        self.draw_rect(canvas, layout.filter, FIELD_COLOR);
+        let filter = match self.filter.is_empty() {
+            true => "Type to filter",
+            false => &self.filter,
+        };
+        self.draw_label(canvas, layout.filter, filter, TEXT_COLOR);
+        for (key, rect) in layout.sorts {
+            let color = match key == self.sort {
+                true => CHOSEN_BUTTON_COLOR,
+                false => BUTTON_COLOR,
+            };
+            self.draw_rect(canvas, rect, color);
+            self.draw_label(canvas, rect, key.name(), TEXT_COLOR);
+        }
+
+        // Only the rows in view are laid out, so a folder of thousands of games stays smooth
+        let rows = visible_rows(
+            self.scroll,
+            layout.row_height,
+            layout.list.h,
+            self.order.len(),
+        );
+        // A window too small to clip to just draws the rows whole
+        let _ = canvas.set_scissor_rect(layout.list);
+        let mut hovered_error = None;
+        for row in rows {
+            let game = &self.games[self.order[row]];
+            let rect = Rect::new(
+                layout.list.x,
+                layout.list.y + row as f32 * layout.row_height - self.scroll,
+                layout.list.w,
+                layout.row_height * 0.9,
+            );
+            let row_color = match Some(self.order[row]) == self.selected {
+                true => SELECTED_ROW_COLOR,
+                false => ROW_COLOR,
+            };
+            self.draw_rect(canvas, rect, row_color);
+            let text_color = match &game.headers {
+                Ok(_) => TEXT_COLOR,
+                Err(message) => {
+                    if rect.contains(self.mouse) && layout.list.contains(self.mouse) {
+                        hovered_error = Some(message);
+                    }
+                    BROKEN_TEXT_COLOR
+                }
+            };
+            self.draw_label(canvas, rect, &Self::row_label(game), text_color);
+        }
+        canvas.set_default_scissor_rect();
+
+        // The reason a file can't be read shows beside the mouse over its row
+        if let Some(message) = hovered_error {
+            let mut text = Text::new(message.as_str());
+            text.set_scale(layout.row_height * 0.6);
+            let (text_width, text_height) = text
+                .dimensions(ctx)
+                .map_or((0.0, 0.0), |dims| (dims.w, dims.h));
+            let tip = Rect::new(
+                self.mouse.x.min(width - text_width - 8.0),
+                self.mouse.y + layout.row_height,
+                text_width + 8.0,
+                text_height + 8.0,
+            );
+            self.draw_rect(canvas, tip, FIELD_COLOR);
+            canvas.draw(
+                &text,
+                graphics::DrawParam::default()
+                    .dest(Point2 {
+                        x: tip.x + 4.0,
+                        y: tip.y + 4.0,
+                    })
+                    .color(ERROR_TEXT_COLOR),
+            );
+        }
+
+        self.draw_label(
+            canvas,
+            layout.hint,
+            "Click or Enter opens the game, Tab sorts, Escape goes back",
+            TEXT_COLOR,
+        );
+    }
+
+    /// Handle a click, a click on the chosen row opens it. The sort buttons take clicks up to
+    /// `slop` pixels past their edges, the rows are too close together for it.
+    pub(crate) fn click(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        slop: f32,
+    ) -> Option<BrowserAction> {
+        let layout = BrowserLayout::new(width, height);
+        let point = Point2 { x, y };
+        if let Some((key, _)) = layout
+            .sorts
+            .iter()
+            .find(|(_, rect)| grown(*rect, slop).contains(point))
+        {
+            self.sort = *key;
+            self.reorder();
+        } else if layout.list.contains(point) {
+            let row = row_at(
+                y - layout.list.y,
+                self.scroll,
+                layout.row_height,
+                self.order.len(),
+            )?;
+            if self.selected_row() == Some(row) {
+                return self.open();
+            }
+            self.select_row(row, width, height);
+        }
+        None
+    }
+
+    pub(crate) fn key(
+        &mut self,
+        keycode: KeyCode,
+        width: f32,
+        height: f32,
+    ) -> Option<BrowserAction> {
+        let last = self.order.len().checked_sub(1);
+        let row = self.selected_row();
+        match keycode {
+            KeyCode::Escape => return Some(BrowserAction::Back),
+            KeyCode::Return | KeyCode::NumpadEnter => return self.open(),
+            KeyCode::Back => {
+                self.filter.pop();
+                self.reorder();
+            }
+            KeyCode::Tab => {
+                self.sort = self.sort.next();
+                self.reorder();
+            }
+            KeyCode::Up => {
+                self.select_row(row.map_or(0, |row| row.saturating_sub(1)), width, height)
+            }
+            KeyCode::Down => self.select_row(row.map_or(0, |row| row + 1), width, height),
+            KeyCode::PageUp => self.select_row(
+                row.map_or(0, |row| row.saturating_sub(PAGE_ROWS)),
+                width,
+                height,
+            ),
+            KeyCode::PageDown => self.select_row(
+                row.map_or(0, |row| (row + PAGE_ROWS).min(last.unwrap_or(0))),
+                width,
+                height,
+            ),
+            KeyCode::Home => self.select_row(0, width, height),
+            KeyCode::End => self.select_row(last.unwrap_or(0), width, height),
+            _ => {}
+        }
+        None
+    }
+
+    /// Typed characters narrow the list down
+    pub(crate) fn text_input(&mut self, character: char) {
+        if !character.is_control() {
+            self.filter.push(character);
+            self.reorder();
+        }
+    }
+
+    pub(crate) fn wheel(&mut self, y: f32, height: f32) {
+        let layout = BrowserLayout::new(1.0, height);
+        self.scroll = (self.scroll - y * layout.row_height * 3.0).clamp(
+            0.0,
+            max_scroll(layout.row_height, layout.list.h, self.order.len()),
+        );
+    }
+
+    #[inline]
+    pub(crate) fn motion(&mut self, x: f32, y: f32) {
+        self.mouse = Point2 { x, y };
+    }
+}
+
+/// The start position of one game of a PGN file, and the game as it was read
+pub(crate) fn read_game(game: &ListedGame) -> Result<(Option<String>, ReadGame), String> {
+    let text = std::fs::read_to_string(&game.path).map_err(|err| err.to_string())?;
+    let games = game_list::split_games(&text);
+    let game_text = games
+        .get(game.index)
+        .ok_or_else(|| "the game is gone from the file".to_owned())?;
+    let read = chess_gui::pgn::read_pgn(game_text);
+    let fen = read
+        .tags
+        .iter()
+        .find(|(name, _)| name == "FEN")
+        .map(|(_, fen)| fen.clone());
+    Ok((fen, read))
+}
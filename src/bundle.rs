@@ -0,0 +1,506 @@
+use crate::pgn;
+use crate::profiles::{self, ProfilePaths};
+use crate::{autosave, connection_book, interop, splitter};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Inside every bundle, what was and wasn't included and why
+pub const MANIFEST: &str = "manifest.json";
+/// Keys whose values redaction blanks wherever they are in a JSON file: the addresses and
+/// nicknames of the connection book, and the save directory, which usually names the user
+pub const REDACTED_KEYS: [&str; 3] = ["address", "nickname", "save_dir"];
+/// What a redacted value becomes, the key stays so the file keeps its shape
+pub const REDACTED: &str = "redacted";
+// Zip's signatures of a file, an entry of the central directory and the end of the archive
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+// Version 2.0, the first with directories, and the flag for names in UTF-8
+const ZIP_VERSION: u16 = 20;
+const UTF8_NAMES: u16 = 1 << 11;
+
+/// Something a bundle takes along if there is any
+pub struct Artifact {
+    // Its name in the archive
+    pub name: &'static str,
+    pub source: Source,
+    // JSON with values in it that redaction blanks
+    pub sensitive: bool,
+}
+
+pub enum Source {
+    File(PathBuf),
+    // Worked out by the game for the bundle, like the transcript of the game being played
+    Text(String),
+    // What there is none of in this run, and why
+    Absent(String),
+}
+
+/// What the manifest says about one artifact
+#[derive(Clone, PartialEq, Debug)]
+pub enum Entry {
+    Included {
+        name: &'static str,
+        // The file it was read from, or "this run"
+        from: String,
+        bytes: usize,
+        // How many values redaction blanked in it
+        redacted: usize,
+    },
+    Skipped {
+        name: &'static str,
+        why: String,
+    },
+}
+
+/// The files of a profile that go into a bundle, the connection book and the settings are
+/// sensitive
+pub fn profile_artifacts(paths: &ProfilePaths) -> Vec<Artifact> {
+    let file = |name, path, sensitive| Artifact {
+        name,
+        source: Source::File(path),
+        sensitive,
+    };
+    vec![
+        file(autosave::FILE_NAME, paths.autosave(), false),
+        file(profiles::SETTINGS_FILE, paths.settings(), true),
+        file(connection_book::FILE_NAME, paths.connections(), true),
+        file(interop::FILE_NAME, paths.interop(), false),
+        file(splitter::FILE_NAME, paths.layout(), false),
+    ]
+}
+
+/// Blank the values of `REDACTED_KEYS` anywhere in `value`, returns how many were blanked.
+/// Values that are null already stay null, there was nothing to hide.
+pub fn redact(value: &mut Value) -> usize {
+    match value {
+        Value::Object(object) => object
+            .iter_mut()
+            .map(
+                |(key, value)| match REDACTED_KEYS.contains(&key.as_str()) && !value.is_null() {
+                    true => {
+                        *value = Value::String(REDACTED.to_owned());
+                        1
+                    }
+                    false => redact(value),
+                },
+            )
+            .sum(),
+        Value::Array(values) => values.iter_mut().map(redact).sum(),
+        _ => 0,
+    }
+}
+
+/// A sensitive file redacted, a file that isn't JSON can't be and is left out instead
+fn redact_file(bytes: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut value: Value = serde_json::from_slice(bytes).map_err(|err| err.to_string())?;
+    let redacted = redact(&mut value);
+    let bytes = serde_json::to_vec(&value).map_err(|err| err.to_string())?;
+    Ok((bytes, redacted))
+}
+
+/// Read every artifact, the sensitive ones redacted if `redact`. What there is none of is
+/// skipped, the entries say why.
+pub fn gather(artifacts: &[Artifact], redact: bool) -> (Vec<(&'static str, Vec<u8>)>, Vec<Entry>) {
+    let mut files = Vec::new();
+    let mut entries = Vec::new();
+    for artifact in artifacts {
+        let name = artifact.name;
+        let read = match &artifact.source {
+            Source::File(path) => match fs::read(path) {
+                Ok(bytes) => Ok((path.display().to_string(), bytes)),
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    Err(format!("There is no {}", path.display()))
+                }
+                Err(err) => Err(format!("Can't read {}: {}", path.display(), err)),
+            },
+            Source::Text(text) => Ok(("this run".to_owned(), text.clone().into_bytes())),
+            Source::Absent(why) => Err(why.clone()),
+        };
+        let redacted = read.and_then(|(from, bytes)| match artifact.sensitive && redact {
+            true => redact_file(&bytes)
+                .map(|(bytes, redacted)| (from, bytes, redacted))
+                .map_err(|err| format!("Left out, it couldn't be redacted: {}", err)),
+            false => Ok((from, bytes, 0)),
+        });
+        match redacted {
+            Ok((from, bytes, redacted)) => {
+                entries.push(Entry::Included {
+                    name,
+                    from,
+                    bytes: bytes.len(),
+                    redacted,
+                });
+                files.push((name, bytes));
+            }
+            Err(why) => entries.push(Entry::Skipped { name, why }),
+        }
+    }
+    (files, entries)
+}
+
+/// The manifest of a bundle: when it was made, whether it was redacted and of what, what the
+/// game runs on, and every artifact with where it came from or why it is missing
+pub fn manifest(
+    created: &str,
+    redacted: bool,
+    system: &[(&str, String)],
+    entries: &[Entry],
+) -> Value {
+    let mut object = Map::new();
+    object.insert("created".to_owned(), Value::String(created.to_owned()));
+    let mut redaction = Map::new();
+    redaction.insert("applied".to_owned(), Value::Bool(redacted));
+    redaction.insert(
+        "keys".to_owned(),
+        Value::Array(
+            REDACTED_KEYS
+                .iter()
+                .map(|key| Value::String((*key).to_owned()))
+                .collect(),
+        ),
+    );
+    object.insert("redaction".to_owned(), Value::Object(redaction));
+    object.insert(
+        "system".to_owned(),
+        Value::Object(
+            system
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), Value::String(value.clone())))
+                .collect(),
+        ),
+    );
+    let (mut included, mut skipped) = (Vec::new(), Vec::new());
+    for entry in entries {
+        let mut object = Map::new();
+        match entry {
+            Entry::Included {
+                name,
+                from,
+                bytes,
+                redacted,
+            } => {
+                object.insert("name".to_owned(), Value::String((*name).to_owned()));
+                object.insert("from".to_owned(), Value::String(from.clone()));
+                object.insert("bytes".to_owned(), Value::Number((*bytes as u64).into()));
+                object.insert(
+                    "redacted".to_owned(),
+                    Value::Number((*redacted as u64).into()),
+                );
+                included.push(Value::Object(object));
+            }
+            Entry::Skipped { name, why } => {
+                object.insert("name".to_owned(), Value::String((*name).to_owned()));
+                object.insert("why".to_owned(), Value::String(why.clone()));
+                skipped.push(Value::Object(object));
+            }
+        }
+    }
+    object.insert("included".to_owned(), Value::Array(included));
+    object.insert("skipped".to_owned(), Value::Array(skipped));
+    Value::Object(object)
+}
+
+/// The CRC-32 zip checks every file against
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+/// The seconds since 1970 as the time and date zip keeps, to the nearest two seconds
+fn dos_time(seconds: u64) -> (u16, u16) {
+    let (year, month, day) = pgn::civil_date((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+    let dos_time = (time / 3600) << 11 | (time / 60 % 60) << 5 | ((time % 60) / 2);
+    // Zip can't go back before 1980
+    let dos_date = ((year.max(1980) - 1980) as u32) << 9 | month << 5 | day;
+    (dos_time as u16, dos_date as u16)
+}
+
+/// A zip archive of the files, stored as they are. Nothing a bug report holds is big enough
+/// to be worth compressing.
+pub fn zip(files: &[(&str, Vec<u8>)], modified: u64) -> Vec<u8> {
+    let (time, date) = dos_time(modified);
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, bytes) in files {
+        let offset = archive.len() as u32;
+        let crc = crc32(bytes);
+        // What the local header and the directory's entry both have, from the version needed on
+        let mut common = Vec::new();
+        common.extend(ZIP_VERSION.to_le_bytes());
+        common.extend(UTF8_NAMES.to_le_bytes());
+        // Stored, not compressed
+        common.extend(0u16.to_le_bytes());
+        common.extend(time.to_le_bytes());
+        common.extend(date.to_le_bytes());
+        common.extend(crc.to_le_bytes());
+        common.extend((bytes.len() as u32).to_le_bytes());
+        common.extend((bytes.len() as u32).to_le_bytes());
+        common.extend((name.len() as u16).to_le_bytes());
+        // No extra field
+        common.extend(0u16.to_le_bytes());
+
+        archive.extend(LOCAL_HEADER.to_le_bytes());
+        archive.extend(&common);
+        archive.extend(name.as_bytes());
+        archive.extend(bytes);
+
+        directory.extend(CENTRAL_HEADER.to_le_bytes());
+        // Made by the same version it needs
+        directory.extend(ZIP_VERSION.to_le_bytes());
+        directory.extend(&common);
+        // No comment, the first disk, no attributes
+        directory.extend([0; 10]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+    let directory_offset = archive.len() as u32;
+    archive.extend(&directory);
+    archive.extend(END_OF_DIRECTORY.to_le_bytes());
+    // The only disk
+    archive.extend([0; 4]);
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+    // No comment
+    archive.extend([0; 2]);
+    archive
+}
+
+/// Bundle the artifacts into `dir` with the manifest, named by when it was made like
+/// bug-report-2023-10-14-153012.zip. Returns where it went.
+pub fn create(
+    dir: &Path,
+    artifacts: &[Artifact],
+    system: &[(&str, String)],
+    redact: bool,
+) -> Result<PathBuf, String> {
+    let seconds = pgn::seconds_since_epoch();
+    let stamp = pgn::file_stamp(seconds);
+
+    let (mut files, entries) = gather(artifacts, redact);
+    let manifest = manifest(&stamp, redact, system, &entries);
+    let manifest = serde_json::to_vec(&manifest)
+        .map_err(|err| format!("Can't write the bundle's manifest: {}", err))?;
+    files.insert(0, (MANIFEST, manifest));
+
+    let path = dir.join(format!("bug-report-{}.zip", stamp));
+    fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&path, zip(&files, seconds)))
+        .map_err(|err| format!("Can't write the bundle to {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::Profiles;
+    use crate::scratch::Scratch;
+
+    /// The names and contents of the files in a zip archive this module wrote
+    fn unzip(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let le16 = |at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]) as usize;
+        let le32 = |at: usize| {
+            u32::from_le_bytes([
+                archive[at],
+                archive[at + 1],
+                archive[at + 2],
+                archive[at + 3],
+            ]) as usize
+        };
+        let mut files = Vec::new();
+        let mut at = 0;
+        while le32(at) == LOCAL_HEADER as usize {
+            let (size, name_length) = (le32(at + 22), le16(at + 26));
+            let name = &archive[at + 30..at + 30 + name_length];
+            let data = at + 30 + name_length;
+            let bytes = archive[data..data + size].to_vec();
+            assert_eq!(le32(at + 14), crc32(&bytes) as usize);
+            files.push((String::from_utf8(name.to_vec()).unwrap(), bytes));
+            at = data + size;
+        }
+        assert_eq!(le32(at), CENTRAL_HEADER as usize);
+        files
+    }
+
+    #[test]
+    fn the_checksum_is_zips() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn an_archive_keeps_its_files_as_they_are() {
+        let files = [("a.txt", b"one".to_vec()), ("b.json", Vec::new())];
+        let archive = zip(&files, 1_697_297_412);
+        let read = unzip(&archive);
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0], ("a.txt".to_owned(), b"one".to_vec()));
+        assert_eq!(read[1], ("b.json".to_owned(), Vec::new()));
+        // The end of the archive counts both and finds the directory
+        let end = archive.len() - 22;
+        assert_eq!(&archive[end..end + 4], &END_OF_DIRECTORY.to_le_bytes());
+        assert_eq!(&archive[end + 8..end + 12], &[2, 0, 2, 0]);
+    }
+
+    #[test]
+    fn zip_dates_count_from_1980() {
+        // 2023-10-14 15:30:12
+        let (time, date) = dos_time(1_697_297_412);
+        assert_eq!(time, 15 << 11 | 30 << 5 | 6);
+        assert_eq!(date, 43 << 9 | 10 << 5 | 14);
+        assert_eq!(dos_time(0).1, 1 << 5 | 1);
+    }
+
+    #[test]
+    fn redaction_blanks_the_marked_keys_at_any_depth() {
+        let mut entry = Map::new();
+        entry.insert(
+            "address".to_owned(),
+            Value::String("192.168.1.10:5000".to_owned()),
+        );
+        entry.insert("nickname".to_owned(), Value::Null);
+        entry.insert("pinned".to_owned(), Value::Bool(true));
+        let mut settings = Map::new();
+        settings.insert(
+            "save_dir".to_owned(),
+            Value::String("/home/alva/chess".to_owned()),
+        );
+        settings.insert(
+            "entries".to_owned(),
+            Value::Array(vec![Value::Object(entry)]),
+        );
+        let mut value = Value::Object(settings);
+
+        assert_eq!(redact(&mut value), 2);
+        assert_eq!(
+            value.get("save_dir").and_then(Value::as_str),
+            Some(REDACTED)
+        );
+        let entry = &value.get("entries").and_then(Value::as_array).unwrap()[0];
+        assert_eq!(entry.get("address").and_then(Value::as_str), Some(REDACTED));
+        assert!(entry.get("nickname").is_some_and(Value::is_null));
+        assert_eq!(entry.get("pinned").and_then(Value::as_bool), Some(true));
+        // Blanking again changes nothing
+        let blanked = value.clone();
+        redact(&mut value);
+        assert!(value == blanked);
+    }
+
+    #[test]
+    fn the_manifest_lists_what_is_in_and_why_the_rest_is_not() {
+        let entries = [
+            Entry::Included {
+                name: "profile.json",
+                from: "profiles/alva/profile.json".to_owned(),
+                bytes: 40,
+                redacted: 1,
+            },
+            Entry::Skipped {
+                name: "transcript.md",
+                why: "Only networked games have a transcript".to_owned(),
+            },
+        ];
+        let manifest = manifest(
+            "2023-10-14-153012",
+            true,
+            &[("os", "linux".to_owned())],
+            &entries,
+        );
+        assert_eq!(
+            manifest.get("created").and_then(Value::as_str),
+            Some("2023-10-14-153012")
+        );
+        let redaction = manifest.get("redaction").unwrap();
+        assert_eq!(
+            redaction.get("applied").and_then(Value::as_bool),
+            Some(true)
+        );
+        assert_eq!(
+            redaction
+                .get("keys")
+                .and_then(Value::as_array)
+                .map(Vec::len),
+            Some(REDACTED_KEYS.len())
+        );
+        assert_eq!(
+            manifest
+                .get("system")
+                .and_then(|system| system.get("os"))
+                .and_then(Value::as_str),
+            Some("linux")
+        );
+        let included = manifest.get("included").and_then(Value::as_array).unwrap();
+        assert_eq!(included.len(), 1);
+        assert_eq!(included[0].get("bytes").and_then(Value::as_u64), Some(40));
+        assert_eq!(included[0].get("redacted").and_then(Value::as_u64), Some(1));
+        let skipped = manifest.get("skipped").and_then(Value::as_array).unwrap();
+        assert_eq!(
+            skipped[0].get("why").and_then(Value::as_str),
+            Some("Only networked games have a transcript")
+        );
+    }
+
+    /// A bundle of a profile with only some of its files, and something the run has none of
+    #[test]
+    fn a_bundle_holds_what_its_manifest_says() {
+        let scratch = Scratch::new("bundle");
+        let root = scratch.path().to_owned();
+        let paths = Profiles::new(root.clone()).create("alva").unwrap();
+        fs::write(
+            paths.settings(),
+            r#"{"swatch": 2, "save_dir": "/home/alva/chess", "mouse": {}}"#,
+        )
+        .unwrap();
+        fs::write(paths.autosave(), r#"{"moves": ["e2e4"]}"#).unwrap();
+        let mut artifacts = profile_artifacts(&paths);
+        artifacts.push(Artifact {
+            name: "recording.jsonl",
+            source: Source::Absent("This build doesn't record sessions".to_owned()),
+            sensitive: false,
+        });
+
+        let path = create(&root, &artifacts, &[("os", "linux".to_owned())], true).unwrap();
+        assert!(path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("bug-report-") && name.ends_with(".zip")));
+        let files = unzip(&fs::read(&path).unwrap());
+        assert_eq!(files[0].0, MANIFEST);
+        let manifest: Value = serde_json::from_slice(&files[0].1).unwrap();
+
+        let listed: Vec<&str> = manifest
+            .get("included")
+            .and_then(Value::as_array)
+            .unwrap()
+            .iter()
+            .filter_map(|entry| entry.get("name").and_then(Value::as_str))
+            .collect();
+        let archived: Vec<&str> = files[1..].iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(listed, archived);
+        assert_eq!(archived, [autosave::FILE_NAME, profiles::SETTINGS_FILE]);
+        let skipped = manifest.get("skipped").and_then(Value::as_array).unwrap();
+        assert_eq!(skipped.len(), artifacts.len() - archived.len());
+
+        let settings: Value = serde_json::from_slice(&files[2].1).unwrap();
+        assert_eq!(
+            settings.get("save_dir").and_then(Value::as_str),
+            Some(REDACTED)
+        );
+        assert_eq!(settings.get("swatch").and_then(Value::as_u64), Some(2));
+        assert!(!String::from_utf8_lossy(&files[2].1).contains("/home/alva"));
+    }
+}
@@ -0,0 +1,103 @@
+use std::process::Command;
+
+/// Where pasted text comes from
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Source {
+    Clipboard,
+    // The text last selected, which X11 and Wayland paste with the middle button
+    Primary,
+}
+
+/// What a middle click pastes: the primary selection on Linux, the clipboard elsewhere
+#[inline]
+pub fn middle_click_source() -> Source {
+    match std::env::consts::OS {
+        "linux" => Source::Primary,
+        _ => Source::Clipboard,
+    }
+}
+
+/// Reads text the system holds for pasting
+pub trait Paste {
+    fn paste(&self, source: Source) -> Result<String, String>;
+}
+
+/// The programs that read `source` on `os`, tried in turn until one works
+pub fn commands(source: Source, os: &str) -> Vec<Vec<&'static str>> {
+    match os {
+        "macos" => vec![vec!["pbpaste"]],
+        "windows" => vec![vec![
+            "powershell",
+            "-NoProfile",
+            "-Command",
+            "Get-Clipboard",
+        ]],
+        _ => match source {
+            Source::Primary => vec![
+                vec!["wl-paste", "--primary", "--no-newline"],
+                vec!["xclip", "-out", "-selection", "primary"],
+                vec!["xsel", "--primary", "--output"],
+            ],
+            Source::Clipboard => vec![
+                vec!["wl-paste", "--no-newline"],
+                vec!["xclip", "-out", "-selection", "clipboard"],
+                vec!["xsel", "--clipboard", "--output"],
+            ],
+        },
+    }
+}
+
+/// Pastes with whatever program the system has for it
+pub struct System;
+
+impl Paste for System {
+    fn paste(&self, source: Source) -> Result<String, String> {
+        let mut failures = Vec::new();
+        for command in commands(source, std::env::consts::OS) {
+            match Command::new(command[0]).args(&command[1..]).output() {
+                Ok(output) if output.status.success() => {
+                    return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+                }
+                Ok(output) => failures.push(format!(
+                    "{}: {}",
+                    command[0],
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )),
+                Err(err) => failures.push(format!("{}: {}", command[0], err)),
+            }
+        }
+        Err(format!("Nothing to paste from, {}", failures.join(", ")))
+    }
+}
+
+/// The first line of pasted text with something on it, trimmed, as a single-line bar takes it
+pub fn pasted_line(text: &str) -> &str {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_platform_has_a_way_to_paste() {
+        for os in ["linux", "freebsd", "macos", "windows"] {
+            for source in [Source::Clipboard, Source::Primary] {
+                assert!(!commands(source, os).is_empty());
+            }
+        }
+        assert!(commands(Source::Primary, "linux")
+            .iter()
+            .all(|command| command.iter().any(|arg| arg.contains("primary"))));
+    }
+
+    #[test]
+    fn only_the_first_line_is_pasted() {
+        assert_eq!(pasted_line("\n  Nf3 \nNc6\n"), "Nf3");
+        assert_eq!(pasted_line("e2e4"), "e2e4");
+        assert_eq!(pasted_line(" \n"), "");
+    }
+}
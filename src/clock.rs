@@ -0,0 +1,131 @@
+use jonathan_hallstrom_chess::Color;
+use std::time::Duration;
+
+/// Starting time and the increment added after every move, like "5+3"
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub struct TimeControl {
+    base: Duration,
+    increment: Duration,
+}
+
+impl TimeControl {
+    /// Minutes and increment seconds as "5+3", a plain "5" has no increment
+    pub fn parse(text: &str) -> Option<Self> {
+        let (minutes, seconds) = text.split_once('+').unwrap_or((text, "0"));
+        let minutes: f64 = minutes.parse().ok()?;
+        let seconds: u64 = seconds.parse().ok()?;
+        if !minutes.is_finite() || minutes <= 0.0 {
+            return None;
+        }
+        Some(Self {
+            // Too many minutes for a Duration are no time control either
+            base: Duration::try_from_secs_f64(minutes * 60.0).ok()?,
+            increment: Duration::from_secs(seconds),
+        })
+    }
+}
+
+/// Time left for each side
+pub struct Clock {
+    white: Duration,
+    black: Duration,
+    increment: Duration,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            white: control.base,
+            black: control.base,
+            increment: control.increment,
+        }
+    }
+
+    pub fn remaining(&self, color: Color) -> Duration {
+        match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        }
+    }
+
+    fn remaining_mut(&mut self, color: Color) -> &mut Duration {
+        match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        }
+    }
+
+    /// Run the clock of `color` for `delta`, returning whether its time just ran out
+    pub fn tick(&mut self, color: Color, delta: Duration) -> bool {
+        let remaining = self.remaining_mut(color);
+        if remaining.is_zero() {
+            return false;
+        }
+        *remaining = remaining.saturating_sub(delta);
+        remaining.is_zero()
+    }
+
+    /// Called for the side that just moved
+    pub fn add_increment(&mut self, color: Color) {
+        let increment = self.increment;
+        *self.remaining_mut(color) += increment;
+    }
+}
+
+// Numbers the countdown before a networked game shows, one a second
+const COUNTDOWN_FROM: u32 = 3;
+const COUNTDOWN_STEP: Duration = Duration::from_secs(1);
+
+/// The 3-2-1 shown once both players are connected. Moves made during it wait until it is
+/// over, and the clocks only start with the first move, so the side whose handshake finished
+/// last loses no time to it.
+pub struct Countdown {
+    // Time since the start of the program when the players were connected
+    start: Duration,
+}
+
+impl Countdown {
+    pub fn new(start: Duration) -> Self {
+        Self { start }
+    }
+
+    /// The number to show at `now`, None once the game may begin
+    pub fn number(&self, now: Duration) -> Option<u32> {
+        let steps = now.saturating_sub(self.start).as_millis() / COUNTDOWN_STEP.as_millis();
+        (steps < COUNTDOWN_FROM.into()).then(|| COUNTDOWN_FROM - steps as u32)
+    }
+
+    #[inline]
+    pub fn is_over(&self, now: Duration) -> bool {
+        self.number(now).is_none()
+    }
+}
+
+/// "m:ss", with tenths of a second once there are less than ten seconds left
+pub fn format_time(time: Duration) -> String {
+    if time < Duration::from_secs(10) {
+        return format!("0:0{}.{}", time.as_secs(), time.subsec_millis() / 100);
+    }
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_countdown_counts_down_from_three() {
+        let start = Duration::from_secs(20);
+        let countdown = Countdown::new(start);
+        let at = |millis| start + Duration::from_millis(millis);
+        assert_eq!(countdown.number(at(0)), Some(3));
+        assert_eq!(countdown.number(at(999)), Some(3));
+        assert_eq!(countdown.number(at(1000)), Some(2));
+        assert_eq!(countdown.number(at(2999)), Some(1));
+        assert!(!countdown.is_over(at(2999)));
+        assert!(countdown.is_over(at(3000)));
+        // A frame from before the connection doesn't count down further
+        assert_eq!(countdown.number(Duration::ZERO), Some(3));
+    }
+}
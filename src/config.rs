@@ -0,0 +1,1003 @@
+use crate::accessibility::{AccessibleInput, MAX_EXPANSION};
+use crate::clock::TimeControl;
+use crate::connectivity::Hosting;
+use crate::engine_match::{
+    parse_levels, MatchConfig, DEFAULT_MATCH_GAMES, DEFAULT_MOVE_TIME, DEFAULT_REPORT,
+};
+use crate::glyphs::GlyphStyle;
+use crate::hook::{HookConfig, DEFAULT_FILTER_TIMEOUT};
+use crate::latency::SlideCurve;
+use crate::layout::{LayoutMode, DEFAULT_PORTRAIT_RATIO};
+use crate::mouse::MouseChange;
+use crate::notation::Notation;
+use crate::opposite_color;
+use crate::piece_style::{Part, StyleChange};
+use crate::profiles;
+use crate::stall::parse_duration;
+use crate::strikes::DEFAULT_MAX_STRIKES;
+use crate::tty_mirror::TtyOrientation;
+use crate::updates::UpdateSwitch;
+use crate::validate::Direction;
+use crate::watchdog::DEFAULT_HANG_AFTER;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "Usage:
+    chess-gui [options]
+    chess-gui local [options]
+    chess-gui local --vs-computer [--depth <n>] [--computer-color <white|black>] [options]
+    chess-gui --resume [options]
+    chess-gui --engine-match <n>v<n> [--games <n>] [--watch] [--move-time <seconds>] [--match-report <file>]
+    chess-gui server <address:port|port> [--strict-port] [options]
+    chess-gui client <address:port> --server-color <white|black> [options]
+    chess-gui client <address:port> --spectate [options]
+    chess-gui validate [<file>] [--type <direction|auto>] [--context-fen <fen>] [--sequence] [--json]
+
+Without a mode the game is chosen on a start screen. Addresses can be IPs, hostnames
+or bracketed IPv6 like [::1]:5000, a server given only a port listens on every address.
+
+In a game Ctrl+B gathers what helps with a bug report into a zip in the save directory, with
+the addresses and names in it blanked, Ctrl+Shift+B without blanking them.
+
+While reviewing, 1 to 6 mark the move that led to the position with !, ?, !!, ??, !? or ?!
+and 0 clears it. Once the evaluation pass is done, A marks the mistakes and blunders it found
+with ? and ?? where no glyph was set.
+
+X while reviewing opens the position in a sandbox, where pieces move anywhere without rules
+or turns and are added from a palette. The game isn't changed by it, and only a legal position
+is analyzed or played from. The start screen opens one from the first position.
+
+The start screen rolls a random handle like SwiftGambit to play under instead of the
+profile's name, in its saved games, its legend and its clock. A on the profile picker plays
+a profile that way on every run, with a new handle each time. The profile itself keeps its
+name and its record.
+
+validate checks protocol messages from the file or the standard input without opening a
+window, the direction is server-to-client, client-to-server, handshake-s2c or handshake-c2s.
+--context-fen also checks them against a position, as if every feature was advertised.
+--sequence checks a whole connection in order, from the client's handshake on. --json
+prints the report as JSON. It exits with 0 when every message is valid and 1 otherwise.
+
+Options:
+    --tty-mirror                            Print every position to the terminal
+    --tty-orientation <white|black|follow>  Orientation of the terminal board
+    --no-selftest                           Skip the startup self-test
+    --mute                                  Start with sound effects off, M toggles them
+    --theme <default|blue|wood|file.json>   Board colors and pieces, T cycles the built-in ones
+    --no-coordinates                        Hide the file and rank labels, C toggles them
+    --legend                                Show which piece is which beside the board, L toggles it
+    --notation <english|figurine|language>  Write the move list in SAN, with the piece letters of sv, de, fr, es, it or nl, G switches it, saved PGN stays English
+    --accessible-input                      Bigger click targets for the selected piece's moves and buttons, and clicking by resting the pointer
+    --target-expansion <squares>            How far past their squares the moves take clicks with --accessible-input, 0 to 0.5, 0.3 by default
+    --dwell <duration|off>                  How long the pointer rests before it clicks with --accessible-input, 1.2s by default
+    --premove                               Pick up the next move while the opponent thinks, it is played once the turn comes if it is still legal
+    --piece-tint <side>=<#rrggbb|off>       Tint white's or black's pieces, like black=#1a2b6d, kept in the profile until turned off
+    --piece-outline <side>=<#rrggbb|off>    Draw a thick outline of this color around a side's pieces, kept the same way
+    --piece-badge <side>=<#rrggbb|off>      Mark a side's pieces with a dot of this color in the corner, kept the same way
+    --mouse <region>.<button>=<action|off>  Rebind a mouse button on the board, panel, entry bar or menu, like board.right=select, to select, cancel or paste, kept in the profile
+    --layout <auto|landscape|portrait>      Put the move list beside the board or below it, by the window's shape for auto
+    --portrait-ratio <ratio>                How many times taller than wide the window gets before auto puts the board on top, 1 by default
+    --fen <fen>                             Start from this position instead, the client gets it from the server
+    --spectate                              Watch the game on the server without playing
+    --connect-attempts <n>                  How many times the client tries to reach the server
+    --time <minutes+seconds>                Play with a clock, like 5+3, the client should match the server
+    --save-dir <dir>                        Save finished games there as PGN, Ctrl+S saves at any time
+    --pgn-glyphs <suffix|nag>               Write the glyphs set while reviewing after the moves like e4!?, or as NAGs like e4 $5, suffix by default
+    --vs-computer                           Play a local game against the computer
+    --depth <n>                             How many moves ahead the computer looks, 3 by default
+    --computer-color <white|black>          The color the computer plays, black by default
+    --alternate-colors                      Switch the computer's color in every game started over with Ctrl+N
+    --assist                                Warn of the opening traps beginners fall for and show pinned pieces, in games that aren't networked
+    --relative-pins                         With --assist, also show pieces pinned to a queen or rook
+    --practice-depth <n>                    How far the computer looks when P plays on from a finished networked game
+    --resume                                Continue the unfinished game saved when the last one closed
+    --save-file <file>                      Where games that aren't networked are saved after every move
+    --profile <name>                        Play as this profile without asking who is playing, it is created if new
+    --claim-draws                           Repetitions and the fifty-move rule only draw once claimed with D
+    --strict-port                           Fail when the server's port is in use instead of trying another
+    --max-illegal-moves <n>                 Illegal moves in a row the server takes from the client before it forfeits, 5 by default, 0 for no limit
+    --max-protocol-errors <n>               The same for moves sent out of turn or after the game, 5 by default, 0 for no limit
+    --strict-handshakes                     Count a handshake the peer sends again after the game started as a protocol error, instead of checking it against the game
+    --max-move-time <duration>              In an untimed networked game, warn once the opponent takes longer than this for a move, like 10m, and allow claiming the win 5 minutes later
+    --slide-latency <ms,ms>                 As a client, let the server's moves slide in full while they are fresher than the first, shorter up to the second and snap past it, by half the round trip of the moves, 40,300 by default
+    --audit-ordering                        Number the messages sent to the peer and stop at once if any leave out of order
+    --dump-ordering <file>                  Write when every message was sent and received there, to compare with the other side's
+    --transcript <file>                     Write every message exchanged with the peer there once the game ends, Ctrl+E writes it any time
+    --metrics-port <port>                   Serve counters of the networked games for Prometheus at localhost:<port>/metrics
+    --check-updates <url|off>               Look once a day at startup whether the releases document at url has a newer version, remembered until turned off
+    --offline                               Never look for updates in this run
+    --hang-report <duration|off>            Write hang-report.txt in the data directory once the focused window hasn't updated for this long, 5s by default
+    --hook <command>                        Run this program and write what happens in the games to its standard input as JSON lines, the command is split at spaces
+    --hook-filter <command>                 The same, and ask it about every move of the player's before it is played, it may veto it
+    --hook-timeout <duration>               How long a filter may take to answer before the move is played anyway, 0.5s by default
+    --hook-allow-networked                  Let the hook run in networked games too, it is refused there otherwise
+    --engine-match <n>v<n>                  Play the computer at one depth against another, like 3v5, and print the score
+    --games <n>                             How many games the match has, 10 by default
+    --watch                                 Show the match in the window instead of playing it as fast as possible, N skips a game
+    --move-time <seconds>                   How long the computer may think about a move in a match before it loses on time, 10 by default
+    --match-report <file>                   Where the match's results are written as JSON, engine-match.json by default";
+
+pub const DEFAULT_COMPUTER_DEPTH: u32 = 3;
+
+pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 10;
+
+/// Startup options parsed from the command line
+pub struct Config {
+    // Whether no mode was given and the start screen should choose it
+    pub show_menu: bool,
+    pub opponent: Opponent,
+    // Orientation of the terminal mirror if it is enabled
+    pub tty_mirror: Option<TtyOrientation>,
+    pub no_selftest: bool,
+    pub muted: bool,
+    // Built-in theme name or theme file, loaded by main
+    pub theme: Option<String>,
+    pub no_coordinates: bool,
+    // Open the piece legend beside the board from the start
+    pub legend: bool,
+    // How the move list writes moves, coordinates if None
+    pub notation: Option<Notation>,
+    // Bigger click targets and dwell clicking, None without --accessible-input
+    pub accessible_input: Option<AccessibleInput>,
+    // Clicks out of turn pick up a move to play once the turn comes instead of shaking
+    pub premoves: bool,
+    // Changes to how a side's pieces are drawn, made to the profile's styles once it is chosen
+    pub piece_style_changes: Vec<StyleChange>,
+    // Buttons bound anew with --mouse, made to the profile's bindings once it is chosen
+    pub mouse_changes: Vec<MouseChange>,
+    pub layout_mode: LayoutMode,
+    pub portrait_ratio: f32,
+    pub time_control: Option<TimeControl>,
+    pub save_dir: Option<PathBuf>,
+    // How the glyphs of the moves are written to saved PGN
+    pub pgn_glyphs: GlyphStyle,
+    // How long the server's moves slide by how stale they are when they arrive
+    pub slide_curve: SlideCurve,
+    // Starting position, the standard one if not given
+    pub fen: Option<String>,
+    pub resume: bool,
+    // The computer switches colors in every game started over with Ctrl+N
+    pub alternate_colors: bool,
+    // Autosave file, the profile's own if not given
+    pub save_file: Option<PathBuf>,
+    // Who is playing, asked at startup if not given and there is more than one profile
+    pub profile: Option<String>,
+    // Threefold repetition and the fifty-move rule wait for a claim instead of ending the game
+    pub claim_draws: bool,
+    // Plies the computer searches in practice games forked from a networked game
+    pub practice_depth: u32,
+    // Warnings of opening traps for beginners, never in networked games
+    pub assist: bool,
+    // Show the pins against a queen or rook too, only with assist
+    pub relative_pins: bool,
+    // Moves of each kind the server rejects in a row before the client forfeits, 0 for no limit
+    pub max_illegal_moves: u32,
+    pub max_protocol_errors: u32,
+    // Two depths of the computer playing each other instead of a game
+    pub engine_match: Option<MatchConfig>,
+    // The program told about the games, and asked about moves as a filter
+    pub hook: Option<HookConfig>,
+    // Where the metrics are served on localhost, not collected at all without it
+    pub metrics_port: Option<u16>,
+    // How long the window may hang before it is reported, None not to watch for hangs
+    pub hang_report: Option<Duration>,
+    // How long the opponent may take for a move in an untimed networked game
+    pub max_move_time: Option<Duration>,
+    // A handshake the peer sends again mid-game is a protocol error instead of checked
+    pub strict_handshakes: bool,
+    // Out of order messages are a hard error instead of only logged in debug builds
+    pub audit_ordering: bool,
+    // Where the timeline of the audit is written
+    pub dump_ordering: Option<PathBuf>,
+    // Where the transcript of a networked game is written once it ends
+    pub transcript: Option<PathBuf>,
+    // Turns the update check on or off for this and every later run
+    pub check_updates: Option<UpdateSwitch>,
+    // No update check in this run whatever is remembered
+    pub offline: bool,
+}
+
+/// Who the game is played against
+pub enum Opponent {
+    // Both sides are played on this computer
+    Local,
+    Computer(ComputerConfig),
+    Network(NetworkConfig),
+}
+
+impl Opponent {
+    /// Who a game started over is played against, the same one with the computer on the other
+    /// side if `alternate_colors`. Networked games aren't started over.
+    pub fn restarted(&self, alternate_colors: bool) -> Option<Opponent> {
+        match self {
+            Opponent::Local => Some(Opponent::Local),
+            Opponent::Computer(config) => Some(Opponent::Computer(ComputerConfig {
+                depth: config.depth,
+                color: match alternate_colors {
+                    true => opposite_color(config.color),
+                    false => config.color,
+                },
+            })),
+            Opponent::Network(_) => None,
+        }
+    }
+}
+
+/// Who sits at which end of the board in a local game, as the color of the player at the
+/// bottom. The players may trade colors until the first move is played. Should a player have
+/// a time control or a name of their own, it goes with them and not with the color.
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub struct Seating {
+    pub near: jonathan_hallstrom_chess::Color,
+}
+
+impl Default for Seating {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            near: jonathan_hallstrom_chess::Color::White,
+        }
+    }
+}
+
+impl Seating {
+    /// The players trade colors, and with them the ends of the board
+    #[inline]
+    pub fn swapped(self) -> Self {
+        Self {
+            near: opposite_color(self.near),
+        }
+    }
+
+    /// Whether the players of a game against `opponent` may still trade colors after `plies`
+    #[inline]
+    pub fn can_swap(opponent: &Opponent, plies: usize) -> bool {
+        matches!(opponent, Opponent::Local) && plies == 0
+    }
+}
+
+pub struct ComputerConfig {
+    // Plies searched for every move
+    pub depth: u32,
+    pub color: jonathan_hallstrom_chess::Color,
+}
+
+pub struct NetworkConfig {
+    pub is_server: bool,
+    // As typed, only resolved by the network thread since a hostname lookup can take a while
+    pub address: String,
+    // The server fails when its port is taken instead of listening on another one
+    pub strict_port: bool,
+    // Only used by the client, the server waits until someone connects
+    pub connect_attempts: u32,
+    // Only chosen by the client, the server is told its color in the handshake
+    pub server_color: Option<chess_network_protocol::Color>,
+    // Connect as a client that only watches, the server accepts these after its player
+    pub spectate: bool,
+    // The port the connection test bound, which the server takes over instead of binding
+    pub hosting: Option<Hosting>,
+}
+
+impl Config {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.peekable();
+        let show_menu = args.peek().is_none_or(|arg| arg.starts_with("--"));
+        let (is_local, is_server) = match show_menu {
+            true => (true, false),
+            false => match args.next().as_deref() {
+                Some("local") => (true, false),
+                Some("server") => (false, true),
+                Some("client") => (false, false),
+                Some(mode) => return Err(format!("Unknown mode \"{}\".", mode)),
+                None => return Err("Missing mode.".to_owned()),
+            },
+        };
+
+        let address = match is_local {
+            true => None,
+            false => Some(parse_address(&args.next().ok_or("Missing IP address.")?)?),
+        };
+
+        let mut server_color = None;
+        let mut tty_mirror = false;
+        let mut tty_orientation = TtyOrientation::Follow;
+        let mut no_selftest = false;
+        let mut muted = false;
+        let mut theme = None;
+        let mut no_coordinates = false;
+        let mut legend = false;
+        let mut notation = None;
+        let mut accessible_input = false;
+        let mut target_expansion = None;
+        let mut dwell = None;
+        let mut premoves = false;
+        let mut piece_style_changes = Vec::new();
+        let mut mouse_changes = Vec::new();
+        let mut layout_mode = LayoutMode::Auto;
+        let mut portrait_ratio = DEFAULT_PORTRAIT_RATIO;
+        let mut time_control = None;
+        let mut save_dir = None;
+        let mut pgn_glyphs = GlyphStyle::default();
+        let mut slide_curve = SlideCurve::default();
+        let mut fen = None;
+        let mut spectate = false;
+        let mut connect_attempts = DEFAULT_CONNECT_ATTEMPTS;
+        let mut vs_computer = false;
+        let mut depth = DEFAULT_COMPUTER_DEPTH;
+        let mut practice_depth = DEFAULT_COMPUTER_DEPTH;
+        let mut assist = false;
+        let mut relative_pins = false;
+        let mut max_illegal_moves = DEFAULT_MAX_STRIKES;
+        let mut max_protocol_errors = DEFAULT_MAX_STRIKES;
+        let mut computer_color = jonathan_hallstrom_chess::Color::Black;
+        let mut alternate_colors = false;
+        let mut resume = false;
+        let mut save_file = None;
+        let mut profile = None;
+        let mut claim_draws = false;
+        let mut strict_port = false;
+        let mut match_levels = None;
+        let mut match_games = None;
+        let mut match_watch = false;
+        let mut move_time = None;
+        let mut match_report = None;
+        let mut metrics_port = None;
+        let mut hang_report = Some(DEFAULT_HANG_AFTER);
+        let mut check_updates = None;
+        let mut offline = false;
+        let mut max_move_time = None;
+        let mut strict_handshakes = false;
+        let mut audit_ordering = false;
+        let mut dump_ordering = None;
+        let mut transcript = None;
+        let mut hook = None;
+        let mut hook_timeout = None;
+        let mut hook_allow_networked = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--server-color" => {
+                    if is_local || is_server {
+                        return Err("--server-color can only be chosen by the client.".to_owned());
+                    }
+                    server_color = Some(match args.next().as_deref() {
+                        Some("white") => chess_network_protocol::Color::White,
+                        Some("black") => chess_network_protocol::Color::Black,
+                        _ => return Err("--server-color must be white or black.".to_owned()),
+                    });
+                }
+                "--spectate" => {
+                    if is_local || is_server {
+                        return Err("Only a client can --spectate.".to_owned());
+                    }
+                    spectate = true;
+                }
+                "--connect-attempts" => {
+                    if is_local || is_server {
+                        return Err("Only the client connects, the server waits.".to_owned());
+                    }
+                    connect_attempts = args
+                        .next()
+                        .and_then(|attempts| attempts.parse().ok())
+                        .filter(|attempts| *attempts > 0)
+                        .ok_or("--connect-attempts must be a positive number.")?;
+                }
+                "--strict-port" => {
+                    if !is_server {
+                        return Err("Only the server listens on a port.".to_owned());
+                    }
+                    strict_port = true;
+                }
+                "--max-illegal-moves" | "--max-protocol-errors" if !is_server => {
+                    return Err(format!("{} is only for the server.", arg));
+                }
+                "--max-illegal-moves" => {
+                    max_illegal_moves = args
+                        .next()
+                        .and_then(|max| max.parse().ok())
+                        .ok_or("--max-illegal-moves must be a number.")?;
+                }
+                "--max-protocol-errors" => {
+                    max_protocol_errors = args
+                        .next()
+                        .and_then(|max| max.parse().ok())
+                        .ok_or("--max-protocol-errors must be a number.")?;
+                }
+                "--max-move-time" if is_local => {
+                    return Err("--max-move-time is only for networked games.".to_owned());
+                }
+                "--max-move-time" => {
+                    max_move_time = Some(
+                        args.next()
+                            .as_deref()
+                            .and_then(parse_duration)
+                            .ok_or("--max-move-time must be a duration like 10m or 90s.")?,
+                    );
+                }
+                "--audit-ordering" | "--dump-ordering" | "--transcript" | "--strict-handshakes"
+                    if is_local =>
+                {
+                    return Err(format!("{} is only for networked games.", arg));
+                }
+                "--audit-ordering" => audit_ordering = true,
+                "--strict-handshakes" => strict_handshakes = true,
+                "--dump-ordering" => {
+                    dump_ordering = Some(PathBuf::from(
+                        args.next().ok_or("--dump-ordering needs a file.")?,
+                    ));
+                }
+                "--transcript" => {
+                    transcript = Some(PathBuf::from(
+                        args.next().ok_or("--transcript needs a file.")?,
+                    ));
+                }
+                "--check-updates" => {
+                    check_updates = Some(
+                        args.next()
+                            .as_deref()
+                            .and_then(UpdateSwitch::parse)
+                            .ok_or("--check-updates needs an http(s) URL or off.")?,
+                    );
+                }
+                "--offline" => offline = true,
+                "--hang-report" => {
+                    hang_report = match args.next().as_deref() {
+                        Some("off") => None,
+                        duration => Some(
+                            duration
+                                .and_then(parse_duration)
+                                .ok_or("--hang-report must be a duration like 5s or off.")?,
+                        ),
+                    };
+                }
+                "--hook" | "--hook-filter" => {
+                    let command: Vec<String> = args
+                        .next()
+                        .map(|command| command.split_whitespace().map(str::to_owned).collect())
+                        .filter(|command: &Vec<String>| !command.is_empty())
+                        .ok_or(format!("{} needs a command.", arg))?;
+                    hook = Some((command, arg == "--hook-filter"));
+                }
+                "--hook-timeout" => {
+                    hook_timeout = Some(
+                        args.next()
+                            .as_deref()
+                            .and_then(parse_duration)
+                            .ok_or("--hook-timeout must be a duration like 0.5s.")?,
+                    );
+                }
+                "--hook-allow-networked" => hook_allow_networked = true,
+                "--metrics-port" => {
+                    metrics_port = Some(
+                        args.next()
+                            .and_then(|port| port.parse().ok())
+                            .ok_or("--metrics-port must be a port number.")?,
+                    );
+                }
+                "--vs-computer" | "--depth" | "--computer-color" | "--resume" | "--save-file"
+                | "--assist" | "--relative-pins" | "--engine-match" | "--alternate-colors"
+                    if !is_local =>
+                {
+                    return Err(format!("{} is only for local games.", arg));
+                }
+                "--vs-computer" => vs_computer = true,
+                "--alternate-colors" => alternate_colors = true,
+                "--assist" => assist = true,
+                "--relative-pins" => relative_pins = true,
+                "--resume" => resume = true,
+                "--save-file" => {
+                    save_file = Some(PathBuf::from(
+                        args.next().ok_or("--save-file needs a file.")?,
+                    ));
+                }
+                "--depth" => {
+                    depth = args
+                        .next()
+                        .and_then(|depth| depth.parse().ok())
+                        .filter(|depth| *depth > 0)
+                        .ok_or("--depth must be a positive number.")?;
+                }
+                "--engine-match" => {
+                    match_levels = Some(
+                        args.next()
+                            .as_deref()
+                            .and_then(parse_levels)
+                            .ok_or("--engine-match must be two depths like 3v5.")?,
+                    );
+                }
+                "--games" => {
+                    match_games = Some(
+                        args.next()
+                            .and_then(|games| games.parse().ok())
+                            .filter(|games| *games > 0)
+                            .ok_or("--games must be a positive number.")?,
+                    );
+                }
+                "--watch" => match_watch = true,
+                "--move-time" => {
+                    move_time = Some(
+                        args.next()
+                            .and_then(|seconds| seconds.parse::<f64>().ok())
+                            .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                            .map(Duration::from_secs_f64)
+                            .ok_or("--move-time must be a positive number of seconds.")?,
+                    );
+                }
+                "--match-report" => {
+                    match_report = Some(PathBuf::from(
+                        args.next().ok_or("--match-report needs a file.")?,
+                    ));
+                }
+                "--practice-depth" => {
+                    practice_depth = args
+                        .next()
+                        .and_then(|depth| depth.parse().ok())
+                        .filter(|depth| *depth > 0)
+                        .ok_or("--practice-depth must be a positive number.")?;
+                }
+                "--computer-color" => {
+                    computer_color = match args.next().as_deref() {
+                        Some("white") => jonathan_hallstrom_chess::Color::White,
+                        Some("black") => jonathan_hallstrom_chess::Color::Black,
+                        _ => return Err("--computer-color must be white or black.".to_owned()),
+                    };
+                }
+                "--tty-mirror" => tty_mirror = true,
+                "--no-selftest" => no_selftest = true,
+                "--mute" => muted = true,
+                "--no-coordinates" => no_coordinates = true,
+                "--legend" => legend = true,
+                "--notation" => {
+                    notation = Some(args.next().as_deref().and_then(Notation::parse).ok_or(
+                        "--notation must be english, figurine or sv, de, fr, es, it or nl.",
+                    )?);
+                }
+                "--accessible-input" => accessible_input = true,
+                "--premove" => premoves = true,
+                "--target-expansion" => {
+                    target_expansion = Some(
+                        args.next()
+                            .and_then(|squares| squares.parse().ok())
+                            .filter(|squares: &f32| (0.0..=MAX_EXPANSION).contains(squares))
+                            .ok_or("--target-expansion must be a number from 0 to 0.5.")?,
+                    );
+                }
+                "--dwell" => {
+                    dwell = Some(match args.next().as_deref() {
+                        Some("off") => None,
+                        duration => Some(
+                            duration
+                                .and_then(parse_duration)
+                                .ok_or("--dwell must be a duration like 1s or off.")?,
+                        ),
+                    });
+                }
+                "--piece-tint" | "--piece-outline" | "--piece-badge" => {
+                    let part = match arg.as_str() {
+                        "--piece-tint" => Part::Tint,
+                        "--piece-outline" => Part::Outline,
+                        _ => Part::Badge,
+                    };
+                    piece_style_changes.push(
+                        args.next()
+                            .and_then(|change| StyleChange::parse(part, &change))
+                            .ok_or_else(|| {
+                                format!(
+                                    "{} must be white= or black= and a #rrggbb color or off.",
+                                    arg
+                                )
+                            })?,
+                    );
+                }
+                "--mouse" => {
+                    mouse_changes.push(
+                        args.next()
+                            .as_deref()
+                            .and_then(MouseChange::parse)
+                            .ok_or("--mouse must be like board.right=select, with a region of board, panel, entry or menu, a button of left, right, middle or button<n> and an action of select, cancel, paste or off.")?,
+                    );
+                }
+                "--claim-draws" => claim_draws = true,
+                "--layout" => {
+                    layout_mode = args
+                        .next()
+                        .as_deref()
+                        .and_then(LayoutMode::parse)
+                        .ok_or("--layout must be auto, landscape or portrait.")?;
+                }
+                "--portrait-ratio" => {
+                    portrait_ratio = args
+                        .next()
+                        .and_then(|ratio| ratio.parse().ok())
+                        .filter(|ratio: &f32| ratio.is_finite() && *ratio > 0.0)
+                        .ok_or("--portrait-ratio must be a positive number.")?;
+                }
+                "--profile" => {
+                    let name = args.next().ok_or("--profile needs a name.")?;
+                    profile = Some(profiles::check_name(&name)?.to_owned());
+                }
+                "--save-dir" => {
+                    save_dir = Some(PathBuf::from(
+                        args.next().ok_or("--save-dir needs a directory.")?,
+                    ));
+                }
+                "--pgn-glyphs" => {
+                    pgn_glyphs = args
+                        .next()
+                        .as_deref()
+                        .and_then(GlyphStyle::parse)
+                        .ok_or("--pgn-glyphs must be suffix or nag.")?;
+                }
+                "--slide-latency" => {
+                    slide_curve = args
+                        .next()
+                        .as_deref()
+                        .and_then(SlideCurve::parse)
+                        .ok_or("--slide-latency must be milliseconds like 40,300, the smaller first and neither past 2000.")?;
+                }
+                "--time" => {
+                    time_control = Some(
+                        args.next()
+                            .as_deref()
+                            .and_then(TimeControl::parse)
+                            .ok_or("--time must be minutes and increment seconds like 5+3.")?,
+                    );
+                }
+                "--theme" => theme = Some(args.next().ok_or("--theme needs a name or file.")?),
+                "--fen" => {
+                    if !is_local && !is_server {
+                        return Err(
+                            "The client gets the starting position from the server.".to_owned()
+                        );
+                    }
+                    fen = Some(args.next().ok_or("--fen needs a position.")?);
+                }
+                "--tty-orientation" => {
+                    tty_orientation = args
+                        .next()
+                        .as_deref()
+                        .and_then(TtyOrientation::parse)
+                        .ok_or("--tty-orientation must be white, black or follow.")?;
+                }
+                _ => return Err(format!("Unknown argument \"{}\".", arg)),
+            }
+        }
+
+        if match_levels.is_none()
+            && (match_games.is_some()
+                || match_watch
+                || move_time.is_some()
+                || match_report.is_some())
+        {
+            return Err(
+                "--games, --watch, --move-time and --match-report only go with --engine-match."
+                    .to_owned(),
+            );
+        }
+        let engine_match = match_levels.map(|(first, second)| MatchConfig {
+            first,
+            second,
+            games: match_games.unwrap_or(DEFAULT_MATCH_GAMES),
+            move_time: move_time.unwrap_or(DEFAULT_MOVE_TIME),
+            watch: match_watch,
+            report: match_report.unwrap_or_else(|| PathBuf::from(DEFAULT_REPORT)),
+        });
+
+        if relative_pins && !assist {
+            return Err("--relative-pins only goes with --assist.".to_owned());
+        }
+        if !accessible_input && (target_expansion.is_some() || dwell.is_some()) {
+            return Err(
+                "--target-expansion and --dwell only go with --accessible-input.".to_owned(),
+            );
+        }
+        let accessible_input = accessible_input.then(|| {
+            let default = AccessibleInput::default();
+            AccessibleInput {
+                expansion: target_expansion.unwrap_or(default.expansion),
+                dwell: dwell.unwrap_or(default.dwell),
+            }
+        });
+
+        if max_move_time.is_some() && time_control.is_some() {
+            return Err(
+                "--max-move-time is only for untimed games, --time already limits them.".to_owned(),
+            );
+        }
+
+        if hook.is_none() && (hook_timeout.is_some() || hook_allow_networked) {
+            return Err(
+                "--hook-timeout and --hook-allow-networked only go with --hook or --hook-filter."
+                    .to_owned(),
+            );
+        }
+        if hook.is_some() && !is_local && !hook_allow_networked {
+            return Err(
+                "The hook doesn't run in networked games unless --hook-allow-networked is given."
+                    .to_owned(),
+            );
+        }
+        let hook = hook.map(|(command, filter)| HookConfig {
+            command,
+            filter,
+            timeout: hook_timeout.unwrap_or(DEFAULT_FILTER_TIMEOUT),
+            allow_networked: hook_allow_networked,
+        });
+
+        if !is_local && !is_server && !spectate && server_color.is_none() {
+            return Err(
+                "The client has to choose the server color with --server-color.".to_owned(),
+            );
+        }
+
+        Ok(Self {
+            // Choosing the computer or resuming is enough to skip the start screen
+            show_menu: show_menu && !vs_computer && !resume && engine_match.is_none(),
+            opponent: match address {
+                Some(address) => Opponent::Network(NetworkConfig {
+                    is_server,
+                    address,
+                    strict_port,
+                    connect_attempts,
+                    server_color,
+                    spectate,
+                    hosting: None,
+                }),
+                None if vs_computer => Opponent::Computer(ComputerConfig {
+                    depth,
+                    color: computer_color,
+                }),
+                None => Opponent::Local,
+            },
+            tty_mirror: match tty_mirror {
+                true => Some(tty_orientation),
+                false => None,
+            },
+            no_selftest,
+            muted,
+            theme,
+            no_coordinates,
+            legend,
+            notation,
+            accessible_input,
+            premoves,
+            piece_style_changes,
+            mouse_changes,
+            layout_mode,
+            portrait_ratio,
+            time_control,
+            save_dir,
+            pgn_glyphs,
+            slide_curve,
+            fen,
+            resume,
+            alternate_colors,
+            save_file,
+            profile,
+            claim_draws,
+            practice_depth,
+            assist,
+            relative_pins,
+            max_illegal_moves,
+            max_protocol_errors,
+            engine_match,
+            hook,
+            metrics_port,
+            hang_report,
+            max_move_time,
+            strict_handshakes,
+            audit_ordering,
+            dump_ordering,
+            transcript,
+            check_updates,
+            offline,
+        })
+    }
+}
+
+/// What the validate subcommand checks, see `validate::run`
+pub struct Validation {
+    // None reads every message as whichever it is
+    pub direction: Option<Direction>,
+    pub context_fen: Option<String>,
+    pub sequence: bool,
+    pub json: bool,
+    // The standard input without one
+    pub input: Option<PathBuf>,
+}
+
+impl Validation {
+    /// The arguments after "validate"
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args = args;
+        let mut validation = Self {
+            direction: None,
+            context_fen: None,
+            sequence: false,
+            json: false,
+            input: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--type" => {
+                    validation.direction = match args.next().as_deref() {
+                        Some("auto") => None,
+                        name => Some(name.and_then(Direction::parse).ok_or(
+                            "--type must be server-to-client, client-to-server, handshake-s2c, handshake-c2s or auto.",
+                        )?),
+                    };
+                }
+                "--context-fen" => {
+                    validation.context_fen =
+                        Some(args.next().ok_or("--context-fen needs a position.")?);
+                }
+                "--sequence" => validation.sequence = true,
+                "--json" => validation.json = true,
+                _ if arg.starts_with("--") => {
+                    return Err(format!("Unknown argument \"{}\".", arg));
+                }
+                _ if validation.input.is_some() => {
+                    return Err("Only one file is validated at a time.".to_owned());
+                }
+                _ => validation.input = Some(PathBuf::from(arg)),
+            }
+        }
+        if validation.sequence && validation.context_fen.is_some() {
+            return Err(
+                "A --sequence takes its position from the server's handshake, not --context-fen."
+                    .to_owned(),
+            );
+        }
+        if validation.sequence && validation.direction.is_some() {
+            return Err(
+                "A --sequence tells the directions apart itself, leave out --type.".to_owned(),
+            );
+        }
+        Ok(validation)
+    }
+}
+
+/// Check the shape of an address like "192.168.1.10:5000", "[::1]:5000", "localhost:5000"
+/// or just "5000" before anything tries to connect to it. Hostnames are looked up later.
+pub fn parse_address(address: &str) -> Result<String, String> {
+    let address = address.trim();
+    let port = match address.rsplit_once(':') {
+        // An IPv6 address without brackets has colons but no port
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => port,
+        Some(_) => "",
+        None => address,
+    };
+    match port.parse::<u16>() {
+        Ok(_) => Ok(address.to_owned()),
+        Err(_) => Err(format!(
+            "Invalid address \"{}\", expected an address and port like 127.0.0.1:8080.",
+            address
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Addresses as typed on the command line or the start screen, and whether they are accepted
+    const ADDRESSES: [(&str, bool); 8] = [
+        ("127.0.0.1:5000", true),
+        ("[::1]:5000", true),
+        ("localhost:5000", true),
+        ("5000", true),
+        (":5000", true),
+        ("::1", false),
+        ("127.0.0.1", false),
+        ("127.0.0.1:70000", false),
+    ];
+
+    #[test]
+    fn address_forms() {
+        for (address, valid) in ADDRESSES {
+            assert_eq!(parse_address(address).is_ok(), valid, "{}", address);
+        }
+    }
+
+    #[test]
+    fn restarted_games_keep_their_opponent() {
+        use jonathan_hallstrom_chess::Color;
+        let args = |line: &str| {
+            line.split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        let config = Config::from_args(
+            args("local --vs-computer --depth 5 --computer-color white").into_iter(),
+        )
+        .unwrap();
+        assert!(!config.alternate_colors);
+        for (alternate, color) in [(false, Color::White), (true, Color::Black)] {
+            let Some(Opponent::Computer(computer)) = config.opponent.restarted(alternate) else {
+                panic!("not against the computer");
+            };
+            assert_eq!(computer.depth, 5);
+            assert!(computer.color == color);
+        }
+        assert!(matches!(
+            Opponent::Local.restarted(true),
+            Some(Opponent::Local)
+        ));
+
+        let networked = Config::from_args(args("server 5000").into_iter()).unwrap();
+        assert!(networked.opponent.restarted(false).is_none());
+        assert!(Config::from_args(args("server 5000 --alternate-colors").into_iter()).is_err());
+        assert!(
+            Config::from_args(args("local --vs-computer --alternate-colors").into_iter())
+                .unwrap()
+                .alternate_colors
+        );
+    }
+
+    #[test]
+    fn seats_are_only_swapped_before_the_first_local_move() {
+        use jonathan_hallstrom_chess::Color;
+        let seating = Seating::default();
+        assert!(seating.near == Color::White);
+        assert!(seating.swapped().near == Color::Black);
+        assert!(seating.swapped().swapped() == seating);
+        assert!((0..7).fold(seating, |seating, _| seating.swapped()) == seating.swapped());
+
+        assert!(Seating::can_swap(&Opponent::Local, 0));
+        assert!(!Seating::can_swap(&Opponent::Local, 1));
+        let computer = Opponent::Computer(ComputerConfig {
+            depth: 2,
+            color: Color::Black,
+        });
+        assert!(!Seating::can_swap(&computer, 0));
+    }
+
+    #[test]
+    fn hooks_are_refused_in_networked_games_unless_allowed() {
+        let args = |line: &str| {
+            line.split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        let config =
+            Config::from_args(args("local --hook-filter ./filter --hook-timeout 2s").into_iter())
+                .unwrap();
+        assert_eq!(
+            config.hook,
+            Some(HookConfig {
+                command: vec!["./filter".to_owned()],
+                filter: true,
+                timeout: Duration::from_secs(2),
+                allow_networked: false,
+            })
+        );
+        assert!(Config::from_args(args("server 5000 --hook ./bot").into_iter()).is_err());
+        assert!(Config::from_args(
+            args("server 5000 --hook ./bot --hook-allow-networked").into_iter()
+        )
+        .unwrap()
+        .hook
+        .is_some_and(|hook| !hook.filter && hook.allow_networked));
+        assert!(Config::from_args(args("local --hook-timeout 1s").into_iter()).is_err());
+    }
+
+    #[test]
+    fn validation_options() {
+        let args = |line: &str| {
+            line.split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        let validation =
+            Validation::from_args(args("state.json --type server-to-client --json")).unwrap();
+        assert!(validation.direction == Some(Direction::ServerToClient));
+        assert!(validation.json && validation.input == Some(PathBuf::from("state.json")));
+        assert!(Validation::from_args(args("--type auto"))
+            .unwrap()
+            .direction
+            .is_none());
+        assert!(Validation::from_args(args("--type sideways")).is_err());
+        assert!(Validation::from_args(args("a.json b.json")).is_err());
+        assert!(Validation::from_args(args("--sequence --context-fen 8/8/8/8/8/8/8/8")).is_err());
+    }
+}
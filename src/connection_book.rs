@@ -0,0 +1,510 @@
+use crate::config::{parse_address, NetworkConfig, DEFAULT_CONNECT_ATTEMPTS};
+use crate::pgn;
+use chess_network_protocol::{Color, Features};
+use serde_json::{Map, Value};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+/// Kept in the profile directory
+pub const FILE_NAME: &str = "connections.json";
+/// The most entries kept, the ones used longest ago that aren't pinned go first
+pub const CAPACITY: usize = 32;
+
+/// Someone played over the network. The protocol exchanges no names, so an entry is known by
+/// its address until it is given a nickname.
+#[derive(Clone, PartialEq)]
+pub struct Entry {
+    // As entered, a hostname stays a hostname so it keeps working when the address behind it
+    // changes
+    pub address: String,
+    pub nickname: Option<String>,
+    // Whether this side hosted, the address is the one listened on then
+    pub as_server: bool,
+    // What the client asked the server to play
+    pub server_color: Option<Color>,
+    // Seconds since 1970
+    pub last_used: u64,
+    // "won", "lost" or "drawn" for this side, None until a game there ended
+    pub result: Option<String>,
+    // What the other side advertised in the handshake, by name
+    pub features: Vec<String>,
+    // Pinned entries are listed first and never pruned
+    pub pinned: bool,
+    // The random handle this side played the last game under, None under its own name
+    pub handle: Option<String>,
+}
+
+/// What choosing an entry on the start screen does
+pub enum Pick {
+    // Join it again right away
+    Connect(NetworkConfig),
+    // Fill in the host screen, hosting waits for the other side to be told
+    Host(String),
+}
+
+#[inline]
+pub fn feature_name(feature: &Features) -> &'static str {
+    match feature {
+        Features::Castling => "castling",
+        Features::EnPassant => "en passant",
+        Features::Promotion => "promotion",
+        Features::Stalemate => "stalemate",
+        _ => "other",
+    }
+}
+
+// Hostnames are the same whatever their case, and typed spaces don't make an address new
+#[inline]
+fn same_address(a: &str, b: &str) -> bool {
+    a.trim().eq_ignore_ascii_case(b.trim())
+}
+
+impl Entry {
+    /// How it is listed, like "Alva 2026-10-14 won as SwiftGambit"
+    pub fn label(&self) -> String {
+        let (year, month, day) = pgn::civil_date((self.last_used / 86_400) as i64);
+        let mut label = format!(
+            "{} {:04}-{:02}-{:02}",
+            self.nickname.as_deref().unwrap_or(&self.address),
+            year,
+            month,
+            day
+        );
+        if let Some(result) = &self.result {
+            label.push(' ');
+            label.push_str(result);
+        }
+        if let Some(handle) = &self.handle {
+            label.push_str(" as ");
+            label.push_str(handle);
+        }
+        label
+    }
+
+    /// What choosing it on the start screen does, None if its address can't be used anymore
+    pub fn pick(&self) -> Option<Pick> {
+        let address = parse_address(&self.address).ok()?;
+        Some(match self.as_server {
+            true => Pick::Host(address),
+            false => Pick::Connect(NetworkConfig {
+                is_server: false,
+                address,
+                strict_port: false,
+                connect_attempts: DEFAULT_CONNECT_ATTEMPTS,
+                server_color: Some(self.server_color.unwrap_or(Color::White)),
+                spectate: false,
+                hosting: None,
+            }),
+        })
+    }
+
+    /// Fold `older`, an entry of the same address, into this newer one. What was last seen
+    /// of the other side is the newer entry's, what the user set lives on: the newer nickname
+    /// wins if both have one, and either pin keeps it pinned.
+    fn merge(&mut self, older: Entry) {
+        if self.nickname.is_none() {
+            self.nickname = older.nickname;
+        }
+        self.pinned |= older.pinned;
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("address".to_owned(), Value::String(self.address.clone()));
+        object.insert(
+            "nickname".to_owned(),
+            self.nickname.clone().map_or(Value::Null, Value::String),
+        );
+        object.insert("as_server".to_owned(), Value::Bool(self.as_server));
+        object.insert(
+            "server_color".to_owned(),
+            self.server_color.map_or(Value::Null, |color| {
+                Value::String(
+                    match color {
+                        Color::White => "white",
+                        Color::Black => "black",
+                    }
+                    .to_owned(),
+                )
+            }),
+        );
+        object.insert("last_used".to_owned(), Value::Number(self.last_used.into()));
+        object.insert(
+            "result".to_owned(),
+            self.result.clone().map_or(Value::Null, Value::String),
+        );
+        object.insert(
+            "features".to_owned(),
+            Value::Array(self.features.iter().cloned().map(Value::String).collect()),
+        );
+        object.insert("pinned".to_owned(), Value::Bool(self.pinned));
+        object.insert(
+            "handle".to_owned(),
+            self.handle.clone().map_or(Value::Null, Value::String),
+        );
+        Value::Object(object)
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            address: value.get("address")?.as_str()?.to_owned(),
+            nickname: value
+                .get("nickname")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            as_server: value
+                .get("as_server")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            server_color: match value.get("server_color").and_then(Value::as_str) {
+                Some("white") => Some(Color::White),
+                Some("black") => Some(Color::Black),
+                _ => None,
+            },
+            last_used: value.get("last_used").and_then(Value::as_u64).unwrap_or(0),
+            result: value
+                .get("result")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            features: value
+                .get("features")
+                .and_then(Value::as_array)
+                .map(|features| {
+                    features
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            pinned: value
+                .get("pinned")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            handle: value
+                .get("handle")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        })
+    }
+}
+
+/// The people played over the network, one entry for every address. Listed pinned first,
+/// then the ones used last.
+#[derive(Clone, PartialEq, Default)]
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    #[inline]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    fn sort(&mut self) {
+        self.entries
+            .sort_by_key(|entry| Reverse((entry.pinned, entry.last_used)));
+    }
+
+    /// Keep `entry`, merged with the one there already is for its address. Returns what
+    /// changed about the other side since the last time, to be told.
+    pub fn record(&mut self, mut entry: Entry) -> Option<String> {
+        let mut change = None;
+        if let Some(index) = self
+            .entries
+            .iter()
+            .position(|known| same_address(&known.address, &entry.address))
+        {
+            let known = self.entries.remove(index);
+            let added: Vec<&str> = entry
+                .features
+                .iter()
+                .filter(|feature| !known.features.contains(feature))
+                .map(String::as_str)
+                .collect();
+            let dropped: Vec<&str> = known
+                .features
+                .iter()
+                .filter(|feature| !entry.features.contains(feature))
+                .map(String::as_str)
+                .collect();
+            let mut changes = Vec::new();
+            if !added.is_empty() {
+                changes.push(format!("now advertises {}", added.join(", ")));
+            }
+            if !dropped.is_empty() {
+                changes.push(format!("no longer advertises {}", dropped.join(", ")));
+            }
+            if !changes.is_empty() {
+                change = Some(format!(
+                    "Their implementation changed: {}",
+                    changes.join(", ")
+                ));
+            }
+            entry.merge(known);
+        }
+        self.entries.push(entry);
+        self.sort();
+        self.prune(CAPACITY);
+        change
+    }
+
+    /// Remember how the last game at `address` ended
+    pub fn finish(&mut self, address: &str, result: &str) {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| same_address(&entry.address, address))
+        {
+            entry.result = Some(result.to_owned());
+        }
+    }
+
+    /// Forget the unpinned entries used longest ago until at most `capacity` are left
+    pub fn prune(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            // Sorted, so the last unpinned one is the one used longest ago
+            let Some(index) = self.entries.iter().rposition(|entry| !entry.pinned) else {
+                return;
+            };
+            self.entries.remove(index);
+        }
+    }
+
+    /// Give the entry at `index` a nickname, or take it away with an empty one
+    pub fn rename(&mut self, index: usize, nickname: &str) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            let nickname = nickname.trim();
+            entry.nickname = (!nickname.is_empty()).then(|| nickname.to_owned());
+        }
+    }
+
+    pub fn toggle_pin(&mut self, index: usize) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.pinned = !entry.pinned;
+        }
+        self.sort();
+    }
+
+    #[inline]
+    pub fn remove(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.entries.remove(index);
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        Value::Array(self.entries.iter().map(Entry::to_json).collect())
+    }
+
+    fn from_json(value: &Value) -> Self {
+        let mut entries: Vec<Entry> = value
+            .as_array()
+            .map(|entries| entries.iter().filter_map(Entry::from_json).collect())
+            .unwrap_or_default();
+        // Oldest first, so an edited file with an address twice merges like recording does
+        entries.sort_by_key(|entry| entry.last_used);
+        let mut book = Self::default();
+        for entry in entries {
+            book.record(entry);
+        }
+        book
+    }
+
+    /// The book kept at `path`, empty if there is none or it is damaged
+    pub fn read(path: &Path) -> Self {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => Self::from_json(&value),
+            Err(err) => {
+                println!(
+                    "Ignoring the damaged connection book {}: {}",
+                    path.display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Can't save the connection book: {}", err))?;
+        }
+        serde_json::to_string(&self.to_json())
+            .map_err(std::io::Error::from)
+            .and_then(|text| fs::write(path, text))
+            .map_err(|err| format!("Can't save the connection book: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    fn entry(address: &str, last_used: u64) -> Entry {
+        Entry {
+            address: address.to_owned(),
+            nickname: None,
+            as_server: false,
+            server_color: Some(Color::Black),
+            last_used,
+            result: None,
+            features: vec!["castling".to_owned()],
+            pinned: false,
+            handle: None,
+        }
+    }
+
+    #[test]
+    fn the_book_reads_back() {
+        let mut book = Book::default();
+        book.record(Entry {
+            nickname: Some("Alva".to_owned()),
+            result: Some("won".to_owned()),
+            pinned: true,
+            handle: Some("SwiftGambit".to_owned()),
+            ..entry("alva-laptop.local:8080", 1_700_000_000)
+        });
+        book.record(Entry {
+            as_server: true,
+            server_color: None,
+            features: Vec::new(),
+            ..entry("0.0.0.0:8080", 1_700_000_500)
+        });
+        let scratch = Scratch::new("connections");
+        let path = scratch.join("connections.json");
+        book.write(&path).unwrap();
+        assert!(Book::read(&path) == book);
+        // The hostname is kept as entered, nothing resolves it
+        assert_eq!(book.entries()[0].address, "alva-laptop.local:8080");
+        fs::remove_file(&path).unwrap();
+        assert!(Book::read(&path) == Book::default());
+    }
+
+    #[test]
+    fn the_unpinned_entries_used_longest_ago_are_pruned() {
+        let mut book = Book::default();
+        book.record(Entry {
+            pinned: true,
+            ..entry("oldest:1", 1)
+        });
+        for seconds in 2..=CAPACITY as u64 + 5 {
+            book.record(entry(&format!("host:{}", seconds), seconds));
+        }
+        assert_eq!(book.entries().len(), CAPACITY);
+        assert_eq!(book.entries()[0].address, "oldest:1");
+        assert_eq!(book.entries()[1].last_used, CAPACITY as u64 + 5);
+        // The pinned one and the newest all but one of the rest
+        assert_eq!(book.entries()[CAPACITY - 1].last_used, 7);
+
+        book.prune(0);
+        assert_eq!(book.entries().len(), 1);
+    }
+
+    /// One entry for every address whatever its case, the newer nickname wins and the older
+    /// one is kept when the newer has none
+    #[test]
+    fn entries_of_the_same_address_merge() {
+        let mut book = Book::default();
+        book.record(Entry {
+            nickname: Some("Alva".to_owned()),
+            pinned: true,
+            ..entry("Alva-Laptop:8080", 10)
+        });
+        assert_eq!(book.record(entry("alva-laptop:8080", 20)), None);
+        assert_eq!(book.entries().len(), 1);
+        assert_eq!(book.entries()[0].nickname.as_deref(), Some("Alva"));
+        assert!(book.entries()[0].pinned);
+        assert_eq!(book.entries()[0].last_used, 20);
+        assert_eq!(book.entries()[0].address, "alva-laptop:8080");
+
+        book.record(Entry {
+            nickname: Some("Alva B".to_owned()),
+            ..entry("alva-laptop:8080", 30)
+        });
+        assert_eq!(book.entries()[0].nickname.as_deref(), Some("Alva B"));
+
+        // A file edited to have it twice reads back as one
+        let mut twice = Value::Array(vec![
+            entry("alva-laptop:8080", 5).to_json(),
+            book.entries()[0].to_json(),
+        ]);
+        if let Value::Array(entries) = &mut twice {
+            entries.push(entry("bo:9000", 1).to_json());
+        }
+        let read = Book::from_json(&twice);
+        assert_eq!(read.entries().len(), 2);
+        assert!(read.entries()[0] == book.entries()[0]);
+    }
+
+    #[test]
+    fn a_change_of_features_is_told_and_remembered() {
+        let mut book = Book::default();
+        book.record(Entry {
+            features: Vec::new(),
+            ..entry("bo:9000", 1)
+        });
+        assert_eq!(
+            book.record(entry("bo:9000", 2)).as_deref(),
+            Some("Their implementation changed: now advertises castling")
+        );
+        assert_eq!(book.entries()[0].features, ["castling"]);
+        assert_eq!(
+            book.record(Entry {
+                features: vec!["stalemate".to_owned()],
+                ..entry("bo:9000", 3)
+            })
+            .as_deref(),
+            Some(
+                "Their implementation changed: now advertises stalemate, no longer advertises \
+                 castling"
+            )
+        );
+        assert_eq!(book.record(entry("other:9000", 4)), None);
+    }
+
+    /// What the start screen does with an entry: joining one connects again as it did,
+    /// hosting one only fills in the host screen
+    #[test]
+    fn picking_an_entry_connects_again_or_fills_in_the_host_screen() {
+        match entry("alva-laptop:8080", 1).pick() {
+            Some(Pick::Connect(config)) => {
+                assert!(!config.is_server);
+                assert_eq!(config.address, "alva-laptop:8080");
+                assert!(config.server_color == Some(Color::Black));
+            }
+            _ => panic!("a joined entry connects"),
+        }
+        let hosted = Entry {
+            as_server: true,
+            ..entry("0.0.0.0:8080", 1)
+        };
+        assert!(matches!(hosted.pick(), Some(Pick::Host(address)) if address == "0.0.0.0:8080"));
+        assert!(entry("no port", 1).pick().is_none());
+
+        let mut book = Book::default();
+        book.record(entry("bo:9000", 1));
+        book.record(entry("alva:8080", 2));
+        book.toggle_pin(1);
+        assert_eq!(book.entries()[0].address, "bo:9000");
+        book.rename(0, "  Bo ");
+        assert_eq!(book.entries()[0].label(), "Bo 1970-01-01");
+        book.rename(0, "");
+        book.finish("BO:9000", "drawn");
+        assert_eq!(book.entries()[0].label(), "bo:9000 1970-01-01 drawn");
+        let anonymous = Entry {
+            handle: Some("SwiftGambit".to_owned()),
+            ..entry("bo:9000", 1)
+        };
+        assert_eq!(anonymous.label(), "bo:9000 1970-01-01 as SwiftGambit");
+        book.remove(0);
+        assert_eq!(book.entries().len(), 1);
+    }
+}
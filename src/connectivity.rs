@@ -0,0 +1,494 @@
+use crate::config::parse_address;
+use crate::network;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The port pairing codes leave out, the one the start screen suggests
+pub const DEFAULT_PORT: u16 = 8080;
+/// Starts every ping. JSON never starts with a NUL, so the game's framing fails on a ping as
+/// malformed instead of reading it as a message.
+pub const PING_MAGIC: u8 = 0;
+// How many pings the guest sends, the latency shown is their average
+const ROUND_TRIPS: u8 = 3;
+// Every step of the test gives up after this, the same as the game's connection attempts
+const TEST_TIMEOUT: Duration = Duration::from_secs(3);
+// How often the responder looks for new connections and whether it should stop
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+/// The digit a pairing code starts with, so a code from another network isn't taken as an
+/// address on this one
+#[inline]
+fn prefix_digit(first: u8, second: u8) -> u32 {
+    (first as u32 * 7 + second as u32 * 3) % 10
+}
+
+/// The code the guest types instead of the host's address: a digit checking the first two
+/// octets, the last two as one five digit number, and the port unless it is the default
+/// one. None for an address that isn't on a private LAN, which can only be typed in full.
+pub fn pairing_code(address: SocketAddrV4) -> Option<String> {
+    let ip = address.ip();
+    if !ip.is_private() {
+        return None;
+    }
+    let [first, second, third, fourth] = ip.octets();
+    let mut code = format!(
+        "{}{:05}",
+        prefix_digit(first, second),
+        u16::from_be_bytes([third, fourth])
+    );
+    if address.port() != DEFAULT_PORT {
+        code.push_str(&format!("{:05}", address.port()));
+    }
+    Some(code)
+}
+
+/// A code in groups of three digits, so it is easy to read out, like "812 345"
+pub fn grouped(code: &str) -> String {
+    let digits: Vec<char> = code.chars().collect();
+    digits
+        .chunks(3)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The host's address from a pairing code, on the LAN of the guest at `own`
+pub fn decode_pairing_code(code: &str, own: Ipv4Addr) -> Result<SocketAddrV4, String> {
+    let digits: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect();
+    let invalid = || {
+        format!(
+            "\"{}\" isn't a pairing code, those have 6 or 11 digits",
+            code.trim()
+        )
+    };
+    if !digits.chars().all(|c| c.is_ascii_digit()) || (digits.len() != 6 && digits.len() != 11) {
+        return Err(invalid());
+    }
+    let number =
+        |range: std::ops::Range<usize>| digits[range].parse::<u32>().map_err(|_| invalid());
+    let check = number(0..1)?;
+    let host = u16::try_from(number(1..6)?).map_err(|_| invalid())?;
+    let port = match digits.len() {
+        11 => u16::try_from(number(6..11)?)
+            .ok()
+            .filter(|port| *port != 0)
+            .ok_or_else(invalid)?,
+        _ => DEFAULT_PORT,
+    };
+    let [first, second, ..] = own.octets();
+    if !own.is_private() || prefix_digit(first, second) != check {
+        return Err(
+            "That code is from another network, type the host's full address instead".to_owned(),
+        );
+    }
+    let [third, fourth] = host.to_be_bytes();
+    Ok(SocketAddrV4::new(
+        Ipv4Addr::new(first, second, third, fourth),
+        port,
+    ))
+}
+
+/// What the guest typed as an address to connect to: a full address as on the start
+/// screen, or a pairing code decoded against `own`, this computer's LAN address
+pub fn host_address(typed: &str, own: Option<Ipv4Addr>) -> Result<String, String> {
+    let typed = typed.trim();
+    if typed.contains(['.', ':', '[']) || typed.chars().any(|c| c.is_ascii_alphabetic()) {
+        return parse_address(typed);
+    }
+    let own = own.ok_or("This computer isn't on a network, type the host's full address")?;
+    decode_pairing_code(typed, own).map(|address| address.to_string())
+}
+
+/// This computer's address on its LAN, the one the way to the internet leaves from.
+/// Nothing is sent to find it.
+pub fn lan_address() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    // An address reserved for documentation, only the route to it matters
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() && !ip.is_loopback() => Some(ip),
+        _ => None,
+    }
+}
+
+/// A ping is the magic byte, the payload's length in one byte and the payload
+fn write_ping(mut stream: &TcpStream, payload: &[u8]) -> Result<(), Error> {
+    let mut frame = vec![PING_MAGIC, payload.len() as u8];
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+fn read_ping(mut stream: &TcpStream) -> Result<Vec<u8>, Error> {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head)?;
+    if head[0] != PING_MAGIC {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "That isn't a connection test",
+        ));
+    }
+    let mut payload = vec![0; head[1] as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Whether a connection is a connection test rather than a player, looked at without
+/// reading anything. Blocks until the other end sends something, which players do first.
+pub fn is_ping(stream: &TcpStream) -> bool {
+    let mut first = [0; 1];
+    matches!(stream.peek(&mut first), Ok(1)) && first[0] == PING_MAGIC
+}
+
+/// Send every ping back until the other end hangs up
+pub fn answer_pings(stream: TcpStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(TEST_TIMEOUT))?;
+    loop {
+        match read_ping(&stream) {
+            Ok(payload) => write_ping(&stream, &payload)?,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// How the guest's connection test came out
+#[derive(PartialEq, Clone, Debug)]
+pub enum Verdict {
+    // With the average time a ping took there and back
+    Reachable(Duration),
+    // Why, in words the players can act on
+    Unreachable(String),
+}
+
+impl Verdict {
+    pub fn describe(&self) -> String {
+        match self {
+            Verdict::Reachable(latency) => {
+                format!("Reachable, {}ms, ready to play", latency.as_millis())
+            }
+            Verdict::Unreachable(why) => why.clone(),
+        }
+    }
+}
+
+/// What most likely went wrong when the test failed like this
+pub fn diagnose(err: &Error) -> String {
+    match err.kind() {
+        ErrorKind::ConnectionRefused => "Nothing answers on that port. Is the host's connection \
+            test open, on the same port?"
+            .to_owned(),
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => "No answer at all. A firewall on the host \
+            may block the port, or the computers aren't on the same network."
+            .to_owned(),
+        ErrorKind::NotFound => "No computer has that name on this network.".to_owned(),
+        ErrorKind::InvalidData => "Something else answers on that port, not this game.".to_owned(),
+        ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset => {
+            "The host hung up during the test, try again.".to_owned()
+        }
+        _ => format!("The test failed: {}", err),
+    }
+}
+
+/// Connect to the host at `address` and time a few pings there and back. Blocks for up to a
+/// few seconds, so it is run on a thread of its own.
+pub fn test_reach(address: &str) -> Verdict {
+    let timed = || -> Result<Duration, Error> {
+        let mut last_err = Error::new(ErrorKind::NotFound, "No address to try");
+        let mut connected = None;
+        for address in network::resolve(address, false)? {
+            match TcpStream::connect_timeout(&address, TEST_TIMEOUT) {
+                Ok(stream) => {
+                    connected = Some(stream);
+                    break;
+                }
+                Err(err) => last_err = err,
+            }
+        }
+        let stream = connected.ok_or(last_err)?;
+        stream.set_read_timeout(Some(TEST_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut total = Duration::ZERO;
+        for round in 0..ROUND_TRIPS {
+            let payload = [round; 8];
+            let sent = Instant::now();
+            write_ping(&stream, &payload)?;
+            if read_ping(&stream)? != payload {
+                return Err(Error::from(ErrorKind::InvalidData));
+            }
+            total += sent.elapsed();
+        }
+        Ok(total / ROUND_TRIPS as u32)
+    };
+    match timed() {
+        Ok(latency) => Verdict::Reachable(latency),
+        Err(err) => Verdict::Unreachable(diagnose(&err)),
+    }
+}
+
+/// The host's listener, bound by the connection test, and the player if one connected
+/// before the game took over. The game takes its player from these instead of binding again.
+pub struct Hosting {
+    pub listener: TcpListener,
+    pub joined: Option<TcpStream>,
+}
+
+impl Hosting {
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Self {
+            listener: self.listener.try_clone()?,
+            joined: self.joined.as_ref().map(TcpStream::try_clone).transpose()?,
+        })
+    }
+}
+
+/// The host's side of the connection test, answering pings on the game's port until the game
+/// takes the port over
+pub struct Responder {
+    pub listening: SocketAddr,
+    stop: Arc<AtomicBool>,
+    // Where the guests who finished a test connected from
+    tested: Receiver<SocketAddr>,
+    joined: Receiver<TcpStream>,
+    // Connections not yet told apart as a test or a player
+    deciding: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<TcpListener>>,
+}
+
+/// Answer a connection test, or pass on a player who connected for the game already
+fn answer(
+    stream: TcpStream,
+    deciding: &AtomicUsize,
+    tested: &Sender<SocketAddr>,
+    joined: &Sender<TcpStream>,
+) {
+    let peer = stream.peer_addr();
+    let ping = stream.set_nonblocking(false).is_ok() && is_ping(&stream);
+    if !ping {
+        let _ = joined.send(stream);
+        deciding.fetch_sub(1, Ordering::Relaxed);
+        return;
+    }
+    deciding.fetch_sub(1, Ordering::Relaxed);
+    match (answer_pings(stream), peer) {
+        (Ok(()), Ok(peer)) => {
+            let _ = tested.send(peer);
+        }
+        (Err(err), _) => println!("Connection test failed: {}", err),
+        _ => {}
+    }
+}
+
+impl Responder {
+    /// Listen on `address` like the server does, a port alone being every address
+    pub fn start(address: &str) -> Result<Self, Error> {
+        let listener = network::bind(&network::resolve(address, true)?, false)?;
+        let listening = listener.local_addr()?;
+        // So it can look at whether to stop between connections
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let (tested_sender, tested) = channel();
+        let (joined_sender, joined) = channel();
+        let deciding = Arc::new(AtomicUsize::new(0));
+        let thread_deciding = Arc::clone(&deciding);
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let (tested, joined) = (tested_sender.clone(), joined_sender.clone());
+                        let deciding = Arc::clone(&thread_deciding);
+                        deciding.fetch_add(1, Ordering::Relaxed);
+                        thread::spawn(move || answer(stream, &deciding, &tested, &joined));
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+                    Err(err) => println!("Connection test failed: {}", err),
+                }
+            }
+            listener
+        });
+        Ok(Self {
+            listening,
+            stop,
+            tested,
+            joined,
+            deciding,
+            thread: Some(thread),
+        })
+    }
+
+    /// A guest who finished a test since this was last asked
+    #[inline]
+    pub fn tested(&self) -> Option<SocketAddr> {
+        self.tested.try_recv().ok()
+    }
+
+    /// Stop answering and hand the port over to the game, still bound
+    pub fn into_hosting(mut self) -> Result<Hosting, Error> {
+        self.stop.store(true, Ordering::Relaxed);
+        let listener = self
+            .thread
+            .take()
+            .expect("The responder is only stopped once")
+            .join()
+            .map_err(|_| Error::other("The connection test stopped unexpectedly"))?;
+        listener.set_nonblocking(false)?;
+        // A player who connected just now mustn't be dropped on the way
+        let waited = Instant::now();
+        while self.deciding.load(Ordering::Relaxed) > 0 && waited.elapsed() < TEST_TIMEOUT {
+            thread::sleep(ACCEPT_POLL);
+        }
+        Ok(Hosting {
+            listener,
+            joined: self.joined.try_recv().ok(),
+        })
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairing_codes_need_the_same_network() {
+        let host = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 20), DEFAULT_PORT);
+        let code = pairing_code(host).unwrap();
+        assert_eq!(code.len(), 6);
+        assert_eq!(grouped(&code), format!("{} {}", &code[..3], &code[3..]));
+        let guest = Ipv4Addr::new(192, 168, 7, 3);
+        assert_eq!(decode_pairing_code(&grouped(&code), guest), Ok(host));
+
+        // Another port makes it longer
+        let moved = SocketAddrV4::new(Ipv4Addr::new(10, 0, 4, 200), 5000);
+        let code = pairing_code(moved).unwrap();
+        assert_eq!(code.len(), 11);
+        assert_eq!(
+            decode_pairing_code(&code, Ipv4Addr::new(10, 0, 0, 9)),
+            Ok(moved)
+        );
+
+        // A guest on another network falls back to the full address
+        assert!(decode_pairing_code(&code, Ipv4Addr::new(192, 168, 1, 5))
+            .unwrap_err()
+            .contains("full address"));
+        assert!(decode_pairing_code(&code, Ipv4Addr::new(8, 8, 8, 8)).is_err());
+        assert_eq!(
+            pairing_code(SocketAddrV4::new(Ipv4Addr::new(8, 8, 8, 8), 80)),
+            None
+        );
+        assert!(decode_pairing_code("12345", guest).is_err());
+        assert!(decode_pairing_code("099999", guest).is_err());
+    }
+
+    #[test]
+    fn guests_type_a_code_or_an_address() {
+        let own = Some(Ipv4Addr::new(192, 168, 1, 5));
+        let code = pairing_code(SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 20), 8080)).unwrap();
+        assert_eq!(host_address(&code, own), Ok("192.168.1.20:8080".to_owned()));
+        assert_eq!(
+            host_address("localhost:5000", own),
+            Ok("localhost:5000".to_owned())
+        );
+        assert_eq!(
+            host_address("192.168.1.20:8080", None),
+            Ok("192.168.1.20:8080".to_owned())
+        );
+        assert!(host_address(&code, None).is_err());
+    }
+
+    #[test]
+    fn a_ping_is_answered_and_timed() {
+        let responder = Responder::start("127.0.0.1:0").unwrap();
+        let verdict = test_reach(&responder.listening.to_string());
+        assert!(matches!(verdict, Verdict::Reachable(_)), "{:?}", verdict);
+        assert!(verdict.describe().ends_with("ready to play"));
+        let deadline = Instant::now() + TEST_TIMEOUT;
+        while responder.tested().is_none() {
+            assert!(
+                Instant::now() < deadline,
+                "the host never heard of the test"
+            );
+            thread::sleep(ACCEPT_POLL);
+        }
+    }
+
+    /// The host starts the game on the port it tested and the guest joins it, with the guest
+    /// joining before the host is ready too
+    #[test]
+    fn a_tested_port_is_handed_over_to_the_game() {
+        use crate::config::NetworkConfig;
+        use crate::network::{internal_to_server_handshake, Handshake, Network, NetworkEvent};
+        use crate::session::Session;
+        use chess_network_protocol::{ClientToServerHandshake, Color};
+
+        fn connected(network: &mut Network) -> bool {
+            let deadline = Instant::now() + TEST_TIMEOUT;
+            while Instant::now() < deadline {
+                match network.poll() {
+                    Some(NetworkEvent::Connected(..)) => return true,
+                    Some(NetworkEvent::Disconnected) => return false,
+                    _ => thread::sleep(ACCEPT_POLL),
+                }
+            }
+            false
+        }
+        let config = |is_server, address: &str, hosting| NetworkConfig {
+            is_server,
+            address: address.to_owned(),
+            strict_port: false,
+            connect_attempts: 3,
+            server_color: Some(Color::Black),
+            spectate: false,
+            hosting,
+        };
+        let session = Session::new(None).unwrap();
+        for guest_first in [false, true] {
+            let responder = Responder::start("127.0.0.1:0").unwrap();
+            let address = responder.listening.to_string();
+            assert!(matches!(test_reach(&address), Verdict::Reachable(_)));
+            let join = || {
+                network::start(
+                    &config(false, &address, None),
+                    Handshake::ClientToServer(ClientToServerHandshake {
+                        server_color: Color::Black,
+                    }),
+                )
+            };
+            let early = guest_first.then(join);
+            let hosting = responder.into_hosting().unwrap();
+            let mut host = network::start(
+                &config(true, &address, Some(hosting)),
+                Handshake::ServerToClient(internal_to_server_handshake(&session)),
+            );
+            let mut guest = early.unwrap_or_else(join);
+            assert!(connected(&mut host), "the host never got its player");
+            assert!(connected(&mut guest), "the guest never got the game");
+        }
+    }
+
+    #[test]
+    fn nothing_listening_is_told_apart() {
+        // Bound and dropped, so nothing listens there
+        let free = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let Verdict::Unreachable(why) = test_reach(&free.to_string()) else {
+            panic!("reached nothing");
+        };
+        assert!(why.starts_with("Nothing answers"), "{}", why);
+    }
+}
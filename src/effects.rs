@@ -0,0 +1,171 @@
+use crate::session::Session;
+use crate::{parse_fen, Square};
+
+/// How a change of position came about. Always decided by whoever makes the change, never
+/// worked out from it, so going over many positions at once can't sound like a game played
+/// at machine-gun speed.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Application {
+    // A move played in the game as it happens
+    Live,
+    // Stepping through the game one position at a time
+    Replay,
+    // Importing a game, replaying a save or jumping over positions
+    Bulk,
+    // The board replaced by the peer's
+    Resync,
+}
+
+/// What a change can sound like
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Cue {
+    Move,
+    Capture,
+    Check,
+    GameOver,
+    // Quieter than a move, for stepping through a game
+    Tick,
+}
+
+/// How the pieces get to their new squares
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Slide {
+    Full,
+    Instant,
+}
+
+/// Carries out the side effects of a change. The bookkeeping of captures, history and hashes
+/// is done before, whatever the application.
+pub trait Effects {
+    fn sound(&mut self, cue: Cue);
+    fn slide(&mut self, slide: Slide);
+    // Toasts and anything else that tells of the move as news
+    fn notify(&mut self);
+    fn autosave(&mut self);
+}
+
+impl Application {
+    /// Set off the effects of a change that sounds like `cue` when it is live. Nothing slides
+    /// unless it is told to.
+    pub fn dispatch(self, cue: Cue, effects: &mut impl Effects) {
+        match self {
+            Application::Live => {
+                effects.sound(cue);
+                effects.slide(Slide::Full);
+                effects.notify();
+                effects.autosave();
+            }
+            Application::Replay => {
+                effects.sound(Cue::Tick);
+                effects.slide(Slide::Instant);
+            }
+            Application::Bulk | Application::Resync => {}
+        }
+    }
+}
+
+/// The effects set off, for the window to carry out once the change is made
+#[derive(Eq, PartialEq, Clone, Debug, Default)]
+pub struct Recorded {
+    pub cues: Vec<Cue>,
+    pub slides: Vec<Slide>,
+    pub notifications: usize,
+    pub autosaves: usize,
+}
+
+impl Effects for Recorded {
+    #[inline]
+    fn sound(&mut self, cue: Cue) {
+        self.cues.push(cue);
+    }
+
+    #[inline]
+    fn slide(&mut self, slide: Slide) {
+        self.slides.push(slide);
+    }
+
+    #[inline]
+    fn notify(&mut self) {
+        self.notifications += 1;
+    }
+
+    #[inline]
+    fn autosave(&mut self) {
+        self.autosaves += 1;
+    }
+}
+
+/// The board after `ply` of the game to show in review, with the effects of getting there
+/// the way `application` says
+pub fn review_position(
+    session: &Session,
+    ply: usize,
+    application: Application,
+    effects: &mut impl Effects,
+) -> Option<[[Square; 8]; 8]> {
+    let squares = parse_fen(session.snapshots.get(ply)?).ok()?;
+    application.dispatch(Cue::Move, effects);
+    Some(squares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::tests::play;
+
+    #[test]
+    fn only_live_changes_are_announced() {
+        let recorded = |application: Application| {
+            let mut effects = Recorded::default();
+            application.dispatch(Cue::Capture, &mut effects);
+            effects
+        };
+        assert_eq!(
+            recorded(Application::Live),
+            Recorded {
+                cues: vec![Cue::Capture],
+                slides: vec![Slide::Full],
+                notifications: 1,
+                autosaves: 1,
+            }
+        );
+        assert_eq!(
+            recorded(Application::Replay),
+            Recorded {
+                cues: vec![Cue::Tick],
+                slides: vec![Slide::Instant],
+                ..Recorded::default()
+            }
+        );
+        for application in [Application::Bulk, Application::Resync] {
+            assert_eq!(recorded(application), Recorded::default());
+        }
+    }
+
+    #[test]
+    fn a_jump_in_review_is_silent_and_lands_where_stepping_does() {
+        let mut session = Session::new(None).unwrap();
+        // Repetitions only draw once claimed, so the knights can go back and forth
+        session.claim_draws = true;
+        for _ in 0..15 {
+            for notation in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                play(&mut session, notation);
+            }
+        }
+        assert_eq!(session.snapshots.len(), 61);
+
+        let mut stepped = Recorded::default();
+        let mut shown = None;
+        for ply in 3..=60 {
+            shown = review_position(&session, ply, Application::Replay, &mut stepped);
+        }
+        assert_eq!(stepped.cues, vec![Cue::Tick; 58]);
+
+        let mut jumped = Recorded::default();
+        let landed = review_position(&session, 60, Application::Bulk, &mut jumped);
+        assert!(jumped.cues.is_empty());
+        assert_eq!(jumped, Recorded::default());
+        assert!(landed.is_some() && landed == shown);
+        assert!(landed == Some(session.board_repr.squares));
+    }
+}
@@ -0,0 +1,447 @@
+use crate::{
+    board_from_fen, captured_piece, castling_squares, is_castling, is_in_check, parse_fen,
+    parse_move, Square,
+};
+use jonathan_hallstrom_chess::{Board, Color, Move, PieceType};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+// Worse than any material difference, so a mate is always preferred
+const MATE_SCORE: i32 = 1_000_000;
+
+/// A game to search: the position it started from, None for the standard one, and every move since
+pub struct Line {
+    pub start_fen: Option<String>,
+    pub moves: Vec<Move>,
+}
+
+impl Line {
+    /// The position at the end of the line. Boards are only ever built by replaying moves,
+    /// once for a search, whose nodes each clone their parent's.
+    pub(crate) fn board(&self) -> Option<Board> {
+        let mut board = match &self.start_fen {
+            Some(fen) => board_from_fen(fen).ok()?,
+            None => Board::default(),
+        };
+        for mv in &self.moves {
+            board.play_move(*mv).ok()?;
+        }
+        Some(board)
+    }
+}
+
+/// Handle to the engine thread, which searches the lines it is given
+pub struct Engine {
+    sender: Sender<Line>,
+    // The position searched as FEN and the move chosen for it, None if it has no moves
+    receiver: Receiver<(String, Option<Move>)>,
+    thinking: bool,
+    depth: u32,
+}
+
+impl Engine {
+    pub fn start(depth: u32) -> Self {
+        let (sender, lines) = mpsc::channel::<Line>();
+        let (results, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // Ends once the game drops its handle
+            for line in lines {
+                let fen = line
+                    .board()
+                    .map_or_else(String::new, |board| board.to_fen());
+                let best = best_move(&line, depth);
+                if results.send((fen, best)).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            sender,
+            receiver,
+            thinking: false,
+            depth,
+        }
+    }
+
+    #[inline]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    #[inline]
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    /// Start searching the position at the end of the line, the move arrives through `poll`
+    pub fn think(&mut self, line: Line) {
+        self.thinking = self.sender.send(line).is_ok();
+    }
+
+    /// The searched position and the move chosen for it, once the search is done
+    pub fn poll(&mut self) -> Option<(String, Option<Move>)> {
+        match self.receiver.try_recv() {
+            Ok(result) => {
+                self.thinking = false;
+                Some(result)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.thinking = false;
+                None
+            }
+        }
+    }
+}
+
+#[inline]
+fn piece_value(piece: &Square) -> i32 {
+    match piece {
+        Square::Empty | Square::King(_) => 0,
+        Square::Pawn(_) => 100,
+        Square::Knight(_) => 320,
+        Square::Bishop(_) => 330,
+        Square::Rook(_) => 500,
+        Square::Queen(_) => 900,
+    }
+}
+
+/// Material of the side to move minus the opponent's
+fn evaluate(squares: &[[Square; 8]; 8], board: &Board) -> i32 {
+    let to_move = board.get_curr_player();
+    squares
+        .iter()
+        .flatten()
+        .map(|piece| match piece.color() {
+            Some(color) if color == to_move => piece_value(piece),
+            Some(_) => -piece_value(piece),
+            None => 0,
+        })
+        .sum()
+}
+
+#[inline]
+fn promoted_piece(piece_type: PieceType, color: Color) -> Square {
+    match piece_type {
+        PieceType::Queen => Square::Queen(color),
+        PieceType::Rook => Square::Rook(color),
+        PieceType::Bishop => Square::Bishop(color),
+        PieceType::Knight => Square::Knight(color),
+        PieceType::Pawn | PieceType::King => Square::Pawn(color),
+    }
+}
+
+/// Material the side to move wins with the move, by what it takes and what it promotes to
+fn material_gain(squares: &[[Square; 8]; 8], mv: &Move) -> i32 {
+    let Ok((from, to)) = parse_move(&mv.to_algebraic_notation()) else {
+        return 0;
+    };
+    let mover = squares[from.0][from.1].color();
+    let taken = captured_piece(squares, from, to).map_or(0, |piece| piece_value(&piece));
+    let promoted = match (mv.get_promoted_type(), mover) {
+        (Some(piece_type), Some(color)) => {
+            piece_value(&promoted_piece(piece_type, color)) - piece_value(&Square::Pawn(color))
+        }
+        _ => 0,
+    };
+    taken + promoted
+}
+
+/// The squares after the legal move `mv` is played on `squares`, worked out here instead of
+/// asking the board for its FEN at every node
+pub(crate) fn squares_after(squares: &[[Square; 8]; 8], mv: &Move) -> [[Square; 8]; 8] {
+    let mut after = *squares;
+    let Ok((from, to)) = parse_move(&mv.to_algebraic_notation()) else {
+        return after;
+    };
+    let piece = squares[from.0][from.1];
+    after[from.0][from.1] = Square::Empty;
+    if is_castling(squares, from, to) {
+        let (king_to, rook_from, rook_to) = castling_squares(from, to);
+        after[rook_from.0][rook_from.1] = Square::Empty;
+        after[king_to.0][king_to.1] = piece;
+        after[rook_to.0][rook_to.1] = squares[rook_from.0][rook_from.1];
+        return after;
+    }
+    // En passant takes the pawn beside the one moving
+    if matches!(piece, Square::Pawn(_)) && from.1 != to.1 && squares[to.0][to.1] == Square::Empty {
+        after[from.0][to.1] = Square::Empty;
+    }
+    after[to.0][to.1] = match (mv.get_promoted_type(), piece.color()) {
+        (Some(piece_type), Some(color)) => promoted_piece(piece_type, color),
+        _ => piece,
+    };
+    after
+}
+
+/// The search didn't finish before its deadline
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct OutOfTime;
+
+/// Score of the position on `board`, whose pieces are `squares`, for the side to move,
+/// searched `depth` plies deep
+fn negamax(
+    board: &Board,
+    squares: &[[Square; 8]; 8],
+    depth: u32,
+    deadline: Option<Instant>,
+) -> Result<i32, OutOfTime> {
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(OutOfTime);
+    }
+    let moves = board.get_legal_moves();
+    if moves.is_empty() {
+        // Being mated with more depth left means it happens sooner, which is worse
+        return Ok(match is_in_check(squares, board.get_curr_player()) {
+            true => -MATE_SCORE - depth as i32,
+            false => 0,
+        });
+    }
+    let score = evaluate(squares, board);
+    Ok(match depth {
+        0 => score,
+        // The last ply only changes the material, so it is scored without replaying it
+        1 => {
+            score
+                + moves
+                    .iter()
+                    .map(|mv| material_gain(squares, mv))
+                    .max()
+                    .unwrap_or(0)
+        }
+        _ => {
+            let mut best = -MATE_SCORE * 2;
+            for mv in moves {
+                best = best.max(-child_score(board, squares, mv, depth - 1, deadline)?);
+            }
+            best
+        }
+    })
+}
+
+/// Score for the side to move after `mv` is played on a clone of `board`, from the side that
+/// played it
+fn child_score(
+    board: &Board,
+    squares: &[[Square; 8]; 8],
+    mv: Move,
+    depth: u32,
+    deadline: Option<Instant>,
+) -> Result<i32, OutOfTime> {
+    let mut child = board.clone();
+    if child.play_move(mv).is_err() {
+        return Ok(0);
+    }
+    negamax(&child, &squares_after(squares, &mv), depth, deadline)
+}
+
+/// The board at the end of the line and its pieces, the only time a search replays the line
+/// or reads a FEN
+fn root(line: &Line) -> Option<(Board, [[Square; 8]; 8])> {
+    let board = line.board()?;
+    let squares = parse_fen(&board.to_fen()).ok()?;
+    Some((board, squares))
+}
+
+/// Score of the position at the end of the line from White's side, None if it can't be replayed
+pub fn white_score(line: &Line, depth: u32) -> Option<i32> {
+    let (board, squares) = root(line)?;
+    let to_move = board.get_curr_player();
+    let score = negamax(&board, &squares, depth, None).ok()?;
+    Some(match to_move {
+        Color::White => score,
+        Color::Black => -score,
+    })
+}
+
+/// Handle to a thread scoring positions of a game one after another for the review graph.
+/// Dropping it stops the thread after the position it is on.
+pub struct Analysis {
+    // The ply of a position and its score from White's side, None if its FEN didn't parse
+    receiver: Receiver<(usize, Option<i32>)>,
+    cancelled: Arc<AtomicBool>,
+    scored: usize,
+    total: usize,
+}
+
+impl Analysis {
+    /// Score each position, given by its ply and FEN, in turn
+    pub fn start(positions: Vec<(usize, String)>, depth: u32) -> Self {
+        let (results, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let total = positions.len();
+
+        let thread_cancelled = Arc::clone(&cancelled);
+        thread::spawn(move || {
+            for (ply, fen) in positions {
+                if thread_cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let position = Line {
+                    start_fen: Some(fen),
+                    moves: Vec::new(),
+                };
+                let score = white_score(&position, depth);
+                if results.send((ply, score)).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            receiver,
+            cancelled,
+            scored: 0,
+            total,
+        }
+    }
+
+    /// The next scored position, if one is ready
+    pub fn poll(&mut self) -> Option<(usize, Option<i32>)> {
+        match self.receiver.try_recv() {
+            Ok(result) => {
+                self.scored += 1;
+                Some(result)
+            }
+            Err(TryRecvError::Empty) => None,
+            // Nothing more is coming, whatever is missing stays unscored
+            Err(TryRecvError::Disconnected) => {
+                self.scored = self.total;
+                None
+            }
+        }
+    }
+
+    /// How much of the pass is done, from 0 to 100
+    #[inline]
+    pub fn percent(&self) -> usize {
+        match self.total {
+            0 => 100,
+            total => self.scored * 100 / total,
+        }
+    }
+
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.scored >= self.total
+    }
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The move with the best score at the end of the line, promotions are separate moves
+/// so they are searched too
+#[inline]
+pub fn best_move(line: &Line, depth: u32) -> Option<Move> {
+    best_move_until(line, depth, None).unwrap_or(None)
+}
+
+/// `best_move`, given up once `deadline` passes
+pub fn best_move_until(
+    line: &Line,
+    depth: u32,
+    deadline: Option<Instant>,
+) -> Result<Option<Move>, OutOfTime> {
+    match root(line) {
+        Some((board, squares)) => best_move_on(&board, &squares, depth, deadline),
+        None => Ok(None),
+    }
+}
+
+/// `best_move_until` for the position on `board`, whose pieces are `squares`
+pub(crate) fn best_move_on(
+    board: &Board,
+    squares: &[[Square; 8]; 8],
+    depth: u32,
+    deadline: Option<Instant>,
+) -> Result<Option<Move>, OutOfTime> {
+    let mut best: Option<(Move, i32)> = None;
+    for mv in board.get_legal_moves() {
+        let score = -child_score(board, squares, mv, depth.saturating_sub(1), deadline)?;
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((mv, score));
+        }
+    }
+    Ok(best.map(|(mv, _)| mv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The moves in algebraic notation like "e2e4", replayed from the standard position
+    fn line(moves: &[&str]) -> Line {
+        let mut board = Board::default();
+        let moves = moves
+            .iter()
+            .map(|notation| {
+                let mv = board
+                    .get_legal_moves()
+                    .into_iter()
+                    .find(|mv| mv.to_algebraic_notation() == *notation)
+                    .unwrap_or_else(|| panic!("{} isn't legal", notation));
+                board.play_move(mv).unwrap();
+                mv
+            })
+            .collect();
+        Line {
+            start_fen: None,
+            moves,
+        }
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        // The queen and bishop both hit f7, which only the king guards
+        let line = line(&["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6"]);
+        let best = best_move(&line, 2).map(|mv| mv.to_algebraic_notation());
+        assert_eq!(best.as_deref(), Some("h5f7"));
+    }
+
+    #[test]
+    fn takes_a_free_queen() {
+        // The queen on h5 can be taken by the knight on f6
+        let line = line(&["e2e4", "e7e5", "d1h5", "g8f6", "a2a3"]);
+        let best = best_move(&line, 1).map(|mv| mv.to_algebraic_notation());
+        assert_eq!(best.as_deref(), Some("f6h5"));
+    }
+
+    #[test]
+    fn squares_after_a_move_match_the_board_it_is_played_on() {
+        for fen in [
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+            // En passant and promotions, with and without a capture
+            "1r2k3/2P5/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+        ] {
+            let board = board_from_fen(fen).unwrap();
+            let squares = parse_fen(fen).unwrap();
+            for mv in board.get_legal_moves() {
+                let mut played = board.clone();
+                played.play_move(mv).unwrap();
+                assert!(
+                    squares_after(&squares, &mv) == parse_fen(&played.to_fen()).unwrap(),
+                    "{} after {}",
+                    fen,
+                    mv.to_algebraic_notation()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_search_past_its_deadline_gives_up() {
+        let line = line(&["e2e4"]);
+        assert_eq!(
+            best_move_until(&line, 3, Some(Instant::now())).err(),
+            Some(OutOfTime)
+        );
+    }
+}
@@ -0,0 +1,534 @@
+use crate::engine::{best_move_until, Line};
+use crate::session::Session;
+use crate::{opposite_color, pgn, GameState};
+use jonathan_hallstrom_chess::{Color, Move};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MATCH_GAMES: u32 = 10;
+// Longest an engine may think about a move before it loses on time, so a hung search
+// can't stall the match
+pub const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(10);
+pub const DEFAULT_REPORT: &str = "engine-match.json";
+
+/// The first two plies of every game, cycled through so the games aren't all the same.
+/// Each one is played twice, once with either engine as White.
+pub const OPENINGS: [[&str; 2]; 8] = [
+    ["e2e4", "e7e5"],
+    ["d2d4", "d7d5"],
+    ["e2e4", "c7c5"],
+    ["c2c4", "e7e5"],
+    ["d2d4", "g8f6"],
+    ["e2e4", "e7e6"],
+    ["g1f3", "d7d5"],
+    ["e2e4", "c7c6"],
+];
+
+/// Two depths of the engine playing a series of games against each other
+#[derive(Clone, Debug)]
+pub struct MatchConfig {
+    // Plies searched by the first engine and the second, the score is from the first one's side
+    pub first: u32,
+    pub second: u32,
+    pub games: u32,
+    pub move_time: Duration,
+    // Play through the window at a pace that can be followed instead of as fast as possible
+    pub watch: bool,
+    pub report: PathBuf,
+}
+
+/// The depths of a match like "3v5"
+pub fn parse_levels(levels: &str) -> Option<(u32, u32)> {
+    let (first, second) = levels.split_once('v')?;
+    let depth = |depth: &str| depth.parse().ok().filter(|depth| *depth > 0);
+    Some((depth(first)?, depth(second)?))
+}
+
+/// The opening of a game of the match, counting from 0, and whether the first engine is White
+#[inline]
+pub fn pairing(game: u32) -> (usize, bool) {
+    ((game / 2) as usize % OPENINGS.len(), game.is_multiple_of(2))
+}
+
+/// How a game ended for the first engine
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// A finished or skipped game of the match
+#[derive(Clone)]
+pub struct GameRecord {
+    // Counting from 0
+    pub number: u32,
+    pub opening: usize,
+    pub first_white: bool,
+    pub state: GameState,
+    // Every move in algebraic notation like "e2e4", the opening's first
+    pub moves: Vec<String>,
+    // Passed over by the watcher before it ended, it isn't scored
+    pub skipped: bool,
+}
+
+impl GameRecord {
+    /// None for a game that didn't end
+    pub fn outcome(&self) -> Option<Outcome> {
+        let winner = match self.state {
+            _ if self.skipped => return None,
+            // Nobody won a game the engine broke down in
+            GameState::Ongoing | GameState::PositionError => return None,
+            GameState::Stalemate
+            | GameState::Draw
+            | GameState::Repetition
+            | GameState::FiftyMoves
+            | GameState::InsufficientMaterial => return Some(Outcome::Draw),
+            GameState::Checkmate(loser)
+            | GameState::Resigned(loser)
+            | GameState::TimeForfeit(loser)
+            | GameState::Forfeit(loser)
+            | GameState::Stalled(loser, _) => opposite_color(loser),
+        };
+        Some(match (winner == Color::White) == self.first_white {
+            true => Outcome::Win,
+            false => Outcome::Loss,
+        })
+    }
+
+    pub fn termination(&self) -> String {
+        match self.skipped {
+            true => "Skipped".to_owned(),
+            false => self
+                .state
+                .description()
+                .unwrap_or_else(|| "Unfinished".to_owned()),
+        }
+    }
+
+    /// The game as a line of the match's log, White's depth first
+    pub fn summary(&self, config: &MatchConfig) -> String {
+        let (white, black) = match self.first_white {
+            true => (config.first, config.second),
+            false => (config.second, config.first),
+        };
+        format!(
+            "Game {}: depth {} against depth {}, {} {} after {} plies",
+            self.number + 1,
+            white,
+            black,
+            pgn::result_token(&self.state),
+            self.termination(),
+            self.moves.len()
+        )
+    }
+
+    fn to_json(&self, config: &MatchConfig) -> Value {
+        let (white, black) = match self.first_white {
+            true => (config.first, config.second),
+            false => (config.second, config.first),
+        };
+        let mut object = Map::new();
+        object.insert("number".to_owned(), Value::Number((self.number + 1).into()));
+        object.insert("white_depth".to_owned(), Value::Number(white.into()));
+        object.insert("black_depth".to_owned(), Value::Number(black.into()));
+        object.insert(
+            "opening".to_owned(),
+            Value::String(OPENINGS[self.opening].join(" ")),
+        );
+        object.insert(
+            "result".to_owned(),
+            Value::String(pgn::result_token(&self.state).to_owned()),
+        );
+        object.insert("termination".to_owned(), Value::String(self.termination()));
+        object.insert(
+            "moves".to_owned(),
+            Value::Array(self.moves.iter().cloned().map(Value::String).collect()),
+        );
+        Value::Object(object)
+    }
+}
+
+/// The first engine's wins, draws and losses so far
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Tally {
+    pub fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Draw => self.draws += 1,
+            Outcome::Loss => self.losses += 1,
+        }
+    }
+
+    #[inline]
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The first engine's points, a draw counting half
+    #[inline]
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    /// Part of the points the first engine got, None before any game
+    pub fn score(&self) -> Option<f64> {
+        match self.games() {
+            0 => None,
+            games => Some(self.points() / games as f64),
+        }
+    }
+
+    /// How much stronger the first engine is in Elo from the score, None while it won or
+    /// lost every game since that has no finite estimate
+    pub fn elo_difference(&self) -> Option<f64> {
+        self.score()
+            .filter(|score| *score > 0.0 && *score < 1.0)
+            .map(|score| -400.0 * (1.0 / score - 1.0).log10())
+    }
+
+    /// The result like "Depth 3 2.5 - 1.5 depth 5"
+    pub fn score_line(&self, config: &MatchConfig) -> String {
+        format!(
+            "Depth {} {} - {} depth {}",
+            config.first,
+            self.points(),
+            self.games() as f64 - self.points(),
+            config.second
+        )
+    }
+
+    /// The final table printed once the match is over
+    pub fn table(&self, config: &MatchConfig) -> String {
+        let score = self
+            .score()
+            .map_or("-".to_owned(), |score| format!("{:.1}%", score * 100.0));
+        let elo = match (self.elo_difference(), self.score()) {
+            (Some(elo), _) => format!("{:+.0}", elo),
+            (None, None) => "-".to_owned(),
+            (None, Some(_)) => "unbounded, one side won every game".to_owned(),
+        };
+        format!(
+            "Depth {} against depth {}, {} games\n\
+             {:>8} {:>6} {:>6} {:>7}\n\
+             {:>8} {:>6} {:>6} {:>7}\n\
+             Elo difference: {}",
+            config.first,
+            config.second,
+            self.games(),
+            "Wins",
+            "Draws",
+            "Losses",
+            "Score",
+            self.wins,
+            self.draws,
+            self.losses,
+            score,
+            elo
+        )
+    }
+
+    fn to_json(self, config: &MatchConfig, records: &[GameRecord]) -> Value {
+        let mut object = Map::new();
+        object.insert("first_depth".to_owned(), Value::Number(config.first.into()));
+        object.insert(
+            "second_depth".to_owned(),
+            Value::Number(config.second.into()),
+        );
+        object.insert("wins".to_owned(), Value::Number(self.wins.into()));
+        object.insert("draws".to_owned(), Value::Number(self.draws.into()));
+        object.insert("losses".to_owned(), Value::Number(self.losses.into()));
+        let number = |value: Option<f64>| {
+            value
+                .and_then(serde_json::Number::from_f64)
+                .map_or(Value::Null, Value::Number)
+        };
+        object.insert("score".to_owned(), number(self.score()));
+        object.insert("elo_difference".to_owned(), number(self.elo_difference()));
+        object.insert(
+            "games".to_owned(),
+            Value::Array(
+                records
+                    .iter()
+                    .map(|record| record.to_json(config))
+                    .collect(),
+            ),
+        );
+        Value::Object(object)
+    }
+}
+
+/// What happened in the match, in the order it happened
+pub enum MatchEvent {
+    // A game starts from `fen` with its number counting from 0
+    Started { number: u32, fen: String },
+    Moved(Move),
+    Finished(GameRecord),
+    Done(Tally),
+}
+
+/// The legal move with this algebraic notation
+fn legal_move(session: &Session, notation: &str) -> Option<Move> {
+    session
+        .board
+        .get_legal_moves()
+        .into_iter()
+        .find(|mv| mv.to_algebraic_notation() == notation)
+}
+
+/// Play game `number` of the match, every move is passed to `emit` and it stops the game
+/// by returning false. Rule draws end the game without a claim and an engine that doesn't
+/// find its move within the move time loses on time.
+pub fn play_game(
+    number: u32,
+    config: &MatchConfig,
+    skip: &AtomicBool,
+    emit: &mut dyn FnMut(MatchEvent) -> bool,
+) -> Option<GameRecord> {
+    let (opening, first_white) = pairing(number);
+    let mut session = Session::new(None).ok()?;
+    if !emit(MatchEvent::Started {
+        number,
+        fen: session.fen(),
+    }) {
+        return None;
+    }
+    for notation in OPENINGS[opening] {
+        let mv = legal_move(&session, notation)?;
+        session.apply_move(mv);
+        if !emit(MatchEvent::Moved(mv)) {
+            return None;
+        }
+    }
+    while session.game_state == GameState::Ongoing && !skip.load(Ordering::Relaxed) {
+        let to_move = session.board.get_curr_player();
+        let depth = match (to_move == Color::White) == first_white {
+            true => config.first,
+            false => config.second,
+        };
+        let line = Line {
+            start_fen: None,
+            moves: session.played_moves.clone(),
+        };
+        match best_move_until(&line, depth, Some(Instant::now() + config.move_time)) {
+            Ok(Some(mv)) => {
+                session.apply_move(mv);
+                if !emit(MatchEvent::Moved(mv)) {
+                    return None;
+                }
+            }
+            // The session ends the game itself when there are no moves, this is only a fallback
+            Ok(None) => break,
+            Err(_) => session.game_state = GameState::TimeForfeit(to_move),
+        }
+    }
+    // Cleared either way, a skip that came in as the game ended is for this game
+    let skipped = skip.swap(false, Ordering::Relaxed) && session.game_state == GameState::Ongoing;
+    Some(GameRecord {
+        number,
+        opening,
+        first_white,
+        state: session.game_state,
+        moves: session.move_notations(),
+        skipped,
+    })
+}
+
+/// Play every game of the match, print each one as it ends and the table at the end, and
+/// write the report. Returns None if `emit` stopped it.
+pub fn play_match(
+    config: &MatchConfig,
+    skip: &AtomicBool,
+    emit: &mut dyn FnMut(MatchEvent) -> bool,
+) -> Option<Tally> {
+    let mut tally = Tally::default();
+    let mut records = Vec::new();
+    for number in 0..config.games {
+        let record = play_game(number, config, skip, emit)?;
+        println!("{}", record.summary(config));
+        if let Some(outcome) = record.outcome() {
+            tally.add(outcome);
+        }
+        records.push(record.clone());
+        if !emit(MatchEvent::Finished(record)) {
+            return None;
+        }
+    }
+    println!("{}", tally.table(config));
+    write_report(&config.report, &tally.to_json(config, &records));
+    emit(MatchEvent::Done(tally));
+    Some(tally)
+}
+
+/// Replace the report, a failure is only printed since the table was printed already
+fn write_report(path: &Path, report: &Value) {
+    let written = serde_json::to_string_pretty(report)
+        .map_err(std::io::Error::from)
+        .and_then(|text| std::fs::write(path, text));
+    match written {
+        Ok(()) => println!("Report written to {}", path.display()),
+        Err(err) => println!("Can't write the report to {}: {}", path.display(), err),
+    }
+}
+
+/// Handle to a thread playing a match for the window to show. Every event waits until it
+/// is polled, so the engines search while the last move is on screen and never get ahead.
+/// Dropping it stops the match after the move it is on.
+pub struct Match {
+    receiver: Receiver<MatchEvent>,
+    skip: Arc<AtomicBool>,
+}
+
+impl Match {
+    pub fn start(config: MatchConfig) -> Self {
+        let (events, receiver) = mpsc::sync_channel(0);
+        let skip = Arc::new(AtomicBool::new(false));
+        let thread_skip = Arc::clone(&skip);
+        thread::spawn(move || {
+            play_match(&config, &thread_skip, &mut |event| {
+                events.send(event).is_ok()
+            });
+        });
+        Self { receiver, skip }
+    }
+
+    /// The next thing that happened, if the match has got that far
+    pub fn poll(&mut self) -> Option<MatchEvent> {
+        match self.receiver.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Give up on the game being played and go on with the next one
+    #[inline]
+    pub fn skip(&self) {
+        self.skip.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    fn config(games: u32) -> MatchConfig {
+        MatchConfig {
+            first: 1,
+            second: 1,
+            games,
+            move_time: DEFAULT_MOVE_TIME,
+            watch: false,
+            // Only written when a match is played, which sets its own
+            report: PathBuf::new(),
+        }
+    }
+
+    fn tally(wins: u32, draws: u32, losses: u32) -> Tally {
+        Tally {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 0.5,
+            "{} isn't {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn levels_are_two_depths() {
+        assert_eq!(parse_levels("3v5"), Some((3, 5)));
+        assert_eq!(parse_levels("3v"), None);
+        assert_eq!(parse_levels("0v2"), None);
+        assert_eq!(parse_levels("35"), None);
+    }
+
+    #[test]
+    fn colors_alternate_and_openings_cycle() {
+        assert_eq!(pairing(0), (0, true));
+        assert_eq!(pairing(1), (0, false));
+        assert_eq!(pairing(2), (1, true));
+        assert_eq!(pairing(2 * OPENINGS.len() as u32 + 1), (0, false));
+    }
+
+    #[test]
+    fn outcomes_are_from_the_first_engines_side() {
+        let record = |state, first_white, skipped| GameRecord {
+            number: 0,
+            opening: 0,
+            first_white,
+            state,
+            moves: Vec::new(),
+            skipped,
+        };
+        let mate = GameState::Checkmate(Color::Black);
+        assert_eq!(record(mate, true, false).outcome(), Some(Outcome::Win));
+        assert_eq!(record(mate, false, false).outcome(), Some(Outcome::Loss));
+        let flagged = GameState::TimeForfeit(Color::White);
+        assert_eq!(record(flagged, false, false).outcome(), Some(Outcome::Win));
+        let repeated = GameState::Repetition;
+        assert_eq!(record(repeated, true, false).outcome(), Some(Outcome::Draw));
+        assert_eq!(record(mate, true, true).outcome(), None);
+        assert_eq!(record(mate, true, true).termination(), "Skipped");
+    }
+
+    #[test]
+    fn the_score_gives_the_elo_difference() {
+        assert_eq!(tally(0, 0, 0).score(), None);
+        assert_eq!(tally(0, 0, 0).elo_difference(), None);
+        assert_close(tally(5, 10, 5).elo_difference().unwrap(), 0.0);
+        // 75% is about 191 points stronger, 25% as much weaker
+        assert_close(tally(3, 0, 1).elo_difference().unwrap(), 190.8);
+        assert_close(tally(1, 2, 3).elo_difference().unwrap(), -120.4);
+        assert_close(tally(0, 1, 3).elo_difference().unwrap(), -338.0);
+        // Winning or losing every game has no finite estimate
+        assert_eq!(tally(4, 0, 0).elo_difference(), None);
+        assert_eq!(tally(0, 0, 4).elo_difference(), None);
+
+        let mut counted = Tally::default();
+        for outcome in [Outcome::Win, Outcome::Draw, Outcome::Draw, Outcome::Loss] {
+            counted.add(outcome);
+        }
+        assert_eq!(counted, tally(1, 2, 1));
+        assert_eq!(counted.score(), Some(0.5));
+        assert_eq!(counted.score_line(&config(4)), "Depth 1 2 - 2 depth 1");
+    }
+
+    #[test]
+    #[ignore = "plays ten whole games"]
+    fn a_match_at_the_lowest_level_finishes() {
+        let scratch = Scratch::new("match");
+        let config = MatchConfig {
+            report: scratch.join("match.json"),
+            ..config(10)
+        };
+        let mut finished = 0;
+        let tally = play_match(&config, &AtomicBool::new(false), &mut |event| {
+            if let MatchEvent::Finished(record) = event {
+                assert!(record.state != GameState::Ongoing);
+                finished += 1;
+            }
+            true
+        })
+        .unwrap();
+        assert_eq!(finished, 10);
+        assert_eq!(tally.games(), 10);
+    }
+}
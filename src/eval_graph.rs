@@ -0,0 +1,188 @@
+use crate::HistoryEntry;
+use jonathan_hallstrom_chess::Color;
+
+// Scores beyond this many centipawns are drawn at the edge of the graph
+pub const CLIP: i32 = 600;
+// How many centipawns a move gives away before it is marked
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+
+/// A move that lost enough of the evaluation to be marked in the graph and the move list
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// The PGN annotation glyph
+    #[inline]
+    pub fn glyph(&self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+}
+
+/// Where the position after `ply` goes across a graph one unit wide showing `positions` of them,
+/// a single position sits in the middle
+#[inline]
+pub fn ply_x(ply: usize, positions: usize) -> f32 {
+    match positions.saturating_sub(1) {
+        0 => 0.5,
+        last => ply as f32 / last as f32,
+    }
+}
+
+/// Where the score of the position after `ply` goes in a graph one unit wide and high.
+/// y is 0 at +CLIP for White and 1 at -CLIP, so the axis is at 0.5.
+pub fn point(scores: &[Option<i32>], ply: usize) -> Option<(f32, f32)> {
+    let score = scores.get(ply).copied().flatten()?.clamp(-CLIP, CLIP);
+    Some((
+        ply_x(ply, scores.len()),
+        0.5 - score as f32 / (2 * CLIP) as f32,
+    ))
+}
+
+/// The points of every position, positions without a score yet are left out
+pub fn polyline(scores: &[Option<i32>]) -> Vec<(f32, f32)> {
+    (0..scores.len())
+        .filter_map(|ply| point(scores, ply))
+        .collect()
+}
+
+/// The polyline with a point added wherever it crosses the axis, so the areas on either side
+/// can be filled from clamped copies of it without overlapping
+pub fn split_at_axis(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let mut split = Vec::with_capacity(points.len());
+    for (i, &(x, y)) in points.iter().enumerate() {
+        if let Some(&(last_x, last_y)) = points[..i].last() {
+            if (last_y - 0.5) * (y - 0.5) < 0.0 {
+                let t = (0.5 - last_y) / (y - last_y);
+                split.push((last_x + (x - last_x) * t, 0.5));
+            }
+        }
+        split.push((x, y));
+    }
+    split
+}
+
+/// The position nearest to `x` across a graph one unit wide showing `positions` of them
+#[inline]
+pub fn ply_at(x: f32, positions: usize) -> usize {
+    let last = positions.saturating_sub(1);
+    ((x.clamp(0.0, 1.0) * last as f32).round() as usize).min(last)
+}
+
+/// How bad each move of the history was, by how much it lost for the side that played it.
+/// A move is judged once the positions before and after it both have a score.
+pub fn judgements(scores: &[Option<i32>], history: &[HistoryEntry]) -> Vec<Option<Judgement>> {
+    history
+        .iter()
+        .enumerate()
+        .map(|(ply, entry)| {
+            let before = scores.get(ply).copied().flatten()?.clamp(-CLIP, CLIP);
+            let after = scores.get(ply + 1).copied().flatten()?.clamp(-CLIP, CLIP);
+            let lost = match entry.color {
+                Color::White => before - after,
+                Color::Black => after - before,
+            };
+            match lost {
+                _ if lost >= BLUNDER => Some(Judgement::Blunder),
+                _ if lost >= MISTAKE => Some(Judgement::Mistake),
+                _ if lost >= INACCURACY => Some(Judgement::Inaccuracy),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(color: Color) -> HistoryEntry {
+        HistoryEntry {
+            notation: String::new(),
+            san: String::new(),
+            color,
+            captured: None,
+            glyph: None,
+        }
+    }
+
+    #[test]
+    fn an_empty_game_has_no_points() {
+        assert!(polyline(&[]).is_empty());
+        assert!(polyline(&[None, None]).is_empty());
+        assert_eq!(ply_at(0.7, 0), 0);
+    }
+
+    #[test]
+    fn a_single_position_is_centered() {
+        assert_eq!(polyline(&[Some(0)]), [(0.5, 0.5)]);
+        assert_eq!(ply_at(0.9, 1), 0);
+    }
+
+    #[test]
+    fn scores_are_clipped_to_the_graph() {
+        let points = polyline(&[Some(1_000_000), Some(300), None, Some(-CLIP * 3)]);
+        assert_eq!(points, [(0.0, 0.0), (1.0 / 3.0, 0.25), (1.0, 1.0)]);
+    }
+
+    #[test]
+    fn crossings_land_on_the_axis() {
+        let points = [(0.0, 0.25), (0.5, 0.75), (1.0, 0.5)];
+        assert_eq!(
+            split_at_axis(&points),
+            [(0.0, 0.25), (0.25, 0.5), (0.5, 0.75), (1.0, 0.5)]
+        );
+        assert!(split_at_axis(&[]).is_empty());
+    }
+
+    #[test]
+    fn clicks_pick_the_nearest_position() {
+        assert_eq!(ply_at(0.0, 11), 0);
+        assert_eq!(ply_at(0.44, 11), 4);
+        assert_eq!(ply_at(0.46, 11), 5);
+        assert_eq!(ply_at(1.5, 11), 10);
+        assert_eq!(ply_at(-1.0, 11), 0);
+    }
+
+    #[test]
+    fn moves_are_judged_for_the_side_that_played_them() {
+        let history = [
+            entry(Color::White),
+            entry(Color::Black),
+            entry(Color::White),
+            entry(Color::Black),
+        ];
+        // White drops a rook, Black gives a little back, White allows mate and Black misses it.
+        // Mates count as the clipped score, so they aren't worth more than the edge of the graph.
+        let scores = [
+            Some(0),
+            Some(-500),
+            Some(-440),
+            Some(-1_000_000),
+            Some(-500),
+        ];
+        assert_eq!(
+            judgements(&scores, &history),
+            [
+                Some(Judgement::Blunder),
+                Some(Judgement::Inaccuracy),
+                Some(Judgement::Mistake),
+                Some(Judgement::Mistake),
+            ]
+        );
+        // Nothing is judged before both of its positions are scored
+        assert_eq!(
+            judgements(&[Some(0), None, Some(-900)], &history[..2]),
+            [None, None]
+        );
+    }
+}
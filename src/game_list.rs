@@ -0,0 +1,570 @@
+use crate::pgn;
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// What the list of saved games shows of a game, read from its tags without playing its moves
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct GameHeaders {
+    pub white: String,
+    pub black: String,
+    // As written, YYYY.MM.DD with ?? for what isn't known
+    pub date: String,
+    pub result: String,
+    // The Opening tag, or the ECO code of a game without one
+    pub opening: Option<String>,
+    pub plies: usize,
+    // Comments like {[#] blunder} in the moves
+    pub bookmarks: usize,
+}
+
+/// One game of a PGN file in the save directory, files with several games get one for each
+#[derive(Clone, PartialEq, Debug)]
+pub struct ListedGame {
+    pub path: PathBuf,
+    // Which game of the file it is, from 0
+    pub index: usize,
+    // Why the game couldn't be read, it is still listed
+    pub headers: Result<GameHeaders, String>,
+}
+
+impl ListedGame {
+    #[inline]
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// The text of each game in a PGN file, a new game starts at tags that follow moves
+pub fn split_games(text: &str) -> Vec<&str> {
+    let mut games = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_moves = false;
+    let mut in_comment = false;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let is_tag = !in_comment && trimmed.starts_with('[');
+        if is_tag && in_moves {
+            games.push(&text[start..offset]);
+            start = offset;
+            in_moves = false;
+        } else if !is_tag && !trimmed.is_empty() {
+            in_moves = true;
+        }
+        offset += line.len();
+        if is_tag {
+            continue;
+        }
+        // A brace comment can run over several lines and hide brackets
+        for character in trimmed.chars() {
+            match character {
+                '{' => in_comment = true,
+                '}' => in_comment = false,
+                _ => {}
+            }
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        games.push(&text[start..]);
+    }
+    games
+}
+
+/// The `[Name "value"]` of a tag line
+fn parse_tag(line: &str) -> Option<(&str, &str)> {
+    let tag = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = tag.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name, value))
+}
+
+/// The headers of a single game and how many plies it has, its moves are only counted
+pub fn read_game_headers(text: &str) -> Result<GameHeaders, String> {
+    let mut headers = GameHeaders::default();
+    let mut eco = None;
+    let mut result_token = None;
+    // How deep in comments and variations the move text is, nothing in them is counted
+    let mut in_comment = false;
+    let mut comment = String::new();
+    let mut variations = 0usize;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('%') || (!in_comment && line.starts_with(';')) {
+            continue;
+        }
+        if !in_comment && line.starts_with('[') {
+            let (name, value) =
+                parse_tag(line).ok_or_else(|| format!("line {}: broken tag", number + 1))?;
+            let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+            match name {
+                "White" => headers.white = value,
+                "Black" => headers.black = value,
+                "Date" => headers.date = value,
+                "Result" => headers.result = value,
+                "Opening" => headers.opening = Some(value),
+                "ECO" => eco = Some(value),
+                _ => {}
+            }
+            continue;
+        }
+        let mut rest = line;
+        while !rest.is_empty() {
+            if in_comment {
+                match rest.find('}') {
+                    Some(end) => {
+                        in_comment = false;
+                        comment.push_str(&rest[..end]);
+                        if variations == 0 && pgn::split_comment(&comment, 0).1.is_some() {
+                            headers.bookmarks += 1;
+                        }
+                        comment.clear();
+                        rest = &rest[end + 1..];
+                    }
+                    None => {
+                        comment.push_str(rest);
+                        comment.push(' ');
+                        break;
+                    }
+                }
+                continue;
+            }
+            let rest_trimmed = rest.trim_start();
+            let Some(first) = rest_trimmed.chars().next() else {
+                break;
+            };
+            match first {
+                '{' => {
+                    in_comment = true;
+                    rest = &rest_trimmed[1..];
+                    continue;
+                }
+                // The rest of the line is a comment
+                ';' => break,
+                '(' => variations += 1,
+                ')' => {
+                    variations = variations
+                        .checked_sub(1)
+                        .ok_or_else(|| format!("line {}: unopened variation", number + 1))?
+                }
+                _ => {}
+            }
+            // A closing brace of no comment is skipped, it would end no token
+            if matches!(first, '(' | ')' | '}') {
+                rest = &rest_trimmed[1..];
+                continue;
+            }
+            let end = rest_trimmed
+                .find(|character: char| character.is_whitespace() || "{}();".contains(character))
+                .unwrap_or(rest_trimmed.len());
+            let token = &rest_trimmed[..end];
+            rest = &rest_trimmed[end..];
+            if variations > 0 || token.starts_with('$') {
+                continue;
+            }
+            if RESULTS.contains(&token) {
+                result_token = Some(token);
+                continue;
+            }
+            // Move numbers can be glued to their move, like 1.e4 or 3...Nc6
+            let mv = token.trim_start_matches(|character: char| {
+                character.is_ascii_digit() || character == '.'
+            });
+            if !mv.is_empty() {
+                headers.plies += 1;
+            }
+        }
+    }
+    if in_comment {
+        return Err("a comment is never closed".to_owned());
+    }
+    if variations > 0 {
+        return Err("a variation is never closed".to_owned());
+    }
+    if headers.opening.is_none() {
+        headers.opening = eco;
+    }
+    if headers.result.is_empty() {
+        headers.result = result_token.unwrap_or("*").to_owned();
+    }
+    Ok(headers)
+}
+
+/// The headers of every game in a PGN file, a file without any game is listed as broken
+pub fn read_headers(text: &str) -> Vec<Result<GameHeaders, String>> {
+    match split_games(text) {
+        games if games.is_empty() => vec![Err("there are no games in the file".to_owned())],
+        games => games.into_iter().map(read_game_headers).collect(),
+    }
+}
+
+/// What the list of saved games is sorted by
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum SortKey {
+    // Newest first
+    Date,
+    // Which side was ours isn't saved, so by White and then Black
+    Players,
+    // White's wins, Black's wins, draws and then unfinished games
+    Result,
+}
+
+impl SortKey {
+    #[inline]
+    pub fn next(&self) -> Self {
+        match self {
+            SortKey::Date => SortKey::Players,
+            SortKey::Players => SortKey::Result,
+            SortKey::Result => SortKey::Date,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortKey::Date => "date",
+            SortKey::Players => "players",
+            SortKey::Result => "result",
+        }
+    }
+}
+
+fn compare(a: &GameHeaders, b: &GameHeaders, key: SortKey) -> Ordering {
+    let result_rank = |result: &str| RESULTS.iter().position(|known| *known == result);
+    match key {
+        // Dates with unknown parts sort as if they were the oldest
+        SortKey::Date => b.date.replace('?', "0").cmp(&a.date.replace('?', "0")),
+        SortKey::Players => (a.white.to_lowercase(), a.black.to_lowercase())
+            .cmp(&(b.white.to_lowercase(), b.black.to_lowercase())),
+        SortKey::Result => result_rank(&a.result)
+            .unwrap_or(RESULTS.len())
+            .cmp(&result_rank(&b.result).unwrap_or(RESULTS.len())),
+    }
+}
+
+fn matches_filter(game: &ListedGame, filter: &str) -> bool {
+    let found = |field: &str| field.to_lowercase().contains(filter);
+    found(&game.file_name())
+        || game.headers.as_ref().is_ok_and(|headers| {
+            [
+                &headers.white,
+                &headers.black,
+                &headers.date,
+                &headers.result,
+            ]
+            .into_iter()
+            .chain(&headers.opening)
+            .any(|field| found(field))
+        })
+}
+
+/// The indices of the games that contain the filter in any column, ignoring case, sorted by the key.
+/// Broken files go last and ties keep the order of the files.
+pub fn list_order(games: &[ListedGame], key: SortKey, filter: &str) -> Vec<usize> {
+    let filter = filter.trim().to_lowercase();
+    let mut order: Vec<usize> = (0..games.len())
+        .filter(|&index| matches_filter(&games[index], &filter))
+        .collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&games[a], &games[b]);
+        let by_key = match (&a.headers, &b.headers) {
+            (Ok(a), Ok(b)) => compare(a, b, key),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => Ordering::Equal,
+        };
+        by_key.then_with(|| (&a.path, a.index).cmp(&(&b.path, b.index)))
+    });
+    order
+}
+
+/// The rows of a list scrolled down by `scroll` that show in a view `height` high,
+/// only these are laid out
+pub fn visible_rows(scroll: f32, row_height: f32, height: f32, rows: usize) -> Range<usize> {
+    if row_height <= 0.0 || rows == 0 {
+        return 0..0;
+    }
+    let scroll = scroll.max(0.0);
+    let first = ((scroll / row_height).floor() as usize).min(rows);
+    let last = (((scroll + height) / row_height).ceil() as usize).min(rows);
+    first..last.max(first)
+}
+
+/// How far the list can be scrolled before its last row leaves the bottom of the view
+#[inline]
+pub fn max_scroll(row_height: f32, height: f32, rows: usize) -> f32 {
+    (rows as f32 * row_height - height).max(0.0)
+}
+
+/// The row under `y`, measured from the top of the view
+pub fn row_at(y: f32, scroll: f32, row_height: f32, rows: usize) -> Option<usize> {
+    if y < 0.0 || row_height <= 0.0 {
+        return None;
+    }
+    let row = ((y + scroll.max(0.0)) / row_height).floor() as usize;
+    (row < rows).then_some(row)
+}
+
+/// The scroll that brings `row` into a view `height` high, moving as little as it can
+pub fn scroll_to(row: usize, scroll: f32, row_height: f32, height: f32) -> f32 {
+    let top = row as f32 * row_height;
+    match () {
+        _ if top < scroll => top,
+        _ if top + row_height > scroll + height => top + row_height - height,
+        _ => scroll,
+    }
+}
+
+/// Reads the headers of every PGN file in a directory on another thread, so a large folder
+/// doesn't hold up the window
+pub struct Scan {
+    // The games of each file once it is read, or why the directory couldn't be listed
+    receiver: Receiver<Result<Vec<ListedGame>, String>>,
+    read: usize,
+    // How many files there are, None until the directory is listed
+    total: Option<usize>,
+    total_receiver: Receiver<usize>,
+    done: bool,
+}
+
+fn listed_games(path: &Path) -> Vec<ListedGame> {
+    let games = match std::fs::read_to_string(path) {
+        Ok(text) => read_headers(&text),
+        Err(err) => vec![Err(err.to_string())],
+    };
+    games
+        .into_iter()
+        .enumerate()
+        .map(|(index, headers)| ListedGame {
+            path: path.to_owned(),
+            index,
+            headers,
+        })
+        .collect()
+}
+
+impl Scan {
+    pub fn start(dir: PathBuf) -> Self {
+        let (results, receiver) = mpsc::channel();
+        let (totals, total_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    let _ = results.send(Err(format!("Can't read {}: {}", dir.display(), err)));
+                    return;
+                }
+            };
+            let mut paths: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|extension| extension == "pgn"))
+                .collect();
+            paths.sort();
+            if totals.send(paths.len()).is_err() {
+                return;
+            }
+            for path in paths {
+                if results.send(Ok(listed_games(&path))).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            receiver,
+            read: 0,
+            total: None,
+            total_receiver,
+            done: false,
+        }
+    }
+
+    /// The games of the files read since the last call
+    pub fn poll(&mut self) -> Result<Vec<ListedGame>, String> {
+        if let Ok(total) = self.total_receiver.try_recv() {
+            self.total = Some(total);
+        }
+        let mut games = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(Ok(file)) => {
+                    self.read += 1;
+                    games.extend(file);
+                }
+                Ok(Err(message)) => {
+                    self.done = true;
+                    return Err(message);
+                }
+                Err(TryRecvError::Empty) => return Ok(games),
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    return Ok(games);
+                }
+            }
+        }
+    }
+
+    /// How many of the files are read, from 0 to 100
+    #[inline]
+    pub fn percent(&self) -> usize {
+        match self.total {
+            _ if self.done => 100,
+            Some(0) | None => 0,
+            Some(total) => self.read * 100 / total,
+        }
+    }
+
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_GAMES: &str = "[Event \"Match\"]
+[White \"Ada\"]
+[Black \"Bo\"]
+[Date \"2024.03.01\"]
+[Result \"1-0\"]
+[ECO \"C20\"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+
+[Event \"Match\"]
+[White \"Bo\"]
+[Black \"Ada\"]
+[Date \"2024.03.02\"]
+[Opening \"Sicilian {Najdorf}\"]
+
+1.e4 c5 {the Sicilian,
+[not a tag]} 2. Nf3 (2. c3 {[#]} d5) d6 $1 {[#]
+sharp} 3... a6 ; a comment
+1/2-1/2
+";
+
+    fn listed(path: &str, index: usize, headers: Result<GameHeaders, String>) -> ListedGame {
+        ListedGame {
+            path: PathBuf::from(path),
+            index,
+            headers,
+        }
+    }
+
+    fn headers(white: &str, black: &str, date: &str, result: &str) -> Result<GameHeaders, String> {
+        Ok(GameHeaders {
+            white: white.to_owned(),
+            black: black.to_owned(),
+            date: date.to_owned(),
+            result: result.to_owned(),
+            opening: None,
+            plies: 0,
+            bookmarks: 0,
+        })
+    }
+
+    #[test]
+    fn headers_are_read_without_the_moves() {
+        let games = read_headers(&TWO_GAMES[..TWO_GAMES.find("\n\n[").unwrap()]);
+        assert_eq!(
+            games,
+            [Ok(GameHeaders {
+                white: "Ada".to_owned(),
+                black: "Bo".to_owned(),
+                date: "2024.03.01".to_owned(),
+                result: "1-0".to_owned(),
+                opening: Some("C20".to_owned()),
+                plies: 7,
+                bookmarks: 0,
+            })]
+        );
+    }
+
+    #[test]
+    fn every_game_of_a_match_file_is_read() {
+        assert_eq!(split_games(TWO_GAMES).len(), 2);
+        let games = read_headers(TWO_GAMES);
+        let second = games[1].as_ref().unwrap();
+        assert_eq!(
+            (second.white.as_str(), second.black.as_str()),
+            ("Bo", "Ada")
+        );
+        assert_eq!(second.opening.as_deref(), Some("Sicilian {Najdorf}"));
+        // Comments, variations, glyphs and move numbers aren't moves
+        assert_eq!(second.plies, 5);
+        // Only the bookmark in the moves themselves, not the one in the variation
+        assert_eq!(second.bookmarks, 1);
+        // Taken from the end of the moves when there is no Result tag
+        assert_eq!(second.result, "1/2-1/2");
+    }
+
+    #[test]
+    fn broken_games_say_what_is_wrong() {
+        assert_eq!(
+            read_headers("[White \"Ada\"]\n[Black Bo]\n\n1. e4 *\n"),
+            [Err("line 2: broken tag".to_owned())]
+        );
+        assert_eq!(
+            read_headers("[White \"Ada\"]\n\n1. e4 {never closed *\n"),
+            [Err("a comment is never closed".to_owned())]
+        );
+        assert_eq!(
+            read_headers("1. e4 ) e5 *"),
+            [Err("line 1: unopened variation".to_owned())]
+        );
+        assert_eq!(
+            read_headers(" \n"),
+            [Err("there are no games in the file".to_owned())]
+        );
+        // A broken game doesn't take the rest of the file with it
+        let games = read_headers("[White Ada]\n\n1. e4 *\n\n[White \"Bo\"]\n\n1. d4 *\n");
+        assert!(games[0].is_err());
+        assert_eq!(games[1].as_ref().unwrap().white, "Bo");
+    }
+
+    #[test]
+    fn games_are_sorted_and_filtered() {
+        let games = [
+            listed("a.pgn", 0, headers("Ada", "Bo", "2024.03.01", "0-1")),
+            listed("b.pgn", 0, Err("line 1: broken tag".to_owned())),
+            listed("c.pgn", 0, headers("ada", "Cy", "2024.??.??", "1-0")),
+            listed("c.pgn", 1, headers("Bo", "Ada", "2024.05.20", "*")),
+        ];
+        assert_eq!(list_order(&games, SortKey::Date, ""), [3, 0, 2, 1]);
+        assert_eq!(list_order(&games, SortKey::Players, ""), [0, 2, 3, 1]);
+        assert_eq!(list_order(&games, SortKey::Result, ""), [2, 0, 3, 1]);
+        assert_eq!(list_order(&games, SortKey::Date, " ADA "), [3, 0, 2]);
+        assert_eq!(list_order(&games, SortKey::Date, "cy"), [2]);
+        // Broken files can still be found by their name
+        assert_eq!(list_order(&games, SortKey::Date, "b.pgn"), [1]);
+        assert!(list_order(&games, SortKey::Date, "nobody").is_empty());
+    }
+
+    #[test]
+    fn only_the_rows_in_view_are_laid_out() {
+        assert_eq!(visible_rows(0.0, 20.0, 100.0, 1000), 0..5);
+        // Rows cut off at either edge are still drawn
+        assert_eq!(visible_rows(30.0, 20.0, 100.0, 1000), 1..7);
+        assert_eq!(visible_rows(19_990.0, 20.0, 100.0, 1000), 999..1000);
+        assert_eq!(visible_rows(0.0, 20.0, 100.0, 3), 0..3);
+        assert_eq!(visible_rows(50.0, 20.0, 100.0, 0), 0..0);
+
+        assert_eq!(max_scroll(20.0, 100.0, 1000), 19_900.0);
+        assert_eq!(max_scroll(20.0, 100.0, 3), 0.0);
+
+        assert_eq!(row_at(5.0, 30.0, 20.0, 1000), Some(1));
+        assert_eq!(row_at(75.0, 0.0, 20.0, 3), None);
+        assert_eq!(row_at(-1.0, 0.0, 20.0, 3), None);
+
+        assert_eq!(scroll_to(0, 50.0, 20.0, 100.0), 0.0);
+        assert_eq!(scroll_to(3, 50.0, 20.0, 100.0), 50.0);
+        assert_eq!(scroll_to(10, 50.0, 20.0, 100.0), 120.0);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::eval_graph::Judgement;
+
+/// An annotation glyph set on a move while reviewing, in the order of the keys 1 to 6 that set
+/// them and of their NAG numbers
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Glyph {
+    Good,
+    Mistake,
+    Brilliant,
+    Blunder,
+    Interesting,
+    Dubious,
+}
+
+const GLYPHS: [Glyph; 6] = [
+    Glyph::Good,
+    Glyph::Mistake,
+    Glyph::Brilliant,
+    Glyph::Blunder,
+    Glyph::Interesting,
+    Glyph::Dubious,
+];
+
+impl Glyph {
+    /// How it is written after the move, like "?!"
+    #[inline]
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Glyph::Good => "!",
+            Glyph::Mistake => "?",
+            Glyph::Brilliant => "!!",
+            Glyph::Blunder => "??",
+            Glyph::Interesting => "!?",
+            Glyph::Dubious => "?!",
+        }
+    }
+
+    /// The Numeric Annotation Glyph standing for it, $1 to $6
+    #[inline]
+    pub fn nag(&self) -> u8 {
+        GLYPHS.iter().position(|glyph| glyph == self).unwrap() as u8 + 1
+    }
+
+    #[inline]
+    pub fn from_nag(nag: u8) -> Option<Self> {
+        GLYPHS.get(usize::from(nag).checked_sub(1)?).copied()
+    }
+
+    #[inline]
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        GLYPHS
+            .iter()
+            .copied()
+            .find(|glyph| glyph.symbol() == symbol)
+    }
+}
+
+/// How glyphs are written to PGN
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub enum GlyphStyle {
+    // Right after the move like "e4!?", how people read them
+    #[default]
+    Suffix,
+    // As a token of their own after the move like "e4 $5", how some tools want them
+    Nag,
+}
+
+impl GlyphStyle {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "suffix" => Some(GlyphStyle::Suffix),
+            "nag" => Some(GlyphStyle::Nag),
+            _ => None,
+        }
+    }
+
+    /// The move as written to PGN with its glyph
+    pub fn write(&self, san: &str, glyph: Option<Glyph>) -> String {
+        match (glyph, self) {
+            (None, _) => san.to_owned(),
+            (Some(glyph), GlyphStyle::Suffix) => format!("{}{}", san, glyph.symbol()),
+            (Some(glyph), GlyphStyle::Nag) => format!("{} ${}", san, glyph.nag()),
+        }
+    }
+}
+
+/// A move token of PGN without its suffix glyph, and the glyph. Suffixes that aren't one of the
+/// six are dropped with it.
+pub fn split_suffix(token: &str) -> (&str, Option<Glyph>) {
+    let san = token.trim_end_matches(['!', '?']);
+    (san, Glyph::from_symbol(&token[san.len()..]))
+}
+
+/// The glyph of a NAG token like "$4", None for other tokens and for NAG numbers past the six
+#[inline]
+pub fn parse_nag(token: &str) -> Option<Glyph> {
+    Glyph::from_nag(token.strip_prefix('$')?.parse().ok()?)
+}
+
+/// The glyphs auto-annotating would set, as the move's index and the glyph: ? for a mistake and
+/// ?? for a blunder by the engine's judgements. Moves that have a glyph already keep it.
+pub fn auto_annotate(
+    judgements: &[Option<Judgement>],
+    glyphs: &[Option<Glyph>],
+) -> Vec<(usize, Glyph)> {
+    judgements
+        .iter()
+        .enumerate()
+        .filter(|(mv, _)| glyphs.get(*mv).copied().flatten().is_none())
+        .filter_map(|(mv, judgement)| match judgement {
+            Some(Judgement::Mistake) => Some((mv, Glyph::Mistake)),
+            Some(Judgement::Blunder) => Some((mv, Glyph::Blunder)),
+            Some(Judgement::Inaccuracy) | None => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval_graph;
+    use crate::HistoryEntry;
+    use jonathan_hallstrom_chess::Color;
+
+    #[test]
+    fn glyphs_write_and_read_back_either_way() {
+        for glyph in GLYPHS {
+            assert_eq!(Glyph::from_nag(glyph.nag()), Some(glyph));
+            assert_eq!(Glyph::from_symbol(glyph.symbol()), Some(glyph));
+
+            let suffixed = GlyphStyle::Suffix.write("Nf3+", Some(glyph));
+            assert_eq!(split_suffix(&suffixed), ("Nf3+", Some(glyph)));
+
+            let tagged = GlyphStyle::Nag.write("Nf3+", Some(glyph));
+            let (san, nag) = tagged.split_once(' ').unwrap();
+            assert_eq!(split_suffix(san), ("Nf3+", None));
+            assert_eq!(parse_nag(nag), Some(glyph));
+        }
+        assert_eq!(GlyphStyle::Suffix.write("e4", None), "e4");
+        assert_eq!(GlyphStyle::Nag.write("e4", Some(Glyph::Good)), "e4 $1");
+        assert_eq!(GlyphStyle::Suffix.write("e4", Some(Glyph::Dubious)), "e4?!");
+        assert_eq!(Glyph::from_nag(3), Some(Glyph::Brilliant));
+    }
+
+    #[test]
+    fn other_annotations_are_not_glyphs() {
+        // $7 and up say things about the position, not the move
+        assert_eq!(parse_nag("$7"), None);
+        assert_eq!(parse_nag("$0"), None);
+        assert_eq!(parse_nag("e4"), None);
+        assert_eq!(split_suffix("e4!!!"), ("e4", None));
+        assert_eq!(split_suffix("O-O-O"), ("O-O-O", None));
+        assert_eq!(GlyphStyle::parse("nag"), Some(GlyphStyle::Nag));
+        assert_eq!(GlyphStyle::parse("symbols"), None);
+    }
+
+    fn entry(color: Color) -> HistoryEntry {
+        HistoryEntry {
+            notation: String::new(),
+            san: String::new(),
+            color,
+            captured: None,
+            glyph: None,
+        }
+    }
+
+    #[test]
+    fn only_mistakes_and_blunders_without_a_glyph_are_marked() {
+        let history: Vec<HistoryEntry> = [Color::White, Color::Black]
+            .into_iter()
+            .cycle()
+            .take(6)
+            .map(entry)
+            .collect();
+        // White gives away 60, Black 150, White 400, Black nothing, then White 120 and Black 500
+        let scores = [
+            Some(20),
+            Some(-40),
+            Some(110),
+            Some(-290),
+            Some(-290),
+            Some(-410),
+            Some(90),
+        ];
+        let judgements = eval_graph::judgements(&scores, &history);
+        assert_eq!(
+            auto_annotate(&judgements, &[None; 6]),
+            [
+                (1, Glyph::Mistake),
+                (2, Glyph::Blunder),
+                (4, Glyph::Mistake),
+                (5, Glyph::Blunder)
+            ]
+        );
+
+        // What was set by hand stays, whatever the engine thinks of it
+        let glyphs = [
+            None,
+            None,
+            Some(Glyph::Interesting),
+            None,
+            None,
+            Some(Glyph::Good),
+        ];
+        assert_eq!(
+            auto_annotate(&judgements, &glyphs),
+            [(1, Glyph::Mistake), (4, Glyph::Mistake)]
+        );
+    }
+}
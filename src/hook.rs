@@ -0,0 +1,601 @@
+use crate::negotiation::{Negotiation, Outcome};
+use jonathan_hallstrom_chess::Color;
+use serde_json::{Map, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Written into every line sent to the hook and checked in every answer
+pub const SCHEMA_VERSION: u64 = 1;
+/// How long a filter may take to answer before the move is played without it
+pub const DEFAULT_FILTER_TIMEOUT: Duration = Duration::from_millis(500);
+// Lines waiting for either side, more are dropped so a slow hook never holds up the game
+const QUEUE: usize = 256;
+
+/// How the hook is run, from the command line
+#[derive(Clone, PartialEq, Debug)]
+pub struct HookConfig {
+    // The program and its arguments, split at spaces since no shell is involved
+    pub command: Vec<String>,
+    // Asked before every move of the player's is played or sent
+    pub filter: bool,
+    pub timeout: Duration,
+    // Networked games refuse the hook without it, it could be playing for the player
+    pub allow_networked: bool,
+}
+
+/// What the hook is told, one JSON object a line
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    GameStarted {
+        white: String,
+        black: String,
+        fen: String,
+        networked: bool,
+        // Milliseconds left for white and black, None in an untimed game
+        clocks: Option<[u64; 2]>,
+    },
+    Moved {
+        // The plies played once it is
+        ply: usize,
+        san: String,
+        uci: String,
+        fen_before: String,
+        fen_after: String,
+        clocks: Option<[u64; 2]>,
+    },
+    GameEnded {
+        result: String,
+        termination: Option<String>,
+    },
+    Draw {
+        ply: usize,
+        side: String,
+        outcome: String,
+    },
+    Connection {
+        status: String,
+    },
+    // A filter answers it with a verdict of the same id
+    Consult {
+        id: u64,
+        uci: String,
+        fen: String,
+    },
+}
+
+/// What a filter thinks of a move
+#[derive(Clone, PartialEq, Debug)]
+pub enum Verdict {
+    Pass,
+    // With the reason shown to the player
+    Veto(String),
+}
+
+#[inline]
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+impl Event {
+    pub fn draw(negotiation: &Negotiation) -> Self {
+        Event::Draw {
+            ply: negotiation.ply,
+            side: color_name(negotiation.side).to_owned(),
+            outcome: match negotiation.outcome {
+                Outcome::Offered => "offered",
+                Outcome::Accepted => "accepted",
+                Outcome::Declined => "declined",
+                Outcome::Lapsed => "lapsed",
+            }
+            .to_owned(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::GameStarted { .. } => "game_started",
+            Event::Moved { .. } => "moved",
+            Event::GameEnded { .. } => "game_ended",
+            Event::Draw { .. } => "draw",
+            Event::Connection { .. } => "connection",
+            Event::Consult { .. } => "consult",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("version".to_owned(), Value::Number(SCHEMA_VERSION.into()));
+        object.insert("event".to_owned(), Value::String(self.name().to_owned()));
+        let text = |text: &str| Value::String(text.to_owned());
+        let clocks = |clocks: &Option<[u64; 2]>| {
+            clocks.map_or(Value::Null, |[white, black]| {
+                Value::Array(vec![
+                    Value::Number(white.into()),
+                    Value::Number(black.into()),
+                ])
+            })
+        };
+        let fields: Vec<(&str, Value)> = match self {
+            Event::GameStarted {
+                white,
+                black,
+                fen,
+                networked,
+                clocks: left,
+            } => vec![
+                ("white", text(white)),
+                ("black", text(black)),
+                ("fen", text(fen)),
+                ("networked", Value::Bool(*networked)),
+                ("clocks", clocks(left)),
+            ],
+            Event::Moved {
+                ply,
+                san,
+                uci,
+                fen_before,
+                fen_after,
+                clocks: left,
+            } => vec![
+                ("ply", Value::Number((*ply as u64).into())),
+                ("san", text(san)),
+                ("uci", text(uci)),
+                ("fen_before", text(fen_before)),
+                ("fen_after", text(fen_after)),
+                ("clocks", clocks(left)),
+            ],
+            Event::GameEnded {
+                result,
+                termination,
+            } => vec![
+                ("result", text(result)),
+                (
+                    "termination",
+                    termination.as_deref().map_or(Value::Null, text),
+                ),
+            ],
+            Event::Draw { ply, side, outcome } => vec![
+                ("ply", Value::Number((*ply as u64).into())),
+                ("side", text(side)),
+                ("outcome", text(outcome)),
+            ],
+            Event::Connection { status } => vec![("status", text(status))],
+            Event::Consult { id, uci, fen } => vec![
+                ("id", Value::Number((*id).into())),
+                ("uci", text(uci)),
+                ("fen", text(fen)),
+            ],
+        };
+        for (key, value) in fields {
+            object.insert(key.to_owned(), value);
+        }
+        Value::Object(object)
+    }
+
+    /// The event `to_json` wrote, None for another version or anything damaged
+    pub fn from_json(value: &Value) -> Option<Self> {
+        if value.get("version")?.as_u64()? != SCHEMA_VERSION {
+            return None;
+        }
+        let text = |key: &str| Some(value.get(key)?.as_str()?.to_owned());
+        let number = |key: &str| value.get(key)?.as_u64();
+        let clocks = || match value.get("clocks")? {
+            Value::Null => Some(None),
+            Value::Array(left) if left.len() == 2 => {
+                Some(Some([left[0].as_u64()?, left[1].as_u64()?]))
+            }
+            _ => None,
+        };
+        Some(match value.get("event")?.as_str()? {
+            "game_started" => Event::GameStarted {
+                white: text("white")?,
+                black: text("black")?,
+                fen: text("fen")?,
+                networked: value.get("networked")?.as_bool()?,
+                clocks: clocks()?,
+            },
+            "moved" => Event::Moved {
+                ply: number("ply")? as usize,
+                san: text("san")?,
+                uci: text("uci")?,
+                fen_before: text("fen_before")?,
+                fen_after: text("fen_after")?,
+                clocks: clocks()?,
+            },
+            "game_ended" => Event::GameEnded {
+                result: text("result")?,
+                termination: match value.get("termination")? {
+                    Value::Null => None,
+                    termination => Some(termination.as_str()?.to_owned()),
+                },
+            },
+            "draw" => Event::Draw {
+                ply: number("ply")? as usize,
+                side: text("side")?,
+                outcome: text("outcome")?,
+            },
+            "connection" => Event::Connection {
+                status: text("status")?,
+            },
+            "consult" => Event::Consult {
+                id: number("id")?,
+                uci: text("uci")?,
+                fen: text("fen")?,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// A filter's answer to a consult, like {"version":1,"id":3,"verdict":"veto","reason":"hangs
+/// the queen"}. None for anything else, which turns the hook off.
+pub fn parse_answer(line: &str) -> Option<(u64, Verdict)> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    if value.get("version")?.as_u64()? != SCHEMA_VERSION {
+        return None;
+    }
+    let id = value.get("id")?.as_u64()?;
+    let verdict = match value.get("verdict")?.as_str()? {
+        "pass" => Verdict::Pass,
+        "veto" => Verdict::Veto(
+            value
+                .get("reason")
+                .and_then(Value::as_str)
+                .unwrap_or("no reason given")
+                .to_owned(),
+        ),
+        _ => return None,
+    };
+    Some((id, verdict))
+}
+
+/// An external program fed the events of the games as newline-delimited JSON on its
+/// standard input, and as a filter asked about the player's moves. Everything it does wrong
+/// turns it off and nothing else, the game goes on without it.
+pub struct Hook {
+    filter: bool,
+    timeout: Duration,
+    lines: SyncSender<String>,
+    // Behind a lock so games can share the hook, only a consult reads them
+    verdicts: Mutex<Receiver<(u64, Verdict)>>,
+    next_id: AtomicU64,
+    // Why the hook was turned off, told to the player once
+    failure: Arc<Mutex<Option<String>>>,
+    told: AtomicBool,
+}
+
+impl Hook {
+    /// Start the program of `config` with piped standard input and output
+    pub fn spawn(config: &HookConfig) -> Result<Self, String> {
+        let (program, args) = config
+            .command
+            .split_first()
+            .ok_or("the hook has no command")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("can't start {}: {}", program, err))?;
+        let (Some(input), Some(output)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err("the hook's pipes weren't opened".to_owned());
+        };
+        Ok(Self::with_pipes(
+            input,
+            output,
+            config.filter,
+            config.timeout,
+        ))
+    }
+
+    /// A hook that writes to `input` and reads its answers from `output`, each on a thread of
+    /// its own
+    pub fn with_pipes(
+        mut input: impl Write + Send + 'static,
+        output: impl Read + Send + 'static,
+        filter: bool,
+        timeout: Duration,
+    ) -> Self {
+        let failure = Arc::new(Mutex::new(None));
+        let fail = |failure: &Arc<Mutex<Option<String>>>, reason: String| {
+            failure
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .get_or_insert(reason);
+        };
+        let (lines, outgoing) = mpsc::sync_channel::<String>(QUEUE);
+        let writer_failure = Arc::clone(&failure);
+        thread::spawn(move || {
+            for line in outgoing {
+                if let Err(err) = writeln!(input, "{}", line).and_then(|_| input.flush()) {
+                    fail(&writer_failure, format!("it stopped reading ({})", err));
+                    return;
+                }
+            }
+        });
+        let (answers, verdicts) = mpsc::sync_channel(QUEUE);
+        let reader_failure = Arc::clone(&failure);
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => return fail(&reader_failure, format!("can't read it: {}", err)),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_answer(&line) {
+                    // An answer nobody waits for any more is dropped
+                    Some(answer) => {
+                        let _ = answers.try_send(answer);
+                    }
+                    None => {
+                        return fail(
+                            &reader_failure,
+                            format!("it wrote something that isn't an answer: {}", line),
+                        )
+                    }
+                }
+            }
+            fail(&reader_failure, "it exited".to_owned());
+        });
+        Self {
+            filter,
+            timeout,
+            lines,
+            verdicts: Mutex::new(verdicts),
+            next_id: AtomicU64::new(0),
+            failure,
+            told: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn is_off(&self) -> bool {
+        self.failure
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .is_some()
+    }
+
+    /// Why the hook was turned off, only the first time it is asked
+    pub fn take_failure(&self) -> Option<String> {
+        let failure = self
+            .failure
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()?;
+        (!self.told.swap(true, Ordering::Relaxed)).then_some(failure)
+    }
+
+    /// Tell the hook about something, dropped if it is off or too far behind
+    pub fn send(&self, event: &Event) {
+        if self.is_off() {
+            return;
+        }
+        if let Ok(line) = serde_json::to_string(&event.to_json()) {
+            let _ = self.lines.try_send(line);
+        }
+    }
+
+    /// What a filter thinks of playing `uci` at `fen`. Without an answer in time, or from a
+    /// hook that isn't a filter or is off, the move passes.
+    pub fn consult(&self, uci: &str, fen: &str) -> Verdict {
+        if !self.filter || self.is_off() {
+            return Verdict::Pass;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.send(&Event::Consult {
+            id,
+            uci: uci.to_owned(),
+            fen: fen.to_owned(),
+        });
+        let deadline = Instant::now() + self.timeout;
+        let verdicts = self.verdicts.lock().unwrap_or_else(|err| err.into_inner());
+        loop {
+            match verdicts.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok((answered, verdict)) if answered == id => return verdict,
+                // Too late for an earlier move
+                Ok(_) => {}
+                Err(_) => return Verdict::Pass,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, PipeReader, PipeWriter};
+
+    /// A scripted stand-in for the hook's program: it reads the lines the hook writes, hands
+    /// every event to `script` and writes back what that returns. Returning None stops it
+    /// like a crash, closing both pipes.
+    fn stub(
+        timeout: Duration,
+        mut script: impl FnMut(Event) -> Option<Vec<String>> + Send + 'static,
+    ) -> Hook {
+        let (from_hook, input): (PipeReader, PipeWriter) = io::pipe().unwrap();
+        let (output, mut to_hook) = io::pipe().unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(from_hook).lines() {
+                let value: Value = serde_json::from_str(&line.unwrap()).unwrap();
+                let Some(written) = script(Event::from_json(&value).unwrap()) else {
+                    return;
+                };
+                for line in written {
+                    writeln!(to_hook, "{}", line).unwrap();
+                }
+            }
+        });
+        Hook::with_pipes(input, output, true, timeout)
+    }
+
+    fn answer(id: u64, verdict: &str) -> String {
+        format!(
+            r#"{{"version":1,"id":{},"verdict":"{}","reason":"hangs the queen"}}"#,
+            id, verdict
+        )
+    }
+
+    fn off_soon(hook: &Hook) -> Option<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Some(failure) = hook.take_failure() {
+                return Some(failure);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        None
+    }
+
+    #[test]
+    fn events_round_trip_through_json() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let events = [
+            Event::GameStarted {
+                white: "Local".to_owned(),
+                black: "Computer".to_owned(),
+                fen: fen.to_owned(),
+                networked: false,
+                clocks: Some([300_000, 300_000]),
+            },
+            Event::Moved {
+                ply: 1,
+                san: "e4".to_owned(),
+                uci: "e2e4".to_owned(),
+                fen_before: fen.to_owned(),
+                fen_after: "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_owned(),
+                clocks: None,
+            },
+            Event::GameEnded {
+                result: "1/2-1/2".to_owned(),
+                termination: None,
+            },
+            Event::Draw {
+                ply: 40,
+                side: "black".to_owned(),
+                outcome: "declined".to_owned(),
+            },
+            Event::Connection {
+                status: "lost".to_owned(),
+            },
+            Event::Consult {
+                id: 7,
+                uci: "g1f3".to_owned(),
+                fen: fen.to_owned(),
+            },
+        ];
+        for event in events {
+            let line = serde_json::to_string(&event.to_json()).unwrap();
+            let value: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(Event::from_json(&value), Some(event));
+        }
+        let mut future = Event::Connection {
+            status: "connected".to_owned(),
+        }
+        .to_json();
+        if let Value::Object(object) = &mut future {
+            object.insert(
+                "version".to_owned(),
+                Value::Number((SCHEMA_VERSION + 1).into()),
+            );
+        }
+        assert_eq!(Event::from_json(&future), None);
+
+        assert_eq!(
+            parse_answer(&answer(3, "veto")),
+            Some((3, Verdict::Veto("hangs the queen".to_owned())))
+        );
+        assert_eq!(parse_answer(&answer(4, "pass")), Some((4, Verdict::Pass)));
+        assert_eq!(parse_answer(&answer(4, "maybe")), None);
+        assert_eq!(parse_answer(r#"{"id":4,"verdict":"pass"}"#), None);
+    }
+
+    #[test]
+    fn an_echoing_filter_is_told_everything_and_consulted() {
+        let (seen, events) = mpsc::channel();
+        let hook = stub(Duration::from_secs(5), move |event| {
+            let answers = match &event {
+                Event::Consult { id, uci, .. } => vec![answer(
+                    *id,
+                    match uci.as_str() {
+                        "f2f3" => "veto",
+                        _ => "pass",
+                    },
+                )],
+                _ => Vec::new(),
+            };
+            seen.send(event.name()).unwrap();
+            Some(answers)
+        });
+        hook.send(&Event::Connection {
+            status: "connected".to_owned(),
+        });
+        assert_eq!(hook.consult("e2e4", "fen"), Verdict::Pass);
+        assert_eq!(
+            hook.consult("f2f3", "fen"),
+            Verdict::Veto("hangs the queen".to_owned())
+        );
+        assert_eq!(
+            events.iter().take(3).collect::<Vec<_>>(),
+            ["connection", "consult", "consult"]
+        );
+        assert_eq!(hook.take_failure(), None);
+    }
+
+    #[test]
+    fn a_stalled_filter_is_passed_once_it_times_out() {
+        let (release, late) = mpsc::channel::<()>();
+        let mut release = Some(release);
+        let hook = stub(Duration::from_millis(100), move |event| match event {
+            // Never answers the first move in time, and answers it while the second waits
+            Event::Consult { id: 0, .. } => {
+                drop(release.take());
+                Some(Vec::new())
+            }
+            Event::Consult { id, .. } => Some(vec![answer(0, "veto"), answer(id, "veto")]),
+            _ => Some(Vec::new()),
+        });
+        let started = Instant::now();
+        assert_eq!(hook.consult("e2e4", "fen"), Verdict::Pass);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        let _ = late.recv();
+        // The late answer to the first move isn't taken for the second's
+        assert_eq!(
+            hook.consult("d2d4", "fen"),
+            Verdict::Veto("hangs the queen".to_owned())
+        );
+        assert!(!hook.is_off());
+    }
+
+    #[test]
+    fn a_crashing_or_garbled_hook_is_turned_off() {
+        let hook = stub(Duration::from_secs(5), |_| None);
+        hook.send(&Event::Connection {
+            status: "connected".to_owned(),
+        });
+        assert!(off_soon(&hook).is_some());
+        // Told once, then quietly off
+        assert_eq!(hook.take_failure(), None);
+        assert_eq!(hook.consult("e2e4", "fen"), Verdict::Pass);
+        hook.send(&Event::Connection {
+            status: "lost".to_owned(),
+        });
+
+        let garbled = stub(Duration::from_secs(5), |_| {
+            Some(vec!["not json".to_owned()])
+        });
+        assert_eq!(garbled.consult("e2e4", "fen"), Verdict::Pass);
+        let failure = off_soon(&garbled).unwrap();
+        assert!(failure.contains("not json"), "{}", failure);
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+
+/// The longest a handle may be, the same as a profile's name
+pub const MAX_HANDLE_LENGTH: usize = 24;
+const ADJECTIVES: [&str; 32] = [
+    "Analytic", "Bold", "Brisk", "Calm", "Clever", "Crafty", "Daring", "Eager", "Fearless",
+    "Gentle", "Golden", "Hasty", "Humble", "Jolly", "Keen", "Lively", "Lucky", "Mellow", "Nimble",
+    "Patient", "Plucky", "Quiet", "Rapid", "Reckless", "Sly", "Steady", "Sturdy", "Swift", "Tidy",
+    "Wily", "Witty", "Zesty",
+];
+const NOUNS: [&str; 32] = [
+    "Battery",
+    "Bishop",
+    "Blitz",
+    "Breakthrough",
+    "Castle",
+    "Checkmate",
+    "Citadel",
+    "Dragon",
+    "Endgame",
+    "Fianchetto",
+    "Fork",
+    "Gambit",
+    "Knight",
+    "King",
+    "Najdorf",
+    "Opening",
+    "Outpost",
+    "Pawn",
+    "Pin",
+    "Promotion",
+    "Queen",
+    "Rook",
+    "Sacrifice",
+    "Sicilian",
+    "Skewer",
+    "Squire",
+    "Stalemate",
+    "Tempo",
+    "Tactic",
+    "Zugzwang",
+    "Zwischenzug",
+    "Sentinel",
+];
+// Handles containing any of these are rolled again. Checked on the whole handle, since two
+// harmless words can make one where they meet.
+const BLOCKED: [&str; 8] = ["anal", "nazi", "rape", "slut", "fag", "cum", "porn", "tit"];
+// Tries before giving up on a roll, only a blocklist covering nearly every handle gets there
+const MAX_ROLLS: usize = 64;
+const FALLBACK: &str = "SturdyFianchetto";
+
+/// A name to play strangers under instead of the profile's, with a color to show it in. Set
+/// for a run, the profile's own name is left as it is.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Identity {
+    pub handle: String,
+    // From the handle, the same handle always gets the same color
+    pub color: [f32; 3],
+}
+
+impl Identity {
+    pub fn new(handle: String) -> Self {
+        let color = avatar_color(&handle);
+        Self { handle, color }
+    }
+
+    /// A new handle from the system's random numbers
+    #[inline]
+    pub fn random() -> Self {
+        Self::roll(&mut os_random)
+    }
+
+    /// A handle made of an adjective and a chess word picked by `next`, rolled again while it
+    /// is blocked or too long
+    pub fn roll(next: &mut impl FnMut() -> u64) -> Self {
+        (0..MAX_ROLLS)
+            .map(|_| {
+                let adjective = ADJECTIVES[(next() % ADJECTIVES.len() as u64) as usize];
+                let noun = NOUNS[(next() % NOUNS.len() as u64) as usize];
+                compose(adjective, noun)
+            })
+            .find(|handle| handle.len() <= MAX_HANDLE_LENGTH && !is_blocked(handle))
+            .map_or_else(|| Self::new(FALLBACK.to_owned()), Self::new)
+    }
+}
+
+#[inline]
+pub fn compose(adjective: &str, noun: &str) -> String {
+    format!("{}{}", adjective, noun)
+}
+
+/// Whether a handle reads as something nobody should be called
+pub fn is_blocked(handle: &str) -> bool {
+    let handle = handle.to_lowercase();
+    BLOCKED.iter().any(|word| handle.contains(word))
+}
+
+/// The color of a handle, picked by its hash so it is the same on every run. Dark enough that
+/// the name reads on the white of the window.
+pub fn avatar_color(handle: &str) -> [f32; 3] {
+    // FNV-1a, std's own hasher is seeded differently on every run
+    let hash = handle.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    let hue = (hash % 360) as f32 / 60.0;
+    let (saturation, value) = (0.7, 0.65);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let low = value - chroma;
+    [r + low, g + low, b + low]
+}
+
+/// A number from the system's cryptographic generator, never from the game's seed
+pub fn os_random() -> u64 {
+    let mut bytes = [0; 8];
+    if File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .is_ok()
+    {
+        return u64::from_le_bytes(bytes);
+    }
+    // Without it, std's hasher keys come from the system's generator as well
+    RandomState::new().build_hasher().finish()
+}
+
+/// The name the local player goes by wherever the other side or the saved games see it: the
+/// handle while anonymous, `named` otherwise
+#[inline]
+pub fn shown_name<'a>(identity: Option<&'a Identity>, named: &'a str) -> &'a str {
+    identity.map_or(named, |identity| &identity.handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles;
+
+    /// Hands out `numbers` in order, as the picks of a roll
+    fn script(numbers: &[u64]) -> impl FnMut() -> u64 + '_ {
+        let mut numbers = numbers.iter();
+        move || *numbers.next().unwrap()
+    }
+
+    #[test]
+    fn every_handle_fits_and_no_word_is_blocked_alone() {
+        for adjective in ADJECTIVES {
+            for noun in NOUNS {
+                let handle = compose(adjective, noun);
+                assert!(handle.len() <= MAX_HANDLE_LENGTH, "{}", handle);
+                // Whatever the blocklist lets through has to be a profile name as well
+                if !is_blocked(&handle) {
+                    assert_eq!(profiles::check_name(&handle), Ok(handle.as_str()));
+                }
+            }
+        }
+        assert!(NOUNS.iter().all(|noun| !is_blocked(noun)));
+        assert!(!is_blocked(FALLBACK));
+    }
+
+    #[test]
+    fn a_blocked_handle_is_rolled_again() {
+        let analytic = ADJECTIVES
+            .iter()
+            .position(|word| *word == "Analytic")
+            .unwrap() as u64;
+        let rook = NOUNS.iter().position(|word| *word == "Rook").unwrap() as u64;
+        let sturdy = ADJECTIVES
+            .iter()
+            .position(|word| *word == "Sturdy")
+            .unwrap() as u64;
+        let fianchetto = NOUNS.iter().position(|word| *word == "Fianchetto").unwrap() as u64;
+        assert!(is_blocked("AnalyticRook"));
+
+        // Past the end of the lists the picks wrap around
+        let numbers = [analytic, rook + NOUNS.len() as u64, sturdy, fianchetto];
+        let identity = Identity::roll(&mut script(&numbers));
+        assert_eq!(identity.handle, "SturdyFianchetto");
+        assert_eq!(identity.color, avatar_color("SturdyFianchetto"));
+
+        // Every roll blocked and it settles for the fallback
+        let mut analytic_only = || analytic;
+        assert_eq!(Identity::roll(&mut analytic_only).handle, FALLBACK);
+    }
+
+    #[test]
+    fn the_color_only_depends_on_the_handle() {
+        let color = avatar_color("SwiftGambit");
+        assert_eq!(avatar_color("SwiftGambit"), color);
+        assert_ne!(avatar_color("SwiftGambits"), color);
+        for handle in ["SwiftGambit", "CalmRook", "", "ZestyZwischenzug"] {
+            let color = avatar_color(handle);
+            assert!(
+                color.iter().all(|part| (0.0..=1.0).contains(part)),
+                "{:?}",
+                color
+            );
+            // Never so light it doesn't read on white
+            let brightest = color.iter().cloned().fold(0.0, f32::max);
+            assert!((brightest - 0.65).abs() < 1e-5, "{:?}", color);
+        }
+    }
+}
@@ -0,0 +1,778 @@
+use crate::network::{network_piece_letter, supported_features};
+use crate::pgn;
+use chess_network_protocol::{Features, ServerToClientHandshake};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// Kept in the profile directory
+pub const FILE_NAME: &str = "interop.json";
+/// The sessions kept, the oldest go first
+pub const CAPACITY: usize = 500;
+/// What an implementation advertising `Other("name=...")` is known by
+const NAME_PREFIX: &str = "name=";
+
+/// Something noticed about the peer in a networked session. Only ever recorded, nothing the
+/// game does depends on it.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Incident {
+    // The server's board differed from the one replayed here
+    Desync,
+    // The server listed moves that aren't legal here, or left out legal ones
+    MovesMismatch,
+    // The server listed no moves at all
+    NoMovesListed,
+    // A handshake again mid-game that agreed with the game
+    DuplicateHandshake,
+    // A handshake again mid-game that didn't
+    DisagreeingHandshake,
+    // A handshake again where none is taken, or with --strict-handshakes
+    MidGameHandshake,
+    // A move that isn't legal in the position
+    IllegalMove,
+    // A move out of turn or after the game
+    OutOfTurn,
+}
+
+const INCIDENTS: [Incident; 8] = [
+    Incident::Desync,
+    Incident::MovesMismatch,
+    Incident::NoMovesListed,
+    Incident::DuplicateHandshake,
+    Incident::DisagreeingHandshake,
+    Incident::MidGameHandshake,
+    Incident::IllegalMove,
+    Incident::OutOfTurn,
+];
+
+/// How bad an incident is for playing the implementation
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
+pub enum Grade {
+    // Nothing out of the ordinary
+    Compatible,
+    // Things done differently, but the same way every time and the game copes
+    Quirky,
+    // The games went out of sync, the server's side won
+    Unreliable,
+    // It broke the protocol
+    Violating,
+}
+
+impl Grade {
+    #[inline]
+    pub fn letter(self) -> char {
+        match self {
+            Grade::Compatible => 'A',
+            Grade::Quirky => 'B',
+            Grade::Unreliable => 'C',
+            Grade::Violating => 'F',
+        }
+    }
+
+    #[inline]
+    pub fn describe(self) -> &'static str {
+        match self {
+            Grade::Compatible => "compatible",
+            Grade::Quirky => "works with quirks",
+            Grade::Unreliable => "goes out of sync",
+            Grade::Violating => "violates the protocol",
+        }
+    }
+}
+
+impl Incident {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Incident::Desync => "desync",
+            Incident::MovesMismatch => "moves mismatch",
+            Incident::NoMovesListed => "no moves listed",
+            Incident::DuplicateHandshake => "duplicate handshake",
+            Incident::DisagreeingHandshake => "disagreeing handshake",
+            Incident::MidGameHandshake => "mid-game handshake",
+            Incident::IllegalMove => "illegal move",
+            Incident::OutOfTurn => "out of turn",
+        }
+    }
+
+    #[inline]
+    pub fn parse(name: &str) -> Option<Self> {
+        INCIDENTS
+            .into_iter()
+            .find(|incident| incident.name() == name)
+    }
+
+    #[inline]
+    pub fn grade(self) -> Grade {
+        match self {
+            Incident::MovesMismatch | Incident::NoMovesListed | Incident::DuplicateHandshake => {
+                Grade::Quirky
+            }
+            Incident::Desync | Incident::DisagreeingHandshake => Grade::Unreliable,
+            Incident::MidGameHandshake | Incident::IllegalMove | Incident::OutOfTurn => {
+                Grade::Violating
+            }
+        }
+    }
+
+    /// The quirk as the report lists it
+    fn quirk(self) -> &'static str {
+        match self {
+            Incident::Desync => "Its board goes its own way",
+            Incident::MovesMismatch => "Lists moves that disagree with the rules",
+            Incident::NoMovesListed => "Lists no moves",
+            Incident::DuplicateHandshake => "Sends its handshake again mid-game",
+            Incident::DisagreeingHandshake => "Sends a handshake mid-game that changes the game",
+            Incident::MidGameHandshake => "Sends handshakes after the game started",
+            Incident::IllegalMove => "Plays illegal moves",
+            Incident::OutOfTurn => "Moves out of turn",
+        }
+    }
+
+    /// What to play it with, by the options this side has
+    fn recommendation(self) -> &'static str {
+        match self {
+            Incident::DuplicateHandshake | Incident::MidGameHandshake => {
+                "Leave --strict-handshakes off, the repeated handshakes are checked against the game"
+            }
+            Incident::Desync | Incident::DisagreeingHandshake | Incident::MovesMismatch => {
+                "Play with --transcript and send them the messages where the boards part"
+            }
+            Incident::NoMovesListed => "Nothing to change, the legal moves are worked out here",
+            Incident::IllegalMove | Incident::OutOfTurn => {
+                "Host with --max-illegal-moves and --max-protocol-errors above 0 so it can't stall the game"
+            }
+        }
+    }
+}
+
+/// One networked session, as recorded when it ended
+#[derive(Clone, PartialEq, Debug)]
+pub struct SessionRecord {
+    // Which implementation it was, see `fingerprint`
+    pub key: String,
+    pub label: String,
+    // Seconds since 1970
+    pub started: u64,
+    pub incidents: Vec<Incident>,
+    // What the peer advertised, by name, empty for a client
+    pub advertised: Vec<String>,
+    // The messages received and the bytes of JSON in them
+    pub messages: usize,
+    pub payload: usize,
+    pub largest_payload: usize,
+}
+
+/// A feature as the fingerprint and report write it
+fn advertised_name(feature: &Features) -> String {
+    match feature {
+        Features::Castling => "castling".to_owned(),
+        Features::EnPassant => "en passant".to_owned(),
+        Features::Promotion => "promotion".to_owned(),
+        Features::Stalemate => "stalemate".to_owned(),
+        Features::PossibleMoveGeneration => "move generation".to_owned(),
+        Features::Other(other) => format!("other {}", other),
+    }
+}
+
+/// The key and label of the implementation behind a session. One that names itself with an
+/// `Other("name=...")` feature is known by the name. A server is otherwise known by its
+/// handshake: the features in the order advertised, the starting board and whether it lists
+/// moves. A client's handshake has nothing to tell it by, so every unnamed client session
+/// stands alone until it is merged by hand.
+pub fn fingerprint(handshake: Option<&ServerToClientHandshake>, started: u64) -> (String, String) {
+    let named = handshake.and_then(|handshake| {
+        handshake.features.iter().find_map(|feature| match feature {
+            Features::Other(other) => other.strip_prefix(NAME_PREFIX).map(str::trim),
+            _ => None,
+        })
+    });
+    if let Some(name) = named.filter(|name| !name.is_empty()) {
+        return (format!("name {}", name), name.to_owned());
+    }
+    let Some(handshake) = handshake else {
+        let (year, month, day) = pgn::civil_date((started / 86_400) as i64);
+        return (
+            format!("client {}", started),
+            format!("Unnamed client {:04}-{:02}-{:02}", year, month, day),
+        );
+    };
+    let features: Vec<String> = handshake.features.iter().map(advertised_name).collect();
+    let board: String = handshake
+        .board
+        .iter()
+        .flatten()
+        .map(|piece| network_piece_letter(*piece).unwrap_or('.'))
+        .collect();
+    let key = format!(
+        "server [{}] {} {}",
+        features.join(","),
+        board,
+        match handshake.moves.is_empty() {
+            true => "unlisted",
+            false => "listed",
+        }
+    );
+    let label = match features.is_empty() {
+        true => "Server advertising nothing".to_owned(),
+        false => format!("Server advertising {}", features.join(", ")),
+    };
+    (key, label)
+}
+
+/// The incidents of a session, the size of what it received and who the peer was, put
+/// together as it goes and recorded once it ends
+#[derive(Default)]
+pub struct Recorder {
+    incidents: Vec<Incident>,
+}
+
+impl Recorder {
+    /// Keep that `incident` happened, a session records each kind once however often it did
+    #[inline]
+    pub fn note(&mut self, incident: Incident) {
+        if !self.incidents.contains(&incident) {
+            self.incidents.push(incident);
+        }
+    }
+
+    /// The session, from the transcript lines "<milliseconds> <direction> <message>" it
+    /// received. The client reads the server's handshake off its first message.
+    pub fn finish(self, entries: &[String], is_server: bool, started: u64) -> SessionRecord {
+        let received: Vec<&str> = entries
+            .iter()
+            .filter_map(|entry| {
+                let (_, rest) = entry.split_once(' ')?;
+                rest.strip_prefix("received ")
+            })
+            .collect();
+        let handshake = match is_server {
+            true => None,
+            false => received
+                .first()
+                .and_then(|message| serde_json::from_str::<ServerToClientHandshake>(message).ok()),
+        };
+        let (key, label) = fingerprint(handshake.as_ref(), started);
+        SessionRecord {
+            key,
+            label,
+            started,
+            incidents: self.incidents,
+            advertised: handshake.map_or_else(Vec::new, |handshake| {
+                handshake.features.iter().map(advertised_name).collect()
+            }),
+            messages: received.len(),
+            payload: received.iter().map(|message| message.len()).sum(),
+            largest_payload: received
+                .iter()
+                .map(|message| message.len())
+                .max()
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// What is known of one implementation over all its sessions
+#[derive(Clone, PartialEq, Debug)]
+pub struct Summary {
+    pub key: String,
+    pub label: String,
+    pub sessions: usize,
+    // Every kind of incident seen with the sessions it was seen in, in the order of `INCIDENTS`
+    pub incidents: Vec<(Incident, usize)>,
+    pub grade: Grade,
+    pub quirks: Vec<String>,
+    pub recommendations: Vec<String>,
+    pub messages: usize,
+    pub largest_payload: usize,
+    pub mean_payload: usize,
+}
+
+/// The sessions kept and which implementations were merged by hand
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Ledger {
+    sessions: Vec<SessionRecord>,
+    // A key and the key it was merged into
+    merges: Vec<(String, String)>,
+}
+
+impl Ledger {
+    /// The key the sessions of `key` are counted under
+    pub fn resolve<'a>(&'a self, key: &'a str) -> &'a str {
+        let mut key = key;
+        // Merges can chain, but a cycle a damaged file has stops after every merge was taken
+        for _ in 0..=self.merges.len() {
+            match self.merges.iter().find(|(from, _)| from == key) {
+                Some((_, into)) => key = into,
+                None => break,
+            }
+        }
+        key
+    }
+
+    pub fn record(&mut self, session: SessionRecord) {
+        self.sessions.push(session);
+        if self.sessions.len() > CAPACITY {
+            self.sessions.remove(0);
+        }
+    }
+
+    /// Count `from` as `into` from now on, both were the same implementation
+    pub fn merge(&mut self, from: &str, into: &str) {
+        let into = self.resolve(into).to_owned();
+        if self.resolve(from) != into && from != into {
+            self.split(from);
+            self.merges.push((from.to_owned(), into));
+        }
+    }
+
+    /// Count everything merged into `key` apart again, and `key` itself if it was merged
+    pub fn split(&mut self, key: &str) {
+        self.merges
+            .retain(|(from, into)| from != key && into != key);
+    }
+
+    /// The implementations with everything known of them, the worst first
+    pub fn summaries(&self) -> Vec<Summary> {
+        summarize(&self.sessions, |key| self.resolve(key).to_owned())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let sessions = self
+            .sessions
+            .iter()
+            .map(|session| {
+                let mut object = Map::new();
+                object.insert("key".to_owned(), Value::String(session.key.clone()));
+                object.insert("label".to_owned(), Value::String(session.label.clone()));
+                object.insert("started".to_owned(), Value::Number(session.started.into()));
+                object.insert(
+                    "incidents".to_owned(),
+                    Value::Array(
+                        session
+                            .incidents
+                            .iter()
+                            .map(|incident| Value::String(incident.name().to_owned()))
+                            .collect(),
+                    ),
+                );
+                object.insert(
+                    "advertised".to_owned(),
+                    Value::Array(
+                        session
+                            .advertised
+                            .iter()
+                            .map(|feature| Value::String(feature.clone()))
+                            .collect(),
+                    ),
+                );
+                for (key, count) in [
+                    ("messages", session.messages),
+                    ("payload", session.payload),
+                    ("largest_payload", session.largest_payload),
+                ] {
+                    object.insert(key.to_owned(), Value::Number((count as u64).into()));
+                }
+                Value::Object(object)
+            })
+            .collect();
+        let merges = self
+            .merges
+            .iter()
+            .map(|(from, into)| {
+                Value::Array(vec![
+                    Value::String(from.clone()),
+                    Value::String(into.clone()),
+                ])
+            })
+            .collect();
+        let mut object = Map::new();
+        object.insert("sessions".to_owned(), Value::Array(sessions));
+        object.insert("merges".to_owned(), Value::Array(merges));
+        Value::Object(object)
+    }
+
+    /// The ledger in `value`, damaged sessions are left out
+    pub fn from_json(value: &Value) -> Self {
+        let strings = |value: Option<&Value>| -> Vec<String> {
+            value
+                .and_then(Value::as_array)
+                .map(|items| {
+                    items
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let sessions = value
+            .get("sessions")
+            .and_then(Value::as_array)
+            .map(|sessions| {
+                sessions
+                    .iter()
+                    .filter_map(|session| {
+                        let count = |key: &str| {
+                            session.get(key).and_then(Value::as_u64).unwrap_or(0) as usize
+                        };
+                        Some(SessionRecord {
+                            key: session.get("key")?.as_str()?.to_owned(),
+                            label: session.get("label")?.as_str()?.to_owned(),
+                            started: session.get("started")?.as_u64()?,
+                            incidents: strings(session.get("incidents"))
+                                .iter()
+                                .filter_map(|name| Incident::parse(name))
+                                .collect(),
+                            advertised: strings(session.get("advertised")),
+                            messages: count("messages"),
+                            payload: count("payload"),
+                            largest_payload: count("largest_payload"),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let merges = value
+            .get("merges")
+            .and_then(Value::as_array)
+            .map(|merges| {
+                merges
+                    .iter()
+                    .filter_map(|merge| match strings(Some(merge)).as_slice() {
+                        [from, into] => Some((from.clone(), into.clone())),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { sessions, merges }
+    }
+
+    pub fn read(path: &Path) -> Self {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => Self::from_json(&value),
+            Err(err) => {
+                println!(
+                    "Ignoring the damaged interop ledger {}: {}",
+                    path.display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Can't save the interop ledger: {}", err))?;
+        }
+        serde_json::to_string(&self.to_json())
+            .map_err(std::io::Error::from)
+            .and_then(|text| fs::write(path, text))
+            .map_err(|err| format!("Can't save the interop ledger: {}", err))
+    }
+}
+
+/// What the sessions say about each implementation, by the key `resolve` counts them under.
+/// The worst implementations come first, then the ones played most.
+pub fn summarize(sessions: &[SessionRecord], resolve: impl Fn(&str) -> String) -> Vec<Summary> {
+    let mut groups: Vec<(String, Vec<&SessionRecord>)> = Vec::new();
+    for session in sessions {
+        let key = resolve(&session.key);
+        match groups.iter_mut().find(|(group, _)| *group == key) {
+            Some((_, members)) => members.push(session),
+            None => groups.push((key, vec![session])),
+        }
+    }
+
+    let ours: Vec<String> = supported_features().iter().map(advertised_name).collect();
+    let mut summaries: Vec<Summary> = groups
+        .into_iter()
+        .map(|(key, members)| {
+            let incidents: Vec<(Incident, usize)> = INCIDENTS
+                .into_iter()
+                .map(|incident| {
+                    let count = members
+                        .iter()
+                        .filter(|session| session.incidents.contains(&incident))
+                        .count();
+                    (incident, count)
+                })
+                .filter(|(_, count)| *count > 0)
+                .collect();
+            let mut quirks: Vec<String> = incidents
+                .iter()
+                .map(|(incident, count)| {
+                    format!(
+                        "{} (in {} of {} sessions)",
+                        incident.quirk(),
+                        count,
+                        members.len()
+                    )
+                })
+                .collect();
+            // What it never advertised is left out of the games, which is no fault of theirs
+            let advertised: Vec<&String> = members
+                .iter()
+                .flat_map(|session| &session.advertised)
+                .collect();
+            let missing: Vec<&str> = ours
+                .iter()
+                .filter(|feature| !advertised.contains(feature))
+                .map(String::as_str)
+                .collect();
+            if !advertised.is_empty() && !missing.is_empty() {
+                quirks.push(format!("Doesn't advertise {}", missing.join(", ")));
+            }
+            let mut recommendations: Vec<String> = Vec::new();
+            for (incident, _) in &incidents {
+                let recommendation = incident.recommendation().to_owned();
+                if !recommendations.contains(&recommendation) {
+                    recommendations.push(recommendation);
+                }
+            }
+            let messages = members.iter().map(|session| session.messages).sum();
+            let payload: usize = members.iter().map(|session| session.payload).sum();
+            // The label of the session merged into comes first, it is what the others joined
+            let label = members
+                .iter()
+                .find(|session| session.key == key)
+                .unwrap_or(&members[0])
+                .label
+                .clone();
+            Summary {
+                label,
+                sessions: members.len(),
+                grade: incidents
+                    .iter()
+                    .map(|(incident, _)| incident.grade())
+                    .max()
+                    .unwrap_or(Grade::Compatible),
+                incidents,
+                quirks,
+                recommendations,
+                messages,
+                largest_payload: members
+                    .iter()
+                    .map(|session| session.largest_payload)
+                    .max()
+                    .unwrap_or(0),
+                mean_payload: payload.checked_div(messages).unwrap_or(0),
+                key,
+            }
+        })
+        .collect();
+    summaries.sort_by(|a, b| b.grade.cmp(&a.grade).then(b.sessions.cmp(&a.sessions)));
+    summaries
+}
+
+/// The report as Markdown, to share with whoever plays the same implementations
+pub fn markdown(summaries: &[Summary]) -> String {
+    let mut text = String::from("# Interop report\n\n");
+    if summaries.is_empty() {
+        text.push_str("No networked sessions recorded yet.\n");
+        return text;
+    }
+    text.push_str(
+        "| Implementation | Grade | Sessions | Messages | Mean bytes | Largest bytes |\n",
+    );
+    text.push_str("|---|---|---|---|---|---|\n");
+    for summary in summaries {
+        text.push_str(&format!(
+            "| {} | {} ({}) | {} | {} | {} | {} |\n",
+            summary.label.replace('|', "\\|"),
+            summary.grade.letter(),
+            summary.grade.describe(),
+            summary.sessions,
+            summary.messages,
+            summary.mean_payload,
+            summary.largest_payload
+        ));
+    }
+    for summary in summaries {
+        text.push_str(&format!("\n## {}\n\n", summary.label));
+        text.push_str(&format!(
+            "Grade {}, {}.\n",
+            summary.grade.letter(),
+            summary.grade.describe()
+        ));
+        if !summary.quirks.is_empty() {
+            text.push_str("\nQuirks:\n\n");
+            for quirk in &summary.quirks {
+                text.push_str(&format!("- {}\n", quirk));
+            }
+        }
+        if !summary.recommendations.is_empty() {
+            text.push_str("\nRecommended:\n\n");
+            for recommendation in &summary.recommendations {
+                text.push_str(&format!("- {}\n", recommendation));
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_network_protocol::Piece;
+
+    fn session(key: &str, started: u64, incidents: &[Incident]) -> SessionRecord {
+        SessionRecord {
+            key: key.to_owned(),
+            label: format!("Implementation {}", key),
+            started,
+            incidents: incidents.to_vec(),
+            advertised: supported_features().iter().map(advertised_name).collect(),
+            messages: 10,
+            payload: 1000,
+            largest_payload: 300,
+        }
+    }
+
+    // One implementation that does everything right, one that repeats its handshake and lists
+    // no moves every game, and one that plays illegal moves
+    fn semester() -> Vec<SessionRecord> {
+        use Incident::*;
+        vec![
+            session("clean", 1, &[]),
+            session("quirky", 2, &[DuplicateHandshake, NoMovesListed]),
+            session("clean", 3, &[]),
+            session("broken", 4, &[IllegalMove, Desync]),
+            session("quirky", 5, &[NoMovesListed]),
+            session("clean", 6, &[]),
+        ]
+    }
+
+    #[test]
+    fn implementations_are_graded_by_their_worst() {
+        let summaries = summarize(&semester(), str::to_owned);
+        let keys: Vec<&str> = summaries
+            .iter()
+            .map(|summary| summary.key.as_str())
+            .collect();
+        assert_eq!(keys, ["broken", "quirky", "clean"]);
+
+        let [broken, quirky, clean] = summaries.as_slice() else {
+            unreachable!()
+        };
+        assert_eq!(broken.grade, Grade::Violating);
+        assert_eq!(
+            broken.incidents,
+            [(Incident::Desync, 1), (Incident::IllegalMove, 1)]
+        );
+        assert_eq!(quirky.grade, Grade::Quirky);
+        assert_eq!(quirky.sessions, 2);
+        assert_eq!(
+            quirky.quirks,
+            [
+                "Lists no moves (in 2 of 2 sessions)",
+                "Sends its handshake again mid-game (in 1 of 2 sessions)"
+            ]
+        );
+        assert!(quirky
+            .recommendations
+            .iter()
+            .any(|recommendation| recommendation.contains("--strict-handshakes")));
+        assert_eq!(clean.grade, Grade::Compatible);
+        assert_eq!(clean.sessions, 3);
+        assert!(clean.quirks.is_empty() && clean.recommendations.is_empty());
+        assert_eq!(clean.mean_payload, 100);
+        assert_eq!(clean.largest_payload, 300);
+
+        let report = markdown(&summaries);
+        assert!(report.contains("| Implementation clean | A (compatible) | 3 |"));
+        assert!(report.contains("\n- Plays illegal moves (in 1 of 1 sessions)\n"));
+    }
+
+    #[test]
+    fn only_merges_by_hand_join_implementations() {
+        let mut ledger = Ledger::default();
+        for session in semester() {
+            ledger.record(session);
+        }
+        assert_eq!(ledger.summaries().len(), 3);
+        ledger.merge("quirky", "clean");
+        let merged = ledger.summaries();
+        assert_eq!(merged.len(), 2);
+        let joined = merged
+            .iter()
+            .find(|summary| summary.key == "clean")
+            .unwrap();
+        assert_eq!((joined.sessions, joined.grade), (5, Grade::Quirky));
+        assert_eq!(joined.label, "Implementation clean");
+
+        // Merging into a merged one counts under where that went, a cycle can't be made
+        ledger.merge("broken", "quirky");
+        assert_eq!(ledger.resolve("broken"), "clean");
+        ledger.merge("clean", "broken");
+        assert_eq!(ledger.summaries().len(), 1);
+        ledger.split("clean");
+        assert_eq!(ledger.summaries().len(), 3);
+        assert_eq!(Ledger::from_json(&ledger.to_json()), ledger);
+    }
+
+    #[test]
+    fn fingerprints_never_guess() {
+        let handshake = |features: Vec<Features>, moves| ServerToClientHandshake {
+            features,
+            board: [[Piece::None; 8]; 8],
+            moves,
+            joever: chess_network_protocol::Joever::Ongoing,
+        };
+        let named = handshake(
+            vec![
+                Features::Castling,
+                Features::Other("name=Knightmare 2".to_owned()),
+            ],
+            vec![],
+        );
+        assert_eq!(
+            fingerprint(Some(&named), 7),
+            ("name Knightmare 2".to_owned(), "Knightmare 2".to_owned())
+        );
+        // The order features are advertised in tells implementations apart too
+        let (forwards, _) = fingerprint(
+            Some(&handshake(
+                vec![Features::Castling, Features::Promotion],
+                vec![],
+            )),
+            7,
+        );
+        let (backwards, _) = fingerprint(
+            Some(&handshake(
+                vec![Features::Promotion, Features::Castling],
+                vec![],
+            )),
+            7,
+        );
+        assert_ne!(forwards, backwards);
+        // Clients only ever match themselves
+        assert_ne!(fingerprint(None, 7).0, fingerprint(None, 8).0);
+    }
+
+    #[test]
+    fn sessions_are_read_off_what_was_received() {
+        let mut recorder = Recorder::default();
+        recorder.note(Incident::OutOfTurn);
+        recorder.note(Incident::OutOfTurn);
+        let entries = [
+            "0 sent {\"server_color\":\"Black\"}".to_owned(),
+            "5 received {\"Move\":1}".to_owned(),
+            "9 note Rejected".to_owned(),
+            "12 received \"Resign\"".to_owned(),
+        ];
+        let session = recorder.finish(&entries, true, 42);
+        assert_eq!(session.incidents, [Incident::OutOfTurn]);
+        assert_eq!(
+            (session.messages, session.payload, session.largest_payload),
+            (2, 18, 10)
+        );
+        assert_eq!(session.key, "client 42");
+    }
+}
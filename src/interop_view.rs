@@ -0,0 +1,372 @@
+use crate::menu::grown;
+use crate::{unit_mesh, Graphics, Restore};
+use chess_gui::game_list::{max_scroll, row_at, scroll_to, visible_rows};
+use chess_gui::interop::{self, Grade, Ledger, Summary};
+use ggez::graphics::{self, Canvas, Mesh, Rect, Text};
+use ggez::input::keyboard::KeyCode;
+use ggez::Context;
+use mint::{Point2, Vector2};
+use std::path::PathBuf;
+
+const BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const CHOSEN_BUTTON_COLOR: graphics::Color = graphics::Color::new(0.0, 0.5, 0.0, 0.75);
+const ROW_COLOR: graphics::Color = graphics::Color::new(0.95, 0.95, 0.95, 1.0);
+const SELECTED_ROW_COLOR: graphics::Color = graphics::Color::new(0.7, 0.85, 0.7, 1.0);
+const TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const ERROR_TEXT_COLOR: graphics::Color = graphics::Color::new(0.7, 0.0, 0.0, 1.0);
+// Part of the window's height each row of the list and the details takes
+const ROW_FRACTION: f32 = 0.04;
+// Written next to the ledger
+const REPORT_FILE: &str = "interop-report.md";
+
+/// The buttons under the list
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Button {
+    Export,
+    Merge,
+    Split,
+    Back,
+}
+
+impl Button {
+    #[inline]
+    fn label(self) -> &'static str {
+        match self {
+            Button::Export => "Export",
+            Button::Merge => "Merge",
+            Button::Split => "Split",
+            Button::Back => "Back",
+        }
+    }
+}
+
+/// Where everything in the interop report is drawn, relative to the window size
+struct ViewLayout {
+    list: Rect,
+    row_height: f32,
+    details: Rect,
+    buttons: [(Button, Rect); 4],
+}
+
+impl ViewLayout {
+    fn new(width: f32, height: f32) -> Self {
+        let rect = |x: f32, y: f32, w: f32, h: f32| {
+            Rect::new(x * width, y * height, w * width, h * height)
+        };
+        Self {
+            list: rect(0.05, 0.1, 0.9, 0.44),
+            row_height: ROW_FRACTION * height,
+            details: rect(0.05, 0.56, 0.9, 0.32),
+            buttons: [
+                (Button::Export, rect(0.05, 0.9, 0.21, 0.07)),
+                (Button::Merge, rect(0.28, 0.9, 0.21, 0.07)),
+                (Button::Split, rect(0.51, 0.9, 0.21, 0.07)),
+                (Button::Back, rect(0.74, 0.9, 0.21, 0.07)),
+            ],
+        }
+    }
+}
+
+/// What the player did in the interop report
+pub(crate) enum InteropAction {
+    Back,
+}
+
+/// How every implementation played over the network got along with this one, from the
+/// profile's interop ledger. Implementations told apart wrongly are merged and split here.
+pub(crate) struct InteropView {
+    ledger: Ledger,
+    file: PathBuf,
+    summaries: Vec<Summary>,
+    selected: Option<usize>,
+    // The key of the implementation being merged, the next row clicked is what it joins
+    merging: Option<String>,
+    // What the last button did, and whether it failed
+    status: Option<(String, bool)>,
+    // How far down the list is scrolled, in pixels
+    scroll: f32,
+    // Unit square tinted and stretched into every row and button
+    rect_mesh: Mesh,
+}
+
+impl Restore for InteropView {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::UnitMesh(&mut self.rect_mesh)
+    }
+}
+
+impl InteropView {
+    /// With the ledger kept at `file`
+    pub(crate) fn new(ctx: &Context, file: PathBuf) -> Self {
+        let ledger = Ledger::read(&file);
+        Self {
+            summaries: ledger.summaries(),
+            ledger,
+            file,
+            selected: None,
+            merging: None,
+            status: None,
+            scroll: 0.0,
+            rect_mesh: unit_mesh(ctx),
+        }
+    }
+
+    /// Save the ledger after a merge or split and list it again, the chosen one stays chosen
+    fn save(&mut self) {
+        let key = self.selected.map(|row| self.summaries[row].key.clone());
+        self.summaries = self.ledger.summaries();
+        self.selected = key.and_then(|key| {
+            let key = self.ledger.resolve(&key).to_owned();
+            self.summaries.iter().position(|summary| summary.key == key)
+        });
+        if let Err(err) = self.ledger.write(&self.file) {
+            self.status = Some((err, true));
+        }
+    }
+
+    fn select_row(&mut self, row: usize, height: f32) {
+        let layout = ViewLayout::new(1.0, height);
+        if row >= self.summaries.len() {
+            return;
+        }
+        self.selected = Some(row);
+        self.scroll = scroll_to(row, self.scroll, layout.row_height, layout.list.h);
+    }
+
+    fn press(&mut self, button: Button) -> Option<InteropAction> {
+        match button {
+            Button::Export => {
+                let path = self.file.with_file_name(REPORT_FILE);
+                self.status = Some(
+                    match std::fs::write(&path, interop::markdown(&self.summaries)) {
+                        Ok(()) => (format!("Wrote the report to {}", path.display()), false),
+                        Err(err) => (
+                            format!("Can't write the report to {}: {}", path.display(), err),
+                            true,
+                        ),
+                    },
+                );
+            }
+            Button::Merge => {
+                let summary = &self.summaries[self.selected?];
+                self.merging = Some(summary.key.clone());
+                self.status = Some((
+                    format!("Click what {} is the same as", summary.label),
+                    false,
+                ));
+            }
+            Button::Split => {
+                let key = self.summaries[self.selected?].key.clone();
+                self.ledger.split(&key);
+                self.status = Some(("Split apart again".to_owned(), false));
+                self.save();
+            }
+            Button::Back => return Some(InteropAction::Back),
+        }
+        None
+    }
+
+    fn draw_rect(&self, canvas: &mut Canvas, rect: Rect, color: graphics::Color) {
+        canvas.draw(
+            &self.rect_mesh,
+            graphics::DrawParam::default().dest_rect(rect).color(color),
+        );
+    }
+
+    fn draw_label(&self, canvas: &mut Canvas, rect: Rect, label: &str, color: graphics::Color) {
+        let mut text = Text::new(label);
+        text.set_scale(rect.h * 0.6);
+        text.set_bounds(Vector2 {
+            x: rect.w,
+            y: rect.h,
+        });
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: rect.x + rect.h * 0.2,
+                    y: rect.y + rect.h * 0.2,
+                })
+                .color(color),
+        );
+    }
+
+    fn row_label(summary: &Summary) -> String {
+        format!(
+            "{}   {}   {} {}",
+            summary.grade.letter(),
+            summary.label,
+            summary.sessions,
+            match summary.sessions {
+                1 => "session",
+                _ => "sessions",
+            }
+        )
+    }
+
+    /// The lines shown for the chosen implementation
+    fn details(summary: &Summary) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{}, {} messages, {} bytes on average, {} at most",
+            summary.grade.describe(),
+            summary.messages,
+            summary.mean_payload,
+            summary.largest_payload
+        )];
+        if summary.grade == Grade::Compatible {
+            lines.push("Nothing out of the ordinary".to_owned());
+        }
+        lines.extend(summary.quirks.iter().cloned());
+        lines.extend(
+            summary
+                .recommendations
+                .iter()
+                .map(|line| format!("→ {}", line)),
+        );
+        lines
+    }
+
+    pub(crate) fn draw(&self, canvas: &mut Canvas) {
+        let (width, height) = {
+            let cords = canvas.screen_coordinates().unwrap();
+            (cords.w, cords.h)
+        };
+        let layout = ViewLayout::new(width, height);
+
+        let title = Rect::new(layout.list.x, 0.02 * height, layout.list.w, 0.06 * height);
+        match &self.status {
+            Some((message, failed)) => {
+                let color = match failed {
+                    true => ERROR_TEXT_COLOR,
+                    false => TEXT_COLOR,
+                };
+                self.draw_label(canvas, title, message, color);
+            }
+            None => self.draw_label(
+                canvas,
+                title,
+                &format!("{} implementations played", self.summaries.len()),
+                TEXT_COLOR,
+            ),
+        }
+
+        let rows = visible_rows(
+            self.scroll,
+            layout.row_height,
+            layout.list.h,
+            self.summaries.len(),
+        );
+        let _ = canvas.set_scissor_rect(layout.list);
+        for row in rows {
+            let rect = Rect::new(
+                layout.list.x,
+                layout.list.y + row as f32 * layout.row_height - self.scroll,
+                layout.list.w,
+                layout.row_height * 0.9,
+            );
+            let color = match Some(row) == self.selected {
+                true => SELECTED_ROW_COLOR,
+                false => ROW_COLOR,
+            };
+            self.draw_rect(canvas, rect, color);
+            self.draw_label(
+                canvas,
+                rect,
+                &Self::row_label(&self.summaries[row]),
+                TEXT_COLOR,
+            );
+        }
+        canvas.set_default_scissor_rect();
+
+        if let Some(summary) = self.selected.map(|row| &self.summaries[row]) {
+            for (line, text) in Self::details(summary).iter().enumerate() {
+                let rect = Rect::new(
+                    layout.details.x,
+                    layout.details.y + line as f32 * layout.row_height,
+                    layout.details.w,
+                    layout.row_height,
+                );
+                if rect.bottom() <= layout.details.bottom() {
+                    self.draw_label(canvas, rect, text, TEXT_COLOR);
+                }
+            }
+        }
+
+        for (button, rect) in layout.buttons {
+            let color = match button == Button::Merge && self.merging.is_some() {
+                true => CHOSEN_BUTTON_COLOR,
+                false => BUTTON_COLOR,
+            };
+            self.draw_rect(canvas, rect, color);
+            self.draw_label(canvas, rect, button.label(), TEXT_COLOR);
+        }
+    }
+
+    /// Handle a click. The buttons take clicks up to `slop` pixels past their edges, the rows
+    /// are too close together for it.
+    pub(crate) fn click(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        slop: f32,
+    ) -> Option<InteropAction> {
+        let layout = ViewLayout::new(width, height);
+        let point = Point2 { x, y };
+        if let Some((button, _)) = layout
+            .buttons
+            .iter()
+            .find(|(_, rect)| grown(*rect, slop).contains(point))
+        {
+            return self.press(*button);
+        }
+        if layout.list.contains(point) {
+            let row = row_at(
+                y - layout.list.y,
+                self.scroll,
+                layout.row_height,
+                self.summaries.len(),
+            )?;
+            match self.merging.take() {
+                Some(from) => {
+                    let into = &self.summaries[row];
+                    self.ledger.merge(&from, &into.key);
+                    self.status = Some((format!("Merged into {}", into.label), false));
+                    self.selected = Some(row);
+                    self.save();
+                }
+                None => self.select_row(row, height),
+            }
+        }
+        None
+    }
+
+    pub(crate) fn key(&mut self, keycode: KeyCode, height: f32) -> Option<InteropAction> {
+        let row = self.selected;
+        match keycode {
+            // Escape gives up on a merge before it leaves
+            KeyCode::Escape if self.merging.is_some() => {
+                self.merging = None;
+                self.status = None;
+            }
+            KeyCode::Escape => return Some(InteropAction::Back),
+            KeyCode::Up => self.select_row(row.map_or(0, |row| row.saturating_sub(1)), height),
+            KeyCode::Down => self.select_row(row.map_or(0, |row| row + 1), height),
+            KeyCode::E => return self.press(Button::Export),
+            KeyCode::M => return self.press(Button::Merge),
+            KeyCode::S => return self.press(Button::Split),
+            _ => {}
+        }
+        None
+    }
+
+    pub(crate) fn wheel(&mut self, y: f32, height: f32) {
+        let layout = ViewLayout::new(1.0, height);
+        self.scroll = (self.scroll - y * layout.row_height * 3.0).clamp(
+            0.0,
+            max_scroll(layout.row_height, layout.list.h, self.summaries.len()),
+        );
+    }
+}
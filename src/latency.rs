@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+/// The shortest a stale move still slides for, anything shorter snaps
+pub const SHORTEST_SLIDE: Duration = Duration::from_millis(50);
+// Neither end of the curve goes past this, a move that old is news however it is shown
+const MAX_STALENESS: Duration = Duration::from_secs(2);
+// Round trips longer than this are a stall rather than the link, they would skew the average
+const MAX_ROUND_TRIP: Duration = Duration::from_secs(5);
+
+/// How long the opponent's move slides by how stale it is when it arrives. It slides in full
+/// up to `fresh`, shortens evenly to `SHORTEST_SLIDE` towards `stale` and snaps from there, so a
+/// move that already took a while over the network isn't kept from the board for longer still.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct SlideCurve {
+    pub fresh: Duration,
+    pub stale: Duration,
+}
+
+impl Default for SlideCurve {
+    fn default() -> Self {
+        Self {
+            fresh: Duration::from_millis(40),
+            stale: Duration::from_millis(300),
+        }
+    }
+}
+
+impl SlideCurve {
+    /// Both ends in milliseconds like "40,300", the first below the second and neither past two
+    /// seconds
+    pub fn parse(text: &str) -> Option<Self> {
+        let (fresh, stale) = text.split_once(',')?;
+        let millis = |text: &str| text.trim().parse().ok().map(Duration::from_millis);
+        let (fresh, stale) = (millis(fresh)?, millis(stale)?);
+        (fresh < stale && stale <= MAX_STALENESS).then_some(Self { fresh, stale })
+    }
+
+    /// How long a move slides that would take `full` when fresh. Without an estimate of how
+    /// stale it is it slides in full.
+    pub fn slide_time(&self, staleness: Option<Duration>, full: Duration) -> Duration {
+        let Some(staleness) = staleness else {
+            return full;
+        };
+        if staleness <= self.fresh {
+            return full;
+        }
+        if staleness >= self.stale {
+            return Duration::ZERO;
+        }
+        // In nanoseconds so the steps are exact
+        let span = (full - SHORTEST_SLIDE.min(full)).as_nanos();
+        let along = (staleness - self.fresh).as_nanos();
+        full - Duration::from_nanos((span * along / (self.stale - self.fresh).as_nanos()) as u64)
+    }
+}
+
+/// The round trip to the server, timed from the client's moves to the state that confirms
+/// them. The server has no answer of the client's to time, so it never has an estimate.
+#[derive(Default)]
+pub struct RoundTrip {
+    // When the move waiting to be confirmed was sent
+    sent: Option<Duration>,
+    // Averaged over the moves, the later ones weighing more
+    smoothed: Option<Duration>,
+}
+
+impl RoundTrip {
+    #[inline]
+    pub fn sent(&mut self, now: Duration) {
+        self.sent = Some(now);
+    }
+
+    /// The server confirmed the move sent last, nothing is timed without one
+    pub fn answered(&mut self, now: Duration) {
+        let Some(sent) = self.sent.take() else {
+            return;
+        };
+        let sample = now.saturating_sub(sent).min(MAX_ROUND_TRIP);
+        self.smoothed = Some(match self.smoothed {
+            // Like TCP's, an eighth of every new round trip
+            Some(smoothed) => (smoothed * 7 + sample) / 8,
+            None => sample,
+        });
+    }
+
+    /// How stale a move from the server is when it arrives, half the round trip
+    #[inline]
+    pub fn staleness(&self) -> Option<Duration> {
+        self.smoothed.map(|smoothed| smoothed / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL: Duration = Duration::from_millis(150);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn the_curve_holds_at_both_ends_and_shortens_between() {
+        let curve = SlideCurve::default();
+        assert_eq!(curve.slide_time(None, FULL), FULL);
+        assert_eq!(curve.slide_time(Some(Duration::ZERO), FULL), FULL);
+        assert_eq!(curve.slide_time(Some(ms(40)), FULL), FULL);
+        assert!(curve.slide_time(Some(ms(41)), FULL) < FULL);
+        assert_eq!(curve.slide_time(Some(ms(170)), FULL), ms(100));
+        assert!(curve.slide_time(Some(ms(299)), FULL) >= SHORTEST_SLIDE);
+        assert!(curve.slide_time(Some(ms(299)), FULL) <= ms(51));
+        assert_eq!(curve.slide_time(Some(ms(300)), FULL), Duration::ZERO);
+        assert_eq!(curve.slide_time(Some(ms(5000)), FULL), Duration::ZERO);
+
+        // A slide already shorter than the shortest isn't stretched
+        assert_eq!(curve.slide_time(Some(ms(200)), ms(30)), ms(30));
+    }
+
+    #[test]
+    fn curves_out_of_order_or_too_long_are_refused() {
+        assert_eq!(
+            SlideCurve::parse("0, 120"),
+            Some(SlideCurve {
+                fresh: Duration::ZERO,
+                stale: ms(120)
+            })
+        );
+        assert_eq!(SlideCurve::parse("40,300"), Some(SlideCurve::default()));
+        assert_eq!(SlideCurve::parse("300,40"), None);
+        assert_eq!(SlideCurve::parse("100,100"), None);
+        assert_eq!(SlideCurve::parse("40,2001"), None);
+        assert_eq!(SlideCurve::parse("40"), None);
+        assert_eq!(SlideCurve::parse("-5,300"), None);
+    }
+
+    #[test]
+    fn moves_over_a_slower_link_slide_for_less() {
+        let curve = SlideCurve::default();
+        let mut round_trip = RoundTrip::default();
+        // A state from the server before any move of ours was sent times nothing
+        round_trip.answered(ms(10));
+        assert_eq!(round_trip.staleness(), None);
+
+        // The injected round trips of a game: the client's move goes at `sent`, the state
+        // confirming it comes back at `answered`, then the server's reply, and how long it slides
+        let script = [
+            (ms(1000), ms(1040), ms(20), FULL),
+            (ms(3000), ms(4400), ms(105), ms(125)),
+            // Cut to five seconds, past that it is a stall and not the link
+            (
+                ms(5000),
+                ms(60_000),
+                Duration::from_micros(404_375),
+                Duration::ZERO,
+            ),
+        ];
+        for (sent, answered, staleness, slide) in script {
+            round_trip.sent(sent);
+            round_trip.answered(answered);
+            assert_eq!(round_trip.staleness(), Some(staleness));
+            assert_eq!(curve.slide_time(round_trip.staleness(), FULL), slide);
+        }
+        // Confirming again without a new move changes nothing
+        round_trip.answered(ms(20_000));
+        assert_eq!(round_trip.staleness(), Some(Duration::from_micros(404_375)));
+    }
+}
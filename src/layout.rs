@@ -0,0 +1,419 @@
+use std::ops::Range;
+
+// Height of the bars above and below the board holding the clocks and captured pieces,
+// as a part of the window's height, or of its width when the board is on top
+pub const BAR_FRACTION: f32 = 0.05;
+// Lines of the move list that fit in a panel as tall as the window's smaller dimension
+pub const HISTORY_LINE_COUNT: usize = 32;
+// The bottom of the history panel the evaluation graph takes while reviewing
+pub const GRAPH_FRACTION: f32 = 0.25;
+// Part of the window width the legend takes once it is fully open
+pub const LEGEND_FRACTION: f32 = 0.2;
+// How much taller than wide the window has to be for the board to go on top
+pub const DEFAULT_PORTRAIT_RATIO: f32 = 1.0;
+// How far past the ratio the window has to get before the layout switches, so a window
+// that is nearly square doesn't flip back and forth
+const SWITCH_MARGIN: f32 = 0.1;
+
+/// Which way the board and the history panel are put next to each other
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Orientation {
+    // The board on the left and the panel to its right
+    Landscape,
+    // The board on top and the panel below it
+    Portrait,
+}
+
+impl Orientation {
+    /// The pointer's position along the axis the splitter is dragged on
+    #[inline]
+    pub fn along(self, x: f32, y: f32) -> f32 {
+        match self {
+            Orientation::Landscape => x,
+            Orientation::Portrait => y,
+        }
+    }
+}
+
+/// The orientation chosen in the settings, by the window's shape for Auto
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum LayoutMode {
+    Auto,
+    Landscape,
+    Portrait,
+}
+
+impl LayoutMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(LayoutMode::Auto),
+            "landscape" => Some(LayoutMode::Landscape),
+            "portrait" => Some(LayoutMode::Portrait),
+            _ => None,
+        }
+    }
+}
+
+/// The orientation for a window of this size. Left to the window's shape, the board only goes
+/// on top once the height is `ratio` times the width and 10% more, and only goes back to the
+/// side once it is 10% less, otherwise `previous` is kept.
+pub fn orientation(
+    width: f32,
+    height: f32,
+    previous: Orientation,
+    mode: LayoutMode,
+    ratio: f32,
+) -> Orientation {
+    match mode {
+        LayoutMode::Landscape => Orientation::Landscape,
+        LayoutMode::Portrait => Orientation::Portrait,
+        LayoutMode::Auto if width <= 0.0 || height <= 0.0 => previous,
+        LayoutMode::Auto => {
+            let shape = height / width;
+            match previous {
+                Orientation::Landscape if shape > ratio * (1.0 + SWITCH_MARGIN) => {
+                    Orientation::Portrait
+                }
+                Orientation::Portrait if shape < ratio / (1.0 + SWITCH_MARGIN) => {
+                    Orientation::Landscape
+                }
+                previous => previous,
+            }
+        }
+    }
+}
+
+/// The lines of a move list `total` lines long that are drawn, scrolled `scroll` lines up from
+/// the latest with room for `count`. Only these are laid out, however long the game gets.
+#[inline]
+pub fn visible_range(total: usize, scroll: usize, count: usize) -> Range<usize> {
+    let end = total - scroll.min(total);
+    end.saturating_sub(count)..end
+}
+
+/// A part of the window, in the window's pixels
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Area {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Area {
+    #[inline]
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    #[inline]
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x <= self.x + self.w && y >= self.y && y <= self.y + self.h
+    }
+
+    /// Whether the two share more than an edge
+    #[inline]
+    pub fn overlaps(&self, other: &Area) -> bool {
+        self.x < other.x + other.w
+            && other.x < self.x + self.w
+            && self.y < other.y + other.h
+            && other.y < self.y + self.h
+    }
+}
+
+/// Where everything goes in the window. All drawing and hit-testing goes by this rather than
+/// assuming the panel is beside the board.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct LayoutPlan {
+    pub orientation: Orientation,
+    // The square the board is drawn in, the clock bars go right above and below it
+    pub board: Area,
+    pub bar: f32,
+    pub history: Area,
+    // Height of a line of the move list, and how many of them fit in the panel
+    pub line_height: f32,
+    pub history_rows: usize,
+    // The bottom of the history panel, where the evaluation graph goes while reviewing
+    pub graph: Area,
+    // None while the legend is closed
+    pub legend: Option<Area>,
+}
+
+impl LayoutPlan {
+    /// `panel` is the history panel's part of the window along the splitter's axis, as the
+    /// splitter has it, and `legend` the part of the window width the legend takes to the
+    /// left of the board as it slides open
+    pub fn new(width: f32, height: f32, panel: f32, legend: f32, orientation: Orientation) -> Self {
+        let left = width * legend;
+        let (bar, board, history) = match orientation {
+            Orientation::Landscape => {
+                let bar = height * BAR_FRACTION;
+                let side = (width * (1.0 - panel - legend)).min(height - 2.0 * bar);
+                let x = left + side;
+                (
+                    bar,
+                    Area::new(left, bar, side, side),
+                    Area::new(x, 0.0, width - x, height),
+                )
+            }
+            Orientation::Portrait => {
+                let bar = width * BAR_FRACTION;
+                let side = (width - left).min(height * (1.0 - panel) - 2.0 * bar);
+                let y = side + 2.0 * bar;
+                (
+                    bar,
+                    Area::new(left, bar, side, side),
+                    Area::new(left, y, width - left, height - y),
+                )
+            }
+        };
+        let graph = history.h * GRAPH_FRACTION;
+        // Lines are sized by the constrained dimension so they read the same either way,
+        // a panel below the board just shows fewer of them
+        let line_height = width.min(height) / HISTORY_LINE_COUNT as f32;
+        Self {
+            orientation,
+            board,
+            bar,
+            history,
+            line_height,
+            history_rows: match line_height > 0.0 {
+                true => (history.h / line_height + 1e-3) as usize,
+                false => 0,
+            },
+            graph: Area::new(history.x, history.y + history.h - graph, history.w, graph),
+            legend: (legend > 0.0).then(|| {
+                Area::new(
+                    width * (legend - LEGEND_FRACTION),
+                    0.0,
+                    width * LEGEND_FRACTION,
+                    height,
+                )
+            }),
+        }
+    }
+
+    /// The lines of the move list shown, fewer while the graph takes the bottom of the panel
+    #[inline]
+    pub fn visible_lines(&self, reviewing: bool) -> usize {
+        match reviewing {
+            true => self.history_rows - (self.history_rows as f32 * GRAPH_FRACTION) as usize,
+            false => self.history_rows,
+        }
+    }
+
+    /// Where the splitter is along its axis, the start of the history panel
+    #[inline]
+    pub fn split_edge(&self) -> f32 {
+        match self.orientation {
+            Orientation::Landscape => self.history.x,
+            Orientation::Portrait => self.history.y,
+        }
+    }
+}
+
+/// The window's scale factor as last seen. It changes when the window is moved to a monitor
+/// of another density, and whatever was laid out or rasterized at the old one is stale then.
+#[derive(Default)]
+pub struct ScaleWatch {
+    last: Option<f64>,
+}
+
+impl ScaleWatch {
+    /// Whether the window is drawn at another scale than the last time it was looked at. The
+    /// first look only takes note of it, nothing was drawn at another one before.
+    pub fn changed(&mut self, scale: f64) -> bool {
+        self.last.replace(scale).is_some_and(|last| last != scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_visible_lines_are_drawn_however_long_the_game() {
+        for total in [0, 3, 32, 500, 100_000] {
+            for scroll in [0, 1, 10, 499, 200_000] {
+                let drawn = visible_range(total, scroll, 24);
+                assert!(drawn.len() <= 24);
+                assert!(drawn.end <= total);
+                // The list is full unless it is shorter than the panel or scrolled past its start
+                if total - scroll.min(total) >= 24 {
+                    assert_eq!(drawn.len(), 24);
+                }
+            }
+        }
+        assert_eq!(visible_range(500, 0, 24), 476..500);
+        assert_eq!(visible_range(500, 10, 24), 466..490);
+    }
+
+    fn in_window(area: &Area, width: f32, height: f32) -> bool {
+        let slack = 1e-3;
+        area.x >= -slack
+            && area.y >= -slack
+            && area.x + area.w <= width + slack
+            && area.y + area.h <= height + slack
+    }
+
+    #[test]
+    fn a_nearly_square_window_keeps_its_layout() {
+        let auto =
+            |width, height, previous| orientation(width, height, previous, LayoutMode::Auto, 1.0);
+        // Within 10% of square either way, whatever was there stays
+        for height in [920.0, 1000.0, 1090.0] {
+            assert_eq!(
+                auto(1000.0, height, Orientation::Landscape),
+                Orientation::Landscape
+            );
+            assert_eq!(
+                auto(1000.0, height, Orientation::Portrait),
+                Orientation::Portrait
+            );
+        }
+        assert_eq!(
+            auto(1000.0, 1110.0, Orientation::Landscape),
+            Orientation::Portrait
+        );
+        assert_eq!(
+            auto(1000.0, 900.0, Orientation::Portrait),
+            Orientation::Landscape
+        );
+        // The ratio moves where the switch happens
+        assert_eq!(
+            orientation(
+                1000.0,
+                1400.0,
+                Orientation::Landscape,
+                LayoutMode::Auto,
+                1.5
+            ),
+            Orientation::Landscape
+        );
+        // The settings win over the shape
+        assert_eq!(
+            orientation(
+                1000.0,
+                500.0,
+                Orientation::Landscape,
+                LayoutMode::Portrait,
+                1.0
+            ),
+            Orientation::Portrait
+        );
+        assert_eq!(
+            orientation(
+                500.0,
+                1000.0,
+                Orientation::Portrait,
+                LayoutMode::Landscape,
+                1.0
+            ),
+            Orientation::Landscape
+        );
+    }
+
+    #[test]
+    fn resizing_to_portrait_switches_once() {
+        let mut current = Orientation::Landscape;
+        let mut switches = 0;
+        // From 1000x600 to 600x1000 a pixel at a time, wobbling back a little on the way
+        for step in 0..=400 {
+            for wobble in [0.0, 3.0, 0.0] {
+                let width = 1000.0 - step as f32 + wobble;
+                let height = 600.0 + step as f32 - wobble;
+                let next = orientation(width, height, current, LayoutMode::Auto, 1.0);
+                if next != current {
+                    switches += 1;
+                    current = next;
+                }
+            }
+        }
+        assert_eq!(current, Orientation::Portrait);
+        assert_eq!(switches, 1);
+    }
+
+    #[test]
+    fn a_new_density_keeps_the_board_where_it_looks() {
+        // The window keeps its logical size, so its pixels grow and shrink with the scale
+        let (width, height) = (800.0, 600.0);
+        let logical = LayoutPlan::new(width, height, 0.2, 0.0, Orientation::Landscape);
+        let mut watch = ScaleWatch::default();
+        assert!(!watch.changed(1.0));
+        assert!(!watch.changed(1.0));
+        for scale in [2.0, 1.5, 1.0] {
+            assert!(watch.changed(scale), "{}", scale);
+            assert!(!watch.changed(scale));
+            let scale = scale as f32;
+            let plan = LayoutPlan::new(
+                width * scale,
+                height * scale,
+                0.2,
+                0.0,
+                Orientation::Landscape,
+            );
+            for (drawn, expected) in [
+                (plan.board, logical.board),
+                (plan.history, logical.history),
+                (plan.graph, logical.graph),
+            ] {
+                for (drawn, expected) in [
+                    (drawn.x, expected.x),
+                    (drawn.y, expected.y),
+                    (drawn.w, expected.w),
+                    (drawn.h, expected.h),
+                ] {
+                    assert!((drawn / scale - expected).abs() < 1e-3, "{}", scale);
+                }
+            }
+            // The move list shows as many lines, just sharper
+            assert_eq!(plan.history_rows, logical.history_rows);
+        }
+    }
+
+    #[test]
+    fn nothing_overlaps() {
+        for orientation in [Orientation::Landscape, Orientation::Portrait] {
+            for (width, height) in [(1000.0, 800.0), (600.0, 1000.0), (300.0, 300.0)] {
+                for panel in [0.0, 0.12, 0.2, 0.5] {
+                    for legend in [0.0, 0.1, LEGEND_FRACTION] {
+                        let plan = LayoutPlan::new(width, height, panel, legend, orientation);
+                        let bars = Area::new(
+                            plan.board.x,
+                            0.0,
+                            plan.board.w,
+                            plan.board.h + 2.0 * plan.bar,
+                        );
+                        assert!(!bars.overlaps(&plan.history), "{:?}", plan);
+                        assert!(in_window(&plan.board, width, height), "{:?}", plan);
+                        assert!(in_window(&plan.history, width, height), "{:?}", plan);
+                        if let Some(legend) = plan.legend {
+                            assert!(!legend.overlaps(&plan.board), "{:?}", plan);
+                            assert!(!legend.overlaps(&plan.history), "{:?}", plan);
+                        }
+                        // The graph is the bottom of the panel
+                        assert!(plan.history.contains(plan.graph.x, plan.graph.y));
+                        assert!(plan.board.w == plan.board.h);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_board_goes_on_top_in_portrait() {
+        let plan = LayoutPlan::new(600.0, 1000.0, 0.2, 0.0, Orientation::Portrait);
+        assert_eq!(plan.board, Area::new(0.0, 30.0, 600.0, 600.0));
+        assert_eq!(plan.history, Area::new(0.0, 660.0, 600.0, 340.0));
+        assert_eq!(plan.split_edge(), 660.0);
+        // Lines as tall as in a 600 pixel high window, 18 of them fit below the board
+        assert_eq!(plan.history_rows, 18);
+        assert_eq!(plan.visible_lines(true), 14);
+
+        let plan = LayoutPlan::new(1000.0, 800.0, 0.2, 0.0, Orientation::Landscape);
+        assert_eq!(plan.board, Area::new(0.0, 40.0, 720.0, 720.0));
+        assert_eq!(plan.split_edge(), 720.0);
+        assert_eq!(plan.history_rows, HISTORY_LINE_COUNT);
+        assert_eq!(plan.visible_lines(true), 24);
+    }
+}
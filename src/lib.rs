@@ -0,0 +1,1495 @@
+//! The chess logic behind the window: the board, parsing, the selection and the network code.
+//! Nothing in here needs ggez, so whole games can be played through `session::Session`.
+
+pub mod accessibility;
+pub mod autosave;
+pub mod bookmarks;
+pub mod bundle;
+pub mod clipboard;
+pub mod clock;
+pub mod config;
+pub mod connection_book;
+pub mod connectivity;
+pub mod effects;
+pub mod engine;
+pub mod engine_match;
+pub mod eval_graph;
+pub mod game_list;
+pub mod glyphs;
+pub mod hook;
+pub mod identity;
+pub mod interop;
+pub mod latency;
+pub mod layout;
+pub mod metrics;
+pub mod mouse;
+pub mod negotiation;
+pub mod network;
+pub mod notation;
+pub mod ordering;
+pub mod pgn;
+pub mod piece_style;
+pub mod pins;
+pub mod practice;
+pub mod profiles;
+#[cfg(test)]
+mod properties;
+pub mod recovery;
+pub mod sandbox;
+pub mod scene;
+#[cfg(test)]
+mod scratch;
+pub mod selftest;
+pub mod session;
+pub mod speculation;
+pub mod splitter;
+pub mod stall;
+pub mod strikes;
+pub mod text_cache;
+pub mod toasts;
+pub mod trainer;
+pub mod transcript;
+pub mod traps;
+pub mod tty_mirror;
+pub mod turn;
+pub mod updates;
+pub mod validate;
+pub mod watchdog;
+
+use jonathan_hallstrom_chess::{Board, Color, Move, PieceType};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Square {
+    Empty,
+    Pawn(Color),
+    Rook(Color),
+    Bishop(Color),
+    Knight(Color),
+    King(Color),
+    Queen(Color),
+}
+
+impl Square {
+    #[inline]
+    pub fn color(&self) -> Option<Color> {
+        match self {
+            Square::Empty => None,
+            Square::Pawn(color)
+            | Square::Rook(color)
+            | Square::Bishop(color)
+            | Square::Knight(color)
+            | Square::King(color)
+            | Square::Queen(color) => Some(*color),
+        }
+    }
+}
+
+/// A played move as listed in the history panel and written to PGN
+pub struct HistoryEntry {
+    // Coordinates like "e2e4"
+    pub notation: String,
+    // Standard algebraic notation like "e4"
+    pub san: String,
+    pub color: Color,
+    // The piece taken by the move, if any
+    pub captured: Option<Square>,
+    // Set while reviewing, like ! or ??, and written to PGN with the move
+    pub glyph: Option<glyphs::Glyph>,
+}
+
+impl HistoryEntry {
+    /// The move as the move list shows it, in coordinates without a notation
+    #[inline]
+    pub fn shown(&self, notation: Option<notation::Notation>) -> String {
+        match notation {
+            Some(notation) => notation::restyle(&self.san, notation),
+            None => self.notation.clone(),
+        }
+    }
+}
+
+/// Column and row of the piece in the 6x2 sprite sheet
+#[inline]
+pub fn sprite_cell(piece: &Square) -> Option<(usize, usize)> {
+    let col = match piece {
+        Square::Empty => return None,
+        Square::Pawn(_) => 5,
+        Square::Rook(_) => 4,
+        Square::Knight(_) => 3,
+        Square::Bishop(_) => 2,
+        Square::Queen(_) => 1,
+        Square::King(_) => 0,
+    };
+    let row = match piece.color()? {
+        Color::White => 0,
+        Color::Black => 1,
+    };
+    Some((col, row))
+}
+
+#[inline]
+pub fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+#[inline]
+pub fn opposite_color(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+/// Progress from 0 to 1 eased so whatever moves settles gently, for the slides and the panel
+#[inline]
+pub fn ease_out(t: f32) -> f32 {
+    1.0 - (1.0 - t) * (1.0 - t)
+}
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum GameState {
+    Ongoing,
+    // Holds the color that got checkmated
+    Checkmate(Color),
+    Stalemate,
+    Draw,
+    // The same position came up for the third time
+    Repetition,
+    // Fifty moves by each side without a capture or a pawn move
+    FiftyMoves,
+    // Neither side has the pieces left to ever checkmate
+    InsufficientMaterial,
+    // Holds the color that resigned
+    Resigned(Color),
+    // Holds the color whose clock ran out
+    TimeForfeit(Color),
+    // Holds the color the server gave the game away for sending too many rejected moves
+    Forfeit(Color),
+    // Holds the color that stopped moving in an untimed networked game and how long it went
+    // without a move before the win was claimed, only decided on this side
+    Stalled(Color, Duration),
+    // The engine has no moves in a position that breaks the rules, so it is neither mate nor
+    // stalemate and nobody won
+    PositionError,
+}
+
+impl GameState {
+    /// Text shown on top of the board once the game is over
+    pub fn description(&self) -> Option<String> {
+        match self {
+            GameState::Ongoing => None,
+            GameState::Checkmate(color) => Some(format!(
+                "Checkmate, {} wins",
+                color_name(opposite_color(*color))
+            )),
+            GameState::Stalemate => Some("Stalemate".to_owned()),
+            GameState::Draw => Some("Draw".to_owned()),
+            GameState::Repetition => Some("Draw by threefold repetition".to_owned()),
+            GameState::FiftyMoves => Some("Draw by the fifty-move rule".to_owned()),
+            GameState::InsufficientMaterial => Some("Draw by insufficient material".to_owned()),
+            GameState::Resigned(color) => Some(format!(
+                "{} resigned, {} wins",
+                color_name(*color),
+                color_name(opposite_color(*color))
+            )),
+            GameState::TimeForfeit(color) => Some(format!(
+                "{} ran out of time, {} wins",
+                color_name(*color),
+                color_name(opposite_color(*color))
+            )),
+            GameState::Forfeit(color) => Some(format!(
+                "{} forfeited with repeated illegal moves, {} wins",
+                color_name(*color),
+                color_name(opposite_color(*color))
+            )),
+            GameState::Stalled(color, waited) => Some(format!(
+                "{} won, claimed after {} without a move from {}",
+                color_name(opposite_color(*color)),
+                stall::format_duration(*waited),
+                color_name(*color)
+            )),
+            GameState::PositionError => {
+                Some("The engine found no moves in a broken position".to_owned())
+            }
+        }
+    }
+}
+
+/// Whether moving the piece at `from` to `to` takes a piece, looked at before the move
+pub fn is_capture(squares: &[[Square; 8]; 8], from: (usize, usize), to: (usize, usize)) -> bool {
+    captured_piece(squares, from, to).is_some()
+}
+
+/// Whether moving the piece at `from` to `to` castles, looked at before the move. The king
+/// moves two files, or onto its own rook as some engines encode it. Whatever tells castling
+/// apart goes by this, so none of them takes the rook for a capture.
+pub fn is_castling(squares: &[[Square; 8]; 8], from: (usize, usize), to: (usize, usize)) -> bool {
+    let (piece, target) = (squares[from.0][from.1], squares[to.0][to.1]);
+    matches!(piece, Square::King(_))
+        && from.0 == to.0
+        && (from.1.abs_diff(to.1) == 2
+            || (matches!(target, Square::Rook(_)) && target.color() == piece.color()))
+}
+
+/// The squares the king goes to and the rook goes from and to when castling from `from`
+/// towards `to`
+pub fn castling_squares(
+    from: (usize, usize),
+    to: (usize, usize),
+) -> ((usize, usize), (usize, usize), (usize, usize)) {
+    let rank = from.0;
+    match to.1 > from.1 {
+        true => ((rank, 6), (rank, 7), (rank, 5)),
+        false => ((rank, 2), (rank, 0), (rank, 3)),
+    }
+}
+
+/// The piece taken by moving from `from` to `to`, looked at before the move
+pub fn captured_piece(
+    squares: &[[Square; 8]; 8],
+    from: (usize, usize),
+    to: (usize, usize),
+) -> Option<Square> {
+    if is_castling(squares, from, to) {
+        return None;
+    }
+    if squares[to.0][to.1] != Square::Empty {
+        return Some(squares[to.0][to.1]);
+    }
+    // En passant lands on an empty square, the pawn taken is beside the one moving
+    match (squares[from.0][from.1], squares[from.0][to.1]) {
+        (Square::Pawn(_), Square::Pawn(color)) if from.1 != to.1 => Some(Square::Pawn(color)),
+        _ => None,
+    }
+}
+
+/// Standard piece values, counted in pawns
+#[inline]
+pub fn material_value(piece: &Square) -> i32 {
+    match piece {
+        Square::Empty | Square::King(_) => 0,
+        Square::Pawn(_) => 1,
+        Square::Knight(_) | Square::Bishop(_) => 3,
+        Square::Rook(_) => 5,
+        Square::Queen(_) => 9,
+    }
+}
+
+/// A kind of piece as the legend explains it
+pub struct PieceGuide {
+    pub piece: fn(Color) -> Square,
+    pub name: &'static str,
+    // How it moves, in a line
+    pub moves: &'static str,
+}
+
+/// Every kind of piece in the order the legend lists them
+pub const PIECE_GUIDE: [PieceGuide; 6] = [
+    PieceGuide {
+        piece: Square::King,
+        name: "King",
+        moves: "One square in any direction",
+    },
+    PieceGuide {
+        piece: Square::Queen,
+        name: "Queen",
+        moves: "Any distance in a straight line or diagonally",
+    },
+    PieceGuide {
+        piece: Square::Rook,
+        name: "Rook",
+        moves: "Any distance along a rank or file",
+    },
+    PieceGuide {
+        piece: Square::Bishop,
+        name: "Bishop",
+        moves: "Any distance diagonally",
+    },
+    PieceGuide {
+        piece: Square::Knight,
+        name: "Knight",
+        moves: "In an L shape, jumping over pieces",
+    },
+    PieceGuide {
+        piece: Square::Pawn,
+        name: "Pawn",
+        moves: "Forward one square, or two from the start, takes diagonally",
+    },
+];
+
+/// For every kind of piece in `PIECE_GUIDE`, whether either side still has one on the board
+pub fn kinds_on_board(squares: &[[Square; 8]; 8]) -> [bool; 6] {
+    PIECE_GUIDE.each_ref().map(|guide| {
+        squares.iter().flatten().any(|square| {
+            *square == (guide.piece)(Color::White) || *square == (guide.piece)(Color::Black)
+        })
+    })
+}
+
+/// White's material minus Black's
+pub fn material_balance(squares: &[[Square; 8]; 8]) -> i32 {
+    squares
+        .iter()
+        .flatten()
+        .map(|piece| match piece.color() {
+            Some(Color::White) => material_value(piece),
+            Some(Color::Black) => -material_value(piece),
+            None => 0,
+        })
+        .sum()
+}
+
+/// Whether the position is dead for lack of material: bare kings, a single knight or bishop,
+/// or only bishops that all stand on squares of one color
+pub fn is_insufficient_material(squares: &[[Square; 8]; 8]) -> bool {
+    let mut knights = 0;
+    // Counted by the color of their squares, light ones first
+    let mut bishops = [0; 2];
+    for (row, rank) in squares.iter().enumerate() {
+        for (col, piece) in rank.iter().enumerate() {
+            match piece {
+                Square::Empty | Square::King(_) => {}
+                Square::Knight(_) => knights += 1,
+                Square::Bishop(_) => bishops[(row + col) % 2] += 1,
+                Square::Pawn(_) | Square::Rook(_) | Square::Queen(_) => return false,
+            }
+        }
+    }
+    matches!((knights, bishops), (0, [0, _]) | (0, [_, 0]) | (1, [0, 0]))
+}
+
+/// Every piece of color `by` attacking the square at (row, col), as (row, col)
+pub fn attackers(
+    squares: &[[Square; 8]; 8],
+    (row, col): (usize, usize),
+    by: Color,
+) -> Vec<(usize, usize)> {
+    let at = |drow: isize, dcol: isize| -> Option<((usize, usize), Square)> {
+        let (row, col) = (row as isize + drow, col as isize + dcol);
+        match (0..8).contains(&row) && (0..8).contains(&col) {
+            true => Some((
+                (row as usize, col as usize),
+                squares[row as usize][col as usize],
+            )),
+            false => None,
+        }
+    };
+    let mut found = Vec::new();
+
+    // Pawns attack diagonally forward, white moves towards row 0
+    let pawn_drow = match by {
+        Color::White => 1,
+        Color::Black => -1,
+    };
+    for dcol in [-1, 1] {
+        if let Some((square, Square::Pawn(color))) = at(pawn_drow, dcol) {
+            if color == by {
+                found.push(square);
+            }
+        }
+    }
+
+    const KNIGHT_OFFSETS: [(isize, isize); 8] = [
+        (-2, -1),
+        (-2, 1),
+        (-1, -2),
+        (-1, 2),
+        (1, -2),
+        (1, 2),
+        (2, -1),
+        (2, 1),
+    ];
+    for (drow, dcol) in KNIGHT_OFFSETS {
+        if let Some((square, Square::Knight(color))) = at(drow, dcol) {
+            if color == by {
+                found.push(square);
+            }
+        }
+    }
+
+    const STRAIGHT: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    const DIAGONAL: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+    for (drow, dcol) in STRAIGHT.iter().chain(DIAGONAL.iter()) {
+        let slider = match *drow == 0 || *dcol == 0 {
+            true => Square::Rook(by),
+            false => Square::Bishop(by),
+        };
+        let mut distance = 1;
+        // Walk the ray until it leaves the board or hits a piece
+        while let Some((square, piece)) = at(drow * distance, dcol * distance) {
+            if (distance == 1 && piece == Square::King(by))
+                || piece == slider
+                || piece == Square::Queen(by)
+            {
+                found.push(square);
+            }
+            if piece != Square::Empty {
+                break;
+            }
+            distance += 1;
+        }
+    }
+    found
+}
+
+/// Whether any piece of color `by` attacks the square at (row, col)
+#[inline]
+fn is_square_attacked(squares: &[[Square; 8]; 8], square: (usize, usize), by: Color) -> bool {
+    !attackers(squares, square, by).is_empty()
+}
+
+#[inline]
+fn find_king(squares: &[[Square; 8]; 8], color: Color) -> Option<(usize, usize)> {
+    (0..8usize)
+        .flat_map(|row| (0..8usize).map(move |col| (row, col)))
+        .find(|(row, col)| squares[*row][*col] == Square::King(color))
+}
+
+/// Square of the king of `color` if it is in check
+#[inline]
+pub fn checked_king(squares: &[[Square; 8]; 8], color: Color) -> Option<(usize, usize)> {
+    find_king(squares, color)
+        .filter(|king| is_square_attacked(squares, *king, opposite_color(color)))
+}
+
+#[inline]
+pub fn is_in_check(squares: &[[Square; 8]; 8], color: Color) -> bool {
+    checked_king(squares, color).is_some()
+}
+
+/// The first rule of chess a position breaks whoever is to move, the engine's answers about
+/// such a position can't be trusted
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum PositionProblem {
+    // A side needs exactly one king
+    Kings(Color, usize),
+    // Kings can never stand next to each other
+    AdjacentKings,
+    // Pawns can't stand on the first or last rank, by the square of the first found
+    PawnOnEdge((usize, usize)),
+    // The side that just moved would have left its king in check, it could be taken
+    WaitingInCheck(Color),
+}
+
+impl std::fmt::Display for PositionProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PositionProblem::Kings(color, 0) => write!(f, "{} has no king", color_name(*color)),
+            PositionProblem::Kings(color, count) => {
+                write!(f, "{} has {} kings", color_name(*color), count)
+            }
+            PositionProblem::AdjacentKings => write!(f, "The kings stand next to each other"),
+            PositionProblem::PawnOnEdge((row, col)) => write!(
+                f,
+                "The pawn on {} is on the first or last rank",
+                pgn::square_name(*row, *col)
+            ),
+            PositionProblem::WaitingInCheck(color) => write!(
+                f,
+                "{} is in check but it is {}'s move",
+                color_name(*color),
+                color_name(opposite_color(*color))
+            ),
+        }
+    }
+}
+
+/// What is wrong with the position with `to_move` to move, None if it could come up in a game
+pub fn position_problem(squares: &[[Square; 8]; 8], to_move: Color) -> Option<PositionProblem> {
+    for color in [Color::White, Color::Black] {
+        let kings = squares
+            .iter()
+            .flatten()
+            .filter(|square| **square == Square::King(color))
+            .count();
+        if kings != 1 {
+            return Some(PositionProblem::Kings(color, kings));
+        }
+    }
+    if let (Some(white), Some(black)) = (
+        find_king(squares, Color::White),
+        find_king(squares, Color::Black),
+    ) {
+        if white.0.abs_diff(black.0) <= 1 && white.1.abs_diff(black.1) <= 1 {
+            return Some(PositionProblem::AdjacentKings);
+        }
+    }
+    for row in [0, 7] {
+        if let Some(col) = (0..8).find(|col| matches!(squares[row][*col], Square::Pawn(_))) {
+            return Some(PositionProblem::PawnOnEdge((row, col)));
+        }
+    }
+    let waiting = opposite_color(to_move);
+    is_in_check(squares, waiting).then_some(PositionProblem::WaitingInCheck(waiting))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FenError {
+    Empty,
+    RankCount(usize),
+    // Ranks are numbered like on the board, 8 is the first one in the FEN
+    TooManyFiles { rank: usize },
+    FileCount { rank: usize, files: usize },
+    InvalidPiece(char),
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "The FEN is empty"),
+            FenError::RankCount(count) => write!(f, "Expected 8 ranks, found {}", count),
+            FenError::TooManyFiles { rank } => write!(f, "Rank {} has more than 8 files", rank),
+            FenError::FileCount { rank, files } => write!(f, "Rank {} has {} files", rank, files),
+            FenError::InvalidPiece(c) => write!(f, "Invalid piece letter '{}'", c),
+        }
+    }
+}
+
+/// Parses the piece placement of a FEN, the remaining fields are left to the engine
+pub fn parse_fen(fen: &str) -> Result<[[Square; 8]; 8], FenError> {
+    let placement = fen.split_whitespace().next().ok_or(FenError::Empty)?;
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::RankCount(ranks.len()));
+    }
+
+    let mut board = [[Square::Empty; 8]; 8];
+    for (row, rank) in ranks.iter().enumerate() {
+        let mut col = 0usize;
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10) {
+                col += empty as usize;
+                continue;
+            }
+            if col >= 8 {
+                return Err(FenError::TooManyFiles { rank: 8 - row });
+            }
+
+            let color = match c.is_uppercase() {
+                true => Color::White,
+                false => Color::Black,
+            };
+            board[row][col] = match c.to_ascii_lowercase() {
+                'p' => Square::Pawn(color),
+                'b' => Square::Bishop(color),
+                'r' => Square::Rook(color),
+                'n' => Square::Knight(color),
+                'q' => Square::Queen(color),
+                'k' => Square::King(color),
+                _ => return Err(FenError::InvalidPiece(c)),
+            };
+            col += 1;
+        }
+        if col != 8 {
+            return Err(FenError::FileCount {
+                rank: 8 - row,
+                files: col,
+            });
+        }
+    }
+    Ok(board)
+}
+
+/// Builds an engine board from a full FEN, the only place the engine parses one.
+/// Positions the game reaches itself are replayed from `Board::default()` instead, this is
+/// only for the ones that exist as FEN alone: --fen, the server's start and its board.
+pub fn board_from_fen(fen: &str) -> Result<Board, String> {
+    // Check the placement first so the error says what is wrong with it
+    parse_fen(fen).map_err(|err| err.to_string())?;
+    let standard = Board::default();
+    if fen
+        .split_whitespace()
+        .eq(standard.to_fen().split_whitespace())
+    {
+        return Ok(standard);
+    }
+    Board::from_fen(fen).map_err(|_| format!("Not a legal position: \"{}\"", fen))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MoveParseError {
+    Length(usize),
+    Square(String),
+    Promotion(char),
+}
+
+impl std::fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MoveParseError::Length(len) => write!(f, "A move has 4 or 5 characters, not {}", len),
+            MoveParseError::Square(square) => write!(f, "\"{}\" is not a square", square),
+            MoveParseError::Promotion(c) => write!(f, "Can't promote to '{}'", c),
+        }
+    }
+}
+
+/// Row and column of a square like "e4", row 0 being the eighth rank
+#[inline]
+pub(crate) fn to_cordinate(file: char, rank: char) -> Result<(usize, usize), MoveParseError> {
+    match (file, rank) {
+        ('a'..='h', '1'..='8') => Ok((
+            7 - (rank as usize - '1' as usize),
+            file as usize - 'a' as usize,
+        )),
+        _ => Err(MoveParseError::Square(format!("{}{}", file, rank))),
+    }
+}
+
+/// The squares a move goes from and to as (row, col)
+pub type MoveSquares = ((usize, usize), (usize, usize));
+
+#[inline]
+pub fn parse_move(mv: &str) -> Result<MoveSquares, MoveParseError> {
+    let x: Vec<char> = mv.chars().collect();
+    if x.len() != 4 && x.len() != 5 {
+        return Err(MoveParseError::Length(x.len()));
+    }
+    Ok((to_cordinate(x[0], x[1])?, to_cordinate(x[2], x[3])?))
+}
+
+/// Kinds of moves the peer didn't advertise, so they can't be played here
+#[derive(Eq, PartialEq, Copy, Clone, Default)]
+pub struct ExcludedMoves {
+    pub castling: bool,
+    pub en_passant: bool,
+    // Promoting to a queen stays allowed and is played without the overlay
+    pub underpromotion: bool,
+}
+
+impl ExcludedMoves {
+    pub fn for_features(features: &[chess_network_protocol::Features]) -> Self {
+        use chess_network_protocol::Features;
+        Self {
+            castling: !features.contains(&Features::Castling),
+            en_passant: !features.contains(&Features::EnPassant),
+            underpromotion: !features.contains(&Features::Promotion),
+        }
+    }
+
+    pub fn excludes(
+        &self,
+        squares: &[[Square; 8]; 8],
+        mv: &Move,
+        from: (usize, usize),
+        to: (usize, usize),
+    ) -> bool {
+        match squares[from.0][from.1] {
+            Square::King(_) => self.castling && is_castling(squares, from, to),
+            Square::Pawn(_) => {
+                let en_passant = from.1 != to.1 && squares[to.0][to.1] == Square::Empty;
+                let underpromotion = mv
+                    .get_promoted_type()
+                    .is_some_and(|piece_type| piece_type != PieceType::Queen);
+                (self.en_passant && en_passant) || (self.underpromotion && underpromotion)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The legal moves from every square, by the square they go to
+pub type MovesBySquare = [[HashMap<(usize, usize), Vec<Move>>; 8]; 8];
+
+pub fn parse_moves(
+    moves: &[Move],
+    squares: &[[Square; 8]; 8],
+    excluded: ExcludedMoves,
+) -> MovesBySquare {
+    let mut parsed: MovesBySquare = Default::default();
+
+    for mv in moves {
+        let Ok((from, to)) = parse_move(&mv.to_algebraic_notation()) else {
+            println!("Skipping unreadable move {}", mv.to_algebraic_notation());
+            continue;
+        };
+        if excluded.excludes(squares, mv, from, to) {
+            continue;
+        }
+        parsed[from.0][from.1].entry(to).or_default().push(*mv);
+    }
+    parsed
+}
+
+/// Everything a refresh works out from a position, so it can also be worked out ahead
+pub struct Refresh {
+    moves: Vec<Move>,
+    squares: [[Square; 8]; 8],
+    legal_moves: MovesBySquare,
+    to_move: Color,
+    checked_king: Option<(usize, usize)>,
+}
+
+impl Refresh {
+    pub fn new(board: &Board, excluded: ExcludedMoves) -> Self {
+        let moves = board.get_legal_moves();
+        let squares = parse_fen(&board.to_fen()).expect("The engine produced an invalid FEN");
+        // En passant is told apart by the squares, so they come first
+        let legal_moves = parse_moves(&moves, &squares, excluded);
+        let to_move = board.get_curr_player();
+        Self {
+            checked_king: checked_king(&squares, to_move),
+            moves,
+            squares,
+            legal_moves,
+            to_move,
+        }
+    }
+
+    /// Whether both came out exactly the same, moves are compared by their notation
+    pub fn same_as(&self, other: &Refresh) -> bool {
+        let notations = |moves: &[Move]| -> Vec<String> {
+            moves.iter().map(Move::to_algebraic_notation).collect()
+        };
+        // A move's piece square, its destination and the moves there
+        type Listed = ((usize, usize), (usize, usize), Vec<String>);
+        let by_square = |legal_moves: &MovesBySquare| {
+            let mut listed: Vec<Listed> = Vec::new();
+            for (row, squares) in legal_moves.iter().enumerate() {
+                for (col, destinations) in squares.iter().enumerate() {
+                    for (to, moves) in destinations {
+                        listed.push(((row, col), *to, notations(moves)));
+                    }
+                }
+            }
+            listed.sort();
+            listed
+        };
+        notations(&self.moves) == notations(&other.moves)
+            && self.squares == other.squares
+            && by_square(&self.legal_moves) == by_square(&other.legal_moves)
+            && self.to_move == other.to_move
+            && self.checked_king == other.checked_king
+    }
+}
+
+pub struct BoardRepr {
+    // Rendering aid
+    pub squares: [[Square; 8]; 8],
+    pub legal_moves: MovesBySquare,
+    pub selected_from: Option<(usize, usize)>,
+    pub selected_to: Option<(usize, usize)>,
+    // A selection a refresh let go of because another piece is on its square now, until the
+    // window has shown that
+    pub dropped_selection: Option<(usize, usize)>,
+    // Whether the board is drawn with Black at the bottom
+    pub flipped: bool,
+    // King of the player to move if it is in check
+    pub checked_king: Option<(usize, usize)>,
+    // Legal moves of the position, generated once and shared by drawing, input and the network
+    pub moves: Vec<Move>,
+    pub to_move: Color,
+    // Left out of `legal_moves` only, the peer's own moves are still matched against `moves`
+    pub excluded: ExcludedMoves,
+    // How big the move chooser's choices are, for drawing and clicking alike
+    pub choice_size: ChoiceSize,
+}
+
+impl BoardRepr {
+    pub fn new(board: &Board) -> Self {
+        let mut repr = Self {
+            squares: [[Square::Empty; 8]; 8],
+            legal_moves: Default::default(),
+            selected_from: None,
+            selected_to: None,
+            dropped_selection: None,
+            flipped: false,
+            checked_king: None,
+            moves: Vec::new(),
+            to_move: board.get_curr_player(),
+            excluded: ExcludedMoves::default(),
+            choice_size: ChoiceSize::Square,
+        };
+        repr.refresh(board);
+        repr
+    }
+
+    /// Regenerate everything derived from the position after it changed
+    #[inline]
+    pub fn refresh(&mut self, board: &Board) {
+        self.install(Refresh::new(board, self.excluded));
+    }
+
+    /// Take a refresh of the position, worked out now or ahead of time
+    pub fn install(&mut self, refresh: Refresh) {
+        let selected = self.selected_piece();
+        self.moves = refresh.moves;
+        self.squares = refresh.squares;
+        self.legal_moves = refresh.legal_moves;
+        self.to_move = refresh.to_move;
+        self.checked_king = refresh.checked_king;
+        self.keep_selection(selected);
+    }
+
+    /// Offer only the moves in `allowed`, like the ones a server lists. `moves` keeps every
+    /// legal move so the peer's moves still match, and so does the game result.
+    pub fn offer_only(&mut self, allowed: &[Move]) {
+        let selected = self.selected_piece();
+        self.legal_moves = parse_moves(allowed, &self.squares, self.excluded);
+        self.keep_selection(selected);
+    }
+
+    /// The selected square and the piece shown on it
+    #[inline]
+    fn selected_piece(&self) -> Option<((usize, usize), Square)> {
+        self.selected_from
+            .map(|(row, col)| ((row, col), self.squares[row][col]))
+    }
+
+    /// Keep the selection only while the very piece that was selected is on its square for
+    /// the side to move, so no move is ever played for a piece nobody selected. The chooser
+    /// always closes, its moves may be gone.
+    fn keep_selection(&mut self, selected: Option<((usize, usize), Square)>) {
+        self.selected_to = None;
+        self.selected_from = selected.and_then(|((row, col), piece)| {
+            let kept = self.squares[row][col] == piece && piece.color() == Some(self.to_move);
+            if !kept {
+                self.dropped_selection = Some((row, col));
+            }
+            kept.then_some((row, col))
+        });
+    }
+
+    /// Maps a square as seen on screen to the square on the board
+    #[inline]
+    pub fn view_to_board(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        match self.flipped {
+            true => (7 - row, 7 - col),
+            false => (row, col),
+        }
+    }
+
+    /// Maps a square on the board to where it is drawn on screen
+    #[inline]
+    pub fn board_to_view(&self, cords: (usize, usize)) -> (usize, usize) {
+        // Rotating the board half a turn is its own inverse
+        self.view_to_board(cords)
+    }
+}
+
+/// How a choice of the move chooser is shown
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ChoiceLabel {
+    // The piece a promotion makes
+    Piece(Square),
+    // What kind of move it is, for the moves that get there without promoting
+    Text(&'static str),
+}
+
+/// How big the choices of the move chooser are
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub enum ChoiceSize {
+    // A square each, in the destination's file
+    #[default]
+    Square,
+    // Two squares wide for a finger or an unsteady hand, reaching into the file beside the
+    // destination's towards the middle of the board
+    Touch,
+}
+
+/// The squares a choice of the move chooser covers on the board, from the corner nearest a8
+/// to the one nearest h1
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Cells {
+    pub first: (usize, usize),
+    pub last: (usize, usize),
+}
+
+impl Cells {
+    #[inline]
+    pub fn contains(&self, (row, col): (usize, usize)) -> bool {
+        (self.first.0..=self.last.0).contains(&row) && (self.first.1..=self.last.1).contains(&col)
+    }
+}
+
+/// A move to choose, how it is shown and the squares it is drawn on
+pub type MoveChoice = (Move, ChoiceLabel, Cells);
+
+// Promotions to all four pieces leave room for a few moves of other kinds
+const MAX_CHOICES: usize = 8;
+
+/// What kind of move a move is that doesn't promote, to tell apart moves to the same square.
+/// Worked out from the move's own squares, the square it was filed under may differ.
+fn move_kind(squares: &[[Square; 8]; 8], mv: &Move) -> &'static str {
+    let Ok((from, to)) = parse_move(&mv.to_algebraic_notation()) else {
+        return "Move";
+    };
+    let piece = squares[from.0][from.1];
+    let target = squares[to.0][to.1];
+    match piece {
+        _ if is_castling(squares, from, to) => "Castle",
+        Square::Pawn(_) if from.1 != to.1 && target == Square::Empty => "En passant",
+        _ if target != Square::Empty => "Capture",
+        Square::King(_) => "King move",
+        _ => "Move",
+    }
+}
+
+/// The choices for a destination that several moves reach and the squares they are drawn on,
+/// promotions by their piece and any other moves by their kind.
+/// Drawing and clicking both go through this so they can't disagree.
+pub struct ChoiceLayout {
+    // Fixed size so an open chooser doesn't allocate every frame
+    pub choices: [Option<MoveChoice>; MAX_CHOICES],
+}
+
+impl ChoiceLayout {
+    pub fn new(board_repr: &BoardRepr) -> Option<Self> {
+        let from = board_repr.selected_from?;
+        let to = board_repr.selected_to?;
+        let color = board_repr.squares[from.0][from.1].color()?;
+        let moves = board_repr.legal_moves[from.0][from.1].get(&to)?;
+
+        let pieces = [
+            (PieceType::Queen, Square::Queen(color)),
+            (PieceType::Knight, Square::Knight(color)),
+            (PieceType::Rook, Square::Rook(color)),
+            (PieceType::Bishop, Square::Bishop(color)),
+        ];
+        // Promotions first in the usual order, then everything else as the engine listed it
+        let promotions = pieces.iter().filter_map(|(piece_type, piece)| {
+            moves
+                .iter()
+                .find(|mv| mv.get_promoted_type().as_ref() == Some(piece_type))
+                .map(|mv| (*mv, ChoiceLabel::Piece(*piece)))
+        });
+        let others = moves
+            .iter()
+            .filter(|mv| mv.get_promoted_type().is_none())
+            .map(|mv| (*mv, ChoiceLabel::Text(move_kind(&board_repr.squares, mv))));
+
+        let mut labeled = [None; MAX_CHOICES];
+        let mut count = 0usize;
+        for choice in promotions.chain(others).take(MAX_CHOICES) {
+            labeled[count] = Some(choice);
+            count += 1;
+        }
+
+        let mut choices = [None; MAX_CHOICES];
+        let placed = labeled
+            .iter()
+            .flatten()
+            .zip(stack_choices(to, count, board_repr.choice_size));
+        for (index, ((mv, label), cells)) in placed.enumerate() {
+            choices[index] = Some((*mv, *label, cells));
+        }
+        Some(Self { choices })
+    }
+
+    #[inline]
+    pub fn choice_at(&self, cords: (usize, usize)) -> Option<Move> {
+        self.choices
+            .iter()
+            .flatten()
+            .find(|(_, _, cells)| cells.contains(cords))
+            .map(|(mv, _, _)| *mv)
+    }
+}
+
+/// Where `count` choices of a move to `to` go, stacked from the destination towards the
+/// middle of the board and moved back as far as it takes to fit them all
+pub fn stack_choices(
+    to: (usize, usize),
+    count: usize,
+    size: ChoiceSize,
+) -> impl Iterator<Item = Cells> {
+    let count = count.min(MAX_CHOICES);
+    let (start, dir) = match to.0 >= 4 {
+        true => (to.0.max(count.saturating_sub(1)), -1isize),
+        false => (to.0.min(8 - count), 1isize),
+    };
+    let cols = match (size, to.1 >= 4) {
+        (ChoiceSize::Square, _) => (to.1, to.1),
+        (ChoiceSize::Touch, true) => (to.1 - 1, to.1),
+        (ChoiceSize::Touch, false) => (to.1, to.1 + 1),
+    };
+    (0..count).map(move |index| {
+        let row = (start as isize + dir * index as isize) as usize;
+        Cells {
+            first: (row, cols.0),
+            last: (row, cols.1),
+        }
+    })
+}
+
+/// What clicking a square of the board does
+pub enum Click {
+    // The new selected piece and the destination the chooser is open for
+    Select(Option<(usize, usize)>, Option<(usize, usize)>),
+    Play(Move),
+}
+
+/// The selection state machine, kept apart from the window so it can be checked on its own
+pub fn click_board(repr: &BoardRepr, clicked: (usize, usize)) -> Click {
+    // The chooser takes the click, anywhere but its choices cancels it
+    if repr.selected_to.is_some() {
+        return match ChoiceLayout::new(repr).and_then(|layout| layout.choice_at(clicked)) {
+            Some(mv) => Click::Play(mv),
+            None => Click::Select(repr.selected_from, None),
+        };
+    }
+
+    // A legal destination is a move even if one of our pieces is there, like a castling rook
+    if let Some(moves) = repr
+        .selected_from
+        .and_then(|(row, col)| repr.legal_moves[row][col].get(&clicked))
+    {
+        // Several moves to one square, promotions or not, open the chooser
+        if moves.len() > 1 {
+            return Click::Select(repr.selected_from, Some(clicked));
+        }
+        return Click::Play(moves[0]);
+    }
+
+    // Any of our pieces is selected instead, the selected one again lets go of it
+    let ours = repr.squares[clicked.0][clicked.1].color() == Some(repr.to_move);
+    match ours && repr.selected_from != Some(clicked) {
+        true => Click::Select(Some(clicked), None),
+        false => Click::Select(None, None),
+    }
+}
+
+/// A xorshift generator, the same sequence on every run of a seed
+pub(crate) struct Random(u64);
+
+impl Random {
+    #[inline]
+    pub(crate) fn new(seed: u64) -> Self {
+        // Xorshift stays at zero forever
+        Self(seed.max(1))
+    }
+
+    /// A number below `n`, or 0 if `n` is 0
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n.max(1) as u64) as usize
+    }
+
+    #[inline]
+    pub(crate) fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The standard position after the moves, given in algebraic notation like "e2e4"
+    pub(crate) fn board_after(moves: &[&str]) -> Board {
+        let mut board = Board::default();
+        for notation in moves {
+            let mv = board
+                .get_legal_moves()
+                .into_iter()
+                .find(|mv| mv.to_algebraic_notation().eq_ignore_ascii_case(notation))
+                .unwrap_or_else(|| panic!("{} isn't legal", notation));
+            board.play_move(mv).unwrap();
+        }
+        board
+    }
+
+    /// Click the squares in turn, selections are kept and the result of the last click returned
+    fn clicks(repr: &mut BoardRepr, squares: &[(usize, usize)]) -> Click {
+        let mut outcome = Click::Select(None, None);
+        for square in squares {
+            outcome = click_board(repr, *square);
+            if let Click::Select(from, to) = outcome {
+                repr.selected_from = from;
+                repr.selected_to = to;
+            }
+        }
+        outcome
+    }
+
+    fn labels(repr: &BoardRepr) -> Vec<ChoiceLabel> {
+        let layout = ChoiceLayout::new(repr).expect("the chooser is open");
+        layout
+            .choices
+            .iter()
+            .flatten()
+            .map(|(_, label, _)| *label)
+            .collect()
+    }
+
+    // The pawn on b7 can promote on a8 and c8 by capturing and on b8 by pushing
+    pub(crate) const PROMOTION_LINE: [&str; 8] = [
+        "e2e4", "d7d5", "e4d5", "c7c6", "d5c6", "g8f6", "c6b7", "b8d7",
+    ];
+    // The same for Black with the pawn on b2, after a waiting move by White
+    pub(crate) const BLACK_PROMOTION_LINE: [&str; 9] = [
+        "h2h3", "e7e5", "d2d4", "e5d4", "c2c3", "d4c3", "g1f3", "c3b2", "b1d2",
+    ];
+    // White can castle short, step the king to f1 or to e2
+    pub(crate) const CASTLING_LINE: [&str; 6] = ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6"];
+    // White can castle long, and Black can once White has moved
+    pub(crate) const LONG_CASTLING_LINE: [&str; 8] = [
+        "d2d4", "d7d5", "b1c3", "b8c6", "c1f4", "c8f5", "d1d2", "d8d7",
+    ];
+    // The pawn on e5 can take d6 en passant
+    pub(crate) const EN_PASSANT_LINE: [&str; 4] = ["e2e4", "a7a6", "e4e5", "d7d5"];
+
+    // Positions and whether they are dead for lack of material
+    const MATERIAL_POSITIONS: [(&str, bool); 8] = [
+        ("8/8/8/4k3/8/8/8/4K3 w - - 0 1", true),
+        ("8/8/8/4k3/8/8/8/2B1K3 w - - 0 1", true),
+        ("8/8/8/4k3/8/8/8/1N2K3 w - - 0 1", true),
+        // Both bishops on dark squares, then on different colors
+        ("5b2/8/8/4k3/8/8/8/2B1K3 w - - 0 1", true),
+        ("2b5/8/8/4k3/8/8/8/2B1K3 w - - 0 1", false),
+        ("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1", false),
+        ("8/8/8/4k3/8/8/8/1N2K1N1 w - - 0 1", false),
+        ("8/8/8/4k3/8/8/8/R3K3 w - - 0 1", false),
+    ];
+
+    #[test]
+    fn clicks_select_and_deselect_pieces() {
+        // Row 0 is the eighth rank
+        let (e2, e4, e5, d2, e7) = ((6, 4), (4, 4), (3, 4), (6, 3), (1, 4));
+        let fresh = || BoardRepr::new(&Board::default());
+        let selected = |outcome: Click| match outcome {
+            Click::Select(from, to) => (from, to),
+            Click::Play(mv) => panic!("{} was played", mv.to_algebraic_notation()),
+        };
+
+        assert_eq!(selected(clicks(&mut fresh(), &[e2])), (Some(e2), None));
+        assert!(matches!(clicks(&mut fresh(), &[e2, e4]), Click::Play(_)));
+        assert_eq!(selected(clicks(&mut fresh(), &[e2, d2])), (Some(d2), None));
+        assert_eq!(selected(clicks(&mut fresh(), &[e2, e2])), (None, None));
+        assert_eq!(selected(clicks(&mut fresh(), &[e2, e5])), (None, None));
+        // The opponent's pieces can't be picked up
+        assert_eq!(selected(clicks(&mut fresh(), &[e7])), (None, None));
+    }
+
+    #[test]
+    fn dead_positions_lack_mating_material() {
+        for (fen, dead) in MATERIAL_POSITIONS {
+            let squares = parse_fen(fen).unwrap();
+            assert_eq!(is_insufficient_material(&squares), dead, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn gone_kinds_of_piece_are_left_out_of_the_legend() {
+        let kinds = |fen| kinds_on_board(&parse_fen(fen).unwrap());
+        assert_eq!(
+            kinds("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            [true; 6]
+        );
+        // One side still having a kind keeps it, whichever side that is
+        assert_eq!(
+            kinds("4k3/8/8/8/8/8/4P3/R3K1n1 w - - 0 1"),
+            [true, false, true, false, true, true]
+        );
+        assert_eq!(
+            kinds("4k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+            [true, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn promotions_open_the_piece_chooser() {
+        let mut repr = BoardRepr::new(&board_after(&PROMOTION_LINE));
+        let (b7, a8) = ((1, 1), (0, 0));
+        assert!(matches!(
+            clicks(&mut repr, &[b7, a8]),
+            Click::Select(Some(_), Some(_))
+        ));
+        assert!(
+            labels(&repr)
+                == [
+                    ChoiceLabel::Piece(Square::Queen(Color::White)),
+                    ChoiceLabel::Piece(Square::Knight(Color::White)),
+                    ChoiceLabel::Piece(Square::Rook(Color::White)),
+                    ChoiceLabel::Piece(Square::Bishop(Color::White)),
+                ]
+        );
+        // The queen is on the promotion square itself
+        match clicks(&mut repr, &[a8]) {
+            Click::Play(mv) => assert_eq!(mv.to_algebraic_notation().to_lowercase(), "b7a8q"),
+            Click::Select(..) => panic!("the queen wasn't played"),
+        }
+    }
+
+    #[test]
+    fn other_moves_to_one_square_are_told_apart_by_kind() {
+        let mut repr = BoardRepr::new(&board_after(&CASTLING_LINE));
+        let (e1, f1, g1) = ((7, 4), (7, 5), (7, 6));
+        // Put the plain king move in the castling move's bucket, as an engine could encode it
+        let king_move = repr.legal_moves[e1.0][e1.1][&f1][0];
+        repr.legal_moves[e1.0][e1.1]
+            .get_mut(&g1)
+            .expect("castling is legal")
+            .push(king_move);
+
+        assert!(matches!(
+            clicks(&mut repr, &[e1, g1]),
+            Click::Select(Some(_), Some(_))
+        ));
+        // Each is labeled by its own squares, neither has a promoted type
+        assert!(labels(&repr) == [ChoiceLabel::Text("Castle"), ChoiceLabel::Text("King move")]);
+        let layout = ChoiceLayout::new(&repr).unwrap();
+        let squares: Vec<_> = layout.choices.iter().flatten().map(|c| c.2.first).collect();
+        assert_eq!(squares, [g1, (6, 6)]);
+        match clicks(&mut repr, &[(6, 6)]) {
+            Click::Play(mv) => assert_eq!(mv.to_algebraic_notation(), "e1f1"),
+            Click::Select(..) => panic!("the second choice wasn't played"),
+        }
+    }
+
+    #[test]
+    fn castling_is_told_apart_the_same_way_everywhere() {
+        let squares = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let e1 = (7, 4);
+        // Two files over or onto the rook, both ways, and nothing taken by either
+        for (to, king_to, rook) in [
+            ((7, 6), (7, 6), ((7, 7), (7, 5))),
+            ((7, 7), (7, 6), ((7, 7), (7, 5))),
+            ((7, 2), (7, 2), ((7, 0), (7, 3))),
+            ((7, 0), (7, 2), ((7, 0), (7, 3))),
+        ] {
+            assert!(is_castling(&squares, e1, to), "{:?}", to);
+            assert!(captured_piece(&squares, e1, to).is_none(), "{:?}", to);
+            assert!(!is_capture(&squares, e1, to), "{:?}", to);
+            assert_eq!(castling_squares(e1, to), (king_to, rook.0, rook.1));
+        }
+        assert!(!is_castling(&squares, e1, (7, 5)));
+        assert!(!is_castling(&squares, e1, (6, 4)));
+
+        // Taking the other side's rook is a capture, whatever the king is next to
+        let squares = parse_fen("4k3/8/8/8/8/8/8/4K2r w - - 0 1").unwrap();
+        let (e1, h1) = ((7, 4), (7, 7));
+        let mut king_takes = squares;
+        king_takes[e1.0][e1.1] = Square::Empty;
+        king_takes[7][6] = Square::King(Color::White);
+        assert!(!is_castling(&king_takes, (7, 6), h1));
+        assert!(captured_piece(&king_takes, (7, 6), h1) == Some(Square::Rook(Color::Black)));
+        // Nor does a rook two files from the king castle
+        assert!(!is_castling(&squares, h1, (7, 5)));
+    }
+
+    #[test]
+    fn choices_stay_on_the_board_apart_in_every_layout() {
+        use crate::layout::Area;
+        use crate::scene::{compose, BoardScene, Overlay, Recording};
+
+        let board = Area::new(0.0, 0.0, 400.0, 400.0);
+        let squares = [[Square::Empty; 8]; 8];
+        let pieces = [
+            Square::Queen(Color::White),
+            Square::Knight(Color::White),
+            Square::Rook(Color::White),
+            Square::Bishop(Color::White),
+        ];
+        for rank in [0, 7, 3, 4] {
+            for file in 0..8 {
+                for size in [ChoiceSize::Square, ChoiceSize::Touch] {
+                    for flipped in [false, true] {
+                        // Every promotion, fewer when some aren't legal, and the most there are
+                        for count in [1, 2, 3, 4, MAX_CHOICES] {
+                            let cells: Vec<Cells> =
+                                stack_choices((rank, file), count, size).collect();
+                            assert_eq!(cells.len(), count);
+                            // The first is drawn where the move goes
+                            assert!(count == MAX_CHOICES || cells[0].contains((rank, file)));
+
+                            let mut scene = BoardScene::new(board, &squares);
+                            scene.flipped = flipped;
+                            let areas: Vec<Area> =
+                                cells.iter().map(|cells| scene.cells(*cells)).collect();
+                            for (index, area) in areas.iter().enumerate() {
+                                assert!(
+                                    area.x >= 0.0
+                                        && area.y >= 0.0
+                                        && area.x + area.w <= 400.0
+                                        && area.y + area.h <= 400.0,
+                                    "{:?} off the board for {:?}",
+                                    area,
+                                    (rank, file, size, flipped, count)
+                                );
+                                for other in &areas[index + 1..] {
+                                    assert!(!area.overlaps(other), "{:?} on {:?}", area, other);
+                                }
+                                let expected = match size {
+                                    ChoiceSize::Square => 50.0,
+                                    ChoiceSize::Touch => 100.0,
+                                };
+                                assert_eq!((area.w, area.h), (expected, 50.0));
+                            }
+
+                            // The pieces are drawn on their own choices, in the same order
+                            let labeled: Vec<(ChoiceLabel, Cells)> = pieces
+                                .iter()
+                                .map(|piece| ChoiceLabel::Piece(*piece))
+                                .zip(cells.iter().copied())
+                                .collect();
+                            scene.overlay = Overlay::Choices(&labeled);
+                            let mut recording = Recording::default();
+                            compose(&scene, &mut recording);
+                            let drawn: Vec<&String> = recording
+                                .lines
+                                .iter()
+                                .filter(|line| line.contains(r#""paint":"piece""#))
+                                .collect();
+                            assert_eq!(drawn.len(), labeled.len());
+                            for (line, (_, cells)) in drawn.iter().zip(&labeled) {
+                                let area = scene.cells(*cells);
+                                let centre = area.x + area.w / 2.0 - 25.0;
+                                assert!(
+                                    line.contains(&format!(r#""x":{:.2}"#, centre)),
+                                    "{}",
+                                    line
+                                );
+                                assert!(
+                                    line.contains(&format!(r#""y":{:.2}"#, area.y)),
+                                    "{}",
+                                    line
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_chooser_takes_no_clicks_beside_its_choices() {
+        // Promoting on a8 used to take clicks on the b file too, anywhere in the top half
+        let on_a8: Vec<Cells> = stack_choices((0, 0), 4, ChoiceSize::Square).collect();
+        for row in 0..8 {
+            for col in 0..8 {
+                let taken = on_a8.iter().any(|cells| cells.contains((row, col)));
+                assert_eq!(taken, col == 0 && row < 4, "{:?}", (row, col));
+            }
+        }
+        // The touch sized ones reach one file over and no further
+        let on_h1: Vec<Cells> = stack_choices((7, 7), 4, ChoiceSize::Touch).collect();
+        for row in 0..8 {
+            for col in 0..8 {
+                let taken = on_h1.iter().any(|cells| cells.contains((row, col)));
+                assert_eq!(taken, col >= 6 && row >= 4, "{:?}", (row, col));
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_buckets_list_promotions_first() {
+        let mut repr = BoardRepr::new(&board_after(&PROMOTION_LINE));
+        let (b7, a8, b8) = ((1, 1), (0, 0), (0, 1));
+        let push = repr.legal_moves[b7.0][b7.1][&b8][0];
+        assert!(push.get_promoted_type().is_some());
+        // A move that doesn't promote, taken from another piece
+        let (e1, e2) = ((7, 4), (6, 4));
+        let step = repr.legal_moves[e1.0][e1.1][&e2][0];
+        repr.legal_moves[b7.0][b7.1]
+            .get_mut(&a8)
+            .unwrap()
+            .push(step);
+
+        clicks(&mut repr, &[b7, a8]);
+        let labels = labels(&repr);
+        assert_eq!(labels.len(), 5);
+        assert!(labels[..4]
+            .iter()
+            .all(|label| matches!(label, ChoiceLabel::Piece(_))));
+        assert!(labels[4] == ChoiceLabel::Text("King move"));
+    }
+
+    #[test]
+    fn a_single_move_is_played_right_away() {
+        let mut repr = BoardRepr::new(&Board::default());
+        match clicks(&mut repr, &[(6, 4), (4, 4)]) {
+            Click::Play(mv) => assert_eq!(mv.to_algebraic_notation(), "e2e4"),
+            Click::Select(..) => panic!("e2e4 opened the chooser"),
+        }
+    }
+
+    #[test]
+    fn a_refresh_never_lets_another_piece_move_for_the_selected_one() {
+        let mut random = Random::new(0x2545_f491_4f6c_dd1d);
+        // Positions a full-board replacement jumps to, like a server repairing a desync
+        let mut seen = vec![Board::default().to_fen()];
+        for _ in 0..300 {
+            let mut board = Board::default();
+            for _ in 0..random.below(40) {
+                let moves = board.get_legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                board.play_move(moves[random.below(moves.len())]).unwrap();
+                seen.push(board.to_fen());
+            }
+            let mut repr = BoardRepr::new(&board);
+            let ours: Vec<(usize, usize)> = (0..8)
+                .flat_map(|row| (0..8).map(move |col| (row, col)))
+                .filter(|&(row, col)| repr.squares[row][col].color() == Some(repr.to_move))
+                .collect();
+            let selected = ours[random.below(ours.len())];
+            clicks(&mut repr, &[selected]);
+            assert_eq!(repr.selected_from, Some(selected));
+            let shown = repr.squares[selected.0][selected.1];
+
+            // The game goes on, or the board is replaced wholesale
+            let moves = board.get_legal_moves();
+            match random.below(3) {
+                0 if !moves.is_empty() => {
+                    board.play_move(moves[random.below(moves.len())]).unwrap();
+                }
+                _ => board = board_from_fen(&seen[random.below(seen.len())]).unwrap(),
+            }
+            repr.refresh(&board);
+            // A server listing only some of the moves
+            if random.below(2) == 0 {
+                let allowed = repr.moves[..random.below(repr.moves.len() + 1)].to_vec();
+                repr.offer_only(&allowed);
+            }
+
+            match repr.selected_from {
+                Some(square) => {
+                    assert_eq!(square, selected);
+                    assert!(repr.squares[square.0][square.1] == shown);
+                    assert!(repr.dropped_selection.is_none());
+                }
+                None => assert_eq!(repr.dropped_selection, Some(selected)),
+            }
+            // No click plays a move for any piece but the one shown when it was selected
+            for row in 0..8 {
+                for col in 0..8 {
+                    if let Click::Play(mv) = click_board(&repr, (row, col)) {
+                        let (from, _) = parse_move(&mv.to_algebraic_notation()).unwrap();
+                        assert_eq!(Some(from), repr.selected_from);
+                        assert!(repr.squares[from.0][from.1] == shown);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_piece_stays_selected_through_a_refresh() {
+        let board = board_after(&["e2e4", "e7e5"]);
+        let mut repr = BoardRepr::new(&board);
+        let knight = (7, 6);
+        clicks(&mut repr, &[knight]);
+        // Like the server sending the board we already have
+        repr.refresh(&board);
+        assert_eq!(repr.selected_from, Some(knight));
+        repr.offer_only(&[]);
+        assert_eq!(repr.selected_from, Some(knight));
+        assert_eq!(repr.dropped_selection, None);
+
+        // Another position with a black piece on g1
+        let replaced =
+            board_from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBnR w KQkq - 0 2").unwrap();
+        repr.refresh(&replaced);
+        assert_eq!(repr.selected_from, None);
+        assert_eq!(repr.dropped_selection, Some(knight));
+    }
+}
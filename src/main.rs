@@ -1,167 +1,595 @@
-mod network;
+#[cfg(feature = "alloc-tracking")]
+mod alloc_tracking;
+mod browser;
+mod interop_view;
+mod menu;
+mod picker;
+mod sandbox_view;
+mod sprites;
+mod theme;
+mod trainer_view;
+mod wizard;
 
-use crate::network::Handshake::ClientToServer;
-use crate::network::{
-    internal_to_network_board, internal_to_network_move, internal_to_network_moves,
-    internal_to_server_handshake, Network,
+use crate::browser::{Browser, BrowserAction};
+use crate::interop_view::{InteropAction, InteropView};
+use crate::menu::{Menu, MenuChoice};
+use crate::picker::Picker;
+use crate::sandbox_view::{SandboxAction, SandboxView};
+use crate::sprites::Sprites;
+use crate::theme::Theme;
+use crate::trainer_view::{TrainerAction, TrainerView};
+use crate::wizard::{Wizard, WizardAction};
+use chess_gui::accessibility::{self, AccessibleInput, Dwell, CLICK_SLOP};
+use chess_gui::autosave::{self, Autosave, SavedGame};
+use chess_gui::bookmarks;
+use chess_gui::bundle::{self, Artifact};
+use chess_gui::clipboard::{self, Paste};
+use chess_gui::clock::{format_time, Clock, Countdown, TimeControl};
+use chess_gui::config::{self, ComputerConfig, Config, Opponent, Seating};
+use chess_gui::connection_book::{self, Book};
+use chess_gui::effects::{self, Application, Cue, Recorded, Slide};
+use chess_gui::engine::{Analysis, Engine, Line};
+use chess_gui::engine_match::{self, Match, MatchConfig, MatchEvent, Tally};
+use chess_gui::eval_graph::{self, Judgement};
+use chess_gui::game_list::ListedGame;
+use chess_gui::glyphs::{self, Glyph, GlyphStyle};
+use chess_gui::hook::{self, Hook};
+use chess_gui::identity::{self, Identity};
+use chess_gui::interop::{Incident, Ledger, Recorder};
+use chess_gui::latency::{RoundTrip, SlideCurve};
+use chess_gui::layout::{self, Area, LayoutMode, LayoutPlan, ScaleWatch, LEGEND_FRACTION};
+use chess_gui::metrics;
+use chess_gui::mouse::{Gestures, MouseAction, MouseBindings, MouseChange, Region};
+use chess_gui::negotiation::{self, Outcome};
+use chess_gui::network::{
+    self, internal_to_network_move, internal_to_server_handshake, network_to_internal_move,
+    Network, NetworkEvent, SendError,
+};
+use chess_gui::notation::{self, Language, Notation};
+use chess_gui::ordering;
+use chess_gui::pgn::{self, PgnHeaders, SaveIndex, SaveTarget};
+use chess_gui::piece_style::{PieceStyles, StyleChange};
+use chess_gui::pins::{self, Pin};
+use chess_gui::practice::Fork;
+use chess_gui::profiles::{self, ProfilePaths, ProfileSettings, Profiles};
+use chess_gui::recovery::{self, Recovery, Response};
+use chess_gui::sandbox::Sandbox;
+use chess_gui::scene::{self, BoardScene, Command, Overlay, Paint, Sink, Sliding};
+use chess_gui::session::{Rehandshake, Session};
+use chess_gui::speculation::Speculation;
+use chess_gui::splitter::{self, Press, Splitter};
+use chess_gui::stall::{self, Stall, StallWatch, DEFAULT_GRACE};
+use chess_gui::strikes::{Strike, Strikes, Verdict};
+use chess_gui::text_cache::TextCache;
+use chess_gui::toasts::Toasts;
+use chess_gui::traps::{self, Trap, TrapNote};
+use chess_gui::tty_mirror::{self, TtyMirror, TtyOrientation};
+use chess_gui::turn::{self, OutOfTurn, Premove, Seat, Shake};
+use chess_gui::updates::{self, Curl, Release, UpdateSettings, UpdateSwitch};
+use chess_gui::validate;
+use chess_gui::watchdog;
+use chess_gui::{
+    board_from_fen, castling_squares, color_name, ease_out, is_castling, kinds_on_board,
+    material_balance, material_value, opposite_color, parse_move, selftest, Cells, ChoiceLabel,
+    ChoiceLayout, ChoiceSize, ExcludedMoves, GameState, MoveParseError, Refresh, Square,
+    PIECE_GUIDE,
 };
-use chess_network_protocol;
 use chess_network_protocol::ServerToClient;
+use ggez::audio::{SoundData, SoundSource, Source};
 use ggez::conf::{FullscreenType, NumSamples, WindowMode, WindowSetup};
-use ggez::event::EventHandler;
-use ggez::graphics::{Canvas, DrawMode, Image, Mesh, Rect, Transform};
+use ggez::graphics::{Canvas, DrawMode, Drawable, Image, ImageFormat, Mesh, Rect, Text};
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
 use ggez::winit::dpi::LogicalSize;
-use ggez::winit::event::VirtualKeyCode::B;
-use ggez::{event, graphics, Context, GameResult};
-use jonathan_hallstrom_chess::{Board, Color, Move};
+use ggez::winit::window::CursorIcon;
+use ggez::{event, graphics, Context, GameError, GameResult};
+use jonathan_hallstrom_chess::{Color, Move, PieceType};
 use mint::{Point2, Vector2};
+use std::cell::RefCell;
 use std::cmp::min;
-use std::collections::HashMap;
 use std::io::prelude::*;
-use std::net::{TcpListener, TcpStream};
-use std::os::macos::raw::stat;
-
-#[macro_use]
-extern crate lazy_static;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::Duration;
 
-static PIECES_IMAGE_BYTES: &'static [u8] = include_bytes!("Pieces.png");
+#[cfg(feature = "alloc-tracking")]
+#[global_allocator]
+static ALLOCATOR: alloc_tracking::CountingAllocator = alloc_tracking::CountingAllocator;
 
-lazy_static! {
-    static ref CHESSBOARD_MESH: graphics::MeshBuilder = {
-        let mut mesh = graphics::MeshBuilder::new();
-        for row in 0..8usize {
-            for col in 0..8usize {
-                mesh.rectangle(
-                    DrawMode::fill(),
-                    graphics::Rect::new(col as f32 / 8.0, row as f32 / 8.0, 1.0 / 8.0, 1.0 / 8.0),
-                    match (row + col) % 2 == 0 {
-                        true => WHITE_SQUARE_COLOR,
-                        false => BLACK_SQUARE_COLOR,
-                    },
-                )
-                .unwrap();
-            }
-        }
-        mesh
-    };
-}
+static MOVE_SOUND_BYTES: &[u8] = include_bytes!("sounds/move.wav");
+static CAPTURE_SOUND_BYTES: &[u8] = include_bytes!("sounds/capture.wav");
+static CHECK_SOUND_BYTES: &[u8] = include_bytes!("sounds/check.wav");
+static GAME_OVER_SOUND_BYTES: &[u8] = include_bytes!("sounds/game_over.wav");
+// The move sound played this much quieter ticks while stepping through a game
+const TICK_VOLUME: f32 = 0.25;
 
+const WINDOW_TITLE: &str = "Arvid Jonassons Chess GUI";
 const COL_COUNT_F32: f32 = 8.0;
 const ROW_COUNT_F32: f32 = 8.0;
-const HIGHLIGHT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.5, 0.0, 0.75);
+// Plies the engine looks ahead for every position of the evaluation graph
+const REVIEW_DEPTH: u32 = 2;
+// Coordinate labels are laid out at this size and scaled with the board
+const LABEL_FONT_SIZE: f32 = 64.0;
+const LEGEND_DURATION: Duration = Duration::from_millis(200);
+// Captured pieces overlap by this much of their size so a whole set fits in the bar
+const CAPTURED_PIECE_SPACING: f32 = 0.6;
 const DARK_FILM_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.75);
-const BLACK_SQUARE_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
-const WHITE_SQUARE_COLOR: graphics::Color = graphics::Color::new(1.0, 0.9, 0.9, 1.0);
+const TEXT_COLOR: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 1.0);
+const ERROR_TEXT_COLOR: graphics::Color = graphics::Color::new(0.7, 0.0, 0.0, 1.0);
+const HISTORY_TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const WAITING_PIECE_COLOR: graphics::Color = graphics::Color::new(0.6, 0.6, 0.6, 1.0);
+// Tints the selection mark of a move picked up while waiting, so it isn't taken for one
+const PREMOVE_COLOR: graphics::Color = graphics::Color::new(0.45, 0.6, 1.0, 0.8);
+// Part of the window height the move entry bar covers at the bottom
+const MOVE_ENTRY_BAR_FRACTION: f32 = 0.05;
+const MOVE_ENTRY_COLOR: graphics::Color = graphics::Color::new(0.85, 0.85, 0.85, 0.95);
+const MOVE_ENTRY_REJECTED_COLOR: graphics::Color = graphics::Color::new(1.0, 0.55, 0.55, 0.95);
+const SWAP_BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const LEGEND_COLOR: graphics::Color = graphics::Color::new(0.9, 0.9, 0.85, 1.0);
+// Kinds of piece that are gone from the board fade out of the legend
+const GONE_PIECE_COLOR: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 0.3);
+const GONE_TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.3);
+const GRAPH_BACKGROUND_COLOR: graphics::Color = graphics::Color::new(0.55, 0.55, 0.55, 1.0);
+const GRAPH_WHITE_COLOR: graphics::Color = graphics::Color::new(0.95, 0.95, 0.95, 1.0);
+const GRAPH_BLACK_COLOR: graphics::Color = graphics::Color::new(0.2, 0.2, 0.2, 1.0);
+const GRAPH_PLY_COLOR: graphics::Color = graphics::Color::new(0.2, 0.45, 0.9, 1.0);
+const INACCURACY_COLOR: graphics::Color = graphics::Color::new(0.9, 0.8, 0.1, 1.0);
+const MISTAKE_COLOR: graphics::Color = graphics::Color::new(0.95, 0.5, 0.1, 1.0);
+const BLUNDER_COLOR: graphics::Color = graphics::Color::new(0.85, 0.1, 0.1, 1.0);
+const DWELL_COLOR: graphics::Color = graphics::Color::new(0.2, 0.45, 0.9, 0.9);
+const PIN_COLOR: graphics::Color = graphics::Color::new(0.85, 0.1, 0.1, 0.8);
+const RELATIVE_PIN_COLOR: graphics::Color = graphics::Color::new(0.95, 0.5, 0.1, 0.45);
+// How big the circle around a resting pointer starts, it shrinks to nothing as it clicks
+const DWELL_RADIUS: f32 = 24.0;
+// How long a moved piece takes to slide to its new square
+const ANIMATION_DURATION: Duration = Duration::from_millis(150);
+// How long a square flashes after the piece selected on it was replaced
+const SELECTION_FLASH_DURATION: Duration = Duration::from_millis(400);
+// How long the glyph of a reviewed move shows over the board on getting to its position
+const GLYPH_FLASH_DURATION: Duration = Duration::from_millis(900);
+// How long the result of a practice game shows before going back to the game it came from
+const PRACTICE_RETURN_DELAY: Duration = Duration::from_secs(3);
+// How long something the hook did shows over the board
+const HOOK_TOAST_DURATION: Duration = Duration::from_secs(4);
+// How long a change of the other side since the last game there shows over the board
+const BOOK_TOAST_DURATION: Duration = Duration::from_secs(4);
+// How long the server shows that the client moved when it wasn't its turn
+const TURN_TOAST_DURATION: Duration = Duration::from_secs(3);
+// How long the first click out of turn explains the dimmed pieces
+const WAITING_TOAST_DURATION: Duration = Duration::from_secs(3);
+// How long where the bug report bundle went shows over the board
+const REPORT_TOAST_DURATION: Duration = Duration::from_secs(8);
+// Plies an unfinished game needs before Ctrl+N asks before starting it over
+const RESTART_CONFIRM_PLIES: usize = 5;
+// How long each move and each result of a watched engine match stays before the next one
+const WATCH_MOVE_DELAY: Duration = Duration::from_millis(600);
+const WATCH_GAME_DELAY: Duration = Duration::from_secs(3);
 
-#[derive(Eq, PartialEq, Copy, Clone, Hash)]
-enum Square {
-    Empty,
-    Pawn(Color),
-    Rook(Color),
-    Bishop(Color),
-    Knight(Color),
-    King(Color),
-    Queen(Color),
+/// Where a line of text goes in its area and how big it is, both relative to the area's height
+#[derive(Copy, Clone)]
+struct TextLine {
+    y: f32,
+    scale: f32,
+    color: graphics::Color,
 }
 
-impl Square {
-    #[inline]
-    fn color(&self) -> Option<Color> {
-        match self {
-            Square::Empty => None,
-            Square::Pawn(color)
-            | Square::Rook(color)
-            | Square::Bishop(color)
-            | Square::Knight(color)
-            | Square::King(color)
-            | Square::Queen(color) => Some(*color),
-        }
+const RESULT_LINE: TextLine = TextLine {
+    y: 0.5,
+    scale: 1.0 / 12.0,
+    color: TEXT_COLOR,
+};
+// Hints about the board at its top edge, warnings and errors at the bottom
+const HINT_LINE: TextLine = TextLine {
+    y: 0.05,
+    scale: 1.0 / 32.0,
+    color: HISTORY_TEXT_COLOR,
+};
+// A move kind in the chooser, sized to fit its square
+const CHOICE_LINE: TextLine = TextLine {
+    y: 0.5,
+    scale: 0.18,
+    color: TEXT_COLOR,
+};
+// Faded as it goes, see GLYPH_FLASH_DURATION
+const GLYPH_LINE: TextLine = TextLine {
+    y: 0.5,
+    scale: 0.3,
+    color: graphics::Color::new(1.0, 1.0, 1.0, 0.6),
+};
+const WARNING_LINE: TextLine = TextLine {
+    y: 0.9,
+    scale: 1.0 / 40.0,
+    color: ERROR_TEXT_COLOR,
+};
+// What the frames allocate with the alloc-tracking feature, under the hints
+#[cfg(feature = "alloc-tracking")]
+const DEBUG_LINE: TextLine = TextLine {
+    y: 0.1,
+    scale: 1.0 / 48.0,
+    color: HISTORY_TEXT_COLOR,
+};
+const ERROR_LINE: TextLine = TextLine {
+    y: 0.95,
+    scale: 1.0 / 32.0,
+    color: ERROR_TEXT_COLOR,
+};
+
+/// The part of the window an area of the layout plan is
+#[inline]
+fn rect(area: Area) -> Rect {
+    Rect::new(area.x, area.y, area.w, area.h)
+}
+
+/// The glyph a digit key sets on the reviewed move, Some(None) for 0 which clears it
+#[inline]
+fn glyph_key(keycode: KeyCode) -> Option<Option<Glyph>> {
+    match keycode {
+        KeyCode::Key0 => Some(None),
+        KeyCode::Key1 => Some(Some(Glyph::Good)),
+        KeyCode::Key2 => Some(Some(Glyph::Mistake)),
+        KeyCode::Key3 => Some(Some(Glyph::Brilliant)),
+        KeyCode::Key4 => Some(Some(Glyph::Blunder)),
+        KeyCode::Key5 => Some(Some(Glyph::Interesting)),
+        KeyCode::Key6 => Some(Some(Glyph::Dubious)),
+        _ => None,
+    }
+}
+
+/// The move entry bar across the bottom of `window`
+#[inline]
+fn entry_bar(window: Rect) -> Rect {
+    let height = window.h * MOVE_ENTRY_BAR_FRACTION;
+    Rect::new(window.x, window.y + window.h - height, window.w, height)
+}
+
+/// The color a random handle is shown in
+#[inline]
+fn avatar_color(identity: &Identity) -> graphics::Color {
+    let [r, g, b] = identity.color;
+    graphics::Color::new(r, g, b, 1.0)
+}
+
+#[inline]
+fn judgement_color(judgement: Judgement) -> graphics::Color {
+    match judgement {
+        Judgement::Inaccuracy => INACCURACY_COLOR,
+        Judgement::Mistake => MISTAKE_COLOR,
+        Judgement::Blunder => BLUNDER_COLOR,
     }
 }
 
-fn parse_fen(fen: &str) -> [[Square; 8]; 8] {
-    let mut board = [[Square::Empty; 8]; 8];
-    let mut iter = fen.chars();
+/// Draws what the board scene sends with the meshes, sprites and text of a render
+struct CanvasSink<'a> {
+    render: &'a Render,
+    theme: &'a Theme,
+    ctx: &'a Context,
+    canvas: &'a mut Canvas,
+    board: Rect,
+    // The first mesh that couldn't be built, everything after it is still drawn
+    result: GameResult,
+}
 
-    let mut row = 0usize;
-    while row < 8 {
-        let mut col = 0usize;
-        while col < 8 {
-            let c = iter.next().unwrap();
+impl CanvasSink<'_> {
+    /// The meshes of marks are a square's size in units of the board
+    #[inline]
+    fn mark(&mut self, mesh: &Mesh, area: Area, color: graphics::Color) {
+        self.canvas.draw(
+            mesh,
+            graphics::DrawParam::default()
+                .dest_rect(Rect::new(area.x, area.y, self.board.w, self.board.h))
+                .color(color),
+        );
+    }
 
-            assert_ne!(c, '/');
-            assert!(c.is_alphanumeric());
+    fn label(&mut self, label: &Text, area: Area, on_light: bool, bottom_right: bool) {
+        // A fifth of a square whatever the window size
+        let scale = area.w * 0.2 / LABEL_FONT_SIZE;
+        let margin = area.w * 0.05;
+        let dest = match bottom_right {
+            true => {
+                let width = label.dimensions(self.ctx).map_or(0.0, |dims| dims.w) * scale;
+                Point2 {
+                    x: area.x + area.w - width - margin,
+                    y: area.y + area.h - LABEL_FONT_SIZE * scale - margin,
+                }
+            }
+            false => Point2 {
+                x: area.x + margin,
+                y: area.y + margin,
+            },
+        };
+        let theme = self.theme;
+        self.canvas.draw(
+            label,
+            graphics::DrawParam::default()
+                .dest(dest)
+                .scale(Vector2 { x: scale, y: scale })
+                .color(match on_light {
+                    true => theme.black_square,
+                    false => theme.white_square,
+                }),
+        );
+    }
+}
 
-            if c.is_numeric() {
-                col += c.to_digit(10).unwrap() as usize;
-            } else {
-                let color = match c.is_uppercase() {
-                    true => Color::White,
-                    false => Color::Black,
-                };
-                board[row][col] = match c.to_lowercase().last().unwrap() {
-                    'p' => Square::Pawn(color),
-                    'b' => Square::Bishop(color),
-                    'r' => Square::Rook(color),
-                    'n' => Square::Knight(color),
-                    'q' => Square::Queen(color),
-                    _ => Square::King(color),
+impl Sink for CanvasSink<'_> {
+    fn paint(&mut self, Command { paint, area, .. }: Command<'_>) {
+        let render = self.render;
+        let pin_color = |absolute: bool| match absolute {
+            true => PIN_COLOR,
+            false => RELATIVE_PIN_COLOR,
+        };
+        match paint {
+            Paint::Squares => self.canvas.draw(
+                &render.chessboard_mesh,
+                graphics::DrawParam::default()
+                    .dest(Point2 {
+                        x: area.x,
+                        y: area.y,
+                    })
+                    .scale(Vector2 {
+                        x: area.w,
+                        y: area.h,
+                    }),
+            ),
+            Paint::FileLabel { file, on_light } => {
+                self.label(&render.file_labels[file], area, on_light, true)
+            }
+            Paint::RankLabel { rank, on_light } => {
+                self.label(&render.rank_labels[rank], area, on_light, false)
+            }
+            Paint::Check => self.mark(&render.check_mesh, area, graphics::Color::WHITE),
+            Paint::Flash { alpha } => self.mark(
+                &render.check_mesh,
+                area,
+                graphics::Color::new(1.0, 1.0, 1.0, alpha),
+            ),
+            Paint::PinLine { absolute } => {
+                let ends = [
+                    Point2 {
+                        x: area.x,
+                        y: area.y,
+                    },
+                    Point2 {
+                        x: area.x + area.w,
+                        y: area.y + area.h,
+                    },
+                ];
+                let width = self.board.w / COL_COUNT_F32 * 0.04;
+                match Mesh::new_line(self.ctx, &ends, width, pin_color(absolute)) {
+                    Ok(mesh) => self.canvas.draw(&mesh, graphics::DrawParam::default()),
+                    Err(err) if self.result.is_ok() => self.result = Err(err),
+                    Err(_) => {}
+                }
+            }
+            Paint::PinMark { absolute } => {
+                let centre = Point2 {
+                    x: area.x + area.w / 2.0,
+                    y: area.y + area.h / 2.0,
                 };
-                col += 1;
+                let mesh = Mesh::new_circle(
+                    self.ctx,
+                    DrawMode::fill(),
+                    centre,
+                    area.w / 2.0,
+                    0.5,
+                    pin_color(absolute),
+                );
+                match mesh {
+                    Ok(mesh) => self.canvas.draw(&mesh, graphics::DrawParam::default()),
+                    Err(err) if self.result.is_ok() => self.result = Err(err),
+                    Err(_) => {}
+                }
             }
+            Paint::Piece { piece, dimmed } => render.pieces.draw(
+                self.canvas,
+                &piece,
+                rect(area),
+                match dimmed {
+                    true => WAITING_PIECE_COLOR,
+                    false => graphics::Color::WHITE,
+                },
+            ),
+            Paint::Selected => self.mark(&render.selected_piece_mesh, area, graphics::Color::WHITE),
+            Paint::Premove => self.mark(&render.selected_piece_mesh, area, PREMOVE_COLOR),
+            Paint::LegalMove => {
+                self.mark(&render.available_move_mesh, area, graphics::Color::WHITE)
+            }
+            Paint::Film => render.draw_film(self.canvas, rect(area)),
+            Paint::Text { text, line } => render.draw_text(
+                self.ctx,
+                self.canvas,
+                rect(area),
+                text,
+                match line {
+                    scene::Line::Result => RESULT_LINE,
+                    scene::Line::Hint => HINT_LINE,
+                    scene::Line::Choice => CHOICE_LINE,
+                },
+            ),
         }
-        {
-            let c = iter.next().unwrap();
-            assert!((c == '/' && row < 7) || (c == ' ' && row == 7));
-        }
-        row += 1;
     }
-    board
 }
 
-#[inline]
-fn to_cordinate(c: char) -> usize {
-    if c >= 'a' && c <= 'h' {
-        return c as usize - 'a' as usize;
+/// A piece sliding from one square to another after a move, drawn on top of the board
+#[derive(Copy, Clone)]
+struct Animation {
+    piece: Square,
+    from: (usize, usize),
+    to: (usize, usize),
+    // Time since the start of the program when the move was applied
+    start_time: Duration,
+    // ANIMATION_DURATION, shorter for a move that was already a while on the way
+    duration: Duration,
+}
+
+impl Animation {
+    /// How far along the slide is, from 0 to 1, eased so the piece settles gently
+    #[inline]
+    fn progress(&self, now: Duration) -> f32 {
+        let t = (now.saturating_sub(self.start_time).as_secs_f32() / self.duration.as_secs_f32())
+            .min(1.0);
+        ease_out(t)
+    }
+
+    #[inline]
+    fn is_done(&self, now: Duration) -> bool {
+        now.saturating_sub(self.start_time) >= self.duration
     }
-    7 - (c as usize - '1' as usize)
 }
 
-#[inline]
-fn parse_move(mv: &str) -> ((usize, usize), (usize, usize)) {
-    let x: Vec<char> = mv.chars().collect();
-    (
-        (to_cordinate(x[1]), to_cordinate(x[0])),
-        (to_cordinate(x[3]), to_cordinate(x[2])),
-    )
+/// The pieces a move slides, `before` and `after` being the squares around it.
+/// Castling moves the rook as well, and a pawn taken en passant stays until it is reached.
+fn move_animations(
+    before: &[[Square; 8]; 8],
+    after: &[[Square; 8]; 8],
+    mv: &Move,
+    start_time: Duration,
+) -> Vec<Animation> {
+    let Ok((from, to)) = parse_move(&mv.to_algebraic_notation()) else {
+        return Vec::new();
+    };
+    let slide = |piece: Square, from: (usize, usize), to: (usize, usize)| Animation {
+        piece,
+        from,
+        to,
+        start_time,
+        duration: ANIMATION_DURATION,
+    };
+    if is_castling(before, from, to) {
+        // The king lands beside the rook's square, whichever way the move was encoded
+        let (king_to, rook_from, rook_to) = castling_squares(from, to);
+        return vec![
+            slide(before[from.0][from.1], from, king_to),
+            slide(before[rook_from.0][rook_from.1], rook_from, rook_to),
+        ];
+    }
+    // The piece that arrives, so a promotion slides as the new piece
+    let mut animations = vec![slide(after[to.0][to.1], from, to)];
+    // En passant takes a pawn off a square the move doesn't land on
+    if matches!(before[from.0][from.1], Square::Pawn(_))
+        && from.1 != to.1
+        && before[to.0][to.1] == Square::Empty
+    {
+        let taken = (from.0, to.1);
+        animations.push(slide(before[taken.0][taken.1], taken, taken));
+    }
+    animations
 }
 
-fn parse_moves(moves: Vec<Move>) -> [[HashMap<(usize, usize), Vec<Move>>; 8]; 8] {
-    let mut parsed: [[HashMap<(usize, usize), Vec<Move>>; 8]; 8] = Default::default();
+/// An action waiting for the player to confirm it with Y or cancel it with N
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Confirmation {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    // Holds the draw the position allows
+    ClaimDraw(GameState),
+    // Started over by the app, which owns the settings the new game needs
+    Restart,
+    // Holds how many moves the engine's judgements would mark
+    AutoAnnotate(usize),
+}
 
-    for mv in moves {
-        let (from, to) = parse_move(&mv.to_algebraic_notation());
-        parsed[from.0][from.1]
-            .entry(to)
-            .or_insert_with(Vec::new)
-            .push(mv);
+impl Confirmation {
+    #[inline]
+    fn prompt(&self) -> String {
+        match self {
+            Confirmation::Resign => "Resign? (Y/N)".to_owned(),
+            Confirmation::OfferDraw => "Offer a draw? (Y/N)".to_owned(),
+            Confirmation::AcceptDraw => "Opponent offers a draw, accept? (Y/N)".to_owned(),
+            Confirmation::ClaimDraw(GameState::FiftyMoves) => {
+                "Claim a draw by the fifty-move rule? (Y/N)".to_owned()
+            }
+            Confirmation::ClaimDraw(_) => "Claim a draw by threefold repetition? (Y/N)".to_owned(),
+            Confirmation::Restart => "Abandon this game and start a new one? (Y/N)".to_owned(),
+            Confirmation::AutoAnnotate(1) => {
+                "Mark 1 move as a mistake or blunder? (Y/N)".to_owned()
+            }
+            Confirmation::AutoAnnotate(count) => {
+                format!("Mark {} moves as mistakes or blunders? (Y/N)", count)
+            }
+        }
     }
-    parsed
 }
 
+/// Everything on the GPU the board is drawn with. All of it is built in `new` from the theme,
+/// the piece styles, constants and the sprite sheet compiled in, so nothing is lost when it is
+/// built again, with another theme or after the surface was lost.
 struct Render {
-    pieces_image: Image,
+    pieces: Sprites,
     chessboard_mesh: Mesh,
     promotion_mesh: Mesh,
     selected_piece_mesh: Mesh,
     available_move_mesh: Mesh,
+    check_mesh: Mesh,
+    // White so it can be tinted when drawn
+    bar_mesh: Mesh,
+    // Every text drawn lately, flushed with the rest of the render when the theme or the
+    // window's density changes
+    texts: RefCell<TextCache<Text>>,
+    // File letters from a and rank numbers from the eighth rank, indexed like the squares
+    file_labels: Vec<Text>,
+    rank_labels: Vec<Text>,
+}
+
+fn chessboard_mesh(ctx: &Context, theme: &Theme) -> Mesh {
+    let mut mesh = graphics::MeshBuilder::new();
+    for row in 0..8usize {
+        for col in 0..8usize {
+            mesh.rectangle(
+                DrawMode::fill(),
+                graphics::Rect::new(col as f32 / 8.0, row as f32 / 8.0, 1.0 / 8.0, 1.0 / 8.0),
+                match (row + col).is_multiple_of(2) {
+                    true => theme.white_square,
+                    false => theme.black_square,
+                },
+            )
+            .unwrap();
+        }
+    }
+    Mesh::from_data(ctx, mesh.build())
+}
+
+/// A white unit square, for the buttons and rows of the screens to tint and stretch
+fn unit_mesh(ctx: &Context) -> Mesh {
+    Mesh::new_rectangle(ctx, DrawMode::fill(), Rect::one(), graphics::Color::WHITE).unwrap()
+}
+
+/// What a screen keeps on the GPU
+enum Graphics<'a> {
+    // The unit square its buttons and rows are drawn with
+    UnitMesh(&'a mut Mesh),
+    // The board, the pieces and the texts, in the colors of the theme
+    Render(&'a mut Render, &'a Theme),
+}
+
+/// A screen with resources on the GPU
+trait Restore {
+    fn graphics(&mut self) -> Graphics<'_>;
+
+    /// Build the GPU resources again after the surface was lost or for a new density. The
+    /// rest of the screen, a game with its connection and clocks included, isn't touched.
+    fn restore_graphics(&mut self, ctx: &Context) {
+        match self.graphics() {
+            Graphics::UnitMesh(mesh) => *mesh = unit_mesh(ctx),
+            Graphics::Render(render, theme) => render.restore(ctx, theme),
+        }
+    }
+}
+
+fn label_text(label: String) -> Text {
+    let mut text = Text::new(label);
+    text.set_scale(LABEL_FONT_SIZE);
+    text
 }
 
 impl Render {
-    fn new(ctx: &Context) -> Self {
+    fn new(ctx: &Context, theme: &Theme, styles: PieceStyles) -> Self {
         Self {
-            pieces_image: Image::from_bytes(ctx, PIECES_IMAGE_BYTES).unwrap(),
-            chessboard_mesh: Mesh::from_data(ctx, CHESSBOARD_MESH.build().clone()),
+            pieces: Sprites::new(ctx, theme, styles),
+            chessboard_mesh: chessboard_mesh(ctx, theme),
             promotion_mesh: Mesh::new_rectangle(
                 ctx,
                 DrawMode::fill(),
@@ -177,7 +605,7 @@ impl Render {
                     rect.scale(1.0 / COL_COUNT_F32, 1.0 / ROW_COUNT_F32);
                     rect
                 },
-                HIGHLIGHT_COLOR,
+                theme.highlight,
             )
             .unwrap(),
             available_move_mesh: Mesh::new_circle(
@@ -189,369 +617,5051 @@ impl Render {
                 },
                 0.25 / COL_COUNT_F32,
                 0.25 / (COL_COUNT_F32 * 1024.0),
-                HIGHLIGHT_COLOR,
+                theme.highlight,
+            )
+            .unwrap(),
+            check_mesh: Mesh::new_rectangle(
+                ctx,
+                DrawMode::fill(),
+                {
+                    let mut rect = Rect::one();
+                    rect.scale(1.0 / COL_COUNT_F32, 1.0 / ROW_COUNT_F32);
+                    rect
+                },
+                theme.check,
             )
             .unwrap(),
+            bar_mesh: unit_mesh(ctx),
+            file_labels: ('a'..='h')
+                .map(|file| label_text(file.to_string()))
+                .collect(),
+            rank_labels: (1..=8)
+                .rev()
+                .map(|rank| label_text(rank.to_string()))
+                .collect(),
+            texts: RefCell::new(TextCache::default()),
+        }
+    }
+
+    /// Build every field again, which is how the GPU resources come back after the surface
+    /// was lost. The fields are named without `..`, so one added later can't be left holding
+    /// what the old surface had, and the text cache starts empty.
+    fn restore(&mut self, ctx: &Context, theme: &Theme) {
+        let Self {
+            pieces,
+            chessboard_mesh,
+            promotion_mesh,
+            selected_piece_mesh,
+            available_move_mesh,
+            check_mesh,
+            bar_mesh,
+            texts,
+            file_labels,
+            rank_labels,
+        } = Self::new(ctx, theme, self.pieces.styles());
+        self.pieces = pieces;
+        self.chessboard_mesh = chessboard_mesh;
+        self.promotion_mesh = promotion_mesh;
+        self.selected_piece_mesh = selected_piece_mesh;
+        self.available_move_mesh = available_move_mesh;
+        self.check_mesh = check_mesh;
+        self.bar_mesh = bar_mesh;
+        self.texts = texts;
+        self.file_labels = file_labels;
+        self.rank_labels = rank_labels;
+    }
+
+    /// Darkens the whole board, used behind the move chooser and the end screen
+    #[inline]
+    fn draw_film(&self, canvas: &mut Canvas, board: Rect) {
+        canvas.draw(
+            &self.promotion_mesh,
+            graphics::DrawParam::default().dest_rect(board),
+        );
+    }
+
+    /// Draws a line of text centered horizontally in `area`
+    fn draw_text(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        area: Rect,
+        message: &str,
+        TextLine { y, scale, color }: TextLine,
+    ) {
+        self.with_text(message, scale * area.h, |text| {
+            let text_width = text.dimensions(ctx).map_or(0.0, |dims| dims.w);
+            canvas.draw(
+                text,
+                graphics::DrawParam::default()
+                    .dest(Point2 {
+                        x: area.x + (area.w - text_width) / 2.0,
+                        y: area.y + y * area.h - scale * area.h / 2.0,
+                    })
+                    .color(color),
+            );
+        });
+    }
+
+    /// Use the text laid out at `scale`, taken from the cache unless it is new
+    fn with_text<R>(&self, string: &str, scale: f32, use_text: impl FnOnce(&mut Text) -> R) -> R {
+        let mut texts = self.texts.borrow_mut();
+        let text = texts.get(string, scale, || {
+            let mut text = Text::new(string);
+            text.set_scale(scale);
+            text
+        });
+        use_text(text)
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Sound {
+    Move,
+    Capture,
+    Check,
+    GameOver,
+    Tick,
+}
+
+impl From<Cue> for Sound {
+    fn from(cue: Cue) -> Self {
+        match cue {
+            Cue::Move => Sound::Move,
+            Cue::Capture => Sound::Capture,
+            Cue::Check => Sound::Check,
+            Cue::GameOver => Sound::GameOver,
+            Cue::Tick => Sound::Tick,
         }
     }
 }
 
-pub(crate) struct BoardRepr {
-    // Rendering aid
-    squares: [[Square; 8]; 8],
-    legal_moves: [[HashMap<(usize, usize), Vec<Move>>; 8]; 8],
-    selected_from: Option<(usize, usize)>,
-    selected_to: Option<(usize, usize)>,
+struct Sounds {
+    move_source: Source,
+    capture_source: Source,
+    check_source: Source,
+    game_over_source: Source,
+    tick_source: Source,
+    muted: bool,
 }
 
-impl BoardRepr {
-    fn new(board: &Board) -> Self {
+impl Sounds {
+    fn new(ctx: &Context, muted: bool) -> Self {
+        let source = |bytes: &[u8]| Source::from_data(ctx, SoundData::from_bytes(bytes)).unwrap();
         Self {
-            squares: parse_fen(&board.to_fen()),
-            legal_moves: parse_moves(board.get_legal_moves()),
-            selected_from: None,
-            selected_to: None,
+            move_source: source(MOVE_SOUND_BYTES),
+            capture_source: source(CAPTURE_SOUND_BYTES),
+            check_source: source(CHECK_SOUND_BYTES),
+            game_over_source: source(GAME_OVER_SOUND_BYTES),
+            tick_source: {
+                let mut tick = source(MOVE_SOUND_BYTES);
+                tick.set_volume(TICK_VOLUME);
+                tick
+            },
+            muted,
+        }
+    }
+
+    fn play(&mut self, ctx: &Context, sound: Sound) {
+        if self.muted {
+            return;
+        }
+        let source = match sound {
+            Sound::Move => &mut self.move_source,
+            Sound::Capture => &mut self.capture_source,
+            Sound::Check => &mut self.check_source,
+            Sound::GameOver => &mut self.game_over_source,
+            Sound::Tick => &mut self.tick_source,
+        };
+        // Detached so a quick reply from the opponent doesn't cut the last sound short
+        if let Err(err) = source.play_detached(ctx) {
+            println!("Can't play sound: {}", err);
         }
     }
 }
 
 struct Game {
-    // Game logic
-    board: Board,
-
-    // Board representation
-    board_repr: BoardRepr,
+    // Board, history and selection, everything that works without the window
+    session: Session,
 
     // Rendering stuff
     render: Render,
+    // Cycled at runtime, the render is rebuilt from it
+    theme: Theme,
+    // How each side's pieces are drawn, the render is rebuilt with them too
+    piece_styles: PieceStyles,
+    // Pieces still sliding after the last move, all of them belong to that move
+    animations: Vec<Animation>,
+    // The edge between the board and the history panel, saved to `layout_file` once moved
+    splitter: Splitter,
+    layout_file: PathBuf,
+    // Whether the board goes beside or above the panel, by the window's shape for Auto
+    layout_mode: LayoutMode,
+    portrait_ratio: f32,
+    // Time since the start of the program at the last update, moves made between updates start then
+    now: Duration,
+    show_coordinates: bool,
+    // The piece letters G switches the move list to, and whether it offers figurines
+    letters: Option<Language>,
+    figurines_render: bool,
+    // Whether the piece legend is open, and when it last started opening or closing
+    legend_open: bool,
+    legend_toggled: Option<Duration>,
+    sounds: Sounds,
+    // Sound for the last move, played on the next update since that is where the context is
+    pending_sound: Option<Sound>,
+
+    // Networking, None in a local game
+    network: Option<Network>,
+    // The computer opponent and its color, None unless playing against it
+    engine: Option<Engine>,
+    computer_color: Option<Color>,
+    // Move suggested to the server that it hasn't answered yet
+    pending_move: Option<Move>,
+    // The refreshes after the opponent's likely replies, worked out while it thinks
+    speculation: Option<Speculation>,
+    // The client's moves rejected since its last one, it forfeits after too many
+    strikes: Strikes,
+    // Times the opponent's moves against --max-move-time, None unless it applies
+    stall: Option<StallWatch>,
+    stall_status: Stall,
+    // Set once the peer is gone, nothing more is read from the stream after that
+    disconnected: bool,
+    // Shared by every game of the run, None if there is none or it may not run in this game
+    hook: Option<Arc<Hook>>,
+    // What goes into the connection book once connected, None unless playing over the network
+    book_entry: Option<connection_book::Entry>,
+    connection_book: PathBuf,
+    // What the hook did, what changed about the other side, moves sent back and where the bug
+    // report went, shown over the board one after another
+    toasts: Toasts,
+    // The folder of the last bug report bundle, Ctrl+O opens it
+    report_dir: Option<PathBuf>,
+    // The files of the profile playing, for bug reports
+    profile: Option<ProfilePaths>,
+    // What the peer did out of the ordinary, kept in the interop ledger once the game is over
+    interop: Recorder,
+    interop_ledger: PathBuf,
+    // When connected, 0 before that and once the session is recorded
+    interop_started: u64,
+    // Why the connection was never made, shown instead of the connection being lost
+    connection_error: Option<String>,
+
+    // Game status
+    // The square whose selection a new position let go of and when
+    selection_flash: Option<((usize, usize), Duration)>,
+    // What the board is drawn with every frame, kept to reuse what they allocated
+    review_text: String,
+    #[cfg(feature = "alloc-tracking")]
+    allocations: alloc_tracking::FrameStats,
+    choice_buffer: Vec<(ChoiceLabel, Cells)>,
+    destination_buffer: Vec<(usize, usize)>,
+    // The piece clicked out of turn, shaking until it settles
+    shake: Option<Shake>,
+    // The move picked up while waiting with --premove
+    premove: Option<Premove>,
+    premoves: bool,
+    // The first click out of turn says why the pieces are dimmed, only the first
+    explained_waiting: bool,
+    // The reviewed ply whose move's glyph flashes over the board and when it was got to
+    glyph_flash: Option<(usize, Duration)>,
+    // Resign or draw prompt shown over the board
+    confirming: Option<Confirmation>,
+    // Time since the start of the program when the game began, what its negotiations are timed by
+    started: Duration,
+    // Move typed in coordinate notation, None unless the entry bar is open
+    move_entry: Option<MoveEntry>,
+    // Features we rely on that the server didn't advertise, shown for the whole game
+    feature_warning: Option<String>,
+    // Last error reported by the server, shown until the next state arrives
+    error_message: Option<String>,
+    // How many lines the history panel is scrolled up from the latest move
+    history_scroll: usize,
+    // The ply shown while looking back through the game, None for the live position
+    viewing_ply: Option<usize>,
+    // Pieces of the reviewed position, parsed when it is chosen
+    review_squares: Option<[[Square; 8]; 8]>,
+    // The 3-2-1 before a networked game, and a move made during it to play once it is over
+    countdown: Option<Countdown>,
+    buffered_move: Option<Move>,
+    // Scores the positions of the game for the evaluation graph while reviewing
+    analysis: Option<Analysis>,
+    // How bad every move was by the scores so far, and the move list with their glyphs
+    judgements: Vec<Option<Judgement>>,
+    review_lines: Vec<String>,
+    // The graph's line as the judgements were made, None once a move made them out of date
+    graph_points: Option<Vec<(f32, f32)>>,
+
+    // Time left for both sides, None without a time control
+    clock: Option<Clock>,
+    // Finished games are saved here when given
+    save_dir: Option<PathBuf>,
+    // How the glyphs set while reviewing are written to PGN
+    pgn_glyphs: GlyphStyle,
+    // The random handle the local player goes by, None to go by the side's name
+    identity: Option<Identity>,
+    // How long the server's moves slide, by half the round trip of the client's
+    slide_curve: SlideCurve,
+    round_trip: RoundTrip,
+    // Where the transcript of a networked game is written once it ends, Ctrl+E writes it any time
+    transcript: Option<PathBuf>,
+    // A handshake the peer sends again mid-game is a protocol error instead of checked
+    strict_handshakes: bool,
+    // How far past their squares the selected piece's moves take clicks, None without
+    // --accessible-input
+    target_expansion: Option<f32>,
+    // The games in the save directory by their hash, so the same game isn't saved twice
+    save_index: SaveIndex,
+    // Whether the finished game has been saved, undoing into a new result saves again
+    auto_saved: bool,
+    // Written after every move so the game can be resumed, None in networked games
+    autosave: Option<Autosave>,
+    // Where a practice game against the computer was forked from, and when it ended
+    practice: Option<Fork>,
+    // Warns the player of opening traps in games that aren't networked
+    assist: bool,
+    // The warning shown until it is dismissed or the next move, and every one shown so far
+    trap_warning: Option<&'static Trap>,
+    trap_notes: Vec<TrapNote>,
+    // With assist, the pins on the pieces of the player to move, also the ones against a
+    // queen or rook with --relative-pins
+    pins: Vec<Pin>,
+    relative_pins: bool,
+    // The square under the pointer, the tooltip of a pinned piece shows while it is there
+    hovered: Option<(usize, usize)>,
+    practice_ended: Option<Duration>,
+    // An engine match played on the board, nobody here makes its moves
+    engine_match: Option<MatchWatch>,
+
+    // Terminal output
+    tty_mirror: Option<TtyMirror>,
+    // Status last put in the window title, it is only set again once it changes
+    title: String,
+    // What the title is written into every frame before it is compared
+    status_buffer: String,
+}
 
-    // Networking
-    network: Network,
+/// An engine match being watched, its moves are shown one at a time as they come
+struct MatchWatch {
+    runner: Match,
+    config: MatchConfig,
+    tally: Tally,
+    // The game on the board counting from 0, and when the next move of it may be shown
+    number: u32,
+    next_at: Duration,
+    done: bool,
+}
+
+impl MatchWatch {
+    /// The depth of the engine playing `color` in the game on the board
+    fn depth(&self, color: Color) -> u32 {
+        let (_, first_white) = engine_match::pairing(self.number);
+        match (color == Color::White) == first_white {
+            true => self.config.first,
+            false => self.config.second,
+        }
+    }
+}
+
+/// Text typed into the move entry bar, opened with / or :, or with B for a bookmark's label
+#[derive(Default)]
+struct MoveEntry {
+    text: String,
+    // Whether the text was rejected, the bar stays red until it is edited
+    rejected: bool,
+    // The ply of the bookmark the text labels, None for a move
+    bookmark: Option<usize>,
+}
+
+/// Options from the command line that every new game starts with
+#[derive(Clone)]
+struct GameSettings {
+    tty_mirror: Option<TtyOrientation>,
+    muted: bool,
+    theme: Theme,
+    show_coordinates: bool,
+    legend: bool,
+    notation: Option<Notation>,
+    letters: Option<Language>,
+    figurines_render: bool,
+    time_control: Option<TimeControl>,
+    save_dir: Option<PathBuf>,
+    pgn_glyphs: GlyphStyle,
+    slide_curve: SlideCurve,
+    // Rolled for the run when the profile plays anonymously or on the start screen
+    identity: Option<Identity>,
+    save_file: PathBuf,
+    // Where the split between the board and the history panel is kept between runs
+    layout_file: PathBuf,
+    layout_mode: LayoutMode,
+    portrait_ratio: f32,
+    claim_draws: bool,
+    practice_depth: u32,
+    assist: bool,
+    relative_pins: bool,
+    max_illegal_moves: u32,
+    max_protocol_errors: u32,
+    max_move_time: Option<Duration>,
+    strict_handshakes: bool,
+    transcript: Option<PathBuf>,
+    // The computer switches colors in every game started over with Ctrl+N
+    alternate_colors: bool,
+    accessible_input: Option<AccessibleInput>,
+    premoves: bool,
+    // The profile's, with the command line's changes
+    piece_styles: PieceStyles,
+    hook: Option<Arc<Hook>>,
+    // Whether the hook may run in networked games too
+    hook_networked: bool,
+    // The profile's, where networked games are remembered
+    connection_book: PathBuf,
+    // The profile's, where what the peers of networked games did is kept
+    interop_ledger: PathBuf,
+    // The profile's, where the best scores of the square trainer are kept
+    trainer_file: PathBuf,
+    // The profile playing, None until one is picked
+    profile: Option<ProfilePaths>,
+    // Who sits where in local games, swapped before their first move
+    seating: Seating,
 }
 
 impl Game {
     #[inline]
     fn refresh_board(&mut self) {
-        self.board_repr.legal_moves = parse_moves(self.board.get_legal_moves());
-        self.board_repr.squares = parse_fen(&self.board.to_fen());
-        self.board_repr.selected_from = None;
-        self.board_repr.selected_to = None;
+        self.session.refresh();
+        self.mirror_to_tty();
     }
-    fn new(
-        ctx: &Context,
-        stream: TcpStream,
-        is_server: bool,
-        server_color: Option<chess_network_protocol::Color>,
-    ) -> Self {
-        let board = Board::default();
-        let board_repr = BoardRepr::new(&board);
-        let network = network::handshake(
-            stream,
-            match is_server {
-                true => network::Handshake::ServerToClient(internal_to_server_handshake(
-                    &board_repr,
-                    &board,
-                )),
-                false => network::Handshake::ClientToServer(
-                    chess_network_protocol::ClientToServerHandshake {
-                        server_color: server_color.expect("Client has to choose server color."),
-                    },
+
+    #[inline]
+    fn mirror_to_tty(&self) {
+        if let Some(tty_mirror) = &self.tty_mirror {
+            // The terminal has always had SAN
+            let last_move =
+                self.session.history.last().map(|entry| {
+                    entry.shown(Some(self.session.notation.unwrap_or(Notation::English)))
+                });
+            tty_mirror.show(
+                &self.session.board_repr.squares,
+                self.session.board_repr.flipped,
+                last_move.as_deref(),
+                self.clock
+                    .as_ref()
+                    .map(|clock| (clock.remaining(Color::White), clock.remaining(Color::Black))),
+                &tty_mirror::status_line(
+                    self.session.board.get_curr_player(),
+                    self.session.game_state.description(),
                 ),
-            },
-        );
-        Self {
-            board,
-            board_repr,
-            render: Render::new(ctx),
-            network,
+            );
         }
     }
-    #[inline]
-    fn draw_squares(&self, canvas: &mut Canvas) {
-        let (width, height) = {
-            let cords = canvas.screen_coordinates().unwrap();
-            (cords.w, cords.h)
-        };
-
-        canvas.draw(
-            &self.render.chessboard_mesh,
-            graphics::DrawParam::default().scale(Vector2 {
-                x: width,
-                y: height,
-            }),
-        );
-    }
 
-    fn draw_piece(&self, canvas: &mut Canvas, piece: &Square, row: usize, col: usize) {
-        if *piece == Square::Empty {
-            return;
+    /// Text drawn over the board when it can't be played on
+    fn overlay_text(&self) -> Option<String> {
+        if self.session.game_state == GameState::PositionError {
+            // So the position can be reported, or tried in another engine
+            return Some(format!(
+                "{}\n{}",
+                GameState::PositionError.description()?,
+                self.session.board.to_fen()
+            ));
         }
-        let color = piece.color().unwrap();
-
-        let rect = Rect::new(
-            match piece {
-                Square::Pawn(_) => 5.0,
-                Square::Rook(_) => 4.0,
-                Square::Knight(_) => 3.0,
-                Square::Bishop(_) => 2.0,
-                Square::Queen(_) => 1.0,
-                _ => 0.0,
-            } / 6.0,
-            match color {
-                Color::White => 0.0,
-                Color::Black => 1.0,
-            } / 2.0,
-            1.0 / 6.0,
-            1.0 / 2.0,
-        );
-
-        let (width, height) = {
-            let cords = canvas.screen_coordinates().unwrap();
-            (cords.w, cords.h)
-        };
-
-        canvas.draw(
-            &self.render.pieces_image,
-            graphics::DrawParam {
-                src: rect,
-                color: graphics::Color::WHITE,
-                transform: Transform::Values {
-                    dest: Point2 {
-                        x: col as f32 * width / 8.0,
-                        y: row as f32 * height / 8.0,
-                    },
-                    rotation: 0.0,
-                    scale: Vector2 {
-                        x: (width * 6.0) / (self.render.pieces_image.width() as f32 * 8.0),
-                        y: (height * 2.0) / (self.render.pieces_image.height() as f32 * 8.0),
+        if let Some(description) = self.session.game_state.description() {
+            return Some(description);
+        }
+        let connected = self
+            .network
+            .as_ref()
+            .is_none_or(|network| network.connected);
+        match (self.disconnected, connected) {
+            (true, _) => Some(
+                self.connection_error
+                    .clone()
+                    .unwrap_or_else(|| "Connection lost".to_owned()),
+            ),
+            (false, false) => Some(
+                match self.network.as_ref().filter(|network| network.is_server) {
+                    Some(network) => match network.listening {
+                        Some(address) if address.ip().is_unspecified() => format!(
+                            "Waiting for opponent\nListening on {}\nConnect to this computer's LAN IP",
+                            network::describe_listening(address)
+                        ),
+                        Some(address) => format!("Waiting for opponent\nListening on {}", address),
+                        None => "Waiting for opponent".to_owned(),
                     },
-                    offset: Point2 { x: 0.0, y: 0.0 },
+                    None => "Connecting...".to_owned(),
                 },
-                z: 0,
-            },
-        );
+            ),
+            (false, true) => self
+                .confirming
+                .map(|confirmation| confirmation.prompt()),
+        }
     }
 
-    #[inline]
-    fn draw_pieces(&self, canvas: &mut Canvas) {
-        for row in 0..8usize {
-            for col in 0..8usize {
-                // Draw piece on current square
-                self.draw_piece(canvas, &self.board_repr.squares[row][col], row, col);
+    /// Whose turn it is as seen from this window, or why nobody can move, written into `text`
+    fn write_status(&self, text: &mut String) {
+        use std::fmt::Write;
+        if let Some(description) = self.session.game_state.description() {
+            text.push_str(&description);
+            return;
+        }
+        if self.disconnected {
+            text.push_str("Connection lost");
+            return;
+        }
+        if let Some(network) = self.network.as_ref().filter(|network| !network.connected) {
+            let _ = match (network.is_server, network.listening) {
+                (true, Some(address)) => write!(
+                    text,
+                    "Waiting for opponent on {}...",
+                    network::describe_listening(address)
+                ),
+                (true, None) => text.write_str("Waiting for opponent..."),
+                (false, _) => text.write_str("Connecting..."),
+            };
+            return;
+        }
+        if let Some(number) = self
+            .countdown
+            .as_ref()
+            .and_then(|countdown| countdown.number(self.now))
+        {
+            let _ = write!(text, "Starting in {}...", number);
+            return;
+        }
+        match self.local_color() {
+            _ if self.is_spectating() => text.push_str("Spectating, "),
+            Some(color) => {
+                let _ = write!(text, "You are {}, ", color_name(color));
             }
+            // Both sides are played here
+            None => {}
+        }
+        let _ = write!(
+            text,
+            "{} to move",
+            color_name(self.session.board.get_curr_player())
+        );
+        // Whoever finished the handshake last mustn't lose time to it
+        if self.clock.is_some() && self.network.is_some() && self.session.played_moves.is_empty() {
+            text.push_str(", the clocks start with the first move");
+        }
+        if self.local_color().is_some() && !self.is_local_turn() {
+            let _ = write!(
+                text,
+                ", waiting for the opponent{}",
+                turn::waiting_dots(self.now)
+            );
         }
     }
-    #[inline]
-    fn draw_promotion_selection(&self, canvas: &mut Canvas, row: usize, col: usize) {
-        let (width, height) = {
-            let cords = canvas.screen_coordinates().unwrap();
-            (cords.w, cords.h)
-        };
 
-        // Grey out the chessboard
-        canvas.draw(
-            &self.render.promotion_mesh,
-            graphics::DrawParam::default().scale(Vector2 {
-                x: width,
-                y: height,
-            }),
-        );
-        let dir = match row {
-            7 => -1isize,
-            0 => 1isize,
-            _ => panic!("Promotion not on last row"),
-        };
-
-        let (from_row, from_col) = self.board_repr.selected_from.unwrap();
-
-        let color = self.board_repr.squares[from_row][from_col].color().unwrap();
-        let pieces: [Square; 4] = [
-            Square::Queen(color),
-            Square::Knight(color),
-            Square::Rook(color),
-            Square::Bishop(color),
-        ];
-
-        for dy in 0..4usize {
-            let row = (row as isize + dir * dy as isize) as usize;
-            self.draw_piece(canvas, &pieces[dy], row, col);
+    fn update_title(&mut self, ctx: &Context) {
+        use std::fmt::Write;
+        // Written over the last frame's, the window is only told when it changed
+        let mut status = std::mem::take(&mut self.status_buffer);
+        status.clear();
+        match (&self.practice, &self.engine_match) {
+            (Some(fork), _) => {
+                let _ = write!(status, "Practice after move {}, ", fork.ply);
+                self.write_status(&mut status);
+            }
+            (None, Some(watch)) if watch.done => {
+                let _ = write!(
+                    status,
+                    "Engine match over, {}",
+                    watch.tally.score_line(&watch.config)
+                );
+            }
+            (None, Some(watch)) => {
+                let _ = write!(
+                    status,
+                    "Engine match game {} of {}, {}, ",
+                    watch.number + 1,
+                    watch.config.games,
+                    watch.tally.score_line(&watch.config)
+                );
+                self.write_status(&mut status);
+            }
+            (None, None) => self.write_status(&mut status),
         }
+        if status != self.title {
+            ctx.gfx
+                .set_window_title(&format!("{} - {}", status, WINDOW_TITLE));
+            std::mem::swap(&mut self.title, &mut status);
+        }
+        self.status_buffer = status;
     }
 
-    #[inline]
-    fn draw_move_selection(&self, canvas: &mut Canvas, row: usize, col: usize) {
-        let (width, height) = {
-            let cords = canvas.screen_coordinates().unwrap();
-            (cords.w, cords.h)
-        };
-
-        canvas.draw(
-            &self.render.selected_piece_mesh,
-            graphics::DrawParam::default().dest_rect(Rect {
-                x: (col as f32) * width / 8.0,
-                y: (row as f32) * height / 8.0,
-                w: width,
-                h: height,
-            }),
+    /// What a reviewed position says of itself over the board, written into `text`. Only
+    /// what asks for it allocates, like a bookmark's label or a negotiation.
+    fn write_review(&self, text: &mut String, ply: usize) {
+        use std::fmt::Write;
+        // Only the old position, nothing on it can be played
+        let _ = write!(
+            text,
+            "Reviewing move {} of {}",
+            ply,
+            self.session.snapshots.len() - 1
         );
-
-        for ((row, col), _) in &self.board_repr.legal_moves[row][col] {
-            canvas.draw(
-                &self.render.available_move_mesh,
-                graphics::DrawParam::default().dest_rect(Rect {
-                    x: (*col as f32) * width / 8.0,
-                    y: (*row as f32) * height / 8.0,
-                    w: width,
-                    h: height,
-                }),
-            );
+        if let Some(analysis) = &self.analysis {
+            let _ = write!(text, ", evaluating {}%", analysis.percent());
         }
-    }
-    fn server_play_move(&mut self, opponent_move: &chess_network_protocol::Move) {
-        let legal_moves = self.board.get_legal_moves();
-        for mv in legal_moves {
-            if network::internal_to_network_move(&mv) == *opponent_move {
-                self.board.play_move(mv).unwrap();
-                self.refresh_board();
-                return;
+        if self.can_fork() {
+            text.push_str(", P plays on from here");
+        }
+        if let Some(bookmark) = self.session.bookmarks.get(ply) {
+            text.push_str(", bookmarked");
+            if let Some(label) = &bookmark.label {
+                let _ = write!(text, ": {}", label);
             }
         }
-        panic!("No internal move matching opponent move found!!!");
-    }
-
-    fn play_move(&mut self, player_move: &Move) {
-        self.board.play_move(*player_move).unwrap();
-        self.refresh_board();
-        if self.network.is_server {
-            let message = chess_network_protocol::ServerToClient::State {
-                board: internal_to_network_board(&self.board_repr.squares),
-                moves: internal_to_network_moves(&self.board.get_legal_moves()),
-                joever: chess_network_protocol::Joever::Ongoing,
-                move_made: internal_to_network_move(player_move),
-            };
-            serde_json::to_writer(&self.network.stream, &message).unwrap();
-        } else {
-            let message =
-                chess_network_protocol::ClientToServer::Move(internal_to_network_move(player_move));
-            // We will suggest our move to the server and the server will respond with a new board state
-            serde_json::to_writer(&self.network.stream, &message).unwrap();
+        for negotiation in self.session.negotiations.at(ply) {
+            let _ = write!(text, ", {}", negotiation.describe());
+        }
+        if !self.session.bookmarks.is_empty() {
+            let _ = write!(
+                text,
+                ", [ and ] jump between the {} bookmarks",
+                self.session.bookmarks.len()
+            );
         }
     }
-}
 
-impl event::EventHandler for Game {
+    /// Whether the game can still be resigned or drawn from here
     #[inline]
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        if let Some(state) = self.network.get_board_state() {
-            match state {
-                ServerToClient::State { move_made, .. } => {
-                    self.server_play_move(&move_made);
-                    self.refresh_board();
+    fn is_playing(&self) -> bool {
+        self.session.game_state == GameState::Ongoing
+            && !self.disconnected
+            && !self.is_spectating()
+            && self.engine_match.is_none()
+            && self
+                .network
+                .as_ref()
+                .is_none_or(|network| network.connected)
+    }
+
+    fn confirm(&mut self, confirmation: Confirmation) {
+        match confirmation {
+            Confirmation::Resign => {
+                // In a local game the player to move resigns
+                let resigner = self
+                    .local_color()
+                    .unwrap_or(self.session.board.get_curr_player());
+                self.session.game_state = GameState::Resigned(resigner);
+                if let Some(network) = &self.network {
+                    network.send_resign(&self.session, resigner);
+                }
+            }
+            Confirmation::OfferDraw => match &self.network {
+                // Both players are sitting here and agreed already
+                None => self.session.game_state = GameState::Draw,
+                Some(network) => {
+                    network.offer_draw(&self.session);
+                    self.negotiate(network.player_color, Outcome::Offered);
+                }
+            },
+            Confirmation::AcceptDraw => {
+                if let Some(network) = &self.network {
+                    network.send_draw(&self.session);
+                    self.negotiate(network.player_color, Outcome::Accepted);
+                }
+                self.session.game_state = GameState::Draw;
+            }
+            // The peer sees the same position and takes the draw message as the claim
+            Confirmation::ClaimDraw(draw) => {
+                if let Some(network) = &self.network {
+                    network.send_draw(&self.session);
                 }
-                ServerToClient::Error { .. } => {}
-                ServerToClient::Resigned { .. } => {}
-                ServerToClient::Draw { .. } => {}
+                self.session.game_state = draw;
             }
+            Confirmation::Restart | Confirmation::AutoAnnotate(_) => {}
         }
-        Ok(())
+        self.clear_selection();
+        self.mirror_to_tty();
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        // Start with a white canvas the size of the program window
-        let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
-
-        // Draw chessboard pattern
-        self.draw_squares(&mut canvas);
-
-        // Draw pieces
-        self.draw_pieces(&mut canvas);
+    /// Let go of the selected piece, closing the move chooser with it
+    #[inline]
+    fn clear_selection(&mut self) {
+        self.session.board_repr.selected_from = None;
+        self.session.board_repr.selected_to = None;
+    }
 
-        // Draw selection for promotion if promoting move is selected
-        if let Some((row, col)) = self.board_repr.selected_to {
-            self.draw_promotion_selection(&mut canvas, row, col);
+    /// The opponent either offers a draw, accepts the one we offered or claims one by the rules
+    fn receive_draw(&mut self) {
+        // Both sides end a game drawn by the rules on their own, the message only confirms it
+        if self.session.game_state != GameState::Ongoing {
+            return;
         }
-        // Else draw available moves if piece is selected
-        else if let Some((row, col)) = self.board_repr.selected_from {
-            self.draw_move_selection(&mut canvas, row, col);
+        if let Some(draw) = self.session.claimable {
+            self.session.game_state = draw;
+            self.mirror_to_tty();
+            return;
+        }
+        match self.local_color() {
+            Some(local) if self.session.negotiations.open() == Some(local) => {
+                self.negotiate(opposite_color(local), Outcome::Accepted);
+                self.session.game_state = GameState::Draw;
+                self.mirror_to_tty();
+            }
+            local => {
+                if let Some(local) = local {
+                    self.negotiate(opposite_color(local), Outcome::Offered);
+                }
+                self.confirming = Some(Confirmation::AcceptDraw);
+            }
+        }
+    }
+
+    /// Keep a step of a draw offer with the game and in the transcript. Both sides record
+    /// the steps they take and the ones they are told of alike.
+    fn negotiate(&mut self, side: Color, outcome: Outcome) {
+        let at = self.now.saturating_sub(self.started);
+        let ply = self.session.played_moves.len();
+        self.session.negotiations.record(ply, side, outcome, at);
+        if let Some(negotiation) = self.session.negotiations.iter().last() {
+            self.note(&negotiation.describe());
+            self.tell_hook(&hook::Event::draw(negotiation));
+        }
+    }
+
+    /// As the server, send the position after the move just played and any draw by the rules
+    /// it brought. Moves are only played with a peer once they convert.
+    fn announce_move(&mut self, mv: &Move) {
+        let Some(network) = self.network.as_ref().filter(|network| network.is_server) else {
+            return;
+        };
+        match network.send_board_state(&self.session, mv) {
+            Ok(()) => self.announce_rule_draw(),
+            Err(SendError::Disconnected) => self.lose_connection(),
+            Err(err) => println!("Can't tell the client about the move: {}", err),
+        }
+    }
+
+    /// The server tells the client about a draw by the rules once the state that caused it is sent,
+    /// the client works out the same draw but the protocol has no other way to say it
+    fn announce_rule_draw(&self) {
+        let Some(network) = self.network.as_ref().filter(|network| network.is_server) else {
+            return;
+        };
+        if matches!(
+            self.session.game_state,
+            GameState::Repetition | GameState::FiftyMoves | GameState::InsufficientMaterial
+        ) {
+            network.send_draw(&self.session);
+        }
+    }
+
+    fn lose_connection(&mut self) {
+        // Noticed while sending and then reported by the network thread as well
+        if self.disconnected {
+            return;
+        }
+        println!("Connection to the opponent lost");
+        self.tell_hook(&hook::Event::Connection {
+            status: "lost".to_owned(),
+        });
+        self.disconnected = true;
+        self.pending_move = None;
+        self.clear_selection();
+        self.remember_interop();
+    }
+    fn new(
+        ctx: &Context,
+        opponent: Opponent,
+        start_fen: Option<String>,
+        settings: GameSettings,
+    ) -> Self {
+        // Checked before the game is started
+        let mut session = Session::new(start_fen).unwrap();
+        session.claim_draws = settings.claim_draws;
+        let (network, computer) = match opponent {
+            Opponent::Local => (None, None),
+            Opponent::Computer(config) => (None, Some(config)),
+            Opponent::Network(config) => (Some(config), None),
+        };
+        // Spectators only watch, there is no game of theirs to go back to
+        let book_entry = network
+            .as_ref()
+            .filter(|config| !config.spectate)
+            .map(|config| connection_book::Entry {
+                address: config.address.clone(),
+                nickname: None,
+                as_server: config.is_server,
+                server_color: config.server_color,
+                last_used: 0,
+                result: None,
+                features: Vec::new(),
+                pinned: false,
+                handle: settings
+                    .identity
+                    .as_ref()
+                    .map(|identity| identity.handle.clone()),
+            });
+        let network = network.map(|config| {
+            network::start(
+                &config,
+                match config.is_server {
+                    true => {
+                        network::Handshake::ServerToClient(internal_to_server_handshake(&session))
+                    }
+                    false => network::Handshake::ClientToServer(
+                        chess_network_protocol::ClientToServerHandshake {
+                            // The server ignores what a spectator asks for
+                            server_color: match config.spectate {
+                                true => config
+                                    .server_color
+                                    .unwrap_or(chess_network_protocol::Color::White),
+                                false => config
+                                    .server_color
+                                    .expect("Client has to choose server color."),
+                            },
+                        },
+                    ),
+                },
+            )
+        });
+        // Only for untimed games, the clock already limits the others
+        let stall = settings
+            .max_move_time
+            .filter(|_| settings.time_control.is_none())
+            .filter(|_| network.as_ref().is_some_and(|network| !network.spectating))
+            .map(|cap| StallWatch::new(cap, DEFAULT_GRACE));
+        let assist = settings.assist && network.is_none();
+        // Refused when the game is networked after all, as chosen on the start screen
+        let refused = settings.hook.is_some() && network.is_some() && !settings.hook_networked;
+        let mut toasts = Toasts::default();
+        if refused {
+            let toast = "The hook doesn't run in networked games without --hook-allow-networked";
+            println!("{}", toast);
+            toasts.push(toast, HOOK_TOAST_DURATION);
+        }
+        let hook = settings.hook.clone().filter(|_| !refused);
+        let computer_color = computer.as_ref().map(|config| config.color);
+        let autosave = match &network {
+            Some(_) => None,
+            None => Some(Autosave::new(settings.save_file.clone())),
+        };
+        // Always have the local player's pieces at the bottom
+        session.board_repr.flipped = match &network {
+            Some(network) => network.player_color == Color::Black,
+            None => match computer_color {
+                Some(computer_color) => computer_color == Color::White,
+                None => settings.seating.near == Color::Black,
+            },
+        };
+        session.set_notation(settings.notation);
+        // Bigger targets need bigger choices too
+        if settings.accessible_input.is_some() {
+            session.board_repr.choice_size = ChoiceSize::Touch;
+        }
+        let mut game = Self {
+            session,
+            render: Render::new(ctx, &settings.theme, settings.piece_styles),
+            theme: settings.theme,
+            piece_styles: settings.piece_styles,
+            animations: Vec::new(),
+            splitter: {
+                let (width, height) = ctx.gfx.drawable_size();
+                let mut splitter =
+                    Splitter::new(splitter::read(&settings.layout_file), width, height);
+                splitter.set_orientation(layout::orientation(
+                    width,
+                    height,
+                    layout::Orientation::Landscape,
+                    settings.layout_mode,
+                    settings.portrait_ratio,
+                ));
+                splitter
+            },
+            layout_file: settings.layout_file,
+            layout_mode: settings.layout_mode,
+            portrait_ratio: settings.portrait_ratio,
+            now: Duration::ZERO,
+            show_coordinates: settings.show_coordinates,
+            letters: settings.letters,
+            figurines_render: settings.figurines_render,
+            // Players of a networked game don't get it, it would shrink their board mid-game
+            legend_open: settings.legend
+                && network.as_ref().is_none_or(|network| network.spectating),
+            legend_toggled: None,
+            sounds: Sounds::new(ctx, settings.muted),
+            pending_sound: None,
+            network,
+            engine: computer.map(|config| Engine::start(config.depth)),
+            computer_color,
+            pending_move: None,
+            speculation: None,
+            disconnected: false,
+            hook,
+            book_entry,
+            connection_book: settings.connection_book,
+            toasts,
+            report_dir: None,
+            profile: settings.profile,
+            interop: Recorder::default(),
+            interop_ledger: settings.interop_ledger,
+            interop_started: 0,
+            connection_error: None,
+            selection_flash: None,
+            review_text: String::new(),
+            #[cfg(feature = "alloc-tracking")]
+            allocations: alloc_tracking::FrameStats::default(),
+            choice_buffer: Vec::new(),
+            destination_buffer: Vec::new(),
+            shake: None,
+            premove: None,
+            premoves: settings.premoves,
+            explained_waiting: false,
+            glyph_flash: None,
+            confirming: None,
+            started: ctx.time.time_since_start(),
+            move_entry: None,
+            feature_warning: None,
+            error_message: None,
+            history_scroll: 0,
+            viewing_ply: None,
+            review_squares: None,
+            countdown: None,
+            buffered_move: None,
+            analysis: None,
+            judgements: Vec::new(),
+            graph_points: None,
+            review_lines: Vec::new(),
+            clock: settings.time_control.map(Clock::new),
+            save_dir: settings.save_dir,
+            pgn_glyphs: settings.pgn_glyphs,
+            slide_curve: settings.slide_curve,
+            identity: settings.identity.clone(),
+            round_trip: RoundTrip::default(),
+            transcript: settings.transcript,
+            strict_handshakes: settings.strict_handshakes,
+            target_expansion: settings
+                .accessible_input
+                .map(|accessible_input| accessible_input.expansion),
+            save_index: SaveIndex::default(),
+            auto_saved: false,
+            autosave,
+            practice: None,
+            assist,
+            trap_warning: None,
+            trap_notes: Vec::new(),
+            pins: Vec::new(),
+            relative_pins: settings.relative_pins,
+            hovered: None,
+            practice_ended: None,
+            engine_match: None,
+            strikes: Strikes::new(settings.max_illegal_moves, settings.max_protocol_errors),
+            stall,
+            stall_status: Stall::Idle,
+            tty_mirror: settings.tty_mirror.map(TtyMirror::start),
+            title: String::new(),
+            status_buffer: String::new(),
+        };
+        // A starting position of its own can have pins already
+        game.find_pins();
+        game.mirror_to_tty();
+        game.tell_hook(&hook::Event::GameStarted {
+            white: game.saved_name(Color::White),
+            black: game.saved_name(Color::Black),
+            fen: game.session.fen(),
+            networked: game.network.is_some(),
+            clocks: game.clocks(),
+        });
+        game
+    }
+
+    #[inline]
+    fn tell_hook(&self, event: &hook::Event) {
+        if let Some(hook) = &self.hook {
+            hook.send(event);
+        }
+    }
+
+    /// The milliseconds left for white and black as the hook is told them
+    fn clocks(&self) -> Option<[u64; 2]> {
+        self.clock.as_ref().map(|clock| {
+            [Color::White, Color::Black].map(|color| clock.remaining(color).as_millis() as u64)
+        })
+    }
+
+    /// Who plays `color` as the saved games and the hook name them
+    fn saved_name(&self, color: Color) -> String {
+        match &self.network {
+            None if Some(color) == self.computer_color => "Computer".to_owned(),
+            None if self.computer_color.is_some() => {
+                identity::shown_name(self.identity.as_ref(), "Local").to_owned()
+            }
+            None => "Local".to_owned(),
+            Some(_) if Some(color) == self.local_color() => {
+                identity::shown_name(self.identity.as_ref(), "Local").to_owned()
+            }
+            Some(_) => "Opponent".to_owned(),
+        }
+    }
+    /// The color played on this computer, None in a local game where both are
+    /// and when spectating where neither is
+    #[inline]
+    fn local_color(&self) -> Option<Color> {
+        self.seat().local_color()
+    }
+
+    /// Who plays at this window and what they wait on
+    fn seat(&self) -> Seat {
+        match &self.network {
+            None => Seat::Local {
+                computer: self.computer_color,
+            },
+            Some(network) => Seat::Network {
+                color: network.player_color,
+                spectating: network.spectating,
+                connected: network.connected,
+                pending: self.pending_move.is_some(),
+            },
+        }
+    }
+
+    #[inline]
+    fn is_spectating(&self) -> bool {
+        self.network
+            .as_ref()
+            .is_some_and(|network| network.spectating)
+    }
+
+    /// Open or close the piece legend, only for local games and spectators
+    fn toggle_legend(&mut self) {
+        if self.network.is_some() && !self.is_spectating() {
+            println!("The legend is only shown in local games and when spectating");
+            return;
+        }
+        // Closing halfway through opening starts from the whole width, it is only a moment
+        self.legend_open = !self.legend_open;
+        self.legend_toggled = Some(self.now);
+    }
+
+    /// Write the move list in the next notation, coordinates after the last one
+    fn switch_notation(&mut self) {
+        let notation = Notation::next(self.session.notation, self.letters, self.figurines_render);
+        self.session.set_notation(notation);
+        if !self.review_lines.is_empty() {
+            self.judge_moves();
+        }
+        println!(
+            "Notation {}",
+            notation.map_or("coordinates", Notation::name)
+        );
+        self.mirror_to_tty();
+    }
+
+    /// Where everything goes in a window of this size as drawn now
+    #[inline]
+    fn plan(&self, width: f32, height: f32) -> LayoutPlan {
+        LayoutPlan::new(
+            width,
+            height,
+            self.splitter.panel_fraction(self.now),
+            self.legend_fraction(),
+            self.splitter.orientation(),
+        )
+    }
+
+    /// The legend's part of the window width as drawn now, sliding while it opens or closes
+    fn legend_fraction(&self) -> f32 {
+        let t = self.legend_toggled.map_or(1.0, |start| {
+            (self.now.saturating_sub(start).as_secs_f32() / LEGEND_DURATION.as_secs_f32()).min(1.0)
+        });
+        LEGEND_FRACTION
+            * match self.legend_open {
+                true => ease_out(t),
+                false => 1.0 - ease_out(t),
+            }
+    }
+
+    /// Who plays `color` as the legend names them
+    fn player_name(&self, color: Color) -> String {
+        if let Some(watch) = &self.engine_match {
+            return format!("{} (depth {})", color_name(color), watch.depth(color));
+        }
+        let who = match self.local_color() {
+            Some(local) if local == color => match &self.identity {
+                Some(identity) => return format!("{} ({})", color_name(color), identity.handle),
+                None => " (you)",
+            },
+            Some(_) if self.computer_color.is_some() => " (computer)",
+            Some(_) => " (opponent)",
+            None => "",
+        };
+        format!("{}{}", color_name(color), who)
+    }
+
+    /// Whether the local player is the one to move and isn't waiting on the server
+    #[inline]
+    fn is_local_turn(&self) -> bool {
+        turn::is_local_turn(self.seat(), self.session.board.get_curr_player())
+    }
+
+    /// A click on the board while the other side moves. Our pieces shake and the first time
+    /// say why, or with --premove pick up the move to play once the turn comes.
+    fn click_out_of_turn(&mut self, square: (usize, usize)) {
+        let squares = &self.session.board_repr.squares;
+        match turn::out_of_turn_click(self.seat(), squares, square, self.premoves) {
+            OutOfTurn::Shake => {
+                self.shake = Some(Shake {
+                    square,
+                    start: self.now,
+                });
+                if !self.explained_waiting {
+                    self.explained_waiting = true;
+                    self.toasts.push(
+                        "Your pieces are dimmed until it is your turn",
+                        WAITING_TOAST_DURATION,
+                    );
+                }
+            }
+            // The piece picked up again is put down
+            OutOfTurn::Premove if self.premove.is_some_and(|premove| premove.from == square) => {
+                self.premove = None
+            }
+            OutOfTurn::Premove => {
+                self.premove = Some(Premove {
+                    from: square,
+                    to: None,
+                })
+            }
+            OutOfTurn::Ignore => {
+                if let Some(premove) = &mut self.premove {
+                    premove.to = Some(square);
+                }
+            }
+        }
+    }
+
+    /// Play the move picked up while waiting once the turn comes, it is dropped if it isn't
+    /// legal by then
+    fn play_premove(&mut self) {
+        let Some(Premove { from, to: Some(to) }) = self.premove else {
+            return;
+        };
+        if !self.is_playing() {
+            self.premove = None;
+            return;
+        }
+        if !self.is_local_turn() || self.countdown.is_some() {
+            return;
+        }
+        self.premove = None;
+        let legal = self.session.board_repr.moves.iter().any(|mv| {
+            parse_move(&mv.to_algebraic_notation()).is_ok_and(|squares| squares == (from, to))
+        });
+        if !legal {
+            return;
+        }
+        // Picked up and put down the way a click does, a promotion still opens the chooser
+        self.session.click(from.0, from.1);
+        if let Some(mv) = self.session.click(to.0, to.1) {
+            self.play_move(&mv);
+        }
+    }
+
+    /// Draw the sprite of a piece scaled to fill `dest`
+    #[inline]
+    fn draw_piece(&self, canvas: &mut Canvas, piece: &Square, dest: Rect, tint: graphics::Color) {
+        self.render.pieces.draw(canvas, piece, dest, tint);
+    }
+
+    /// The move of the selected piece a click at `point` goes to with --accessible-input, in
+    /// squares as drawn. A click on one of our pieces still selects it, and the chooser takes
+    /// clicks only on its choices.
+    fn grown_destination(&self, point: (f32, f32)) -> Option<(usize, usize)> {
+        let expansion = self.target_expansion?;
+        let repr = &self.session.board_repr;
+        if repr.selected_to.is_some() {
+            return None;
+        }
+        let (row, col) = repr.selected_from?;
+        let on_board =
+            (0.0..COL_COUNT_F32).contains(&point.0) && (0.0..ROW_COUNT_F32).contains(&point.1);
+        if on_board {
+            let (row, col) = repr.view_to_board((point.1 as usize, point.0 as usize));
+            if repr.squares[row][col].color() == Some(repr.to_move) {
+                return None;
+            }
+        }
+        let destinations: Vec<_> = repr.legal_moves[row][col]
+            .keys()
+            .map(|cords| repr.board_to_view(*cords))
+            .collect();
+        accessibility::nearest_destination(point, &destinations, expansion)
+            .map(|view| repr.view_to_board(view))
+    }
+
+    /// The square of the board drawn in `board` at `x`, `y`
+    fn square_under(&self, board: Rect, x: f32, y: f32) -> Option<(usize, usize)> {
+        if !board.contains(Point2 { x, y }) {
+            return None;
+        }
+        let view = (
+            min(((y - board.y) * ROW_COUNT_F32 / board.h) as usize, 7),
+            min(((x - board.x) * COL_COUNT_F32 / board.w) as usize, 7),
+        );
+        Some(self.session.board_repr.view_to_board(view))
+    }
+
+    /// Whether resting the pointer at `x`, `y` mustn't click, while the splitter is dragged
+    /// or the piece on the square under it is still sliding there
+    fn dwell_blocked(&self, ctx: &Context, x: f32, y: f32) -> bool {
+        if self.splitter.is_active() {
+            return true;
+        }
+        let (width, height) = ctx.gfx.drawable_size();
+        let board = rect(self.plan(width, height).board);
+        let Some(square) = self.square_under(board, x, y) else {
+            return false;
+        };
+        let now = ctx.time.time_since_start();
+        self.animations.iter().any(|animation| {
+            (animation.to == square || animation.from == square) && !animation.is_done(now)
+        })
+    }
+
+    #[inline]
+    fn draw_text(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        area: Rect,
+        message: &str,
+        line: TextLine,
+    ) {
+        self.render.draw_text(ctx, canvas, area, message, line);
+    }
+
+    #[inline]
+    fn with_text<R>(&self, string: &str, scale: f32, use_text: impl FnOnce(&mut Text) -> R) -> R {
+        self.render.with_text(string, scale, use_text)
+    }
+
+    /// Draw the text at `scale` from the cache
+    #[inline]
+    fn draw_cached_text(
+        &self,
+        canvas: &mut Canvas,
+        string: &str,
+        scale: f32,
+        param: graphics::DrawParam,
+    ) {
+        self.with_text(string, scale, |text| canvas.draw(text, param));
+    }
+
+    /// The clock of the side at the top above the board, the other one below it
+    fn draw_clocks(&self, ctx: &Context, canvas: &mut Canvas, board: Rect) {
+        let Some(clock) = &self.clock else {
+            return;
+        };
+        // The bars are as high as the space left above the board
+        let bar = board.y;
+        let bottom = match self.session.board_repr.flipped {
+            true => Color::Black,
+            false => Color::White,
+        };
+
+        for (color, y) in [(opposite_color(bottom), 0.0), (bottom, board.y + board.h)] {
+            let remaining = clock.remaining(color);
+            let running = self.session.game_state == GameState::Ongoing
+                && self.session.board.get_curr_player() == color;
+            let param = graphics::DrawParam::default().color(match running {
+                true if remaining < Duration::from_secs(10) => ERROR_TEXT_COLOR,
+                true => HISTORY_TEXT_COLOR,
+                false => WAITING_PIECE_COLOR,
+            });
+            // The name never changes, only the time is laid out again when it ticks. The local
+            // player's handle is shown in its color.
+            let (label, label_param) = match &self.identity {
+                Some(identity) if self.local_color() == Some(color) => (
+                    format!("{} ", identity.handle),
+                    param.color(avatar_color(identity)),
+                ),
+                _ => (format!("{} ", color_name(color)), param),
+            };
+            let x = board.x + bar / 4.0;
+            let label_width = self.with_text(&label, bar * 0.8, |text| {
+                canvas.draw(
+                    text,
+                    label_param.dest(Point2 {
+                        x,
+                        y: y + bar * 0.1,
+                    }),
+                );
+                text.dimensions(ctx).map_or(0.0, |dims| dims.w)
+            });
+            self.draw_cached_text(
+                canvas,
+                &format_time(remaining),
+                bar * 0.8,
+                param.dest(Point2 {
+                    x: x + label_width,
+                    y: y + bar * 0.1,
+                }),
+            );
+        }
+    }
+
+    /// The move being typed in a bar across the bottom of the window
+    fn draw_move_entry(&self, canvas: &mut Canvas) {
+        let Some(entry) = &self.move_entry else {
+            return;
+        };
+        let bar = entry_bar(canvas.screen_coordinates().unwrap());
+        let height = bar.h;
+        canvas.draw(
+            &self.render.bar_mesh,
+            graphics::DrawParam::default()
+                .dest_rect(bar)
+                .color(match entry.rejected {
+                    true => MOVE_ENTRY_REJECTED_COLOR,
+                    false => MOVE_ENTRY_COLOR,
+                }),
+        );
+
+        self.draw_cached_text(
+            canvas,
+            &format!(
+                "{}: {}_",
+                match entry.bookmark {
+                    Some(_) => "Bookmark label (Enter keeps it, Escape skips it)",
+                    None => "Move",
+                },
+                entry.text
+            ),
+            height * 0.8,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: bar.x + height / 4.0,
+                    y: bar.y + height * 0.1,
+                })
+                .color(HISTORY_TEXT_COLOR),
+        );
+    }
+
+    /// Whose turn it is and which piece is which, kinds of piece gone from the board faded
+    fn draw_legend(&self, canvas: &mut Canvas, area: Rect) {
+        canvas.draw(
+            &self.render.bar_mesh,
+            graphics::DrawParam::default()
+                .dest_rect(area)
+                .color(LEGEND_COLOR),
+        );
+        // Two lines for the players, then a row for every kind of piece
+        let row = area.h / (PIECE_GUIDE.len() + 2) as f32;
+        let margin = area.w * 0.05;
+
+        let to_move = self.session.board.get_curr_player();
+        let ongoing = self.session.game_state == GameState::Ongoing;
+        for (i, color) in [Color::White, Color::Black].into_iter().enumerate() {
+            let marker = match ongoing && color == to_move {
+                true => "> ",
+                false => "  ",
+            };
+            self.draw_cached_text(
+                canvas,
+                &format!("{}{}", marker, self.player_name(color)),
+                row * 0.3,
+                graphics::DrawParam::default()
+                    .dest(Point2 {
+                        x: area.x + margin,
+                        y: area.y + (i as f32 + 0.35) * row,
+                    })
+                    .color(HISTORY_TEXT_COLOR),
+            );
+        }
+
+        // Of the position shown, which is an older one while reviewing
+        let on_board = kinds_on_board(
+            self.review_squares
+                .as_ref()
+                .unwrap_or(&self.session.board_repr.squares),
+        );
+        let sprite = (row * 0.8).min(area.w * 0.3);
+        for (i, (guide, present)) in PIECE_GUIDE.iter().zip(on_board).enumerate() {
+            let y = area.y + (i + 2) as f32 * row;
+            let (tint, text_color) = match present {
+                true => (graphics::Color::WHITE, HISTORY_TEXT_COLOR),
+                false => (GONE_PIECE_COLOR, GONE_TEXT_COLOR),
+            };
+            self.draw_piece(
+                canvas,
+                &(guide.piece)(to_move),
+                Rect::new(area.x + margin, y + (row - sprite) / 2.0, sprite, sprite),
+                tint,
+            );
+
+            let x = area.x + 2.0 * margin + sprite;
+            let param = |top: f32| {
+                graphics::DrawParam::default()
+                    .dest(Point2 {
+                        x,
+                        y: y + top * row,
+                    })
+                    .color(text_color)
+            };
+            self.draw_cached_text(canvas, guide.name, row * 0.25, param(0.1));
+            // Wrapped to the width the legend has now, which only changes with the window
+            self.with_text(guide.moves, row * 0.16, |text| {
+                text.set_bounds(Vector2 {
+                    x: area.x + area.w - margin - x,
+                    y: row * 0.6,
+                });
+                canvas.draw(text, param(0.4));
+            });
+        }
+    }
+
+    /// Pieces each side has taken in its bar, after the clock, and how far ahead it is
+    fn draw_captures(&self, canvas: &mut Canvas, board: Rect) {
+        let bar = board.y;
+        let bottom = match self.session.board_repr.flipped {
+            true => Color::Black,
+            false => Color::White,
+        };
+        // The position shown, which is an older one while reviewing
+        let plies = self.viewing_ply.unwrap_or(self.session.history.len());
+        let squares = self
+            .review_squares
+            .as_ref()
+            .unwrap_or(&self.session.board_repr.squares);
+        let balance = material_balance(squares);
+
+        for (color, y) in [(opposite_color(bottom), 0.0), (bottom, board.y + board.h)] {
+            let mut captured: Vec<Square> = self.session.history[..plies]
+                .iter()
+                .filter(|entry| entry.color == color)
+                .filter_map(|entry| entry.captured)
+                .collect();
+            captured.sort_by_key(material_value);
+
+            // Leave room for the clock when there is one
+            let mut x = board.x
+                + match self.clock {
+                    Some(_) => board.w * 0.4,
+                    None => bar / 4.0,
+                };
+            for piece in &captured {
+                let dest = Rect::new(x, y + bar * 0.05, bar * 0.9, bar * 0.9);
+                self.draw_piece(canvas, piece, dest, graphics::Color::WHITE);
+                x += bar * 0.9 * CAPTURED_PIECE_SPACING;
+            }
+
+            let lead = match color {
+                Color::White => balance,
+                Color::Black => -balance,
+            };
+            if lead > 0 {
+                self.draw_cached_text(
+                    canvas,
+                    &format!("+{}", lead),
+                    bar * 0.8,
+                    graphics::DrawParam::default()
+                        .dest(Point2 {
+                            x: x + bar * 0.5,
+                            y: y + bar * 0.1,
+                        })
+                        .color(HISTORY_TEXT_COLOR),
+                );
+            }
+        }
+    }
+
+    /// Numbered move pairs, scrolled `history_scroll` lines up from the latest move.
+    /// While reviewing the moves carry their judgements and the graph takes the bottom lines.
+    fn draw_history(&self, canvas: &mut Canvas, plan: &LayoutPlan) {
+        let panel = rect(plan.history);
+        let line_height = plan.line_height;
+        let count = plan.visible_lines(self.viewing_ply.is_some());
+        let lines = match self.viewing_ply {
+            Some(_) => &self.review_lines,
+            None => &self.session.history_lines,
+        };
+
+        let visible = layout::visible_range(lines.len(), self.history_scroll, count);
+        let first = visible.start;
+        for (i, line) in lines[visible].iter().enumerate() {
+            let marked;
+            let glyphs: Vec<&str> = [
+                self.line_bookmark(first + i).map(|_| bookmarks::GLYPH),
+                self.line_negotiation(first + i)
+                    .then_some(negotiation::GLYPH),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            let line = match glyphs.is_empty() {
+                false => {
+                    marked = format!("{}  {}", line, glyphs.join(" "));
+                    &marked
+                }
+                true => line,
+            };
+            self.draw_cached_text(
+                canvas,
+                line,
+                line_height * 0.8,
+                graphics::DrawParam::default()
+                    .dest(Point2 {
+                        x: panel.x + line_height / 2.0,
+                        y: panel.y + i as f32 * line_height,
+                    })
+                    .color(HISTORY_TEXT_COLOR),
+            );
+        }
+    }
+
+    /// The score of every position as a line over the axis, White's advantage above it,
+    /// with the reviewed position and the judged moves marked
+    fn draw_graph(&self, ctx: &Context, canvas: &mut Canvas, area: Rect, ply: usize) -> GameResult {
+        let at = |(x, y): (f32, f32)| Point2 {
+            x: area.x + x * area.w,
+            y: area.y + y * area.h,
+        };
+        let scores = &self.session.scores;
+        let points = self.graph_points.as_deref().unwrap_or_default();
+        let mut mesh = graphics::MeshBuilder::new();
+        mesh.rectangle(DrawMode::fill(), area, GRAPH_BACKGROUND_COLOR)?;
+
+        if let (Some(&(first, _)), Some(&(last, _)), true) =
+            (points.first(), points.last(), points.len() > 1)
+        {
+            // Each side's advantage is filled between the axis and its half of the line
+            for (color, side) in [
+                (GRAPH_WHITE_COLOR, f32::min as fn(f32, f32) -> f32),
+                (GRAPH_BLACK_COLOR, f32::max),
+            ] {
+                let outline: Vec<Point2<f32>> = std::iter::once((first, 0.5))
+                    .chain(points.iter().map(|&(x, y)| (x, side(y, 0.5))))
+                    .chain(std::iter::once((last, 0.5)))
+                    .map(at)
+                    .collect();
+                mesh.polygon(DrawMode::fill(), &outline, color)?;
+            }
+            let line: Vec<Point2<f32>> = points.iter().copied().map(at).collect();
+            mesh.line(&line, 1.5, GRAPH_PLY_COLOR)?;
+        }
+        mesh.line(&[at((0.0, 0.5)), at((1.0, 0.5))], 1.0, GRAPH_BLACK_COLOR)?;
+
+        let x = eval_graph::ply_x(ply, self.session.snapshots.len());
+        mesh.line(&[at((x, 0.0)), at((x, 1.0))], 2.0, GRAPH_PLY_COLOR)?;
+
+        // A judged move is marked on the position it led to
+        for (mv, judgement) in self.judgements.iter().enumerate() {
+            if let (Some(judgement), Some(point)) = (judgement, eval_graph::point(scores, mv + 1)) {
+                mesh.circle(
+                    DrawMode::fill(),
+                    at(point),
+                    3.5,
+                    0.1,
+                    judgement_color(*judgement),
+                )?;
+            }
+        }
+        canvas.draw(
+            &Mesh::from_data(ctx, mesh.build()),
+            graphics::DrawParam::default(),
+        );
+        Ok(())
+    }
+
+    /// The refresh after the opponent's move `mv` if it was worked out ahead, counted in the
+    /// metrics either way
+    fn take_speculation(&mut self, mv: &Move) -> Option<Refresh> {
+        let mut speculation = self.speculation.take()?;
+        // Worked out for a position that is gone, it says nothing about how well it guesses
+        if speculation.generation() != self.session.generation {
+            return None;
+        }
+        let prepared = speculation.take(
+            self.session.generation,
+            self.session.board_repr.excluded,
+            mv,
+        );
+        if let Some(metrics) = metrics::global() {
+            metrics.speculated(prepared.is_some());
+        }
+        prepared
+    }
+
+    /// Whether the other side of a computer or networked game is the one to move
+    fn opponent_to_move(&self) -> bool {
+        let to_move = self.session.board.get_curr_player();
+        match &self.network {
+            Some(network) => {
+                !network.spectating && network.connected && to_move != network.player_color
+            }
+            None => Some(to_move) == self.computer_color,
+        }
+    }
+
+    /// Start working out the refreshes after the opponent's likely replies, once per position
+    fn speculate(&mut self) {
+        let current = self
+            .speculation
+            .as_ref()
+            .is_some_and(|speculation| speculation.generation() == self.session.generation);
+        // Computer matches play too fast to be worth it
+        if current
+            || self.engine_match.is_some()
+            || self.session.game_state != GameState::Ongoing
+            || !self.opponent_to_move()
+        {
+            return;
+        }
+        self.speculation = Some(Speculation::start(
+            Line {
+                start_fen: self.session.start_fen.clone(),
+                moves: self.session.played_moves.clone(),
+            },
+            &self.session.board_repr,
+            self.session.generation,
+        ));
+    }
+
+    /// Play a move on the board and record it in the history, announced the way `application`
+    /// says
+    fn apply_move(&mut self, mv: Move, application: Application) {
+        let _stage = watchdog::checkpoint("applying move");
+        let mover = self.session.board.get_curr_player();
+        if let Some(clock) = &mut self.clock {
+            clock.add_increment(mover);
+        }
+        // Moving instead of answering lets the other side's offer lapse, the prompt goes with it
+        if let Some(offerer) = self
+            .session
+            .negotiations
+            .open()
+            .filter(|offerer| *offerer != mover)
+        {
+            self.negotiate(offerer, Outcome::Lapsed);
+            if self.confirming == Some(Confirmation::AcceptDraw) {
+                self.confirming = None;
+            }
+        }
+        let before = self.session.board_repr.squares;
+        let fen_before = self.session.fen();
+        let captured = match self.take_speculation(&mv) {
+            Some(prepared) => self.session.apply_prepared_move(mv, prepared),
+            None => self.session.apply_move(mv),
+        };
+        // The client hears of the server's move before the hooks and the autosave run, so a
+        // reply already on the way is judged against this position and the plies go out in order
+        if application == Application::Live {
+            self.announce_move(&mv);
+        }
+        self.graph_points = None;
+        self.check_traps();
+        self.find_pins();
+        if let Some(metrics) = metrics::global().filter(|_| self.network.is_some()) {
+            metrics.ply(self.session.history.len());
+        }
+        // Whatever was being reviewed, the new move is what matters now
+        self.review(None, application);
+        self.mirror_to_tty();
+        if let Some(watchdog) = watchdog::global() {
+            watchdog.position(self.session.board.to_fen());
+        }
+
+        let mut effects = Recorded::default();
+        application.dispatch(
+            if self.session.game_state != GameState::Ongoing {
+                Cue::GameOver
+            } else if self.session.board_repr.checked_king.is_some() {
+                Cue::Check
+            } else if captured.is_some() {
+                Cue::Capture
+            } else {
+                Cue::Move
+            },
+            &mut effects,
+        );
+        // Any slide still going is cut short, the board must never lag behind the game
+        self.animations = match effects.slides.contains(&Slide::Full) {
+            true => move_animations(&before, &self.session.board_repr.squares, &mv, self.now),
+            false => Vec::new(),
+        };
+        if let Some(cue) = effects.cues.last() {
+            self.pending_sound = Some(Sound::from(*cue));
+        }
+        match effects.notifications {
+            0 => self.trap_warning = None,
+            _ => {
+                if let Some(entry) = self.session.history.last() {
+                    self.tell_hook(&hook::Event::Moved {
+                        ply: self.session.history.len(),
+                        san: entry.san.clone(),
+                        uci: mv.to_algebraic_notation(),
+                        fen_before,
+                        fen_after: self.session.fen(),
+                        clocks: self.clocks(),
+                    });
+                }
+            }
+        }
+        if effects.autosaves > 0 {
+            self.write_autosave();
+        }
+    }
+
+    /// Warn the side to move of a trap it is about to fall for, unless it is the computer,
+    /// and follow the earlier warnings for whether the trap went off
+    fn check_traps(&mut self) {
+        if !self.assist || self.engine_match.is_some() {
+            return;
+        }
+        let ply = self.session.history.len();
+        let squares = &self.session.board_repr.squares;
+        let to_move = self.session.board.get_curr_player();
+        // A taken back move can be played again, only the warnings before it stay
+        self.trap_notes.retain(|note| note.ply < ply);
+        for note in &mut self.trap_notes {
+            note.follow(squares, to_move, ply);
+        }
+        self.trap_warning =
+            traps::warning_for(squares, to_move).filter(|_| Some(to_move) != self.computer_color);
+        if let Some(trap) = self.trap_warning {
+            println!("Trap warning at ply {}: {}", ply, trap.name);
+            self.trap_notes.push(TrapNote::new(trap, ply));
+        }
+    }
+
+    /// Find the pins on the pieces of the player to move with assist, unless it is the computer
+    fn find_pins(&mut self) {
+        let repr = &self.session.board_repr;
+        self.pins = match self.assist
+            && self.engine_match.is_none()
+            && Some(repr.to_move) != self.computer_color
+        {
+            true => pins::pins(
+                &repr.squares,
+                &repr.legal_moves,
+                repr.to_move,
+                self.relative_pins,
+            ),
+            false => Vec::new(),
+        };
+    }
+
+    /// How the computer plays in a game against it
+    fn computer_config(&self) -> Option<ComputerConfig> {
+        self.engine
+            .as_ref()
+            .zip(self.computer_color)
+            .map(|(engine, color)| ComputerConfig {
+                depth: engine.depth(),
+                color,
+            })
+    }
+
+    /// Who the game is played against, None for a networked game.
+    /// Practice and watched matches are set up by the app and aren't either.
+    fn opponent(&self) -> Option<Opponent> {
+        if self.network.is_some() || self.practice.is_some() || self.engine_match.is_some() {
+            return None;
+        }
+        Some(match self.computer_config() {
+            Some(config) => Opponent::Computer(config),
+            None => Opponent::Local,
+        })
+    }
+
+    /// Whether Ctrl+N may start the game over now
+    #[inline]
+    fn can_restart(&self) -> bool {
+        self.opponent().is_some() && self.confirming.is_none() && self.move_entry.is_none()
+    }
+
+    /// Whether the players of a local game may still trade colors
+    #[inline]
+    fn can_swap(&self) -> bool {
+        self.opponent()
+            .is_some_and(|opponent| Seating::can_swap(&opponent, self.session.played_moves.len()))
+            && self.confirming.is_none()
+            && self.move_entry.is_none()
+    }
+
+    /// The button that swaps the players, on the first line of the still empty move list
+    #[inline]
+    fn swap_button(&self, plan: &LayoutPlan) -> Area {
+        Area::new(
+            plan.history.x,
+            plan.history.y,
+            plan.history.w,
+            plan.line_height,
+        )
+    }
+
+    fn draw_swap_button(&self, canvas: &mut Canvas, plan: &LayoutPlan) {
+        if !self.can_swap() {
+            return;
+        }
+        let button = rect(self.swap_button(plan));
+        canvas.draw(
+            &self.render.bar_mesh,
+            graphics::DrawParam::default()
+                .dest_rect(button)
+                .color(SWAP_BUTTON_COLOR),
+        );
+        self.draw_cached_text(
+            canvas,
+            "Swap sides (S)",
+            button.h * 0.8,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: button.x + button.h / 4.0,
+                    y: button.y + button.h * 0.1,
+                })
+                .color(HISTORY_TEXT_COLOR),
+        );
+    }
+
+    /// Whether starting over throws away enough of an unfinished game to ask first
+    #[inline]
+    fn restart_needs_confirming(&self) -> bool {
+        self.session.game_state == GameState::Ongoing
+            && self.session.history.len() > RESTART_CONFIRM_PLIES
+    }
+
+    /// Save the game so it can be resumed, a finished game removes the save instead
+    fn write_autosave(&mut self) {
+        let computer = self.computer_config();
+        let Some(autosave) = &mut self.autosave else {
+            return;
+        };
+        if self.session.game_state != GameState::Ongoing {
+            autosave.remove();
+            return;
+        }
+        autosave.write(&SavedGame {
+            start_fen: self.session.start_fen.clone(),
+            moves: self.session.move_notations(),
+            fen: self.session.board.to_fen(),
+            computer,
+        });
+    }
+
+    /// Play the saved moves again so the history and review work like they were never gone
+    fn replay(&mut self, saved: &SavedGame) -> Result<(), String> {
+        for notation in &saved.moves {
+            let mv = self
+                .session
+                .board_repr
+                .moves
+                .iter()
+                .copied()
+                .find(|mv| mv.to_algebraic_notation() == *notation)
+                .ok_or_else(|| format!("{} is not a legal move", notation))?;
+            self.apply_move(mv, Application::Bulk);
+        }
+        if self.session.board.to_fen() != saved.fen {
+            return Err("the moves don't lead to the saved position".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Play the moves of a saved game and review it from the start, nothing is saved from it
+    fn load_pgn(&mut self, moves: &[String]) -> Result<(), String> {
+        self.autosave = None;
+        self.clock = None;
+        for token in moves {
+            let wanted = token.trim_end_matches(['+', '#', '!', '?']);
+            let mv = self
+                .session
+                .board_repr
+                .moves
+                .iter()
+                .copied()
+                .find(|mv| {
+                    pgn::san(&self.session.board_repr, mv) == wanted
+                        || mv.to_algebraic_notation() == wanted
+                })
+                .ok_or_else(|| format!("{} is not a legal move", token))?;
+            self.apply_move(mv, Application::Bulk);
+        }
+        // The file is already there, ending the game again shouldn't save it twice
+        self.auto_saved = true;
+        self.review(Some(0), Application::Bulk);
+        Ok(())
+    }
+
+    /// Whether P can play on against the computer from the reviewed position,
+    /// only once a networked game we played in is over
+    fn can_fork(&self) -> bool {
+        let over = self.session.game_state != GameState::Ongoing || self.disconnected;
+        self.network.is_some()
+            && over
+            && self.local_color().is_some()
+            && self.confirming.is_none()
+            && self.move_entry.is_none()
+    }
+
+    /// The practice game from the reviewed position, or the final one when not reviewing
+    fn fork(&self, depth: u32) -> Result<Fork, String> {
+        let player = self
+            .local_color()
+            .ok_or("only the players can practice from their game")?;
+        Fork::new(&self.session, self.viewing_ply, player, depth)
+    }
+
+    /// Whether the practice game ended long enough ago to go back to the game it came from
+    #[inline]
+    fn practice_over(&self) -> bool {
+        self.practice_ended
+            .is_some_and(|ended| self.now >= ended + PRACTICE_RETURN_DELAY)
+    }
+
+    /// Whether Escape has nothing left to close, so it would leave the game
+    #[inline]
+    fn escape_leaves(&self) -> bool {
+        self.confirming.is_none()
+            && self.move_entry.is_none()
+            && self.session.board_repr.selected_from.is_none()
+            && self.analysis.is_none()
+    }
+
+    /// Leave out the moves the peer can't handle and warn about anything else it lacks
+    fn use_features(&mut self) {
+        use chess_network_protocol::Features;
+        let Some(network) = &self.network else {
+            return;
+        };
+        let features = network.features().to_vec();
+        self.session.board_repr.excluded = ExcludedMoves::for_features(&features);
+        self.refresh_board();
+
+        let missing: Vec<&str> = network::supported_features()
+            .iter()
+            .filter(|feature| !features.contains(feature))
+            .map(|feature| match feature {
+                Features::Castling => "castling",
+                Features::EnPassant => "en passant",
+                Features::Promotion => "promoting to anything but a queen",
+                Features::Stalemate => "stalemate",
+                _ => "other rules",
+            })
+            .collect();
+        if !missing.is_empty() {
+            let warning = format!("The server doesn't support {}", missing.join(", "));
+            self.note(&warning);
+            self.feature_warning = Some(warning);
+        }
+    }
+
+    /// Keep the other side in the connection book with what it advertised, telling what
+    /// changed since the last game there
+    fn remember_connection(&mut self) {
+        let (Some(network), Some(entry)) = (&self.network, &mut self.book_entry) else {
+            return;
+        };
+        entry.features = network
+            .features()
+            .iter()
+            .map(|feature| connection_book::feature_name(feature).to_owned())
+            .collect();
+        entry.last_used = updates::seconds_since_epoch();
+        let mut book = Book::read(&self.connection_book);
+        if let Some(change) = book.record(entry.clone()) {
+            self.note(&change);
+            self.toasts.push(change, BOOK_TOAST_DURATION);
+        }
+        if let Err(err) = book.write(&self.connection_book) {
+            println!("{}", err);
+        }
+    }
+
+    /// Keep how the game ended with the other side in the connection book
+    fn remember_result(&self) {
+        let Some(entry) = self.book_entry.as_ref().filter(|entry| entry.last_used > 0) else {
+            return;
+        };
+        let result = match (
+            pgn::result_token(&self.session.game_state),
+            self.local_color(),
+        ) {
+            ("1-0", Some(Color::White)) | ("0-1", Some(Color::Black)) => "won",
+            ("1-0", _) | ("0-1", _) => "lost",
+            ("1/2-1/2", _) => "drawn",
+            _ => return,
+        };
+        let mut book = Book::read(&self.connection_book);
+        book.finish(&entry.address, result);
+        if let Err(err) = book.write(&self.connection_book) {
+            println!("{}", err);
+        }
+    }
+
+    /// Keep what the peer did in the interop ledger, once per session
+    fn remember_interop(&mut self) {
+        let Some(network) = self.network.as_ref().filter(|_| self.interop_started > 0) else {
+            return;
+        };
+        let session = std::mem::take(&mut self.interop).finish(
+            &network.entries(),
+            network.is_server,
+            self.interop_started,
+        );
+        self.interop_started = 0;
+        let mut ledger = Ledger::read(&self.interop_ledger);
+        ledger.record(session);
+        if let Err(err) = ledger.write(&self.interop_ledger) {
+            println!("{}", err);
+        }
+    }
+
+    /// End the game when the server did, like on a draw by a rule this side doesn't count
+    fn use_server_result(&mut self, joever: &chess_network_protocol::Joever) {
+        let before = self.session.game_state;
+        self.session.follow_server_result(joever);
+        if self.session.game_state != before {
+            self.mirror_to_tty();
+        }
+    }
+
+    /// The server's board is the real one, switch to it if ours went another way
+    fn use_server_board(
+        &mut self,
+        board: &[[chess_network_protocol::Piece; 8]; 8],
+        moves: &[chess_network_protocol::Move],
+    ) {
+        match self.session.follow_server_board(board, moves) {
+            Ok(false) => {}
+            Ok(true) => {
+                self.interop.note(Incident::Desync);
+                self.note(&format!(
+                    "Out of sync with the server, using its board: {}",
+                    self.session.fen()
+                ));
+                self.error_message =
+                    Some("Out of sync with the server, using its board".to_owned());
+                self.review(None, Application::Resync);
+                self.mirror_to_tty();
+            }
+            Err(message) => self.note(&format!("Can't use the board from the server: {}", message)),
+        }
+    }
+
+    /// Offer only the moves the server listed, so none of ours gets rejected.
+    /// An empty list from a server that doesn't send moves keeps the ones worked out here.
+    fn use_server_moves(&mut self, moves: &[chess_network_protocol::Move]) {
+        let legal_moves = &self.session.board_repr.moves;
+        if moves.is_empty() && !legal_moves.is_empty() {
+            self.interop.note(Incident::NoMovesListed);
+            self.note("The server sent no moves, offering the legal ones instead");
+            return;
+        }
+        let allowed = network::network_to_internal_moves(moves, legal_moves);
+        if allowed.len() != moves.len() || allowed.len() != legal_moves.len() {
+            self.interop.note(Incident::MovesMismatch);
+        }
+        self.session.board_repr.offer_only(&allowed);
+    }
+
+    /// Start over from the position the server sent, unless it is the one we already have
+    fn use_server_position(&mut self, fen: String) {
+        let position = |fen: &str| fen.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+        if position(&fen) == position(&self.session.board.to_fen()) {
+            return;
+        }
+        match self.session.restart(fen) {
+            Ok(()) => {
+                self.review(None, Application::Resync);
+                self.mirror_to_tty();
+            }
+            Err(message) => println!(
+                "Can't use the starting position from the server: {}",
+                message
+            ),
+        }
+    }
+
+    /// Show the position after `ply`, None or the latest ply go back to the live board.
+    /// Getting to a reviewed position sounds the way `application` says, nothing slides.
+    fn review(&mut self, ply: Option<usize>, application: Application) {
+        let latest = self.session.snapshots.len() - 1;
+        self.viewing_ply = ply.filter(|ply| *ply < latest);
+        let mut effects = Recorded::default();
+        self.review_squares = self.viewing_ply.and_then(|ply| {
+            effects::review_position(&self.session, ply, application, &mut effects)
+        });
+        if let Some(cue) = effects.cues.last() {
+            self.pending_sound = Some(Sound::from(*cue));
+        }
+        self.animations.clear();
+        // A half made move on the live board would be confusing once it is back
+        if let Some(ply) = self.viewing_ply {
+            self.glyph_flash = Some((ply, self.now));
+            self.clear_selection();
+            self.start_analysis();
+            // Only new scores or moves change them, stepping through the game doesn't
+            if self.graph_points.is_none() {
+                self.judge_moves();
+            }
+        } else {
+            // Dropping the pass stops it, whatever it scored so far is kept
+            self.analysis = None;
+        }
+    }
+
+    /// Whether a networked game is still being played, which nothing may hold up
+    #[inline]
+    fn live(&self) -> bool {
+        self.network.is_some()
+            && !self.disconnected
+            && self.session.game_state == GameState::Ongoing
+    }
+
+    /// Score the positions the graph is missing in the background. Never while a networked
+    /// game is still being played, the search would slow down the live game.
+    fn start_analysis(&mut self) {
+        if self.live() || self.analysis.is_some() {
+            return;
+        }
+        let positions: Vec<(usize, String)> = self
+            .session
+            .scores
+            .iter()
+            .zip(&self.session.snapshots)
+            .enumerate()
+            .filter(|(_, (score, _))| score.is_none())
+            .map(|(ply, (_, fen))| (ply, fen.clone()))
+            .collect();
+        if !positions.is_empty() {
+            self.analysis = Some(Analysis::start(positions, REVIEW_DEPTH));
+        }
+    }
+
+    /// Take in whatever the background pass scored since the last frame
+    fn poll_analysis(&mut self) {
+        let Some(analysis) = &mut self.analysis else {
+            return;
+        };
+        let mut scored = false;
+        while let Some((ply, score)) = analysis.poll() {
+            if let Some(slot) = self.session.scores.get_mut(ply) {
+                *slot = score;
+            }
+            scored = true;
+        }
+        if analysis.is_done() {
+            self.analysis = None;
+        }
+        if scored {
+            self.judge_moves();
+        }
+    }
+
+    /// Judge every move by the scores so far and put the glyphs into the reviewed move list
+    fn judge_moves(&mut self) {
+        self.judgements = eval_graph::judgements(&self.session.scores, &self.session.history);
+        self.graph_points = Some(eval_graph::split_at_axis(&eval_graph::polyline(
+            &self.session.scores,
+        )));
+        self.review_lines = self
+            .session
+            .history
+            .chunks(2)
+            .zip(self.judgements.chunks(2))
+            .enumerate()
+            .map(|(i, (pair, judged))| {
+                let moves: Vec<String> = pair
+                    .iter()
+                    .zip(judged)
+                    .map(|(entry, judgement)| {
+                        // A glyph set by hand says more than the engine's
+                        let glyph = match (entry.glyph, judgement) {
+                            (Some(glyph), _) => glyph.symbol(),
+                            (None, Some(judgement)) => judgement.glyph(),
+                            (None, None) => "",
+                        };
+                        format!("{}{}", entry.shown(self.session.notation), glyph)
+                    })
+                    .collect();
+                format!("{}. {}", i + 1, moves.join(" "))
+            })
+            .collect();
+    }
+
+    /// Set the glyph of the move that led to the reviewed position, or clear it with None
+    fn annotate(&mut self, glyph: Option<Glyph>) {
+        let Some(ply) = self.viewing_ply.filter(|ply| *ply > 0) else {
+            return;
+        };
+        self.session.set_glyph(ply - 1, glyph);
+        self.glyph_flash = Some((ply, self.now));
+        self.judge_moves();
+    }
+
+    /// Ask before marking the moves the evaluation pass found to be mistakes or blunders
+    fn offer_auto_annotate(&mut self) {
+        if self.analysis.is_some() {
+            println!("The moves can be marked once the evaluation is done");
+            return;
+        }
+        match glyphs::auto_annotate(&self.judgements, &self.session.glyphs()).len() {
+            0 => println!("No move without a glyph was a mistake or a blunder"),
+            count => self.confirming = Some(Confirmation::AutoAnnotate(count)),
+        }
+    }
+
+    /// Mark the mistakes and blunders with ? and ??, the glyphs set by hand stay
+    fn auto_annotate(&mut self) {
+        for (mv, glyph) in glyphs::auto_annotate(&self.judgements, &self.session.glyphs()) {
+            self.session.set_glyph(mv, Some(glyph));
+        }
+        self.judge_moves();
+    }
+
+    /// Take back the last move by replaying every move before it, only in local games
+    fn undo(&mut self) {
+        if self.engine_match.is_some() {
+            return;
+        }
+        if self.network.is_some() {
+            println!("Undo is only available in local games");
+            return;
+        }
+        if self.session.undo().is_none() {
+            return;
+        }
+        self.find_pins();
+        self.graph_points = None;
+        self.review(None, Application::Live);
+        self.mirror_to_tty();
+        self.write_autosave();
+
+        // Against the computer its reply is taken back too so the player is to move again
+        if Some(self.session.board.get_curr_player()) == self.computer_color
+            && !self.session.played_moves.is_empty()
+        {
+            self.undo();
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.network.is_some() || self.engine_match.is_some() {
+            return;
+        }
+        let Some(mv) = self.session.undone_moves.pop() else {
+            return;
+        };
+        // Applying the move forgets the redo stack, keep the rest of it
+        let undone_moves = std::mem::take(&mut self.session.undone_moves);
+        self.apply_move(mv, Application::Live);
+        self.session.undone_moves = undone_moves;
+
+        // Like undo, the computer's reply comes back with the player's move
+        if Some(self.session.board.get_curr_player()) == self.computer_color {
+            self.redo();
+        }
+    }
+
+    /// Cut the slide of the move just applied to how stale it was on arriving, or snap it. Only
+    /// the slide changes: its sound is already chosen, and the clock was switched as it arrived.
+    /// A new move replaces whatever slide was going, so moves coming fast never queue.
+    fn shorten_slide(&mut self, staleness: Option<Duration>) {
+        let duration = self.slide_curve.slide_time(staleness, ANIMATION_DURATION);
+        let now = self.now;
+        self.animations.retain_mut(|animation| {
+            if animation.start_time != now {
+                return true;
+            }
+            animation.duration = duration;
+            !duration.is_zero()
+        });
+    }
+
+    /// Play the move the server reports, either ours being confirmed or the opponent's
+    fn server_play_move(&mut self, opponent_move: &chess_network_protocol::Move) {
+        if let Some(mv) = network_to_internal_move(opponent_move, &self.session.board_repr.moves) {
+            self.apply_move(mv, Application::Live);
+            return;
+        }
+        // The protocol has no way to answer the server, so say it here and take its board after
+        self.interop.note(Incident::IllegalMove);
+        self.note(&format!(
+            "Rejected move from the server: {:?} isn't legal in {}",
+            opponent_move,
+            self.session.fen()
+        ));
+        self.error_message = Some("The server played a move that isn't legal here".to_owned());
+    }
+
+    /// Play a move suggested by the client if it is legal and tell the client the outcome
+    fn client_play_move(&mut self, client_move: &chess_network_protocol::Move) {
+        // Only the server reads client moves, and it always plays
+        let Some(server_color) = self.local_color() else {
+            return;
+        };
+        let in_turn = self.session.game_state == GameState::Ongoing
+            && self.session.board.get_curr_player() != server_color;
+        let validated =
+            self.session
+                .judge_client_move(client_move, server_color, &mut self.strikes);
+        match &validated {
+            Ok(mv) => self.apply_move(*mv, Application::Live),
+            Err((_, Some(Verdict::Forfeit))) => {
+                self.confirming = None;
+                self.clear_selection();
+                self.pending_sound = Some(Sound::GameOver);
+                self.mirror_to_tty();
+            }
+            Err(_) => {}
+        }
+        if validated.is_err() {
+            self.interop.note(match in_turn {
+                true => Incident::IllegalMove,
+                false => Incident::OutOfTurn,
+            });
+        }
+        // Only a move sent on top of one the client already had accepted gets here, a reply
+        // sent before the server's move arrived is judged after that move is played
+        if validated.is_err() && !in_turn && self.session.game_state == GameState::Ongoing {
+            self.toasts.push(
+                "The opponent moved out of turn, the move was sent back",
+                TURN_TOAST_DURATION,
+            );
+        }
+
+        let Some(network) = &self.network else {
+            return;
+        };
+        match validated {
+            // Sent as it was applied
+            Ok(_) => {}
+            Err((message, Some(Verdict::Forfeit))) => {
+                self.note(&format!(
+                    "Rejected client move {:?}: {}, that is too many and the client forfeits",
+                    client_move, message
+                ));
+                network.send_illegal_moves_forfeit(&self.session);
+            }
+            Err((message, verdict)) => {
+                self.note(&match verdict {
+                    Some(Verdict::Counted(count)) => format!(
+                        "Rejected client move {:?}: {}, strike {}",
+                        client_move, message, count
+                    ),
+                    Some(Verdict::Repeated) => format!(
+                        "Rejected client move {:?} again: {}, it isn't counted twice",
+                        client_move, message
+                    ),
+                    _ => format!("Rejected client move {:?}: {}", client_move, message),
+                });
+                network.send_error(&self.session, message);
+            }
+        }
+    }
+
+    /// Deal with a handshake the peer sent again after the game started, the server's side
+    /// of the game wins when it disagrees with ours
+    fn rehandshake(&mut self, handshake: &network::Handshake) {
+        let Some(network) = &self.network else {
+            return;
+        };
+        let is_server = network.is_server;
+        let server_color = match is_server {
+            true => network.player_color,
+            false => opposite_color(network.player_color),
+        };
+        let judged = self.session.judge_rehandshake(
+            handshake,
+            is_server,
+            server_color,
+            network.features(),
+            self.strict_handshakes,
+        );
+        self.interop.note(match judged {
+            Rehandshake::Duplicate => Incident::DuplicateHandshake,
+            Rehandshake::Desync => Incident::DisagreeingHandshake,
+            Rehandshake::Violation => Incident::MidGameHandshake,
+        });
+        match (judged, handshake) {
+            (Rehandshake::Duplicate, _) => {
+                self.note("The peer's handshake agrees with the game, ignoring it")
+            }
+            (Rehandshake::Desync, network::Handshake::ServerToClient(handshake)) => {
+                self.note("The server's handshake disagrees with the game, using its board");
+                self.use_server_board(&handshake.board, &handshake.moves);
+                self.use_server_moves(&handshake.moves);
+                self.use_server_result(&handshake.joever);
+            }
+            (Rehandshake::Desync, network::Handshake::ClientToServer(_)) => {
+                self.note("The client's handshake wants other colors, sending it the board");
+                network.send_error(
+                    &self.session,
+                    "The game is already under way, the colors stay as they are".to_owned(),
+                );
+            }
+            (Rehandshake::Violation, _) if is_server => {
+                let verdict = self.session.count_strike(
+                    Strike::Protocol,
+                    "handshake",
+                    server_color,
+                    &mut self.strikes,
+                );
+                let Some(network) = &self.network else {
+                    return;
+                };
+                if verdict != Verdict::Forfeit {
+                    self.note("Rejected a handshake from the client after the game started");
+                    network.send_error(
+                        &self.session,
+                        "The handshake is over, only moves are taken now".to_owned(),
+                    );
+                    return;
+                }
+                self.note("Rejected a handshake from the client, that is too many and it forfeits");
+                network.send_illegal_moves_forfeit(&self.session);
+                self.confirming = None;
+                self.clear_selection();
+                self.pending_sound = Some(Sound::GameOver);
+                self.mirror_to_tty();
+            }
+            (Rehandshake::Violation, _) => {
+                self.note("Rejected a handshake from the server after the game started");
+                self.error_message =
+                    Some("The server sent its handshake again mid-game".to_owned());
+                if let Some(metrics) = metrics::global() {
+                    metrics.protocol_error();
+                }
+            }
+        }
+    }
+
+    fn server_message(&mut self, message: ServerToClient) {
+        match message {
+            ServerToClient::State {
+                board,
+                moves,
+                joever,
+                move_made,
+            } => {
+                // Either our pending move being confirmed or the opponent's move
+                let remote = self.pending_move.take().is_none();
+                if !remote {
+                    self.round_trip.answered(self.now);
+                }
+                self.error_message = None;
+                self.server_play_move(&move_made);
+                if remote {
+                    self.shorten_slide(self.round_trip.staleness());
+                }
+                self.refresh_board();
+                self.use_server_board(&board, &moves);
+                self.use_server_moves(&moves);
+                self.use_server_result(&joever);
+            }
+            ServerToClient::Error {
+                joever, message, ..
+            } if message == network::OUT_OF_TIME || message == network::ILLEGAL_MOVES_FORFEIT => {
+                self.pending_move = None;
+                self.session.follow_forfeit(&joever, &message);
+                self.confirming = None;
+                self.clear_selection();
+                self.pending_sound = Some(Sound::GameOver);
+                self.mirror_to_tty();
+            }
+            ServerToClient::Error {
+                board,
+                moves,
+                message,
+                ..
+            } => {
+                // Our suggested move was rejected, let the player pick another one
+                self.note(&format!("Server rejected move: {}", message));
+                self.pending_move = None;
+                self.error_message = Some(message);
+                self.use_server_board(&board, &moves);
+                self.use_server_moves(&moves);
+            }
+            ServerToClient::Resigned { joever, .. } => {
+                self.pending_move = None;
+                let resigner = match joever {
+                    chess_network_protocol::Joever::White => Color::Black,
+                    chess_network_protocol::Joever::Black => Color::White,
+                    // No winner given, it has to be the opponent that resigned
+                    _ => self
+                        .local_color()
+                        .map_or(self.session.board.get_curr_player(), opposite_color),
+                };
+                self.session.game_state = GameState::Resigned(resigner);
+                self.mirror_to_tty();
+            }
+            ServerToClient::Draw { .. } => self.receive_draw(),
+        }
+    }
+
+    /// Time the opponent's move against the cap. The peer never agreed to it, so going past it
+    /// is only warned about here and the win is only claimed here.
+    fn watch_stall(&mut self) {
+        let waiting = self.session.game_state == GameState::Ongoing
+            && self.countdown.is_none()
+            && self
+                .local_color()
+                .is_some_and(|color| color != self.session.board.get_curr_player());
+        let connected = self
+            .network
+            .as_ref()
+            .is_some_and(|network| network.connected);
+        let Some(watch) = &mut self.stall else {
+            return;
+        };
+        let status = watch.status(self.now, self.session.history.len(), waiting, connected);
+        if matches!(status, Stall::Over(_)) && !matches!(self.stall_status, Stall::Over(_)) {
+            println!(
+                "The opponent has gone past the {} limit for a move",
+                stall::format_duration(watch.cap())
+            );
+        }
+        self.stall_status = status;
+    }
+
+    /// End the game for the opponent that stopped moving, only on this side
+    fn claim_stall(&mut self) {
+        let Stall::Claimable(waited) = self.stall_status else {
+            return;
+        };
+        self.session.game_state = GameState::Stalled(self.session.board.get_curr_player(), waited);
+        self.stall_status = Stall::Idle;
+        self.note(&format!(
+            "Claimed the win after {} without a move, the opponent never agreed to the limit",
+            stall::format_duration(waited)
+        ));
+        self.confirming = None;
+        self.clear_selection();
+        self.pending_sound = Some(Sound::GameOver);
+        self.mirror_to_tty();
+    }
+
+    /// Run the clock of the side to move, a loss on time is only decided by the server or locally
+    fn tick_clock(&mut self, delta: Duration) {
+        // Nobody loses time before the first move, or once the game is decided
+        if self.session.played_moves.is_empty()
+            || self.session.game_state != GameState::Ongoing
+            || self.disconnected
+        {
+            return;
+        }
+        let to_move = self.session.board.get_curr_player();
+        let Some(clock) = &mut self.clock else {
+            return;
+        };
+        if !clock.tick(to_move, delta) {
+            return;
+        }
+        // The client's clock only follows the server's, which tells it about a loss on time
+        if self
+            .network
+            .as_ref()
+            .is_some_and(|network| !network.is_server)
+        {
+            return;
+        }
+
+        self.session.game_state = GameState::TimeForfeit(to_move);
+        if let Some(network) = &self.network {
+            network.send_time_forfeit(&self.session);
+        }
+        self.confirming = None;
+        self.clear_selection();
+        self.pending_sound = Some(Sound::GameOver);
+        self.mirror_to_tty();
+    }
+
+    /// Write the game so far as PGN, the result is "*" while it is still going
+    fn export_pgn(&self, path: &Path) -> std::io::Result<()> {
+        let mut comments: Vec<String> = match self.session.game_state {
+            GameState::Stalled(_, waited) => vec![format!(
+                "claimed after {} without a move, under a limit only this side set",
+                stall::format_duration(waited)
+            )],
+            _ => self.practice.iter().map(Fork::comment).collect(),
+        };
+        if !self.trap_notes.is_empty() {
+            let notes: Vec<String> = self.trap_notes.iter().map(TrapNote::describe).collect();
+            comments.push(format!("Trap warnings: {}", notes.join("; ")));
+        }
+        let headers = PgnHeaders {
+            site: match (&self.network, &self.engine) {
+                (Some(_), _) => "Network game".to_owned(),
+                (None, Some(_)) => "Game against the computer".to_owned(),
+                (None, None) => "Local game".to_owned(),
+            },
+            white: self.saved_name(Color::White),
+            black: self.saved_name(Color::Black),
+            result: pgn::result_token(&self.session.game_state),
+            fen: self.session.start_fen.clone(),
+            hash: self.session.game_hash(),
+            comment: (!comments.is_empty()).then(|| comments.join(". ")),
+            termination: pgn::termination(&self.session.game_state),
+            bookmarks: self.session.bookmarks.clone(),
+            negotiations: self.session.negotiations.clone(),
+            glyphs: self.session.glyphs(),
+            glyph_style: self.pgn_glyphs,
+        };
+        let moves: Vec<(String, Color)> = self
+            .session
+            .history
+            .iter()
+            .map(|entry| (entry.san.clone(), entry.color))
+            .collect();
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        pgn::write_pgn(&mut file, &headers, &moves)?;
+        file.flush()
+    }
+
+    /// Print something decided about a networked game and keep it in the transcript
+    fn note(&self, text: &str) {
+        println!("{}", text);
+        if let Some(network) = &self.network {
+            network.note(text);
+        }
+    }
+
+    /// Write what was exchanged with the peer to the --transcript file, or the save directory
+    fn write_transcript(&self) {
+        let Some(network) = &self.network else {
+            println!("Only networked games have a transcript");
+            return;
+        };
+        let path = self.transcript.clone().unwrap_or_else(|| {
+            self.save_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("transcript.md")
+        });
+        let result = self
+            .session
+            .game_state
+            .description()
+            .unwrap_or_else(|| "not over yet".to_owned());
+        match std::fs::write(&path, network.transcript(result)) {
+            Ok(()) => println!("Wrote the transcript to {}", path.display()),
+            Err(err) => println!("Can't write the transcript to {}: {}", path.display(), err),
+        }
+    }
+
+    /// Gather what helps with a bug report into a zip in the save directory, or the working
+    /// directory without one. The addresses and names in it are blanked if `redact`.
+    fn write_bug_report(&mut self, ctx: &Context, redact: bool) {
+        let mut artifacts = self
+            .profile
+            .as_ref()
+            .map_or_else(Vec::new, bundle::profile_artifacts);
+        // Games saved with --save-file go elsewhere than the profile's autosave
+        if let Some(path) = self.autosave.as_ref().map(Autosave::path).filter(|path| {
+            self.profile
+                .as_ref()
+                .is_none_or(|profile| profile.autosave() != *path)
+        }) {
+            artifacts.push(Artifact {
+                name: "save-file.json",
+                source: bundle::Source::File(path.to_owned()),
+                sensitive: false,
+            });
+        }
+        artifacts.push(Artifact {
+            name: watchdog::REPORT_FILE,
+            source: bundle::Source::File(ctx.fs.user_data_dir().join(watchdog::REPORT_FILE)),
+            sensitive: false,
+        });
+        artifacts.push(Artifact {
+            name: "transcript.md",
+            source: match &self.network {
+                Some(network) => bundle::Source::Text(
+                    network.transcript(
+                        self.session
+                            .game_state
+                            .description()
+                            .unwrap_or_else(|| "not over yet".to_owned()),
+                    ),
+                ),
+                None => bundle::Source::Absent("Only networked games have a transcript".to_owned()),
+            },
+            sensitive: false,
+        });
+        artifacts.push(Artifact {
+            name: "ordering.txt",
+            source: match ordering::global() {
+                Some(audit) => bundle::Source::Text(audit.render()),
+                None => bundle::Source::Absent(
+                    "The connection timeline is only kept with --audit-ordering or --dump-ordering"
+                        .to_owned(),
+                ),
+            },
+            sensitive: false,
+        });
+        artifacts.push(Artifact {
+            name: "metrics.txt",
+            source: match metrics::global() {
+                Some(metrics) => bundle::Source::Text(metrics.render()),
+                None => bundle::Source::Absent(
+                    "Metrics are only counted with --metrics-port".to_owned(),
+                ),
+            },
+            sensitive: false,
+        });
+        // A theme from a file is named by its path, the built-in ones by their names
+        let theme_file = Path::new(&self.theme.name);
+        artifacts.push(Artifact {
+            name: "theme.json",
+            source: match theme_file.is_file() {
+                true => bundle::Source::File(theme_file.to_owned()),
+                false => {
+                    bundle::Source::Absent(format!("The theme is the built-in {}", self.theme.name))
+                }
+            },
+            sensitive: false,
+        });
+        artifacts.push(Artifact {
+            name: "recording.jsonl",
+            source: bundle::Source::Absent("This build doesn't record sessions".to_owned()),
+            sensitive: false,
+        });
+
+        let system = [
+            ("version", env!("CARGO_PKG_VERSION").to_owned()),
+            (
+                "build",
+                match cfg!(debug_assertions) {
+                    true => "debug".to_owned(),
+                    false => "release".to_owned(),
+                },
+            ),
+            ("os", std::env::consts::OS.to_owned()),
+            ("arch", std::env::consts::ARCH.to_owned()),
+            ("scale_factor", ctx.gfx.window().scale_factor().to_string()),
+            ("theme", self.theme.name.clone()),
+            ("fen", self.session.fen()),
+        ];
+        let dir = self.save_dir.clone().unwrap_or_else(|| PathBuf::from("."));
+        let toast = match bundle::create(&dir, &artifacts, &system, redact) {
+            Ok(path) => {
+                println!("Wrote the bug report bundle to {}", path.display());
+                self.report_dir = Some(dir);
+                format!(
+                    "Wrote the bug report bundle to {}, Ctrl+O opens its folder",
+                    path.display()
+                )
+            }
+            Err(err) => {
+                println!("{}", err);
+                err
+            }
+        };
+        self.toasts.push(toast, REPORT_TOAST_DURATION);
+    }
+
+    /// Open the folder of the last bug report bundle in the file manager
+    fn open_report_dir(&self) {
+        let Some(dir) = &self.report_dir else {
+            return;
+        };
+        if let Err(err) = updates::open_folder(dir) {
+            println!("Can't open {}: {}", dir.display(), err);
+        }
+    }
+
+    /// Save the game in the save directory, or the working directory without one
+    fn save_game(&mut self) {
+        let dir = self.save_dir.clone().unwrap_or_else(|| PathBuf::from("."));
+        let target = self.save_index.target(
+            &dir,
+            self.session.start_fen.as_deref(),
+            &self.session.move_notations(),
+        );
+        if let SaveTarget::AlreadySaved(path) = &target {
+            // The moves are the same, but bookmarks and glyphs may have been set since
+            let glyphs = self
+                .session
+                .history
+                .iter()
+                .any(|entry| entry.glyph.is_some());
+            if self.session.bookmarks.is_empty() && !glyphs {
+                println!("The game is already saved as {}", path.display());
+            } else if let Err(err) = self.export_pgn(path) {
+                println!("Can't save the game to {}: {}", path.display(), err);
+            } else {
+                println!("Updated the bookmarks and glyphs in {}", path.display());
+            }
+            return;
+        }
+        let path = dir.join(pgn::export_file_name(&self.session.game_hash()));
+        match self.export_pgn(&path) {
+            Ok(()) => println!("Saved the game to {}", path.display()),
+            Err(err) => {
+                println!("Can't save the game to {}: {}", path.display(), err);
+                return;
+            }
+        }
+        // Only once the longer game is safely written
+        if let SaveTarget::Replaces(partial) = target {
+            match std::fs::remove_file(&partial) {
+                Ok(()) => println!("It replaces {}", partial.display()),
+                Err(err) => println!("Can't remove {}: {}", partial.display(), err),
+            }
+        }
+    }
+
+    /// The legal move typed as coordinates like "e2e4", promotions need the piece like "e7e8q"
+    fn entered_move(&self, text: &str) -> Result<Move, String> {
+        if !self.is_playing() || !self.is_local_turn() {
+            return Err("It is not your turn".to_owned());
+        }
+        // SAN in English or in the notation the move list shows
+        let typed = text.trim().trim_end_matches(['+', '#']);
+        let repr = &self.session.board_repr;
+        if let Some(mv) = repr.moves.iter().copied().find(|mv| {
+            let english = pgn::san(repr, mv);
+            english == typed
+                || self
+                    .session
+                    .notation
+                    .is_some_and(|shown| notation::format_san(repr, mv, shown) == typed)
+        }) {
+            return Ok(mv);
+        }
+        let text = text.trim().to_ascii_lowercase();
+        let (from, to) = parse_move(&text).map_err(|err| err.to_string())?;
+        let promotion = match text.chars().nth(4) {
+            None => None,
+            Some('q') => Some(PieceType::Queen),
+            Some('r') => Some(PieceType::Rook),
+            Some('b') => Some(PieceType::Bishop),
+            Some('n') => Some(PieceType::Knight),
+            Some(c) => return Err(MoveParseError::Promotion(c).to_string()),
+        };
+
+        let moves = self.session.board_repr.legal_moves[from.0][from.1]
+            .get(&to)
+            .ok_or_else(|| format!("{} is not a legal move", text))?;
+        match promotion {
+            // The same promotions the move chooser offers
+            Some(piece_type) => moves
+                .iter()
+                .find(|mv| mv.get_promoted_type().as_ref() == Some(&piece_type))
+                .copied()
+                .ok_or_else(|| format!("{} is not a legal move", text)),
+            None if moves.len() == 1 => Ok(moves[0]),
+            None if moves.iter().all(|mv| mv.get_promoted_type().is_some()) => {
+                Err(format!("{} promotes, add q, r, b or n", text))
+            }
+            None => Err(format!(
+                "{} can be played several ways, pick one on the board",
+                text
+            )),
+        }
+    }
+
+    fn submit_move_entry(&mut self) {
+        let Some(entry) = &mut self.move_entry else {
+            return;
+        };
+        let text = entry.text.clone();
+        if let Some(ply) = entry.bookmark {
+            self.session.bookmarks.set_label(ply, &text);
+            self.move_entry = None;
+            return;
+        }
+        match self.entered_move(&text) {
+            Ok(mv) => {
+                self.move_entry = None;
+                self.review(None, Application::Live);
+                self.clear_selection();
+                self.play_move(&mv);
+            }
+            Err(message) => {
+                println!("Can't play \"{}\": {}", text.trim(), message);
+                if let Some(entry) = &mut self.move_entry {
+                    entry.rejected = true;
+                }
+            }
+        }
+    }
+
+    /// Bookmark the position on the board and open the bar for its label, Escape leaves it
+    /// without one. Bookmarks stay here, the peer of a networked game never hears of them.
+    fn bookmark(&mut self) {
+        let ply = self.viewing_ply.unwrap_or(self.session.snapshots.len() - 1);
+        self.session.bookmarks.add(ply);
+        self.move_entry = Some(MoveEntry {
+            text: self
+                .session
+                .bookmarks
+                .get(ply)
+                .and_then(|bookmark| bookmark.label.clone())
+                .unwrap_or_default(),
+            rejected: false,
+            bookmark: Some(ply),
+        });
+    }
+
+    /// Review the bookmarked position before or after the reviewed one
+    fn jump_to_bookmark(&mut self, forward: bool) {
+        let Some(ply) = self.viewing_ply else {
+            return;
+        };
+        let bookmarks = &self.session.bookmarks;
+        if let Some(ply) = match forward {
+            true => bookmarks.next(ply),
+            false => bookmarks.previous(ply),
+        } {
+            self.review(Some(ply), Application::Bulk);
+        }
+    }
+
+    /// The bookmarked ply on a line of the move list, the first if it has two
+    #[inline]
+    fn line_bookmark(&self, line: usize) -> Option<usize> {
+        (line * 2 + 1..=line * 2 + 2).find(|ply| self.session.bookmarks.get(*ply).is_some())
+    }
+
+    /// Whether a draw was offered or answered after a move on a line of the move list
+    #[inline]
+    fn line_negotiation(&self, line: usize) -> bool {
+        (line * 2 + 1..=line * 2 + 2).any(|ply| self.session.negotiations.at(ply).next().is_some())
+    }
+
+    /// Show what the watched engine match did next, once the last move was up long enough
+    fn watch_match(&mut self) {
+        let now = self.now;
+        let Some(event) = self
+            .engine_match
+            .as_mut()
+            .filter(|watch| now >= watch.next_at)
+            .and_then(|watch| watch.runner.poll())
+        else {
+            return;
+        };
+        match event {
+            MatchEvent::Started { number, fen } => {
+                if let Err(message) = self.session.restart(fen) {
+                    println!("Can't show game {} of the match: {}", number + 1, message);
+                }
+                self.review(None, Application::Bulk);
+                self.history_scroll = 0;
+                self.auto_saved = false;
+                self.mirror_to_tty();
+                if let Some(watch) = &mut self.engine_match {
+                    watch.number = number;
+                }
+            }
+            MatchEvent::Moved(mv) => {
+                self.play_move(&mv);
+                if let Some(watch) = &mut self.engine_match {
+                    watch.next_at = now + WATCH_MOVE_DELAY;
+                }
+            }
+            MatchEvent::Finished(record) => {
+                // Ran out of its move time, or a rule draw this board waits for a claim on
+                if self.session.game_state == GameState::Ongoing && !record.skipped {
+                    self.session.game_state = record.state;
+                    self.pending_sound = Some(Sound::GameOver);
+                    self.mirror_to_tty();
+                }
+                if let Some(watch) = &mut self.engine_match {
+                    if let Some(outcome) = record.outcome() {
+                        watch.tally.add(outcome);
+                    }
+                    watch.next_at = now + WATCH_GAME_DELAY;
+                }
+            }
+            MatchEvent::Done(tally) => {
+                if let Some(watch) = &mut self.engine_match {
+                    watch.tally = tally;
+                    watch.done = true;
+                }
+            }
+        }
+    }
+
+    /// Ask the engine for a move on its turn and play the move once it is found
+    fn computer_turn(&mut self) {
+        let Some(engine) = &mut self.engine else {
+            return;
+        };
+        if let Some((fen, best)) = engine.poll() {
+            if let Some(speculation) = &self.speculation {
+                speculation.cancel();
+            }
+            // The position may have changed by undoing while it was thinking
+            match best {
+                Some(mv)
+                    if fen == self.session.board.to_fen()
+                        && self.session.game_state == GameState::Ongoing =>
+                {
+                    self.play_move(&mv)
+                }
+                _ => {}
+            }
+            return;
+        }
+        if !engine.is_thinking()
+            && self.session.game_state == GameState::Ongoing
+            && Some(self.session.board.get_curr_player()) == self.computer_color
+        {
+            engine.think(Line {
+                start_fen: self.session.start_fen.clone(),
+                moves: self.session.played_moves.clone(),
+            });
+        }
+    }
+
+    fn play_move(&mut self, player_move: &Move) {
+        // Kept rather than dropped, it is played the moment the countdown is over
+        if self.countdown.is_some() {
+            self.buffered_move = Some(*player_move);
+            self.clear_selection();
+            return;
+        }
+        // Only the player's own moves are the filter's business
+        if let Some(hook) = self.hook.as_ref().filter(|_| {
+            self.engine_match.is_none()
+                && Some(self.session.board.get_curr_player()) != self.computer_color
+        }) {
+            if let hook::Verdict::Veto(reason) =
+                hook.consult(&player_move.to_algebraic_notation(), &self.session.fen())
+            {
+                let toast = format!("The hook vetoed the move: {}", reason);
+                self.toasts.push(toast, HOOK_TOAST_DURATION);
+                self.clear_selection();
+                return;
+            }
+        }
+        // Reject a move the peer couldn't be told about before it touches the board
+        if let (Some(_), Err(err)) = (&self.network, internal_to_network_move(player_move)) {
+            println!(
+                "Can't play {}: {}",
+                player_move.to_algebraic_notation(),
+                err
+            );
+            return;
+        }
+
+        match &self.network {
+            None => self.apply_move(*player_move, Application::Live),
+            Some(network) if network.is_server => {
+                // The server is authoritative and applies its own moves immediately, the
+                // client is told as they are applied
+                self.apply_move(*player_move, Application::Live);
+            }
+            Some(network) => {
+                // We will suggest our move to the server and the server will respond with a new board state
+                match network.send_move(player_move) {
+                    Ok(()) => {}
+                    Err(SendError::Disconnected) => return self.lose_connection(),
+                    Err(err) => {
+                        println!("Can't send the move: {}", err);
+                        return;
+                    }
+                }
+                self.round_trip.sent(self.now);
+                // Don't touch the board until the server confirms, keep the selected piece in case it doesn't
+                self.pending_move = Some(*player_move);
+                self.session.board_repr.selected_to = None;
+            }
+        }
+    }
+
+    /// Where a button pressed at x, y is, the entry bar lies over whatever is under it
+    fn region(&self, ctx: &Context, x: f32, y: f32) -> Region {
+        let (width, height) = ctx.gfx.drawable_size();
+        if self.move_entry.is_some()
+            && entry_bar(Rect::new(0.0, 0.0, width, height)).contains(Point2 { x, y })
+        {
+            return Region::Entry;
+        }
+        match rect(self.plan(width, height).board).contains(Point2 { x, y }) {
+            true => Region::Board,
+            false => Region::Panel,
+        }
+    }
+
+    /// Do what a button pressed at x, y is bound to
+    fn press(&mut self, ctx: &mut Context, action: MouseAction, x: f32, y: f32) -> GameResult {
+        match action {
+            MouseAction::Select => {}
+            // Lets go of the selected piece and closes the move chooser
+            MouseAction::Cancel => {
+                self.clear_selection();
+                return Ok(());
+            }
+            MouseAction::Paste => {
+                self.paste(ctx)?;
+                return Ok(());
+            }
+        }
+        let (width, height) = ctx.gfx.drawable_size();
+        let plan = self.plan(width, height);
+        let board = rect(plan.board);
+        // The splitter comes first so a press on its edge never selects a square next to it
+        match self.splitter.pressed(
+            plan.orientation.along(x, y),
+            plan.split_edge(),
+            ctx.time.time_since_start(),
+        ) {
+            Press::Missed => {}
+            Press::Grabbed => return Ok(()),
+            Press::Toggled => {
+                splitter::write(&self.layout_file, self.splitter.layout);
+                return Ok(());
+            }
+        }
+        // A click on the graph reviews the position under it
+        let graph = plan.graph;
+        if self.viewing_ply.is_some() && graph.contains(x, y) {
+            let positions = self.session.snapshots.len();
+            self.review(
+                Some(eval_graph::ply_at((x - graph.x) / graph.w, positions)),
+                Application::Bulk,
+            );
+            return Ok(());
+        }
+        // A click on a bookmarked line of the reviewed move list reviews its bookmark
+        let history = plan.history;
+        if self.viewing_ply.is_some() && history.contains(x, y) {
+            let visible = layout::visible_range(
+                self.review_lines.len(),
+                self.history_scroll,
+                plan.visible_lines(true),
+            );
+            let line = visible.start + ((y - history.y) / plan.line_height) as usize;
+            if let Some(ply) = self.line_bookmark(line).filter(|_| line < visible.end) {
+                self.review(Some(ply), Application::Bulk);
+            }
+            return Ok(());
+        }
+        if !self.is_playing() || self.confirming.is_some() || self.viewing_ply.is_some() {
+            return Ok(());
+        }
+        // Only the player to move may move, our pieces are dimmed otherwise
+        if !self.is_local_turn() {
+            if let Some(square) = self.square_under(board, x, y) {
+                self.click_out_of_turn(square);
+            }
+            return Ok(());
+        }
+
+        // The selected piece's moves take clicks around their squares too, even off the board
+        let point = (
+            (x - board.x) * COL_COUNT_F32 / board.w,
+            (y - board.y) * ROW_COUNT_F32 / board.h,
+        );
+        if let Some((row, col)) = self.grown_destination(point) {
+            if let Some(mv) = self.session.click(row, col) {
+                self.play_move(&mv);
+            }
+            return Ok(());
+        }
+
+        // Clicks on the history panel don't touch the board, but they do close the overlay
+        if !board.contains(Point2 { x, y }) {
+            self.session.board_repr.selected_to = None;
+            return Ok(());
+        }
+        // Coerce in the range 0..=7 in case mouse pointer registers outside normal range
+        let row = min(
+            ((y - board.y) * ROW_COUNT_F32 / board.h).abs() as usize,
+            7usize,
+        );
+        let col = min(
+            ((x - board.x) * COL_COUNT_F32 / board.w).abs() as usize,
+            7usize,
+        );
+        let (row, col) = self.session.board_repr.view_to_board((row, col));
+
+        if let Some(mv) = self.session.click(row, col) {
+            self.play_move(&mv);
+        }
+
+        Ok(())
+    }
+
+    /// Type what the system holds to paste into the open entry bar, as if it was typed
+    fn paste(&mut self, ctx: &mut Context) -> GameResult {
+        if self.move_entry.is_none() {
+            return Ok(());
+        }
+        let text = match clipboard::System.paste(clipboard::middle_click_source()) {
+            Ok(text) => text,
+            Err(err) => {
+                println!("Can't paste: {}", err);
+                return Ok(());
+            }
+        };
+        for character in clipboard::pasted_line(&text).chars() {
+            event::EventHandler::text_input_event(self, ctx, character)?;
+        }
+        Ok(())
+    }
+
+    /// The end of a gesture, whatever it was bound to
+    fn release(&mut self) {
+        if self.splitter.released() {
+            splitter::write(&self.layout_file, self.splitter.layout);
+        }
+    }
+}
+
+impl Restore for Game {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::Render(&mut self.render, &self.theme)
+    }
+}
+
+impl event::EventHandler for Game {
+    #[inline]
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.now = ctx.time.time_since_start();
+        let now = self.now;
+        self.animations.retain(|animation| !animation.is_done(now));
+        if self.disconnected {
+            self.update_title(ctx);
+            return Ok(());
+        }
+
+        let draining = watchdog::checkpoint("draining network");
+        while let Some(event) = self.network.as_mut().and_then(Network::poll) {
+            // The thread working out replies gets out of the way of whatever came in
+            if let Some(speculation) = &self.speculation {
+                speculation.cancel();
+            }
+            match event {
+                NetworkEvent::Connected(player_color, server_fen, _, server_moves) => {
+                    self.use_features();
+                    self.remember_connection();
+                    self.interop_started = updates::seconds_since_epoch();
+                    if let Some(fen) = server_fen {
+                        self.use_server_position(fen);
+                    }
+                    if let Some(moves) = server_moves {
+                        self.use_server_moves(&moves);
+                    }
+                    // Always have the local player's pieces at the bottom
+                    self.session.board_repr.flipped = player_color == Color::Black;
+                    self.tell_hook(&hook::Event::Connection {
+                        status: "connected".to_owned(),
+                    });
+                    if let Some(metrics) = metrics::global() {
+                        metrics.game_started();
+                        metrics.ply(self.session.history.len());
+                    }
+                    // A spectator joins a game that is already going
+                    if !self.is_spectating() {
+                        self.countdown = Some(Countdown::new(self.now));
+                    }
+                    self.mirror_to_tty();
+                }
+                NetworkEvent::FromClient(chess_network_protocol::ClientToServer::Move(
+                    client_move,
+                )) => self.client_play_move(&client_move),
+                NetworkEvent::FromClient(chess_network_protocol::ClientToServer::Resign) => {
+                    self.session.game_state = GameState::Resigned(
+                        self.local_color()
+                            .map_or(self.session.board.get_curr_player(), opposite_color),
+                    );
+                    self.mirror_to_tty();
+                }
+                NetworkEvent::FromClient(chess_network_protocol::ClientToServer::Draw) => {
+                    self.receive_draw()
+                }
+                NetworkEvent::FromServer(message) => self.server_message(message),
+                NetworkEvent::Rehandshake(handshake) => self.rehandshake(&handshake),
+                // Kept by the network handle and shown while waiting
+                NetworkEvent::Listening(_) => {}
+                NetworkEvent::Unreachable(message) => {
+                    self.tell_hook(&hook::Event::Connection {
+                        status: format!("unreachable: {}", message),
+                    });
+                    self.connection_error = Some(message);
+                }
+                NetworkEvent::Disconnected => {
+                    self.lose_connection();
+                    break;
+                }
+            }
+        }
+        drop(draining);
+
+        if self
+            .countdown
+            .as_ref()
+            .is_some_and(|countdown| countdown.is_over(self.now))
+        {
+            self.countdown = None;
+            if let Some(mv) = self.buffered_move.take() {
+                self.play_move(&mv);
+            }
+        }
+        if let Some(square) = self.session.board_repr.dropped_selection.take() {
+            self.selection_flash = Some((square, self.now));
+        }
+        if let Some(failure) = self.hook.as_ref().and_then(|hook| hook.take_failure()) {
+            let toast = format!("The hook was turned off: {}", failure);
+            println!("{}", toast);
+            self.toasts.push(toast, HOOK_TOAST_DURATION);
+        }
+        self.toasts.advance(self.now);
+        self.tick_clock(ctx.time.delta());
+        self.watch_stall();
+        self.computer_turn();
+        self.play_premove();
+        self.speculate();
+        self.watch_match();
+        self.poll_analysis();
+
+        if self.session.game_state == GameState::Ongoing {
+            self.auto_saved = false;
+            self.practice_ended = None;
+        } else if !self.auto_saved {
+            self.auto_saved = true;
+            self.tell_hook(&hook::Event::GameEnded {
+                result: pgn::result_token(&self.session.game_state).to_owned(),
+                termination: pgn::termination(&self.session.game_state).map(str::to_owned),
+            });
+            self.write_autosave();
+            self.remember_result();
+            self.remember_interop();
+            if let Some(metrics) = metrics::global().filter(|_| self.network.is_some()) {
+                metrics.game_finished(&self.session.game_state);
+            }
+            // Practice games are only saved when asked to with Ctrl+S
+            if self.practice.is_some() {
+                self.practice_ended = Some(self.now);
+            } else if self.save_dir.is_some() {
+                self.save_game();
+            }
+            if self.transcript.is_some() {
+                self.write_transcript();
+            }
+        }
+
+        // Covers local moves from the input handlers as well as the ones just received
+        if let Some(sound) = self.pending_sound.take() {
+            self.sounds.play(ctx, sound);
+        }
+        self.update_title(ctx);
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        // Start with a white canvas the size of the program window
+        let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+        let plan = {
+            let cords = canvas.screen_coordinates().unwrap();
+            self.plan(cords.w, cords.h)
+        };
+        let board = rect(plan.board);
+
+        let now = ctx.time.time_since_start();
+        let sliding: Vec<Sliding> = self
+            .animations
+            .iter()
+            .filter(|animation| !animation.is_done(now))
+            .map(|animation| Sliding {
+                piece: animation.piece,
+                from: animation.from,
+                to: animation.to,
+                progress: animation.progress(now),
+            })
+            .collect();
+        let repr = &self.session.board_repr;
+        let mut scene = BoardScene::new(
+            plan.board,
+            self.review_squares.as_ref().unwrap_or(&repr.squares),
+        );
+        scene.flipped = repr.flipped;
+        scene.coordinates = self.show_coordinates;
+        scene.sliding = &sliding;
+        // Our own pieces are dimmed while waiting for the opponent
+        scene.waiting = turn::dimmed(self.seat(), self.session.board.get_curr_player());
+        if self.viewing_ply.is_none() {
+            scene.shake = self
+                .shake
+                .and_then(|shake| Some((shake.square, shake.offset(self.now)?)));
+            scene.premove = self.premove.map(|premove| (premove.from, premove.to));
+        }
+
+        // Mark a king in check, the end screen covers checkmate instead
+        if self.session.game_state == GameState::Ongoing && self.viewing_ply.is_none() {
+            scene.check = repr.checked_king;
+        }
+        if let Some((square, at)) = self.selection_flash {
+            let left = SELECTION_FLASH_DURATION.saturating_sub(self.now.saturating_sub(at));
+            if !left.is_zero() && self.viewing_ply.is_none() {
+                let fade = left.as_secs_f32() / SELECTION_FLASH_DURATION.as_secs_f32();
+                scene.flash = Some((square, fade));
+            }
+        }
+        if self.viewing_ply.is_none() && self.is_playing() {
+            scene.pins = &self.pins;
+        }
+
+        // Filled again every frame into buffers kept by the game, which only allocate while
+        // they grow
+        let mut review = std::mem::take(&mut self.review_text);
+        let mut choices = std::mem::take(&mut self.choice_buffer);
+        let mut destinations = std::mem::take(&mut self.destination_buffer);
+        review.clear();
+        choices.clear();
+        destinations.clear();
+        if let Some(ply) = self.viewing_ply {
+            self.write_review(&mut review, ply);
+        }
+        if let Some(layout) = ChoiceLayout::new(repr) {
+            let shown = layout.choices.iter().flatten();
+            choices.extend(shown.map(|(_, label, cells)| (*label, *cells)));
+        }
+        if let Some((row, col)) = repr.selected_from {
+            destinations.extend(repr.legal_moves[row][col].keys().copied());
+        }
+        scene.overlay = match (self.viewing_ply, repr.selected_from) {
+            (Some(_), _) => Overlay::Review(&review),
+            // The chooser when a destination several moves reach is picked
+            (None, _) if !choices.is_empty() => Overlay::Choices(&choices),
+            // Else where the selected piece can go
+            (None, Some(from)) => Overlay::Selection {
+                from,
+                destinations: &destinations,
+            },
+            (None, None) => Overlay::None,
+        };
+
+        // The result on top of everything once the game is over, looking back hides it unless
+        // it asks about the review
+        let result = match (self.confirming, self.viewing_ply) {
+            (Some(confirmation @ Confirmation::AutoAnnotate(_)), _) => Some(confirmation.prompt()),
+            (_, None) => self.overlay_text(),
+            (_, Some(_)) => None,
+        };
+        scene.result = result.as_deref();
+        let countdown = self
+            .countdown
+            .as_ref()
+            .and_then(|countdown| countdown.number(self.now))
+            .map(|number| number.to_string());
+        scene.countdown = countdown.as_deref();
+
+        let mut sink = CanvasSink {
+            render: &self.render,
+            theme: &self.theme,
+            ctx,
+            canvas: &mut canvas,
+            board,
+            result: Ok(()),
+        };
+        scene::compose(&scene, &mut sink);
+        let drawn = sink.result;
+        self.review_text = review;
+        self.choice_buffer = choices;
+        self.destination_buffer = destinations;
+        drawn?;
+
+        // The glyph of the move that led to the reviewed position, fading over the board
+        if let Some((ply, at)) = self
+            .glyph_flash
+            .filter(|(ply, _)| Some(*ply) == self.viewing_ply && *ply > 0)
+        {
+            let left = GLYPH_FLASH_DURATION.saturating_sub(self.now.saturating_sub(at));
+            if let Some(glyph) = self.session.history[ply - 1]
+                .glyph
+                .filter(|_| !left.is_zero())
+            {
+                let mut line = GLYPH_LINE;
+                line.color.a *= left.as_secs_f32() / GLYPH_FLASH_DURATION.as_secs_f32();
+                self.draw_text(ctx, &mut canvas, board, glyph.symbol(), line);
+            }
+        }
+
+        self.draw_history(&mut canvas, &plan);
+        self.draw_swap_button(&mut canvas, &plan);
+        if let Some(legend) = plan.legend {
+            self.draw_legend(&mut canvas, rect(legend));
+        }
+        if let Some(ply) = self.viewing_ply {
+            self.draw_graph(ctx, &mut canvas, rect(plan.graph), ply)?;
+        }
+        self.draw_clocks(ctx, &mut canvas, board);
+        self.draw_captures(&mut canvas, board);
+        self.draw_move_entry(&mut canvas);
+
+        // The tooltip of the pinned piece under the pointer or selected
+        let pinned = |square: Option<(usize, usize)>| {
+            self.pins.iter().find(|pin| Some(pin.pinned) == square)
+        };
+        if let Some(pin) =
+            pinned(self.hovered).or_else(|| pinned(self.session.board_repr.selected_from))
+        {
+            if self.is_playing() && self.viewing_ply.is_none() && self.session.claimable.is_none() {
+                let tooltip = pin.describe(&self.session.board_repr.squares);
+                self.draw_text(ctx, &mut canvas, board, &tooltip, HINT_LINE);
+            }
+        }
+
+        if let Some(draw) = self.session.claimable {
+            if self.is_playing() && self.viewing_ply.is_none() && self.confirming.is_none() {
+                self.draw_text(
+                    ctx,
+                    &mut canvas,
+                    board,
+                    match draw {
+                        GameState::FiftyMoves => "Fifty moves without a capture, D claims a draw",
+                        _ => "The position came up three times, D claims a draw",
+                    },
+                    HINT_LINE,
+                );
+            }
+        }
+
+        if let (Some(watch), None) = (&self.stall, self.viewing_ply) {
+            let cap = stall::format_duration(watch.cap());
+            let hint = match self.stall_status {
+                Stall::Over(over) => Some(format!(
+                    "The opponent is {} past the {} limit for a move",
+                    stall::format_duration(over),
+                    cap
+                )),
+                Stall::Claimable(waited) => Some(format!(
+                    "No move for {}, K claims the win under the {} limit set here",
+                    stall::format_duration(waited),
+                    cap
+                )),
+                Stall::Idle | Stall::Thinking => None,
+            };
+            if let Some(hint) = hint {
+                self.draw_text(ctx, &mut canvas, board, &hint, WARNING_LINE);
+            }
+        }
+
+        if let Some(watch) = self.engine_match.as_ref().filter(|watch| !watch.done) {
+            if self.viewing_ply.is_none() {
+                self.draw_text(
+                    ctx,
+                    &mut canvas,
+                    board,
+                    &format!(
+                        "{}, N skips to the next game",
+                        watch.tally.score_line(&watch.config)
+                    ),
+                    HINT_LINE,
+                );
+            }
+        }
+
+        match self.viewing_ply {
+            None => {
+                if let Some(trap) = self.trap_warning {
+                    let toast = format!("{} (Escape dismisses)", trap.toast());
+                    self.draw_text(ctx, &mut canvas, board, &toast, WARNING_LINE);
+                }
+            }
+            // The review shows what was warned about at the move and how it went
+            Some(ply) => {
+                if let Some(note) = self.trap_notes.iter().find(|note| note.ply == ply) {
+                    self.draw_text(ctx, &mut canvas, board, &note.describe(), WARNING_LINE);
+                }
+            }
+        }
+
+        if let Some(warning) = &self.feature_warning {
+            self.draw_text(ctx, &mut canvas, board, warning, WARNING_LINE);
+        }
+        if let Some(toast) = self.toasts.current() {
+            self.draw_text(ctx, &mut canvas, board, toast, WARNING_LINE);
+        }
+
+        if let Some(message) = &self.error_message {
+            self.draw_text(ctx, &mut canvas, board, message, ERROR_LINE);
+        }
+
+        #[cfg(feature = "alloc-tracking")]
+        {
+            let text_cache = self.render.texts.borrow_mut().take_counts();
+            self.allocations.frame(text_cache);
+            self.draw_text(ctx, &mut canvas, board, self.allocations.line(), DEBUG_LINE);
+            // What the line itself cost is left out of the next frame's
+            alloc_tracking::take_counts();
+            self.render.texts.borrow_mut().take_counts();
+        }
+
+        // Submit drawing
+        canvas.finish(ctx)
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        _dx: f32,
+        _dy: f32,
+    ) -> GameResult {
+        let (width, height) = ctx.gfx.drawable_size();
+        let plan = self.plan(width, height);
+        self.hovered = self.square_under(rect(plan.board), x, y);
+        let was_active = self.splitter.is_active();
+        self.splitter
+            .moved(plan.orientation.along(x, y), plan.split_edge());
+        if self.splitter.is_active() != was_active {
+            ctx.mouse.set_cursor_type(match self.splitter.is_active() {
+                false => CursorIcon::Default,
+                true => match plan.orientation {
+                    layout::Orientation::Landscape => CursorIcon::EwResize,
+                    layout::Orientation::Portrait => CursorIcon::NsResize,
+                },
+            });
+        }
+        Ok(())
+    }
+
+    fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        self.splitter.resized(width, height);
+        self.splitter.set_orientation(layout::orientation(
+            width,
+            height,
+            self.splitter.orientation(),
+            self.layout_mode,
+            self.portrait_ratio,
+        ));
+        Ok(())
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> GameResult {
+        // Scrolling up goes back to older moves
+        let line_count = self.session.history_lines.len();
+        let (width, height) = ctx.gfx.drawable_size();
+        let rows = self.plan(width, height).visible_lines(false);
+        self.history_scroll = match y > 0.0 {
+            true => min(self.history_scroll + 1, line_count.saturating_sub(rows)),
+            false => self.history_scroll.saturating_sub(1),
+        };
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, _repeat: bool) -> GameResult {
+        // A prompt takes every key until it is answered
+        if let Some(confirmation) = self.confirming {
+            match input.keycode {
+                Some(KeyCode::Y) | Some(KeyCode::Return) => {
+                    self.confirming = None;
+                    match confirmation {
+                        // Marking moves is for reviewing, the game may well be over
+                        Confirmation::AutoAnnotate(_) => self.auto_annotate(),
+                        _ if self.is_playing() => self.confirm(confirmation),
+                        _ => {}
+                    }
+                }
+                Some(KeyCode::N) | Some(KeyCode::Escape) => {
+                    self.confirming = None;
+                    // The protocol has no message for it, only this side knows
+                    if let Some(color) = self
+                        .local_color()
+                        .filter(|_| confirmation == Confirmation::AcceptDraw)
+                    {
+                        self.negotiate(color, Outcome::Declined);
+                    }
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        // The entry bar takes every key too, the characters arrive through text_input_event
+        if let Some(entry) = &mut self.move_entry {
+            match input.keycode {
+                Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) => self.submit_move_entry(),
+                Some(KeyCode::Escape) => self.move_entry = None,
+                Some(KeyCode::Back) => {
+                    entry.text.pop();
+                    entry.rejected = false;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        match input.keycode {
+            Some(KeyCode::R) if self.is_playing() => self.confirming = Some(Confirmation::Resign),
+            Some(KeyCode::K) if matches!(self.stall_status, Stall::Claimable(_)) => {
+                self.claim_stall()
+            }
+            // A draw the rules allow is claimed instead of offered
+            Some(KeyCode::D) if self.is_playing() && self.session.claimable.is_some() => {
+                self.confirming = self.session.claimable.map(Confirmation::ClaimDraw)
+            }
+            Some(KeyCode::D)
+                if self.is_playing() && self.session.negotiations.open() != self.local_color() =>
+            {
+                self.confirming = Some(Confirmation::OfferDraw)
+            }
+            #[cfg(feature = "alloc-tracking")]
+            Some(KeyCode::F12) => self.allocations.reset(),
+            // Manually flip the board, e.g. when spectating
+            Some(KeyCode::F) => self.session.board_repr.flipped = !self.session.board_repr.flipped,
+            Some(KeyCode::M) => {
+                self.sounds.muted = !self.sounds.muted;
+                println!(
+                    "Sound {}",
+                    match self.sounds.muted {
+                        true => "muted",
+                        false => "unmuted",
+                    }
+                );
+            }
+            Some(KeyCode::N) if self.engine_match.as_ref().is_some_and(|watch| !watch.done) => {
+                if let Some(watch) = &self.engine_match {
+                    watch.runner.skip();
+                }
+                println!("Skipping to the next game");
+            }
+            Some(KeyCode::C) => self.show_coordinates = !self.show_coordinates,
+            Some(KeyCode::L) => self.toggle_legend(),
+            Some(KeyCode::G) => self.switch_notation(),
+            Some(KeyCode::T) => {
+                self.theme = self.theme.next();
+                self.render = Render::new(ctx, &self.theme, self.piece_styles);
+                println!("Theme {}", self.theme.name);
+            }
+            Some(KeyCode::S) if input.mods.contains(KeyMods::CTRL) => self.save_game(),
+            Some(KeyCode::E) if input.mods.contains(KeyMods::CTRL) => self.write_transcript(),
+            Some(KeyCode::B) if input.mods.contains(KeyMods::CTRL) => {
+                self.write_bug_report(ctx, !input.mods.contains(KeyMods::SHIFT))
+            }
+            Some(KeyCode::O) if input.mods.contains(KeyMods::CTRL) => self.open_report_dir(),
+            Some(KeyCode::Left) => {
+                let latest = self.session.snapshots.len() - 1;
+                self.review(
+                    Some(self.viewing_ply.unwrap_or(latest).saturating_sub(1)),
+                    Application::Replay,
+                );
+            }
+            Some(KeyCode::Right) => {
+                self.review(self.viewing_ply.map(|ply| ply + 1), Application::Replay)
+            }
+            Some(KeyCode::Home) => self.review(Some(0), Application::Bulk),
+            Some(keycode) if self.viewing_ply.is_some() && glyph_key(keycode).is_some() => {
+                self.annotate(glyph_key(keycode).flatten())
+            }
+            Some(KeyCode::A) if self.viewing_ply.is_some() => self.offer_auto_annotate(),
+            Some(KeyCode::LBracket) => self.jump_to_bookmark(false),
+            Some(KeyCode::RBracket) => self.jump_to_bookmark(true),
+            Some(KeyCode::End) => self.review(None, Application::Bulk),
+            Some(KeyCode::U) => self.undo(),
+            Some(KeyCode::Z) if input.mods.contains(KeyMods::CTRL) => {
+                match input.mods.contains(KeyMods::SHIFT) {
+                    true => self.redo(),
+                    false => self.undo(),
+                }
+            }
+            Some(KeyCode::Z) if input.mods.contains(KeyMods::SHIFT) => self.redo(),
+            // Escape lets go of the selection first, then keeps ggez's default of quitting
+            Some(KeyCode::Escape) if self.trap_warning.is_some() => self.trap_warning = None,
+            Some(KeyCode::Escape) if self.session.board_repr.selected_from.is_some() => {
+                self.clear_selection()
+            }
+            // Stops the evaluation pass, the positions scored so far stay on the graph
+            Some(KeyCode::Escape) if self.analysis.is_some() => self.analysis = None,
+            Some(KeyCode::Escape) => ctx.request_quit(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+        let can_open = self.confirming.is_none() && self.engine_match.is_none();
+        match &mut self.move_entry {
+            // Opened here rather than on the key so the / or : isn't typed into the bar
+            None if (character == '/' || character == ':') && can_open => {
+                self.move_entry = Some(MoveEntry::default());
+            }
+            None if character.eq_ignore_ascii_case(&'b') && can_open => self.bookmark(),
+            Some(entry) if entry.bookmark.is_some() && !character.is_control() => {
+                entry.text.push(character);
+            }
+            // SAN needs = for promotions and - for castling too
+            Some(entry) if character.is_ascii_alphanumeric() || "=-+#".contains(character) => {
+                entry.text.push(character);
+                entry.rejected = false;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> GameResult<bool> {
+        // Moves are saved as they are played, this catches anything since then
+        self.write_autosave();
+        if let Some(Err(err)) = ordering::global().map(ordering::Audit::write_dump) {
+            println!("Could not write the ordering timeline: {}", err);
+        }
+        Ok(false)
+    }
+}
+
+enum Scene {
+    Menu(Menu),
+    // Connecting is shown by the game itself until the network thread reports the opponent
+    Playing(Box<Game>),
+    Browsing(Box<Browser>),
+    // A saved game opened from the list, which is kept to go back to
+    Reviewing(Box<Game>, Box<Browser>),
+    // A practice game against the computer, and the networked game it was forked from
+    Practicing(Box<Game>, Box<Game>),
+    // Who is playing, before anything else when there is more than one profile
+    Profiles(Box<Picker>),
+    // Testing the connection to the other computer, reached from the start screen
+    Wizard(Box<Wizard>),
+    // How the implementations played over the network did, reached from the start screen
+    Interop(Box<InteropView>),
+    // Drilling the names of the squares, reached from the start screen
+    Trainer(Box<TrainerView>),
+    // Pieces moved without rules, over the screen it was opened from which is put back as it was
+    Sandbox(Box<SandboxView>, Box<Scene>),
+}
+
+impl Scene {
+    /// Build the GPU resources of every screen that is open again, after the surface was lost
+    /// or for a new density
+    fn restore_graphics(&mut self, ctx: &Context) {
+        match self {
+            Scene::Menu(menu) => menu.restore_graphics(ctx),
+            Scene::Browsing(browser) => browser.restore_graphics(ctx),
+            Scene::Interop(view) => view.restore_graphics(ctx),
+            Scene::Trainer(view) => view.restore_graphics(ctx),
+            Scene::Profiles(picker) => picker.restore_graphics(ctx),
+            Scene::Wizard(wizard) => wizard.restore_graphics(ctx),
+            Scene::Playing(game) => game.restore_graphics(ctx),
+            Scene::Reviewing(game, browser) => {
+                game.restore_graphics(ctx);
+                browser.restore_graphics(ctx);
+            }
+            Scene::Practicing(practice, game) => {
+                practice.restore_graphics(ctx);
+                game.restore_graphics(ctx);
+            }
+            Scene::Sandbox(view, under) => {
+                view.restore_graphics(ctx);
+                under.restore_graphics(ctx);
+            }
+        }
+    }
+}
+
+/// The update check of this run and the newer release it found
+struct UpdateCheck {
+    file: PathBuf,
+    settings: UpdateSettings,
+    // Answers once the background check finds a newer release
+    pending: Option<Receiver<Release>>,
+    // Shown on the start screen until it is dismissed
+    release: Option<Release>,
+}
+
+impl UpdateCheck {
+    /// Turn the check on or off as asked, and start it if it is due
+    fn start(file: PathBuf, switch: Option<UpdateSwitch>, offline: bool) -> Self {
+        let mut settings = UpdateSettings::read(&file);
+        let now = updates::seconds_since_epoch();
+        let mut changed = true;
+        match switch {
+            Some(UpdateSwitch::On(url)) => {
+                println!("Checking {} for updates. {}", url, updates::EXPLANATION);
+                settings.url = Some(url);
+            }
+            Some(UpdateSwitch::Off) => settings.url = None,
+            None => changed = false,
+        }
+        let pending = match !offline && settings.due(now) {
+            true => {
+                settings.last_checked = Some(now);
+                changed = true;
+                Some(updates::check_in_background(
+                    Box::new(Curl),
+                    settings.clone(),
+                ))
+            }
+            false => None,
+        };
+        if changed {
+            if let Err(message) = settings.write(&file) {
+                println!("{}", message);
+            }
+        }
+        Self {
+            file,
+            settings,
+            pending,
+            release: None,
+        }
+    }
+
+    /// Take the release once the background check has found it
+    fn poll(&mut self) {
+        if let Some(release) = self
+            .pending
+            .as_ref()
+            .and_then(|pending| pending.try_recv().ok())
+        {
+            self.pending = None;
+            self.release = Some(release);
+        }
+    }
+
+    /// Never tell of the shown release again
+    fn skip(&mut self) {
+        let Some(release) = self.release.take() else {
+            return;
+        };
+        self.settings.skipped = Some(release.version);
+        if let Err(message) = self.settings.write(&self.file) {
+            println!("{}", message);
+        }
+    }
+}
+
+/// What the command line asked for, held until the profile it is for has been picked
+struct Startup {
+    resume: bool,
+    // None to show the start screen
+    opponent: Option<Opponent>,
+    engine_match: Option<MatchConfig>,
+    // --save-file, used instead of the profile's autosave
+    save_file: Option<PathBuf>,
+    // --save-dir, for the profiles that don't have their own
+    save_dir: Option<PathBuf>,
+    // --piece-tint, --piece-outline and --piece-badge, saved to the profile
+    piece_style_changes: Vec<StyleChange>,
+    // --mouse, saved to the profile
+    mouse_changes: Vec<MouseChange>,
+}
+
+/// Switches from the start screen to the game once the player has chosen how to play
+struct App {
+    scene: Scene,
+    fen: Option<String>,
+    settings: GameSettings,
+    // Clicks where the pointer rests with --accessible-input
+    dwell: Option<Dwell>,
+    // Where the pointer was last seen in the window
+    pointer: Option<(f32, f32)>,
+    // The profile's, every button goes through them before anything sees it
+    mouse: MouseBindings,
+    gestures: Gestures,
+    profiles: Profiles,
+    // Taken once a profile has been picked
+    startup: Option<Startup>,
+    updates: UpdateCheck,
+    // Whether the frames come through after the GPU surface was lost
+    recovery: Recovery,
+    // The density the window was last drawn at
+    scale: ScaleWatch,
+}
+
+impl App {
+    #[inline]
+    fn start(&mut self, ctx: &Context, opponent: Opponent) {
+        // The client is sent the position by the server
+        let fen = match &opponent {
+            Opponent::Network(network) if !network.is_server => None,
+            _ => self.fen.clone(),
+        };
+        self.scene = Scene::Playing(Box::new(Game::new(
+            ctx,
+            opponent,
+            fen,
+            self.settings.clone(),
+        )));
+    }
+
+    /// Handle what was done on the connection test
+    fn test_connection(&mut self, ctx: &Context, action: WizardAction) {
+        match action {
+            WizardAction::Back => {
+                self.scene = Scene::Menu(Menu::new(ctx, &self.settings.connection_book))
+            }
+            WizardAction::Play(config) => self.start(ctx, Opponent::Network(config)),
+        }
+    }
+
+    fn leave_interop(&mut self, ctx: &Context, action: InteropAction) {
+        match action {
+            InteropAction::Back => {
+                self.scene = Scene::Menu(Menu::new(ctx, &self.settings.connection_book))
+            }
+        }
+    }
+
+    fn leave_trainer(&mut self, ctx: &Context, action: TrainerAction) {
+        match action {
+            TrainerAction::Back => {
+                self.scene = Scene::Menu(Menu::new(ctx, &self.settings.connection_book))
+            }
+        }
+    }
+
+    /// Open the sandbox over the current screen, which is kept as it is to go back to
+    fn open_sandbox(&mut self, ctx: &Context, sandbox: Sandbox) {
+        let view = SandboxView::new(
+            ctx,
+            sandbox,
+            self.settings.theme.clone(),
+            self.settings.piece_styles,
+            REVIEW_DEPTH,
+            self.settings
+                .save_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(".")),
+        );
+        let under = std::mem::replace(
+            &mut self.scene,
+            Scene::Menu(Menu::new(ctx, &self.settings.connection_book)),
+        );
+        self.scene = Scene::Sandbox(Box::new(view), Box::new(under));
+    }
+
+    /// Handle what was done in the sandbox
+    fn leave_sandbox(&mut self, ctx: &Context, action: SandboxAction) {
+        let scene = std::mem::replace(
+            &mut self.scene,
+            Scene::Menu(Menu::new(ctx, &self.settings.connection_book)),
+        );
+        let Scene::Sandbox(_, under) = scene else {
+            self.scene = scene;
+            return;
+        };
+        match action {
+            SandboxAction::Back => self.scene = *under,
+            SandboxAction::Play { fen, computer } => {
+                let to_move = match fen.split_whitespace().nth(1) {
+                    Some("b") => Color::Black,
+                    _ => Color::White,
+                };
+                let opponent = match computer {
+                    true => Opponent::Computer(ComputerConfig {
+                        depth: self.settings.practice_depth,
+                        color: opposite_color(to_move),
+                    }),
+                    false => Opponent::Local,
+                };
+                let mut settings = self.settings.clone();
+                // A made-up position has no clock, there is no hurry
+                settings.time_control = None;
+                let mut game = Game::new(ctx, opponent, Some(fen), settings);
+                // It mustn't take the place of a game saved to resume
+                game.autosave = None;
+                self.scene = Scene::Playing(Box::new(game));
+            }
+        }
+    }
+
+    /// Handle what was done in the list of saved games
+    fn browse(&mut self, ctx: &Context, action: BrowserAction) {
+        let scene = std::mem::replace(
+            &mut self.scene,
+            Scene::Menu(Menu::new(ctx, &self.settings.connection_book)),
+        );
+        let Scene::Browsing(browser) = scene else {
+            self.scene = scene;
+            return;
+        };
+        let BrowserAction::Open(listed) = action else {
+            // Back to the start screen
+            return;
+        };
+        self.scene = match self.open_saved(ctx, &listed) {
+            Ok(game) => Scene::Reviewing(Box::new(game), browser),
+            Err(message) => {
+                println!("Can't open {}: {}", listed.file_name(), message);
+                Scene::Browsing(browser)
+            }
+        };
+    }
+
+    /// A local game with the moves of a saved game played, being reviewed from its start
+    fn open_saved(&self, ctx: &Context, listed: &ListedGame) -> Result<Game, String> {
+        let (fen, read) = browser::read_game(listed)?;
+        if let Some(Err(message)) = fen.as_deref().map(board_from_fen) {
+            return Err(format!("invalid FEN: {}", message));
+        }
+        let mut game = Game::new(ctx, Opponent::Local, fen, self.settings.clone());
+        game.load_pgn(&read.moves)?;
+        for (mv, glyph) in read.glyphs.into_iter().enumerate() {
+            game.session.set_glyph(mv, glyph);
+        }
+        game.session.bookmarks = read.bookmarks;
+        game.session.negotiations = read.negotiations;
+        Ok(game)
+    }
+
+    /// Go back from a reviewed saved game to the list, where it is still chosen
+    fn close_review(&mut self, ctx: &Context) {
+        let scene = std::mem::replace(
+            &mut self.scene,
+            Scene::Menu(Menu::new(ctx, &self.settings.connection_book)),
+        );
+        self.scene = match scene {
+            Scene::Reviewing(_, browser) => Scene::Browsing(browser),
+            scene => scene,
+        };
+    }
+
+    /// Play on against the computer from the reviewed position of a finished networked game.
+    /// The networked game is set aside untouched until the practice is over.
+    fn start_practice(&mut self, ctx: &Context) {
+        let scene = std::mem::replace(
+            &mut self.scene,
+            Scene::Menu(Menu::new(ctx, &self.settings.connection_book)),
+        );
+        let Scene::Playing(source) = scene else {
+            self.scene = scene;
+            return;
+        };
+        let fork = match source.fork(self.settings.practice_depth) {
+            Ok(fork) => fork,
+            Err(message) => {
+                println!("Can't practice from here: {}", message);
+                self.scene = Scene::Playing(source);
+                return;
+            }
+        };
+        let mut settings = self.settings.clone();
+        // Practice has no clock, there is no hurry
+        settings.time_control = None;
+        let mut practice = Game::new(
+            ctx,
+            Opponent::Computer(fork.computer()),
+            Some(fork.start_fen.clone()),
+            settings,
+        );
+        // It mustn't take the place of a local game saved to resume
+        practice.autosave = None;
+        practice.practice = Some(fork);
+        self.scene = Scene::Practicing(Box::new(practice), source);
+    }
+
+    /// Back to reviewing the networked game at the position the practice started from
+    fn end_practice(&mut self, ctx: &Context) {
+        let scene = std::mem::replace(
+            &mut self.scene,
+            Scene::Menu(Menu::new(ctx, &self.settings.connection_book)),
+        );
+        self.scene = match scene {
+            Scene::Practicing(practice, mut source) => {
+                if let Some(fork) = &practice.practice {
+                    source.review(Some(fork.ply), Application::Bulk);
+                }
+                Scene::Playing(source)
+            }
+            scene => scene,
+        };
+    }
+
+    /// Show an engine match on the board as it is played
+    fn watch(&mut self, ctx: &Context, config: MatchConfig) {
+        let mut settings = self.settings.clone();
+        // The engines have their move time instead of a clock, and the report instead of saves
+        settings.time_control = None;
+        settings.save_dir = None;
+        let mut game = Game::new(ctx, Opponent::Local, None, settings);
+        game.autosave = None;
+        game.engine_match = Some(MatchWatch {
+            runner: Match::start(config.clone()),
+            config,
+            tally: Tally::default(),
+            number: 0,
+            next_at: Duration::ZERO,
+            done: false,
+        });
+        self.scene = Scene::Playing(Box::new(game));
+    }
+
+    /// Do what a button is bound to where it was pressed
+    fn press(&mut self, ctx: &mut Context, action: MouseAction, x: f32, y: f32) -> GameResult {
+        let slop = match self.settings.accessible_input {
+            Some(_) => CLICK_SLOP,
+            None => 0.0,
+        };
+        // The screens outside a game only have things to press
+        let in_game = matches!(
+            self.scene,
+            Scene::Playing(_) | Scene::Reviewing(..) | Scene::Practicing(..)
+        );
+        if !in_game && action != MouseAction::Select {
+            return Ok(());
+        }
+        match &mut self.scene {
+            Scene::Menu(menu) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                match menu.click(x, y, width, height, slop, self.updates.release.as_ref()) {
+                    Some(MenuChoice::Play(opponent)) => self.start(ctx, opponent),
+                    Some(MenuChoice::OpenRelease) => {
+                        if let Some(release) = &self.updates.release {
+                            if let Err(err) = updates::open_in_browser(&release.url) {
+                                println!("Can't open {}: {}", release.url, err);
+                            }
+                        }
+                    }
+                    Some(MenuChoice::SkipRelease) => self.updates.skip(),
+                    Some(MenuChoice::DismissRelease) => self.updates.release = None,
+                    Some(MenuChoice::Browse) => {
+                        let dir = self
+                            .settings
+                            .save_dir
+                            .clone()
+                            .unwrap_or_else(|| PathBuf::from("."));
+                        self.scene = Scene::Browsing(Box::new(Browser::new(ctx, dir)));
+                    }
+                    Some(MenuChoice::TestConnection(role)) => {
+                        self.scene = Scene::Wizard(Box::new(Wizard::new(ctx, role)));
+                    }
+                    Some(MenuChoice::Interop) => {
+                        self.scene = Scene::Interop(Box::new(InteropView::new(
+                            ctx,
+                            self.settings.interop_ledger.clone(),
+                        )));
+                    }
+                    Some(MenuChoice::Trainer) => {
+                        self.scene = Scene::Trainer(Box::new(TrainerView::new(
+                            ctx,
+                            self.settings.theme.clone(),
+                            self.settings.piece_styles,
+                            self.settings.trainer_file.clone(),
+                        )));
+                    }
+                    Some(MenuChoice::Sandbox) => self.open_sandbox(ctx, Sandbox::standard()),
+                    Some(MenuChoice::RollIdentity) => {
+                        self.settings.identity = Some(Identity::random())
+                    }
+                    Some(MenuChoice::DropIdentity) => self.settings.identity = None,
+                    None => {}
+                }
+                Ok(())
+            }
+            Scene::Browsing(browser) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                if let Some(action) = browser.click(x, y, width, height, slop) {
+                    self.browse(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Interop(view) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                if let Some(action) = view.click(x, y, width, height, slop) {
+                    self.leave_interop(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Trainer(view) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                let now = ctx.time.time_since_start();
+                if let Some(action) = view.click(x, y, width, height, slop, now) {
+                    self.leave_trainer(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Sandbox(view, _) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                if let Some(action) = view.click(x, y, width, height, slop) {
+                    self.leave_sandbox(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Profiles(picker) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                if let Some(name) = picker.click(&self.profiles, x, y, width, height, slop) {
+                    self.begin(ctx, &name);
+                }
+                Ok(())
+            }
+            Scene::Wizard(wizard) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                if let Some(action) = wizard.click(x, y, width, height, slop) {
+                    self.test_connection(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Playing(game)
+                if action == MouseAction::Select && game.can_swap() && {
+                    let (width, height) = ctx.gfx.drawable_size();
+                    game.swap_button(&game.plan(width, height)).contains(x, y)
+                } =>
+            {
+                self.swap_sides(ctx);
+                Ok(())
+            }
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.press(ctx, action, x, y)
+            }
+        }
+    }
+
+    /// Finish the gesture a press started
+    fn release(&mut self) {
+        match &mut self.scene {
+            Scene::Menu(_)
+            | Scene::Browsing(_)
+            | Scene::Profiles(_)
+            | Scene::Wizard(_)
+            | Scene::Interop(_)
+            | Scene::Trainer(_)
+            | Scene::Sandbox(..) => {}
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.release()
+            }
+        }
+    }
+
+    /// Whether resting the pointer there mustn't click
+    fn dwell_blocked(&self, ctx: &Context, (x, y): (f32, f32)) -> bool {
+        match &self.scene {
+            Scene::Menu(_)
+            | Scene::Browsing(_)
+            | Scene::Profiles(_)
+            | Scene::Wizard(_)
+            | Scene::Interop(_)
+            | Scene::Trainer(_)
+            | Scene::Sandbox(..) => false,
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.dwell_blocked(ctx, x, y)
+            }
+        }
+    }
+
+    /// Draw at the window's new density. It keeps its logical size and so has more or fewer
+    /// pixels now: the texts and labels are laid out again at their new size, and the screens
+    /// are laid out again for the new drawable.
+    fn rescale(&mut self, ctx: &mut Context) -> GameResult {
+        self.scene.restore_graphics(ctx);
+        let (width, height) = ctx.gfx.drawable_size();
+        event::EventHandler::resize_event(self, ctx, width, height)
+    }
+
+    /// Click where the pointer has rested long enough
+    fn dwell(&mut self, ctx: &mut Context) -> GameResult {
+        let now = ctx.time.time_since_start();
+        let pointer = self
+            .pointer
+            .filter(|pointer| !self.dwell_blocked(ctx, *pointer));
+        let Some((x, y)) = self
+            .dwell
+            .as_mut()
+            .and_then(|dwell| dwell.update(now, pointer))
+        else {
+            return Ok(());
+        };
+        // Resting selects whichever buttons select
+        self.press(ctx, MouseAction::Select, x, y)?;
+        self.release();
+        Ok(())
+    }
+
+    /// Say the graphics are being restored over whatever the scene drew, for a moment after
+    /// they were
+    fn draw_restoring(&self, ctx: &mut Context) -> GameResult {
+        if !self.recovery.restoring(ctx.time.time_since_start()) {
+            return Ok(());
+        }
+        let (width, height) = ctx.gfx.drawable_size();
+        let mut canvas = Canvas::from_frame(ctx, None);
+        canvas.draw(
+            &unit_mesh(ctx),
+            graphics::DrawParam::default()
+                .dest_rect(Rect::new(0.0, 0.0, width, height))
+                .color(DARK_FILM_COLOR),
+        );
+        let mut text = Text::new("Restoring graphics…");
+        text.set_scale(height * 0.05);
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: width * 0.5,
+                    y: height * 0.5,
+                })
+                .offset(Point2 { x: 0.5, y: 0.5 })
+                .color(graphics::Color::WHITE),
+        );
+        canvas.finish(ctx)
+    }
+
+    /// A circle shrinking around the resting pointer until it clicks
+    fn draw_dwell(&self, ctx: &mut Context) -> GameResult {
+        let Some(((x, y), left)) = self
+            .dwell
+            .as_ref()
+            .and_then(|dwell| dwell.progress(ctx.time.time_since_start()))
+        else {
+            return Ok(());
+        };
+        if left <= 0.0 {
+            return Ok(());
+        }
+        let circle = Mesh::new_circle(
+            ctx,
+            DrawMode::stroke(3.0),
+            Point2 { x, y },
+            DWELL_RADIUS * left,
+            0.5,
+            DWELL_COLOR,
+        )?;
+        // Over whatever the scene drew
+        let mut canvas = Canvas::from_frame(ctx, None);
+        canvas.draw(&circle, graphics::DrawParam::default());
+        canvas.finish(ctx)
+    }
+
+    /// Throw the game on the board away and start a new one just like it, from the same position
+    /// against the same opponent. Only its save is removed, nothing else records it.
+    fn restart(&mut self, ctx: &Context) {
+        let Scene::Playing(game) = &mut self.scene else {
+            return;
+        };
+        let Some(opponent) = game
+            .opponent()
+            .and_then(|opponent| opponent.restarted(self.settings.alternate_colors))
+        else {
+            return;
+        };
+        if let Some(autosave) = &mut game.autosave {
+            autosave.remove();
+        }
+        let start_fen = game.session.start_fen.clone();
+        println!("Starting a new game");
+        self.scene = Scene::Playing(Box::new(Game::new(
+            ctx,
+            opponent,
+            start_fen,
+            self.settings.clone(),
+        )));
+    }
+
+    /// Let the players of a local game that hasn't started trade colors. The game is started
+    /// again with the seats swapped, like starting over does, nothing of it is kept.
+    fn swap_sides(&mut self, ctx: &Context) {
+        let Scene::Playing(game) = &self.scene else {
+            return;
+        };
+        let start_fen = game.session.start_fen.clone();
+        self.settings.seating = self.settings.seating.swapped();
+        println!(
+            "Swapped sides, {} is at the bottom",
+            color_name(self.settings.seating.near)
+        );
+        self.scene = Scene::Playing(Box::new(Game::new(
+            ctx,
+            Opponent::Local,
+            start_fen,
+            self.settings.clone(),
+        )));
+    }
+
+    /// Play as the profile `name`, with its own saves, and start what the command line asked for
+    fn begin(&mut self, ctx: &Context, name: &str) {
+        let Some(startup) = self.startup.take() else {
+            self.scene = Scene::Menu(Menu::new(ctx, &self.settings.connection_book));
+            return;
+        };
+        self.profiles.remember(name);
+        let paths = self.profiles.paths(name);
+        self.settings.save_file = startup.save_file.unwrap_or_else(|| paths.autosave());
+        self.settings.layout_file = paths.layout();
+        self.settings.connection_book = paths.connections();
+        self.settings.interop_ledger = paths.interop();
+        self.settings.trainer_file = paths.trainer();
+        self.settings.profile = Some(paths.clone());
+        self.scene = Scene::Menu(Menu::new(ctx, &self.settings.connection_book));
+        let mut profile_settings = ProfileSettings::read(&paths);
+        for change in &startup.piece_style_changes {
+            profile_settings.piece_styles.apply(change);
+        }
+        for change in &startup.mouse_changes {
+            profile_settings
+                .mouse
+                .bind(change.region, change.button, change.action);
+        }
+        if !startup.piece_style_changes.is_empty() || !startup.mouse_changes.is_empty() {
+            if let Err(err) = profile_settings.write(&paths) {
+                println!("{}", err);
+            }
+        }
+        for conflict in profile_settings.mouse.conflicts() {
+            println!("Mouse bindings: {}", conflict);
+        }
+        self.settings.save_dir = profile_settings.save_dir.or(startup.save_dir);
+        self.settings.piece_styles = profile_settings.piece_styles;
+        self.mouse = profile_settings.mouse;
+        // A new handle every run, so games can't be told apart by it
+        self.settings.identity = profile_settings.anonymous.then(Identity::random);
+
+        let saved = match startup.resume {
+            true => autosave::read(&self.settings.save_file),
+            false => {
+                if self.settings.save_file.exists() {
+                    println!("An unfinished game was saved, start with --resume to continue it");
+                }
+                None
+            }
+        };
+        if startup.resume && saved.is_none() {
+            println!("There is no saved game to resume");
+        }
+        if let Some(saved) = saved {
+            self.resume(ctx, saved);
+        }
+        if let Some(engine_match) = startup.engine_match {
+            self.watch(ctx, engine_match);
+        } else if let Some(opponent) = startup.opponent {
+            if matches!(self.scene, Scene::Menu(_)) {
+                self.start(ctx, opponent);
+            }
+        }
+    }
+
+    /// Continue the saved game, a save whose moves don't replay is ignored
+    fn resume(&mut self, ctx: &Context, saved: SavedGame) {
+        let mut game = Game::new(
+            ctx,
+            saved.opponent(),
+            saved.start_fen.clone(),
+            self.settings.clone(),
+        );
+        match game.replay(&saved) {
+            Ok(()) => self.scene = Scene::Playing(Box::new(game)),
+            Err(message) => println!("Ignoring the damaged save: {}", message),
+        }
+    }
+}
+
+impl event::EventHandler for App {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if let Some(watchdog) = watchdog::global() {
+            watchdog.beat();
+        }
+        self.dwell(ctx)?;
+        self.updates.poll();
+        if self.scale.changed(ctx.gfx.window().scale_factor()) {
+            self.rescale(ctx)?;
+        }
+        match &mut self.scene {
+            Scene::Menu(_) | Scene::Profiles(_) | Scene::Interop(_) => Ok(()),
+            Scene::Browsing(browser) => {
+                browser.update();
+                Ok(())
+            }
+            Scene::Wizard(wizard) => {
+                wizard.update();
+                Ok(())
+            }
+            Scene::Trainer(view) => {
+                view.update(ctx.time.time_since_start());
+                Ok(())
+            }
+            Scene::Sandbox(view, _) => {
+                view.update();
+                Ok(())
+            }
+            Scene::Practicing(practice, _) => {
+                practice.update(ctx)?;
+                if practice.practice_over() {
+                    self.end_practice(ctx);
+                }
+                Ok(())
+            }
+            Scene::Playing(game) | Scene::Reviewing(game, _) => game.update(ctx),
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let _stage = watchdog::checkpoint("building frame");
+        match &mut self.scene {
+            Scene::Menu(menu) => {
+                let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+                let identity = self.settings.identity.as_ref();
+                menu.draw(ctx, &mut canvas, self.updates.release.as_ref(), identity);
+                canvas.finish(ctx)
+            }
+            Scene::Browsing(browser) => {
+                let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+                browser.draw(ctx, &mut canvas);
+                canvas.finish(ctx)
+            }
+            Scene::Interop(view) => {
+                let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+                view.draw(&mut canvas);
+                canvas.finish(ctx)
+            }
+            Scene::Trainer(view) => {
+                let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+                view.draw(ctx, &mut canvas, ctx.time.time_since_start())?;
+                canvas.finish(ctx)
+            }
+            Scene::Sandbox(view, _) => {
+                let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+                view.draw(ctx, &mut canvas)?;
+                canvas.finish(ctx)
+            }
+            Scene::Profiles(picker) => {
+                let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+                picker.draw(ctx, &mut canvas);
+                canvas.finish(ctx)
+            }
+            Scene::Wizard(wizard) => {
+                let mut canvas = Canvas::from_frame(ctx, graphics::Color::WHITE);
+                wizard.draw(ctx, &mut canvas);
+                canvas.finish(ctx)
+            }
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.draw(ctx)
+            }
+        }?;
+        self.draw_dwell(ctx)?;
+        self.draw_restoring(ctx)?;
+        self.recovery.drawn();
+        Ok(())
+    }
+
+    /// A lost surface is configured and built again, and once that keeps failing or the device
+    /// itself is gone the game is saved before the window closes. Anything else ends the game
+    /// like ggez does by default.
+    fn on_error(&mut self, ctx: &mut Context, origin: event::ErrorOrigin, err: GameError) -> bool {
+        let loss = match (origin, &err) {
+            (event::ErrorOrigin::Draw, GameError::RenderError(message)) => recovery::loss(message),
+            _ => None,
+        };
+        match self.recovery.failed(loss, ctx.time.time_since_start()) {
+            Response::Rebuild => {
+                println!(
+                    "Lost the graphics surface ({}), restoring the graphics",
+                    err
+                );
+                // Asking for the size it has configures the surface for the window again
+                let (width, height) = ctx.gfx.drawable_size();
+                if let Err(err) = ctx.gfx.set_drawable_size(width, height) {
+                    println!("Can't configure the surface again: {}", err);
+                }
+                self.scene.restore_graphics(ctx);
+                false
+            }
+            Response::GiveUp => {
+                println!(
+                    "Can't restore the graphics ({}), saving the game and closing. Start \
+                     again with --resume to go on with a local game.",
+                    err
+                );
+                if let Err(err) = self.quit_event(ctx) {
+                    println!("Can't save the game: {}", err);
+                }
+                true
+            }
+            Response::Fatal => true,
         }
-
-        // Submit drawing
-        canvas.finish(ctx)
     }
 
     fn mouse_button_down_event(
         &mut self,
         ctx: &mut Context,
-        _button: event::MouseButton,
+        button: event::MouseButton,
         x: f32,
         y: f32,
     ) -> GameResult {
-        let (width, height) = ctx.gfx.drawable_size();
-        // Coerce in the range 0..=7 in case mouse pointer registers outside normal range
-        let row = min((y * ROW_COUNT_F32 / height).abs() as usize, 7usize);
-        let col = min((x * COL_COUNT_F32 / width).abs() as usize, 7usize);
+        let region = match &self.scene {
+            Scene::Menu(_)
+            | Scene::Browsing(_)
+            | Scene::Profiles(_)
+            | Scene::Wizard(_)
+            | Scene::Interop(_)
+            | Scene::Trainer(_)
+            | Scene::Sandbox(..) => Region::Menu,
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.region(ctx, x, y)
+            }
+        };
+        match self.gestures.press(&self.mouse, region, button) {
+            Some(action) => self.press(ctx, action, x, y),
+            None => Ok(()),
+        }
+    }
 
-        let (prev_row, prev_col) = self.board_repr.selected_from.unwrap_or((0usize, 0usize));
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: event::MouseButton,
+        _x: f32,
+        _y: f32,
+    ) -> GameResult {
+        if self.gestures.release(button).is_some() {
+            self.release();
+        }
+        Ok(())
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+    ) -> GameResult {
+        self.pointer = Some((x, y));
+        match &mut self.scene {
+            Scene::Menu(_)
+            | Scene::Profiles(_)
+            | Scene::Wizard(_)
+            | Scene::Interop(_)
+            | Scene::Trainer(_)
+            | Scene::Sandbox(..) => Ok(()),
+            Scene::Browsing(browser) => {
+                browser.motion(x, y);
+                Ok(())
+            }
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.mouse_motion_event(ctx, x, y, dx, dy)
+            }
+        }
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        match &mut self.scene {
+            Scene::Menu(_)
+            | Scene::Browsing(_)
+            | Scene::Profiles(_)
+            | Scene::Wizard(_)
+            | Scene::Interop(_)
+            | Scene::Trainer(_)
+            | Scene::Sandbox(..) => Ok(()),
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.resize_event(ctx, width, height)
+            }
+        }
+    }
 
-        let cords = Some((row, col));
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult {
+        match &mut self.scene {
+            Scene::Menu(_)
+            | Scene::Profiles(_)
+            | Scene::Wizard(_)
+            | Scene::Trainer(_)
+            | Scene::Sandbox(..) => Ok(()),
+            Scene::Browsing(browser) => {
+                browser.wheel(y, ctx.gfx.drawable_size().1);
+                Ok(())
+            }
+            Scene::Interop(view) => {
+                view.wheel(y, ctx.gfx.drawable_size().1);
+                Ok(())
+            }
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.mouse_wheel_event(ctx, x, y)
+            }
+        }
+    }
 
-        if self.board_repr.selected_to.is_some() {
-            if col == self.board_repr.selected_to.as_ref().unwrap().1
-                && ((self.board_repr.selected_to.as_ref().unwrap().0 == 0 && row < 4)
-                    || (self.board_repr.selected_to.as_ref().unwrap().0 == 7 && row >= 4))
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, repeat: bool) -> GameResult {
+        match &mut self.scene {
+            Scene::Menu(menu) => {
+                match input.keycode {
+                    // Keep ggez's default of quitting on escape
+                    Some(KeyCode::Escape) => ctx.request_quit(),
+                    Some(keycode) => {
+                        if let Some(opponent) = menu.key(keycode) {
+                            self.start(ctx, opponent);
+                        }
+                    }
+                    None => {}
+                }
+                Ok(())
+            }
+            Scene::Browsing(browser) => {
+                let (width, height) = ctx.gfx.drawable_size();
+                if let Some(action) = input
+                    .keycode
+                    .and_then(|keycode| browser.key(keycode, width, height))
+                {
+                    self.browse(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Interop(view) => {
+                let height = ctx.gfx.drawable_size().1;
+                if let Some(action) = input.keycode.and_then(|keycode| view.key(keycode, height)) {
+                    self.leave_interop(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Trainer(view) => {
+                let now = ctx.time.time_since_start();
+                if let Some(action) = input.keycode.and_then(|keycode| view.key(keycode, now)) {
+                    self.leave_trainer(ctx, action);
+                }
+                Ok(())
+            }
+            Scene::Sandbox(view, _) => {
+                if let Some(action) = input
+                    .keycode
+                    .and_then(|keycode| view.key(keycode, input.mods))
+                {
+                    self.leave_sandbox(ctx, action);
+                }
+                Ok(())
+            }
+            // Nobody has been picked yet, so there is nothing to go back to
+            Scene::Profiles(picker)
+                if input.keycode == Some(KeyCode::Escape) && picker.escape_leaves() =>
             {
-                let drow = (self.board_repr.selected_to.as_ref().unwrap().0 as isize - row as isize)
-                    .abs() as usize;
-                let promotion_piece = match drow {
-                    0 => jonathan_hallstrom_chess::PieceType::Queen,
-                    1 => jonathan_hallstrom_chess::PieceType::Knight,
-                    2 => jonathan_hallstrom_chess::PieceType::Rook,
-                    3 => jonathan_hallstrom_chess::PieceType::Bishop,
-                    _ => panic!("Couldn't select a promotion piece."),
-                };
-                let mut move_to_be_made = None;
-                for mv in &self.board_repr.legal_moves[prev_row][prev_col]
-                    [self.board_repr.selected_to.as_ref().unwrap()]
+                ctx.request_quit();
+                Ok(())
+            }
+            Scene::Profiles(picker) => {
+                if let Some(name) = input
+                    .keycode
+                    .and_then(|keycode| picker.key(&self.profiles, keycode))
                 {
-                    if mv.get_promoted_type().unwrap() == promotion_piece {
-                        move_to_be_made = Some(mv);
-                        break;
-                    }
+                    self.begin(ctx, &name);
                 }
-                assert!(move_to_be_made.is_some());
-                self.play_move(move_to_be_made.unwrap());
-                self.refresh_board();
-            } else {
-                self.board_repr.selected_to = None;
-                self.board_repr.selected_from = None;
+                Ok(())
             }
-        } else if self.board_repr.selected_from.is_some()
-            && self.board_repr.legal_moves[prev_row][prev_col].contains_key(cords.as_ref().unwrap())
-        {
-            let moves = &self.board_repr.legal_moves[prev_row][prev_col][cords.as_ref().unwrap()];
-            if moves.len() > 1 {
-                self.board_repr.selected_to = cords.clone();
-            } else {
-                self.board.play_move(moves[0]).unwrap();
-                self.refresh_board();
+            Scene::Wizard(wizard) => {
+                if let Some(action) = input.keycode.and_then(|keycode| wizard.key(keycode)) {
+                    self.test_connection(ctx, action);
+                }
+                Ok(())
+            }
+            // Escape goes back to the list once there is nothing else for it to close
+            Scene::Reviewing(game, _)
+                if input.keycode == Some(KeyCode::Escape) && game.escape_leaves() =>
+            {
+                self.close_review(ctx);
+                Ok(())
+            }
+            // Giving up on a practice game goes back just like finishing it
+            Scene::Practicing(game, _)
+                if input.keycode == Some(KeyCode::Escape) && game.escape_leaves() =>
+            {
+                self.end_practice(ctx);
+                Ok(())
+            }
+            // The position looked at, copied into the sandbox. A networked game being played
+            // can't wait for it.
+            Scene::Playing(game) | Scene::Reviewing(game, _)
+                if input.keycode == Some(KeyCode::X)
+                    && game.confirming.is_none()
+                    && game.move_entry.is_none()
+                    && !game.live() =>
+            {
+                if let Some(sandbox) = game
+                    .viewing_ply
+                    .and_then(|ply| Sandbox::at(&game.session, ply))
+                {
+                    self.open_sandbox(ctx, sandbox);
+                }
+                Ok(())
+            }
+            Scene::Playing(game) if input.keycode == Some(KeyCode::P) && game.can_fork() => {
+                self.start_practice(ctx);
+                Ok(())
+            }
+            Scene::Playing(game)
+                if input.keycode == Some(KeyCode::N)
+                    && input.mods.contains(KeyMods::CTRL)
+                    && game.can_restart() =>
+            {
+                match game.restart_needs_confirming() {
+                    true => game.confirming = Some(Confirmation::Restart),
+                    false => self.restart(ctx),
+                }
+                Ok(())
+            }
+            Scene::Playing(game)
+                if input.keycode == Some(KeyCode::S)
+                    && !input.mods.contains(KeyMods::CTRL)
+                    && game.can_swap() =>
+            {
+                self.swap_sides(ctx);
+                Ok(())
+            }
+            Scene::Playing(game)
+                if game.confirming == Some(Confirmation::Restart)
+                    && matches!(input.keycode, Some(KeyCode::Y) | Some(KeyCode::Return)) =>
+            {
+                self.restart(ctx);
+                Ok(())
+            }
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.key_down_event(ctx, input, repeat)
+            }
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) -> GameResult {
+        match &mut self.scene {
+            Scene::Menu(menu) => {
+                menu.text_input(character);
+                Ok(())
+            }
+            Scene::Browsing(browser) => {
+                browser.text_input(character);
+                Ok(())
+            }
+            Scene::Profiles(picker) => {
+                picker.text_input(&self.profiles, character);
+                Ok(())
+            }
+            Scene::Wizard(wizard) => {
+                wizard.text_input(character);
+                Ok(())
+            }
+            Scene::Interop(_) | Scene::Trainer(_) | Scene::Sandbox(..) => Ok(()),
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.text_input_event(ctx, character)
             }
-        } else if self.board_repr.squares[row][col]
-            .color()
-            .map_or(false, |color| color == self.board.get_curr_player())
-            && self.board_repr.selected_from != cords
-        {
-            self.board_repr.selected_from = cords;
-        } else {
-            self.board_repr.selected_from = None;
         }
+    }
 
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) -> GameResult {
+        if let Some(watchdog) = watchdog::global() {
+            watchdog.focus(gained);
+        }
         Ok(())
     }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> GameResult<bool> {
+        match &mut self.scene {
+            Scene::Menu(_)
+            | Scene::Browsing(_)
+            | Scene::Profiles(_)
+            | Scene::Wizard(_)
+            | Scene::Interop(_)
+            | Scene::Trainer(_) => Ok(false),
+            Scene::Playing(game) | Scene::Reviewing(game, _) | Scene::Practicing(game, _) => {
+                game.quit_event(ctx)
+            }
+            // The game under the sandbox still writes what it has to
+            Scene::Sandbox(_, under) => match under.as_mut() {
+                Scene::Playing(game) | Scene::Reviewing(game, _) => game.quit_event(ctx),
+                _ => Ok(false),
+            },
+        }
+    }
+}
+
+/// Whether the font draws the chess figurines. Glyphs it lacks all come out as the same
+/// placeholder, so the knight is drawn next to a character no font has and compared.
+fn figurines_render(ctx: &mut Context) -> bool {
+    const PROBE_SIZE: u32 = 32;
+    let mut render = |glyph: &str| -> GameResult<Vec<u8>> {
+        let image =
+            Image::new_canvas_image(ctx, ImageFormat::Rgba8UnormSrgb, PROBE_SIZE, PROBE_SIZE, 1);
+        let mut canvas = Canvas::from_image(ctx, image.clone(), graphics::Color::WHITE);
+        let mut text = Text::new(glyph);
+        text.set_scale(PROBE_SIZE as f32);
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default().color(graphics::Color::BLACK),
+        );
+        canvas.finish(ctx)?;
+        image.to_pixels(ctx)
+    };
+    match (render("♘"), render("\u{E000}")) {
+        (Ok(knight), Ok(missing)) => knight != missing,
+        _ => false,
+    }
+}
+
+/// The language of the system's locale if it has its own piece letters
+fn locale_language() -> Option<Language> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .and_then(|locale| Language::from_locale(&locale))
 }
 
 fn main() -> GameResult {
+    // Checking messages needs no window, nor anything else the game sets up
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "validate").is_some() {
+        match config::Validation::from_args(args) {
+            Ok(validation) => std::process::exit(validate::run(&validation)),
+            Err(message) => {
+                eprintln!("{}\n\n{}", message, config::USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let config = match Config::from_args(args) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, config::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(Err(message)) = config.fen.as_deref().map(board_from_fen) {
+        eprintln!("Invalid --fen: {}\n\n{}", message, config::USAGE);
+        std::process::exit(2);
+    }
+
+    let theme = match config.theme.as_deref().map(Theme::load) {
+        None => Theme::default(),
+        Some(Ok(theme)) => theme,
+        Some(Err(message)) => {
+            eprintln!("Invalid --theme: {}\n\n{}", message, config::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // A failure here means the build is miswired, so refuse to play rather than show a broken board
+    if !config.no_selftest {
+        match selftest::run() {
+            Ok(elapsed) => println!("Self-test passed in {:?}", elapsed),
+            Err(report) => {
+                let path = std::env::temp_dir().join("chess-gui-selftest.txt");
+                let _ = std::fs::write(&path, &report);
+                eprintln!(
+                    "Self-test failed, report written to {}:\n{}",
+                    path.display(),
+                    report
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(port) = config.metrics_port {
+        match metrics::serve(metrics::enable(), port) {
+            Ok(address) => println!("Metrics at http://{}/metrics", address),
+            Err(err) => {
+                eprintln!("Could not serve the metrics on port {}: {}", port, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Debug builds always check their own ordering, --audit-ordering makes it a hard error
+    if config.audit_ordering || config.dump_ordering.is_some() || cfg!(debug_assertions) {
+        ordering::enable(config.audit_ordering, config.dump_ordering);
+    }
+
+    // A match played as fast as possible needs no window
+    if let Some(engine_match) = config
+        .engine_match
+        .as_ref()
+        .filter(|engine_match| !engine_match.watch)
+    {
+        engine_match::play_match(engine_match, &AtomicBool::new(false), &mut |_| true);
+        return Ok(());
+    }
+
     let ws = WindowSetup {
-        title: "Arvid Jonassons Chess GUI".to_owned(),
+        title: WINDOW_TITLE.to_owned(),
         samples: NumSamples::One,
         vsync: true,
         icon: "".to_string(),
@@ -559,7 +5669,7 @@ fn main() -> GameResult {
     };
 
     let wm = WindowMode {
-        width: 800.0,
+        width: 1000.0,
         height: 800.0,
         maximized: false,
         fullscreen_type: FullscreenType::Windowed,
@@ -571,20 +5681,156 @@ fn main() -> GameResult {
         resizable: true,
         visible: true,
         transparent: false,
-        resize_on_scale_factor_change: false,
-        logical_size: Some(LogicalSize::new(800.0, 800.0)),
+        // Keep the logical size when moving between monitors so the board is redrawn at the new density
+        resize_on_scale_factor_change: true,
+        logical_size: Some(LogicalSize::new(1000.0, 800.0)),
     };
 
     let cb = ggez::ContextBuilder::new("Chess GUI", "Arvid Jonasson")
         .window_setup(ws)
         .window_mode(wm);
 
-    let (ctx, event_loop) = cb.build()?;
-    let is_server = true;
-    let server_color = Some(chess_network_protocol::Color::Black);
-    let ip = "127.0.0.1:5000";
-
-    let stream = network::connect(is_server, ip);
-    let game = Game::new(&ctx, stream, is_server, server_color);
-    event::run(ctx, event_loop, game)
+    let (mut ctx, event_loop) = cb.build()?;
+    let figurines_render = figurines_render(&mut ctx);
+    // The letters of the language asked for, otherwise the system's
+    let letters = match config.notation {
+        Some(Notation::Localized(language)) => Some(language),
+        _ => locale_language(),
+    };
+    let notation = config.notation.map(|asked| {
+        let usable = asked.usable(figurines_render, letters);
+        if usable != asked {
+            println!(
+                "The font has no chess figurines, showing {} notation instead",
+                usable.name()
+            );
+        }
+        usable
+    });
+    let data_dir = ctx.fs.user_data_dir().to_owned();
+    if let Some(report) = watchdog::froze(&data_dir).filter(|_| !config.resume) {
+        println!(
+            "The last run froze, see {}. Start with --resume to continue the game it saved",
+            report.display()
+        );
+    }
+    if let Some(hang_after) = config.hang_report {
+        watchdog::enable(hang_after, data_dir.clone());
+    }
+    let profiles = Profiles::new(data_dir);
+    match profiles.migrate() {
+        Ok(true) => println!(
+            "Moved the saved files into the {} profile",
+            profiles::DEFAULT_PROFILE
+        ),
+        Ok(false) => {}
+        Err(message) => println!("Can't move the saved files into a profile: {}", message),
+    }
+    let listed = profiles.list();
+    // Only ask who is playing when there is a choice
+    let profile = match config.profile {
+        Some(name) => {
+            if !listed.contains(&name) {
+                if let Err(message) = profiles.create(&name) {
+                    eprintln!("Can't create the profile {}: {}", name, message);
+                    std::process::exit(1);
+                }
+            }
+            Some(name)
+        }
+        None if listed.len() <= 1 => Some(
+            listed
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| profiles::DEFAULT_PROFILE.to_owned()),
+        ),
+        None => None,
+    };
+    let mut app = App {
+        scene: Scene::Menu(Menu::new(&ctx, Path::new(""))),
+        fen: config.fen,
+        settings: GameSettings {
+            tty_mirror: config.tty_mirror,
+            muted: config.muted,
+            theme,
+            show_coordinates: !config.no_coordinates,
+            legend: config.legend,
+            notation,
+            letters,
+            figurines_render,
+            time_control: config.time_control,
+            // Set once the profile is picked
+            save_dir: None,
+            pgn_glyphs: config.pgn_glyphs,
+            slide_curve: config.slide_curve,
+            // Set once the profile is picked
+            identity: None,
+            save_file: PathBuf::new(),
+            layout_file: PathBuf::new(),
+            layout_mode: config.layout_mode,
+            portrait_ratio: config.portrait_ratio,
+            claim_draws: config.claim_draws,
+            practice_depth: config.practice_depth,
+            assist: config.assist,
+            relative_pins: config.relative_pins,
+            max_illegal_moves: config.max_illegal_moves,
+            max_protocol_errors: config.max_protocol_errors,
+            max_move_time: config.max_move_time,
+            strict_handshakes: config.strict_handshakes,
+            transcript: config.transcript,
+            alternate_colors: config.alternate_colors,
+            accessible_input: config.accessible_input,
+            premoves: config.premoves,
+            // Set once the profile is picked
+            piece_styles: PieceStyles::default(),
+            hook: config
+                .hook
+                .as_ref()
+                .and_then(|config| match Hook::spawn(config) {
+                    Ok(hook) => Some(Arc::new(hook)),
+                    Err(message) => {
+                        println!("Can't start the hook: {}", message);
+                        None
+                    }
+                }),
+            hook_networked: config
+                .hook
+                .as_ref()
+                .is_some_and(|config| config.allow_networked),
+            connection_book: PathBuf::new(),
+            interop_ledger: PathBuf::new(),
+            trainer_file: PathBuf::new(),
+            profile: None,
+            seating: Seating::default(),
+        },
+        dwell: config
+            .accessible_input
+            .and_then(|accessible_input| accessible_input.dwell)
+            .map(Dwell::new),
+        pointer: None,
+        mouse: MouseBindings::default(),
+        gestures: Gestures::default(),
+        profiles,
+        updates: UpdateCheck::start(
+            ctx.fs.user_data_dir().join(updates::FILE_NAME),
+            config.check_updates,
+            config.offline,
+        ),
+        startup: Some(Startup {
+            resume: config.resume,
+            opponent: (!config.show_menu).then_some(config.opponent),
+            engine_match: config.engine_match,
+            save_file: config.save_file,
+            save_dir: config.save_dir,
+            piece_style_changes: config.piece_style_changes,
+            mouse_changes: config.mouse_changes,
+        }),
+        recovery: Recovery::default(),
+        scale: ScaleWatch::default(),
+    };
+    match profile {
+        Some(name) => app.begin(&ctx, &name),
+        None => app.scene = Scene::Profiles(Box::new(Picker::new(&ctx, &app.profiles))),
+    }
+    event::run(ctx, event_loop, app)
 }
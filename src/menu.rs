@@ -0,0 +1,528 @@
+use crate::wizard::Role;
+use crate::{avatar_color, unit_mesh, Graphics, Restore};
+use chess_gui::config::{
+    parse_address, ComputerConfig, NetworkConfig, Opponent, DEFAULT_COMPUTER_DEPTH,
+    DEFAULT_CONNECT_ATTEMPTS,
+};
+use chess_gui::connection_book::{Book, Pick};
+use chess_gui::connectivity::DEFAULT_PORT;
+use chess_gui::identity::Identity;
+use chess_gui::updates::Release;
+use ggez::graphics::{self, Canvas, Drawable, Mesh, Rect, Text};
+use ggez::input::keyboard::KeyCode;
+use ggez::Context;
+use mint::Point2;
+use std::path::{Path, PathBuf};
+
+const BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const CHOSEN_BUTTON_COLOR: graphics::Color = graphics::Color::new(0.0, 0.5, 0.0, 0.75);
+const FIELD_COLOR: graphics::Color = graphics::Color::new(1.0, 0.9, 0.9, 1.0);
+const MENU_TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const BANNER_COLOR: graphics::Color = graphics::Color::new(1.0, 0.95, 0.7, 1.0);
+const DEFAULT_IP: &str = "127.0.0.1:8080";
+// Entries of the connection book listed on the host and join screens
+const BOOK_ROWS: usize = 5;
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Mode {
+    Host,
+    Join,
+    Local,
+    Computer,
+}
+
+impl Mode {
+    #[inline]
+    fn uses_network(&self) -> bool {
+        matches!(self, Mode::Host | Mode::Join)
+    }
+
+    // The other side's color is chosen when joining or playing the computer
+    #[inline]
+    fn chooses_color(&self) -> bool {
+        matches!(self, Mode::Join | Mode::Computer)
+    }
+}
+
+/// The rect with `slop` pixels more on every side, for the bigger click targets of
+/// --accessible-input
+#[inline]
+pub(crate) fn grown(rect: Rect, slop: f32) -> Rect {
+    Rect::new(
+        rect.x - slop,
+        rect.y - slop,
+        rect.w + 2.0 * slop,
+        rect.h + 2.0 * slop,
+    )
+}
+
+/// Where everything on the start screen is drawn, relative to the window size.
+/// Drawing and clicking both go through this so they can't disagree.
+struct MenuLayout {
+    modes: [(Mode, Rect); 4],
+    ip_field: Rect,
+    // Opens the connection test for the network modes
+    test: Rect,
+    colors: [(chess_network_protocol::Color, Rect); 2],
+    // The entries of the connection book down the left, with buttons to pin, name and delete
+    // each
+    book: [[Rect; 4]; BOOK_ROWS],
+    start: Rect,
+    browse: Rect,
+    // How the implementations played over the network got along with this one
+    interop: Rect,
+    // Drilling the names of the squares
+    trainer: Rect,
+    // Moving pieces freely, without rules
+    sandbox: Rect,
+    // Rolling a random handle to play under, and going back to the profile's name
+    identity: Rect,
+    drop_identity: Rect,
+    // The newer release, with buttons to open its page, skip it and close the banner
+    banner: Rect,
+    open_release: Rect,
+    skip_release: Rect,
+    dismiss_release: Rect,
+}
+
+impl MenuLayout {
+    fn new(width: f32, height: f32) -> Self {
+        let rect = |x: f32, y: f32, w: f32, h: f32| {
+            Rect::new(x * width, y * height, w * width, h * height)
+        };
+        Self {
+            modes: [
+                (Mode::Host, rect(0.06, 0.3, 0.19, 0.08)),
+                (Mode::Join, rect(0.29, 0.3, 0.19, 0.08)),
+                (Mode::Local, rect(0.52, 0.3, 0.19, 0.08)),
+                (Mode::Computer, rect(0.75, 0.3, 0.19, 0.08)),
+            ],
+            ip_field: rect(0.25, 0.45, 0.5, 0.08),
+            test: rect(0.77, 0.45, 0.17, 0.08),
+            colors: [
+                (
+                    chess_network_protocol::Color::White,
+                    rect(0.25, 0.6, 0.23, 0.08),
+                ),
+                (
+                    chess_network_protocol::Color::Black,
+                    rect(0.52, 0.6, 0.23, 0.08),
+                ),
+            ],
+            book: std::array::from_fn(|row| {
+                let y = 0.42 + 0.08 * row as f32;
+                [
+                    rect(0.01, y, 0.14, 0.07),
+                    rect(0.155, y, 0.025, 0.07),
+                    rect(0.185, y, 0.025, 0.07),
+                    rect(0.215, y, 0.025, 0.07),
+                ]
+            }),
+            start: rect(0.35, 0.78, 0.3, 0.1),
+            browse: rect(0.35, 0.9, 0.3, 0.07),
+            interop: rect(0.67, 0.9, 0.27, 0.07),
+            trainer: rect(0.06, 0.9, 0.27, 0.07),
+            sandbox: rect(0.67, 0.8, 0.27, 0.07),
+            identity: rect(0.67, 0.7, 0.22, 0.07),
+            drop_identity: rect(0.9, 0.7, 0.04, 0.07),
+            banner: rect(0.02, 0.02, 0.6, 0.06),
+            open_release: rect(0.63, 0.02, 0.12, 0.06),
+            skip_release: rect(0.76, 0.02, 0.15, 0.06),
+            dismiss_release: rect(0.92, 0.02, 0.06, 0.06),
+        }
+    }
+}
+
+/// What was chosen on the start screen
+pub(crate) enum MenuChoice {
+    Play(Opponent),
+    // Look through the saved games instead of playing
+    Browse,
+    // Look at how the implementations played over the network did
+    Interop,
+    // Practice the names of the squares
+    Trainer,
+    // Set up a position by hand
+    Sandbox,
+    // Play under a new random handle, or under the profile's name again
+    RollIdentity,
+    DropIdentity,
+    // Check that the other computer can be reached before playing it
+    TestConnection(Role),
+    // What was pressed on the banner of a newer release
+    OpenRelease,
+    SkipRelease,
+    DismissRelease,
+}
+
+/// Start screen for choosing how to play, used when no mode is given on the command line
+pub(crate) struct Menu {
+    mode: Mode,
+    ip: String,
+    // The color the server or the computer plays as
+    other_color: chess_network_protocol::Color,
+    book: Book,
+    book_file: PathBuf,
+    // The index of the entry being given a nickname, and what was typed so far
+    naming: Option<(usize, String)>,
+    // Unit square tinted and stretched into every button
+    button_mesh: Mesh,
+}
+
+impl Restore for Menu {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::UnitMesh(&mut self.button_mesh)
+    }
+}
+
+impl Menu {
+    /// With the connection book kept at `book_file`
+    pub(crate) fn new(ctx: &Context, book_file: &Path) -> Self {
+        Self {
+            mode: Mode::Host,
+            ip: DEFAULT_IP.to_owned(),
+            other_color: chess_network_protocol::Color::White,
+            book: Book::read(book_file),
+            book_file: book_file.to_owned(),
+            naming: None,
+            button_mesh: unit_mesh(ctx),
+        }
+    }
+
+    /// The game to start, None until everything it needs is filled in
+    fn choice(&self) -> Option<Opponent> {
+        match self.mode {
+            Mode::Local => return Some(Opponent::Local),
+            Mode::Computer => {
+                return Some(Opponent::Computer(ComputerConfig {
+                    depth: DEFAULT_COMPUTER_DEPTH,
+                    color: match self.other_color {
+                        chess_network_protocol::Color::White => {
+                            jonathan_hallstrom_chess::Color::White
+                        }
+                        chess_network_protocol::Color::Black => {
+                            jonathan_hallstrom_chess::Color::Black
+                        }
+                    },
+                }))
+            }
+            Mode::Host | Mode::Join => {}
+        }
+        // Start stays disabled until the address can be used
+        let address = parse_address(&self.ip).ok()?;
+        Some(Opponent::Network(match self.mode {
+            Mode::Host => NetworkConfig {
+                is_server: true,
+                address,
+                strict_port: false,
+                connect_attempts: DEFAULT_CONNECT_ATTEMPTS,
+                server_color: None,
+                spectate: false,
+                hosting: None,
+            },
+            _ => NetworkConfig {
+                is_server: false,
+                address,
+                strict_port: false,
+                connect_attempts: DEFAULT_CONNECT_ATTEMPTS,
+                server_color: Some(self.other_color),
+                spectate: false,
+                hosting: None,
+            },
+        }))
+    }
+
+    /// The indexes of the book entries listed for the chosen mode, joined ones to join and
+    /// hosted ones to host
+    fn listed(&self) -> Vec<usize> {
+        match self.mode.uses_network() {
+            true => self
+                .book
+                .entries()
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.as_server == (self.mode == Mode::Host))
+                .map(|(index, _)| index)
+                .take(BOOK_ROWS)
+                .collect(),
+            false => Vec::new(),
+        }
+    }
+
+    fn save_book(&self) {
+        if let Err(err) = self.book.write(&self.book_file) {
+            println!("{}", err);
+        }
+    }
+
+    /// Give the entry being named what was typed
+    fn finish_naming(&mut self) {
+        if let Some((index, nickname)) = self.naming.take() {
+            self.book.rename(index, &nickname);
+            self.save_book();
+        }
+    }
+
+    fn draw_button(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        rect: Rect,
+        label: &str,
+        color: graphics::Color,
+    ) {
+        canvas.draw(
+            &self.button_mesh,
+            graphics::DrawParam::default().dest_rect(rect).color(color),
+        );
+
+        let mut text = Text::new(label);
+        text.set_scale(rect.h / 2.0);
+        let text_width = text.dimensions(ctx).map_or(0.0, |dims| dims.w);
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: rect.x + (rect.w - text_width) / 2.0,
+                    y: rect.y + rect.h / 4.0,
+                })
+                .color(MENU_TEXT_COLOR),
+        );
+    }
+
+    /// Draw the start screen, with a banner on top while there is a newer `release`. The
+    /// random `identity` played under, if any, is shown in its color.
+    pub(crate) fn draw(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        release: Option<&Release>,
+        identity: Option<&Identity>,
+    ) {
+        let (width, height) = {
+            let cords = canvas.screen_coordinates().unwrap();
+            (cords.w, cords.h)
+        };
+        let layout = MenuLayout::new(width, height);
+
+        let title = Rect::new(0.0, 0.1 * height, width, 0.12 * height);
+        self.draw_button(ctx, canvas, title, "Chess", graphics::Color::WHITE);
+
+        for (mode, rect) in layout.modes {
+            let label = match mode {
+                Mode::Host => "Host game",
+                Mode::Join => "Join game",
+                Mode::Local => "Local game",
+                Mode::Computer => "Computer",
+            };
+            let color = match mode == self.mode {
+                true => CHOSEN_BUTTON_COLOR,
+                false => BUTTON_COLOR,
+            };
+            self.draw_button(ctx, canvas, rect, label, color);
+        }
+
+        if self.mode.uses_network() {
+            self.draw_button(ctx, canvas, layout.ip_field, &self.ip, FIELD_COLOR);
+            self.draw_button(ctx, canvas, layout.test, "Test", BUTTON_COLOR);
+        }
+
+        for (index, [entry_rect, pin, name, delete]) in self.listed().into_iter().zip(layout.book) {
+            let entry = &self.book.entries()[index];
+            let label = match &self.naming {
+                Some((naming, nickname)) if *naming == index => format!("{}_", nickname),
+                _ => entry.label(),
+            };
+            self.draw_button(ctx, canvas, entry_rect, &label, FIELD_COLOR);
+            let pin_color = match entry.pinned {
+                true => CHOSEN_BUTTON_COLOR,
+                false => BUTTON_COLOR,
+            };
+            self.draw_button(ctx, canvas, pin, "P", pin_color);
+            self.draw_button(ctx, canvas, name, "N", BUTTON_COLOR);
+            self.draw_button(ctx, canvas, delete, "X", BUTTON_COLOR);
+        }
+
+        if self.mode.chooses_color() {
+            for (color, rect) in layout.colors {
+                let other = match self.mode {
+                    Mode::Computer => "Computer",
+                    _ => "Server",
+                };
+                let label = match color {
+                    chess_network_protocol::Color::White => format!("{} plays white", other),
+                    chess_network_protocol::Color::Black => format!("{} plays black", other),
+                };
+                let button_color = match color == self.other_color {
+                    true => CHOSEN_BUTTON_COLOR,
+                    false => BUTTON_COLOR,
+                };
+                self.draw_button(ctx, canvas, rect, &label, button_color);
+            }
+        }
+
+        let start_color = match self.choice() {
+            Some(_) => CHOSEN_BUTTON_COLOR,
+            None => BUTTON_COLOR,
+        };
+        self.draw_button(ctx, canvas, layout.start, "Start", start_color);
+        self.draw_button(ctx, canvas, layout.browse, "Saved games", BUTTON_COLOR);
+        self.draw_button(ctx, canvas, layout.interop, "Interop report", BUTTON_COLOR);
+        self.draw_button(ctx, canvas, layout.trainer, "Square trainer", BUTTON_COLOR);
+        self.draw_button(ctx, canvas, layout.sandbox, "Sandbox", BUTTON_COLOR);
+        match identity {
+            Some(identity) => {
+                let label = format!("As {}", identity.handle);
+                let color = avatar_color(identity);
+                self.draw_button(ctx, canvas, layout.identity, &label, color);
+                self.draw_button(ctx, canvas, layout.drop_identity, "X", BUTTON_COLOR);
+            }
+            None => {
+                let label = "Random identity";
+                self.draw_button(ctx, canvas, layout.identity, label, BUTTON_COLOR);
+            }
+        }
+
+        if let Some(release) = release {
+            self.draw_button(ctx, canvas, layout.banner, &release.banner(), BANNER_COLOR);
+            self.draw_button(ctx, canvas, layout.open_release, "Open", BUTTON_COLOR);
+            self.draw_button(
+                ctx,
+                canvas,
+                layout.skip_release,
+                "Skip version",
+                BUTTON_COLOR,
+            );
+            self.draw_button(ctx, canvas, layout.dismiss_release, "X", BUTTON_COLOR);
+        }
+    }
+
+    /// Handle a click, returning the chosen game once Start is pressed. The buttons take
+    /// clicks up to `slop` pixels past their edges.
+    pub(crate) fn click(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        slop: f32,
+        release: Option<&Release>,
+    ) -> Option<MenuChoice> {
+        let layout = MenuLayout::new(width, height);
+        let point = Point2 { x, y };
+        let hit = |rect: &Rect| grown(*rect, slop).contains(point);
+        // Clicking anywhere else keeps the nickname typed so far
+        let naming = self.naming.as_ref().map(|(index, _)| *index);
+        self.finish_naming();
+
+        if release.is_some() {
+            if hit(&layout.open_release) {
+                return Some(MenuChoice::OpenRelease);
+            } else if hit(&layout.skip_release) {
+                return Some(MenuChoice::SkipRelease);
+            } else if hit(&layout.dismiss_release) {
+                return Some(MenuChoice::DismissRelease);
+            }
+        }
+        let listed = self.listed();
+        if let Some((index, buttons)) = listed
+            .into_iter()
+            .zip(layout.book)
+            .find(|(_, buttons)| buttons.iter().any(hit))
+        {
+            match buttons.iter().position(hit) {
+                Some(0) => match self.book.entries()[index].pick() {
+                    Some(Pick::Connect(config)) => {
+                        return Some(MenuChoice::Play(Opponent::Network(config)))
+                    }
+                    Some(Pick::Host(address)) => self.ip = address,
+                    None => {}
+                },
+                Some(1) => {
+                    self.book.toggle_pin(index);
+                    self.save_book();
+                }
+                // A second click on it only keeps what was typed
+                Some(2) if naming != Some(index) => {
+                    let nickname = self.book.entries()[index].nickname.clone();
+                    self.naming = Some((index, nickname.unwrap_or_default()));
+                }
+                Some(3) => {
+                    self.book.remove(index);
+                    self.save_book();
+                }
+                _ => {}
+            }
+        } else if let Some((mode, _)) = layout.modes.iter().find(|(_, rect)| hit(rect)) {
+            self.mode = *mode;
+        } else if let Some((color, _)) = layout
+            .colors
+            .iter()
+            .find(|(_, rect)| self.mode.chooses_color() && hit(rect))
+        {
+            self.other_color = *color;
+        } else if self.mode.uses_network() && hit(&layout.test) {
+            return Some(MenuChoice::TestConnection(match self.mode {
+                // On the typed port, the default one until it can be used
+                Mode::Host => Role::Host(
+                    parse_address(&self.ip)
+                        .ok()
+                        .and_then(|address| address.rsplit(':').next()?.parse().ok())
+                        .unwrap_or(DEFAULT_PORT),
+                ),
+                _ => Role::Guest(self.other_color),
+            }));
+        } else if hit(&layout.start) {
+            return self.choice().map(MenuChoice::Play);
+        } else if hit(&layout.browse) {
+            return Some(MenuChoice::Browse);
+        } else if hit(&layout.interop) {
+            return Some(MenuChoice::Interop);
+        } else if hit(&layout.trainer) {
+            return Some(MenuChoice::Trainer);
+        } else if hit(&layout.sandbox) {
+            return Some(MenuChoice::Sandbox);
+        } else if hit(&layout.drop_identity) {
+            // Only drawn under a handle, without one there is nothing to drop
+            return Some(MenuChoice::DropIdentity);
+        } else if hit(&layout.identity) {
+            return Some(MenuChoice::RollIdentity);
+        }
+        None
+    }
+
+    /// Handle a key press, returning the chosen game once Enter is pressed
+    pub(crate) fn key(&mut self, keycode: KeyCode) -> Option<Opponent> {
+        if let Some((_, nickname)) = &mut self.naming {
+            match keycode {
+                KeyCode::Back => {
+                    nickname.pop();
+                }
+                KeyCode::Return | KeyCode::NumpadEnter => self.finish_naming(),
+                _ => {}
+            }
+            return None;
+        }
+        match keycode {
+            KeyCode::Back => {
+                self.ip.pop();
+                None
+            }
+            KeyCode::Return | KeyCode::NumpadEnter => self.choice(),
+            _ => None,
+        }
+    }
+
+    /// Typed characters go into the nickname being typed or the address field
+    pub(crate) fn text_input(&mut self, character: char) {
+        if let Some((_, nickname)) = &mut self.naming {
+            if !character.is_control() {
+                nickname.push(character);
+            }
+        } else if self.mode.uses_network() && !character.is_control() && !character.is_whitespace()
+        {
+            self.ip.push(character);
+        }
+    }
+}
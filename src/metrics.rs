@@ -0,0 +1,629 @@
+use crate::strikes::Strike;
+use crate::GameState;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Error, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A scraper that doesn't finish its request in this time is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Longer request heads than this aren't from a scraper
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Every kind of message sent or received, in the order they are listed
+pub const MESSAGE_KINDS: [&str; 7] = [
+    "handshake",
+    "state",
+    "error",
+    "resigned",
+    "draw",
+    "move",
+    "resign",
+];
+/// Who a socket is connected to
+pub const ROLES: [&str; 2] = ["peer", "spectator"];
+/// How finished games ended
+pub const RESULTS: [&str; 3] = ["white", "black", "draw"];
+/// Whether the refresh after an opponent's move had been worked out ahead
+pub const SPECULATIONS: [&str; 2] = ["hit", "miss"];
+
+/// Counters and gauges for the network play, all updated without locking
+pub struct Metrics {
+    started: Instant,
+    games_started: AtomicU64,
+    games_finished: [AtomicU64; RESULTS.len()],
+    connected: [AtomicU64; ROLES.len()],
+    sent: [AtomicU64; MESSAGE_KINDS.len()],
+    received: [AtomicU64; MESSAGE_KINDS.len()],
+    bytes_out: AtomicU64,
+    bytes_in: AtomicU64,
+    protocol_errors: AtomicU64,
+    illegal_move_strikes: AtomicU64,
+    ply: AtomicU64,
+    speculated: [AtomicU64; SPECULATIONS.len()],
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// The metrics once `enable` was called, the instrumentation does nothing before that
+#[inline]
+pub fn global() -> Option<&'static Metrics> {
+    METRICS.get()
+}
+
+/// Start collecting the metrics the rest of the program reports through `global`
+pub fn enable() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// A connected socket, counted by its role until it is dropped
+pub struct Socket {
+    role: &'static str,
+}
+
+impl Socket {
+    pub fn open(role: &'static str) -> Self {
+        if let Some(metrics) = global() {
+            metrics.connected(role);
+        }
+        Self { role }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Some(metrics) = global() {
+            metrics.disconnected(self.role);
+        }
+    }
+}
+
+#[inline]
+fn position(list: &[&str], name: &str) -> usize {
+    list.iter()
+        .position(|listed| *listed == name)
+        .unwrap_or_else(|| panic!("{} isn't listed", name))
+}
+
+/// A label value with backslashes, quotes and newlines escaped as the text format wants
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write one metric with its help and type lines, and a sample for every label value
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(Option<(&str, &str)>, u64)],
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (label, value) in samples {
+        match label {
+            Some((key, label)) => {
+                let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, key, escape(label), value);
+            }
+            None => {
+                let _ = writeln!(out, "{} {}", name, value);
+            }
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            games_started: AtomicU64::new(0),
+            games_finished: Default::default(),
+            connected: Default::default(),
+            sent: Default::default(),
+            received: Default::default(),
+            bytes_out: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            protocol_errors: AtomicU64::new(0),
+            illegal_move_strikes: AtomicU64::new(0),
+            ply: AtomicU64::new(0),
+            speculated: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn game_started(&self) {
+        self.games_started.fetch_add(1, Ordering::Relaxed);
+        self.ply.store(0, Ordering::Relaxed);
+    }
+
+    /// Count a game by how it ended, an ongoing one isn't finished
+    pub fn game_finished(&self, state: &GameState) {
+        let result = match state {
+            GameState::Ongoing => return,
+            GameState::Checkmate(loser)
+            | GameState::Resigned(loser)
+            | GameState::TimeForfeit(loser)
+            | GameState::Forfeit(loser)
+            | GameState::Stalled(loser, _) => match loser {
+                jonathan_hallstrom_chess::Color::White => "black",
+                jonathan_hallstrom_chess::Color::Black => "white",
+            },
+            _ => "draw",
+        };
+        self.games_finished[position(&RESULTS, result)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn connected(&self, role: &str) {
+        self.connected[position(&ROLES, role)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn disconnected(&self, role: &str) {
+        // Never below zero, even for a socket counted before the metrics were enabled
+        let _ = self.connected[position(&ROLES, role)].fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |count| count.checked_sub(1),
+        );
+    }
+
+    #[inline]
+    pub fn sent(&self, kind: &str, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.sent[position(&MESSAGE_KINDS, kind)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn received(&self, kind: &str) {
+        self.received[position(&MESSAGE_KINDS, kind)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Bytes read from a socket, the messages in them are counted once parsed
+    #[inline]
+    pub fn read(&self, bytes: usize) {
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// A message that didn't parse as the protocol
+    #[inline]
+    pub fn protocol_error(&self) {
+        self.protocol_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A rejected client move, moves sent out of turn count as protocol errors
+    pub fn strike(&self, strike: Strike) {
+        match strike {
+            Strike::Illegal => &self.illegal_move_strikes,
+            Strike::Protocol => &self.protocol_errors,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn ply(&self, ply: usize) {
+        self.ply.store(ply as u64, Ordering::Relaxed);
+    }
+
+    /// An opponent's move played, `hit` if its refresh had been worked out ahead
+    #[inline]
+    pub fn speculated(&self, hit: bool) {
+        let outcome = match hit {
+            true => "hit",
+            false => "miss",
+        };
+        self.speculated[position(&SPECULATIONS, outcome)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn render(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let labelled = |key, names: &'static [&'static str], counters: &[AtomicU64]| {
+            names
+                .iter()
+                .zip(counters)
+                .map(|(name, counter)| (Some((key, *name)), load(counter)))
+                .collect::<Vec<_>>()
+        };
+        let mut out = String::new();
+        write_metric(
+            &mut out,
+            "chess_games_started_total",
+            "counter",
+            "Networked games that started.",
+            &[(None, load(&self.games_started))],
+        );
+        write_metric(
+            &mut out,
+            "chess_games_finished_total",
+            "counter",
+            "Networked games that finished, by the winner.",
+            &labelled("result", &RESULTS, &self.games_finished),
+        );
+        write_metric(
+            &mut out,
+            "chess_connected_sockets",
+            "gauge",
+            "Sockets connected now, by who is on the other end.",
+            &labelled("role", &ROLES, &self.connected),
+        );
+        write_metric(
+            &mut out,
+            "chess_messages_sent_total",
+            "counter",
+            "Protocol messages sent, by type.",
+            &labelled("type", &MESSAGE_KINDS, &self.sent),
+        );
+        write_metric(
+            &mut out,
+            "chess_messages_received_total",
+            "counter",
+            "Protocol messages received, by type.",
+            &labelled("type", &MESSAGE_KINDS, &self.received),
+        );
+        write_metric(
+            &mut out,
+            "chess_bytes_sent_total",
+            "counter",
+            "Bytes written to sockets.",
+            &[(None, load(&self.bytes_out))],
+        );
+        write_metric(
+            &mut out,
+            "chess_bytes_received_total",
+            "counter",
+            "Bytes read from sockets.",
+            &[(None, load(&self.bytes_in))],
+        );
+        write_metric(
+            &mut out,
+            "chess_protocol_errors_total",
+            "counter",
+            "Messages that didn't parse and moves sent out of turn.",
+            &[(None, load(&self.protocol_errors))],
+        );
+        write_metric(
+            &mut out,
+            "chess_illegal_move_strikes_total",
+            "counter",
+            "Illegal moves rejected from the client.",
+            &[(None, load(&self.illegal_move_strikes))],
+        );
+        write_metric(
+            &mut out,
+            "chess_game_ply",
+            "gauge",
+            "Plies played in the current game.",
+            &[(None, load(&self.ply))],
+        );
+        write_metric(
+            &mut out,
+            "chess_speculated_moves_total",
+            "counter",
+            "Opponent moves played, by whether their refresh was worked out ahead.",
+            &labelled("outcome", &SPECULATIONS, &self.speculated),
+        );
+        write_metric(
+            &mut out,
+            "chess_uptime_seconds",
+            "gauge",
+            "Seconds since the metrics were enabled.",
+            &[(None, self.started.elapsed().as_secs())],
+        );
+        out
+    }
+}
+
+/// Answer GET /metrics on `port` of localhost from a thread that lives as long as the
+/// program, the address it listens on is returned
+pub fn serve(metrics: &'static Metrics, port: u16) -> Result<SocketAddr, Error> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let address = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // One at a time, scrapes are rare and answered at once
+            if let Err(err) = answer(metrics, stream) {
+                println!("Metrics request failed: {}", err);
+            }
+        }
+    });
+    Ok(address)
+}
+
+/// Read the request head and answer it, anything that isn't HTTP just gets the
+/// connection closed
+fn answer(metrics: &Metrics, stream: TcpStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut read = request_line.len();
+    // The headers mean nothing here, they are only read past
+    loop {
+        let mut header = String::new();
+        let length = reader.read_line(&mut header)?;
+        read += length;
+        if length == 0 || read > MAX_REQUEST_BYTES {
+            return Ok(());
+        }
+        if header == "\r\n" || header == "\n" {
+            break;
+        }
+    }
+    let (status, body) = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", "/metrics", version] if version.starts_with("HTTP/1.") => {
+            ("200 OK", metrics.render())
+        }
+        ["GET", _, version] if version.starts_with("HTTP/1.") => {
+            ("404 Not Found", "Only /metrics is here\n".to_owned())
+        }
+        _ => return Ok(()),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::sync::Arc;
+
+    /// Metrics outliving the test, the endpoint's thread needs them for the rest of the program
+    fn leaked() -> &'static Metrics {
+        Box::leak(Box::new(Metrics::new()))
+    }
+
+    fn scrape(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn counters_and_gauges_are_told_apart() {
+        let metrics = Metrics::new();
+        metrics.sent("state", 120);
+        metrics.connected("peer");
+        let text = metrics.render();
+        assert!(text.contains("# TYPE chess_messages_sent_total counter\n"));
+        assert!(text.contains("chess_messages_sent_total{type=\"state\"} 1\n"));
+        assert!(text.contains("chess_messages_sent_total{type=\"move\"} 0\n"));
+        assert!(text.contains("# TYPE chess_connected_sockets gauge\n"));
+        assert!(text.contains("chess_connected_sockets{role=\"peer\"} 1\n"));
+        assert!(text.contains("chess_bytes_sent_total 120\n"));
+        // A socket can't be disconnected twice
+        metrics.disconnected("peer");
+        metrics.disconnected("peer");
+        assert!(metrics
+            .render()
+            .contains("chess_connected_sockets{role=\"peer\"} 0\n"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+        let mut out = String::new();
+        write_metric(&mut out, "x", "gauge", "An x.", &[(Some(("k", "\"")), 2)]);
+        assert_eq!(out, "# HELP x An x.\n# TYPE x gauge\nx{k=\"\\\"\"} 2\n");
+    }
+
+    #[test]
+    fn updates_from_many_threads_all_count() {
+        let metrics = Arc::new(Metrics::new());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let metrics = Arc::clone(&metrics);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        metrics.received("move");
+                        metrics.read(10);
+                        metrics.strike(Strike::Illegal);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let text = metrics.render();
+        assert!(text.contains("chess_messages_received_total{type=\"move\"} 8000\n"));
+        assert!(text.contains("chess_bytes_received_total 80000\n"));
+        assert!(text.contains("chess_illegal_move_strikes_total 8000\n"));
+    }
+
+    #[test]
+    fn malformed_requests_are_hung_up_on() {
+        let address = serve(leaked(), 0).unwrap();
+        assert!(address.ip().is_loopback());
+        assert_eq!(scrape(address, "HELLO\r\n\r\n"), "");
+        assert!(scrape(address, "GET /other HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn games_are_counted_by_the_winner() {
+        use jonathan_hallstrom_chess::Color;
+
+        let metrics = Metrics::new();
+        metrics.game_started();
+        metrics.ply(12);
+        metrics.game_finished(&GameState::Checkmate(Color::White));
+        metrics.game_finished(&GameState::Forfeit(Color::Black));
+        metrics.game_finished(&GameState::Repetition);
+        metrics.game_finished(&GameState::Ongoing);
+        let text = metrics.render();
+        assert!(text.contains("chess_games_started_total 1\n"));
+        assert!(text.contains("chess_games_finished_total{result=\"white\"} 1\n"));
+        assert!(text.contains("chess_games_finished_total{result=\"black\"} 1\n"));
+        assert!(text.contains("chess_games_finished_total{result=\"draw\"} 1\n"));
+        assert!(text.contains("chess_game_ply 12\n"));
+        // A new game starts from nothing
+        metrics.game_started();
+        assert!(metrics.render().contains("chess_game_ply 0\n"));
+    }
+
+    /// A game over the loopback between a server and a client in this process, the
+    /// only test that enables the metrics the network thread reports to
+    #[test]
+    fn a_networked_game_shows_up_in_the_metrics() {
+        use crate::config::NetworkConfig;
+        use crate::network::{
+            self, internal_to_server_handshake, Handshake, Network, NetworkEvent,
+        };
+        use crate::session::tests::play;
+        use crate::session::Session;
+        use crate::strikes::{Strikes, DEFAULT_MAX_STRIKES};
+        use chess_network_protocol::{ClientToServer, ClientToServerHandshake};
+        use jonathan_hallstrom_chess::Color;
+
+        fn next(network: &mut Network) -> NetworkEvent {
+            let deadline = Instant::now() + REQUEST_TIMEOUT;
+            loop {
+                match network.poll() {
+                    Some(NetworkEvent::Disconnected) => panic!("disconnected"),
+                    Some(event) => return event,
+                    None if Instant::now() > deadline => panic!("nothing arrived"),
+                    None => thread::sleep(Duration::from_millis(5)),
+                }
+            }
+        }
+        /// The client follows the server's answer to what was last sent
+        fn answer(client: &mut Network, session: &mut Session) -> Result<(), String> {
+            match next(client) {
+                NetworkEvent::FromServer(message) => session.apply_server_message(&message),
+                _ => panic!("not from the server"),
+            }
+        }
+
+        let metrics = enable();
+        let address = serve(metrics, 0).unwrap();
+        let config = |is_server, address: String| NetworkConfig {
+            is_server,
+            address,
+            strict_port: false,
+            connect_attempts: 3,
+            server_color: Some(chess_network_protocol::Color::Black),
+            spectate: false,
+            hosting: None,
+        };
+
+        let mut server_session = Session::new(None).unwrap();
+        let mut strikes = Strikes::new(DEFAULT_MAX_STRIKES, DEFAULT_MAX_STRIKES);
+        let mut server = network::start(
+            &config(true, "127.0.0.1:0".to_owned()),
+            Handshake::ServerToClient(internal_to_server_handshake(&server_session)),
+        );
+        let NetworkEvent::Listening(listening) = next(&mut server) else {
+            panic!("not listening");
+        };
+        let mut client_session = Session::new(None).unwrap();
+        let mut client = network::start(
+            &config(false, listening.to_string()),
+            Handshake::ClientToServer(ClientToServerHandshake {
+                server_color: chess_network_protocol::Color::Black,
+            }),
+        );
+        assert!(matches!(next(&mut server), NetworkEvent::Connected(..)));
+        assert!(matches!(next(&mut client), NetworkEvent::Connected(..)));
+
+        // Fool's mate, the client plays White and sends its first move again on the way
+        let mut first = None;
+        for notation in ["f2f3", "e7e5", "f2f3", "g2g4", "d8h4"] {
+            if server_session.board.get_curr_player() == Color::Black {
+                play(&mut server_session, notation);
+                let mv = *server_session.played_moves.last().unwrap();
+                server.send_board_state(&server_session, &mv).unwrap();
+                answer(&mut client, &mut client_session).unwrap();
+                continue;
+            }
+            let mut legal = client_session.board.get_legal_moves().into_iter();
+            let mv = first
+                .filter(|_| notation == "f2f3")
+                .or_else(|| legal.find(|mv| mv.to_algebraic_notation() == notation))
+                .unwrap();
+            first.get_or_insert(mv);
+            client.send_move(&mv).unwrap();
+            let NetworkEvent::FromClient(ClientToServer::Move(client_move)) = next(&mut server)
+            else {
+                panic!("no move from the client");
+            };
+            match server_session.judge_client_move(&client_move, Color::Black, &mut strikes) {
+                Ok(mv) => {
+                    server_session.apply_move(mv);
+                    server.send_board_state(&server_session, &mv).unwrap();
+                    answer(&mut client, &mut client_session).unwrap();
+                }
+                Err((message, _)) => {
+                    server.send_error(&server_session, message);
+                    assert!(answer(&mut client, &mut client_session).is_err());
+                }
+            }
+        }
+        assert!(client_session.game_state == GameState::Checkmate(Color::White));
+
+        // Counted once the writing side is done with them, which may be after they arrived
+        let expected = [
+            "chess_connected_sockets{role=\"peer\"} 2",
+            "chess_messages_sent_total{type=\"handshake\"} 2",
+            "chess_messages_received_total{type=\"handshake\"} 2",
+            "chess_messages_sent_total{type=\"move\"} 3",
+            "chess_messages_received_total{type=\"move\"} 3",
+            "chess_messages_sent_total{type=\"state\"} 4",
+            "chess_messages_received_total{type=\"state\"} 4",
+            "chess_messages_sent_total{type=\"error\"} 1",
+            "chess_messages_received_total{type=\"error\"} 1",
+            "chess_illegal_move_strikes_total 1",
+            "chess_protocol_errors_total 0",
+        ];
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        let response = loop {
+            let response = scrape(address, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+            let done = expected
+                .iter()
+                .all(|line| response.contains(&format!("{}\n", line)));
+            if done || Instant::now() > deadline {
+                break response;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        for line in expected {
+            assert!(response.contains(&format!("{}\n", line)), "{}", line);
+        }
+        let bytes = |name: &str| {
+            response
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .unwrap()
+        };
+        // Everything written was read on the other side of the loopback
+        assert!(bytes("chess_bytes_sent_total") > 0);
+        assert_eq!(
+            bytes("chess_bytes_sent_total"),
+            bytes("chess_bytes_received_total")
+        );
+    }
+}
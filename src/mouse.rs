@@ -0,0 +1,429 @@
+use ggez::event::MouseButton;
+use serde_json::{Map, Value};
+
+/// Where a button is pressed. The same button may do something else in each, so bindings
+/// only conflict within one.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Region {
+    Board,
+    // The move list, the graph and everything else of a game beside the board
+    Panel,
+    // The move entry bar while it is open
+    Entry,
+    // The start screen and the other screens outside a game
+    Menu,
+}
+
+const REGIONS: [Region; 4] = [Region::Board, Region::Panel, Region::Entry, Region::Menu];
+
+impl Region {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Board => "board",
+            Region::Panel => "panel",
+            Region::Entry => "entry",
+            Region::Menu => "menu",
+        }
+    }
+
+    #[inline]
+    pub fn parse(name: &str) -> Option<Self> {
+        REGIONS.into_iter().find(|region| region.name() == name)
+    }
+}
+
+/// What a button can be bound to
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum MouseAction {
+    // Pick up a piece, play it or press whatever is under the pointer
+    Select,
+    // Let go of the selected piece and close the move chooser
+    Cancel,
+    // Type the system's selection or clipboard into the move entry bar
+    Paste,
+}
+
+const ACTIONS: [MouseAction; 3] = [MouseAction::Select, MouseAction::Cancel, MouseAction::Paste];
+
+impl MouseAction {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            MouseAction::Select => "select",
+            MouseAction::Cancel => "cancel",
+            MouseAction::Paste => "paste",
+        }
+    }
+
+    #[inline]
+    pub fn parse(name: &str) -> Option<Self> {
+        ACTIONS.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// A button as the settings name it, like "middle" or "button8"
+pub fn button_name(button: MouseButton) -> String {
+    match button {
+        MouseButton::Left => "left".to_owned(),
+        MouseButton::Right => "right".to_owned(),
+        MouseButton::Middle => "middle".to_owned(),
+        MouseButton::Other(number) => format!("button{}", number),
+    }
+}
+
+pub fn parse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "left" => Some(MouseButton::Left),
+        "right" => Some(MouseButton::Right),
+        "middle" => Some(MouseButton::Middle),
+        name => name
+            .strip_prefix("button")?
+            .parse()
+            .ok()
+            .map(MouseButton::Other),
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Binding {
+    pub region: Region,
+    pub button: MouseButton,
+    pub action: MouseAction,
+}
+
+/// Which button does what where, kept in the profile settings. The defaults are what the
+/// buttons did before they could be bound, with a middle click on the entry bar pasting.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MouseBindings {
+    // In the order they were bound, the first of a region and button counts
+    bindings: Vec<Binding>,
+}
+
+impl Default for MouseBindings {
+    fn default() -> Self {
+        use MouseAction::{Cancel, Paste, Select};
+        let (left, middle, right) = (MouseButton::Left, MouseButton::Middle, MouseButton::Right);
+        let bindings = [
+            (Region::Board, left, Select),
+            (Region::Board, middle, Select),
+            (Region::Board, right, Cancel),
+            (Region::Panel, left, Select),
+            (Region::Panel, middle, Select),
+            (Region::Panel, right, Cancel),
+            (Region::Entry, left, Select),
+            (Region::Entry, middle, Paste),
+            (Region::Entry, right, Cancel),
+            (Region::Menu, left, Select),
+            (Region::Menu, middle, Select),
+            (Region::Menu, right, Select),
+        ];
+        Self {
+            bindings: bindings
+                .into_iter()
+                .map(|(region, button, action)| Binding {
+                    region,
+                    button,
+                    action,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl MouseBindings {
+    #[inline]
+    pub fn action(&self, region: Region, button: MouseButton) -> Option<MouseAction> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.region == region && binding.button == button)
+            .map(|binding| binding.action)
+    }
+
+    /// Bind `button` in `region`, or unbind it with None. What it did before is returned, a
+    /// button only does one thing at a time in a region.
+    pub fn bind(
+        &mut self,
+        region: Region,
+        button: MouseButton,
+        action: Option<MouseAction>,
+    ) -> Option<MouseAction> {
+        let before = self.action(region, button);
+        self.bindings
+            .retain(|binding| binding.region != region || binding.button != button);
+        if let Some(action) = action {
+            self.bindings.push(Binding {
+                region,
+                button,
+                action,
+            });
+        }
+        before
+    }
+
+    /// What is wrong with the bindings, like a button bound twice in a region as damaged
+    /// settings can have it, or a region where nothing selects
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (at, binding) in self.bindings.iter().enumerate() {
+            let first = self.bindings[..at]
+                .iter()
+                .find(|other| other.region == binding.region && other.button == binding.button);
+            if let Some(first) = first.filter(|first| first.action != binding.action) {
+                conflicts.push(format!(
+                    "{} is bound to both {} and {} on the {}, it does {}",
+                    button_name(binding.button),
+                    first.action.name(),
+                    binding.action.name(),
+                    binding.region.name(),
+                    first.action.name()
+                ));
+            }
+        }
+        for region in [Region::Board, Region::Panel, Region::Menu] {
+            if !self
+                .bindings
+                .iter()
+                .any(|binding| binding.region == region && binding.action == MouseAction::Select)
+            {
+                conflicts.push(format!("No button selects on the {}", region.name()));
+            }
+        }
+        conflicts
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.bindings
+                .iter()
+                .map(|binding| {
+                    let mut object = Map::new();
+                    object.insert(
+                        "region".to_owned(),
+                        Value::String(binding.region.name().to_owned()),
+                    );
+                    object.insert(
+                        "button".to_owned(),
+                        Value::String(button_name(binding.button)),
+                    );
+                    object.insert(
+                        "action".to_owned(),
+                        Value::String(binding.action.name().to_owned()),
+                    );
+                    Value::Object(object)
+                })
+                .collect(),
+        )
+    }
+
+    /// The bindings in `value`, the damaged ones are left out
+    pub fn from_json(value: &Value) -> Self {
+        fn field<'a>(binding: &'a Value, key: &str) -> Option<&'a str> {
+            binding.get(key).and_then(Value::as_str)
+        }
+        let bindings = value
+            .as_array()
+            .map(|bindings| {
+                bindings
+                    .iter()
+                    .filter_map(|binding| {
+                        Some(Binding {
+                            region: Region::parse(field(binding, "region")?)?,
+                            button: parse_button(field(binding, "button")?)?,
+                            action: MouseAction::parse(field(binding, "action")?)?,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { bindings }
+    }
+}
+
+/// One binding set from the command line, None unbinds the button
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct MouseChange {
+    pub region: Region,
+    pub button: MouseButton,
+    pub action: Option<MouseAction>,
+}
+
+impl MouseChange {
+    /// A change written like "board.right=select" or "panel.middle=off"
+    pub fn parse(text: &str) -> Option<Self> {
+        let (place, action) = text.split_once('=')?;
+        let (region, button) = place.split_once('.')?;
+        Some(Self {
+            region: Region::parse(region)?,
+            button: parse_button(button)?,
+            action: match action {
+                "off" => None,
+                action => Some(MouseAction::parse(action)?),
+            },
+        })
+    }
+}
+
+/// The buttons held down with what they were pressed as. A gesture ends as it started: a
+/// release does what its press did, even when the buttons were bound anew in between.
+#[derive(Default)]
+pub struct Gestures {
+    held: Vec<(MouseButton, MouseAction)>,
+}
+
+impl Gestures {
+    /// What pressing `button` in `region` does, remembered for its release
+    pub fn press(
+        &mut self,
+        bindings: &MouseBindings,
+        region: Region,
+        button: MouseButton,
+    ) -> Option<MouseAction> {
+        // A release the window never saw leaves nothing behind
+        self.held.retain(|(held, _)| *held != button);
+        let action = bindings.action(region, button)?;
+        self.held.push((button, action));
+        Some(action)
+    }
+
+    /// What releasing `button` finishes, None if its press did nothing
+    pub fn release(&mut self, button: MouseButton) -> Option<MouseAction> {
+        let at = self.held.iter().position(|(held, _)| *held == button)?;
+        Some(self.held.remove(at).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_defaults_keep_what_the_buttons_did() {
+        let bindings = MouseBindings::default();
+        for region in [Region::Board, Region::Panel] {
+            assert_eq!(
+                bindings.action(region, MouseButton::Right),
+                Some(MouseAction::Cancel)
+            );
+            for button in [MouseButton::Left, MouseButton::Middle] {
+                assert_eq!(bindings.action(region, button), Some(MouseAction::Select));
+            }
+        }
+        for button in [MouseButton::Left, MouseButton::Middle, MouseButton::Right] {
+            assert_eq!(
+                bindings.action(Region::Menu, button),
+                Some(MouseAction::Select)
+            );
+        }
+        assert_eq!(
+            bindings.action(Region::Entry, MouseButton::Middle),
+            Some(MouseAction::Paste)
+        );
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn conflicts_are_only_within_a_region() {
+        let mut bindings = MouseBindings::default();
+        // The same button does something else elsewhere, which is fine
+        assert_eq!(
+            bindings.bind(
+                Region::Board,
+                MouseButton::Middle,
+                Some(MouseAction::Cancel)
+            ),
+            Some(MouseAction::Select)
+        );
+        assert!(bindings.conflicts().is_empty());
+        bindings.bind(Region::Board, MouseButton::Left, Some(MouseAction::Cancel));
+        assert_eq!(bindings.conflicts(), ["No button selects on the board"]);
+        bindings.bind(Region::Board, MouseButton::Right, Some(MouseAction::Select));
+        assert!(bindings.conflicts().is_empty());
+
+        let mut twice = MouseBindings::default().to_json();
+        if let Value::Array(list) = &mut twice {
+            list.extend(
+                MouseBindings {
+                    bindings: vec![Binding {
+                        region: Region::Panel,
+                        button: MouseButton::Left,
+                        action: MouseAction::Paste,
+                    }],
+                }
+                .to_json()
+                .as_array()
+                .cloned()
+                .unwrap_or_default(),
+            );
+        }
+        let twice = MouseBindings::from_json(&twice);
+        assert_eq!(twice.conflicts().len(), 1);
+        assert_eq!(
+            twice.action(Region::Panel, MouseButton::Left),
+            Some(MouseAction::Select)
+        );
+    }
+
+    #[test]
+    fn bindings_read_back() {
+        let mut bindings = MouseBindings::default();
+        bindings.bind(
+            Region::Panel,
+            MouseButton::Other(8),
+            Some(MouseAction::Paste),
+        );
+        bindings.bind(Region::Menu, MouseButton::Right, None);
+        assert_eq!(MouseBindings::from_json(&bindings.to_json()), bindings);
+        assert_eq!(
+            MouseBindings::from_json(&Value::String("left".to_owned())),
+            MouseBindings { bindings: vec![] }
+        );
+        assert_eq!(
+            MouseChange::parse("panel.button8=paste"),
+            Some(MouseChange {
+                region: Region::Panel,
+                button: MouseButton::Other(8),
+                action: Some(MouseAction::Paste),
+            })
+        );
+        assert_eq!(
+            MouseChange::parse("board.middle=off").map(|change| change.action),
+            Some(None)
+        );
+        assert!(MouseChange::parse("board.left").is_none());
+        assert!(MouseChange::parse("side.left=select").is_none());
+    }
+
+    #[test]
+    fn a_gesture_ends_under_the_bindings_it_started_with() {
+        let mut bindings = MouseBindings::default();
+        let mut gestures = Gestures::default();
+        assert_eq!(
+            gestures.press(&bindings, Region::Board, MouseButton::Left),
+            Some(MouseAction::Select)
+        );
+        // Swapped for the left-handed in the middle of a drag
+        bindings.bind(Region::Board, MouseButton::Left, Some(MouseAction::Cancel));
+        bindings.bind(Region::Board, MouseButton::Right, Some(MouseAction::Select));
+        assert_eq!(
+            gestures.release(MouseButton::Left),
+            Some(MouseAction::Select)
+        );
+        assert_eq!(gestures.release(MouseButton::Left), None);
+        assert_eq!(
+            gestures.press(&bindings, Region::Board, MouseButton::Left),
+            Some(MouseAction::Cancel)
+        );
+        assert_eq!(
+            gestures.release(MouseButton::Left),
+            Some(MouseAction::Cancel)
+        );
+
+        bindings.bind(Region::Board, MouseButton::Middle, None);
+        assert_eq!(
+            gestures.press(&bindings, Region::Board, MouseButton::Middle),
+            None
+        );
+        assert_eq!(gestures.release(MouseButton::Middle), None);
+    }
+}
@@ -0,0 +1,184 @@
+use jonathan_hallstrom_chess::Color;
+use std::time::Duration;
+
+/// What marks a move the players negotiated at in the move list
+pub const GLYPH: &str = "(=)";
+
+/// What a step of a draw offer did. Draw offers are the only negotiation the protocol has,
+/// answering one with a move lets it lapse.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Outcome {
+    Offered,
+    Accepted,
+    Declined,
+    Lapsed,
+}
+
+/// One step of a draw offer, kept with the game it happened in
+#[derive(PartialEq, Copy, Clone)]
+pub struct Negotiation {
+    // After this many plies
+    pub ply: usize,
+    // Who offered, answered or let their offer lapse. By color, so both sides record the
+    // same whoever started it.
+    pub side: Color,
+    pub outcome: Outcome,
+    // Since the game started, to the second
+    pub at: Duration,
+}
+
+#[inline]
+fn side_name(side: Color) -> &'static str {
+    match side {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+impl Negotiation {
+    /// A sentence like "White offers a draw at 3:05", as written in PGN comments
+    pub fn describe(&self) -> String {
+        let seconds = self.at.as_secs();
+        format!(
+            "{} {} at {}:{:02}",
+            side_name(self.side),
+            match self.outcome {
+                Outcome::Offered => "offers a draw",
+                Outcome::Accepted => "accepts the draw",
+                Outcome::Declined => "declines the draw",
+                Outcome::Lapsed => "lets the draw offer lapse",
+            },
+            seconds / 60,
+            seconds % 60
+        )
+    }
+
+    /// The negotiation `describe` wrote, at `ply`
+    pub fn parse(sentence: &str, ply: usize) -> Option<Self> {
+        let (said, time) = sentence.trim().trim_end_matches('.').rsplit_once(" at ")?;
+        let (side, outcome) = said.split_once(' ')?;
+        let side = match side {
+            "White" => Color::White,
+            "Black" => Color::Black,
+            _ => return None,
+        };
+        let outcome = match outcome {
+            "offers a draw" => Outcome::Offered,
+            "accepts the draw" => Outcome::Accepted,
+            "declines the draw" => Outcome::Declined,
+            "lets the draw offer lapse" => Outcome::Lapsed,
+            _ => return None,
+        };
+        let (minutes, seconds) = time
+            .split_once(':')
+            .filter(|(_, seconds)| seconds.len() == 2)?;
+        let seconds = seconds
+            .parse::<u64>()
+            .ok()
+            .filter(|seconds| *seconds < 60)?;
+        Some(Self {
+            ply,
+            side,
+            outcome,
+            at: Duration::from_secs(
+                minutes
+                    .parse::<u64>()
+                    .ok()?
+                    .checked_mul(60)?
+                    .checked_add(seconds)?,
+            ),
+        })
+    }
+}
+
+/// The negotiations of a game in the order they happened. Like bookmarks they belong to the
+/// game, a new game starts without any.
+#[derive(Clone, PartialEq, Default)]
+pub struct Negotiations {
+    events: Vec<Negotiation>,
+}
+
+impl Negotiations {
+    #[inline]
+    pub fn record(&mut self, ply: usize, side: Color, outcome: Outcome, at: Duration) {
+        self.events.push(Negotiation {
+            ply,
+            side,
+            outcome,
+            at: Duration::from_secs(at.as_secs()),
+        });
+    }
+
+    /// Who has a draw offer standing
+    pub fn open(&self) -> Option<Color> {
+        self.events
+            .last()
+            .filter(|event| event.outcome == Outcome::Offered)
+            .map(|event| event.side)
+    }
+
+    /// The negotiations after `ply`
+    pub fn at(&self, ply: usize) -> impl Iterator<Item = &Negotiation> {
+        self.events.iter().filter(move |event| event.ply == ply)
+    }
+
+    /// Forget the negotiations past `ply`, once the moves they were made at are replaced
+    #[inline]
+    pub fn forget_after(&mut self, ply: usize) {
+        self.events.retain(|event| event.ply <= ply);
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Negotiation> {
+        self.events.iter()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentences_read_back() {
+        for outcome in [
+            Outcome::Offered,
+            Outcome::Accepted,
+            Outcome::Declined,
+            Outcome::Lapsed,
+        ] {
+            let event = Negotiation {
+                ply: 59,
+                side: Color::Black,
+                outcome,
+                at: Duration::from_secs(62 * 60 + 5),
+            };
+            assert!(event.describe().ends_with(" at 62:05"));
+            assert!(Negotiation::parse(&event.describe(), 59) == Some(event));
+        }
+        assert!(Negotiation::parse("White offers a draw", 3).is_none());
+        assert!(Negotiation::parse("White offers a draw at 3:5", 3).is_none());
+        assert!(Negotiation::parse("Both offer a draw at 3:05", 3).is_none());
+        assert!(Negotiation::parse("White offers a draw at 999999999999999999:05", 3).is_none());
+    }
+
+    #[test]
+    fn an_offer_stands_until_it_is_answered() {
+        let mut negotiations = Negotiations::default();
+        let second = Duration::from_secs(1);
+        assert!(negotiations.open().is_none());
+        negotiations.record(4, Color::White, Outcome::Offered, 10 * second);
+        assert!(negotiations.open() == Some(Color::White));
+        negotiations.record(5, Color::White, Outcome::Lapsed, 14 * second);
+        assert!(negotiations.open().is_none());
+        negotiations.record(9, Color::Black, Outcome::Offered, 30 * second);
+        negotiations.record(9, Color::White, Outcome::Declined, 31 * second);
+        assert_eq!(negotiations.at(9).count(), 2);
+        negotiations.forget_after(8);
+        assert_eq!(negotiations.iter().count(), 2);
+    }
+}
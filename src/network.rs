@@ -1,80 +1,959 @@
-use crate::network::Handshake::{ClientToServer, ServerToClient};
-use crate::{parse_move, BoardRepr, Move, Square};
+use crate::config::NetworkConfig;
+use crate::connectivity::{self, Hosting};
+use crate::metrics;
+use crate::ordering;
+use crate::session::Session;
+use crate::transcript::{self, WireLog};
+use crate::{opposite_color, parse_move, GameState, Move, MoveParseError, Square};
 use chess_network_protocol;
-use chess_network_protocol::{ClientToServerHandshake, ServerToClientHandshake};
+use chess_network_protocol::{
+    ClientToServer, ClientToServerHandshake, Features, ServerToClient, ServerToClientHandshake,
+};
 use jonathan_hallstrom_chess::PieceType;
-use serde_json;
-use std::net::{TcpListener, TcpStream};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-pub(crate) struct Network {
-    pub(crate) stream: TcpStream,
-    pub(crate) is_server: bool,
-    pub(crate) player_color: jonathan_hallstrom_chess::Color,
+// A spectator that doesn't read its messages is dropped rather than stalling the game
+const SPECTATOR_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// The client keeps trying for a while so it can be started before the server
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+// Ports after a taken one the server tries before letting the system pick any free one
+const PORT_FALLBACKS: u16 = 10;
+/// The protocol has no loss on time, the server sends this as an error carrying the winner
+pub const OUT_OF_TIME: &str = "Out of time";
+/// Sent the same way once the client has had too many moves rejected
+pub const ILLEGAL_MOVES_FORFEIT: &str = "forfeit: repeated illegal moves";
+
+/// Handle to the network thread, which owns the socket
+pub struct Network {
+    // Messages go out with their number in the ordering audit, None while it is off
+    sender: Sender<(Option<u64>, Outgoing)>,
+    receiver: Receiver<NetworkEvent>,
+    pub is_server: bool,
+    // Only known by the server once the client has sent its handshake
+    pub player_color: jonathan_hallstrom_chess::Color,
+    pub connected: bool,
+    // Connected as a client that only watches the game
+    pub spectating: bool,
+    // Where the server can be reached once it listens, for the other player to type in
+    pub listening: Option<SocketAddr>,
+    // What both sides support, only our own features until the handshake is done
+    features: Vec<Features>,
+    ordering: Option<Arc<ordering::Connection>>,
+    // Every message exchanged with the peer, for the transcript
+    log: Arc<WireLog>,
+}
+
+pub enum NetworkEvent {
+    // Our color, for the client the starting position sent by the server,
+    // the features both sides support and for the client the moves the server allows
+    Connected(
+        jonathan_hallstrom_chess::Color,
+        Option<String>,
+        Vec<Features>,
+        Option<Vec<chess_network_protocol::Move>>,
+    ),
+    // The server listens here, sent before it waits for the client
+    Listening(SocketAddr),
+    FromClient(ClientToServer),
+    FromServer(ServerToClient),
+    // A handshake the peer sent again after the game started
+    Rehandshake(Handshake),
+    // Why the connection couldn't be made, sent before Disconnected
+    Unreachable(String),
+    Disconnected,
+}
+
+/// Why a move couldn't be sent to the peer
+#[derive(Debug)]
+pub enum SendError {
+    // The protocol has no way to describe it
+    Unsendable(MoveParseError),
+    // The network thread stopped, the peer is gone
+    Disconnected,
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SendError::Unsendable(err) => write!(f, "{}", err),
+            SendError::Disconnected => write!(f, "The connection to the opponent is gone"),
+        }
+    }
+}
+
+enum Outgoing {
+    // With the plies played when it was sent, spectators never see an earlier one after it.
+    // None for what only concerns the player, answers to its moves and draw offers.
+    ToClient(Option<usize>, ServerToClient),
+    ToServer(ClientToServer),
 }
 
-pub(crate) enum Handshake {
+/// The network thread's end of the messages the game sends, and the records kept of them
+struct Queue {
+    receiver: Receiver<(Option<u64>, Outgoing)>,
+    ordering: Option<Arc<ordering::Connection>>,
+    log: Arc<WireLog>,
+}
+
+pub enum Handshake {
     ServerToClient(ServerToClientHandshake),
     ClientToServer(ClientToServerHandshake),
 }
 
-pub(crate) fn connect(as_server: bool, ip: &str) -> TcpStream {
+/// A message read once the handshakes are done. Some peers send their handshake again when
+/// they hiccup, or every few moves to be safe.
+pub enum Frame<T> {
+    Message(T),
+    Handshake(Handshake),
+}
+
+/// What a message read after the handshakes is, tried as a `T` first and then as either
+/// handshake. Text that isn't JSON never gets this far, and JSON that is none of them fails
+/// with why it isn't a `T`.
+pub fn decode_frame<T: DeserializeOwned>(value: Value) -> Result<Frame<T>, serde_json::Error> {
+    let primary = match serde_json::from_value(value.clone()) {
+        Ok(message) => return Ok(Frame::Message(message)),
+        Err(err) => err,
+    };
+    if let Ok(handshake) = serde_json::from_value(value.clone()) {
+        return Ok(Frame::Handshake(Handshake::ServerToClient(handshake)));
+    }
+    match serde_json::from_value(value) {
+        Ok(handshake) => Ok(Frame::Handshake(Handshake::ClientToServer(handshake))),
+        Err(_) => Err(primary),
+    }
+}
+
+/// Log a frame and wrap it for the game, a handshake is called out in the transcript
+fn frame_event<T: Serialize + MessageKind>(
+    frame: Frame<T>,
+    log: &WireLog,
+    wrap: fn(T) -> NetworkEvent,
+) -> (&'static str, NetworkEvent) {
+    match frame {
+        Frame::Message(message) => {
+            log.received(&message);
+            (message.kind(), wrap(message))
+        }
+        Frame::Handshake(handshake) => {
+            log.note("The peer sent a handshake again after the game started");
+            match &handshake {
+                Handshake::ServerToClient(handshake) => log.received(handshake),
+                Handshake::ClientToServer(handshake) => log.received(handshake),
+            }
+            ("handshake", NetworkEvent::Rehandshake(handshake))
+        }
+    }
+}
+
+/// What a message is counted as in the metrics
+trait MessageKind {
+    fn kind(&self) -> &'static str;
+}
+
+impl MessageKind for ServerToClientHandshake {
+    fn kind(&self) -> &'static str {
+        "handshake"
+    }
+}
+
+impl MessageKind for ClientToServerHandshake {
+    fn kind(&self) -> &'static str {
+        "handshake"
+    }
+}
+
+impl MessageKind for ServerToClient {
+    fn kind(&self) -> &'static str {
+        match self {
+            ServerToClient::State { .. } => "state",
+            ServerToClient::Error { .. } => "error",
+            ServerToClient::Resigned { .. } => "resigned",
+            ServerToClient::Draw { .. } => "draw",
+        }
+    }
+}
+
+impl MessageKind for ClientToServer {
+    fn kind(&self) -> &'static str {
+        match self {
+            ClientToServer::Move(_) => "move",
+            ClientToServer::Resign => "resign",
+            ClientToServer::Draw => "draw",
+        }
+    }
+}
+
+impl<T: MessageKind> MessageKind for Frame<T> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Frame::Message(message) => message.kind(),
+            Frame::Handshake(_) => "handshake",
+        }
+    }
+}
+
+impl MessageKind for Outgoing {
+    fn kind(&self) -> &'static str {
+        match self {
+            Outgoing::ToClient(_, message) => message.kind(),
+            Outgoing::ToServer(message) => message.kind(),
+        }
+    }
+}
+
+/// Counts the bytes read through it for the metrics
+struct Counted<'a> {
+    stream: &'a TcpStream,
+    read: usize,
+}
+
+impl Read for Counted<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = (&mut &*self.stream).read(buf)?;
+        self.read += read;
+        Ok(read)
+    }
+}
+
+/// Everyone the server accepted after the player, only ever written to
+struct Spectators {
+    streams: Vec<Box<dyn Write + Send>>,
+    // The position a spectator joining now is sent, kept up to date with every move
+    handshake: ServerToClientHandshake,
+    // Plies played by the last message passed on
+    ply: usize,
+}
+
+/// A square as the internal board indexes it, row 0 is the eighth rank and col 0 the a file
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct BoardCoord {
+    pub row: usize,
+    pub col: usize,
+}
+
+/// A square as the protocol indexes it, x is the file and y the rank with 0 at White's side
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct NetworkCoord {
+    pub x: usize,
+    pub y: usize,
+}
+
+// The only place the two orientations are mapped onto each other
+impl BoardCoord {
+    #[inline]
+    pub fn to_network(self) -> NetworkCoord {
+        NetworkCoord {
+            x: self.col,
+            y: 7 - self.row,
+        }
+    }
+}
+
+impl NetworkCoord {
+    /// None for a square off the board, which a peer can send
+    #[inline]
+    pub fn to_board(self) -> Option<BoardCoord> {
+        (self.x < 8 && self.y < 8).then(|| BoardCoord {
+            row: 7 - self.y,
+            col: self.x,
+        })
+    }
+}
+
+impl From<(usize, usize)> for BoardCoord {
+    #[inline]
+    fn from((row, col): (usize, usize)) -> Self {
+        Self { row, col }
+    }
+}
+
+#[inline]
+fn client_color(server_color: chess_network_protocol::Color) -> jonathan_hallstrom_chess::Color {
+    match server_color {
+        chess_network_protocol::Color::White => jonathan_hallstrom_chess::Color::Black,
+        chess_network_protocol::Color::Black => jonathan_hallstrom_chess::Color::White,
+    }
+}
+
+/// Connect and handshake on a background thread, the returned handle reports progress as events
+pub fn start(config: &NetworkConfig, handshake: Handshake) -> Network {
+    let spectating = config.spectate;
+    let is_server = matches!(handshake, Handshake::ServerToClient(_));
+    let player_color = match &handshake {
+        // Spectators watch from White's side
+        _ if spectating => jonathan_hallstrom_chess::Color::White,
+        // client_to_server_handshake contains the color the server will play as,
+        // so we will play as the opposite color
+        Handshake::ClientToServer(client_to_server_handshake) => {
+            client_color(client_to_server_handshake.server_color)
+        }
+        Handshake::ServerToClient(_) => jonathan_hallstrom_chess::Color::White,
+    };
+
+    let (sender, outgoing) = mpsc::channel();
+    let (incoming, receiver) = mpsc::channel();
+    let endpoint = Endpoint {
+        address: config.address.clone(),
+        // Shared with the config, which is dropped once the game has started
+        hosting: config.hosting.as_ref().and_then(|hosting| {
+            hosting
+                .try_clone()
+                .map_err(|err| println!("Can't take over the tested port: {}", err))
+                .ok()
+        }),
+        strict_port: config.strict_port,
+        connect_attempts: config.connect_attempts,
+    };
+    let ordering = ordering::global().map(|audit| audit.connection());
+    let log = Arc::new(WireLog::default());
+    let queue = Queue {
+        receiver: outgoing,
+        ordering: ordering.clone(),
+        log: Arc::clone(&log),
+    };
+    thread::spawn(move || {
+        let connected = run(endpoint, handshake, spectating, queue, &incoming);
+        if let Err(err) = connected {
+            println!("Network error: {}", err);
+        }
+        if let Some(Err(err)) = ordering::global().map(ordering::Audit::write_dump) {
+            println!("Could not write the ordering timeline: {}", err);
+        }
+        let _ = incoming.send(NetworkEvent::Disconnected);
+    });
+
+    Network {
+        sender,
+        receiver,
+        is_server,
+        player_color,
+        connected: false,
+        spectating,
+        listening: None,
+        features: supported_features(),
+        ordering,
+        log,
+    }
+}
+
+/// Everything this GUI handles, advertised by the server in its handshake
+pub fn supported_features() -> Vec<Features> {
+    vec![
+        Features::Castling,
+        Features::EnPassant,
+        Features::Promotion,
+        Features::Stalemate,
+    ]
+}
+
+/// The features in the server's handshake that we support too
+pub fn negotiated_features(handshake: &ServerToClientHandshake) -> Vec<Features> {
+    supported_features()
+        .into_iter()
+        .filter(|feature| handshake.features.contains(feature))
+        .collect()
+}
+
+/// Where to listen or connect, taken from the config for the network thread
+struct Endpoint {
+    address: String,
+    hosting: Option<Hosting>,
+    strict_port: bool,
+    connect_attempts: u32,
+}
+
+fn run(
+    endpoint: Endpoint,
+    handshake: Handshake,
+    spectating: bool,
+    queue: Queue,
+    incoming: &Sender<NetworkEvent>,
+) -> Result<(), Error> {
+    let is_server = matches!(handshake, Handshake::ServerToClient(_));
+    let spectators = match &handshake {
+        Handshake::ServerToClient(handshake) => Some(Arc::new(Mutex::new(Spectators {
+            streams: Vec::new(),
+            handshake: handshake.clone(),
+            ply: 0,
+        }))),
+        Handshake::ClientToServer(_) => None,
+    };
+    let (stream, listener) = match connect(is_server, endpoint, incoming) {
+        Ok(connected) => connected,
+        Err(err) => {
+            // Shown in the window instead of a plain lost connection
+            let _ = incoming.send(NetworkEvent::Unreachable(err.to_string()));
+            return Err(err);
+        }
+    };
+    let _connection = metrics::Socket::open("peer");
+    let (player_color, server_fen, features, server_moves) =
+        exchange_handshakes(&stream, handshake, &queue.log)?;
+    let player_color = match spectating {
+        true => jonathan_hallstrom_chess::Color::White,
+        false => player_color,
+    };
+    let _ = incoming.send(NetworkEvent::Connected(
+        player_color,
+        server_fen,
+        features,
+        server_moves,
+    ));
+
+    // The first client plays, everyone after it watches
+    if let (Some(listener), Some(spectators)) = (listener, &spectators) {
+        let spectators = Arc::clone(spectators);
+        thread::spawn(move || accept_spectators(listener, spectators));
+    }
+    // Spectators are written to on a thread of their own so a slow one never holds up the player
+    let fanout = spectators.map(spawn_fanout);
+
+    let writer = stream.try_clone()?;
+    let writer_events = incoming.clone();
+    let ordering = queue.ordering.clone();
+    let log = Arc::clone(&queue.log);
+    thread::spawn(move || {
+        // Ends once the game drops its handle
+        for (seq, message) in queue.receiver {
+            if let (Some(ordering), Some(seq)) = (&queue.ordering, seq) {
+                ordering.written(seq, message.kind());
+            }
+            let written = match message {
+                Outgoing::ToClient(ply, message) => {
+                    let written =
+                        write_message(&writer, &message).map(|()| queue.log.sent(&message));
+                    // Passed on in the order the game sent them, which is the order of the plies
+                    if let (Some(fanout), Some(ply)) = (&fanout, ply) {
+                        let _ = fanout.send((ply, message));
+                    }
+                    written
+                }
+                Outgoing::ToServer(message) => {
+                    write_message(&writer, &message).map(|()| queue.log.sent(&message))
+                }
+            };
+            if let Err(err) = written {
+                println!("Network write failed: {}", err);
+                let _ = writer_events.send(NetworkEvent::Disconnected);
+                return;
+            }
+        }
+    });
+
+    loop {
+        // What couldn't be read ends the connection, the transcript says why
+        let unreadable = |err: Error| {
+            log.note(&format!("Couldn't read a message from the peer: {}", err));
+            err
+        };
+        let (kind, event) = match is_server {
+            true => frame_event(
+                read_frame(&stream).map_err(unreadable)?,
+                &log,
+                NetworkEvent::FromClient,
+            ),
+            false => frame_event(
+                read_frame(&stream).map_err(unreadable)?,
+                &log,
+                NetworkEvent::FromServer,
+            ),
+        };
+        // The protocol has no room for numbers, so a peer's messages are only timed
+        if let Some(ordering) = &ordering {
+            ordering.received(None, kind);
+        }
+        // The game is gone, nobody is left to read the messages
+        if incoming.send(event).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Block until a whole message has been read
+#[inline]
+fn read_message<T: DeserializeOwned + MessageKind>(stream: &TcpStream) -> Result<T, Error> {
+    read_decoded(stream, serde_json::from_value)
+}
+
+/// Block until a whole message has been read once the handshakes are done, see `decode_frame`
+#[inline]
+fn read_frame<T: DeserializeOwned + MessageKind>(stream: &TcpStream) -> Result<Frame<T>, Error> {
+    read_decoded(stream, decode_frame)
+}
+
+/// Read a whole JSON value and decode it, malformed JSON fails before it is decoded
+fn read_decoded<T: MessageKind>(
+    stream: &TcpStream,
+    decode: fn(Value) -> Result<T, serde_json::Error>,
+) -> Result<T, Error> {
+    let mut counted = Counted { stream, read: 0 };
+    let message = serde_json::Deserializer::from_reader(&mut counted)
+        .into_iter::<Value>()
+        .next()
+        .map(|value| value.and_then(decode));
+    if let Some(metrics) = metrics::global() {
+        metrics.read(counted.read);
+        match &message {
+            Some(Ok(message)) => metrics.received(message.kind()),
+            Some(Err(err)) if !err.is_io() => metrics.protocol_error(),
+            _ => {}
+        }
+    }
+    match message {
+        Some(message) => Ok(message?),
+        // The stream ended, the peer closed the connection
+        None => Err(Error::from(ErrorKind::UnexpectedEof)),
+    }
+}
+
+/// Write a whole message at once
+fn write_message<W: Write, T: Serialize + MessageKind>(
+    mut stream: W,
+    message: &T,
+) -> Result<(), Error> {
+    let bytes = serde_json::to_vec(message)?;
+    stream.write_all(&bytes)?;
+    if let Some(metrics) = metrics::global() {
+        metrics.sent(message.kind(), bytes.len());
+    }
+    Ok(())
+}
+
+/// The connection to the peer, and for the server the listener spectators connect to.
+/// A server the connection test already bound a port for takes its player from there.
+fn connect(
+    as_server: bool,
+    endpoint: Endpoint,
+    incoming: &Sender<NetworkEvent>,
+) -> Result<(TcpStream, Option<TcpListener>), Error> {
+    let Endpoint {
+        address,
+        hosting,
+        strict_port,
+        connect_attempts,
+    } = endpoint;
     let stream;
+    let mut listener = None;
     if as_server {
-        println!("Listening to clients on IP: {}.", ip);
-        let listener = TcpListener::bind(ip).unwrap();
-        stream = listener.accept().unwrap().0;
+        let (bound, joined) = match hosting {
+            Some(hosting) => (hosting.listener, hosting.joined),
+            None => (bind(&resolve(&address, true)?, strict_port)?, None),
+        };
+        let local = bound.local_addr()?;
+        match local.ip().is_unspecified() {
+            true => println!(
+                "=== Listening to clients on {}, connect to this computer's LAN IP ===",
+                describe_listening(local)
+            ),
+            false => println!("=== Listening to clients on {} ===", local),
+        }
+        let _ = incoming.send(NetworkEvent::Listening(local));
+        stream = match joined {
+            Some(joined) => joined,
+            None => accept_player(&bound)?,
+        };
+        listener = Some(bound);
     } else {
-        println!("Connecting to IP: {}", ip);
-        stream = TcpStream::connect(ip).unwrap();
+        stream = connect_with_retry(
+            &resolve(&address, false)?,
+            connect_attempts,
+            CONNECT_RETRY_DELAY,
+            CONNECT_TIMEOUT,
+        )?;
     }
 
     println!("Connection established");
-    stream
+    Ok((stream, listener))
+}
+
+/// Every address a typed address stands for. Only a port is every address of this
+/// computer for the server, IPv6 first since it usually takes IPv4 clients too, and
+/// this computer for the client.
+pub(crate) fn resolve(address: &str, as_server: bool) -> Result<Vec<SocketAddr>, Error> {
+    let address = address.trim();
+    if let Ok(port) = address.trim_start_matches(':').parse::<u16>() {
+        return match as_server {
+            true => Ok(vec![
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)),
+                SocketAddr::from(([0, 0, 0, 0], port)),
+            ]),
+            false => Ok(("localhost", port).to_socket_addrs()?.collect()),
+        };
+    }
+    let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    match addresses.is_empty() {
+        true => Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} has no address", address),
+        )),
+        false => Ok(addresses),
+    }
 }
 
-pub(crate) fn handshake(stream: TcpStream, handshake: Handshake) -> Network {
-    let mut is_server;
-    let mut player_color;
+/// Listen on the first of the addresses that works. A taken port moves on to the next
+/// ports and then any free one, unless the port has to be exactly the one asked for.
+pub(crate) fn bind(addresses: &[SocketAddr], strict_port: bool) -> Result<TcpListener, Error> {
+    let with_port = |port: u16| -> Vec<SocketAddr> {
+        addresses
+            .iter()
+            .map(|address| SocketAddr::new(address.ip(), port))
+            .collect()
+    };
+    let port = addresses.first().map_or(0, SocketAddr::port);
+    let in_use = |port: u16| {
+        Error::new(
+            ErrorKind::AddrInUse,
+            format!("Port {} already in use", port),
+        )
+    };
+
+    let err = match TcpListener::bind(addresses) {
+        Ok(listener) => return Ok(listener),
+        Err(err) => err,
+    };
+    if err.kind() != ErrorKind::AddrInUse {
+        return Err(err);
+    }
+    if strict_port {
+        return Err(in_use(port));
+    }
+    // Port 0 is any free port
+    let fallbacks = (1..=PORT_FALLBACKS).filter_map(|offset| port.checked_add(offset));
+    for fallback in fallbacks.chain([0]) {
+        match fallback {
+            0 => println!("Port {} already in use, trying any free port", port),
+            _ => println!("Port {} already in use, trying {}", port, fallback),
+        }
+        match TcpListener::bind(&with_port(fallback)[..]) {
+            Ok(listener) => return Ok(listener),
+            Err(err) if err.kind() == ErrorKind::AddrInUse => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(in_use(port))
+}
+
+/// The first connection that is a player, a guest's connection test still running after
+/// the host started the game is answered on the side
+fn accept_player(listener: &TcpListener) -> Result<TcpStream, Error> {
+    loop {
+        let stream = listener.accept()?.0;
+        if !connectivity::is_ping(&stream) {
+            return Ok(stream);
+        }
+        thread::spawn(move || connectivity::answer_pings(stream));
+    }
+}
+
+/// Where the server listens, in words when it is bound to every address of the computer.
+/// Which of those the other player can reach isn't known here, it is up to their network.
+pub fn describe_listening(bound: SocketAddr) -> String {
+    match bound.ip().is_unspecified() {
+        true => format!("port {} of every address", bound.port()),
+        false => bound.to_string(),
+    }
+}
+
+/// Try to reach the server a number of times, waiting `delay` between attempts
+fn connect_with_retry(
+    addresses: &[SocketAddr],
+    attempts: u32,
+    delay: Duration,
+    timeout: Duration,
+) -> Result<TcpStream, Error> {
+    let attempts = attempts.max(1);
+    for attempt in 1..=attempts {
+        for address in addresses {
+            println!(
+                "Connecting to IP: {} (attempt {} of {})",
+                address, attempt, attempts
+            );
+            match TcpStream::connect_timeout(address, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => println!("Connection attempt failed: {}", err),
+            }
+        }
+        if attempt < attempts {
+            thread::sleep(delay);
+        }
+    }
+    let tried: Vec<String> = addresses.iter().map(SocketAddr::to_string).collect();
+    Err(Error::new(
+        ErrorKind::NotConnected,
+        format!("Could not reach {}", tried.join(" or ")),
+    ))
+}
+
+/// Accept spectators for as long as the server runs
+fn accept_spectators(listener: TcpListener, spectators: Arc<Mutex<Spectators>>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                println!("Spectator failed to connect: {}", err);
+                continue;
+            }
+        };
+        let spectators = Arc::clone(&spectators);
+        // A spectator that never sends its handshake must not keep others out
+        thread::spawn(move || {
+            if let Err(err) = add_spectator(stream, &spectators) {
+                println!("Spectator handshake failed: {}", err);
+            }
+        });
+    }
+}
+
+fn add_spectator(stream: TcpStream, spectators: &Mutex<Spectators>) -> Result<(), Error> {
+    // The color a spectator asks for means nothing, the player already chose
+    let _: ClientToServerHandshake = read_message(&stream)?;
+    stream.set_write_timeout(Some(SPECTATOR_WRITE_TIMEOUT))?;
+
+    // Held until the spectator is added so it can't miss a move made in between
+    let mut spectators = spectators.lock().unwrap();
+    write_message(&stream, &spectators.handshake)?;
+    spectators.streams.push(Box::new(stream));
+    if let Some(metrics) = metrics::global() {
+        metrics.connected("spectator");
+    }
+    println!("Spectator joined, {} watching", spectators.streams.len());
+    Ok(())
+}
+
+/// Pass every message the game sends the player on to the spectators, until the game is over
+fn spawn_fanout(spectators: Arc<Mutex<Spectators>>) -> Sender<(usize, ServerToClient)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (ply, message) in receiver {
+            broadcast(&spectators, ply, &message);
+        }
+    });
+    sender
+}
+
+/// Pass the game after `ply` plies on to the spectators and remember it for the ones joining
+/// later. Anything from before what they were last sent would take them back, it is dropped.
+/// The lock is only held to update the position, never while a spectator is written to, so
+/// a slow one can't keep others from joining.
+fn broadcast(spectators: &Mutex<Spectators>, ply: usize, message: &ServerToClient) {
+    let mut streams = {
+        let mut spectators = spectators.lock().unwrap();
+        if ply < spectators.ply {
+            println!(
+                "Not passing ply {} on to the spectators, they are at {}",
+                ply, spectators.ply
+            );
+            return;
+        }
+        let (board, moves, joever) = match message {
+            ServerToClient::State {
+                board,
+                moves,
+                joever,
+                ..
+            }
+            | ServerToClient::Error {
+                board,
+                moves,
+                joever,
+                ..
+            } => (board, moves.clone(), *joever),
+            ServerToClient::Resigned { board, joever } => (board, Vec::new(), *joever),
+            // Only a draw that ends the game is passed on
+            ServerToClient::Draw { board, moves } => {
+                (board, moves.clone(), chess_network_protocol::Joever::Draw)
+            }
+        };
+        spectators.handshake.board = *board;
+        spectators.handshake.moves = moves;
+        spectators.handshake.joever = joever;
+        spectators.ply = ply;
+        // Joining from here on they are sent the position this message brings instead
+        std::mem::take(&mut spectators.streams)
+    };
+    // Losing a spectator doesn't concern the game
+    streams.retain_mut(|stream| {
+        let written = write_message(stream, message);
+        if written.is_err() {
+            println!("Spectator left");
+            if let Some(metrics) = metrics::global() {
+                metrics.disconnected("spectator");
+            }
+        }
+        written.is_ok()
+    });
+    let mut spectators = spectators.lock().unwrap();
+    streams.append(&mut spectators.streams);
+    spectators.streams = streams;
+}
+
+// What the handshakes settle, in the order `NetworkEvent::Connected` has it
+type Handshaken = (
+    jonathan_hallstrom_chess::Color,
+    Option<String>,
+    Vec<Features>,
+    Option<Vec<chess_network_protocol::Move>>,
+);
+
+/// Exchange handshakes with the peer and return the color we play as, the starting position
+/// if we are the client, the features both sides support and the moves the server allows
+fn exchange_handshakes(
+    stream: &TcpStream,
+    handshake: Handshake,
+    log: &WireLog,
+) -> Result<Handshaken, Error> {
     match handshake {
         Handshake::ServerToClient(server_to_client_handshake) => {
-            is_server = true;
-
-            let received: ClientToServerHandshake = serde_json::from_reader(&stream).unwrap();
+            let received: ClientToServerHandshake = read_message(stream)?;
             println!("Handshake from client: {:?}", received);
+            log.received(&received);
+
+            write_message(stream, &server_to_client_handshake)?;
+            log.sent(&server_to_client_handshake);
 
             // This is the color the client wants us to play as
-            player_color = match received.server_color {
+            let player_color = match received.server_color {
                 chess_network_protocol::Color::White => jonathan_hallstrom_chess::Color::White,
                 chess_network_protocol::Color::Black => jonathan_hallstrom_chess::Color::Black,
             };
-
-            serde_json::to_writer(&stream, &server_to_client_handshake).unwrap();
+            // The client's handshake has no features, it has to cope with ours
+            Ok((
+                player_color,
+                None,
+                server_to_client_handshake.features,
+                None,
+            ))
         }
         Handshake::ClientToServer(client_to_server_handshake) => {
-            is_server = false;
+            write_message(stream, &client_to_server_handshake)?;
+            log.sent(&client_to_server_handshake);
 
-            // client_to_server_handshake contains the color the server will play as,
-            // so we will play as the opposite color
-            player_color = match client_to_server_handshake.server_color {
-                chess_network_protocol::Color::White => jonathan_hallstrom_chess::Color::Black,
-                chess_network_protocol::Color::Black => jonathan_hallstrom_chess::Color::White,
-            };
-
-            serde_json::to_writer(&stream, &client_to_server_handshake).unwrap();
-
-            let received: ServerToClientHandshake = serde_json::from_reader(&stream).unwrap();
+            let received: ServerToClientHandshake = read_message(stream)?;
             println!("Handshake from server: {:?}", received);
+            log.received(&received);
+
+            Ok((
+                client_color(client_to_server_handshake.server_color),
+                Some(network_fen(
+                    &received.board,
+                    &received.moves,
+                    jonathan_hallstrom_chess::Color::White,
+                )),
+                negotiated_features(&received),
+                Some(received.moves),
+            ))
         }
     }
-    stream.set_nonblocking(true).unwrap();
-    Network {
-        stream,
-        is_server,
-        player_color,
-    }
 }
 
-pub(crate) fn internal_to_network_piece(internal: &Square) -> chess_network_protocol::Piece {
+#[inline]
+pub(crate) fn network_piece_letter(piece: chess_network_protocol::Piece) -> Option<char> {
+    use chess_network_protocol::Piece;
+    Some(match piece {
+        Piece::WhitePawn => 'P',
+        Piece::WhiteKnight => 'N',
+        Piece::WhiteBishop => 'B',
+        Piece::WhiteRook => 'R',
+        Piece::WhiteQueen => 'Q',
+        Piece::WhiteKing => 'K',
+        Piece::BlackPawn => 'p',
+        Piece::BlackKnight => 'n',
+        Piece::BlackBishop => 'b',
+        Piece::BlackRook => 'r',
+        Piece::BlackQueen => 'q',
+        Piece::BlackKing => 'k',
+        Piece::None => return None,
+    })
+}
+
+/// A FEN for a board the server sent. The protocol has no side to move or castling rights,
+/// so the side to move comes from the legal moves, `to_move` without any, and castling is
+/// allowed wherever king and rook are still on their starting squares.
+pub fn network_fen(
+    board: &[[chess_network_protocol::Piece; 8]; 8],
+    moves: &[chess_network_protocol::Move],
+    to_move: jonathan_hallstrom_chess::Color,
+) -> String {
+    // The network board starts at the first rank, FEN starts at the eighth
+    let placement: Vec<String> = (0..8usize)
+        .rev()
+        .map(|y| {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for piece in board[y] {
+                match network_piece_letter(piece) {
+                    Some(letter) => {
+                        if empty > 0 {
+                            rank.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        rank.push(letter);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            rank
+        })
+        .collect();
+
+    let white_to_move = moves
+        .first()
+        .map_or(to_move == jonathan_hallstrom_chess::Color::White, |mv| {
+            network_piece_letter(board[mv.start_y][mv.start_x]).is_none_or(|c| c.is_uppercase())
+        });
+
+    use chess_network_protocol::Piece;
+    let castling: String = [
+        (
+            'K',
+            board[0][4] == Piece::WhiteKing && board[0][7] == Piece::WhiteRook,
+        ),
+        (
+            'Q',
+            board[0][4] == Piece::WhiteKing && board[0][0] == Piece::WhiteRook,
+        ),
+        (
+            'k',
+            board[7][4] == Piece::BlackKing && board[7][7] == Piece::BlackRook,
+        ),
+        (
+            'q',
+            board[7][4] == Piece::BlackKing && board[7][0] == Piece::BlackRook,
+        ),
+    ]
+    .iter()
+    .filter(|(_, allowed)| *allowed)
+    .map(|(right, _)| *right)
+    .collect();
+
+    format!(
+        "{} {} {} - 0 1",
+        placement.join("/"),
+        match white_to_move {
+            true => 'w',
+            false => 'b',
+        },
+        match castling.is_empty() {
+            true => "-".to_owned(),
+            false => castling,
+        }
+    )
+}
+
+pub fn internal_to_network_piece(internal: &Square) -> chess_network_protocol::Piece {
     match internal {
         Square::Empty => chess_network_protocol::Piece::None,
         Square::Pawn(color) => match color {
@@ -104,29 +983,64 @@ pub(crate) fn internal_to_network_piece(internal: &Square) -> chess_network_prot
     }
 }
 
-pub(crate) fn internal_to_network_board(
+pub fn network_to_internal_piece(network: &chess_network_protocol::Piece) -> Square {
+    use jonathan_hallstrom_chess::Color;
+    match network {
+        chess_network_protocol::Piece::None => Square::Empty,
+        chess_network_protocol::Piece::WhitePawn => Square::Pawn(Color::White),
+        chess_network_protocol::Piece::BlackPawn => Square::Pawn(Color::Black),
+        chess_network_protocol::Piece::WhiteRook => Square::Rook(Color::White),
+        chess_network_protocol::Piece::BlackRook => Square::Rook(Color::Black),
+        chess_network_protocol::Piece::WhiteBishop => Square::Bishop(Color::White),
+        chess_network_protocol::Piece::BlackBishop => Square::Bishop(Color::Black),
+        chess_network_protocol::Piece::WhiteKnight => Square::Knight(Color::White),
+        chess_network_protocol::Piece::BlackKnight => Square::Knight(Color::Black),
+        chess_network_protocol::Piece::WhiteKing => Square::King(Color::White),
+        chess_network_protocol::Piece::BlackKing => Square::King(Color::Black),
+        chess_network_protocol::Piece::WhiteQueen => Square::Queen(Color::White),
+        chess_network_protocol::Piece::BlackQueen => Square::Queen(Color::Black),
+    }
+}
+
+/// The inverse of `internal_to_network_board`
+pub fn network_to_internal_board(
+    network: &[[chess_network_protocol::Piece; 8]; 8],
+) -> [[Square; 8]; 8] {
+    let mut squares = [[Square::Empty; 8]; 8];
+
+    for (y, pieces) in network.iter().enumerate() {
+        for (x, piece) in pieces.iter().enumerate() {
+            // Every index of the array is on the board
+            if let Some(BoardCoord { row, col }) = (NetworkCoord { x, y }).to_board() {
+                squares[row][col] = network_to_internal_piece(piece);
+            }
+        }
+    }
+
+    squares
+}
+
+pub fn internal_to_network_board(
     internal: &[[Square; 8]; 8],
 ) -> [[chess_network_protocol::Piece; 8]; 8] {
     let mut board = [[chess_network_protocol::Piece::None; 8]; 8];
 
-    for row in 0..8usize {
-        for col in 0..8usize {
-            let piece = &internal[row][col];
-            // Network representation has the rows flipped
-            board[7 - row][col] = internal_to_network_piece(piece);
+    for (row, pieces) in internal.iter().enumerate() {
+        for (col, piece) in pieces.iter().enumerate() {
+            let NetworkCoord { x, y } = BoardCoord { row, col }.to_network();
+            board[y][x] = internal_to_network_piece(piece);
         }
     }
 
     board
 }
 
-pub(crate) fn internal_to_network_move(internal: &Move) -> chess_network_protocol::Move {
-    let ((start_x, mut start_y), (end_x, mut end_y)) =
-        parse_move(&internal.to_algebraic_notation());
-
-    // Flip the row
-    start_y = 7 - start_y;
-    end_y = 7 - end_y;
+pub fn internal_to_network_move(
+    internal: &Move,
+) -> Result<chess_network_protocol::Move, MoveParseError> {
+    let (from, to) = parse_move(&internal.to_algebraic_notation())?;
+    let start = BoardCoord::from(from).to_network();
+    let end = BoardCoord::from(to).to_network();
 
     let mut promotion = chess_network_protocol::Piece::None;
 
@@ -140,69 +1054,930 @@ pub(crate) fn internal_to_network_move(internal: &Move) -> chess_network_protoco
             'n' => chess_network_protocol::Piece::BlackKnight,
             'Q' => chess_network_protocol::Piece::WhiteQueen,
             'q' => chess_network_protocol::Piece::BlackQueen,
-            _ => panic!("Invalid promotion piece"),
+            c => return Err(MoveParseError::Promotion(c)),
         }
     }
 
-    chess_network_protocol::Move {
-        start_x,
-        start_y,
-        end_x,
-        end_y,
+    Ok(chess_network_protocol::Move {
+        start_x: start.x,
+        start_y: start.y,
+        end_x: end.x,
+        end_y: end.y,
         promotion,
+    })
+}
+
+/// The piece type a promotion field asks for whatever its color, None without a promotion
+#[inline]
+fn promotion_type(piece: chess_network_protocol::Piece) -> Option<PieceType> {
+    use chess_network_protocol::Piece;
+    match piece {
+        Piece::None => None,
+        Piece::WhiteQueen | Piece::BlackQueen => Some(PieceType::Queen),
+        Piece::WhiteRook | Piece::BlackRook => Some(PieceType::Rook),
+        Piece::WhiteBishop | Piece::BlackBishop => Some(PieceType::Bishop),
+        Piece::WhiteKnight | Piece::BlackKnight => Some(PieceType::Knight),
+        // Nothing promotes to these, so they match no move
+        Piece::WhitePawn | Piece::BlackPawn => Some(PieceType::Pawn),
+        Piece::WhiteKing | Piece::BlackKing => Some(PieceType::King),
     }
 }
 
-pub(crate) fn internal_to_network_moves(internal: &Vec<Move>) -> Vec<chess_network_protocol::Move> {
+/// The legal move a network move describes. The promotion is matched by piece type so a piece
+/// of the wrong color still counts, but a promoting move without a promotion piece matches nothing.
+pub fn network_to_internal_move(
+    network: &chess_network_protocol::Move,
+    legal_moves: &[Move],
+) -> Option<Move> {
+    let from = NetworkCoord {
+        x: network.start_x,
+        y: network.start_y,
+    }
+    .to_board()?;
+    let to = NetworkCoord {
+        x: network.end_x,
+        y: network.end_y,
+    }
+    .to_board()?;
+    let promoted = promotion_type(network.promotion);
+
+    legal_moves.iter().copied().find(|mv| {
+        parse_move(&mv.to_algebraic_notation()).is_ok_and(|(mv_from, mv_to)| {
+            BoardCoord::from(mv_from) == from && BoardCoord::from(mv_to) == to
+        }) && mv.get_promoted_type() == promoted
+    })
+}
+
+pub fn internal_to_network_moves(internal: &[Move]) -> Vec<chess_network_protocol::Move> {
     let mut moves = Vec::new();
     for mv in internal {
-        moves.push(internal_to_network_move(mv));
+        // A move we can't describe can't be played by the peer either
+        if let Ok(mv) = internal_to_network_move(mv) {
+            moves.push(mv);
+        }
     }
     moves
 }
 
-pub(crate) fn internal_to_server_handshake(
-    board_repr: &BoardRepr,
-    board: &jonathan_hallstrom_chess::Board,
-) -> ServerToClientHandshake {
+/// Whether the game on the board is still going, and if not who won, as the protocol has
+/// it. Every kind of draw is a draw, and a win doesn't say how it was won.
+pub fn compute_joever(state: &GameState) -> chess_network_protocol::Joever {
+    let winner = match state {
+        GameState::Ongoing => return chess_network_protocol::Joever::Ongoing,
+        GameState::PositionError => return chess_network_protocol::Joever::Indeterminate,
+        GameState::Stalemate
+        | GameState::Draw
+        | GameState::Repetition
+        | GameState::FiftyMoves
+        | GameState::InsufficientMaterial => return chess_network_protocol::Joever::Draw,
+        GameState::Checkmate(loser)
+        | GameState::Resigned(loser)
+        | GameState::TimeForfeit(loser)
+        | GameState::Forfeit(loser)
+        | GameState::Stalled(loser, _) => opposite_color(*loser),
+    };
+    match winner {
+        jonathan_hallstrom_chess::Color::White => chess_network_protocol::Joever::White,
+        jonathan_hallstrom_chess::Color::Black => chess_network_protocol::Joever::Black,
+    }
+}
+
+pub fn internal_to_server_handshake(session: &Session) -> ServerToClientHandshake {
     ServerToClientHandshake {
-        board: internal_to_network_board(&board_repr.squares),
-        features: vec![
-            chess_network_protocol::Features::EnPassant,
-            chess_network_protocol::Features::Promotion,
-        ],
-        joever: chess_network_protocol::Joever::White,
-        moves: internal_to_network_moves(&board.get_legal_moves()),
+        board: internal_to_network_board(&session.board_repr.squares),
+        features: supported_features(),
+        joever: compute_joever(&session.game_state),
+        moves: internal_to_network_moves(&session.board_repr.moves),
+    }
+}
+
+/// The moves the server allows that are legal here too, for a server that leaves some out.
+/// Disagreements are logged, they are what to look at when a server rejects moves.
+pub fn network_to_internal_moves(
+    network: &[chess_network_protocol::Move],
+    legal_moves: &[Move],
+) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for network_move in network {
+        match network_to_internal_move(network_move, legal_moves) {
+            Some(mv) => moves.push(mv),
+            None => println!(
+                "The server allows {:?}, which isn't legal here",
+                network_move
+            ),
+        }
+    }
+    if cfg!(debug_assertions) {
+        let allowed: Vec<String> = moves.iter().map(Move::to_algebraic_notation).collect();
+        for mv in legal_moves {
+            if !allowed.contains(&mv.to_algebraic_notation()) {
+                println!(
+                    "{} is legal here but the server doesn't allow it",
+                    mv.to_algebraic_notation()
+                );
+            }
+        }
+    }
+    moves
+}
+
+/// The authoritative position after `server_move`, sent for every move the server accepts
+pub fn board_state(
+    session: &Session,
+    server_move: &jonathan_hallstrom_chess::Move,
+) -> Result<ServerToClient, MoveParseError> {
+    Ok(ServerToClient::State {
+        board: internal_to_network_board(&session.board_repr.squares),
+        moves: internal_to_network_moves(&session.board_repr.moves),
+        joever: compute_joever(&session.game_state),
+        move_made: internal_to_network_move(server_move)?,
+    })
+}
+
+/// The unchanged position and why the client's move was rejected
+pub fn error_state(session: &Session, message: String) -> ServerToClient {
+    ServerToClient::Error {
+        board: internal_to_network_board(&session.board_repr.squares),
+        moves: internal_to_network_moves(&session.board_repr.moves),
+        joever: compute_joever(&session.game_state),
+        message,
     }
 }
 
 impl Network {
-    pub(crate) fn send_board_state(
+    /// The next event from the network thread, if any has arrived
+    pub fn poll(&mut self) -> Option<NetworkEvent> {
+        let event = match self.receiver.try_recv() {
+            Ok(event) => event,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => NetworkEvent::Disconnected,
+        };
+        if let NetworkEvent::Listening(address) = &event {
+            self.listening = Some(*address);
+        }
+        if let NetworkEvent::Connected(player_color, _, features, _) = &event {
+            self.player_color = *player_color;
+            self.features = features.clone();
+            self.connected = true;
+        }
+        Some(event)
+    }
+
+    /// The features both sides support, once connected
+    #[inline]
+    pub fn features(&self) -> &[Features] {
+        &self.features
+    }
+
+    /// Keep something the GUI decided about the game in the transcript, like a rejected move
+    pub fn note(&self, text: &str) {
+        self.log.note(text);
+    }
+
+    /// Every line of the log so far, like "<milliseconds> <sent|received|note> <message>"
+    #[inline]
+    pub fn entries(&self) -> Vec<String> {
+        self.log.entries()
+    }
+
+    /// Everything exchanged with the peer so far as a Markdown document, ending in `result`
+    pub fn transcript(&self, result: String) -> String {
+        transcript::document(
+            &self.log.entries(),
+            &transcript::Header {
+                is_server: self.is_server,
+                ours: supported_features(),
+                negotiated: self.features.clone(),
+                result,
+            },
+        )
+    }
+
+    /// Hand a message to the network thread, Err once it stopped
+    fn send(&self, message: Outgoing) -> Result<(), SendError> {
+        let sent = match &self.ordering {
+            Some(ordering) => ordering.enqueue(&self.sender, message),
+            None => self.sender.send((None, message)),
+        };
+        sent.map_err(|_| SendError::Disconnected)
+    }
+
+    pub fn send_board_state(
         &self,
-        repr: &BoardRepr,
-        board: &jonathan_hallstrom_chess::Board,
+        session: &Session,
         server_move: &jonathan_hallstrom_chess::Move,
-    ) {
-        let state = chess_network_protocol::ServerToClient::State {
-            board: internal_to_network_board(&repr.squares),
-            moves: internal_to_network_moves(&board.get_legal_moves()),
-            joever: chess_network_protocol::Joever::White,
-            move_made: internal_to_network_move(server_move),
+    ) -> Result<(), SendError> {
+        let state = board_state(session, server_move).map_err(SendError::Unsendable)?;
+        self.send(Outgoing::ToClient(Some(session.history.len()), state))
+    }
+
+    pub fn send_error(&self, session: &Session, message: String) {
+        // Spectators only hear of the errors that end the game
+        let ends = message == OUT_OF_TIME || message == ILLEGAL_MOVES_FORFEIT;
+        // A stopped network thread reports the disconnect by itself, only moves wait on it
+        let _ = self.send(Outgoing::ToClient(
+            ends.then_some(session.history.len()),
+            error_state(session, message),
+        ));
+    }
+
+    /// Tell the client that the side to move ran out of time, only the server decides that
+    pub fn send_time_forfeit(&self, session: &Session) {
+        if self.is_server {
+            self.send_error(session, OUT_OF_TIME.to_owned());
+        }
+    }
+
+    /// Tell the client it lost for sending too many rejected moves
+    pub fn send_illegal_moves_forfeit(&self, session: &Session) {
+        if self.is_server {
+            self.send_error(session, ILLEGAL_MOVES_FORFEIT.to_owned());
+        }
+    }
+
+    /// Tell the opponent that `resigner` gave up
+    pub fn send_resign(&self, session: &Session, resigner: jonathan_hallstrom_chess::Color) {
+        let _ = self.send(match self.is_server {
+            true => Outgoing::ToClient(
+                Some(session.history.len()),
+                ServerToClient::Resigned {
+                    board: internal_to_network_board(&session.board_repr.squares),
+                    // The winner is the one that didn't resign
+                    joever: match resigner {
+                        jonathan_hallstrom_chess::Color::White => {
+                            chess_network_protocol::Joever::Black
+                        }
+                        jonathan_hallstrom_chess::Color::Black => {
+                            chess_network_protocol::Joever::White
+                        }
+                    },
+                },
+            ),
+            false => Outgoing::ToServer(ClientToServer::Resign),
+        });
+    }
+
+    /// Accept the draw the opponent offered, or claim or announce one that ends the game
+    #[inline]
+    pub fn send_draw(&self, session: &Session) {
+        self.draw(session, true);
+    }
+
+    /// Offer a draw, the game goes on until the opponent accepts
+    #[inline]
+    pub fn offer_draw(&self, session: &Session) {
+        self.draw(session, false);
+    }
+
+    // The two look the same on the wire, only spectators are told of the one that ends the game
+    fn draw(&self, session: &Session, ends: bool) {
+        let _ = self.send(match self.is_server {
+            true => Outgoing::ToClient(
+                ends.then_some(session.history.len()),
+                ServerToClient::Draw {
+                    board: internal_to_network_board(&session.board_repr.squares),
+                    moves: internal_to_network_moves(&session.board_repr.moves),
+                },
+            ),
+            false => Outgoing::ToServer(ClientToServer::Draw),
+        });
+    }
+
+    pub fn send_move(&self, client_move: &Move) -> Result<(), SendError> {
+        let mv = internal_to_network_move(client_move).map_err(SendError::Unsendable)?;
+        self.send(Outgoing::ToServer(ClientToServer::Move(mv)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::tests::play;
+    use crate::session::Rehandshake;
+    use crate::strikes::{Strikes, DEFAULT_MAX_STRIKES};
+    use crate::tests::{
+        board_after, BLACK_PROMOTION_LINE, CASTLING_LINE, EN_PASSANT_LINE, LONG_CASTLING_LINE,
+        PROMOTION_LINE,
+    };
+    use crate::{BoardRepr, ExcludedMoves};
+    use chess_network_protocol::{Joever, Piece};
+    use jonathan_hallstrom_chess::{Board, Color};
+    use std::collections::HashSet;
+    use std::time::Instant;
+
+    // Castling both ways, en passant and promotions for both colors
+    const COORDINATE_LINES: [&[&str]; 5] = [
+        &CASTLING_LINE,
+        &LONG_CASTLING_LINE,
+        &EN_PASSANT_LINE,
+        &PROMOTION_LINE,
+        &BLACK_PROMOTION_LINE,
+    ];
+
+    /// The move between two squares as the protocol has it
+    fn network_move(
+        from: (usize, usize),
+        to: (usize, usize),
+        promotion: Piece,
+    ) -> chess_network_protocol::Move {
+        let (from, to) = (
+            BoardCoord::from(from).to_network(),
+            BoardCoord::from(to).to_network(),
+        );
+        chess_network_protocol::Move {
+            start_x: from.x,
+            start_y: from.y,
+            end_x: to.x,
+            end_y: to.y,
+            promotion,
+        }
+    }
+
+    /// The legal move with the given squares and promotion
+    fn find_move(board: &Board, notation: &str, promoted: Option<PieceType>) -> Move {
+        board
+            .get_legal_moves()
+            .into_iter()
+            .find(|mv| {
+                mv.to_algebraic_notation().get(..4) == Some(notation)
+                    && mv.get_promoted_type() == promoted
+            })
+            .unwrap_or_else(|| panic!("{} isn't legal", notation))
+    }
+
+    #[test]
+    fn the_network_board_starts_at_the_first_rank() {
+        let network = internal_to_network_board(&BoardRepr::new(&Board::default()).squares);
+        assert!(matches!(
+            (network[0][4], network[7][3], network[1][0], network[6][7]),
+            (
+                Piece::WhiteKing,
+                Piece::BlackQueen,
+                Piece::WhitePawn,
+                Piece::BlackPawn
+            )
+        ));
+    }
+
+    #[test]
+    fn moves_use_file_and_rank_from_whites_side() {
+        let e4 = internal_to_network_move(&find_move(&Board::default(), "e2e4", None)).unwrap();
+        assert_eq!((e4.start_x, e4.start_y, e4.end_x, e4.end_y), (4, 1, 4, 3));
+        assert!(e4.promotion == Piece::None);
+
+        let promotion = board_after(&PROMOTION_LINE);
+        let b8 = internal_to_network_move(&find_move(&promotion, "b7b8", Some(PieceType::Queen)))
+            .unwrap();
+        assert_eq!((b8.start_x, b8.start_y, b8.end_x, b8.end_y), (1, 6, 1, 7));
+        assert!(b8.promotion == Piece::WhiteQueen);
+    }
+
+    #[test]
+    fn every_move_converts_back() {
+        for line in COORDINATE_LINES {
+            let moves = board_after(line).get_legal_moves();
+            let mut seen = HashSet::new();
+            for mv in &moves {
+                let notation = mv.to_algebraic_notation();
+                let (from, to) = parse_move(&notation).unwrap();
+                let network = internal_to_network_move(mv).unwrap();
+                // Moves differing only in their promotion must stay apart
+                assert!(seen.insert(format!("{:?}", network)), "{}", notation);
+
+                let squares = (
+                    NetworkCoord {
+                        x: network.start_x,
+                        y: network.start_y,
+                    }
+                    .to_board(),
+                    NetworkCoord {
+                        x: network.end_x,
+                        y: network.end_y,
+                    }
+                    .to_board(),
+                );
+                assert_eq!(
+                    squares,
+                    (Some(BoardCoord::from(from)), Some(BoardCoord::from(to))),
+                    "{} after {:?}",
+                    notation,
+                    line
+                );
+                assert_eq!(
+                    network.promotion != Piece::None,
+                    mv.get_promoted_type().is_some()
+                );
+                let back = network_to_internal_move(&network, &moves);
+                assert_eq!(
+                    back.map(|back| back.to_algebraic_notation()),
+                    Some(notation)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn en_passant_lands_on_the_empty_square() {
+        let moves = board_after(&EN_PASSANT_LINE).get_legal_moves();
+        let (e5, d6) = ((3, 4), (2, 3));
+        let mv = network_to_internal_move(&network_move(e5, d6, Piece::None), &moves)
+            .expect("en passant is legal");
+        assert_eq!(parse_move(&mv.to_algebraic_notation()), Ok((e5, d6)));
+    }
+
+    #[test]
+    fn promotions_keep_their_piece_whatever_its_color() {
+        let moves = board_after(&PROMOTION_LINE).get_legal_moves();
+        let (b7, b8) = ((1, 1), (0, 1));
+        for (promotion, expected) in [
+            (Piece::WhiteQueen, Some(PieceType::Queen)),
+            (Piece::WhiteRook, Some(PieceType::Rook)),
+            (Piece::WhiteBishop, Some(PieceType::Bishop)),
+            (Piece::WhiteKnight, Some(PieceType::Knight)),
+            (Piece::BlackKnight, Some(PieceType::Knight)),
+            // Leaving it out or naming another piece doesn't match any move
+            (Piece::None, None),
+            (Piece::WhiteKing, None),
+        ] {
+            let found = network_to_internal_move(&network_move(b7, b8, promotion), &moves)
+                .and_then(|mv| mv.get_promoted_type());
+            assert!(found == expected, "{:?}", promotion);
+        }
+    }
+
+    #[test]
+    fn boards_survive_the_round_trip() {
+        let mut lines: Vec<&[&str]> = vec![&[]];
+        lines.extend(COORDINATE_LINES);
+        for line in lines {
+            let squares = BoardRepr::new(&board_after(line)).squares;
+            assert!(network_to_internal_board(&internal_to_network_board(&squares)) == squares);
+        }
+
+        // Every piece on a square of its own, starting at a1 so a missing row flip shows
+        let pieces = [
+            Piece::WhitePawn,
+            Piece::WhiteKnight,
+            Piece::WhiteBishop,
+            Piece::WhiteRook,
+            Piece::WhiteQueen,
+            Piece::WhiteKing,
+            Piece::BlackPawn,
+            Piece::BlackKnight,
+            Piece::BlackBishop,
+            Piece::BlackRook,
+            Piece::BlackQueen,
+            Piece::BlackKing,
+        ];
+        let mut network = [[Piece::None; 8]; 8];
+        for (index, piece) in pieces.iter().enumerate() {
+            network[index / 8][index % 8] = *piece;
+        }
+        let squares = network_to_internal_board(&network);
+        assert!(squares[7][0] == Square::Pawn(Color::White));
+        assert!(squares[6][3] == Square::King(Color::Black));
+        assert!(internal_to_network_board(&squares) == network);
+    }
+
+    #[test]
+    fn features_negotiate_to_the_common_ones() {
+        // A server that only knows promotion and something of its own
+        let mut handshake = internal_to_server_handshake(&Session::new(None).unwrap());
+        handshake.features = vec![Features::Promotion, Features::Other("Chess960".to_owned())];
+
+        let features = negotiated_features(&handshake);
+        assert!(features == [Features::Promotion]);
+        assert!(
+            ExcludedMoves::for_features(&features)
+                == ExcludedMoves {
+                    castling: true,
+                    en_passant: true,
+                    underpromotion: false,
+                }
+        );
+    }
+
+    #[test]
+    fn only_moves_the_server_lists_are_offered() {
+        let mut session = Session::new(None).unwrap();
+        let (e2, e3, e4, e5) = ((6, 4), (5, 4), (4, 4), (3, 4));
+        // A server that only allows e2e4, and lists a move that isn't legal at all
+        let listed = [
+            network_move(e2, e4, Piece::None),
+            network_move(e2, e5, Piece::None),
+        ];
+        let allowed = network_to_internal_moves(&listed, &session.board_repr.moves);
+        assert_eq!(allowed.len(), 1);
+        session.board_repr.offer_only(&allowed);
+
+        session.click(e2.0, e2.1);
+        assert!(session.click(e3.0, e3.1).is_none());
+        session.click(e2.0, e2.1);
+        let picked = session
+            .click(e4.0, e4.1)
+            .map(|mv| mv.to_algebraic_notation());
+        assert_eq!(picked.as_deref(), Some("e2e4"));
+    }
+
+    #[test]
+    fn the_starting_position_is_ongoing() {
+        let session = Session::new(None).unwrap();
+        assert!(matches!(
+            compute_joever(&session.game_state),
+            Joever::Ongoing
+        ));
+    }
+
+    #[test]
+    fn every_draw_is_reported_as_a_draw() {
+        for state in [
+            GameState::Stalemate,
+            GameState::Draw,
+            GameState::Repetition,
+            GameState::FiftyMoves,
+            GameState::InsufficientMaterial,
+        ] {
+            assert!(matches!(compute_joever(&state), Joever::Draw));
+        }
+        assert!(matches!(
+            compute_joever(&GameState::Ongoing),
+            Joever::Ongoing
+        ));
+    }
+
+    #[test]
+    fn wins_name_the_winner() {
+        assert!(matches!(
+            compute_joever(&GameState::Checkmate(Color::Black)),
+            Joever::White
+        ));
+        assert!(matches!(
+            compute_joever(&GameState::Resigned(Color::White)),
+            Joever::Black
+        ));
+        assert!(matches!(
+            compute_joever(&GameState::TimeForfeit(Color::Black)),
+            Joever::White
+        ));
+    }
+
+    /// Judge a handshake sent again to a client that plays Black
+    fn judge_on_client(
+        session: &Session,
+        handshake: ServerToClientHandshake,
+        strict: bool,
+    ) -> Rehandshake {
+        let features = negotiated_features(&internal_to_server_handshake(session));
+        session.judge_rehandshake(
+            &Handshake::ServerToClient(handshake),
+            false,
+            Color::White,
+            &features,
+            strict,
+        )
+    }
+
+    #[test]
+    fn a_handshake_sent_again_that_agrees_is_a_duplicate() {
+        let mut session = Session::new(None).unwrap();
+        play(&mut session, "e2e4");
+        play(&mut session, "e7e5");
+        let handshake = internal_to_server_handshake(&session);
+        assert_eq!(
+            judge_on_client(&session, handshake, false),
+            Rehandshake::Duplicate
+        );
+
+        // The client only asks the server to keep its color
+        let again = Handshake::ClientToServer(ClientToServerHandshake {
+            server_color: chess_network_protocol::Color::White,
+        });
+        assert_eq!(
+            session.judge_rehandshake(&again, true, Color::White, &supported_features(), false),
+            Rehandshake::Duplicate
+        );
+    }
+
+    #[test]
+    fn a_handshake_sent_again_that_disagrees_is_a_desync() {
+        let mut session = Session::new(None).unwrap();
+        play(&mut session, "e2e4");
+        // The starting board after a move was played
+        let stale = internal_to_server_handshake(&Session::new(None).unwrap());
+        assert_eq!(judge_on_client(&session, stale, false), Rehandshake::Desync);
+        // The right board but features other than the ones agreed on
+        let mut features = internal_to_server_handshake(&session);
+        features.features = vec![Features::Promotion];
+        assert_eq!(
+            judge_on_client(&session, features, false),
+            Rehandshake::Desync
+        );
+
+        // A client that wants the server to switch colors mid-game
+        let switch = Handshake::ClientToServer(ClientToServerHandshake {
+            server_color: chess_network_protocol::Color::Black,
+        });
+        assert_eq!(
+            session.judge_rehandshake(&switch, true, Color::White, &supported_features(), false),
+            Rehandshake::Desync
+        );
+    }
+
+    #[test]
+    fn strict_handshakes_take_no_handshake_at_all() {
+        let session = Session::new(None).unwrap();
+        let handshake = internal_to_server_handshake(&session);
+        assert_eq!(
+            judge_on_client(&session, handshake, true),
+            Rehandshake::Violation
+        );
+
+        // Our own kind of handshake never comes from the peer, strict or not
+        let own = Handshake::ClientToServer(ClientToServerHandshake {
+            server_color: chess_network_protocol::Color::White,
+        });
+        assert_eq!(
+            session.judge_rehandshake(&own, false, Color::White, &supported_features(), false),
+            Rehandshake::Violation
+        );
+    }
+
+    #[test]
+    fn handshakes_are_picked_out_between_states_in_one_burst() {
+        let mut session = Session::new(None).unwrap();
+        let mut burst = Vec::new();
+        for mv in ["e2e4", "e7e5"] {
+            play(&mut session, mv);
+            let mv = *session.played_moves.last().unwrap();
+            serde_json::to_writer(&mut burst, &board_state(&session, &mv).unwrap()).unwrap();
+            serde_json::to_writer(&mut burst, &internal_to_server_handshake(&session)).unwrap();
+        }
+        let kinds: Vec<&str> = serde_json::Deserializer::from_slice(&burst)
+            .into_iter::<Value>()
+            .map(|value| {
+                decode_frame::<ServerToClient>(value.unwrap())
+                    .unwrap()
+                    .kind()
+            })
+            .collect();
+        assert_eq!(kinds, ["state", "handshake", "state", "handshake"]);
+    }
+
+    #[test]
+    fn a_connection_test_is_never_read_as_a_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut guest = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let host = listener.accept().unwrap().0;
+        guest
+            .write_all(&[connectivity::PING_MAGIC, 4, b'{', b'}', b'{', b'}'])
+            .unwrap();
+        assert!(connectivity::is_ping(&host));
+        let read = read_frame::<ClientToServer>(&host);
+        assert!(read.is_err_and(|err| err.kind() != ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn malformed_messages_are_not_taken_for_handshakes() {
+        // Cut off halfway, it never gets as far as being decoded
+        let cut = br#"{"board": [["#;
+        let read = serde_json::Deserializer::from_slice(cut)
+            .into_iter::<Value>()
+            .next();
+        assert!(read.is_some_and(|read| read.is_err()));
+
+        // JSON that is none of the messages fails with why it isn't the one expected
+        let value: Value = serde_json::from_str(r#"{"Move": 3}"#).unwrap();
+        let expected = serde_json::from_value::<ClientToServer>(value.clone())
+            .err()
+            .map(|err| err.to_string());
+        assert!(expected.is_some());
+        assert_eq!(
+            decode_frame::<ClientToServer>(value)
+                .err()
+                .map(|err| err.to_string()),
+            expected
+        );
+    }
+
+    /// A spectator that takes its time over every message and keeps them
+    struct SlowSpectator {
+        delay: Duration,
+        messages: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl Write for SlowSpectator {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            thread::sleep(self.delay);
+            self.messages.lock().unwrap().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    /// A position sent after `ply` plies, told apart from the others by its move
+    fn state_after(ply: usize) -> ServerToClient {
+        ServerToClient::State {
+            board: [[Piece::None; 8]; 8],
+            moves: Vec::new(),
+            joever: Joever::Ongoing,
+            move_made: network_move((ply / 8, ply % 8), (7, 7), Piece::None),
+        }
+    }
+
+    #[test]
+    fn a_slow_spectator_gets_the_plies_in_order_without_holding_up_the_game() {
+        let delay = Duration::from_millis(50);
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let spectators = Arc::new(Mutex::new(Spectators {
+            streams: vec![Box::new(SlowSpectator {
+                delay,
+                messages: Arc::clone(&messages),
+            })],
+            handshake: internal_to_server_handshake(&Session::new(None).unwrap()),
+            ply: 0,
+        }));
+        let fanout = spawn_fanout(Arc::clone(&spectators));
+        let resigned = || ServerToClient::Resigned {
+            board: [[Piece::None; 8]; 8],
+            joever: Joever::White,
         };
-        serde_json::to_writer(&self.stream, &state).unwrap();
+
+        let started = Instant::now();
+        for ply in 1..=5 {
+            fanout.send((ply, state_after(ply))).unwrap();
+        }
+        // Sent again once the game moved on, it would take the spectator back
+        fanout.send((3, state_after(3))).unwrap();
+        // Resigning plays no move, it comes after the last one
+        fanout.send((5, resigned())).unwrap();
+        assert!(started.elapsed() < delay);
+        drop(fanout);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while messages.lock().unwrap().len() < 6 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut expected: Vec<Vec<u8>> = (1..=5)
+            .map(|ply| serde_json::to_vec(&state_after(ply)).unwrap())
+            .collect();
+        expected.push(serde_json::to_vec(&resigned()).unwrap());
+        assert!(*messages.lock().unwrap() == expected);
+        let spectators = spectators.lock().unwrap();
+        assert_eq!(spectators.ply, 5);
+        assert!(spectators.handshake.joever == Joever::White);
     }
 
-    pub(crate) fn send_move(&self, client_move: &Move) {
-        let mv = chess_network_protocol::ClientToServer::Move {
-            0: internal_to_network_move(client_move),
+    #[test]
+    fn the_end_of_the_game_reaches_spectators_that_write_slowly_without_locking_them_out() {
+        let delay = Duration::from_millis(200);
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let spectators = Arc::new(Mutex::new(Spectators {
+            streams: vec![Box::new(SlowSpectator {
+                delay,
+                messages: Arc::clone(&messages),
+            })],
+            handshake: internal_to_server_handshake(&Session::new(None).unwrap()),
+            ply: 0,
+        }));
+        let out_of_time = ServerToClient::Error {
+            board: [[Piece::None; 8]; 8],
+            moves: Vec::new(),
+            joever: Joever::Black,
+            message: OUT_OF_TIME.to_owned(),
+        };
+        let writing = thread::spawn({
+            let (spectators, out_of_time) = (Arc::clone(&spectators), out_of_time.clone());
+            move || broadcast(&spectators, 3, &out_of_time)
+        });
+        thread::sleep(delay / 4);
+        // Someone joining now isn't held up by the write
+        let started = Instant::now();
+        let joever = spectators.lock().unwrap().handshake.joever;
+        assert!(started.elapsed() < delay / 2);
+        assert!(joever == Joever::Black);
+        writing.join().unwrap();
+
+        let agreed = ServerToClient::Draw {
+            board: [[Piece::None; 8]; 8],
+            moves: Vec::new(),
         };
-        serde_json::to_writer(&self.stream, &mv).unwrap();
+        broadcast(&spectators, 3, &agreed);
+        let expected = [
+            serde_json::to_vec(&out_of_time).unwrap(),
+            serde_json::to_vec(&agreed).unwrap(),
+        ];
+        assert!(*messages.lock().unwrap() == expected);
+        let spectators = spectators.lock().unwrap();
+        assert_eq!(spectators.streams.len(), 1);
+        assert!(spectators.handshake.joever == Joever::Draw);
     }
 
-    pub(crate) fn get_board_state(&self) -> Option<chess_network_protocol::ServerToClient> {
-        if let Ok(state) = serde_json::from_reader(&self.stream) {
-            return Some(state);
+    /// The client's reply races the server's own move, sent once before the move reaches it
+    /// and again right after, while a spectator reads slowly
+    #[test]
+    fn a_reply_racing_the_servers_move_is_played_once_after_it() {
+        fn next(network: &mut Network) -> NetworkEvent {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                match network.poll() {
+                    Some(NetworkEvent::Disconnected) => panic!("disconnected"),
+                    Some(event) => return event,
+                    None if Instant::now() > deadline => panic!("nothing arrived"),
+                    None => thread::sleep(Duration::from_millis(5)),
+                }
+            }
+        }
+        let config = |is_server, address: String| NetworkConfig {
+            is_server,
+            address,
+            strict_port: false,
+            connect_attempts: 3,
+            server_color: Some(chess_network_protocol::Color::White),
+            spectate: false,
+            hosting: None,
+        };
+        let server_plays_white = || ClientToServerHandshake {
+            server_color: chess_network_protocol::Color::White,
+        };
+        let reply = || ClientToServer::Move(network_move((1, 4), (3, 4), Piece::None));
+
+        let mut session = Session::new(None).unwrap();
+        let mut strikes = Strikes::new(DEFAULT_MAX_STRIKES, DEFAULT_MAX_STRIKES);
+        let mut server = start(
+            &config(true, "127.0.0.1:0".to_owned()),
+            Handshake::ServerToClient(internal_to_server_handshake(&session)),
+        );
+        let NetworkEvent::Listening(listening) = next(&mut server) else {
+            panic!("not listening");
+        };
+        let mut client = start(
+            &config(false, listening.to_string()),
+            Handshake::ClientToServer(server_plays_white()),
+        );
+        assert!(matches!(next(&mut server), NetworkEvent::Connected(..)));
+        assert!(matches!(next(&mut client), NetworkEvent::Connected(..)));
+
+        let spectator = TcpStream::connect(listening).unwrap();
+        write_message(&spectator, &server_plays_white()).unwrap();
+        let _: ServerToClientHandshake = read_message(&spectator).unwrap();
+        let watching = thread::spawn(move || {
+            (0..3)
+                .map(|_| {
+                    thread::sleep(Duration::from_millis(50));
+                    read_message::<ServerToClient>(&spectator).unwrap()
+                })
+                .collect::<Vec<_>>()
+        });
+
+        client.send(Outgoing::ToServer(reply())).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        play(&mut session, "e2e4");
+        server
+            .send_board_state(&session, session.played_moves.last().unwrap())
+            .unwrap();
+        assert!(matches!(
+            next(&mut client),
+            NetworkEvent::FromServer(ServerToClient::State { .. })
+        ));
+        client.send(Outgoing::ToServer(reply())).unwrap();
+
+        // Both are judged once the server's move is played, only the first is in turn
+        let mut accepted = 0;
+        for _ in 0..2 {
+            let NetworkEvent::FromClient(ClientToServer::Move(client_move)) = next(&mut server)
+            else {
+                panic!("no move from the client");
+            };
+            match session.judge_client_move(&client_move, Color::White, &mut strikes) {
+                Ok(mv) => {
+                    accepted += 1;
+                    session.apply_move(mv);
+                    server.send_board_state(&session, &mv).unwrap();
+                }
+                Err((message, _)) => {
+                    assert_eq!(message, "It is not your turn");
+                    server.send_error(&session, message);
+                }
+            }
+        }
+        assert_eq!(accepted, 1);
+        assert_eq!(session.history.len(), 2);
+        play(&mut session, "g1f3");
+        server
+            .send_board_state(&session, session.played_moves.last().unwrap())
+            .unwrap();
+
+        // The rejected reply's error isn't passed on, every position is
+        let line = ["e2e4", "e7e5", "g1f3"];
+        for (ply, message) in watching.join().unwrap().iter().enumerate() {
+            let ServerToClient::State { board, .. } = message else {
+                panic!("the spectator was sent something other than a position");
+            };
+            let squares = BoardRepr::new(&board_after(&line[..=ply])).squares;
+            assert!(*board == internal_to_network_board(&squares));
         }
-        None
     }
 }
@@ -0,0 +1,242 @@
+use crate::{pgn, BoardRepr, Move};
+
+// The piece letters of English SAN, king, queen, rook, bishop and knight
+const ENGLISH_LETTERS: [char; 5] = ['K', 'Q', 'R', 'B', 'N'];
+// The glyphs of the white pieces, figurine notation uses them for both sides
+const FIGURINES: [char; 5] = ['♔', '♕', '♖', '♗', '♘'];
+
+/// A language with its own piece letters
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Language {
+    Swedish,
+    German,
+    French,
+    Spanish,
+    Italian,
+    Dutch,
+}
+
+impl Language {
+    /// A language by its code, like "sv"
+    pub fn parse(code: &str) -> Option<Self> {
+        match code {
+            "sv" => Some(Language::Swedish),
+            "de" => Some(Language::German),
+            "fr" => Some(Language::French),
+            "es" => Some(Language::Spanish),
+            "it" => Some(Language::Italian),
+            "nl" => Some(Language::Dutch),
+            _ => None,
+        }
+    }
+
+    /// The language of a locale like "sv_SE.UTF-8", None for English or one without a table
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        let code = locale.split(['_', '.', '@', '-']).next()?;
+        Self::parse(&code.to_ascii_lowercase())
+    }
+
+    /// King, queen, rook, bishop and knight
+    fn letters(self) -> [char; 5] {
+        match self {
+            Language::Swedish | Language::German => ['K', 'D', 'T', 'L', 'S'],
+            Language::French => ['R', 'D', 'T', 'F', 'C'],
+            Language::Spanish | Language::Italian => ['R', 'D', 'T', 'A', 'C'],
+            Language::Dutch => ['K', 'D', 'T', 'L', 'P'],
+        }
+    }
+
+    #[inline]
+    fn code(self) -> &'static str {
+        match self {
+            Language::Swedish => "sv",
+            Language::German => "de",
+            Language::French => "fr",
+            Language::Spanish => "es",
+            Language::Italian => "it",
+            Language::Dutch => "nl",
+        }
+    }
+}
+
+/// How moves are written where they are shown. Saved PGN is always English.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Notation {
+    English,
+    Localized(Language),
+    // ♘f3, needs a font with the chess glyphs
+    Figurine,
+}
+
+impl Notation {
+    /// "english", "figurine" or the code of a language with its own piece letters
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "english" => Some(Notation::English),
+            "figurine" => Some(Notation::Figurine),
+            code => Language::parse(code).map(Notation::Localized),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Notation::English => "english",
+            Notation::Localized(language) => language.code(),
+            Notation::Figurine => "figurine",
+        }
+    }
+
+    /// What is shown when the font can't draw the figurines: the letters of `language`,
+    /// English without one
+    pub fn usable(self, figurines_render: bool, language: Option<Language>) -> Self {
+        match self {
+            Notation::Figurine if !figurines_render => {
+                language.map_or(Notation::English, Notation::Localized)
+            }
+            notation => notation,
+        }
+    }
+
+    /// The notation after this one when switching: English, then `language`'s letters if
+    /// there are any, then figurines if they render, and None for coordinates after the last
+    pub fn next(
+        notation: Option<Self>,
+        language: Option<Language>,
+        figurines_render: bool,
+    ) -> Option<Self> {
+        let Some(notation) = notation else {
+            return Some(Notation::English);
+        };
+        let order = [
+            Some(Notation::English),
+            language.map(Notation::Localized),
+            figurines_render.then_some(Notation::Figurine),
+        ];
+        let at = order
+            .iter()
+            .position(|listed| *listed == Some(notation))
+            .map_or(order.len(), |at| at + 1);
+        order[at..].iter().flatten().next().copied()
+    }
+}
+
+/// A move in English SAN, as `pgn::san` writes it, rewritten in `notation`. Castling and
+/// squares stay the same, only the piece letters change.
+pub fn restyle(san: &str, notation: Notation) -> String {
+    let pieces = match notation {
+        Notation::English => return san.to_owned(),
+        Notation::Localized(language) => language.letters(),
+        Notation::Figurine => FIGURINES,
+    };
+    let mut restyled = String::with_capacity(san.len());
+    let mut previous = None;
+    for c in san.chars() {
+        // Piece letters are the first of a piece move and the one after = in a promotion
+        let piece = match previous {
+            None | Some('=') => ENGLISH_LETTERS.iter().position(|letter| *letter == c),
+            Some(_) => None,
+        };
+        restyled.push(piece.map_or(c, |piece| pieces[piece]));
+        previous = Some(c);
+    }
+    restyled
+}
+
+/// A move in the position `repr` shows written in `notation`, without the check mark.
+/// Every place a move is shown goes through this, so they all agree.
+#[inline]
+pub fn format_san(repr: &BoardRepr, mv: &Move, notation: Notation) -> String {
+    restyle(&pgn::san(repr, mv), notation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_from_fen;
+
+    fn in_all_styles(fen: &str, wanted: &str) -> Vec<String> {
+        let repr = BoardRepr::new(&board_from_fen(fen).unwrap());
+        let mv = repr
+            .moves
+            .iter()
+            .find(|mv| pgn::san(&repr, mv) == wanted)
+            .copied()
+            .unwrap();
+        [
+            Notation::English,
+            Notation::Localized(Language::Swedish),
+            Notation::Figurine,
+        ]
+        .into_iter()
+        .map(|notation| format_san(&repr, &mv, notation))
+        .collect()
+    }
+
+    #[test]
+    fn disambiguation_is_kept_in_every_style() {
+        // Knights on b1 and f3 can both go to d2
+        assert_eq!(
+            in_all_styles("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", "Nbd2"),
+            ["Nbd2", "Sbd2", "♘bd2"]
+        );
+        // Rooks on a1 and a5 share the file
+        assert_eq!(
+            in_all_styles("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "R1a3"),
+            ["R1a3", "T1a3", "♖1a3"]
+        );
+    }
+
+    #[test]
+    fn only_piece_letters_change() {
+        assert_eq!(restyle("exd8=Q", Notation::Figurine), "exd8=♕");
+        assert_eq!(
+            restyle("bxc8=N", Notation::Localized(Language::French)),
+            "bxc8=C"
+        );
+        // The b-pawn and the queenside castle aren't pieces
+        assert_eq!(restyle("b4", Notation::Localized(Language::German)), "b4");
+        assert_eq!(restyle("O-O-O", Notation::Figurine), "O-O-O");
+        assert_eq!(
+            restyle("Bxe5#", Notation::Localized(Language::Spanish)),
+            "Axe5#"
+        );
+        assert_eq!(restyle("Qh5+", Notation::English), "Qh5+");
+    }
+
+    #[test]
+    fn figurines_fall_back_to_letters_without_the_glyphs() {
+        let swedish = Some(Language::Swedish);
+        assert_eq!(
+            Notation::Figurine.usable(false, swedish),
+            Notation::Localized(Language::Swedish)
+        );
+        assert_eq!(Notation::Figurine.usable(false, None), Notation::English);
+        assert_eq!(Notation::Figurine.usable(true, None), Notation::Figurine);
+        // Switching skips the figurines too
+        assert_eq!(
+            Notation::next(Some(Notation::Localized(Language::Swedish)), swedish, false),
+            None
+        );
+        assert_eq!(
+            Notation::next(Some(Notation::English), None, true),
+            Some(Notation::Figurine)
+        );
+        assert_eq!(Notation::next(None, None, false), Some(Notation::English));
+    }
+
+    #[test]
+    fn languages_come_from_codes_and_locales() {
+        assert_eq!(
+            Language::from_locale("sv_SE.UTF-8"),
+            Some(Language::Swedish)
+        );
+        assert_eq!(Language::from_locale("nl"), Some(Language::Dutch));
+        assert_eq!(Language::from_locale("en_US.UTF-8"), None);
+        assert_eq!(Language::from_locale("C"), None);
+        assert_eq!(
+            Notation::parse("fr"),
+            Some(Notation::Localized(Language::French))
+        );
+        assert_eq!(Notation::parse("klingon"), None);
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{SendError, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+// Messages kept in the timeline, the oldest are dropped past this so a long session can't
+// grow it without end
+const TIMELINE_CAPACITY: usize = 16_384;
+
+/// Which way a message went
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Whose side broke the order, so it is clear who to blame
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Culprit {
+    // Our queue got to the socket with a message queued before the last one written
+    Ours,
+    // The numbers the peer sent skipped some or went back
+    Peer,
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Violation {
+    // A number no higher than the last one
+    Inversion { last: u64, seq: u64 },
+    // The numbers from `expected` up to this one never came
+    Gap { expected: u64, seq: u64 },
+}
+
+/// A message in the timeline of a connection
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Event {
+    pub connection: usize,
+    pub direction: Direction,
+    // None for everything from a peer that doesn't number its messages
+    pub seq: Option<u64>,
+    pub kind: &'static str,
+    // Since the audit was enabled
+    pub nanos: u128,
+}
+
+/// Numbers every queued message per connection and records when it was written, to show the
+/// messages leave in the order they were queued. Off unless `enable` was called, then nothing
+/// is numbered and the network only checks that the audit is missing.
+pub struct Audit {
+    started: Instant,
+    // Our own violations panic instead of being logged
+    strict: bool,
+    dump: Option<PathBuf>,
+    connections: AtomicUsize,
+    timeline: Mutex<VecDeque<Event>>,
+    // Messages dropped from the front of the timeline
+    dropped: AtomicUsize,
+    violations: Mutex<Vec<(usize, Culprit, Violation)>>,
+}
+
+static AUDIT: OnceLock<Audit> = OnceLock::new();
+
+/// The audit once `enable` was called
+#[inline]
+pub fn global() -> Option<&'static Audit> {
+    AUDIT.get()
+}
+
+/// Start numbering the messages of every connection made after this
+pub fn enable(strict: bool, dump: Option<PathBuf>) -> &'static Audit {
+    AUDIT.get_or_init(|| Audit::new(strict, dump))
+}
+
+impl Audit {
+    pub fn new(strict: bool, dump: Option<PathBuf>) -> Self {
+        Self {
+            started: Instant::now(),
+            strict,
+            dump,
+            connections: AtomicUsize::new(0),
+            timeline: Mutex::new(VecDeque::with_capacity(TIMELINE_CAPACITY)),
+            dropped: AtomicUsize::new(0),
+            violations: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The bookkeeping for a new connection, numbered from 0 both ways
+    pub fn connection(&'static self) -> Arc<Connection> {
+        Arc::new(Connection {
+            audit: self,
+            id: self.connections.fetch_add(1, Ordering::Relaxed),
+            next: Mutex::new(0),
+            written: Mutex::new(None),
+            received: Mutex::new(None),
+        })
+    }
+
+    fn record(&self, event: Event) {
+        let mut timeline = self.timeline.lock().unwrap();
+        if timeline.len() == TIMELINE_CAPACITY {
+            timeline.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        timeline.push_back(event);
+    }
+
+    fn violate(&self, connection: usize, culprit: Culprit, violation: Violation) {
+        let description = format!(
+            "Ordering error on connection {} by {}: {}",
+            connection,
+            culprit_name(culprit),
+            describe(violation)
+        );
+        assert!(!self.strict || culprit == Culprit::Peer, "{}", description);
+        println!("{}", description);
+        self.violations
+            .lock()
+            .unwrap()
+            .push((connection, culprit, violation));
+    }
+
+    /// The last `TIMELINE_CAPACITY` messages, oldest first
+    pub fn timeline(&self) -> Vec<Event> {
+        self.timeline.lock().unwrap().iter().copied().collect()
+    }
+
+    pub fn violations(&self) -> Vec<(usize, Culprit, Violation)> {
+        self.violations.lock().unwrap().clone()
+    }
+
+    /// The timeline one message a line, then the violations, for the other party to line up
+    /// against theirs
+    pub fn render(&self) -> String {
+        let mut out = String::from("# nanoseconds connection direction sequence kind\n");
+        match self.dropped.load(Ordering::Relaxed) {
+            0 => {}
+            dropped => {
+                let _ = writeln!(out, "# {} earlier messages dropped", dropped);
+            }
+        }
+        for event in self.timeline() {
+            let _ = writeln!(
+                out,
+                "{} {} {} {} {}",
+                event.nanos,
+                event.connection,
+                match event.direction {
+                    Direction::Sent => "sent",
+                    Direction::Received => "received",
+                },
+                event.seq.map_or("-".to_owned(), |seq| seq.to_string()),
+                event.kind
+            );
+        }
+        for (connection, culprit, violation) in self.violations() {
+            let _ = writeln!(
+                out,
+                "# violation on connection {} by {}: {}",
+                connection,
+                culprit_name(culprit),
+                describe(violation)
+            );
+        }
+        out
+    }
+
+    /// Write the timeline to the file given with --dump-ordering, if any
+    pub fn write_dump(&self) -> Result<(), Error> {
+        match &self.dump {
+            Some(path) => fs::write(path, self.render()),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn now(&self) -> u128 {
+        self.started.elapsed().as_nanos()
+    }
+}
+
+fn culprit_name(culprit: Culprit) -> &'static str {
+    match culprit {
+        Culprit::Ours => "us",
+        Culprit::Peer => "the peer",
+    }
+}
+
+fn describe(violation: Violation) -> String {
+    match violation {
+        Violation::Inversion { last, seq } => format!("{} came after {}", seq, last),
+        Violation::Gap { expected, seq } => format!("{} came while {} was next", seq, expected),
+    }
+}
+
+/// The numbers of one connection, shared by whoever queues messages, the thread writing them
+/// and the one reading the peer's
+pub struct Connection {
+    audit: &'static Audit,
+    id: usize,
+    // The number the next queued message gets. Held while it is queued, so the numbers go
+    // into the queue in order whichever thread queues them.
+    next: Mutex<u64>,
+    // The last numbers written to the socket and received from the peer
+    written: Mutex<Option<u64>>,
+    received: Mutex<Option<u64>>,
+}
+
+impl Connection {
+    /// Queue the message with the next number
+    pub fn enqueue<T>(
+        &self,
+        sender: &Sender<(Option<u64>, T)>,
+        message: T,
+    ) -> Result<(), SendError<(Option<u64>, T)>> {
+        let mut next = self.next.lock().unwrap();
+        sender.send((Some(*next), message))?;
+        *next += 1;
+        Ok(())
+    }
+
+    /// Called right before the message numbered `seq` is written to the socket, which is
+    /// where the numbers have to strictly increase
+    pub fn written(&self, seq: u64, kind: &'static str) {
+        let nanos = self.audit.now();
+        let mut written = self.written.lock().unwrap();
+        match *written {
+            Some(last) if seq <= last => {
+                self.audit
+                    .violate(self.id, Culprit::Ours, Violation::Inversion { last, seq })
+            }
+            _ => *written = Some(seq),
+        }
+        self.audit.record(Event {
+            connection: self.id,
+            direction: Direction::Sent,
+            seq: Some(seq),
+            kind,
+            nanos,
+        });
+    }
+
+    /// A message read from the peer, with its number if the peer numbers its messages,
+    /// which has to be the one after the last
+    pub fn received(&self, seq: Option<u64>, kind: &'static str) {
+        let nanos = self.audit.now();
+        if let Some(seq) = seq {
+            let mut received = self.received.lock().unwrap();
+            let expected = received.map_or(0, |last| last + 1);
+            match *received {
+                Some(last) if seq <= last => {
+                    self.audit
+                        .violate(self.id, Culprit::Peer, Violation::Inversion { last, seq })
+                }
+                _ => {
+                    if seq != expected {
+                        self.audit.violate(
+                            self.id,
+                            Culprit::Peer,
+                            Violation::Gap { expected, seq },
+                        );
+                    }
+                    *received = Some(seq);
+                }
+            }
+        }
+        self.audit.record(Event {
+            connection: self.id,
+            direction: Direction::Received,
+            seq,
+            kind,
+            nanos,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_network_protocol::ClientToServer;
+    use std::io::{BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn audit(strict: bool) -> &'static Audit {
+        Box::leak(Box::new(Audit::new(strict, None)))
+    }
+
+    #[test]
+    fn the_queue_never_reorders_between_producers() {
+        let audit = audit(true);
+        let connection = audit.connection();
+        let (sender, queue) = mpsc::channel();
+        let producers: Vec<_> = (0..8)
+            .map(|producer| {
+                let (connection, sender) = (Arc::clone(&connection), sender.clone());
+                thread::spawn(move || {
+                    for message in 0..1000 {
+                        connection.enqueue(&sender, (producer, message)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        let mut last_of = [None; 8];
+        // The writer end, checked by the audit at the point a socket would be written
+        for (seq, (producer, message)) in queue {
+            connection.written(seq.unwrap(), "move");
+            // Every producer's own messages stay in its order too
+            assert!(last_of[producer].is_none_or(|last| last < message));
+            last_of[producer] = Some(message);
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let sent: Vec<_> = audit.timeline().iter().map(|event| event.seq).collect();
+        assert_eq!(sent, (0..8000).map(Some).collect::<Vec<_>>());
+        assert!(audit.violations().is_empty());
+    }
+
+    #[test]
+    fn violations_are_blamed_on_the_side_that_made_them() {
+        let audit = audit(false);
+        let connection = audit.connection();
+        connection.written(0, "state");
+        connection.written(2, "state");
+        connection.written(1, "state");
+        connection.received(Some(0), "move");
+        connection.received(Some(3), "move");
+        connection.received(Some(3), "move");
+        // A peer that doesn't number its messages can't be blamed for anything
+        connection.received(None, "move");
+        assert_eq!(
+            audit.violations(),
+            [
+                (0, Culprit::Ours, Violation::Inversion { last: 2, seq: 1 }),
+                (
+                    0,
+                    Culprit::Peer,
+                    Violation::Gap {
+                        expected: 1,
+                        seq: 3
+                    }
+                ),
+                (0, Culprit::Peer, Violation::Inversion { last: 3, seq: 3 }),
+            ]
+        );
+        let rendered = audit.render();
+        assert!(rendered.contains(" 0 sent 1 state\n"));
+        assert!(rendered.contains(" 0 received - move\n"));
+        assert!(rendered.contains("# violation on connection 0 by us: 1 came after 2\n"));
+    }
+
+    #[test]
+    fn the_timeline_keeps_only_the_latest_messages() {
+        let audit = audit(true);
+        let connection = audit.connection();
+        for seq in 0..TIMELINE_CAPACITY as u64 + 10 {
+            connection.written(seq, "move");
+        }
+        let timeline = audit.timeline();
+        assert_eq!(timeline.len(), TIMELINE_CAPACITY);
+        assert_eq!(timeline[0].seq, Some(10));
+        assert!(audit.render().contains("# 10 earlier messages dropped\n"));
+        assert!(audit.violations().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "by us")]
+    fn our_own_inversion_is_a_hard_error_in_audit_mode() {
+        let connection = audit(true).connection();
+        connection.written(1, "state");
+        connection.written(0, "state");
+    }
+
+    #[test]
+    fn the_peer_is_only_reported_in_audit_mode() {
+        let audit = audit(true);
+        let connection = audit.connection();
+        connection.received(Some(1), "move");
+        assert_eq!(audit.violations().len(), 1);
+    }
+
+    #[test]
+    fn two_numbering_peers_see_no_gaps_or_inversions() {
+        let audit = audit(true);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let sending = audit.connection();
+        let receiving = audit.connection();
+        let (sender, queue) = mpsc::channel::<(Option<u64>, ClientToServer)>();
+
+        // Each peer writes a message with its number in front of it and reads the other's
+        let writer = thread::spawn({
+            let sending = Arc::clone(&sending);
+            move || {
+                let mut stream = TcpStream::connect(address).unwrap();
+                for (seq, message) in queue {
+                    let seq = seq.unwrap();
+                    sending.written(seq, "draw");
+                    serde_json::to_writer(&mut stream, &(seq, message)).unwrap();
+                }
+                stream.flush().unwrap();
+            }
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let producer = thread::spawn(move || {
+            for _ in 0..1000 {
+                sending.enqueue(&sender, ClientToServer::Draw).unwrap();
+            }
+        });
+        let mut count = 0;
+        for message in serde_json::Deserializer::from_reader(BufReader::new(stream))
+            .into_iter::<(u64, ClientToServer)>()
+        {
+            let (seq, _) = message.unwrap();
+            receiving.received(Some(seq), "draw");
+            count += 1;
+        }
+        producer.join().unwrap();
+        writer.join().unwrap();
+        assert_eq!(count, 1000);
+        assert!(audit.violations().is_empty());
+    }
+}
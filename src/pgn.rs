@@ -0,0 +1,862 @@
+use crate::bookmarks::{self, Bookmarks};
+use crate::glyphs::{self, Glyph, GlyphStyle};
+use crate::negotiation::{Negotiation, Negotiations};
+use crate::{
+    is_capture, is_castling, opposite_color, parse_move, BoardRepr, GameState, Move, Square,
+};
+use jonathan_hallstrom_chess::Color;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Export format lines are at most this long
+const LINE_WIDTH: usize = 80;
+
+/// Tags that differ between games, the rest of the Seven Tag Roster is filled in when writing
+pub struct PgnHeaders {
+    pub site: String,
+    pub white: String,
+    pub black: String,
+    pub result: &'static str,
+    // Starting position if it isn't the standard one
+    pub fen: Option<String>,
+    // `game_hash` of the game, written as the GameHash tag
+    pub hash: String,
+    // Put before the moves in braces
+    pub comment: Option<String>,
+    // How the game ended when the moves don't show it, see `termination`
+    pub termination: Option<&'static str>,
+    // Each is written after the move it was set at, like {[#] blunder}
+    pub bookmarks: Bookmarks,
+    // Written after the move they were made at, in the same comment as a bookmark there
+    pub negotiations: Negotiations,
+    // The glyph of every move, written the way `glyph_style` says
+    pub glyphs: Vec<Option<Glyph>>,
+    pub glyph_style: GlyphStyle,
+}
+
+/// Where the parts of a move's comment come from, in the order they are merged into it
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
+pub enum CommentSource {
+    // What the players negotiated, in the order it happened
+    Negotiation,
+    // Always last, its label runs to the end of the comment
+    Bookmark,
+}
+
+/// The one comment for everything at a move, without its braces: the parts by their source
+/// in the order of `CommentSource` and in the order given within a source, like
+/// "White offers a draw at 3:05. [#] blunder". None without any parts.
+pub fn merge_comments(mut parts: Vec<(CommentSource, String)>) -> Option<String> {
+    // Stable, so the parts of one source keep their order
+    parts.sort_by_key(|(source, _)| *source);
+    let merged = parts
+        .into_iter()
+        // A brace would end the comment early
+        .map(|(_, part)| part.replace(['{', '}'], ""))
+        .collect::<Vec<_>>()
+        .join(". ");
+    (!merged.is_empty()).then_some(merged)
+}
+
+/// The negotiations at `ply` and the bookmark of a comment `merge_comments` wrote, the
+/// bookmark as `bookmarks::parse_comment` gives it. Anything else in the comment is skipped.
+pub fn split_comment(comment: &str, ply: usize) -> (Vec<Negotiation>, Option<Option<String>>) {
+    let (others, bookmark) = match comment.find(bookmarks::GLYPH) {
+        Some(at) => (&comment[..at], bookmarks::parse_comment(&comment[at..])),
+        None => (comment, None),
+    };
+    let negotiations = others
+        .split(". ")
+        .filter_map(|sentence| Negotiation::parse(sentence, ply))
+        .collect();
+    (negotiations, bookmark)
+}
+
+// FNV-1a, simple enough to stay the same in every release
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A short name for the game that stays the same across releases: FNV-1a over the starting
+/// position and the moves, folded to 48 bits and written as 12 hex digits. The position is
+/// "startpos" for the standard one, otherwise its FEN with single spaces, and every move
+/// follows on its own line in lowercase coordinate notation like "e7e8q".
+pub fn game_hash(start_fen: Option<&str>, moves: &[String]) -> String {
+    let position = start_fen.map_or("startpos".to_owned(), |fen| {
+        fen.split_whitespace().collect::<Vec<_>>().join(" ")
+    });
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    feed(position.as_bytes());
+    for mv in moves {
+        feed(b"\n");
+        feed(mv.trim().to_ascii_lowercase().as_bytes());
+    }
+    format!("{:012x}", (hash ^ (hash >> 48)) & 0xffff_ffff_ffff)
+}
+
+#[inline]
+pub(crate) fn file_name(col: usize) -> char {
+    (b'a' + col as u8) as char
+}
+
+#[inline]
+pub(crate) fn rank_name(row: usize) -> char {
+    (b'8' - row as u8) as char
+}
+
+#[inline]
+pub fn square_name(row: usize, col: usize) -> String {
+    format!("{}{}", file_name(col), rank_name(row))
+}
+
+/// Standard algebraic notation for a move in the position `repr` shows, without the check mark
+pub fn san(repr: &BoardRepr, mv: &Move) -> String {
+    let notation = mv.to_algebraic_notation();
+    let Ok((from, to)) = parse_move(&notation) else {
+        return notation;
+    };
+    let piece = repr.squares[from.0][from.1];
+    let capture = is_capture(&repr.squares, from, to);
+
+    let letter = match piece {
+        _ if is_castling(&repr.squares, from, to) => {
+            return match to.1 > from.1 {
+                true => "O-O".to_owned(),
+                false => "O-O-O".to_owned(),
+            };
+        }
+        Square::Pawn(_) => {
+            let mut san = String::new();
+            if capture {
+                san.push(file_name(from.1));
+                san.push('x');
+            }
+            san.push_str(&square_name(to.0, to.1));
+            if let Some(promotion) = notation.chars().nth(4) {
+                san.push('=');
+                san.push(promotion.to_ascii_uppercase());
+            }
+            return san;
+        }
+        Square::King(_) => 'K',
+        Square::Queen(_) => 'Q',
+        Square::Rook(_) => 'R',
+        Square::Bishop(_) => 'B',
+        Square::Knight(_) => 'N',
+        Square::Empty => return notation,
+    };
+
+    // Other pieces of the same kind that could also go there
+    let rivals: Vec<(usize, usize)> = repr
+        .moves
+        .iter()
+        .filter_map(|other| parse_move(&other.to_algebraic_notation()).ok())
+        .filter(|&(other_from, other_to)| {
+            other_to == to
+                && other_from != from
+                && repr.squares[other_from.0][other_from.1] == piece
+        })
+        .map(|(other_from, _)| other_from)
+        .collect();
+
+    let mut san = letter.to_string();
+    if !rivals.is_empty() {
+        let file_unique = rivals.iter().all(|&(_, col)| col != from.1);
+        let rank_unique = rivals.iter().all(|&(row, _)| row != from.0);
+        match (file_unique, rank_unique) {
+            (true, _) => san.push(file_name(from.1)),
+            (false, true) => san.push(rank_name(from.0)),
+            (false, false) => san.push_str(&square_name(from.0, from.1)),
+        }
+    }
+    if capture {
+        san.push('x');
+    }
+    san.push_str(&square_name(to.0, to.1));
+    san
+}
+
+/// "#" for checkmate and "+" for check, looked at in the position after the move
+pub fn check_suffix(repr: &BoardRepr) -> &'static str {
+    match (repr.checked_king.is_some(), repr.moves.is_empty()) {
+        (true, true) => "#",
+        (true, false) => "+",
+        (false, _) => "",
+    }
+}
+
+/// The Termination tag of a game that didn't end over the board
+#[inline]
+pub fn termination(state: &GameState) -> Option<&'static str> {
+    match state {
+        GameState::Forfeit(_) => Some(crate::network::ILLEGAL_MOVES_FORFEIT),
+        // Only claimed here under a limit the opponent never agreed to
+        GameState::Stalled(..) => Some("adjudication"),
+        _ => None,
+    }
+}
+
+pub fn result_token(state: &GameState) -> &'static str {
+    let winner = match state {
+        // A game that broke down has no result, neither side won or lost it
+        GameState::Ongoing | GameState::PositionError => return "*",
+        GameState::Stalemate
+        | GameState::Draw
+        | GameState::Repetition
+        | GameState::FiftyMoves
+        | GameState::InsufficientMaterial => return "1/2-1/2",
+        GameState::Checkmate(loser)
+        | GameState::Resigned(loser)
+        | GameState::TimeForfeit(loser)
+        | GameState::Forfeit(loser)
+        | GameState::Stalled(loser, _) => opposite_color(*loser),
+    };
+    match winner {
+        Color::White => "1-0",
+        Color::Black => "0-1",
+    }
+}
+
+/// Days since 1970 to year, month and day, valid for any date a game is played on
+pub fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March so the leap day comes last
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+#[inline]
+pub(crate) fn seconds_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// When a file is written, for names that sort by it, like 2023-10-14-153012
+pub fn file_stamp(seconds: u64) -> String {
+    let (year, month, day) = civil_date((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// A file name that sorts by when the game was saved and tells games apart by their hash,
+/// like chess-2023-10-14-153012-3f2a9c01b7de.pgn
+pub fn export_file_name(hash: &str) -> String {
+    format!("chess-{}-{}.pgn", file_stamp(seconds_since_epoch()), hash)
+}
+
+#[inline]
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Write the game with the Seven Tag Roster and the moves in standard algebraic notation
+pub fn write_pgn(
+    out: &mut impl Write,
+    headers: &PgnHeaders,
+    moves: &[(String, Color)],
+) -> io::Result<()> {
+    let (year, month, day) = civil_date((seconds_since_epoch() / 86_400) as i64);
+    let mut tags = vec![
+        ("Event", "Casual game".to_owned()),
+        ("Site", headers.site.clone()),
+        ("Date", format!("{:04}.{:02}.{:02}", year, month, day)),
+        ("Round", "-".to_owned()),
+        ("White", headers.white.clone()),
+        ("Black", headers.black.clone()),
+        ("Result", headers.result.to_owned()),
+    ];
+    if let Some(fen) = &headers.fen {
+        tags.push(("SetUp", "1".to_owned()));
+        tags.push(("FEN", fen.clone()));
+    }
+    if let Some(termination) = headers.termination {
+        tags.push(("Termination", termination.to_owned()));
+    }
+    tags.push(("GameHash", headers.hash.clone()));
+    for (name, value) in tags {
+        writeln!(out, "[{} \"{}\"]", name, escape(&value))?;
+    }
+    writeln!(out)?;
+    if let Some(comment) = &headers.comment {
+        // A brace would end the comment early
+        writeln!(out, "{{{}}}", comment.replace(['{', '}'], ""))?;
+    }
+    let comments: Vec<Option<String>> = (0..=moves.len())
+        .map(|ply| {
+            let mut parts: Vec<(CommentSource, String)> = headers
+                .negotiations
+                .at(ply)
+                .map(|negotiation| (CommentSource::Negotiation, negotiation.describe()))
+                .collect();
+            parts.extend(
+                headers
+                    .bookmarks
+                    .get(ply)
+                    .map(|bookmark| (CommentSource::Bookmark, bookmarks::comment(bookmark))),
+            );
+            merge_comments(parts).map(|comment| format!("{{{}}}", comment))
+        })
+        .collect();
+
+    // A game from a FEN goes on from its move number and may start with Black
+    let mut number: u32 = headers
+        .fen
+        .as_deref()
+        .and_then(|fen| fen.split_whitespace().nth(5))
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(1);
+    let mut tokens: Vec<String> = comments[0].iter().cloned().collect();
+    for (i, (san, color)) in moves.iter().enumerate() {
+        match color {
+            Color::White => tokens.push(format!("{}.", number)),
+            // Black's move after a comment gets its number again
+            Color::Black if i == 0 || comments[i].is_some() => {
+                tokens.push(format!("{}...", number))
+            }
+            Color::Black => {}
+        }
+        let glyph = headers.glyphs.get(i).copied().flatten();
+        tokens.push(headers.glyph_style.write(san, glyph));
+        tokens.extend(comments[i + 1].clone());
+        if *color == Color::Black {
+            number += 1;
+        }
+    }
+    tokens.push(headers.result.to_owned());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            writeln!(out, "{}", line)?;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    writeln!(out, "{}", line)
+}
+
+/// The moves in text outside of comments, and their glyphs either as suffixes or as NAGs
+fn push_moves(moves: &mut Vec<String>, glyphs: &mut Vec<Option<Glyph>>, text: &str) {
+    let tokens = text
+        .split_whitespace()
+        // Move numbers and the result aren't moves
+        .filter(|token| !token.ends_with('.'))
+        .filter(|token| !["1-0", "0-1", "1/2-1/2", "*"].contains(token));
+    for token in tokens {
+        if token.starts_with('$') {
+            // A NAG goes with the move before it, those that aren't glyphs are skipped
+            if let (Some(glyph), Some(last)) = (glyphs::parse_nag(token), glyphs.last_mut()) {
+                *last = Some(glyph);
+            }
+            continue;
+        }
+        let (san, glyph) = glyphs::split_suffix(token);
+        moves.push(san.to_owned());
+        glyphs.push(glyph);
+    }
+}
+
+/// A game as `read_pgn` reads it
+pub struct ReadGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    // One for every move
+    pub glyphs: Vec<Option<Glyph>>,
+    pub bookmarks: Bookmarks,
+    pub negotiations: Negotiations,
+}
+
+/// The tag pairs, moves, glyphs, bookmarks and negotiations of a game written by `write_pgn`,
+/// other comments are skipped and variations aren't read
+pub fn read_pgn(text: &str) -> ReadGame {
+    let mut tags = Vec::new();
+    let mut moves = Vec::new();
+    let mut glyphs = Vec::new();
+    let mut marks = Bookmarks::default();
+    let mut negotiations = Negotiations::default();
+    // The comment being read while in one, comments can go on over several lines
+    let mut comment: Option<String> = None;
+    for line in text.lines() {
+        let in_comment = comment.is_some();
+        let line = line.trim();
+        let tag = line
+            .strip_prefix('[')
+            .and_then(|tag| tag.strip_suffix(']'))
+            .filter(|_| !in_comment);
+        if let Some(tag) = tag {
+            if let Some((name, value)) = tag.split_once(' ') {
+                let value = value.trim().trim_matches('"');
+                tags.push((
+                    name.to_owned(),
+                    value.replace("\\\"", "\"").replace("\\\\", "\\"),
+                ));
+            }
+            continue;
+        }
+        if let Some(comment) = &mut comment {
+            comment.push(' ');
+        }
+        let mut plain = String::new();
+        for character in line.chars() {
+            match (&mut comment, character) {
+                (None, '{') => {
+                    // The moves before it decide which ply a bookmark is at
+                    push_moves(&mut moves, &mut glyphs, &plain);
+                    plain.clear();
+                    comment = Some(String::new());
+                }
+                (Some(text), '}') => {
+                    let (negotiated, bookmark) = split_comment(text, moves.len());
+                    for negotiation in negotiated {
+                        negotiations.record(
+                            negotiation.ply,
+                            negotiation.side,
+                            negotiation.outcome,
+                            negotiation.at,
+                        );
+                    }
+                    if let Some(label) = bookmark {
+                        marks.add(moves.len());
+                        marks.set_label(moves.len(), label.as_deref().unwrap_or(""));
+                    }
+                    comment = None;
+                }
+                (Some(text), character) => text.push(character),
+                (None, character) => plain.push(character),
+            }
+        }
+        push_moves(&mut moves, &mut glyphs, &plain);
+    }
+    ReadGame {
+        tags,
+        moves,
+        glyphs,
+        bookmarks: marks,
+        negotiations,
+    }
+}
+
+/// The GameHash tag of a PGN file, reading no further than its tags
+pub fn read_game_hash(path: &Path) -> io::Result<Option<String>> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        let Some(tag) = line.strip_prefix('[').and_then(|tag| tag.strip_suffix(']')) else {
+            // The tags are over once the moves start
+            return Ok(None);
+        };
+        if let Some(("GameHash", value)) = tag.split_once(' ') {
+            return Ok(Some(value.trim().trim_matches('"').to_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// What saving a game to a directory should do with the saves already there
+#[derive(PartialEq, Debug)]
+pub enum SaveTarget {
+    New,
+    // The same game, with the same moves, is saved in this file
+    AlreadySaved(PathBuf),
+    // This file holds the game as it was some moves ago, the new save replaces it
+    Replaces(PathBuf),
+}
+
+/// The GameHash of every PGN file in the directories saved to, read again only for files
+/// that changed since
+#[derive(Default)]
+pub struct SaveIndex {
+    files: HashMap<PathBuf, (SystemTime, Option<String>)>,
+}
+
+impl SaveIndex {
+    /// The hashes of the PGN files in `dir`, unreadable files have none
+    fn hashes(&mut self, dir: &Path) -> Vec<(PathBuf, String)> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut hashes = Vec::new();
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            if path.extension().is_none_or(|extension| extension != "pgn") {
+                continue;
+            }
+            let Ok(modified) = std::fs::metadata(&path).and_then(|meta| meta.modified()) else {
+                continue;
+            };
+            let hash = match self.files.get(&path) {
+                Some((seen, hash)) if *seen == modified => hash.clone(),
+                _ => {
+                    let hash = read_game_hash(&path).ok().flatten();
+                    self.files.insert(path.clone(), (modified, hash.clone()));
+                    hash
+                }
+            };
+            if let Some(hash) = hash {
+                hashes.push((path, hash));
+            }
+        }
+        hashes
+    }
+
+    /// Whether the game is saved in `dir` already, in full or only some of its moves
+    pub fn target(&mut self, dir: &Path, start_fen: Option<&str>, moves: &[String]) -> SaveTarget {
+        let hash = game_hash(start_fen, moves);
+        // Every earlier point in the game, a save from back then is a partial one
+        let earlier: HashSet<String> = (0..moves.len())
+            .map(|count| game_hash(start_fen, &moves[..count]))
+            .collect();
+        let mut target = SaveTarget::New;
+        for (path, saved) in self.hashes(dir) {
+            if saved == hash {
+                return SaveTarget::AlreadySaved(path);
+            }
+            if earlier.contains(&saved) {
+                target = SaveTarget::Replaces(path);
+            }
+        }
+        target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::Outcome;
+    use crate::scratch::Scratch;
+    use jonathan_hallstrom_chess::Board;
+
+    #[test]
+    fn a_written_game_reads_back() {
+        let mut board = Board::default();
+        let mut moves = Vec::new();
+        for notation in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+            let repr = BoardRepr::new(&board);
+            let mv = *repr
+                .moves
+                .iter()
+                .find(|mv| mv.to_algebraic_notation() == notation)
+                .unwrap_or_else(|| panic!("{} isn't legal", notation));
+            moves.push((san(&repr, &mv), board.get_curr_player()));
+            board.play_move(mv).unwrap();
+        }
+
+        let headers = PgnHeaders {
+            site: "Test".to_owned(),
+            white: "White".to_owned(),
+            black: "Black".to_owned(),
+            result: "*",
+            fen: None,
+            hash: game_hash(None, &[]),
+            comment: Some("Practice from {here}".to_owned()),
+            termination: None,
+            bookmarks: {
+                let mut bookmarks = Bookmarks::default();
+                bookmarks.add(1);
+                bookmarks.set_label(1, "king's pawn");
+                bookmarks.add(4);
+                bookmarks
+            },
+            negotiations: Negotiations::default(),
+            glyphs: Vec::new(),
+            glyph_style: GlyphStyle::Suffix,
+        };
+        let mut written = Vec::new();
+        write_pgn(&mut written, &headers, &moves).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        assert!(
+            text.contains("1. e4 {[#] king's pawn} 1... e5 2. Nf3 Nc6 {[#]} *"),
+            "{}",
+            text
+        );
+        assert!(text.contains("\n{Practice from here}\n"), "{}", text);
+
+        let read = read_pgn(&text);
+        let tag_names: Vec<&str> = read.tags.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            tag_names,
+            ["Event", "Site", "Date", "Round", "White", "Black", "Result", "GameHash"]
+        );
+        assert_eq!(read.moves, ["e4", "e5", "Nf3", "Nc6"]);
+        assert_eq!(read.bookmarks, headers.bookmarks);
+    }
+
+    #[test]
+    fn glyphs_read_back_from_suffixes_and_nags() {
+        let moves: Vec<(String, Color)> = [("e4", Color::White), ("e5", Color::Black)]
+            .into_iter()
+            .chain([("Qh5", Color::White), ("Ke7", Color::Black)])
+            .map(|(san, color)| (san.to_owned(), color))
+            .collect();
+        let glyphs = vec![
+            None,
+            Some(Glyph::Good),
+            Some(Glyph::Dubious),
+            Some(Glyph::Blunder),
+        ];
+        for (style, written_moves) in [
+            (GlyphStyle::Suffix, "1. e4 e5! 2. Qh5?! {[#]} 2... Ke7?? *"),
+            (GlyphStyle::Nag, "1. e4 e5 $1 2. Qh5 $6 {[#]} 2... Ke7 $4 *"),
+        ] {
+            let mut bookmarks = Bookmarks::default();
+            bookmarks.add(3);
+            let headers = PgnHeaders {
+                site: "Test".to_owned(),
+                white: "White".to_owned(),
+                black: "Black".to_owned(),
+                result: "*",
+                fen: None,
+                hash: game_hash(None, &[]),
+                comment: None,
+                termination: None,
+                bookmarks,
+                negotiations: Negotiations::default(),
+                glyphs: glyphs.clone(),
+                glyph_style: style,
+            };
+            let mut written = Vec::new();
+            write_pgn(&mut written, &headers, &moves).unwrap();
+            let text = String::from_utf8(written).unwrap();
+            assert!(text.contains(written_moves), "{}", text);
+
+            let read = read_pgn(&text);
+            assert_eq!(read.moves, ["e4", "e5", "Qh5", "Ke7"]);
+            assert_eq!(read.glyphs, glyphs);
+            assert_eq!(read.bookmarks, headers.bookmarks);
+        }
+
+        // Other NAGs are about the position and are skipped
+        let read = read_pgn("1. e4 $14 e5 $2 $19 *");
+        assert_eq!(read.moves, ["e4", "e5"]);
+        assert_eq!(read.glyphs, [None, Some(Glyph::Mistake)]);
+    }
+
+    fn negotiation(ply: usize, side: Color, outcome: Outcome, seconds: u64) -> Negotiation {
+        Negotiation {
+            ply,
+            side,
+            outcome,
+            at: std::time::Duration::from_secs(seconds),
+        }
+    }
+
+    #[test]
+    fn comments_merge_in_the_order_of_their_sources() {
+        let offer = || negotiation(3, Color::White, Outcome::Offered, 65);
+        let decline = || negotiation(3, Color::Black, Outcome::Declined, 80);
+        let bookmark = |label: Option<&str>| {
+            bookmarks::comment(&bookmarks::Bookmark {
+                ply: 3,
+                label: label.map(str::to_owned),
+            })
+        };
+        let cases = [
+            (Vec::new(), None),
+            (
+                vec![(CommentSource::Negotiation, offer().describe())],
+                Some("White offers a draw at 1:05"),
+            ),
+            (
+                vec![(CommentSource::Bookmark, bookmark(Some("{sharp}")))],
+                Some("[#] sharp"),
+            ),
+            // The bookmark goes last even when it is given first
+            (
+                vec![
+                    (CommentSource::Bookmark, bookmark(None)),
+                    (CommentSource::Negotiation, offer().describe()),
+                ],
+                Some("White offers a draw at 1:05. [#]"),
+            ),
+            (
+                vec![
+                    (CommentSource::Negotiation, offer().describe()),
+                    (CommentSource::Negotiation, decline().describe()),
+                ],
+                Some("White offers a draw at 1:05. Black declines the draw at 1:20"),
+            ),
+            (
+                vec![
+                    (CommentSource::Negotiation, offer().describe()),
+                    (CommentSource::Bookmark, bookmark(Some("a. b"))),
+                    (CommentSource::Negotiation, decline().describe()),
+                ],
+                Some("White offers a draw at 1:05. Black declines the draw at 1:20. [#] a. b"),
+            ),
+        ];
+        for (parts, merged) in cases {
+            let wanted: Vec<bool> = parts
+                .iter()
+                .map(|(source, _)| *source == CommentSource::Bookmark)
+                .collect();
+            let comment = merge_comments(parts);
+            assert_eq!(comment.as_deref(), merged);
+            let Some(comment) = comment else {
+                continue;
+            };
+            let (negotiations, bookmark) = split_comment(&comment, 3);
+            assert_eq!(
+                negotiations.len(),
+                wanted.iter().filter(|bookmark| !**bookmark).count()
+            );
+            assert_eq!(bookmark.is_some(), wanted.contains(&true));
+        }
+        // A bookmark's label keeps whatever it says
+        let (_, bookmark) = split_comment("White offers a draw at 1:05. [#] a. b", 3);
+        assert_eq!(bookmark, Some(Some("a. b".to_owned())));
+    }
+
+    #[test]
+    fn negotiations_read_back_with_the_bookmarks() {
+        let mut board = Board::default();
+        let mut moves = Vec::new();
+        for notation in ["d2d4", "d7d5", "c2c4"] {
+            let repr = BoardRepr::new(&board);
+            let mv = *repr
+                .moves
+                .iter()
+                .find(|mv| mv.to_algebraic_notation() == notation)
+                .unwrap();
+            moves.push((san(&repr, &mv), board.get_curr_player()));
+            board.play_move(mv).unwrap();
+        }
+        let mut negotiations = Negotiations::default();
+        let second = std::time::Duration::from_secs(1);
+        negotiations.record(0, Color::White, Outcome::Offered, 2 * second);
+        negotiations.record(1, Color::White, Outcome::Lapsed, 9 * second);
+        negotiations.record(1, Color::Black, Outcome::Offered, 9 * second);
+        negotiations.record(2, Color::White, Outcome::Accepted, 70 * second);
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.add(1);
+        bookmarks.set_label(1, "offered back");
+        let headers = PgnHeaders {
+            site: "Test".to_owned(),
+            white: "White".to_owned(),
+            black: "Black".to_owned(),
+            result: "1/2-1/2",
+            fen: None,
+            hash: game_hash(None, &[]),
+            comment: None,
+            termination: None,
+            bookmarks,
+            negotiations,
+            glyphs: Vec::new(),
+            glyph_style: GlyphStyle::Suffix,
+        };
+        let mut written = Vec::new();
+        write_pgn(&mut written, &headers, &moves).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        assert!(
+            text.contains(
+                "1. d4 {White lets the draw offer lapse at 0:09. Black offers a draw at 0:09. \
+                 [#] offered back} 1... d5"
+            ),
+            "{}",
+            text
+        );
+        let read = read_pgn(&text);
+        assert_eq!(read.moves, ["d4", "d5", "c4"]);
+        assert_eq!(read.bookmarks, headers.bookmarks);
+        assert!(read.negotiations == headers.negotiations);
+    }
+
+    fn moves(notations: &[&str]) -> Vec<String> {
+        notations.iter().map(|mv| mv.to_string()).collect()
+    }
+
+    #[test]
+    fn game_hashes_never_change() {
+        // Saves written by earlier releases are found by these, they must stay as they are
+        assert_eq!(game_hash(None, &[]), "11b1d9eaaee8");
+        assert_eq!(game_hash(None, &moves(&["e2e4", "e7e5"])), "5c912caf70ca");
+        assert_eq!(game_hash(None, &moves(&["E2E4 ", "e7e5"])), "5c912caf70ca");
+        assert_eq!(
+            game_hash(Some("4k3/8/8/8/8/8/4P3/4K3  w - - 0 1"), &moves(&["e2e4"])),
+            "396d07ca7cae"
+        );
+        // The same moves in another order are another game
+        assert_eq!(
+            game_hash(None, &moves(&["e2e4", "e7e5", "g1f3"])),
+            "c6131489d60d"
+        );
+        assert_eq!(
+            game_hash(None, &moves(&["g1f3", "e7e5", "e2e4"])),
+            "bb3a30db06b3"
+        );
+    }
+
+    #[test]
+    fn saves_of_the_same_game_are_found() {
+        let scratch = Scratch::new("saves");
+        let dir = scratch.path();
+        let save = |name: &str, game: &[String]| {
+            let path = dir.join(name);
+            let headers = PgnHeaders {
+                site: "Test".to_owned(),
+                white: "White".to_owned(),
+                black: "Black".to_owned(),
+                result: "*",
+                fen: None,
+                hash: game_hash(None, game),
+                comment: None,
+                termination: None,
+                bookmarks: Bookmarks::default(),
+                negotiations: Negotiations::default(),
+                glyphs: Vec::new(),
+                glyph_style: GlyphStyle::Suffix,
+            };
+            let mut file = std::fs::File::create(&path).unwrap();
+            write_pgn(&mut file, &headers, &[]).unwrap();
+            path
+        };
+        let game = moves(&["e2e4", "e7e5", "g1f3"]);
+        let mut index = SaveIndex::default();
+        assert_eq!(index.target(dir, None, &game), SaveTarget::New);
+
+        // A save from two moves ago is replaced by the longer game
+        let partial = save("partial.pgn", &game[..1]);
+        assert_eq!(
+            index.target(dir, None, &game),
+            SaveTarget::Replaces(partial.clone())
+        );
+        // The same game again isn't saved twice
+        let full = save("full.pgn", &game);
+        assert_eq!(
+            index.target(dir, None, &game),
+            SaveTarget::AlreadySaved(full)
+        );
+        // Another game saved there is left alone
+        let other = moves(&["d2d4", "d7d5"]);
+        assert_eq!(index.target(dir, None, &other), SaveTarget::New);
+    }
+}
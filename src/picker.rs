@@ -0,0 +1,437 @@
+use crate::menu::grown;
+use crate::{unit_mesh, Graphics, Restore};
+use chess_gui::profiles::{ProfileSettings, Profiles};
+use ggez::graphics::{self, Canvas, Drawable, Mesh, Rect, Text};
+use ggez::input::keyboard::KeyCode;
+use ggez::Context;
+use mint::Point2;
+
+const BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const CHOSEN_BUTTON_COLOR: graphics::Color = graphics::Color::new(0.0, 0.5, 0.0, 0.75);
+const FIELD_COLOR: graphics::Color = graphics::Color::new(1.0, 0.9, 0.9, 1.0);
+const TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const ERROR_TEXT_COLOR: graphics::Color = graphics::Color::new(0.7, 0.0, 0.0, 1.0);
+// What a profile's swatch can be, C goes through them
+const SWATCHES: [graphics::Color; 6] = [
+    graphics::Color::new(0.85, 0.2, 0.2, 1.0),
+    graphics::Color::new(0.95, 0.6, 0.1, 1.0),
+    graphics::Color::new(0.9, 0.85, 0.2, 1.0),
+    graphics::Color::new(0.2, 0.65, 0.3, 1.0),
+    graphics::Color::new(0.2, 0.45, 0.9, 1.0),
+    graphics::Color::new(0.6, 0.3, 0.8, 1.0),
+];
+const NO_SWATCH_COLOR: graphics::Color = graphics::Color::new(0.8, 0.8, 0.8, 1.0);
+// The most profiles listed at once, the arrow keys scroll through more
+const SHOWN_PROFILES: usize = 7;
+
+/// Where everything on the profile picker is drawn, relative to the window size
+struct PickerLayout {
+    // One row for each profile shown, the swatch and the name
+    rows: Vec<(Rect, Rect)>,
+    new: Rect,
+    rename: Rect,
+    delete: Rect,
+    // Whether the selected profile plays under a random handle
+    anonymous: Rect,
+    play: Rect,
+    field: Rect,
+    hint: Rect,
+}
+
+impl PickerLayout {
+    fn new(width: f32, height: f32, shown: usize) -> Self {
+        let rect = |x: f32, y: f32, w: f32, h: f32| {
+            Rect::new(x * width, y * height, w * width, h * height)
+        };
+        Self {
+            rows: (0..shown)
+                .map(|row| {
+                    let y = 0.25 + row as f32 * 0.07;
+                    (rect(0.25, y, 0.05, 0.06), rect(0.32, y, 0.43, 0.06))
+                })
+                .collect(),
+            new: rect(0.05, 0.78, 0.16, 0.07),
+            rename: rect(0.23, 0.78, 0.16, 0.07),
+            delete: rect(0.41, 0.78, 0.16, 0.07),
+            anonymous: rect(0.59, 0.78, 0.18, 0.07),
+            play: rect(0.79, 0.78, 0.16, 0.07),
+            field: rect(0.25, 0.87, 0.5, 0.06),
+            hint: rect(0.05, 0.94, 0.9, 0.04),
+        }
+    }
+}
+
+/// A name being typed for a new profile, or for the one being renamed
+struct NameEntry {
+    renaming: Option<String>,
+    text: String,
+}
+
+/// The first screen when more than one person plays on this computer, for choosing who
+pub(crate) struct Picker {
+    names: Vec<String>,
+    swatches: Vec<Option<usize>>,
+    anonymous: Vec<bool>,
+    selected: usize,
+    // The first profile shown, when there are more than fit
+    first_shown: usize,
+    entry: Option<NameEntry>,
+    // Waiting for Y or N before the selected profile is moved to the trash
+    confirming_delete: bool,
+    message: Option<String>,
+    // Unit square tinted and stretched into every button
+    button_mesh: Mesh,
+}
+
+impl Restore for Picker {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::UnitMesh(&mut self.button_mesh)
+    }
+}
+
+impl Picker {
+    pub(crate) fn new(ctx: &Context, profiles: &Profiles) -> Self {
+        let mut picker = Self {
+            names: Vec::new(),
+            swatches: Vec::new(),
+            anonymous: Vec::new(),
+            selected: 0,
+            first_shown: 0,
+            entry: None,
+            confirming_delete: false,
+            message: None,
+            button_mesh: unit_mesh(ctx),
+        };
+        picker.reload(profiles, profiles.last());
+        picker
+    }
+
+    /// Read the profiles again after one changed, selecting `selected` if it is there
+    fn reload(&mut self, profiles: &Profiles, selected: Option<String>) {
+        self.names = profiles.list();
+        let settings: Vec<ProfileSettings> = self
+            .names
+            .iter()
+            .map(|name| ProfileSettings::read(&profiles.paths(name)))
+            .collect();
+        self.swatches = settings.iter().map(|settings| settings.swatch).collect();
+        self.anonymous = settings.iter().map(|settings| settings.anonymous).collect();
+        self.selected = selected
+            .and_then(|selected| self.names.iter().position(|name| *name == selected))
+            .unwrap_or(0);
+        self.select(self.selected);
+    }
+
+    /// Select the profile at `index` and scroll it into view
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.names.len().saturating_sub(1));
+        if self.selected < self.first_shown {
+            self.first_shown = self.selected;
+        } else if self.selected >= self.first_shown + SHOWN_PROFILES {
+            self.first_shown = self.selected + 1 - SHOWN_PROFILES;
+        }
+    }
+
+    #[inline]
+    fn selected_name(&self) -> Option<&String> {
+        self.names.get(self.selected)
+    }
+
+    /// Whether Escape has nothing left to cancel, so it would quit
+    #[inline]
+    pub(crate) fn escape_leaves(&self) -> bool {
+        self.entry.is_none() && !self.confirming_delete
+    }
+
+    fn start_entry(&mut self, renaming: bool) {
+        let renaming = match renaming {
+            true => match self.selected_name() {
+                Some(name) => Some(name.clone()),
+                None => return,
+            },
+            false => None,
+        };
+        self.message = None;
+        self.entry = Some(NameEntry {
+            text: renaming.clone().unwrap_or_default(),
+            renaming,
+        });
+    }
+
+    /// Create or rename the profile with the typed name
+    fn submit_entry(&mut self, profiles: &Profiles) {
+        let Some(entry) = self.entry.take() else {
+            return;
+        };
+        let done = match &entry.renaming {
+            Some(from) => profiles.rename(from, &entry.text),
+            None => profiles.create(&entry.text),
+        };
+        match done {
+            Ok(_) => {
+                self.message = None;
+                self.reload(profiles, Some(entry.text.trim().to_owned()));
+            }
+            Err(message) => {
+                self.message = Some(message);
+                self.entry = Some(entry);
+            }
+        }
+    }
+
+    fn delete(&mut self, profiles: &Profiles) {
+        self.confirming_delete = false;
+        let Some(name) = self.selected_name().cloned() else {
+            return;
+        };
+        self.message = Some(match profiles.delete(&name) {
+            Ok(trashed) => format!("Moved {} to {}", name, trashed.display()),
+            Err(message) => message,
+        });
+        // There is always someone to play as
+        if profiles.list().is_empty() {
+            if let Err(message) = profiles.create(chess_gui::profiles::DEFAULT_PROFILE) {
+                self.message = Some(message);
+            }
+        }
+        self.reload(profiles, None);
+    }
+
+    /// Give the selected profile the next swatch, and none after the last one
+    fn next_swatch(&mut self, profiles: &Profiles) {
+        let Some(name) = self.selected_name() else {
+            return;
+        };
+        let paths = profiles.paths(name);
+        let mut settings = ProfileSettings::read(&paths);
+        settings.swatch = match settings.swatch {
+            None => Some(0),
+            Some(swatch) if swatch + 1 < SWATCHES.len() => Some(swatch + 1),
+            Some(_) => None,
+        };
+        match settings.write(&paths) {
+            Ok(()) => self.swatches[self.selected] = settings.swatch,
+            Err(message) => self.message = Some(message),
+        }
+    }
+
+    /// Have the selected profile play under a random handle from the next run on, or stop
+    fn toggle_anonymous(&mut self, profiles: &Profiles) {
+        let Some(name) = self.selected_name() else {
+            return;
+        };
+        let paths = profiles.paths(name);
+        let mut settings = ProfileSettings::read(&paths);
+        settings.anonymous = !settings.anonymous;
+        match settings.write(&paths) {
+            Ok(()) => self.anonymous[self.selected] = settings.anonymous,
+            Err(message) => self.message = Some(message),
+        }
+    }
+
+    fn draw_button(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        rect: Rect,
+        label: &str,
+        color: graphics::Color,
+    ) {
+        canvas.draw(
+            &self.button_mesh,
+            graphics::DrawParam::default().dest_rect(rect).color(color),
+        );
+        let mut text = Text::new(label);
+        text.set_scale(rect.h / 2.0);
+        let text_width = text.dimensions(ctx).map_or(0.0, |dims| dims.w);
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: rect.x + (rect.w - text_width) / 2.0,
+                    y: rect.y + rect.h / 4.0,
+                })
+                .color(TEXT_COLOR),
+        );
+    }
+
+    pub(crate) fn draw(&self, ctx: &Context, canvas: &mut Canvas) {
+        let (width, height) = {
+            let cords = canvas.screen_coordinates().unwrap();
+            (cords.w, cords.h)
+        };
+        let shown = self.names.len().min(SHOWN_PROFILES);
+        let layout = PickerLayout::new(width, height, shown);
+
+        let title = Rect::new(0.0, 0.1 * height, width, 0.1 * height);
+        self.draw_button(
+            ctx,
+            canvas,
+            title,
+            "Who is playing?",
+            graphics::Color::WHITE,
+        );
+
+        for (row, (swatch, name)) in layout.rows.iter().enumerate() {
+            let index = self.first_shown + row;
+            let color = self.swatches[index].map_or(NO_SWATCH_COLOR, |swatch| {
+                SWATCHES[swatch.min(SWATCHES.len() - 1)]
+            });
+            canvas.draw(
+                &self.button_mesh,
+                graphics::DrawParam::default()
+                    .dest_rect(*swatch)
+                    .color(color),
+            );
+            let color = match index == self.selected {
+                true => CHOSEN_BUTTON_COLOR,
+                false => BUTTON_COLOR,
+            };
+            let label = match self.anonymous[index] {
+                true => format!("{} (anonymous)", self.names[index]),
+                false => self.names[index].clone(),
+            };
+            self.draw_button(ctx, canvas, *name, &label, color);
+        }
+
+        self.draw_button(ctx, canvas, layout.new, "New", BUTTON_COLOR);
+        self.draw_button(ctx, canvas, layout.rename, "Rename", BUTTON_COLOR);
+        self.draw_button(ctx, canvas, layout.delete, "Delete", BUTTON_COLOR);
+        let anonymous_color = match self.anonymous.get(self.selected) {
+            Some(true) => CHOSEN_BUTTON_COLOR,
+            _ => BUTTON_COLOR,
+        };
+        self.draw_button(ctx, canvas, layout.anonymous, "Anonymous", anonymous_color);
+        self.draw_button(ctx, canvas, layout.play, "Play", CHOSEN_BUTTON_COLOR);
+
+        if let Some(entry) = &self.entry {
+            self.draw_button(ctx, canvas, layout.field, &entry.text, FIELD_COLOR);
+        }
+
+        let (hint, color) = match (&self.message, &self.entry) {
+            _ if self.confirming_delete => (
+                format!(
+                    "Delete {}? Its files are moved to the trash (Y/N)",
+                    self.selected_name().map_or("", String::as_str)
+                ),
+                ERROR_TEXT_COLOR,
+            ),
+            (Some(message), _) => (message.clone(), ERROR_TEXT_COLOR),
+            (None, Some(entry)) => (
+                match entry.renaming {
+                    Some(_) => "A new name, Enter renames and Escape cancels".to_owned(),
+                    None => "The name of the profile, Enter creates it".to_owned(),
+                },
+                TEXT_COLOR,
+            ),
+            (None, None) => (
+                "N for a new profile, R renames, C changes the color, A goes anonymous, Delete deletes".to_owned(),
+                TEXT_COLOR,
+            ),
+        };
+        let mut text = Text::new(hint);
+        text.set_scale(layout.hint.h * 0.8);
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: layout.hint.x,
+                    y: layout.hint.y,
+                })
+                .color(color),
+        );
+    }
+
+    /// Handle a click, the profile to play as once Play or a selected profile is clicked.
+    /// The buttons take clicks up to `slop` pixels past their edges.
+    pub(crate) fn click(
+        &mut self,
+        profiles: &Profiles,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        slop: f32,
+    ) -> Option<String> {
+        let shown = self.names.len().min(SHOWN_PROFILES);
+        let layout = PickerLayout::new(width, height, shown);
+        let point = Point2 { x, y };
+        let hit = |rect: &Rect| grown(*rect, slop).contains(point);
+        if self.confirming_delete {
+            return None;
+        }
+
+        if let Some(row) = layout.rows.iter().position(|(_, name)| hit(name)) {
+            let index = self.first_shown + row;
+            // Clicking the selected one again plays as it
+            if index == self.selected && self.entry.is_none() {
+                return self.selected_name().cloned();
+            }
+            self.select(index);
+        } else if hit(&layout.new) {
+            self.start_entry(false);
+        } else if hit(&layout.rename) {
+            self.start_entry(true);
+        } else if hit(&layout.delete) {
+            self.entry = None;
+            self.confirming_delete = true;
+        } else if hit(&layout.anonymous) {
+            self.toggle_anonymous(profiles);
+        } else if hit(&layout.play) {
+            match &self.entry {
+                Some(_) => self.submit_entry(profiles),
+                None => return self.selected_name().cloned(),
+            }
+        }
+        None
+    }
+
+    /// Handle a key press, the profile to play as once Enter is pressed
+    pub(crate) fn key(&mut self, profiles: &Profiles, keycode: KeyCode) -> Option<String> {
+        if self.entry.is_some() {
+            match keycode {
+                KeyCode::Return | KeyCode::NumpadEnter => self.submit_entry(profiles),
+                KeyCode::Escape => self.entry = None,
+                KeyCode::Back => {
+                    if let Some(entry) = &mut self.entry {
+                        entry.text.pop();
+                    }
+                }
+                _ => {}
+            }
+            return None;
+        }
+        match keycode {
+            KeyCode::Escape => self.confirming_delete = false,
+            // Y and N come through text_input, like every other letter
+            _ if self.confirming_delete => {}
+            KeyCode::Up => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down => self.select(self.selected + 1),
+            KeyCode::Delete => self.confirming_delete = true,
+            KeyCode::Return | KeyCode::NumpadEnter => return self.selected_name().cloned(),
+            _ => {}
+        }
+        None
+    }
+
+    /// Typed characters go into the name being typed, otherwise they are shortcuts
+    pub(crate) fn text_input(&mut self, profiles: &Profiles, character: char) {
+        if let Some(entry) = &mut self.entry {
+            if !character.is_control() {
+                entry.text.push(character);
+            }
+            return;
+        }
+        match (self.confirming_delete, character.to_ascii_lowercase()) {
+            (true, 'y') => self.delete(profiles),
+            (true, 'n') => self.confirming_delete = false,
+            (true, _) => {}
+            (false, 'n') => self.start_entry(false),
+            (false, 'r') => self.start_entry(true),
+            (false, 'c') => self.next_swatch(profiles),
+            (false, 'a') => self.toggle_anonymous(profiles),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use jonathan_hallstrom_chess::Color;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Outlines aren't made for sprite sheets bigger than this, they would take as much memory again
+pub const MAX_OUTLINE_PIXELS: usize = 4096 * 2048;
+// Pixels at least this opaque are part of the piece the outline goes around
+const SOLID_ALPHA: u8 = 128;
+
+/// A color as "#rrggbb"
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub fn parse(text: &str) -> Option<Self> {
+        let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+        let channel = |at: usize| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok();
+        Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// How one side's pieces are drawn differently, all off by default
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct SideStyle {
+    // Multiplied into the sprite's colors
+    pub tint: Option<Rgb>,
+    // A solid silhouette a little bigger than the piece drawn behind it
+    pub outline: Option<Rgb>,
+    // A dot in the top right corner of the square
+    pub badge: Option<Rgb>,
+}
+
+impl SideStyle {
+    fn to_json(self) -> Value {
+        let mut object = Map::new();
+        for (key, color) in [
+            ("tint", self.tint),
+            ("outline", self.outline),
+            ("badge", self.badge),
+        ] {
+            object.insert(
+                key.to_owned(),
+                color.map_or(Value::Null, |color| Value::String(color.to_string())),
+            );
+        }
+        Value::Object(object)
+    }
+
+    fn from_json(value: Option<&Value>) -> Self {
+        let color = |key: &str| {
+            value
+                .and_then(|value| value.get(key))
+                .and_then(Value::as_str)
+                .and_then(Rgb::parse)
+        };
+        Self {
+            tint: color("tint"),
+            outline: color("outline"),
+            badge: color("badge"),
+        }
+    }
+}
+
+/// The styles of both sides' pieces, kept in the profile settings
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct PieceStyles {
+    pub white: SideStyle,
+    pub black: SideStyle,
+}
+
+impl PieceStyles {
+    #[inline]
+    pub fn side(&self, color: Color) -> &SideStyle {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    /// Whether either side has an outline, only then are the outlines made
+    #[inline]
+    pub fn outlined(&self) -> bool {
+        self.white.outline.is_some() || self.black.outline.is_some()
+    }
+
+    pub fn apply(&mut self, change: &StyleChange) {
+        let side = match change.side {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        };
+        *match change.part {
+            Part::Tint => &mut side.tint,
+            Part::Outline => &mut side.outline,
+            Part::Badge => &mut side.badge,
+        } = change.color;
+    }
+
+    pub fn to_json(self) -> Value {
+        let mut object = Map::new();
+        object.insert("white".to_owned(), self.white.to_json());
+        object.insert("black".to_owned(), self.black.to_json());
+        Value::Object(object)
+    }
+
+    /// The styles in `value`, anything missing or damaged is left off
+    pub fn from_json(value: &Value) -> Self {
+        Self {
+            white: SideStyle::from_json(value.get("white")),
+            black: SideStyle::from_json(value.get("black")),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Part {
+    Tint,
+    Outline,
+    Badge,
+}
+
+/// One part of one side's style set from the command line, None turns it off
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub struct StyleChange {
+    pub part: Part,
+    pub side: Color,
+    pub color: Option<Rgb>,
+}
+
+impl StyleChange {
+    /// A change written like "black=#1a2b6d" or "white=off"
+    pub fn parse(part: Part, text: &str) -> Option<Self> {
+        let (side, color) = text.split_once('=')?;
+        let side = match side {
+            "white" => Color::White,
+            "black" => Color::Black,
+            _ => return None,
+        };
+        let color = match color {
+            "off" => None,
+            color => Some(Rgb::parse(color)?),
+        };
+        Some(Self { part, side, color })
+    }
+}
+
+/// The color a sprite is drawn with: `color`, which dims or fades it where it is drawn,
+/// times the side's tint. Alpha is left alone.
+pub fn tinted(color: [f32; 4], tint: Option<Rgb>) -> [f32; 4] {
+    let Some(Rgb(r, g, b)) = tint else {
+        return color;
+    };
+    let [red, green, blue, alpha] = color;
+    [
+        red * r as f32 / 255.0,
+        green * g as f32 / 255.0,
+        blue * b as f32 / 255.0,
+        alpha,
+    ]
+}
+
+/// The outlines of a sprite sheet of `columns` by `rows` pieces, as RGBA pixels the size of
+/// `pixels`: white wherever a solid pixel of the same piece is at most `radius` away, so it
+/// can be tinted to any solid color, and clear elsewhere. None for a sheet too big to keep
+/// twice.
+pub fn silhouettes(
+    pixels: &[u8],
+    (width, height): (usize, usize),
+    (columns, rows): (usize, usize),
+    radius: usize,
+) -> Option<Vec<u8>> {
+    if width * height > MAX_OUTLINE_PIXELS || pixels.len() != width * height * 4 {
+        return None;
+    }
+    let (cell_width, cell_height) = (width / columns.max(1), height / rows.max(1));
+    // The reach around `at` that stays inside its piece's cell
+    let reach = |at: usize, cell: usize, size: usize| {
+        let start = at - at % cell.max(1);
+        at.saturating_sub(radius).max(start)..(at + radius + 1).min(start + cell).min(size)
+    };
+    // Growing rows first and then columns grows by a square
+    let solid: Vec<bool> = pixels
+        .chunks_exact(4)
+        .map(|pixel| pixel[3] >= SOLID_ALPHA)
+        .collect();
+    let mut across = vec![false; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            across[y * width + x] = reach(x, cell_width, width).any(|x| solid[y * width + x]);
+        }
+    }
+    let mut outlines = vec![0; pixels.len()];
+    for y in 0..height {
+        for x in 0..width {
+            if reach(y, cell_height, height).any(|y| across[y * width + x]) {
+                outlines[(y * width + x) * 4..][..4].copy_from_slice(&[255; 4]);
+            }
+        }
+    }
+    Some(outlines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tints_multiply_and_keep_the_alpha() {
+        let close = |color: [f32; 4], wanted: [f32; 4]| {
+            color
+                .iter()
+                .zip(wanted)
+                .all(|(channel, wanted)| (channel - wanted).abs() < 1e-6)
+        };
+        // Cream
+        assert!(close(
+            tinted([1.0, 1.0, 1.0, 1.0], Some(Rgb(255, 240, 204))),
+            [1.0, 0.941_176_5, 0.8, 1.0]
+        ));
+        // Dimmed while waiting and tinted dark blue too
+        assert!(close(
+            tinted([0.6, 0.6, 0.6, 0.5], Some(Rgb(0, 51, 255))),
+            [0.0, 0.12, 0.6, 0.5]
+        ));
+        assert_eq!(tinted([0.6, 0.6, 0.6, 1.0], None), [0.6, 0.6, 0.6, 1.0]);
+    }
+
+    #[test]
+    fn outlines_grow_around_each_piece_without_bleeding_into_the_next() {
+        // Two 4x3 cells side by side, one solid pixel at the right edge of the first
+        let (width, height) = (8, 3);
+        let mut pixels = vec![0; width * height * 4];
+        pixels[(width + 3) * 4 + 3] = 255;
+        let outlines = silhouettes(&pixels, (width, height), (2, 1), 1).unwrap();
+        let opaque: Vec<(usize, usize)> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|(x, y)| outlines[(y * width + x) * 4 + 3] == 255)
+            .collect();
+        assert_eq!(
+            opaque,
+            [(2, 0), (3, 0), (2, 1), (3, 1), (2, 2), (3, 2)],
+            "stays in the first cell"
+        );
+        assert_eq!(silhouettes(&pixels, (4096, 4096), (6, 2), 1), None);
+    }
+
+    #[test]
+    fn styles_round_trip_through_the_profile_settings() {
+        let mut styles = PieceStyles::default();
+        for (part, text) in [
+            (Part::Tint, "black=#1a2b6d"),
+            (Part::Tint, "white=#fff0cc"),
+            (Part::Outline, "black=#ffffff"),
+            (Part::Badge, "white=#00a000"),
+            (Part::Badge, "white=off"),
+            (Part::Badge, "black=#ff8800"),
+        ] {
+            styles.apply(&StyleChange::parse(part, text).unwrap());
+        }
+        assert_eq!(
+            styles.black,
+            SideStyle {
+                tint: Some(Rgb(0x1a, 0x2b, 0x6d)),
+                outline: Some(Rgb(255, 255, 255)),
+                badge: Some(Rgb(255, 136, 0)),
+            }
+        );
+        assert_eq!(styles.white.badge, None);
+        assert!(styles.outlined());
+        assert_eq!(PieceStyles::from_json(&styles.to_json()), styles);
+        assert_eq!(PieceStyles::from_json(&Value::Null), PieceStyles::default());
+        assert!(StyleChange::parse(Part::Tint, "red=#000000").is_none());
+        assert!(StyleChange::parse(Part::Tint, "white=#12345").is_none());
+    }
+}
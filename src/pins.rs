@@ -0,0 +1,297 @@
+use crate::{material_value, opposite_color, pgn, MovesBySquare, Square};
+use jonathan_hallstrom_chess::Color;
+
+type Coord = (usize, usize);
+
+const STRAIGHT: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAGONAL: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+const KNIGHT_OFFSETS: [(isize, isize); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+
+/// A piece that can't move off the line between an enemy slider and a piece behind it
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Pin {
+    // The enemy rook, bishop or queen
+    pub pinner: Coord,
+    pub pinned: Coord,
+    // The king, or for a relative pin the queen or rook moving would expose
+    pub target: Coord,
+    // Against the king, so moving off the line isn't even legal
+    pub absolute: bool,
+}
+
+impl Pin {
+    /// The squares from the pinner up to the target, where the pinned piece may still go
+    pub fn ray(&self) -> Vec<Coord> {
+        let step = |from: usize, to: usize| (to as isize - from as isize).signum();
+        let (drow, dcol) = (
+            step(self.pinner.0, self.target.0),
+            step(self.pinner.1, self.target.1),
+        );
+        let mut ray = vec![self.pinner];
+        let mut at = self.pinner;
+        while at != self.target {
+            at = (
+                (at.0 as isize + drow) as usize,
+                (at.1 as isize + dcol) as usize,
+            );
+            ray.push(at);
+        }
+        ray
+    }
+
+    /// What the tooltip of the pinned piece says, like "The knight on c6 is pinned to the
+    /// king by the bishop on b5"
+    pub fn describe(&self, squares: &[[Square; 8]; 8]) -> String {
+        let name = |(row, col): Coord| {
+            format!(
+                "{} on {}",
+                piece_name(&squares[row][col]),
+                pgn::square_name(row, col)
+            )
+        };
+        format!(
+            "The {} is pinned to the {} by the {}",
+            name(self.pinned),
+            piece_name(&squares[self.target.0][self.target.1]),
+            name(self.pinner)
+        )
+    }
+}
+
+#[inline]
+fn piece_name(piece: &Square) -> &'static str {
+    match piece {
+        Square::Empty => "empty square",
+        Square::Pawn(_) => "pawn",
+        Square::Rook(_) => "rook",
+        Square::Bishop(_) => "bishop",
+        Square::Knight(_) => "knight",
+        Square::King(_) => "king",
+        Square::Queen(_) => "queen",
+    }
+}
+
+#[inline]
+fn offset((row, col): Coord, (drow, dcol): (isize, isize)) -> Option<Coord> {
+    let (row, col) = (row as isize + drow, col as isize + dcol);
+    ((0..8).contains(&row) && (0..8).contains(&col)).then_some((row as usize, col as usize))
+}
+
+/// How many squares the piece at `from` could go to if its own king's safety didn't matter,
+/// without en passant
+fn pseudo_legal_moves(squares: &[[Square; 8]; 8], from: Coord) -> usize {
+    let piece = squares[from.0][from.1];
+    let Some(color) = piece.color() else {
+        return 0;
+    };
+    let open = |to: Coord| squares[to.0][to.1].color() != Some(color);
+    let slide = |rays: &[(isize, isize)]| -> usize {
+        let mut count = 0;
+        for ray in rays {
+            let mut at = from;
+            while let Some(to) = offset(at, *ray) {
+                if !open(to) {
+                    break;
+                }
+                count += 1;
+                if squares[to.0][to.1] != Square::Empty {
+                    break;
+                }
+                at = to;
+            }
+        }
+        count
+    };
+    match piece {
+        // A king is never pinned
+        Square::Empty | Square::King(_) => 0,
+        Square::Rook(_) => slide(&STRAIGHT),
+        Square::Bishop(_) => slide(&DIAGONAL),
+        Square::Queen(_) => slide(&STRAIGHT) + slide(&DIAGONAL),
+        Square::Knight(_) => KNIGHT_OFFSETS
+            .iter()
+            .filter_map(|jump| offset(from, *jump))
+            .filter(|to| open(*to))
+            .count(),
+        Square::Pawn(_) => {
+            // White moves towards row 0
+            let (forward, start_row) = match color {
+                Color::White => (-1, 6),
+                Color::Black => (1, 1),
+            };
+            let empty = |to: &Coord| squares[to.0][to.1] == Square::Empty;
+            let mut count = 0;
+            if let Some(one) = offset(from, (forward, 0)).filter(empty) {
+                count += 1;
+                if from.0 == start_row && offset(one, (forward, 0)).is_some_and(|two| empty(&two)) {
+                    count += 1;
+                }
+            }
+            count
+                + [-1, 1]
+                    .into_iter()
+                    .filter_map(|dcol| offset(from, (forward, dcol)))
+                    .filter(|to| squares[to.0][to.1].color() == Some(opposite_color(color)))
+                    .count()
+        }
+    }
+}
+
+/// The first two pieces along a ray from `from`, with their squares
+fn first_two(squares: &[[Square; 8]; 8], from: Coord, ray: (isize, isize)) -> Vec<Coord> {
+    let mut found = Vec::new();
+    let mut at = from;
+    while let Some(to) = offset(at, ray).filter(|_| found.len() < 2) {
+        if squares[to.0][to.1] != Square::Empty {
+            found.push(to);
+        }
+        at = to;
+    }
+    found
+}
+
+/// The pins on the pieces of `to_move`. A piece is pinned to its king when an enemy slider
+/// lines up with the king through it and it has fewer legal moves in `legal_moves` than it
+/// would have if its king's safety didn't matter. With `relative`, also the pieces pinned
+/// to a queen or rook by a slider worth less, which could move but would lose it.
+pub fn pins(
+    squares: &[[Square; 8]; 8],
+    legal_moves: &MovesBySquare,
+    to_move: Color,
+    relative: bool,
+) -> Vec<Pin> {
+    let mut found: Vec<Pin> = Vec::new();
+    for (row, col) in (0..8usize).flat_map(|row| (0..8usize).map(move |col| (row, col))) {
+        let target = squares[row][col];
+        let absolute = match target {
+            Square::King(color) if color == to_move => true,
+            Square::Queen(color) | Square::Rook(color) if color == to_move && relative => false,
+            _ => continue,
+        };
+        for ray in STRAIGHT.iter().chain(DIAGONAL.iter()) {
+            let [pinned, pinner] = first_two(squares, (row, col), *ray)[..] else {
+                continue;
+            };
+            let slider = match ray.0 == 0 || ray.1 == 0 {
+                true => Square::Rook(opposite_color(to_move)),
+                false => Square::Bishop(opposite_color(to_move)),
+            };
+            let pinner_piece = squares[pinner.0][pinner.1];
+            let lined_up = squares[pinned.0][pinned.1].color() == Some(to_move)
+                && (pinner_piece == slider
+                    || pinner_piece == Square::Queen(opposite_color(to_move)));
+            if !lined_up {
+                continue;
+            }
+            let held = match absolute {
+                // The legal moves have to show it, so a pin the engine doesn't see isn't drawn
+                true => legal_moves[pinned.0][pinned.1].len() < pseudo_legal_moves(squares, pinned),
+                false => material_value(&pinner_piece) < material_value(&target),
+            };
+            // A piece pinned to its king isn't also shown pinned to something less
+            if held && !found.iter().any(|pin| pin.pinned == pinned && pin.absolute) {
+                found.retain(|pin| pin.pinned != pinned);
+                found.push(Pin {
+                    pinner,
+                    pinned,
+                    target: (row, col),
+                    absolute,
+                });
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board_from_fen, BoardRepr};
+
+    fn pins_in(fen: &str, relative: bool) -> (BoardRepr, Vec<Pin>) {
+        let repr = BoardRepr::new(&board_from_fen(fen).unwrap());
+        let found = pins(&repr.squares, &repr.legal_moves, repr.to_move, relative);
+        (repr, found)
+    }
+
+    #[test]
+    fn a_knight_pinned_to_its_king_has_no_moves() {
+        // The bishop on b5 through the knight on c6 to the king on e8
+        let (repr, found) = pins_in("4k3/8/2n5/1B6/8/8/8/4K3 b - - 0 1", false);
+        assert!(repr.legal_moves[2][2].is_empty());
+        assert_eq!(
+            found,
+            [Pin {
+                pinner: (3, 1),
+                pinned: (2, 2),
+                target: (0, 4),
+                absolute: true,
+            }]
+        );
+        assert_eq!(
+            found[0].describe(&repr.squares),
+            "The knight on c6 is pinned to the king by the bishop on b5"
+        );
+    }
+
+    #[test]
+    fn a_pinned_rook_still_slides_along_the_ray() {
+        // The rook on e7 can only go down the e-file, up to taking the rook on e1
+        let (repr, found) = pins_in("4k3/4r3/8/8/8/8/8/4R1K1 b - - 0 1", false);
+        assert_eq!(found.len(), 1);
+        let pin = found[0];
+        assert_eq!(
+            (pin.pinner, pin.pinned, pin.target),
+            ((7, 4), (1, 4), (0, 4))
+        );
+        let ray = pin.ray();
+        assert_eq!(ray.first(), Some(&(7, 4)));
+        assert_eq!(ray.last(), Some(&(0, 4)));
+        let destinations = &repr.legal_moves[1][4];
+        assert_eq!(destinations.len(), 6);
+        assert!(destinations.keys().all(|to| ray.contains(to)));
+    }
+
+    #[test]
+    fn a_discovered_check_waiting_to_happen_is_no_pin() {
+        // White's bishop stands between its own rook and the black king
+        for fen in [
+            "4k3/8/8/8/4B3/8/8/4R1K1 w - - 0 1",
+            "4k3/8/8/8/4B3/8/8/4R1K1 b - - 0 1",
+        ] {
+            assert_eq!(pins_in(fen, true).1, []);
+        }
+    }
+
+    #[test]
+    fn relative_pins_only_show_when_asked_for() {
+        // The rook on d1 through the knight on d6 to the queen on d8
+        let fen = "3qk3/8/3n4/8/8/8/8/3RK3 b - - 0 1";
+        assert_eq!(pins_in(fen, false).1, []);
+        let (repr, found) = pins_in(fen, true);
+        assert_eq!(
+            found,
+            [Pin {
+                pinner: (7, 3),
+                pinned: (2, 3),
+                target: (0, 3),
+                absolute: false,
+            }]
+        );
+        // The knight can still move, it would only lose the queen
+        assert!(!repr.legal_moves[2][3].is_empty());
+        assert_eq!(
+            found[0].describe(&repr.squares),
+            "The knight on d6 is pinned to the queen by the rook on d1"
+        );
+    }
+}
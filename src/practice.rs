@@ -0,0 +1,145 @@
+use crate::config::ComputerConfig;
+use crate::opposite_color;
+use crate::session::Session;
+use jonathan_hallstrom_chess::Color;
+
+/// A practice game against the computer from a position of a finished networked game.
+/// The source game is only read, so it is exactly the same once the practice is over.
+#[derive(Clone)]
+pub struct Fork {
+    // The game it came from by `pgn::game_hash`, and the ply its start position is after
+    pub source_hash: String,
+    pub ply: usize,
+    pub start_fen: String,
+    // The side we played in the source game, the computer takes the other one
+    pub player: Color,
+    pub depth: u32,
+}
+
+impl Fork {
+    /// Fork `source` at the position after `ply`, the final position for None
+    pub fn new(
+        source: &Session,
+        ply: Option<usize>,
+        player: Color,
+        depth: u32,
+    ) -> Result<Self, String> {
+        let ply = ply.unwrap_or(source.snapshots.len() - 1);
+        let start_fen = source
+            .snapshots
+            .get(ply)
+            .ok_or_else(|| format!("the game has no position after ply {}", ply))?
+            .clone();
+        Ok(Self {
+            source_hash: source.game_hash(),
+            ply,
+            start_fen,
+            player,
+            depth,
+        })
+    }
+
+    /// A new game from the forked position, nothing of the source game is shared with it
+    #[inline]
+    pub fn session(&self) -> Result<Session, String> {
+        Session::new(Some(self.start_fen.clone()))
+    }
+
+    #[inline]
+    pub fn computer(&self) -> ComputerConfig {
+        ComputerConfig {
+            depth: self.depth,
+            color: opposite_color(self.player),
+        }
+    }
+
+    /// Goes before the moves of the practice game's PGN, so it can be traced to its game
+    #[inline]
+    pub fn comment(&self) -> String {
+        format!(
+            "Practice from game {} after ply {}",
+            self.source_hash, self.ply
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::tests::play;
+
+    // The position, moves, snapshots, scores and move list
+    type Record = (
+        String,
+        Vec<String>,
+        Vec<String>,
+        Vec<Option<i32>>,
+        Vec<String>,
+    );
+
+    /// Everything the source game keeps about its moves
+    fn record(session: &Session) -> Record {
+        (
+            session.fen(),
+            session.move_notations(),
+            session.snapshots.clone(),
+            session.scores.clone(),
+            session.history_lines.clone(),
+        )
+    }
+
+    fn source() -> Session {
+        let mut session = Session::new(None).unwrap();
+        for mv in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+            play(&mut session, mv);
+        }
+        session
+    }
+
+    #[test]
+    fn forking_the_final_position() {
+        let source = source();
+        let fork = Fork::new(&source, None, Color::Black, 2).unwrap();
+        assert_eq!(fork.ply, 4);
+        assert_eq!(fork.start_fen, source.fen());
+        assert_eq!(fork.source_hash, source.game_hash());
+        assert!(fork.computer().color == Color::White);
+        assert_eq!(fork.computer().depth, 2);
+        assert_eq!(fork.session().unwrap().fen(), source.fen());
+    }
+
+    #[test]
+    fn forking_the_first_move() {
+        let source = source();
+        let fork = Fork::new(&source, Some(1), Color::White, 3).unwrap();
+        assert_eq!(fork.start_fen, source.snapshots[1]);
+        let practice = fork.session().unwrap();
+        // Black is to move after 1. e4, and nothing was played in the practice game yet
+        assert!(practice.board.get_curr_player() == Color::Black);
+        assert!(practice.history.is_empty());
+        assert_eq!(
+            fork.comment(),
+            format!("Practice from game {} after ply 1", source.game_hash())
+        );
+
+        assert!(Fork::new(&source, Some(5), Color::White, 3).is_err());
+    }
+
+    #[test]
+    fn abandoning_a_fork_leaves_the_source_game_alone() {
+        let mut source = source();
+        source.scores[2] = Some(35);
+        let before = record(&source);
+
+        let fork = Fork::new(&source, Some(2), Color::White, 3).unwrap();
+        let mut practice = fork.session().unwrap();
+        play(&mut practice, "d2d4");
+        play(&mut practice, "e5d4");
+        practice.undo();
+        // Given up halfway through
+        drop(practice);
+
+        assert_eq!(record(&source), before);
+        assert_eq!(source.game_hash(), fork.source_hash);
+    }
+}
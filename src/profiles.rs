@@ -0,0 +1,457 @@
+use crate::mouse::MouseBindings;
+use crate::piece_style::PieceStyles;
+use crate::{autosave, connection_book, interop, splitter, trainer};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The profile files from before there were profiles are moved into
+pub const DEFAULT_PROFILE: &str = "default";
+// Longest name a profile may have, it has to fit a button on the picker
+const MAX_NAME_LENGTH: usize = 24;
+// Everything of one person is in a directory of its own in here, named like the profile
+const PROFILES_DIR: &str = "profiles";
+// Deleted profiles are moved here, nothing is ever erased
+const TRASH_DIR: &str = "trash";
+// Shared by everyone, the profile picked last time
+const LAST_FILE: &str = "last-profile.txt";
+/// The settings of a profile, in its directory
+pub const SETTINGS_FILE: &str = "profile.json";
+// Files of one person that were kept right in the user data directory before profiles
+const LEGACY_FILES: [&str; 3] = [
+    autosave::FILE_NAME,
+    "autosave.json.moves",
+    splitter::FILE_NAME,
+];
+
+/// Where the files of one profile are kept. Under the user data directory the layout is
+///
+/// - `last-profile.txt`, shared: the profile picked last time
+/// - `profiles/<name>/`: everything of one person, the autosave, the layout, the connection
+///   book, the interop ledger, the best scores of the square trainer and `profile.json`
+/// - `trash/<name>/`: deleted profiles
+///
+/// Saved games go to the --save-dir everyone shares unless a profile has a directory of its
+/// own in its `profile.json`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProfilePaths {
+    dir: PathBuf,
+}
+
+impl ProfilePaths {
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[inline]
+    pub fn autosave(&self) -> PathBuf {
+        self.dir.join(autosave::FILE_NAME)
+    }
+
+    #[inline]
+    pub fn layout(&self) -> PathBuf {
+        self.dir.join(splitter::FILE_NAME)
+    }
+
+    #[inline]
+    pub fn settings(&self) -> PathBuf {
+        self.dir.join(SETTINGS_FILE)
+    }
+
+    #[inline]
+    pub fn connections(&self) -> PathBuf {
+        self.dir.join(connection_book::FILE_NAME)
+    }
+
+    #[inline]
+    pub fn interop(&self) -> PathBuf {
+        self.dir.join(interop::FILE_NAME)
+    }
+
+    #[inline]
+    pub fn trainer(&self) -> PathBuf {
+        self.dir.join(trainer::FILE_NAME)
+    }
+}
+
+/// What a profile keeps in its `profile.json`
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ProfileSettings {
+    // The color shown next to its name on the picker, an index into the picker's colors
+    pub swatch: Option<usize>,
+    // Where its finished games are saved, the shared --save-dir if None
+    pub save_dir: Option<PathBuf>,
+    // Set with --piece-tint, --piece-outline and --piece-badge, kept for the next run
+    pub piece_styles: PieceStyles,
+    // Set with --mouse, which button does what where
+    pub mouse: MouseBindings,
+    // Play under a random handle from the start of every run, so the name is never shown
+    pub anonymous: bool,
+}
+
+impl ProfileSettings {
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert(
+            "swatch".to_owned(),
+            self.swatch
+                .map_or(Value::Null, |swatch| Value::Number((swatch as u64).into())),
+        );
+        object.insert(
+            "save_dir".to_owned(),
+            self.save_dir.as_ref().map_or(Value::Null, |dir| {
+                Value::String(dir.to_string_lossy().into_owned())
+            }),
+        );
+        object.insert("piece_styles".to_owned(), self.piece_styles.to_json());
+        object.insert("mouse".to_owned(), self.mouse.to_json());
+        object.insert("anonymous".to_owned(), Value::Bool(self.anonymous));
+        Value::Object(object)
+    }
+
+    fn from_json(value: &Value) -> Self {
+        Self {
+            swatch: value
+                .get("swatch")
+                .and_then(Value::as_u64)
+                .map(|swatch| swatch as usize),
+            save_dir: value
+                .get("save_dir")
+                .and_then(Value::as_str)
+                .map(PathBuf::from),
+            piece_styles: value
+                .get("piece_styles")
+                .map_or_else(PieceStyles::default, PieceStyles::from_json),
+            mouse: value
+                .get("mouse")
+                .map_or_else(MouseBindings::default, MouseBindings::from_json),
+            anonymous: value
+                .get("anonymous")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        }
+    }
+
+    /// The settings of a profile, the defaults if it has none or they are damaged
+    pub fn read(paths: &ProfilePaths) -> Self {
+        let Ok(text) = fs::read_to_string(paths.settings()) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => Self::from_json(&value),
+            Err(err) => {
+                println!(
+                    "Ignoring the damaged profile settings {}: {}",
+                    paths.settings().display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn write(&self, paths: &ProfilePaths) -> Result<(), String> {
+        fs::create_dir_all(paths.dir())
+            .and_then(|()| {
+                fs::write(
+                    paths.settings(),
+                    serde_json::to_string(&self.to_json()).map_err(std::io::Error::from)?,
+                )
+            })
+            .map_err(|err| format!("Can't save the profile settings: {}", err))
+    }
+}
+
+/// The name as it is kept, or why it can't name a profile. Names are directory names, so
+/// nothing that could leave the profiles directory.
+pub fn check_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("A profile needs a name".to_owned());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "A profile name can't be longer than {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if name.starts_with('.')
+        || name
+            .chars()
+            .any(|c| c.is_control() || "/\\:*?\"<>|".contains(c))
+    {
+        return Err(format!("\"{}\" can't name a profile", name));
+    }
+    Ok(name)
+}
+
+/// The profiles kept in a user data directory
+pub struct Profiles {
+    root: PathBuf,
+}
+
+impl Profiles {
+    #[inline]
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    #[inline]
+    pub fn paths(&self, name: &str) -> ProfilePaths {
+        ProfilePaths {
+            dir: self.root.join(PROFILES_DIR).join(name),
+        }
+    }
+
+    /// Every profile by name in alphabetical order
+    pub fn list(&self) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(self.root.join(PROFILES_DIR)) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+    }
+
+    /// Move the files from before profiles into the default profile, the first time only.
+    /// True if there was anything to move.
+    pub fn migrate(&self) -> Result<bool, String> {
+        if self.root.join(PROFILES_DIR).exists() {
+            return Ok(false);
+        }
+        let paths = self.paths(DEFAULT_PROFILE);
+        fs::create_dir_all(paths.dir())
+            .map_err(|err| format!("Can't create the default profile: {}", err))?;
+        let mut moved = false;
+        for file in LEGACY_FILES {
+            let legacy = self.root.join(file);
+            if legacy.exists() {
+                fs::rename(&legacy, paths.dir().join(file))
+                    .map_err(|err| format!("Can't move {} into a profile: {}", file, err))?;
+                moved = true;
+            }
+        }
+        Ok(moved)
+    }
+
+    pub fn create(&self, name: &str) -> Result<ProfilePaths, String> {
+        let name = check_name(name)?;
+        let paths = self.paths(name);
+        if paths.dir().exists() {
+            return Err(format!("There already is a profile called {}", name));
+        }
+        fs::create_dir_all(paths.dir())
+            .map_err(|err| format!("Can't create the profile {}: {}", name, err))?;
+        Ok(paths)
+    }
+
+    /// Give a profile another name, its files go with it
+    pub fn rename(&self, from: &str, to: &str) -> Result<ProfilePaths, String> {
+        let to = check_name(to)?;
+        let (old, new) = (self.paths(from), self.paths(to));
+        if new.dir().exists() {
+            return Err(format!("There already is a profile called {}", to));
+        }
+        let was_last = self.last().as_deref() == Some(from);
+        fs::rename(old.dir(), new.dir())
+            .map_err(|err| format!("Can't rename the profile {}: {}", from, err))?;
+        if was_last {
+            self.remember(to);
+        }
+        Ok(new)
+    }
+
+    /// Move a profile to the trash, where it is kept next to any earlier one of the same name
+    pub fn delete(&self, name: &str) -> Result<PathBuf, String> {
+        let trash = self.root.join(TRASH_DIR);
+        fs::create_dir_all(&trash).map_err(|err| format!("Can't create the trash: {}", err))?;
+        let to = (1..)
+            .map(|number| match number {
+                1 => trash.join(name),
+                _ => trash.join(format!("{} ({})", name, number)),
+            })
+            .find(|to| !to.exists())
+            .expect("a free name in the trash");
+        fs::rename(self.paths(name).dir(), &to)
+            .map_err(|err| format!("Can't delete the profile {}: {}", name, err))?;
+        Ok(to)
+    }
+
+    /// The profile picked last time if it is still there
+    pub fn last(&self) -> Option<String> {
+        let name = fs::read_to_string(self.root.join(LAST_FILE)).ok()?;
+        let name = name.trim();
+        self.paths(name)
+            .dir()
+            .is_dir()
+            .then(|| name.to_owned())
+            .filter(|_| check_name(name).is_ok())
+    }
+
+    pub fn remember(&self, name: &str) {
+        let path = self.root.join(LAST_FILE);
+        if let Err(err) = fs::write(&path, name) {
+            println!("Can't remember the profile in {}: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    #[test]
+    fn every_file_of_a_profile_is_in_its_directory() {
+        let profiles = Profiles::new(PathBuf::from("data"));
+        let paths = profiles.paths("Alva");
+        let dir = Path::new("data").join("profiles").join("Alva");
+        assert_eq!(paths.dir(), dir);
+        for file in [
+            paths.autosave(),
+            paths.layout(),
+            paths.settings(),
+            paths.connections(),
+        ] {
+            assert_eq!(file.parent(), Some(dir.as_path()));
+        }
+        assert_ne!(profiles.paths("Bo").autosave(), paths.autosave());
+
+        assert_eq!(check_name("  Alva "), Ok("Alva"));
+        for name in ["", "  ", "../up", "a/b", ".hidden", "a\\b"] {
+            assert!(check_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn files_from_before_profiles_move_into_the_default_one_once() {
+        // A user data directory of its own
+        let scratch = Scratch::new("profiles-migrate");
+        let root = scratch.path().to_owned();
+        fs::write(root.join(autosave::FILE_NAME), "saved").unwrap();
+        fs::write(root.join(splitter::FILE_NAME), "layout").unwrap();
+        let profiles = Profiles::new(root.clone());
+
+        assert_eq!(profiles.migrate(), Ok(true));
+        let default = profiles.paths(DEFAULT_PROFILE);
+        assert_eq!(fs::read_to_string(default.autosave()).unwrap(), "saved");
+        assert_eq!(fs::read_to_string(default.layout()).unwrap(), "layout");
+        assert!(!root.join(autosave::FILE_NAME).exists());
+        assert_eq!(profiles.list(), [DEFAULT_PROFILE]);
+
+        // A file written where the old ones were later isn't taken for one
+        fs::write(root.join(autosave::FILE_NAME), "newer").unwrap();
+        assert_eq!(profiles.migrate(), Ok(false));
+        assert_eq!(fs::read_to_string(default.autosave()).unwrap(), "saved");
+    }
+
+    #[test]
+    fn renamed_and_deleted_profiles_keep_their_files() {
+        // A user data directory of its own
+        let scratch = Scratch::new("profiles-rename");
+        let root = scratch.path().to_owned();
+        let profiles = Profiles::new(root.clone());
+        let paths = profiles.create("Alva").unwrap();
+        fs::write(paths.autosave(), "saved").unwrap();
+        profiles.remember("Alva");
+        assert!(profiles.create("Alva").is_err());
+
+        let renamed = profiles.rename("Alva", "Alva B").unwrap();
+        assert_eq!(fs::read_to_string(renamed.autosave()).unwrap(), "saved");
+        assert!(!paths.dir().exists());
+        assert_eq!(profiles.last().as_deref(), Some("Alva B"));
+        profiles.create("Bo").unwrap();
+        assert!(profiles.rename("Bo", "Alva B").is_err());
+
+        let trashed = profiles.delete("Alva B").unwrap();
+        assert_eq!(
+            fs::read_to_string(trashed.join(autosave::FILE_NAME)).unwrap(),
+            "saved"
+        );
+        assert_eq!(profiles.list(), ["Bo"]);
+        assert_eq!(profiles.last(), None);
+        // Deleting one of the same name again keeps both in the trash
+        profiles.create("Alva B").unwrap();
+        assert_ne!(profiles.delete("Alva B").unwrap(), trashed);
+        assert!(trashed.exists());
+    }
+
+    #[test]
+    fn profiles_keep_their_settings_apart() {
+        use crate::mouse::{MouseAction, Region};
+        use crate::piece_style::{Rgb, SideStyle};
+        use ggez::event::MouseButton;
+
+        // A user data directory of its own
+        let scratch = Scratch::new("profiles-settings");
+        let root = scratch.path().to_owned();
+        let profiles = Profiles::new(root.clone());
+        let (alva, bo) = (
+            profiles.create("Alva").unwrap(),
+            profiles.create("Bo").unwrap(),
+        );
+        let alva_settings = ProfileSettings {
+            swatch: Some(2),
+            save_dir: Some(PathBuf::from("games/alva")),
+            piece_styles: PieceStyles::default(),
+            mouse: MouseBindings::default(),
+            anonymous: true,
+        };
+        alva_settings.write(&alva).unwrap();
+        assert_eq!(ProfileSettings::read(&bo), ProfileSettings::default());
+
+        let bo_settings = ProfileSettings {
+            swatch: Some(4),
+            save_dir: None,
+            piece_styles: PieceStyles {
+                black: SideStyle {
+                    tint: Some(Rgb(0x1a, 0x2b, 0x6d)),
+                    outline: Some(Rgb(255, 255, 255)),
+                    badge: None,
+                },
+                ..PieceStyles::default()
+            },
+            mouse: {
+                let mut mouse = MouseBindings::default();
+                mouse.bind(Region::Board, MouseButton::Left, Some(MouseAction::Cancel));
+                mouse.bind(Region::Board, MouseButton::Right, Some(MouseAction::Select));
+                mouse
+            },
+            anonymous: false,
+        };
+        bo_settings.write(&bo).unwrap();
+        assert_eq!(ProfileSettings::read(&alva), alva_settings);
+        assert_eq!(ProfileSettings::read(&bo), bo_settings);
+    }
+
+    #[test]
+    fn playing_anonymously_leaves_the_profile_as_it_was() {
+        use crate::identity::{self, Identity};
+
+        // A user data directory of its own
+        let scratch = Scratch::new("profiles-anonymous");
+        let root = scratch.path().to_owned();
+        let profiles = Profiles::new(root.clone());
+        let alva = profiles.create("Alva").unwrap();
+        let settings = ProfileSettings {
+            anonymous: true,
+            ..ProfileSettings::default()
+        };
+        settings.write(&alva).unwrap();
+
+        // What a run playing as Alva does with the setting
+        let read = ProfileSettings::read(&alva);
+        let identity = read.anonymous.then(Identity::random).unwrap();
+        let shown = identity::shown_name(Some(&identity), "Alva");
+        assert_eq!(shown, identity.handle);
+        assert_ne!(shown, "Alva");
+        assert_eq!(profiles.list(), ["Alva"]);
+        assert_eq!(profiles.paths("Alva").dir(), alva.dir());
+        assert_eq!(ProfileSettings::read(&alva), settings);
+    }
+}
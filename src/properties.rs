@@ -0,0 +1,385 @@
+//! Properties of the conversions between the engine, the board arrays, FEN and the protocol,
+//! checked on generated cases. Every interop bug so far was in one of these layers, and
+//! examples only cover the corners someone thought of.
+//!
+//! A failing case is shrunk before it is reported, so the message shows the smallest board,
+//! line of moves or string that still fails. The normal run checks `CASES` of each, the ignored
+//! `the_conversions_hold_for_many_more_cases` checks `MANY_CASES` with
+//! `cargo test -- --ignored`.
+
+use crate::network::{
+    internal_to_network_board, internal_to_network_move, internal_to_network_piece, network_fen,
+    network_to_internal_board, network_to_internal_move, BoardCoord, NetworkCoord,
+};
+use crate::{parse_fen, parse_move, to_cordinate, Random, Square};
+use jonathan_hallstrom_chess::{Board, Color};
+use std::panic::{self, AssertUnwindSafe};
+
+// Cases of every property in the normal run
+const CASES: usize = 256;
+const MANY_CASES: usize = 20_000;
+// Plies of the lines played from the start
+const MAX_LINE: usize = 120;
+const MAX_STRING: usize = 40;
+
+/// How cases of one kind are made, made smaller and shown
+trait Generate {
+    type Case: Clone;
+    fn generate(&self, random: &mut Random) -> Self::Case;
+    // Cases a little smaller than `case`, the first that still fails is kept
+    fn shrink(&self, case: &Self::Case) -> Vec<Self::Case>;
+    fn show(&self, case: &Self::Case) -> String;
+}
+
+/// Check `property` on `cases` generated cases, panicking with the smallest failing one
+fn check<G: Generate>(
+    name: &str,
+    generator: &G,
+    cases: usize,
+    property: impl Fn(&G::Case) -> Result<(), String>,
+) {
+    // A panic is a failure like any other, and is shrunk the same way
+    let fails = |case: &G::Case| -> Option<String> {
+        match panic::catch_unwind(AssertUnwindSafe(|| property(case))) {
+            Ok(result) => result.err(),
+            Err(payload) => Some(format!(
+                "panicked: {}",
+                payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default()
+            )),
+        }
+    };
+    let mut random = Random::new(0x2545_f491_4f6c_dd1d ^ name.len() as u64);
+    for number in 0..cases {
+        let mut case = generator.generate(&mut random);
+        let Some(mut failure) = fails(&case) else {
+            continue;
+        };
+        while let Some((smaller, still)) = generator
+            .shrink(&case)
+            .into_iter()
+            .find_map(|smaller| fails(&smaller).map(|failure| (smaller, failure)))
+        {
+            case = smaller;
+            failure = still;
+        }
+        panic!(
+            "{} fails for case {}, shrunk to {}: {}",
+            name,
+            number,
+            generator.show(&case),
+            failure
+        );
+    }
+}
+
+/// Valid board arrays: a king of each color, and no pawns on the first or last rank
+struct Boards;
+
+// What a generated square may hold besides a king
+const MEN: [fn(Color) -> Square; 5] = [
+    Square::Pawn,
+    Square::Knight,
+    Square::Bishop,
+    Square::Rook,
+    Square::Queen,
+];
+
+/// The placement field of the FEN of `squares`, written the way the server's boards are
+fn placement(squares: &[[Square; 8]; 8]) -> String {
+    let fen = network_fen(&internal_to_network_board(squares), &[], Color::White);
+    fen.split_whitespace().next().unwrap_or_default().to_owned()
+}
+
+impl Generate for Boards {
+    type Case = [[Square; 8]; 8];
+
+    fn generate(&self, random: &mut Random) -> Self::Case {
+        let mut squares = [[Square::Empty; 8]; 8];
+        let density = random.below(60);
+        for (row, rank) in squares.iter_mut().enumerate() {
+            for square in rank.iter_mut() {
+                if !random.chance(density) {
+                    continue;
+                }
+                let color = match random.chance(50) {
+                    true => Color::White,
+                    false => Color::Black,
+                };
+                let man = match row {
+                    0 | 7 => 1 + random.below(MEN.len() - 1),
+                    _ => random.below(MEN.len()),
+                };
+                *square = MEN[man](color);
+            }
+        }
+        for color in [Color::White, Color::Black] {
+            loop {
+                let (row, col) = (random.below(8), random.below(8));
+                if !matches!(squares[row][col], Square::King(_)) {
+                    squares[row][col] = Square::King(color);
+                    break;
+                }
+            }
+        }
+        squares
+    }
+
+    fn shrink(&self, case: &Self::Case) -> Vec<Self::Case> {
+        (0..64)
+            .map(|index| (index / 8, index % 8))
+            .filter(|&(row, col)| !matches!(case[row][col], Square::Empty | Square::King(_)))
+            .map(|(row, col)| {
+                let mut smaller = *case;
+                smaller[row][col] = Square::Empty;
+                smaller
+            })
+            .collect()
+    }
+
+    fn show(&self, case: &Self::Case) -> String {
+        placement(case)
+    }
+}
+
+/// Legal positions, reached by playing a line of moves from the start. A line is kept as
+/// the index of every move in the legal moves of its position, so any part of it still
+/// plays.
+struct Lines;
+
+/// The position after `line`, with the moves that got there
+fn play_line(line: &[usize]) -> (Board, Vec<String>) {
+    let mut board = Board::default();
+    let mut played = Vec::new();
+    for choice in line {
+        let moves = board.get_legal_moves();
+        if moves.is_empty() {
+            break;
+        }
+        let mv = moves[choice % moves.len()];
+        played.push(mv.to_algebraic_notation());
+        board.play_move(mv).unwrap();
+    }
+    (board, played)
+}
+
+impl Generate for Lines {
+    type Case = Vec<usize>;
+
+    fn generate(&self, random: &mut Random) -> Self::Case {
+        (0..random.below(MAX_LINE))
+            .map(|_| random.below(usize::MAX))
+            .collect()
+    }
+
+    fn shrink(&self, case: &Self::Case) -> Vec<Self::Case> {
+        let mut smaller = vec![case[..case.len() / 2].to_vec()];
+        smaller.extend((0..case.len()).rev().map(|index| {
+            let mut shorter = case.clone();
+            shorter.remove(index);
+            shorter
+        }));
+        smaller.retain(|shorter| shorter.len() < case.len());
+        smaller
+    }
+
+    fn show(&self, case: &Self::Case) -> String {
+        let (board, played) = play_line(case);
+        format!("\"{}\" after {}", board.to_fen(), played.join(" "))
+    }
+}
+
+/// Arbitrary strings, leaning towards what the parsers look for so they get past the first
+/// check now and then
+struct Strings;
+
+const ALPHABET: &str = "0123456789abcdefghKQRBNPkqrbnpwx/ -+:.;=[]{}()\"'\\#veE\n\t";
+
+impl Generate for Strings {
+    type Case = String;
+
+    fn generate(&self, random: &mut Random) -> Self::Case {
+        let mut text = String::new();
+        let length = random.below(MAX_STRING);
+        while text.chars().count() < length {
+            match random.below(10) {
+                // Long numbers, which overflow whatever they are read into
+                0 => text.extend(
+                    (0..1 + random.below(30)).map(|_| char::from(b'0' + random.below(10) as u8)),
+                ),
+                1 => text.push(char::from_u32(random.below(0x11_0000) as u32).unwrap_or('é')),
+                _ => text.push(ALPHABET.as_bytes()[random.below(ALPHABET.len())] as char),
+            }
+        }
+        text
+    }
+
+    fn shrink(&self, case: &Self::Case) -> Vec<Self::Case> {
+        let chars: Vec<char> = case.chars().collect();
+        let mut smaller = vec![
+            chars[..chars.len() / 2].iter().collect(),
+            chars[chars.len() / 2..].iter().collect(),
+        ];
+        smaller.extend((0..chars.len()).map(|index| {
+            let mut shorter = chars.clone();
+            shorter.remove(index);
+            shorter.into_iter().collect::<String>()
+        }));
+        smaller.retain(|shorter: &String| shorter.len() < case.len());
+        smaller
+    }
+
+    fn show(&self, case: &Self::Case) -> String {
+        format!("{:?}", case)
+    }
+}
+
+fn board_round_trip(cases: usize) {
+    check(
+        "board to network board and back",
+        &Boards,
+        cases,
+        |squares| {
+            let network = internal_to_network_board(squares);
+            if network_to_internal_board(&network) != *squares {
+                return Err("the board came back different".to_owned());
+            }
+            for (x, y) in (0..64).map(|index| (index % 8, index / 8)) {
+                if network[y][x] != internal_to_network_piece(&squares[7 - y][x]) {
+                    return Err(format!("({}, {}) isn't the internal board's square", x, y));
+                }
+            }
+            Ok(())
+        },
+    );
+}
+
+fn fen_round_trip(cases: usize) {
+    check("FEN to squares and back", &Boards, cases, |squares| {
+        let fen = placement(squares);
+        let parsed = parse_fen(&fen).map_err(|err| err.to_string())?;
+        match parsed == *squares && placement(&parsed) == fen {
+            true => Ok(()),
+            false => Err(format!("read back as {}", placement(&parsed))),
+        }
+    });
+    check("engine FEN to squares and back", &Lines, cases, |line| {
+        let fen = play_line(line).0.to_fen();
+        let squares = parse_fen(&fen).map_err(|err| err.to_string())?;
+        let engine_placement = fen.split_whitespace().next().unwrap_or_default();
+        match placement(&squares) == engine_placement {
+            true => Ok(()),
+            false => Err(format!("written as {}", placement(&squares))),
+        }
+    });
+}
+
+fn move_round_trip(cases: usize) {
+    check("move to network move and back", &Lines, cases, |line| {
+        let moves = play_line(line).0.get_legal_moves();
+        for mv in &moves {
+            let network = internal_to_network_move(mv).map_err(|err| err.to_string())?;
+            let matched = network_to_internal_move(&network, &moves)
+                .map(|matched| matched.to_algebraic_notation());
+            if matched.as_deref() != Some(mv.to_algebraic_notation().as_str()) {
+                return Err(format!(
+                    "{} came back as {:?}",
+                    mv.to_algebraic_notation(),
+                    matched
+                ));
+            }
+        }
+        Ok(())
+    });
+}
+
+fn parsers_never_panic(cases: usize) {
+    use crate::{
+        bookmarks, clock, config, connectivity, engine_match, game_list, hook, layout, negotiation,
+        notation, pgn, piece_style, traps, tty_mirror, updates,
+    };
+    check("parsers on any string", &Strings, cases, |text| {
+        let _ = parse_fen(text);
+        let _ = crate::board_from_fen(text);
+        let _ = parse_move(text);
+        let _ = config::parse_address(text);
+        let _ = clock::TimeControl::parse(text);
+        let _ = negotiation::Negotiation::parse(text, 0);
+        let _ = hook::parse_answer(text);
+        let _ = pgn::read_pgn(text);
+        let _ = pgn::split_comment(text, 0);
+        let _ = bookmarks::parse_comment(text);
+        let _ = game_list::read_headers(text);
+        let _ = traps::parse(text);
+        let _ = engine_match::parse_levels(text);
+        let _ = connectivity::decode_pairing_code(text, std::net::Ipv4Addr::new(192, 168, 1, 7));
+        let _ = connectivity::host_address(text, None);
+        let _ = piece_style::Rgb::parse(text);
+        let _ = updates::Version::parse(text);
+        let _ = updates::UpdateSwitch::parse(text);
+        let _ = notation::Language::parse(text);
+        let _ = notation::Notation::parse(text);
+        let _ = layout::LayoutMode::parse(text);
+        let _ = tty_mirror::TtyOrientation::parse(text);
+        Ok(())
+    });
+}
+
+#[test]
+fn the_board_survives_the_network_orientation() {
+    board_round_trip(CASES);
+}
+
+#[test]
+fn fens_read_back_as_they_were_written() {
+    fen_round_trip(CASES);
+}
+
+#[test]
+fn moves_survive_the_network() {
+    move_round_trip(CASES);
+}
+
+#[test]
+fn no_parser_panics() {
+    parsers_never_panic(CASES);
+}
+
+/// Every square, so nothing is left to chance
+#[test]
+fn the_square_conversions_agree() {
+    for (file, rank) in (0..64).map(|index| (index % 8, index / 8)) {
+        let (file_letter, rank_digit) =
+            (char::from(b'a' + file as u8), char::from(b'1' + rank as u8));
+        let square = BoardCoord::from(to_cordinate(file_letter, rank_digit).unwrap());
+        assert_eq!(
+            square,
+            BoardCoord {
+                row: 7 - rank,
+                col: file
+            }
+        );
+        assert_eq!(square.to_network(), NetworkCoord { x: file, y: rank });
+        assert_eq!(square.to_network().to_board(), Some(square));
+        let notation = format!("{}{}a1", file_letter, rank_digit);
+        assert_eq!(
+            parse_move(&notation),
+            Ok(((square.row, square.col), (7, 0)))
+        );
+    }
+    for (x, y) in [(8, 0), (0, 8), (usize::MAX, 3)] {
+        assert_eq!(NetworkCoord { x, y }.to_board(), None);
+    }
+}
+
+#[test]
+#[ignore = "takes minutes, run with --ignored"]
+fn the_conversions_hold_for_many_more_cases() {
+    board_round_trip(MANY_CASES);
+    fen_round_trip(MANY_CASES);
+    move_round_trip(MANY_CASES);
+    parsers_never_panic(MANY_CASES);
+}
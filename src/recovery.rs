@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+/// How long "Restoring graphics" shows over the window after the GPU resources are rebuilt
+pub const RESTORE_FILM: Duration = Duration::from_secs(1);
+/// Rebuilds in a row without a frame getting through before the game is saved and closed
+pub const MAX_REBUILDS: u32 = 3;
+// What wgpu says when the surface no longer fits the window or the device is gone, which is
+// what waking from sleep tends to give. ggez passes it on as the text of a render error.
+const LOSS_WORDS: [&str; 3] = ["lost", "outdated", "timeout"];
+
+/// What a render error lost
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Loss {
+    // The surface the frames go to, configured again for the window it comes back
+    Surface,
+    // The device itself, which ggez can't make again for a window it already has
+    Device,
+}
+
+/// What a render error lost, None for a mistake of the game's that rebuilding won't fix.
+/// Only whole words count, a path or a name with "lost" in it is no loss.
+pub fn loss(message: &str) -> Option<Loss> {
+    let message = message.to_lowercase();
+    let words = || message.split(|c: char| !c.is_alphanumeric());
+    if !words().any(|word| LOSS_WORDS.contains(&word)) {
+        return None;
+    }
+    match words().any(|word| word == "device") {
+        true => Some(Loss::Device),
+        false => Some(Loss::Surface),
+    }
+}
+
+/// What a failed frame calls for
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Response {
+    // Configure the surface and build everything on the GPU again, the game goes on as if
+    // nothing happened
+    Rebuild,
+    // The device is gone or rebuilding didn't bring the frames back, save the game and close
+    // the window
+    GiveUp,
+    // Nothing was lost, it ends the game like any other error
+    Fatal,
+}
+
+/// Follows the frames through a lost surface. Every loss rebuilds, until `MAX_REBUILDS` in a
+/// row went by without a frame getting through. A lost device gives up right away.
+#[derive(Default)]
+pub struct Recovery {
+    // Rebuilds since the last frame that was drawn
+    attempts: u32,
+    // When the last rebuild was, for the film
+    rebuilt: Option<Duration>,
+}
+
+impl Recovery {
+    /// A frame failed at `now`, having lost `loss`
+    pub fn failed(&mut self, loss: Option<Loss>, now: Duration) -> Response {
+        match loss {
+            None => return Response::Fatal,
+            Some(Loss::Device) => return Response::GiveUp,
+            Some(Loss::Surface) if self.attempts == MAX_REBUILDS => return Response::GiveUp,
+            Some(Loss::Surface) => {}
+        }
+        self.attempts += 1;
+        self.rebuilt = Some(now);
+        Response::Rebuild
+    }
+
+    /// A frame was drawn, the device is back if it was lost
+    #[inline]
+    pub fn drawn(&mut self) {
+        self.attempts = 0;
+    }
+
+    /// Whether "Restoring graphics" shows at `now`
+    #[inline]
+    pub fn restoring(&self, now: Duration) -> bool {
+        self.rebuilt
+            .is_some_and(|rebuilt| now.saturating_sub(rebuilt) < RESTORE_FILM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_lost_surface_is_rebuilt() {
+        assert_eq!(loss("Surface error: Lost"), Some(Loss::Surface));
+        assert_eq!(loss("The surface is Outdated"), Some(Loss::Surface));
+        assert_eq!(loss("Timeout"), Some(Loss::Surface));
+        assert_eq!(loss("Parent device is lost"), Some(Loss::Device));
+        assert_eq!(loss("Can't load the image: not found"), None);
+        // Words with a loss word in them are something else
+        assert_eq!(loss("Can't load /mnt/lostandfound/pieces.png"), None);
+        assert_eq!(loss("Unknown option timeouts in the shader"), None);
+
+        let mut recovery = Recovery::default();
+        assert_eq!(recovery.failed(None, Duration::ZERO), Response::Fatal);
+        assert!(!recovery.restoring(Duration::ZERO));
+        // The device can't be made again on the same window
+        assert_eq!(
+            recovery.failed(Some(Loss::Device), Duration::ZERO),
+            Response::GiveUp
+        );
+        assert!(!recovery.restoring(Duration::ZERO));
+    }
+
+    #[test]
+    fn rebuilding_gives_up_once_no_frame_gets_through() {
+        let mut recovery = Recovery::default();
+        let second = Duration::from_secs(1);
+        for _ in 0..MAX_REBUILDS {
+            assert_eq!(
+                recovery.failed(Some(Loss::Surface), second),
+                Response::Rebuild
+            );
+        }
+        assert_eq!(
+            recovery.failed(Some(Loss::Surface), second),
+            Response::GiveUp
+        );
+
+        // A frame in between and it starts over
+        recovery.drawn();
+        assert_eq!(
+            recovery.failed(Some(Loss::Surface), second),
+            Response::Rebuild
+        );
+    }
+
+    #[test]
+    fn the_film_shows_for_a_second_after_a_rebuild() {
+        let mut recovery = Recovery::default();
+        let at = Duration::from_secs(10);
+        recovery.failed(Some(Loss::Surface), at);
+        assert!(recovery.restoring(at));
+        assert!(recovery.restoring(at + RESTORE_FILM / 2));
+        assert!(!recovery.restoring(at + RESTORE_FILM));
+        // Drawing doesn't cut it short
+        recovery.drawn();
+        assert!(recovery.restoring(at));
+    }
+}
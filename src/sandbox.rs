@@ -0,0 +1,295 @@
+use crate::network::{internal_to_network_board, network_fen};
+use crate::session::Session;
+use crate::{board_from_fen, parse_fen, position_problem, tty_mirror, PositionProblem, Square};
+use jonathan_hallstrom_chess::Color;
+use std::fmt;
+
+/// The pieces of a sandbox position, indexed like the board's squares
+pub type Squares = [[Square; 8]; 8];
+// Where a sandbox opened from the start screen begins
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// The first rule a sandbox position breaks, so it can't be played or analyzed from
+#[derive(Eq, PartialEq, Clone)]
+pub enum Problem {
+    // A rule of chess the position breaks
+    Position(PositionProblem),
+    // The engine turned the position down for a reason of its own
+    Engine(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Position(problem) => write!(f, "{}", problem),
+            Problem::Engine(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// A board the pieces are put anywhere on, without rules or turns, to show ideas no game
+/// reaches. Every change keeps the squares before it for undo. Nothing in it is a game: it
+/// has no engine board, and only a position that passes `check` leaves it, as FEN.
+pub struct Sandbox {
+    pub squares: Squares,
+    // Who is to move once the position is played or analyzed, moving pieces never changes it
+    pub to_move: Color,
+    undone: Vec<Squares>,
+    redone: Vec<Squares>,
+}
+
+impl Sandbox {
+    pub fn new(squares: Squares, to_move: Color) -> Self {
+        Self {
+            squares,
+            to_move,
+            undone: Vec::new(),
+            redone: Vec::new(),
+        }
+    }
+
+    /// The pieces where a game starts, White to move
+    pub fn standard() -> Self {
+        Self::new(parse_fen(START).unwrap(), Color::White)
+    }
+
+    /// The position after `ply` of a game, copied so the game can't be changed through it
+    pub fn at(session: &Session, ply: usize) -> Option<Self> {
+        let fen = session.snapshots.get(ply)?;
+        let to_move = match fen.split_whitespace().nth(1) {
+            Some("b") => Color::Black,
+            _ => Color::White,
+        };
+        Some(Self::new(parse_fen(fen).ok()?, to_move))
+    }
+
+    /// Change the squares, keeping them as they were for undo if anything changed
+    fn change(&mut self, change: impl FnOnce(&mut Squares)) {
+        let before = self.squares;
+        change(&mut self.squares);
+        if self.squares != before {
+            self.undone.push(before);
+            self.redone.clear();
+        }
+    }
+
+    /// Put the piece on `from` on `to`, whatever was there is taken off the board and returned.
+    /// An empty square has nothing to move.
+    pub fn relocate(&mut self, from: (usize, usize), to: (usize, usize)) -> Option<Square> {
+        let piece = self.squares[from.0][from.1];
+        if piece == Square::Empty || from == to {
+            return None;
+        }
+        let taken = self.squares[to.0][to.1];
+        self.change(|squares| {
+            squares[to.0][to.1] = piece;
+            squares[from.0][from.1] = Square::Empty;
+        });
+        (taken != Square::Empty).then_some(taken)
+    }
+
+    /// Put `piece` on a square, Square::Empty takes off what is there
+    pub fn place(&mut self, (row, col): (usize, usize), piece: Square) {
+        self.change(|squares| squares[row][col] = piece);
+    }
+
+    /// Take every piece off the board
+    pub fn clear(&mut self) {
+        self.change(|squares| *squares = [[Square::Empty; 8]; 8]);
+    }
+
+    /// Go back to the squares before the last change, false if there is none
+    pub fn undo(&mut self) -> bool {
+        let Some(before) = self.undone.pop() else {
+            return false;
+        };
+        self.redone
+            .push(std::mem::replace(&mut self.squares, before));
+        true
+    }
+
+    /// Make the last undone change again, false if there is none
+    pub fn redo(&mut self) -> bool {
+        let Some(after) = self.redone.pop() else {
+            return false;
+        };
+        self.undone
+            .push(std::mem::replace(&mut self.squares, after));
+        true
+    }
+
+    /// The position as FEN, with the castling a king and rook on their squares allow
+    #[inline]
+    pub fn fen(&self) -> String {
+        network_fen(&internal_to_network_board(&self.squares), &[], self.to_move)
+    }
+
+    /// The FEN of the position if it can be played from, or the first rule it breaks
+    pub fn check(&self) -> Result<String, Problem> {
+        if let Some(problem) = position_problem(&self.squares, self.to_move) {
+            return Err(Problem::Position(problem));
+        }
+        let fen = self.fen();
+        board_from_fen(&fen).map_err(Problem::Engine)?;
+        Ok(fen)
+    }
+
+    /// What exporting writes, as the file's extension and its text: the FEN of a position that
+    /// can be played from, else a diagram of the board and what is wrong with it
+    pub fn export(&self) -> (&'static str, String) {
+        match self.check() {
+            Ok(fen) => ("fen", fen + "\n"),
+            Err(problem) => (
+                "txt",
+                format!(
+                    "{}\n{}\n",
+                    tty_mirror::render_board(&self.squares, false, false),
+                    problem
+                ),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY: Squares = [[Square::Empty; 8]; 8];
+
+    /// Kings on e1 and e8 and whatever else is given
+    fn kings_and(pieces: &[((usize, usize), Square)]) -> Sandbox {
+        let mut squares = EMPTY;
+        squares[7][4] = Square::King(Color::White);
+        squares[0][4] = Square::King(Color::Black);
+        for ((row, col), piece) in pieces {
+            squares[*row][*col] = *piece;
+        }
+        Sandbox::new(squares, Color::White)
+    }
+
+    #[test]
+    fn pieces_go_anywhere_and_take_what_is_there() {
+        let rook = Square::Rook(Color::White);
+        let knight = Square::Knight(Color::Black);
+        let mut sandbox = kings_and(&[((7, 0), rook), ((2, 5), knight)]);
+
+        // Not a rook's move, and onto a piece of either color
+        assert!(sandbox.relocate((7, 0), (2, 5)) == Some(knight));
+        assert!(sandbox.squares[2][5] == rook);
+        assert!(sandbox.squares[7][0] == Square::Empty);
+        assert!(sandbox.relocate((2, 5), (0, 4)) == Some(Square::King(Color::Black)));
+        // Nor does the turn pass
+        assert!(sandbox.to_move == Color::White);
+
+        assert!(sandbox.relocate((4, 4), (3, 3)).is_none());
+        assert!(sandbox.squares[3][3] == Square::Empty);
+        sandbox.place((4, 4), Square::Queen(Color::Black));
+        sandbox.place((7, 4), Square::Empty);
+        assert!(sandbox.squares[4][4] == Square::Queen(Color::Black));
+        assert!(sandbox.squares[7][4] == Square::Empty);
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_changes() {
+        let mut sandbox = kings_and(&[]);
+        let start = sandbox.squares;
+        assert!(!sandbox.undo());
+
+        sandbox.relocate((7, 4), (6, 4));
+        let moved = sandbox.squares;
+        sandbox.place((3, 3), Square::Pawn(Color::Black));
+        // Changes that change nothing aren't kept
+        sandbox.relocate((5, 5), (4, 4));
+        sandbox.place((3, 3), Square::Pawn(Color::Black));
+
+        assert!(sandbox.undo());
+        assert!(sandbox.squares == moved);
+        assert!(sandbox.undo());
+        assert!(sandbox.squares == start);
+        assert!(!sandbox.undo());
+        assert!(sandbox.redo());
+        assert!(sandbox.squares == moved);
+
+        // A new change after undoing drops what was undone
+        sandbox.clear();
+        assert!(sandbox.squares == EMPTY);
+        assert!(!sandbox.redo());
+        assert!(sandbox.undo());
+        assert!(sandbox.squares == moved);
+    }
+
+    #[test]
+    fn positions_that_break_a_rule_say_which() {
+        let mut sandbox = kings_and(&[]);
+        sandbox.place((0, 4), Square::Empty);
+        assert!(sandbox.check() == Err(Problem::Position(PositionProblem::Kings(Color::Black, 0))));
+        assert_eq!(
+            sandbox.check().err().unwrap().to_string(),
+            "Black has no king"
+        );
+        sandbox.place((0, 0), Square::King(Color::Black));
+        sandbox.place((0, 7), Square::King(Color::Black));
+        assert!(sandbox.check() == Err(Problem::Position(PositionProblem::Kings(Color::Black, 2))));
+
+        let sandbox = kings_and(&[((0, 2), Square::Pawn(Color::White))]);
+        assert!(sandbox.check() == Err(Problem::Position(PositionProblem::PawnOnEdge((0, 2)))));
+        assert_eq!(
+            sandbox.check().err().unwrap().to_string(),
+            "The pawn on c8 is on the first or last rank"
+        );
+
+        // White to move with Black's king attacked
+        let sandbox = kings_and(&[((3, 4), Square::Rook(Color::White))]);
+        assert!(
+            sandbox.check()
+                == Err(Problem::Position(PositionProblem::WaitingInCheck(
+                    Color::Black
+                )))
+        );
+
+        let (extension, text) = sandbox.export();
+        assert_eq!(extension, "txt");
+        assert!(
+            text.ends_with("Black is in check but it is White's move\n"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn a_legal_position_leaves_as_fen() {
+        let mut sandbox = kings_and(&[((7, 7), Square::Rook(Color::White))]);
+        sandbox.to_move = Color::Black;
+        assert_eq!(
+            sandbox.check().ok().as_deref(),
+            Some("4k3/8/8/8/8/8/8/4K2R b K - 0 1")
+        );
+        assert_eq!(sandbox.export().0, "fen");
+    }
+
+    #[test]
+    fn the_game_a_sandbox_is_opened_from_stays_as_it_was() {
+        let mut session = Session::new(None).unwrap();
+        let mv = *session
+            .board_repr
+            .moves
+            .iter()
+            .find(|mv| mv.to_algebraic_notation() == "e2e4")
+            .unwrap();
+        session.apply_move(mv);
+        let (fen, squares) = (session.board.to_fen(), session.board_repr.squares);
+
+        let mut sandbox = Sandbox::at(&session, 1).unwrap();
+        assert!(sandbox.to_move == Color::Black);
+        assert!(sandbox.squares == squares);
+        sandbox.relocate((7, 3), (1, 4));
+        sandbox.clear();
+        drop(sandbox);
+
+        assert_eq!(session.board.to_fen(), fen);
+        assert!(session.board_repr.squares == squares);
+        assert_eq!(session.history.len(), 1);
+        assert!(Sandbox::at(&session, 2).is_none());
+    }
+}
@@ -0,0 +1,494 @@
+use crate::menu::grown;
+use crate::theme::Theme;
+use crate::{rect, CanvasSink, Graphics, Render, Restore, TextLine};
+use chess_gui::engine::Analysis;
+use chess_gui::layout::Area;
+use chess_gui::piece_style::PieceStyles;
+use chess_gui::sandbox::Sandbox;
+use chess_gui::scene::{self, BoardScene, Overlay};
+use chess_gui::{opposite_color, pgn, updates, Square};
+use ggez::graphics::{self, Canvas, Rect};
+use ggez::input::keyboard::{KeyCode, KeyMods};
+use ggez::{Context, GameResult};
+use jonathan_hallstrom_chess::Color;
+use mint::Point2;
+use std::path::PathBuf;
+
+// Around the board, so a sandbox can't be taken for a game
+const SANDBOX_COLOR: graphics::Color = graphics::Color::new(0.8, 0.3, 0.85, 1.0);
+const BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const CHOSEN_BUTTON_COLOR: graphics::Color = graphics::Color::new(0.7, 0.9, 0.7, 1.0);
+const TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const ERROR_TEXT_COLOR: graphics::Color = graphics::Color::new(0.7, 0.0, 0.0, 1.0);
+// How far the border reaches past the board, of the board's side
+const BORDER: f32 = 0.03;
+// The pieces of the palette, a row for each side
+const MEN: [fn(Color) -> Square; 6] = [
+    Square::King,
+    Square::Queen,
+    Square::Rook,
+    Square::Bishop,
+    Square::Knight,
+    Square::Pawn,
+];
+
+/// The buttons beside and under the board
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Button {
+    ToMove,
+    Erase,
+    Clear,
+    Undo,
+    Redo,
+    Analyze,
+    PlayComputer,
+    PlayBoth,
+    Export,
+    Back,
+}
+
+/// What a click on the board does
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Tool {
+    // Pick a piece up and put it down anywhere
+    Move,
+    Place(Square),
+    Erase,
+}
+
+/// Where everything in the sandbox is drawn, relative to the window size
+struct SandboxLayout {
+    board: Area,
+    title: Rect,
+    // Who is to move and what the last action said
+    status: Rect,
+    palette: [(Square, Rect); 12],
+    buttons: [(Button, Rect); 10],
+}
+
+impl SandboxLayout {
+    fn new(width: f32, height: f32) -> Self {
+        let rect = |x: f32, y: f32, w: f32, h: f32| {
+            Rect::new(x * width, y * height, w * width, h * height)
+        };
+        let side = (0.75 * height).min(0.55 * width);
+        let board = Area::new(0.05 * width, 0.05 * height, side, side);
+        let left = board.x + board.w + 0.05 * width;
+        let panel_width = 0.95 * width - left;
+        let panel = |y: f32, h: f32| Rect::new(left, y * height, panel_width, h * height);
+        // Erase and Clear share a row
+        let half = |y: f32, second: bool| {
+            let w = (panel_width - 0.02 * width) / 2.0;
+            let x = match second {
+                true => left + panel_width - w,
+                false => left,
+            };
+            Rect::new(x, y * height, w, 0.07 * height)
+        };
+        // Two rows under the status, short of the buttons however wide the window
+        let cell = (panel_width / 6.0).min(0.1 * height);
+        let palette_top = 0.25 * height;
+        Self {
+            board,
+            title: panel(0.05, 0.08),
+            status: panel(0.14, 0.08),
+            palette: std::array::from_fn(|index| {
+                let color = match index / 6 {
+                    0 => Color::White,
+                    _ => Color::Black,
+                };
+                let area = Rect::new(
+                    left + (index % 6) as f32 * cell,
+                    palette_top + (index / 6) as f32 * cell,
+                    cell,
+                    cell,
+                );
+                (MEN[index % 6](color), area)
+            }),
+            buttons: [
+                (Button::ToMove, panel(0.5, 0.07)),
+                (Button::Erase, half(0.59, false)),
+                (Button::Clear, half(0.59, true)),
+                (Button::Analyze, panel(0.68, 0.07)),
+                (Button::PlayComputer, panel(0.77, 0.07)),
+                (Button::Undo, rect(0.05, 0.88, 0.13, 0.07)),
+                (Button::Redo, rect(0.2, 0.88, 0.13, 0.07)),
+                (Button::PlayBoth, rect(0.35, 0.88, 0.2, 0.07)),
+                (Button::Export, rect(0.57, 0.88, 0.17, 0.07)),
+                (Button::Back, rect(0.76, 0.88, 0.19, 0.07)),
+            ],
+        }
+    }
+}
+
+/// What the player did in the sandbox
+pub(crate) enum SandboxAction {
+    Back,
+    // Play a game from a position that passed the checks, against the computer playing the
+    // side not to move or with both sides here
+    Play { fen: String, computer: bool },
+}
+
+/// The free-move sandbox: pieces go anywhere, are added from the palette and taken off, with
+/// no rules and no turns. Opened from the start screen or from a reviewed position, it holds
+/// its own copy of the squares and nothing of a game is changed by it. Only a position that
+/// passes the checks is played or analyzed from, it is never autosaved or sent anywhere.
+pub(crate) struct SandboxView {
+    sandbox: Sandbox,
+    tool: Tool,
+    // The piece picked up to move
+    selected: Option<(usize, usize)>,
+    flipped: bool,
+    // The evaluation, the rule the position breaks or where it was exported, and whether
+    // it is an error
+    status: Option<(String, bool)>,
+    // Scores the position once it passed the checks
+    analysis: Option<Analysis>,
+    depth: u32,
+    // Exports are written there
+    save_dir: PathBuf,
+    render: Render,
+    theme: Theme,
+}
+
+impl Restore for SandboxView {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::Render(&mut self.render, &self.theme)
+    }
+}
+
+impl SandboxView {
+    pub(crate) fn new(
+        ctx: &Context,
+        sandbox: Sandbox,
+        theme: Theme,
+        styles: PieceStyles,
+        depth: u32,
+        save_dir: PathBuf,
+    ) -> Self {
+        Self {
+            sandbox,
+            tool: Tool::Move,
+            selected: None,
+            flipped: false,
+            status: None,
+            analysis: None,
+            depth,
+            save_dir,
+            render: Render::new(ctx, &theme, styles),
+            theme,
+        }
+    }
+
+    /// Take in the score of the position once the engine has it
+    pub(crate) fn update(&mut self) {
+        let Some((_, score)) = self.analysis.as_mut().and_then(Analysis::poll) else {
+            return;
+        };
+        self.analysis = None;
+        self.status = Some(match score {
+            Some(score) => (
+                format!("{:+.2} from White's side", score as f32 / 100.0),
+                false,
+            ),
+            None => ("The engine couldn't score the position".to_owned(), true),
+        });
+    }
+
+    /// Whatever was said about the position no longer holds once it changes
+    fn changed(&mut self) {
+        self.analysis = None;
+        self.status = None;
+    }
+
+    fn undo(&mut self, redo: bool) {
+        self.selected = None;
+        let done = match redo {
+            true => self.sandbox.redo(),
+            false => self.sandbox.undo(),
+        };
+        if done {
+            self.changed();
+        }
+    }
+
+    /// Check the position and score it when it is a legal one
+    fn analyze(&mut self) {
+        match self.sandbox.check() {
+            Ok(fen) => {
+                self.analysis = Some(Analysis::start(vec![(0, fen)], self.depth));
+                self.status = Some(("Evaluating...".to_owned(), false));
+            }
+            Err(problem) => self.status = Some((problem.to_string(), true)),
+        }
+    }
+
+    /// Check the position and play from it when it is a legal one
+    fn play(&mut self, computer: bool) -> Option<SandboxAction> {
+        match self.sandbox.check() {
+            Ok(fen) => Some(SandboxAction::Play { fen, computer }),
+            Err(problem) => {
+                self.status = Some((problem.to_string(), true));
+                None
+            }
+        }
+    }
+
+    /// Write the position to the save directory, as FEN if it is legal and as a diagram if not
+    fn export(&mut self) {
+        let (extension, text) = self.sandbox.export();
+        let path = self.save_dir.join(format!(
+            "sandbox-{}.{}",
+            pgn::file_stamp(updates::seconds_since_epoch()),
+            extension
+        ));
+        self.status = Some(
+            match std::fs::create_dir_all(&self.save_dir).and_then(|()| std::fs::write(&path, text))
+            {
+                Ok(()) => (format!("Wrote {}", path.display()), false),
+                Err(err) => (format!("Can't write {}: {}", path.display(), err), true),
+            },
+        );
+    }
+
+    fn press(&mut self, button: Button) -> Option<SandboxAction> {
+        match button {
+            Button::ToMove => {
+                self.sandbox.to_move = opposite_color(self.sandbox.to_move);
+                self.changed();
+            }
+            Button::Erase => {
+                self.tool = match self.tool {
+                    Tool::Erase => Tool::Move,
+                    _ => Tool::Erase,
+                };
+                self.selected = None;
+            }
+            Button::Clear => {
+                self.sandbox.clear();
+                self.selected = None;
+                self.changed();
+            }
+            Button::Undo => self.undo(false),
+            Button::Redo => self.undo(true),
+            Button::Analyze => self.analyze(),
+            Button::PlayComputer => return self.play(true),
+            Button::PlayBoth => return self.play(false),
+            Button::Export => self.export(),
+            Button::Back => return Some(SandboxAction::Back),
+        }
+        None
+    }
+
+    /// Do what the tool does to the square clicked
+    fn click_square(&mut self, square: (usize, usize)) {
+        let before = self.sandbox.squares;
+        match (self.tool, self.selected) {
+            (Tool::Place(piece), _) => self.sandbox.place(square, piece),
+            (Tool::Erase, _) => self.sandbox.place(square, Square::Empty),
+            (Tool::Move, Some(from)) => {
+                self.sandbox.relocate(from, square);
+                self.selected = None;
+            }
+            (Tool::Move, None) => {
+                let (row, col) = square;
+                self.selected = (self.sandbox.squares[row][col] != Square::Empty).then_some(square);
+            }
+        }
+        if self.sandbox.squares != before {
+            self.changed();
+        }
+    }
+
+    fn button_label(&self, button: Button) -> &'static str {
+        match button {
+            Button::ToMove => match self.sandbox.to_move {
+                Color::White => "White to move",
+                Color::Black => "Black to move",
+            },
+            Button::Erase => "Erase",
+            Button::Clear => "Clear",
+            Button::Undo => "Undo",
+            Button::Redo => "Redo",
+            Button::Analyze => "Analyze from here",
+            Button::PlayComputer => "Play the computer",
+            Button::PlayBoth => "Play both sides",
+            Button::Export => "Export",
+            Button::Back => "Back",
+        }
+    }
+
+    fn line(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        area: Rect,
+        text: &str,
+        scale: f32,
+        color: graphics::Color,
+    ) {
+        self.render.draw_text(
+            ctx,
+            canvas,
+            area,
+            text,
+            TextLine {
+                y: 0.5,
+                scale,
+                color,
+            },
+        );
+    }
+
+    fn fill(&self, canvas: &mut Canvas, area: Rect, color: graphics::Color) {
+        canvas.draw(
+            &self.render.bar_mesh,
+            graphics::DrawParam::default().dest_rect(area).color(color),
+        );
+    }
+
+    #[inline]
+    fn board_scene<'a>(&'a self, layout: &SandboxLayout) -> BoardScene<'a> {
+        let mut board = BoardScene::new(layout.board, &self.sandbox.squares);
+        board.flipped = self.flipped;
+        board.coordinates = true;
+        board
+    }
+
+    pub(crate) fn draw(&self, ctx: &Context, canvas: &mut Canvas) -> GameResult {
+        let (width, height) = {
+            let cords = canvas.screen_coordinates().unwrap();
+            (cords.w, cords.h)
+        };
+        let layout = SandboxLayout::new(width, height);
+
+        let board_rect = rect(layout.board);
+        self.fill(
+            canvas,
+            grown(board_rect, board_rect.w * BORDER),
+            SANDBOX_COLOR,
+        );
+        let mut board = self.board_scene(&layout);
+        if let Some(from) = self.selected {
+            board.overlay = Overlay::Selection {
+                from,
+                destinations: &[],
+            };
+        }
+        let mut sink = CanvasSink {
+            render: &self.render,
+            theme: &self.theme,
+            ctx,
+            canvas,
+            board: board_rect,
+            result: Ok(()),
+        };
+        scene::compose(&board, &mut sink);
+        sink.result?;
+
+        self.line(
+            ctx,
+            canvas,
+            layout.title,
+            "Sandbox: no rules, no turns, not a game",
+            0.45,
+            SANDBOX_COLOR,
+        );
+        if let Some((text, error)) = &self.status {
+            let color = match error {
+                true => ERROR_TEXT_COLOR,
+                false => TEXT_COLOR,
+            };
+            self.line(ctx, canvas, layout.status, text, 0.4, color);
+        }
+
+        for (piece, area) in layout.palette {
+            let color = match self.tool == Tool::Place(piece) {
+                true => CHOSEN_BUTTON_COLOR,
+                false => BUTTON_COLOR,
+            };
+            self.fill(canvas, grown(area, -1.0), color);
+            self.render
+                .pieces
+                .draw(canvas, &piece, area, graphics::Color::WHITE);
+        }
+        for (button, area) in layout.buttons {
+            let color = match button == Button::Erase && self.tool == Tool::Erase {
+                true => CHOSEN_BUTTON_COLOR,
+                false => BUTTON_COLOR,
+            };
+            self.fill(canvas, area, color);
+            self.line(
+                ctx,
+                canvas,
+                area,
+                self.button_label(button),
+                0.45,
+                TEXT_COLOR,
+            );
+        }
+        Ok(())
+    }
+
+    /// Handle a click. The buttons take clicks up to `slop` pixels past their edges, the
+    /// squares and the palette are too close together for it.
+    pub(crate) fn click(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        slop: f32,
+    ) -> Option<SandboxAction> {
+        let layout = SandboxLayout::new(width, height);
+        let point = Point2 { x, y };
+        if let Some((button, _)) = layout
+            .buttons
+            .iter()
+            .find(|(_, area)| grown(*area, slop).contains(point))
+        {
+            return self.press(*button);
+        }
+        if let Some((piece, _)) = layout.palette.iter().find(|(_, area)| area.contains(point)) {
+            // The chosen piece again puts it away
+            self.tool = match self.tool == Tool::Place(*piece) {
+                true => Tool::Move,
+                false => Tool::Place(*piece),
+            };
+            self.selected = None;
+            return None;
+        }
+        if let Some(square) = self.board_scene(&layout).square_at(x, y) {
+            self.click_square(square);
+        }
+        None
+    }
+
+    pub(crate) fn key(&mut self, keycode: KeyCode, mods: KeyMods) -> Option<SandboxAction> {
+        let ctrl = mods.contains(KeyMods::CTRL);
+        match keycode {
+            // Escape lets go of the piece and the tool first
+            KeyCode::Escape if self.selected.is_some() => self.selected = None,
+            KeyCode::Escape if self.tool != Tool::Move => self.tool = Tool::Move,
+            KeyCode::Escape => return Some(SandboxAction::Back),
+            KeyCode::Z if ctrl => self.undo(mods.contains(KeyMods::SHIFT)),
+            KeyCode::Y if ctrl => self.undo(true),
+            KeyCode::U => self.undo(false),
+            KeyCode::S if ctrl => self.export(),
+            KeyCode::Delete | KeyCode::Back => {
+                if let Some(square) = self.selected.take() {
+                    self.sandbox.place(square, Square::Empty);
+                    self.changed();
+                }
+            }
+            KeyCode::F => self.flipped = !self.flipped,
+            KeyCode::W => return self.press(Button::ToMove),
+            KeyCode::A => self.analyze(),
+            KeyCode::P => return self.play(true),
+            KeyCode::L => return self.play(false),
+            _ => {}
+        }
+        None
+    }
+}
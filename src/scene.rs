@@ -0,0 +1,693 @@
+use crate::layout::Area;
+use crate::network::{internal_to_network_piece, network_piece_letter};
+use crate::pins::Pin;
+use crate::{Cells, ChoiceLabel, Square};
+use jonathan_hallstrom_chess::Color;
+
+type Coord = (usize, usize);
+
+/// Where a command goes relative to the pieces. Within a layer the order drawn is kept.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Layer {
+    // The squares and everything marked on them under the pieces
+    Board,
+    Pieces,
+    // The marks, chooser, text and film over the pieces
+    Overlay,
+}
+
+/// The line of text a message goes on, the window sizes and colors it
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Line {
+    // The result or the countdown in the middle of the board
+    Result,
+    // A hint along the top edge of the board
+    Hint,
+    // A move kind in a square of the chooser
+    Choice,
+}
+
+/// What a command paints
+#[derive(PartialEq, Copy, Clone)]
+pub enum Paint<'a> {
+    // The checkered pattern over the whole board
+    Squares,
+    // A file letter or rank number in the corner of its square, drawn in the other square
+    // color so it stands out
+    FileLabel { file: usize, on_light: bool },
+    RankLabel { rank: usize, on_light: bool },
+    Check,
+    // The selection a new position let go of, fading out
+    Flash { alpha: f32 },
+    // From the pinner to what it is pinned to, the area runs from one centre to the other
+    PinLine { absolute: bool },
+    // In the corner of a pinned piece's square, centred in the area
+    PinMark { absolute: bool },
+    Piece { piece: Square, dimmed: bool },
+    Selected,
+    // A square of the move picked up while waiting, marked apart from a selection
+    Premove,
+    LegalMove,
+    // Darkens the board behind the chooser, the result and the countdown
+    Film,
+    Text { text: &'a str, line: Line },
+}
+
+/// One thing drawn, in the window's pixels
+#[derive(PartialEq, Copy, Clone)]
+pub struct Command<'a> {
+    pub layer: Layer,
+    pub paint: Paint<'a>,
+    pub area: Area,
+}
+
+/// Takes what the board is drawn with, the window draws it and the tests record it
+pub trait Sink {
+    fn paint(&mut self, command: Command<'_>);
+}
+
+/// A piece on its way from one square to another, `progress` from 0 to 1
+#[derive(PartialEq, Copy, Clone)]
+pub struct Sliding {
+    pub piece: Square,
+    pub from: Coord,
+    pub to: Coord,
+    pub progress: f32,
+}
+
+/// What is shown over the pieces, only one at a time
+#[derive(PartialEq, Copy, Clone, Default)]
+pub enum Overlay<'a> {
+    #[default]
+    None,
+    // Looking back at an old position, with what tells of it
+    Review(&'a str),
+    // The move chooser, by how each choice is shown and the squares it is drawn on
+    Choices(&'a [(ChoiceLabel, Cells)]),
+    // The piece picked up and where it can go
+    Selection {
+        from: Coord,
+        destinations: &'a [Coord],
+    },
+}
+
+/// Everything the board is drawn from. Squares are the board's, the scene turns them into
+/// where they are drawn.
+#[derive(PartialEq, Copy, Clone)]
+pub struct BoardScene<'a> {
+    pub area: Area,
+    pub flipped: bool,
+    pub squares: &'a [[Square; 8]; 8],
+    pub coordinates: bool,
+    // The king to mark, None while reviewing or once the game is over
+    pub check: Option<Coord>,
+    pub flash: Option<(Coord, f32)>,
+    // Empty while they aren't shown
+    pub pins: &'a [Pin],
+    pub sliding: &'a [Sliding],
+    // Whose pieces are dimmed while they wait for the opponent
+    pub waiting: Option<Color>,
+    // The piece shaking after a click out of turn, pushed aside by a part of its square
+    pub shake: Option<(Coord, f32)>,
+    // The squares of the move picked up while waiting, the second once it is chosen
+    pub premove: Option<(Coord, Option<Coord>)>,
+    pub overlay: Overlay<'a>,
+    // The result or the state of the connection, over a film
+    pub result: Option<&'a str>,
+    pub countdown: Option<&'a str>,
+}
+
+impl<'a> BoardScene<'a> {
+    /// The board of `squares` drawn in `area` with nothing on it
+    pub fn new(area: Area, squares: &'a [[Square; 8]; 8]) -> Self {
+        Self {
+            area,
+            flipped: false,
+            squares,
+            coordinates: false,
+            check: None,
+            flash: None,
+            pins: &[],
+            sliding: &[],
+            waiting: None,
+            shake: None,
+            premove: None,
+            overlay: Overlay::None,
+            result: None,
+            countdown: None,
+        }
+    }
+
+    /// The square of the board drawn at `view`, or where a square is drawn. Rotating the board
+    /// half a turn is its own inverse.
+    #[inline]
+    pub fn flip(&self, (row, col): Coord) -> Coord {
+        match self.flipped {
+            true => (7 - row, 7 - col),
+            false => (row, col),
+        }
+    }
+
+    /// Where a square of the board is drawn
+    #[inline]
+    pub fn square(&self, square: Coord) -> Area {
+        let (row, col) = self.flip(square);
+        let side = self.area.w / 8.0;
+        Area::new(
+            self.area.x + col as f32 * side,
+            self.area.y + row as f32 * self.area.h / 8.0,
+            side,
+            self.area.h / 8.0,
+        )
+    }
+
+    /// The square of the board drawn at `x`, `y` in the window, None off the board
+    pub fn square_at(&self, x: f32, y: f32) -> Option<Coord> {
+        let area = self.area;
+        if x < area.x || y < area.y || x >= area.x + area.w || y >= area.y + area.h {
+            return None;
+        }
+        let view = (
+            (((y - area.y) * 8.0 / area.h) as usize).min(7),
+            (((x - area.x) * 8.0 / area.w) as usize).min(7),
+        );
+        Some(self.flip(view))
+    }
+
+    /// Where the squares of a choice are drawn, whichever corner is drawn first
+    pub fn cells(&self, cells: Cells) -> Area {
+        let (a, b) = (self.square(cells.first), self.square(cells.last));
+        let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+        Area::new(x, y, a.x.max(b.x) + a.w - x, a.y.max(b.y) + a.h - y)
+    }
+
+    #[inline]
+    fn centre(&self, square: Coord) -> (f32, f32) {
+        let area = self.square(square);
+        (area.x + area.w / 2.0, area.y + area.h / 2.0)
+    }
+
+    #[inline]
+    fn piece(&self, piece: Square) -> Paint<'a> {
+        Paint::Piece {
+            piece,
+            dimmed: piece.color().is_some() && piece.color() == self.waiting,
+        }
+    }
+}
+
+/// Send everything drawn on the board to `sink` in the order it is drawn: the squares, the
+/// marks under the pieces, the pieces with the sliding ones on top, then whatever is shown
+/// over them
+pub fn compose(scene: &BoardScene, sink: &mut impl Sink) {
+    let mut paint =
+        |layer: Layer, paint: Paint, area: Area| sink.paint(Command { layer, paint, area });
+
+    paint(Layer::Board, Paint::Squares, scene.area);
+    if scene.coordinates {
+        // Along the bottom edge and the left one as drawn, whichever way the board is turned
+        for view in 0..8 {
+            let bottom = scene.flip((7, view));
+            let on_light = (7 + view) % 2 == 0;
+            paint(
+                Layer::Board,
+                Paint::FileLabel {
+                    file: bottom.1,
+                    on_light,
+                },
+                scene.square(bottom),
+            );
+            let left = scene.flip((view, 0));
+            let on_light = view % 2 == 0;
+            paint(
+                Layer::Board,
+                Paint::RankLabel {
+                    rank: left.0,
+                    on_light,
+                },
+                scene.square(left),
+            );
+        }
+    }
+    if let Some(king) = scene.check {
+        paint(Layer::Board, Paint::Check, scene.square(king));
+    }
+    if let Some((square, alpha)) = scene.flash {
+        paint(Layer::Board, Paint::Flash { alpha }, scene.square(square));
+    }
+    if let Some((from, to)) = scene.premove {
+        for square in std::iter::once(from).chain(to) {
+            paint(Layer::Board, Paint::Premove, scene.square(square));
+        }
+    }
+    for pin in scene.pins {
+        let (from, to) = (scene.centre(pin.pinner), scene.centre(pin.target));
+        paint(
+            Layer::Board,
+            Paint::PinLine {
+                absolute: pin.absolute,
+            },
+            Area::new(from.0, from.1, to.0 - from.0, to.1 - from.1),
+        );
+    }
+
+    // Whatever slides somewhere is drawn on its way instead
+    for (row, rank) in scene.squares.iter().enumerate() {
+        for (col, piece) in rank.iter().enumerate() {
+            if *piece == Square::Empty
+                || scene.sliding.iter().any(|sliding| sliding.to == (row, col))
+            {
+                continue;
+            }
+            let mut area = scene.square((row, col));
+            if let Some((_, offset)) = scene.shake.filter(|(square, _)| *square == (row, col)) {
+                area.x += offset * area.w;
+            }
+            paint(Layer::Pieces, scene.piece(*piece), area);
+        }
+    }
+    for sliding in scene.sliding {
+        let (from, to) = (scene.square(sliding.from), scene.square(sliding.to));
+        let t = sliding.progress;
+        let area = Area::new(
+            from.x + (to.x - from.x) * t,
+            from.y + (to.y - from.y) * t,
+            to.w,
+            to.h,
+        );
+        paint(Layer::Pieces, scene.piece(sliding.piece), area);
+    }
+
+    for pin in scene.pins {
+        let square = scene.square(pin.pinned);
+        let (x, y) = scene.centre(pin.pinned);
+        let radius = square.w * 0.09;
+        paint(
+            Layer::Overlay,
+            Paint::PinMark {
+                absolute: pin.absolute,
+            },
+            Area::new(
+                x + square.w * 0.32 - radius,
+                y - square.w * 0.32 - radius,
+                2.0 * radius,
+                2.0 * radius,
+            ),
+        );
+    }
+    match scene.overlay {
+        Overlay::None => {}
+        Overlay::Review(text) => paint(
+            Layer::Overlay,
+            Paint::Text {
+                text,
+                line: Line::Hint,
+            },
+            scene.area,
+        ),
+        Overlay::Choices(choices) => {
+            paint(Layer::Overlay, Paint::Film, scene.area);
+            for (label, cells) in choices {
+                let area = scene.cells(*cells);
+                match label {
+                    // Square in the middle of a wider choice, not stretched over it
+                    ChoiceLabel::Piece(piece) => {
+                        let side = area.w.min(area.h);
+                        let piece_area = Area::new(
+                            area.x + (area.w - side) / 2.0,
+                            area.y + (area.h - side) / 2.0,
+                            side,
+                            side,
+                        );
+                        paint(Layer::Overlay, scene.piece(*piece), piece_area)
+                    }
+                    ChoiceLabel::Text(text) => paint(
+                        Layer::Overlay,
+                        Paint::Text {
+                            text,
+                            line: Line::Choice,
+                        },
+                        area,
+                    ),
+                }
+            }
+        }
+        Overlay::Selection { from, destinations } => {
+            paint(Layer::Overlay, Paint::Selected, scene.square(from));
+            for destination in destinations {
+                paint(Layer::Overlay, Paint::LegalMove, scene.square(*destination));
+            }
+        }
+    }
+    for text in [scene.result, scene.countdown].into_iter().flatten() {
+        paint(Layer::Overlay, Paint::Film, scene.area);
+        paint(
+            Layer::Overlay,
+            Paint::Text {
+                text,
+                line: Line::Result,
+            },
+            scene.area,
+        );
+    }
+}
+
+#[inline]
+fn layer_name(layer: Layer) -> &'static str {
+    match layer {
+        Layer::Board => "board",
+        Layer::Pieces => "pieces",
+        Layer::Overlay => "overlay",
+    }
+}
+
+#[inline]
+fn line_name(line: Line) -> &'static str {
+    match line {
+        Line::Result => "result",
+        Line::Hint => "hint",
+        Line::Choice => "choice",
+    }
+}
+
+/// `text` as a JSON string
+fn quoted(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            character if character.is_control() => {
+                quoted.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A command as one line of JSON with its fields always in the same order and every length
+/// to the hundredth of a pixel, so the same scene always records the same text
+pub fn command_json(command: &Command) -> String {
+    let paint = match command.paint {
+        Paint::Squares => r#""squares""#.to_owned(),
+        Paint::FileLabel { file, on_light } => {
+            format!(r#""file label","file":{},"on_light":{}"#, file, on_light)
+        }
+        Paint::RankLabel { rank, on_light } => {
+            format!(r#""rank label","rank":{},"on_light":{}"#, rank, on_light)
+        }
+        Paint::Check => r#""check""#.to_owned(),
+        Paint::Flash { alpha } => format!(r#""flash","alpha":{:.2}"#, alpha),
+        Paint::PinLine { absolute } => format!(r#""pin line","absolute":{}"#, absolute),
+        Paint::PinMark { absolute } => format!(r#""pin mark","absolute":{}"#, absolute),
+        Paint::Piece { piece, dimmed } => format!(
+            r#""piece","piece":"{}","dimmed":{}"#,
+            network_piece_letter(internal_to_network_piece(&piece)).unwrap_or('.'),
+            dimmed
+        ),
+        Paint::Selected => r#""selected""#.to_owned(),
+        Paint::Premove => r#""premove""#.to_owned(),
+        Paint::LegalMove => r#""legal move""#.to_owned(),
+        Paint::Film => r#""film""#.to_owned(),
+        Paint::Text { text, line } => format!(
+            r#""text","line":"{}","text":{}"#,
+            line_name(line),
+            quoted(text)
+        ),
+    };
+    let area = command.area;
+    format!(
+        r#"{{"layer":"{}","paint":{},"x":{:.2},"y":{:.2},"w":{:.2},"h":{:.2}}}"#,
+        layer_name(command.layer),
+        paint,
+        area.x,
+        area.y,
+        area.w,
+        area.h
+    )
+}
+
+/// Keeps what is drawn instead of drawing it, as `command_json` writes each command
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Recording {
+    pub lines: Vec<String>,
+}
+
+impl Sink for Recording {
+    #[inline]
+    fn paint(&mut self, command: Command<'_>) {
+        self.lines.push(command_json(&command));
+    }
+}
+
+impl Recording {
+    /// The recording as a file of JSON lines
+    pub fn text(&self) -> String {
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        text
+    }
+
+    /// Where the recording first strays from `golden`, a file of JSON lines, None when they
+    /// are the same
+    pub fn first_difference(&self, golden: &str) -> Option<String> {
+        let golden: Vec<&str> = golden.lines().collect();
+        let drawn: Vec<&str> = self.lines.iter().map(String::as_str).collect();
+        let index = golden
+            .iter()
+            .zip(&drawn)
+            .position(|(golden, drawn)| golden != drawn)
+            .or_else(|| (golden.len() != drawn.len()).then(|| golden.len().min(drawn.len())))?;
+        let line = |lines: &[&str]| lines.get(index).copied().unwrap_or("nothing").to_owned();
+        Some(format!(
+            "command {} of {} differs from the golden's {} of {}\n  golden: {}\n  drawn:  {}",
+            index + 1,
+            drawn.len(),
+            index + 1,
+            golden.len(),
+            line(&golden),
+            line(&drawn)
+        ))
+    }
+}
+
+/// The board scenes of the snapshot tests, each compared against its golden in
+/// `src/snapshots`. After a change to how the board is drawn that is meant to be there, run
+///
+/// ```text
+/// BLESS_SNAPSHOTS=1 cargo test --lib scene
+/// ```
+///
+/// to write the goldens again and look over their diff before committing it. The scenes are
+/// recorded as the commands drawn rather than as pixels, so they need no window or GPU and
+/// record the same everywhere.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{LayoutPlan, Orientation};
+    use crate::parse_fen;
+    use crate::splitter::DEFAULT_PANEL_FRACTION;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const BLESS: &str = "BLESS_SNAPSHOTS";
+    const E2_DESTINATIONS: [Coord; 2] = [(5, 4), (4, 4)];
+    // Where the chooser stacks a promotion on a8
+    const PROMOTIONS: [(ChoiceLabel, Cells); 4] = [
+        (ChoiceLabel::Piece(Square::Queen(Color::White)), square(0)),
+        (ChoiceLabel::Piece(Square::Knight(Color::White)), square(1)),
+        (ChoiceLabel::Piece(Square::Rook(Color::White)), square(2)),
+        (ChoiceLabel::Piece(Square::Bishop(Color::White)), square(3)),
+    ];
+
+    // A choice a square big on the a file
+    const fn square(row: usize) -> Cells {
+        Cells {
+            first: (row, 0),
+            last: (row, 0),
+        }
+    }
+
+    /// Where everything goes in a window of this size, recorded ahead of the board so a
+    /// change to the margins shows up too
+    fn window(width: f32, height: f32, recording: &mut Recording) -> Area {
+        let plan = LayoutPlan::new(
+            width,
+            height,
+            DEFAULT_PANEL_FRACTION,
+            0.0,
+            Orientation::Landscape,
+        );
+        for (name, area) in [
+            ("board", plan.board),
+            ("history", plan.history),
+            ("graph", plan.graph),
+        ] {
+            recording.lines.push(format!(
+                r#"{{"region":"{}","x":{:.2},"y":{:.2},"w":{:.2},"h":{:.2}}}"#,
+                name, area.x, area.y, area.w, area.h
+            ));
+        }
+        plan.board
+    }
+
+    fn record(width: f32, height: f32, fen: &str, build: impl Fn(&mut BoardScene)) -> Recording {
+        let mut recording = Recording::default();
+        let area = window(width, height, &mut recording);
+        let squares = parse_fen(fen).unwrap();
+        let mut scene = BoardScene::new(area, &squares);
+        scene.coordinates = true;
+        build(&mut scene);
+        compose(&scene, &mut recording);
+        recording
+    }
+
+    fn scenes() -> Vec<(&'static str, Recording)> {
+        let checked = "rnbqkbnr/ppppp1pp/8/5p1Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2";
+        vec![
+            ("initial", record(800.0, 600.0, START, |_| {})),
+            (
+                "selection",
+                record(800.0, 600.0, START, |scene| {
+                    scene.overlay = Overlay::Selection {
+                        from: (6, 4),
+                        destinations: &E2_DESTINATIONS,
+                    }
+                }),
+            ),
+            (
+                "promotion",
+                record(800.0, 600.0, "4k3/P7/8/8/8/8/8/4K3 w - - 0 1", |scene| {
+                    scene.overlay = Overlay::Choices(&PROMOTIONS)
+                }),
+            ),
+            (
+                "check",
+                record(800.0, 600.0, checked, |scene| {
+                    scene.check = Some((0, 4));
+                    scene.waiting = Some(Color::White);
+                }),
+            ),
+            (
+                "flipped",
+                record(800.0, 600.0, START, |scene| scene.flipped = true),
+            ),
+            (
+                "premove",
+                record(800.0, 600.0, START, |scene| {
+                    scene.waiting = Some(Color::White);
+                    scene.premove = Some(((7, 6), Some((5, 5))));
+                    scene.shake = Some(((6, 4), -0.05));
+                }),
+            ),
+            ("compact", record(320.0, 240.0, START, |_| {})),
+            (
+                "review",
+                record(800.0, 600.0, checked, |scene| {
+                    scene.overlay = Overlay::Review("Reviewing move 3 of 3")
+                }),
+            ),
+            (
+                "result",
+                record(800.0, 600.0, checked, |scene| {
+                    scene.result = Some("Black resigned, White wins")
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn board_scenes_match_their_goldens() {
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/snapshots");
+        let bless = std::env::var_os(BLESS).is_some();
+        let mut failures = Vec::new();
+        for (name, recording) in scenes() {
+            let path = folder.join(format!("{}.jsonl", name));
+            if bless {
+                std::fs::create_dir_all(&folder).unwrap();
+                std::fs::write(&path, recording.text()).unwrap();
+                continue;
+            }
+            match std::fs::read_to_string(&path) {
+                Ok(golden) => {
+                    if let Some(difference) = recording.first_difference(&golden) {
+                        failures.push(format!("{}: {}", name, difference));
+                    }
+                }
+                Err(err) => failures.push(format!(
+                    "{}: no golden at {} ({}), {}=1 writes it",
+                    name,
+                    path.display(),
+                    err,
+                    BLESS
+                )),
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn the_first_difference_is_named() {
+        let recording = record(800.0, 600.0, START, |_| {});
+        assert!(recording.first_difference(&recording.text()).is_none());
+
+        // The board a pixel lower
+        let moved = recording.text().replacen(r#""y":30.00"#, r#""y":31.00"#, 1);
+        let difference = recording.first_difference(&moved).unwrap();
+        assert!(difference.starts_with("command 1 of "), "{}", difference);
+        assert!(difference.contains(r#""y":31.00"#));
+
+        // A tint drawn under the highlight instead of over it
+        let mut lines = recording.lines.clone();
+        let last = lines.pop().unwrap();
+        lines.insert(4, last);
+        let reordered = lines.join("\n");
+        let difference = recording.first_difference(&reordered).unwrap();
+        assert!(difference.starts_with("command 5 of "), "{}", difference);
+
+        let shorter: Vec<&str> = recording.lines[..3].iter().map(String::as_str).collect();
+        let difference = recording.first_difference(&shorter.join("\n")).unwrap();
+        assert!(difference.contains("golden: nothing"), "{}", difference);
+    }
+
+    #[test]
+    fn a_flipped_board_turns_every_square_around() {
+        let squares = parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b").unwrap();
+        let area = Area::new(10.0, 20.0, 400.0, 400.0);
+        let mut scene = BoardScene::new(area, &squares);
+        assert_eq!(scene.square((0, 0)), Area::new(10.0, 20.0, 50.0, 50.0));
+        scene.flipped = true;
+        assert_eq!(scene.square((0, 0)), Area::new(360.0, 370.0, 50.0, 50.0));
+        assert_eq!(scene.flip(scene.flip((2, 5))), (2, 5));
+
+        // A click finds the square drawn under it either way round
+        assert_eq!(scene.square_at(15.0, 25.0), Some((7, 7)));
+        assert_eq!(scene.square_at(409.0, 419.0), Some((0, 0)));
+        assert_eq!(scene.square_at(5.0, 25.0), None);
+        scene.flipped = false;
+        assert_eq!(scene.square_at(15.0, 25.0), Some((0, 0)));
+        let d5 = scene.square((3, 3));
+        assert_eq!(scene.square_at(d5.x + 1.0, d5.y + 1.0), Some((3, 3)));
+        scene.flipped = true;
+
+        // The sliding piece is drawn on its way and not on its square as well
+        let sliding = [Sliding {
+            piece: Square::Pawn(Color::White),
+            from: (6, 4),
+            to: (4, 4),
+            progress: 0.5,
+        }];
+        scene.sliding = &sliding;
+        let mut recording = Recording::default();
+        compose(&scene, &mut recording);
+        let pieces = recording
+            .lines
+            .iter()
+            .filter(|line| line.contains(r#""layer":"pieces""#))
+            .count();
+        assert_eq!(pieces, 32);
+    }
+}
@@ -0,0 +1,75 @@
+//! Directories of their own for the tests that write files. Each one is removed with
+//! everything in it once the test is done with it, whether the test passed or panicked, so
+//! nothing is left behind in the system's temporary directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Tests run side by side in one process, each directory gets a number of its own
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory under the system's temporary one, removed when dropped
+pub(crate) struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    /// A new directory with `name` in its name, for telling whose it was
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "chess-gui-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Left by a run that was killed before it could clean up
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    #[inline]
+    pub(crate) fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// A file or directory in it, not made yet
+    #[inline]
+    pub(crate) fn join(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+
+    #[test]
+    fn the_directory_goes_even_when_the_test_panics() {
+        let first = Scratch::new("scratch");
+        let second = Scratch::new("scratch");
+        assert!(first.path() != second.path());
+
+        let dir = panic::catch_unwind(|| {
+            let scratch = Scratch::new("scratch");
+            fs::write(scratch.join("left.txt"), "behind").unwrap();
+            let dir = scratch.path().to_owned();
+            panic!("{}", dir.display());
+        })
+        .unwrap_err();
+        let dir = PathBuf::from(dir.downcast_ref::<String>().unwrap());
+        assert!(!dir.exists());
+
+        let dir = first.path().to_owned();
+        drop(first);
+        assert!(!dir.exists() && second.path().exists());
+    }
+}
@@ -0,0 +1,218 @@
+use crate::network::{
+    compute_joever, internal_to_network_board, internal_to_network_move, network_to_internal_board,
+    network_to_internal_move,
+};
+use crate::session::Session;
+use crate::{parse_fen, sprite_cell, Square};
+use jonathan_hallstrom_chess::{Board, Color};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+type Check = fn(&Board) -> Result<(), String>;
+
+// Only the starting position, so launching stays fast. The rules themselves are unit tested.
+const CHECKS: [(&str, Check); 6] = [
+    ("starting position parses", check_parsed_position),
+    ("network board orientation", check_network_board),
+    ("network board round trip", check_board_round_trip),
+    ("starting moves convert", check_starting_moves),
+    ("sprite mapping", check_sprite_mapping),
+    ("starting position is ongoing", check_ongoing),
+];
+
+/// Back rank from the a file to the h file
+fn back_rank(color: Color) -> [Square; 8] {
+    [
+        Square::Rook(color),
+        Square::Knight(color),
+        Square::Bishop(color),
+        Square::Queen(color),
+        Square::King(color),
+        Square::Bishop(color),
+        Square::Knight(color),
+        Square::Rook(color),
+    ]
+}
+
+fn parse_position(board: &Board) -> Result<[[Square; 8]; 8], String> {
+    parse_fen(&board.to_fen()).map_err(|err| err.to_string())
+}
+
+fn check_parsed_position(board: &Board) -> Result<(), String> {
+    let squares = parse_position(board)?;
+
+    let mut expected = [[Square::Empty; 8]; 8];
+    expected[0] = back_rank(Color::Black);
+    expected[1] = [Square::Pawn(Color::Black); 8];
+    expected[6] = [Square::Pawn(Color::White); 8];
+    expected[7] = back_rank(Color::White);
+
+    for row in 0..8usize {
+        for col in 0..8usize {
+            if squares[row][col] != expected[row][col] {
+                return Err(format!(
+                    "unexpected piece at row {} col {} of {}",
+                    row,
+                    col,
+                    board.to_fen()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_network_board(board: &Board) -> Result<(), String> {
+    use chess_network_protocol::Piece;
+
+    let network = internal_to_network_board(&parse_position(board)?);
+    // The network board starts at the first rank
+    match (network[0][4], network[7][3], network[1][0], network[6][7]) {
+        (Piece::WhiteKing, Piece::BlackQueen, Piece::WhitePawn, Piece::BlackPawn) => Ok(()),
+        _ => Err("network board has the wrong orientation".to_owned()),
+    }
+}
+
+fn check_starting_moves(board: &Board) -> Result<(), String> {
+    let moves = board.get_legal_moves();
+    if moves.len() != 20 {
+        return Err(format!("expected 20 legal moves, found {}", moves.len()));
+    }
+
+    let network = internal_to_network_board(&parse_position(board)?);
+    let mut seen = HashSet::new();
+    for mv in &moves {
+        let network_move = internal_to_network_move(mv).map_err(|err| err.to_string())?;
+        let key = (
+            network_move.start_x,
+            network_move.start_y,
+            network_move.end_x,
+            network_move.end_y,
+        );
+        if !seen.insert(key) {
+            return Err(format!(
+                "{} converts to the same network move as another move",
+                mv.to_algebraic_notation()
+            ));
+        }
+        if network_to_internal_move(&network_move, &moves)
+            .is_none_or(|back| back.to_algebraic_notation() != mv.to_algebraic_notation())
+        {
+            return Err(format!(
+                "{} doesn't convert back from the network",
+                mv.to_algebraic_notation()
+            ));
+        }
+        // Only white pieces on the first two ranks can move at the start
+        if network_move.start_y > 1
+            || network[network_move.start_y][network_move.start_x]
+                == chess_network_protocol::Piece::None
+        {
+            return Err(format!(
+                "{} starts from an empty square on the network board",
+                mv.to_algebraic_notation()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_board_round_trip(board: &Board) -> Result<(), String> {
+    let squares = parse_position(board)?;
+    match network_to_internal_board(&internal_to_network_board(&squares)) == squares {
+        true => Ok(()),
+        false => Err("the board changed on its way to the network and back".to_owned()),
+    }
+}
+
+fn check_sprite_mapping(_board: &Board) -> Result<(), String> {
+    check_sprite_cells(sprite_cell)
+}
+
+/// Every piece has a cell of its own in the two rows of six of the sprite sheet
+fn check_sprite_cells(cell: fn(&Square) -> Option<(usize, usize)>) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for color in [Color::White, Color::Black] {
+        for piece in [
+            Square::Pawn(color),
+            Square::Rook(color),
+            Square::Bishop(color),
+            Square::Knight(color),
+            Square::King(color),
+            Square::Queen(color),
+        ] {
+            match cell(&piece) {
+                Some((col, row)) if col < 6 && row < 2 => {
+                    if !seen.insert((col, row)) {
+                        return Err(format!("sprite cell ({}, {}) used twice", col, row));
+                    }
+                }
+                _ => return Err("piece without a sprite cell".to_owned()),
+            }
+        }
+    }
+    match cell(&Square::Empty) {
+        Some(_) => Err("empty square has a sprite".to_owned()),
+        None => Ok(()),
+    }
+}
+
+fn check_ongoing(_board: &Board) -> Result<(), String> {
+    match compute_joever(&Session::new(None)?.game_state) {
+        chess_network_protocol::Joever::Ongoing => Ok(()),
+        _ => Err("starting position is reported as finished".to_owned()),
+    }
+}
+
+/// Run every check against the starting position, returning the failed checks as a report
+pub fn run() -> Result<Duration, String> {
+    run_checks(&CHECKS)
+}
+
+fn run_checks(checks: &[(&str, Check)]) -> Result<Duration, String> {
+    let start = Instant::now();
+    let board = Board::default();
+
+    let failures: Vec<String> = checks
+        .iter()
+        .filter_map(|(name, check)| check(&board).err().map(|err| format!("{}: {}", name, err)))
+        .collect();
+
+    match failures.is_empty() {
+        true => Ok(start.elapsed()),
+        false => Err(failures.join("\n")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_starting_position_passes() {
+        if let Err(report) = run() {
+            panic!("{}", report);
+        }
+    }
+
+    #[test]
+    fn a_miswired_sprite_sheet_is_reported() {
+        // A piece set that draws every piece from the same cell
+        fn broken(_: &Square) -> Option<(usize, usize)> {
+            Some((0, 0))
+        }
+        fn check_broken_sprites(_: &Board) -> Result<(), String> {
+            check_sprite_cells(broken)
+        }
+
+        let report = run_checks(&[
+            ("sprite mapping", check_sprite_mapping),
+            ("broken sprite mapping", check_broken_sprites),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            report,
+            "broken sprite mapping: sprite cell (0, 0) used twice"
+        );
+    }
+}
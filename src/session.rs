@@ -0,0 +1,969 @@
+use crate::bookmarks::Bookmarks;
+use crate::glyphs::Glyph;
+use crate::metrics;
+use crate::negotiation::Negotiations;
+use crate::network::{
+    negotiated_features, network_fen, network_to_internal_board, network_to_internal_move,
+    Handshake, ILLEGAL_MOVES_FORFEIT, OUT_OF_TIME,
+};
+use crate::notation::Notation;
+use crate::strikes::{Strike, Strikes, Verdict};
+use crate::{
+    board_from_fen, captured_piece, click_board, is_in_check, is_insufficient_material,
+    opposite_color, parse_move, pgn, position_problem, BoardRepr, Click, GameState, HistoryEntry,
+    Refresh, Square,
+};
+use chess_network_protocol::{Features, Joever, ServerToClient};
+use jonathan_hallstrom_chess::{Board, Color, Move};
+use std::collections::HashMap;
+
+// Plies without a capture or a pawn move that make the fifty-move rule
+const FIFTY_MOVE_PLIES: u32 = 100;
+
+/// The part of a FEN that makes positions the same for repetitions: the pieces,
+/// the side to move, castling rights and the en passant square
+fn position_key(fen: &str) -> String {
+    fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+}
+
+/// The halfmove clock of a FEN, 0 when it has none
+#[inline]
+fn halfmove_clock(fen: &str) -> u32 {
+    fen.split_whitespace()
+        .nth(4)
+        .and_then(|clock| clock.parse().ok())
+        .unwrap_or(0)
+}
+
+/// What a handshake the peer sends again after the game started comes down to
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Rehandshake {
+    // It agrees with the game here, the peer only sent it again
+    Duplicate,
+    // It disagrees with the game here, the server's side of it wins
+    Desync,
+    // A handshake this side never takes, or any at all with --strict-handshakes
+    Violation,
+}
+
+/// A move pair like "1. e2e4 e7e5", or "1. e4! e5" in a notation and with a glyph
+fn history_line(number: usize, pair: &[HistoryEntry], notation: Option<Notation>) -> String {
+    let moves: Vec<String> = pair
+        .iter()
+        .map(|entry| {
+            let glyph = entry.glyph.map_or("", |glyph| glyph.symbol());
+            entry.shown(notation) + glyph
+        })
+        .collect();
+    format!("{}. {}", number, moves.join(" "))
+}
+
+/// A game without a window, driven by clicks and network messages.
+/// The GUI draws one of these and adds the clock, sounds and the connection around it.
+pub struct Session {
+    pub board: Board,
+    pub board_repr: BoardRepr,
+    pub game_state: GameState,
+    // Last move applied to the board
+    pub last_move: Option<Move>,
+    // Every move played so far and the color that played it
+    pub history: Vec<HistoryEntry>,
+    // The history as numbered move pairs for the move list, kept in step so drawing doesn't rebuild it
+    pub history_lines: Vec<String>,
+    // Position the game started from, None for the standard one
+    pub start_fen: Option<String>,
+    // Moves replayed from the starting position on undo, and the undone moves for redo
+    pub played_moves: Vec<Move>,
+    pub undone_moves: Vec<Move>,
+    // Position after every ply so far as FEN, the starting one first
+    pub snapshots: Vec<String>,
+    // Score of every snapshot from White's side once review has worked it out, kept with them
+    pub scores: Vec<Option<i32>>,
+    // How many times every position so far came up, by `position_key`
+    pub repetitions: HashMap<String, u32>,
+    // Plies since the last capture or pawn move after every snapshot
+    pub halfmove_clocks: Vec<u32>,
+    // Whether rule draws wait for a player to claim them instead of ending the game
+    pub claim_draws: bool,
+    // The draw the position allows by threefold repetition or the fifty-move rule
+    pub claimable: Option<GameState>,
+    // Positions marked with B, taking moves back keeps the ones past them for redo
+    pub bookmarks: Bookmarks,
+    // The draw offers of the game and what came of them
+    pub negotiations: Negotiations,
+    // How the move list writes moves, coordinates if None
+    pub notation: Option<Notation>,
+    // Counts every refresh, so whatever was worked out for a position knows when it is stale
+    pub generation: u64,
+    // The refresh of the position after the move being played, worked out ahead
+    prepared: Option<Refresh>,
+}
+
+impl Session {
+    /// A game from the standard position or the given FEN. A FEN the engine takes is still
+    /// turned down if the position breaks the rules, the engine's moves in it mean nothing.
+    pub fn new(start_fen: Option<String>) -> Result<Self, String> {
+        let board = match &start_fen {
+            Some(fen) => board_from_fen(fen)?,
+            None => Board::default(),
+        };
+        let mut session = Self {
+            board_repr: BoardRepr::new(&board),
+            // Keyed by the engine's FEN like every later position, the given one may differ in spacing
+            repetitions: HashMap::from([(position_key(&board.to_fen()), 1)]),
+            halfmove_clocks: vec![start_fen.as_deref().map_or(0, halfmove_clock)],
+            snapshots: vec![board.to_fen()],
+            scores: vec![None],
+            board,
+            game_state: GameState::Ongoing,
+            last_move: None,
+            history: Vec::new(),
+            history_lines: Vec::new(),
+            start_fen,
+            played_moves: Vec::new(),
+            undone_moves: Vec::new(),
+            claim_draws: false,
+            claimable: None,
+            bookmarks: Bookmarks::default(),
+            negotiations: Negotiations::default(),
+            notation: None,
+            generation: 0,
+            prepared: None,
+        };
+        if let Some(fen) = &session.start_fen {
+            let to_move = session.board.get_curr_player();
+            if let Some(problem) = position_problem(&session.board_repr.squares, to_move) {
+                return Err(format!("{}: \"{}\"", problem, fen));
+            }
+        }
+        // The starting position may already be over
+        session.refresh();
+        Ok(session)
+    }
+
+    /// Start over from another position before any move is played, keeping how the board is shown
+    pub fn restart(&mut self, fen: String) -> Result<(), String> {
+        let mut restarted = Session::new(Some(fen))?;
+        restarted.board_repr.flipped = self.board_repr.flipped;
+        restarted.board_repr.excluded = self.board_repr.excluded;
+        restarted.claim_draws = self.claim_draws;
+        restarted.notation = self.notation;
+        // Whatever was worked out for the old position mustn't match the new one
+        restarted.generation = self.generation + 1;
+        restarted.refresh();
+        *self = restarted;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn fen(&self) -> String {
+        self.board.to_fen()
+    }
+
+    #[inline]
+    pub fn squares(&self) -> &[[Square; 8]; 8] {
+        &self.board_repr.squares
+    }
+
+    /// Every move played so far in algebraic notation like "e2e4"
+    pub fn move_notations(&self) -> Vec<String> {
+        self.played_moves
+            .iter()
+            .map(|mv| mv.to_algebraic_notation())
+            .collect()
+    }
+
+    /// `pgn::game_hash` of the game so far
+    #[inline]
+    pub fn game_hash(&self) -> String {
+        pgn::game_hash(self.start_fen.as_deref(), &self.move_notations())
+    }
+
+    /// Click the square at (row, col) on the board, the move it picks is returned and not played.
+    /// A networked game sends it to the peer first, a local one plays it with `apply_move`.
+    pub fn click(&mut self, row: usize, col: usize) -> Option<Move> {
+        match click_board(&self.board_repr, (row, col)) {
+            Click::Select(from, to) => {
+                self.board_repr.selected_from = from;
+                self.board_repr.selected_to = to;
+                None
+            }
+            Click::Play(mv) => Some(mv),
+        }
+    }
+
+    /// Play a legal move, the piece it took is returned
+    pub fn apply_move(&mut self, mv: Move) -> Option<Square> {
+        // Whatever was selected made the move or can't move now
+        self.board_repr.selected_from = None;
+        self.board_repr.selected_to = None;
+        let captured = parse_move(&mv.to_algebraic_notation())
+            .ok()
+            .and_then(|(from, to)| captured_piece(&self.board_repr.squares, from, to));
+        let san = pgn::san(&self.board_repr, &mv);
+        let mover = self.board.get_curr_player();
+        let pawn_moved = parse_move(&mv.to_algebraic_notation()).is_ok_and(|(from, _)| {
+            matches!(self.board_repr.squares[from.0][from.1], Square::Pawn(_))
+        });
+        // A move other than the next undone one replaces the moves past it, and so their
+        // bookmarks and negotiations too
+        if self
+            .undone_moves
+            .last()
+            .is_some_and(|undone| undone.to_algebraic_notation() != mv.to_algebraic_notation())
+        {
+            self.bookmarks.forget_after(self.played_moves.len());
+            self.negotiations.forget_after(self.played_moves.len());
+        }
+        self.played_moves.push(mv);
+        // A new move makes the undone ones unreachable
+        self.undone_moves.clear();
+        self.board.play_move(mv).unwrap();
+        self.last_move = Some(mv);
+        self.snapshots.push(self.board.to_fen());
+        self.scores.push(None);
+        *self
+            .repetitions
+            .entry(position_key(&self.board.to_fen()))
+            .or_insert(0) += 1;
+        self.halfmove_clocks
+            .push(match pawn_moved || captured.is_some() {
+                true => 0,
+                false => self.halfmove_clocks.last().map_or(1, |clock| clock + 1),
+            });
+        self.refresh();
+        // Check marks need the position after the move
+        self.history.push(HistoryEntry {
+            notation: mv.to_algebraic_notation(),
+            san: san + pgn::check_suffix(&self.board_repr),
+            color: mover,
+            captured,
+            glyph: None,
+        });
+        self.sync_history_lines();
+        captured
+    }
+
+    /// Play a legal move with the refresh of the position after it worked out ahead, see
+    /// `speculation`. The game comes out exactly as with `apply_move`.
+    pub fn apply_prepared_move(&mut self, mv: Move, prepared: Refresh) -> Option<Square> {
+        self.prepared = Some(prepared);
+        self.apply_move(mv)
+    }
+
+    /// Play a move that came over the network if it is legal here
+    pub fn apply_network_move(
+        &mut self,
+        network_move: &chess_network_protocol::Move,
+    ) -> Result<Move, String> {
+        let mv = network_to_internal_move(network_move, &self.board_repr.moves)
+            .ok_or_else(|| format!("Illegal move {:?}", network_move))?;
+        self.apply_move(mv);
+        Ok(mv)
+    }
+
+    /// The legal move matching a move suggested by the client, or why the server rejects it
+    pub fn validate_client_move(
+        &self,
+        client_move: &chess_network_protocol::Move,
+        server_color: Color,
+    ) -> Result<Move, (Strike, String)> {
+        if self.board.get_curr_player() == server_color {
+            return Err((Strike::Protocol, "It is not your turn".to_owned()));
+        }
+        if self.game_state != GameState::Ongoing {
+            return Err((Strike::Protocol, "The game is already over".to_owned()));
+        }
+
+        network_to_internal_move(client_move, &self.board_repr.moves)
+            .ok_or_else(|| (Strike::Illegal, format!("Illegal move {:?}", client_move)))
+    }
+
+    /// Validate a move from the client and count it against the client's strikes if it is
+    /// rejected, the client forfeits once it has too many. A valid move isn't played yet.
+    /// Rejected moves after the game is over aren't counted and have no verdict.
+    pub fn judge_client_move(
+        &mut self,
+        client_move: &chess_network_protocol::Move,
+        server_color: Color,
+        strikes: &mut Strikes,
+    ) -> Result<Move, (String, Option<Verdict>)> {
+        let (strike, message) = match self.validate_client_move(client_move, server_color) {
+            Ok(mv) => {
+                strikes.accept();
+                return Ok(mv);
+            }
+            Err(rejected) => rejected,
+        };
+        if self.game_state != GameState::Ongoing {
+            return Err((message, None));
+        }
+        let verdict =
+            self.count_strike(strike, &format!("{:?}", client_move), server_color, strikes);
+        Err((message, Some(verdict)))
+    }
+
+    /// Count something the server rejected from the client against its strikes, the client
+    /// forfeits once it has too many
+    pub fn count_strike(
+        &mut self,
+        strike: Strike,
+        raw: &str,
+        server_color: Color,
+        strikes: &mut Strikes,
+    ) -> Verdict {
+        let verdict = strikes.reject(strike, self.history.len(), raw);
+        if let Some(metrics) = metrics::global().filter(|_| verdict != Verdict::Repeated) {
+            metrics.strike(strike);
+        }
+        if verdict == Verdict::Forfeit {
+            self.game_state = GameState::Forfeit(opposite_color(server_color));
+        }
+        verdict
+    }
+
+    /// Check a handshake the peer sent again after the game started against the game here.
+    /// The server's handshake has to have our board and the features we agreed on, the
+    /// client's has to want the server to keep its color. `strict` takes none of them.
+    pub fn judge_rehandshake(
+        &self,
+        handshake: &Handshake,
+        is_server: bool,
+        server_color: Color,
+        features: &[Features],
+        strict: bool,
+    ) -> Rehandshake {
+        let agrees = match handshake {
+            _ if strict => return Rehandshake::Violation,
+            Handshake::ServerToClient(handshake) if !is_server => {
+                network_to_internal_board(&handshake.board) == self.board_repr.squares
+                    && negotiated_features(handshake) == features
+            }
+            Handshake::ClientToServer(handshake) if is_server => {
+                let wanted = match handshake.server_color {
+                    chess_network_protocol::Color::White => Color::White,
+                    chess_network_protocol::Color::Black => Color::Black,
+                };
+                wanted == server_color
+            }
+            // Our own kind of handshake, no peer of ours sends that
+            _ => return Rehandshake::Violation,
+        };
+        match agrees {
+            true => Rehandshake::Duplicate,
+            false => Rehandshake::Desync,
+        }
+    }
+
+    /// Take the board the server sent if the one replayed here differs, true if it did.
+    /// The moves so far stay in the history even though they no longer lead to it.
+    pub fn follow_server_board(
+        &mut self,
+        board: &[[chess_network_protocol::Piece; 8]; 8],
+        moves: &[chess_network_protocol::Move],
+    ) -> Result<bool, String> {
+        if network_to_internal_board(board) == self.board_repr.squares {
+            return Ok(false);
+        }
+        let fen = network_fen(board, moves, self.board.get_curr_player());
+        self.board = board_from_fen(&fen)?;
+        // Reviewing the latest ply shows the server's board too
+        if let Some(snapshot) = self.snapshots.last_mut() {
+            *snapshot = self.board.to_fen();
+        }
+        if let Some(score) = self.scores.last_mut() {
+            *score = None;
+        }
+        self.refresh();
+        Ok(true)
+    }
+
+    /// End the game once the server says it is over, the server has the last word on the result
+    pub fn follow_server_result(&mut self, joever: &Joever) {
+        if self.game_state != GameState::Ongoing {
+            return;
+        }
+        let loser = match joever {
+            Joever::White => Color::Black,
+            Joever::Black => Color::White,
+            // By the rule that holds here if one does, the protocol doesn't say which
+            Joever::Draw => {
+                self.game_state = self.claimable.unwrap_or(GameState::Draw);
+                return;
+            }
+            _ => return,
+        };
+        // Without moves left it is mate, otherwise the game was won some other way
+        self.game_state = match self.board_repr.moves.is_empty() {
+            true => GameState::Checkmate(loser),
+            false => GameState::Resigned(loser),
+        };
+    }
+
+    /// The server gave the game away on time or for rejected moves, which `message` tells.
+    /// The clocks and strikes here only follow the server's own.
+    pub fn follow_forfeit(&mut self, joever: &Joever, message: &str) {
+        if self.game_state != GameState::Ongoing {
+            return;
+        }
+        let forfeit = match message {
+            OUT_OF_TIME => GameState::TimeForfeit,
+            ILLEGAL_MOVES_FORFEIT => GameState::Forfeit,
+            _ => return,
+        };
+        match joever {
+            Joever::White => self.game_state = forfeit(Color::Black),
+            Joever::Black => self.game_state = forfeit(Color::White),
+            _ => {}
+        }
+    }
+
+    /// Follow a message from the server, the moves it reports and resignations change the game
+    pub fn apply_server_message(&mut self, message: &ServerToClient) -> Result<(), String> {
+        match message {
+            ServerToClient::State {
+                move_made, joever, ..
+            } => {
+                self.apply_network_move(move_made)?;
+                self.follow_server_result(joever);
+                Ok(())
+            }
+            ServerToClient::Resigned { joever, .. } => {
+                self.game_state = GameState::Resigned(match joever {
+                    Joever::White => Color::Black,
+                    Joever::Black => Color::White,
+                    _ => return Err(format!("No winner in the resignation {:?}", joever)),
+                });
+                Ok(())
+            }
+            ServerToClient::Error {
+                message, joever, ..
+            } if message == OUT_OF_TIME || message == ILLEGAL_MOVES_FORFEIT => {
+                self.follow_forfeit(joever, message);
+                Ok(())
+            }
+            ServerToClient::Error { message, .. } => Err(message.clone()),
+            // Drawing is agreed between the players, it doesn't change the board
+            ServerToClient::Draw { .. } => Ok(()),
+        }
+    }
+
+    /// Take back the last move by replaying every move before it
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.played_moves.pop()?;
+        self.undone_moves.push(mv);
+        self.history.pop();
+        self.sync_history_lines();
+        self.scores.pop();
+        if let Some(fen) = self.snapshots.pop() {
+            if let Some(count) = self.repetitions.get_mut(&position_key(&fen)) {
+                *count -= 1;
+            }
+        }
+        self.halfmove_clocks.pop();
+
+        self.board = self.start_board();
+        for mv in &self.played_moves {
+            self.board.play_move(*mv).unwrap();
+        }
+        self.last_move = self.played_moves.last().copied();
+        // The result no longer holds, refresh works out the new one
+        self.game_state = GameState::Ongoing;
+        self.refresh();
+        Some(mv)
+    }
+
+    /// Rewrite the line holding the latest move, the only one a move or an undo can change
+    fn sync_history_lines(&mut self) {
+        let count = self.history.len().div_ceil(2);
+        self.history_lines.truncate(count);
+        if count == 0 {
+            return;
+        }
+        let line = history_line(count, &self.history[(count - 1) * 2..], self.notation);
+        match self.history_lines.len() == count {
+            true => self.history_lines[count - 1] = line,
+            false => self.history_lines.push(line),
+        }
+    }
+
+    /// Set or clear the glyph of the move at index `mv` of the history, its line is rewritten
+    pub fn set_glyph(&mut self, mv: usize, glyph: Option<Glyph>) {
+        let Some(entry) = self.history.get_mut(mv) else {
+            return;
+        };
+        entry.glyph = glyph;
+        let line = mv / 2;
+        self.history_lines[line] = history_line(
+            line + 1,
+            &self.history[line * 2..(line * 2 + 2).min(self.history.len())],
+            self.notation,
+        );
+    }
+
+    /// The glyph of every move of the history
+    #[inline]
+    pub fn glyphs(&self) -> Vec<Option<Glyph>> {
+        self.history.iter().map(|entry| entry.glyph).collect()
+    }
+
+    /// Write the move list in another notation, all of it is rewritten
+    pub fn set_notation(&mut self, notation: Option<Notation>) {
+        self.notation = notation;
+        self.history_lines = self
+            .history
+            .chunks(2)
+            .enumerate()
+            .map(|(i, pair)| history_line(i + 1, pair, notation))
+            .collect();
+    }
+
+    #[inline]
+    pub fn start_board(&self) -> Board {
+        match &self.start_fen {
+            // Checked when the game started
+            Some(fen) => board_from_fen(fen).unwrap(),
+            None => Board::default(),
+        }
+    }
+
+    /// Regenerate the board representation after the position changed
+    pub fn refresh(&mut self) {
+        match self.prepared.take() {
+            Some(prepared) => {
+                debug_assert!(
+                    prepared.same_as(&Refresh::new(&self.board, self.board_repr.excluded)),
+                    "The refresh worked out ahead differs from a fresh one"
+                );
+                self.board_repr.install(prepared);
+            }
+            None => self.board_repr.refresh(&self.board),
+        }
+        self.generation += 1;
+
+        // A resignation or agreed draw sticks, otherwise check whether the position ended the game
+        if self.game_state == GameState::Ongoing {
+            self.game_state = self.position_state();
+        }
+        self.claimable = self.rule_draw();
+        if self.game_state == GameState::Ongoing && !self.claim_draws {
+            self.game_state = self.claimable.unwrap_or(GameState::Ongoing);
+        }
+    }
+
+    fn rule_draw(&self) -> Option<GameState> {
+        let repeated = self.repetitions.get(&position_key(&self.board.to_fen()));
+        if repeated.is_some_and(|count| *count >= 3) {
+            return Some(GameState::Repetition);
+        }
+        let clock = self.halfmove_clocks.last().copied().unwrap_or(0);
+        (clock >= FIFTY_MOVE_PLIES).then_some(GameState::FiftyMoves)
+    }
+
+    #[inline]
+    fn position_state(&self) -> GameState {
+        // Moves excluded for the peer are still legal, the game isn't over while they are left
+        let has_moves = !self.board_repr.moves.is_empty();
+        classify(
+            has_moves,
+            &self.board_repr.squares,
+            self.board.get_curr_player(),
+        )
+    }
+}
+
+/// Checkmate or stalemate once the side to move has no legal moves left, a draw once neither
+/// side can mate anymore. No moves in a position that breaks the rules is the engine giving up
+/// on it rather than the end of the game.
+pub(crate) fn classify(has_moves: bool, squares: &[[Square; 8]; 8], to_move: Color) -> GameState {
+    match (has_moves, is_in_check(squares, to_move)) {
+        (true, _) if is_insufficient_material(squares) => GameState::InsufficientMaterial,
+        (true, _) => GameState::Ongoing,
+        (false, _) if position_problem(squares, to_move).is_some() => GameState::PositionError,
+        (false, true) => GameState::Checkmate(to_move),
+        (false, false) => GameState::Stalemate,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::network::{
+        board_state, error_state, internal_to_network_board, internal_to_network_move, BoardCoord,
+    };
+    use crate::notation::Language;
+    use crate::tests::PROMOTION_LINE;
+    use chess_network_protocol::{ClientToServer, Piece};
+
+    /// Play a move given in algebraic notation like "e2e4"
+    pub(crate) fn play(session: &mut Session, notation: &str) {
+        let mv = session
+            .board
+            .get_legal_moves()
+            .into_iter()
+            .find(|mv| mv.to_algebraic_notation().eq_ignore_ascii_case(notation))
+            .unwrap_or_else(|| panic!("{} isn't legal", notation));
+        session.apply_move(mv);
+    }
+
+    /// Click the squares one after another, only the last click may pick a move and it is played
+    fn play_clicks(session: &mut Session, squares: &[(usize, usize)]) {
+        let (last, rest) = squares.split_last().expect("squares to click");
+        for (row, col) in rest {
+            assert!(
+                session.click(*row, *col).is_none(),
+                "played before {:?}",
+                last
+            );
+        }
+        let mv = session
+            .click(last.0, last.1)
+            .unwrap_or_else(|| panic!("clicking {:?} played nothing", squares));
+        session.apply_move(mv);
+    }
+
+    /// The move between two squares as the protocol has it, without a promotion
+    fn network_move(from: (usize, usize), to: (usize, usize)) -> chess_network_protocol::Move {
+        let (from, to) = (
+            BoardCoord::from(from).to_network(),
+            BoardCoord::from(to).to_network(),
+        );
+        chess_network_protocol::Move {
+            start_x: from.x,
+            start_y: from.y,
+            end_x: to.x,
+            end_y: to.y,
+            promotion: Piece::None,
+        }
+    }
+
+    /// Send a message the way the network thread does and read it back on the other side
+    fn over_the_wire<T: serde::Serialize + serde::de::DeserializeOwned>(message: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(message).unwrap()).unwrap()
+    }
+
+    /// White clicks its moves, Black's arrive from the server, and the game ends in an underpromotion
+    #[test]
+    fn a_scripted_game_through_clicks_and_the_server() {
+        let mut session = Session::new(None).unwrap();
+        for (ply, notation) in PROMOTION_LINE.iter().enumerate() {
+            let (from, to) = parse_move(notation).unwrap();
+            match ply % 2 {
+                0 => play_clicks(&mut session, &[from, to]),
+                _ => session
+                    .apply_server_message(&ServerToClient::State {
+                        board: internal_to_network_board(session.squares()),
+                        moves: Vec::new(),
+                        joever: Joever::Ongoing,
+                        move_made: network_move(from, to),
+                    })
+                    .unwrap(),
+            }
+        }
+        // The knight is the second choice of the chooser, just below the promotion square
+        let (b7, a8, a7) = ((1, 1), (0, 0), (1, 0));
+        play_clicks(&mut session, &[b7, a8, a7]);
+
+        let fen = session.fen();
+        assert_eq!(
+            fen.split_whitespace().next(),
+            Some("N1bqkb1r/p2npppp/5n2/8/8/8/PPPP1PPP/RNBQKBNR")
+        );
+        let sans: Vec<&str> = session
+            .history
+            .iter()
+            .map(|entry| entry.san.as_str())
+            .collect();
+        assert_eq!(
+            sans,
+            ["e4", "d5", "exd5", "c6", "dxc6", "Nf6", "cxb7", "Nbd7", "bxa8=N"]
+        );
+        assert!(session.game_state == GameState::Ongoing);
+
+        assert!(session.undo().is_some());
+        assert!(session.squares()[b7.0][b7.1] == Square::Pawn(Color::White));
+    }
+
+    /// The server plays White and relays every move, the client's moves only count once it answers
+    #[test]
+    fn the_client_follows_the_moves_the_server_relays() {
+        let (mut server, mut client) = (Session::new(None).unwrap(), Session::new(None).unwrap());
+        for (ply, notation) in ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4"].iter().enumerate() {
+            let (from, to) = parse_move(notation).unwrap();
+            let mv = match ply % 2 {
+                0 => {
+                    server.click(from.0, from.1);
+                    server.click(to.0, to.1)
+                }
+                _ => {
+                    client.click(from.0, from.1);
+                    let suggested = client.click(to.0, to.1).expect("the move is picked");
+                    let sent = internal_to_network_move(&suggested).unwrap();
+                    // Shown to the client only once the server's state confirms it
+                    let ClientToServer::Move(received) = over_the_wire(&ClientToServer::Move(sent))
+                    else {
+                        panic!("the move arrived as something else");
+                    };
+                    server.validate_client_move(&received, Color::White).ok()
+                }
+            }
+            .unwrap_or_else(|| panic!("{} wasn't picked", notation));
+            server.apply_move(mv);
+
+            let state = board_state(&server, &mv).unwrap();
+            client.apply_server_message(&over_the_wire(&state)).unwrap();
+            assert_eq!(client.fen(), server.fen(), "after {}", notation);
+        }
+
+        // The pawn on e5 is blocked, and the client can't move for White
+        let (e5, e4, e2, e3) = ((3, 4), (4, 4), (6, 4), (5, 4));
+        assert!(server
+            .validate_client_move(&network_move(e5, e4), Color::White)
+            .is_err());
+        assert!(Session::new(None)
+            .unwrap()
+            .validate_client_move(&network_move(e2, e3), Color::White)
+            .is_err());
+    }
+
+    #[test]
+    fn the_client_follows_the_server_board() {
+        let (mut server, mut client) = (Session::new(None).unwrap(), Session::new(None).unwrap());
+        play(&mut server, "e2e4");
+        play(&mut client, "e2e4");
+        // The client missed e7e5, so the move after it can't be replayed there
+        play(&mut server, "e7e5");
+        play(&mut server, "g1f3");
+
+        let mv = server.played_moves[2];
+        let ServerToClient::State { board, moves, .. } = board_state(&server, &mv).unwrap() else {
+            panic!("the board state is something else");
+        };
+        assert!(client
+            .apply_network_move(&internal_to_network_move(&mv).unwrap())
+            .is_err());
+        assert_eq!(client.follow_server_board(&board, &moves), Ok(true));
+        assert!(client.squares() == server.squares());
+        assert!(client.board.get_curr_player() == Color::Black);
+        // Nothing changes once the boards agree
+        assert_eq!(client.follow_server_board(&board, &moves), Ok(false));
+    }
+
+    /// Knights going out and back twice bring the starting position up for the third time
+    #[test]
+    fn a_third_repetition_is_a_draw_or_claimable() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for claim_draws in [false, true] {
+            let mut session = Session::new(None).unwrap();
+            session.claim_draws = claim_draws;
+            for notation in shuffle.iter().chain(shuffle.iter()) {
+                assert!(session.game_state == GameState::Ongoing);
+                play(&mut session, notation);
+            }
+            let expected = match claim_draws {
+                true => GameState::Ongoing,
+                false => GameState::Repetition,
+            };
+            assert!(session.game_state == expected);
+            assert!(session.claimable == Some(GameState::Repetition));
+        }
+    }
+
+    #[test]
+    fn fifty_moves_without_a_capture_or_pawn_move_draw() {
+        let quiet = |notation| {
+            let mut session = Session::new(None).unwrap();
+            // Pretend the last 99 plies were quiet
+            *session.halfmove_clocks.last_mut().unwrap() = FIFTY_MOVE_PLIES - 1;
+            play(&mut session, notation);
+            session
+        };
+        let knight = quiet("g1f3");
+        assert!(knight.game_state == GameState::FiftyMoves);
+        assert_eq!(knight.halfmove_clocks.last(), Some(&FIFTY_MOVE_PLIES));
+
+        // A pawn move starts the count over
+        let pawn = quiet("e2e4");
+        assert!(pawn.game_state == GameState::Ongoing);
+        assert_eq!(pawn.halfmove_clocks.last(), Some(&0));
+    }
+
+    #[test]
+    fn bare_kings_end_the_game_before_anyone_moves() {
+        let session = Session::new(Some("8/8/8/4k3/8/8/8/4K3 w - - 0 1".to_owned())).unwrap();
+        assert!(session.game_state == GameState::InsufficientMaterial);
+    }
+
+    #[test]
+    fn history_lines_follow_moves_and_undo() {
+        let mut session = Session::new(None).unwrap();
+        for notation in ["e2e4", "e7e5", "g1f3"] {
+            play(&mut session, notation);
+        }
+        assert_eq!(session.history_lines, ["1. e2e4 e7e5", "2. g1f3"]);
+
+        session.undo();
+        assert_eq!(session.history_lines, ["1. e2e4 e7e5"]);
+        session.undo();
+        assert_eq!(session.history_lines, ["1. e2e4"]);
+        play(&mut session, "c7c5");
+        assert_eq!(session.history_lines, ["1. e2e4 c7c5"]);
+    }
+
+    #[test]
+    fn glyphs_show_after_their_moves() {
+        let mut session = Session::new(None).unwrap();
+        for notation in ["e2e4", "e7e5", "g1f3"] {
+            play(&mut session, notation);
+        }
+        session.set_glyph(1, Some(Glyph::Dubious));
+        session.set_glyph(2, Some(Glyph::Good));
+        assert_eq!(session.history_lines, ["1. e2e4 e7e5?!", "2. g1f3!"]);
+        session.set_notation(Some(Notation::English));
+        assert_eq!(session.history_lines, ["1. e4 e5?!", "2. Nf3!"]);
+
+        session.set_glyph(1, None);
+        assert_eq!(session.history_lines, ["1. e4 e5", "2. Nf3!"]);
+        assert_eq!(session.glyphs(), [None, None, Some(Glyph::Good)]);
+    }
+
+    #[test]
+    fn switching_notation_rewrites_the_move_list() {
+        let mut session = Session::new(None).unwrap();
+        for notation in ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"] {
+            play(&mut session, notation);
+        }
+        session.set_notation(Some(Notation::Localized(Language::Swedish)));
+        assert_eq!(session.history_lines, ["1. e4 e5", "2. Sf3 Sc6", "3. Lb5"]);
+        // Later moves follow the new notation
+        play(&mut session, "a7a6");
+        session.set_notation(Some(Notation::Figurine));
+        play(&mut session, "b5c6");
+        assert_eq!(
+            session.history_lines,
+            ["1. e4 e5", "2. ♘f3 ♘c6", "3. ♗b5 a6", "4. ♗xc6"]
+        );
+        session.set_notation(None);
+        assert_eq!(session.history_lines[3], "4. b5c6");
+    }
+
+    #[test]
+    fn the_client_loses_on_time_when_the_server_says_so() {
+        let mut server = Session::new(None).unwrap();
+        let mut client = Session::new(None).unwrap();
+        for notation in ["e2e4", "e7e5"] {
+            play(&mut server, notation);
+            play(&mut client, notation);
+        }
+        server.game_state = GameState::TimeForfeit(Color::White);
+
+        let message = error_state(&server, OUT_OF_TIME.to_owned());
+        assert!(client.apply_server_message(&message).is_ok());
+        assert!(client.game_state == GameState::TimeForfeit(Color::White));
+    }
+
+    /// A scripted client playing White mixes illegal moves in until the server gives up on it
+    #[test]
+    fn a_client_that_keeps_sending_illegal_moves_forfeits() {
+        use crate::strikes::DEFAULT_MAX_STRIKES;
+        let mut server = Session::new(None).unwrap();
+        let mut client = Session::new(None).unwrap();
+        let mut strikes = Strikes::new(2, DEFAULT_MAX_STRIKES);
+        let mut send = |server: &mut Session, from, to| {
+            let ClientToServer::Move(received) =
+                over_the_wire(&ClientToServer::Move(network_move(from, to)))
+            else {
+                panic!("the move arrived as something else");
+            };
+            server.judge_client_move(&received, Color::Black, &mut strikes)
+        };
+        let relay = |server: &mut Session, client: &mut Session, mv: Move| {
+            server.apply_move(mv);
+            let state = board_state(server, &mv).unwrap();
+            client.apply_server_message(&over_the_wire(&state)).unwrap();
+        };
+        let answer = |server: &mut Session, client: &mut Session, notation: &str| {
+            play(server, notation);
+            let mv = *server.played_moves.last().unwrap();
+            let state = board_state(server, &mv).unwrap();
+            client.apply_server_message(&over_the_wire(&state)).unwrap();
+        };
+        let (e1, e2, e3, e4, e5) = ((7, 4), (6, 4), (5, 4), (4, 4), (3, 4));
+        let (g1, f3, f5) = ((7, 6), (5, 5), (3, 5));
+
+        assert_eq!(
+            send(&mut server, e2, e5).err().unwrap().1,
+            Some(Verdict::Counted(1))
+        );
+        // Sent again before the answer came back
+        assert_eq!(
+            send(&mut server, e2, e5).err().unwrap().1,
+            Some(Verdict::Repeated)
+        );
+        let mv = send(&mut server, e2, e4).unwrap();
+        relay(&mut server, &mut client, mv);
+        answer(&mut server, &mut client, "e7e5");
+
+        // The legal move cleared the strike before this one
+        assert_eq!(
+            send(&mut server, e4, e5).err().unwrap().1,
+            Some(Verdict::Counted(1))
+        );
+        let mv = send(&mut server, g1, f3).unwrap();
+        relay(&mut server, &mut client, mv);
+        answer(&mut server, &mut client, "b8c6");
+
+        assert_eq!(
+            send(&mut server, f3, f5).err().unwrap().1,
+            Some(Verdict::Counted(1))
+        );
+        assert_eq!(
+            send(&mut server, e1, e3).err().unwrap().1,
+            Some(Verdict::Forfeit)
+        );
+        assert!(server.game_state == GameState::Forfeit(Color::White));
+        assert_eq!(pgn::result_token(&server.game_state), "0-1");
+        assert_eq!(
+            pgn::termination(&server.game_state),
+            Some(ILLEGAL_MOVES_FORFEIT)
+        );
+        // Nothing counts once the game is over
+        assert_eq!(send(&mut server, e1, e3).err().unwrap().1, None);
+
+        let message = error_state(&server, ILLEGAL_MOVES_FORFEIT.to_owned());
+        assert!(client
+            .apply_server_message(&over_the_wire(&message))
+            .is_ok());
+        assert!(client.game_state == GameState::Forfeit(Color::White));
+        assert_eq!(client.fen(), server.fen());
+    }
+
+    // White to move, with the rook able to take the king on e8 already
+    const CAPTURABLE_KING: &str = "4k3/8/8/8/8/8/8/4R1K1 w - - 0 1";
+    const ADJACENT_KINGS: &str = "8/8/8/3kK3/8/8/8/8 b - - 0 1";
+
+    #[test]
+    fn no_moves_in_a_broken_position_is_neither_mate_nor_stalemate() {
+        let capturable = crate::parse_fen(CAPTURABLE_KING).unwrap();
+        assert!(classify(false, &capturable, Color::White) == GameState::PositionError);
+        let adjacent = crate::parse_fen(ADJACENT_KINGS).unwrap();
+        assert!(classify(false, &adjacent, Color::Black) == GameState::PositionError);
+        assert!(pgn::result_token(&GameState::PositionError) == "*");
+
+        // Positions that could come up in a game still end the way they did
+        let mate = crate::parse_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1").unwrap();
+        assert!(classify(false, &mate, Color::Black) == GameState::Checkmate(Color::Black));
+        let stalemate = crate::parse_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(classify(false, &stalemate, Color::Black) == GameState::Stalemate);
+        assert!(classify(true, &stalemate, Color::Black) == GameState::Ongoing);
+    }
+
+    #[test]
+    fn broken_fens_are_turned_down_before_the_game_starts() {
+        for fen in [CAPTURABLE_KING, ADJACENT_KINGS] {
+            let err = Session::new(Some(fen.to_owned())).err().unwrap();
+            assert!(err.contains(fen), "{}", err);
+        }
+        assert!(Session::new(Some("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1".to_owned())).is_ok());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::engine::{best_move_on, squares_after, Line};
+use crate::{
+    captured_piece, is_in_check, material_value, opposite_color, parse_move, BoardRepr,
+    ExcludedMoves, Refresh, Square,
+};
+use jonathan_hallstrom_chess::{Board, Move};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The most replies worked out for one position
+pub const MAX_REPLIES: usize = 6;
+/// Working out stops once this has passed, whatever is left isn't worth the CPU
+pub const BUDGET: Duration = Duration::from_millis(250);
+// Plies the engine looks ahead for the reply it would pick itself
+const SHALLOW_DEPTH: u32 = 2;
+
+/// The opponent's replies most worth working out first, of the `moves` on `board` whose
+/// pieces are `squares`: checks, then captures and promotions by what they win, then the one
+/// the engine picks at a shallow depth, then the rest in the engine's order
+pub fn likely_replies(board: &Board, squares: &[[Square; 8]; 8], moves: &[Move]) -> Vec<Move> {
+    let opponent = opposite_color(board.get_curr_player());
+    // Given up on if it takes too long, the other tiers still stand
+    let deadline = Instant::now() + BUDGET / 4;
+    let pick = best_move_on(board, squares, SHALLOW_DEPTH, Some(deadline))
+        .unwrap_or(None)
+        .map(|mv| mv.to_algebraic_notation());
+    let mut replies: Vec<((u8, i32), Move)> = moves
+        .iter()
+        .map(|mv| {
+            let notation = mv.to_algebraic_notation();
+            let taken = parse_move(&notation)
+                .ok()
+                .and_then(|(from, to)| captured_piece(squares, from, to))
+                .map_or(0, |piece| material_value(&piece));
+            // A promotion wins at least as much as taking a pawn
+            let won = taken + mv.get_promoted_type().map_or(0, |_| 1);
+            let priority = match () {
+                _ if is_in_check(&squares_after(squares, mv), opponent) => (3, won),
+                _ if won > 0 => (2, won),
+                _ if pick.as_deref() == Some(notation.as_str()) => (1, 0),
+                _ => (0, 0),
+            };
+            (priority, *mv)
+        })
+        .collect();
+    // Stable, so moves worth the same keep the engine's order
+    replies.sort_by_key(|&((tier, won), _)| (std::cmp::Reverse(tier), -won));
+    replies
+        .into_iter()
+        .take(MAX_REPLIES)
+        .map(|(_, mv)| mv)
+        .collect()
+}
+
+/// Work out the refresh after each reply on `position` in turn until `cancelled` is set, the
+/// budget is spent or `found` can't take more
+fn work_out(
+    position: &Board,
+    replies: &[Move],
+    excluded: ExcludedMoves,
+    cancelled: &AtomicBool,
+    budget: Duration,
+    mut found: impl FnMut(String, Refresh) -> bool,
+) {
+    let started = Instant::now();
+    for reply in replies {
+        if cancelled.load(Ordering::Relaxed) || started.elapsed() > budget {
+            return;
+        }
+        let mut board = position.clone();
+        if board.play_move(*reply).is_err() {
+            continue;
+        }
+        if !found(
+            reply.to_algebraic_notation(),
+            Refresh::new(&board, excluded),
+        ) {
+            return;
+        }
+    }
+}
+
+/// The refreshes after the opponent's likely replies, worked out on a thread of its own while
+/// the opponent thinks. Only the position it was started for can use them, told by the
+/// session's generation, and the move played has to be one of them.
+pub struct Speculation {
+    generation: u64,
+    excluded: ExcludedMoves,
+    cancelled: Arc<AtomicBool>,
+    receiver: Receiver<(String, Refresh)>,
+    ready: Vec<(String, Refresh)>,
+}
+
+impl Speculation {
+    /// Start working out the replies in the position at the end of `line`, shown by `repr`
+    pub fn start(line: Line, repr: &BoardRepr, generation: u64) -> Self {
+        let (sender, receiver) = channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = Arc::clone(&cancelled);
+        let (squares, moves) = (repr.squares, repr.moves.clone());
+        let excluded = repr.excluded;
+        thread::spawn(move || {
+            // Replayed once, each reply is played on a clone of it
+            let Some(position) = line.board() else {
+                return;
+            };
+            let replies = likely_replies(&position, &squares, &moves);
+            work_out(
+                &position,
+                &replies,
+                excluded,
+                &thread_cancelled,
+                BUDGET,
+                |notation, refresh| sender.send((notation, refresh)).is_ok(),
+            );
+        });
+        Self {
+            generation,
+            excluded,
+            cancelled,
+            receiver,
+            ready: Vec::new(),
+        }
+    }
+
+    /// The generation of the position it works out replies in
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Work out no more, what is already worked out can still be taken
+    #[inline]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// The refresh after `mv`, if it was worked out for this very position with the same
+    /// moves excluded
+    pub fn take(&mut self, generation: u64, excluded: ExcludedMoves, mv: &Move) -> Option<Refresh> {
+        if generation != self.generation || excluded != self.excluded {
+            return None;
+        }
+        self.ready.extend(self.receiver.try_iter());
+        let notation = mv.to_algebraic_notation();
+        let at = self
+            .ready
+            .iter()
+            .position(|(ready, _)| *ready == notation)?;
+        Some(self.ready.swap_remove(at).1)
+    }
+}
+
+impl Drop for Speculation {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_from_fen;
+    use crate::session::Session;
+
+    fn replies(fen: &str) -> Vec<String> {
+        let board = board_from_fen(fen).unwrap();
+        let repr = BoardRepr::new(&board);
+        likely_replies(&board, &repr.squares, &repr.moves)
+            .iter()
+            .map(Move::to_algebraic_notation)
+            .collect()
+    }
+
+    fn worked_out(fen: &str) -> Vec<(String, Refresh)> {
+        let board = board_from_fen(fen).unwrap();
+        let repr = BoardRepr::new(&board);
+        let mut found = Vec::new();
+        work_out(
+            &board,
+            &likely_replies(&board, &repr.squares, &repr.moves),
+            repr.excluded,
+            &AtomicBool::new(false),
+            Duration::MAX,
+            |notation, refresh| {
+                found.push((notation, refresh));
+                true
+            },
+        );
+        found
+    }
+
+    #[test]
+    fn captures_of_the_most_valuable_pieces_come_first() {
+        // The knight on c3 can take the queen on d5 or the pawn on e4
+        let replies = replies("4k3/8/8/3q4/4p3/2N5/8/4K3 w - - 0 1");
+        assert_eq!(replies.len(), MAX_REPLIES);
+        assert_eq!(replies[..2], ["c3d5", "c3e4"]);
+    }
+
+    #[test]
+    fn checks_come_before_captures_and_the_engine_pick_after() {
+        // The rook checks on a8 or takes the knight on c1
+        let replies = replies("4k3/8/8/8/8/8/7K/R1n5 w - - 0 1");
+        assert_eq!(replies[..2], ["a1a8", "a1c1"]);
+
+        // Nothing checks or takes at the start, the engine's own pick goes first
+        let board = Board::default();
+        let repr = BoardRepr::new(&board);
+        let pick = best_move_on(&board, &repr.squares, SHALLOW_DEPTH, None).unwrap();
+        let replies = likely_replies(&board, &repr.squares, &repr.moves);
+        assert_eq!(
+            replies.first().map(Move::to_algebraic_notation),
+            pick.map(|mv| mv.to_algebraic_notation())
+        );
+    }
+
+    #[test]
+    fn worked_out_refreshes_match_fresh_ones() {
+        for fen in [
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            // En passant and a promotion
+            "4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        ] {
+            let found = worked_out(fen);
+            assert!(!found.is_empty());
+            for (notation, refresh) in found {
+                let mut fresh = Session::new(Some(fen.to_owned())).unwrap();
+                let mut prepared = Session::new(Some(fen.to_owned())).unwrap();
+                let mv = fresh
+                    .board
+                    .get_legal_moves()
+                    .into_iter()
+                    .find(|mv| mv.to_algebraic_notation() == notation)
+                    .unwrap();
+                fresh.apply_move(mv);
+                assert!(refresh.same_as(&Refresh::new(&fresh.board, fresh.board_repr.excluded)));
+                prepared.apply_prepared_move(mv, refresh);
+                assert!(fresh.board_repr.squares == prepared.board_repr.squares);
+                assert_eq!(
+                    fresh.board_repr.checked_king,
+                    prepared.board_repr.checked_king
+                );
+                assert_eq!(fresh.move_notations(), prepared.move_notations());
+                assert!(fresh.game_state == prepared.game_state);
+            }
+        }
+    }
+
+    #[test]
+    fn cancelling_stops_the_work() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let board = board_from_fen(fen).unwrap();
+        let repr = BoardRepr::new(&board);
+        let replies = likely_replies(&board, &repr.squares, &repr.moves);
+        let cancelled = AtomicBool::new(true);
+        let mut found = 0;
+        work_out(
+            &board,
+            &replies,
+            repr.excluded,
+            &cancelled,
+            Duration::MAX,
+            |_, _| {
+                found += 1;
+                true
+            },
+        );
+        assert_eq!(found, 0);
+        // A message arriving halfway through stops it after the reply being worked out
+        cancelled.store(false, Ordering::Relaxed);
+        work_out(
+            &board,
+            &replies,
+            repr.excluded,
+            &cancelled,
+            Duration::MAX,
+            |_, _| {
+                found += 1;
+                cancelled.store(true, Ordering::Relaxed);
+                true
+            },
+        );
+        assert_eq!(found, 1);
+    }
+
+    #[test]
+    fn a_stale_generation_takes_nothing() {
+        let fen = "4k3/8/8/3q4/4p3/2N5/8/4K3 w - - 0 1";
+        let board = board_from_fen(fen).unwrap();
+        let repr = BoardRepr::new(&board);
+        let (sender, receiver) = channel();
+        let mut speculation = Speculation {
+            generation: 4,
+            excluded: repr.excluded,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            receiver,
+            ready: Vec::new(),
+        };
+        for (notation, refresh) in worked_out(fen) {
+            sender.send((notation, refresh)).unwrap();
+        }
+        let mv = likely_replies(&board, &repr.squares, &repr.moves)[0];
+        assert!(speculation.take(5, repr.excluded, &mv).is_none());
+        let excluded = ExcludedMoves {
+            castling: true,
+            ..repr.excluded
+        };
+        assert!(speculation.take(4, excluded, &mv).is_none());
+        assert!(speculation.take(4, repr.excluded, &mv).is_some());
+        // Each refresh is only taken once
+        assert!(speculation.take(4, repr.excluded, &mv).is_none());
+    }
+}
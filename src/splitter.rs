@@ -0,0 +1,415 @@
+use crate::ease_out;
+use crate::layout::Orientation;
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::time::Duration;
+
+/// Kept in ggez's user data directory next to the autosave
+pub const FILE_NAME: &str = "layout.json";
+// Part of the window the history panel gets along the splitter's axis until it is dragged
+pub const DEFAULT_PANEL_FRACTION: f32 = 0.2;
+// The board never gets narrower than this part of the smaller window dimension
+const MIN_BOARD_FRACTION: f32 = 0.6;
+// Narrowest panel that still fits a line of the move list, unless it is collapsed
+const MIN_PANEL_WIDTH: f32 = 120.0;
+// How far from the edge between the board and the panel a press still grabs it
+pub const GRAB_DISTANCE: f32 = 4.0;
+// How far a pressed splitter has to move before it is dragged rather than clicked
+const DRAG_THRESHOLD: f32 = 3.0;
+// Two presses on the splitter this close together collapse or expand the panel
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+const COLLAPSE_DURATION: Duration = Duration::from_millis(200);
+
+/// How the window is split between the board and the history panel, saved between runs
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Layout {
+    // Part of the window the panel gets along the splitter's axis while it is expanded
+    pub panel_fraction: f32,
+    pub collapsed: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            panel_fraction: DEFAULT_PANEL_FRACTION,
+            collapsed: false,
+        }
+    }
+}
+
+impl Layout {
+    fn to_json(self) -> Value {
+        let mut object = Map::new();
+        object.insert(
+            "panel_fraction".to_owned(),
+            serde_json::Number::from_f64(self.panel_fraction.into())
+                .map_or(Value::Null, Value::Number),
+        );
+        object.insert("collapsed".to_owned(), Value::Bool(self.collapsed));
+        Value::Object(object)
+    }
+
+    fn from_json(value: &Value) -> Result<Self, String> {
+        Ok(Self {
+            panel_fraction: value
+                .get("panel_fraction")
+                .and_then(Value::as_f64)
+                .filter(|fraction| (0.0..1.0).contains(fraction))
+                .ok_or("\"panel_fraction\" must be a number from 0 to 1")?
+                as f32,
+            collapsed: value
+                .get("collapsed")
+                .and_then(Value::as_bool)
+                .ok_or("\"collapsed\" must be true or false")?,
+        })
+    }
+}
+
+/// The panel's part of the window along the splitter's axis kept within bounds for a window
+/// `along` long on that axis and `across` on the other: the board keeps at least 60% of the
+/// smaller dimension and the panel stays usable
+pub fn clamp_panel_fraction(fraction: f32, along: f32, across: f32) -> f32 {
+    if along <= 0.0 {
+        return fraction;
+    }
+    let widest = (along - MIN_BOARD_FRACTION * along.min(across)).max(0.0);
+    // A window too small for both gives the board the room
+    let narrowest = MIN_PANEL_WIDTH.min(widest);
+    (fraction * along).clamp(narrowest, widest) / along
+}
+
+/// Where the pointer is with the splitter
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Drag {
+    Idle,
+    Hover,
+    // Pressed at `x` but not moved far enough to drag yet, the layout before it is kept for
+    // cancelling
+    Pressed { x: f32, before: Layout },
+    Dragging { before: Layout },
+}
+
+/// What a press did with the splitter
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Press {
+    // Nowhere near it, the press is for the board or the panel
+    Missed,
+    Grabbed,
+    // The second press of a double-click collapsed or expanded the panel
+    Toggled,
+}
+
+/// The draggable edge between the board and the history panel, upright while the panel is
+/// beside the board and lying down while it is below it
+pub struct Splitter {
+    pub layout: Layout,
+    pub drag: Drag,
+    // Size of the window, the panel is kept within bounds for it
+    width: f32,
+    height: f32,
+    orientation: Orientation,
+    // When the splitter was last pressed, for telling double-clicks apart
+    last_press: Option<Duration>,
+    // When the panel last started collapsing or expanding, and its width then
+    toggled: Option<(Duration, f32)>,
+}
+
+impl Splitter {
+    pub fn new(layout: Layout, width: f32, height: f32) -> Self {
+        Self {
+            layout,
+            drag: Drag::Idle,
+            width,
+            height,
+            orientation: Orientation::Landscape,
+            last_press: None,
+            toggled: None,
+        }
+    }
+
+    /// The window's length along the splitter's axis and across it
+    #[inline]
+    fn extents(&self) -> (f32, f32) {
+        match self.orientation {
+            Orientation::Landscape => (self.width, self.height),
+            Orientation::Portrait => (self.height, self.width),
+        }
+    }
+
+    /// The panel's part of the window as drawn now, sliding while it collapses or expands
+    pub fn panel_fraction(&self, now: Duration) -> f32 {
+        let (along, across) = self.extents();
+        let target = match self.layout.collapsed {
+            true => 0.0,
+            false => clamp_panel_fraction(self.layout.panel_fraction, along, across),
+        };
+        match self.toggled {
+            Some((start, from)) => {
+                let t = (now.saturating_sub(start).as_secs_f32() / COLLAPSE_DURATION.as_secs_f32())
+                    .min(1.0);
+                from + (target - from) * ease_out(t)
+            }
+            None => target,
+        }
+    }
+
+    /// Whether the pointer should show that the splitter can be dragged
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.drag != Drag::Idle
+    }
+
+    #[inline]
+    fn is_near(x: f32, edge: f32) -> bool {
+        (x - edge).abs() <= GRAB_DISTANCE
+    }
+
+    /// The pointer moved to `x` along the splitter's axis, `edge` being where the panel starts
+    pub fn moved(&mut self, x: f32, edge: f32) {
+        let (along, across) = self.extents();
+        self.drag = match self.drag {
+            Drag::Pressed { x: pressed, before } if (x - pressed).abs() < DRAG_THRESHOLD => {
+                Drag::Pressed { x: pressed, before }
+            }
+            Drag::Pressed { before, .. } | Drag::Dragging { before } => {
+                self.layout = Layout {
+                    panel_fraction: clamp_panel_fraction((along - x) / along, along, across),
+                    collapsed: false,
+                };
+                self.toggled = None;
+                Drag::Dragging { before }
+            }
+            _ if Self::is_near(x, edge) => Drag::Hover,
+            _ => Drag::Idle,
+        };
+    }
+
+    pub fn pressed(&mut self, x: f32, edge: f32, now: Duration) -> Press {
+        if !Self::is_near(x, edge) {
+            return Press::Missed;
+        }
+        let double = self
+            .last_press
+            .is_some_and(|last| now.saturating_sub(last) <= DOUBLE_CLICK);
+        if double {
+            self.toggled = Some((now, self.panel_fraction(now)));
+            self.layout.collapsed = !self.layout.collapsed;
+            self.last_press = None;
+            self.drag = Drag::Hover;
+            return Press::Toggled;
+        }
+        self.last_press = Some(now);
+        self.drag = Drag::Pressed {
+            x,
+            before: self.layout,
+        };
+        Press::Grabbed
+    }
+
+    /// Let go of the splitter, true if a drag changed the layout and it should be saved
+    pub fn released(&mut self) -> bool {
+        match self.drag {
+            Drag::Dragging { .. } => {
+                self.drag = Drag::Hover;
+                // A drag is never the first half of a double-click
+                self.last_press = None;
+                true
+            }
+            Drag::Pressed { .. } => {
+                self.drag = Drag::Hover;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// The window changed size, a drag going on is undone since its pointer positions
+    /// belong to the old size
+    pub fn resized(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+        self.cancel_drag();
+    }
+
+    /// The panel moved beside or below the board, the splitter turns with it
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        if orientation != self.orientation {
+            self.orientation = orientation;
+            self.cancel_drag();
+        }
+    }
+
+    #[inline]
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    fn cancel_drag(&mut self) {
+        if let Drag::Pressed { before, .. } | Drag::Dragging { before } = self.drag {
+            self.layout = before;
+            self.drag = Drag::Idle;
+        }
+    }
+}
+
+/// Replace the saved layout, a failure only costs the layout
+pub fn write(path: &Path, layout: Layout) {
+    let written = (|| -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string(&layout.to_json())?)
+    })();
+    if let Err(err) = written {
+        println!("Can't save the layout to {}: {}", path.display(), err);
+    }
+}
+
+/// The saved layout, the default one if there is none or it is damaged
+pub fn read(path: &Path) -> Layout {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Layout::default();
+    };
+    let parsed = serde_json::from_str::<Value>(&text)
+        .map_err(|err| err.to_string())
+        .and_then(|value| Layout::from_json(&value));
+    match parsed {
+        Ok(layout) => layout,
+        Err(message) => {
+            println!(
+                "Ignoring the damaged layout {}: {}",
+                path.display(),
+                message
+            );
+            Layout::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    fn at(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn assert_close(fraction: f32, expected: f32) {
+        assert!(
+            (fraction - expected).abs() < 1e-4,
+            "{} isn't {}",
+            fraction,
+            expected
+        );
+    }
+
+    #[test]
+    fn the_split_stays_within_bounds() {
+        // Plenty of room, the fraction is only kept between the bounds
+        assert_close(clamp_panel_fraction(0.2, 1000.0, 800.0), 0.2);
+        assert_close(clamp_panel_fraction(0.0, 1000.0, 800.0), 0.12);
+        assert_close(clamp_panel_fraction(0.9, 1000.0, 800.0), 0.52);
+        // A wide window can give the panel all but the board's 60% of the height
+        assert_close(clamp_panel_fraction(0.9, 2000.0, 500.0), 0.85);
+        // A tall one is limited by its width
+        assert_close(clamp_panel_fraction(0.9, 500.0, 2000.0), 0.4);
+        // Too narrow for a usable panel next to the board, the board wins
+        assert_close(clamp_panel_fraction(0.3, 200.0, 200.0), 0.4);
+        assert_close(clamp_panel_fraction(0.9, 0.0, 0.0), 0.9);
+    }
+
+    #[test]
+    fn layouts_survive_the_round_trip() {
+        let layout = Layout {
+            panel_fraction: 0.35,
+            collapsed: true,
+        };
+        assert_eq!(Layout::from_json(&layout.to_json()), Ok(layout));
+
+        let scratch = Scratch::new("layout");
+        let path = scratch.join("layout.json");
+        write(&path, layout);
+        assert_eq!(read(&path), layout);
+        std::fs::write(&path, "{\"panel_fraction\": 7}").unwrap();
+        assert_eq!(read(&path), Layout::default());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read(&path), Layout::default());
+    }
+
+    #[test]
+    fn a_double_click_collapses_and_expands_the_panel() {
+        let mut splitter = Splitter::new(Layout::default(), 1000.0, 800.0);
+        assert_eq!(splitter.pressed(500.0, 800.0, at(0)), Press::Missed);
+
+        assert_eq!(splitter.pressed(801.0, 800.0, at(1000)), Press::Grabbed);
+        splitter.released();
+        assert_eq!(splitter.pressed(799.0, 800.0, at(1200)), Press::Toggled);
+        assert!(splitter.layout.collapsed);
+        // Slides shut, the width to come back to is kept
+        assert!(splitter.panel_fraction(at(1250)) > 0.0);
+        assert_eq!(splitter.panel_fraction(at(2000)), 0.0);
+        assert_eq!(splitter.layout.panel_fraction, DEFAULT_PANEL_FRACTION);
+
+        // Presses too far apart aren't a double-click
+        assert_eq!(splitter.pressed(1000.0, 1000.0, at(3000)), Press::Grabbed);
+        splitter.released();
+        assert_eq!(splitter.pressed(1000.0, 1000.0, at(4000)), Press::Grabbed);
+        splitter.released();
+        assert_eq!(splitter.pressed(1000.0, 1000.0, at(4100)), Press::Toggled);
+        assert!(!splitter.layout.collapsed);
+        assert_close(splitter.panel_fraction(at(5000)), DEFAULT_PANEL_FRACTION);
+    }
+
+    #[test]
+    fn dragging_moves_the_edge() {
+        let mut splitter = Splitter::new(Layout::default(), 1000.0, 800.0);
+        splitter.moved(790.0, 800.0);
+        assert_eq!(splitter.drag, Drag::Idle);
+        splitter.moved(798.0, 800.0);
+        assert_eq!(splitter.drag, Drag::Hover);
+
+        splitter.pressed(798.0, 800.0, at(0));
+        // A wobble while pressing isn't a drag yet
+        splitter.moved(799.0, 800.0);
+        assert!(matches!(splitter.drag, Drag::Pressed { .. }));
+        splitter.moved(700.0, 800.0);
+        assert!(matches!(splitter.drag, Drag::Dragging { .. }));
+        assert_close(splitter.panel_fraction(at(10)), 0.3);
+        assert!(splitter.released());
+        assert_eq!(splitter.drag, Drag::Hover);
+        assert_close(splitter.layout.panel_fraction, 0.3);
+    }
+
+    #[test]
+    fn resizing_cancels_a_drag() {
+        let mut splitter = Splitter::new(Layout::default(), 1000.0, 800.0);
+        splitter.pressed(800.0, 800.0, at(0));
+        splitter.moved(600.0, 800.0);
+        assert_close(splitter.layout.panel_fraction, 0.4);
+
+        splitter.resized(1200.0, 800.0);
+        assert_eq!(splitter.drag, Drag::Idle);
+        assert_eq!(splitter.layout, Layout::default());
+        // The release that follows has nothing to save
+        assert!(!splitter.released());
+    }
+
+    #[test]
+    fn a_lying_splitter_is_dragged_up_and_down() {
+        let mut splitter = Splitter::new(Layout::default(), 600.0, 1000.0);
+        splitter.set_orientation(Orientation::Portrait);
+        // The panel is kept within bounds for the height
+        assert_close(splitter.panel_fraction(at(0)), DEFAULT_PANEL_FRACTION);
+        splitter.pressed(800.0, 800.0, at(0));
+        splitter.moved(700.0, 800.0);
+        assert_close(splitter.layout.panel_fraction, 0.3);
+        splitter.moved(100.0, 800.0);
+        assert_close(splitter.layout.panel_fraction, 0.64);
+
+        // Turning back cancels the drag like a resize
+        splitter.set_orientation(Orientation::Landscape);
+        assert_eq!(splitter.drag, Drag::Idle);
+        assert_eq!(splitter.layout, Layout::default());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::theme::Theme;
+use chess_gui::piece_style::{self, PieceStyles, Rgb};
+use chess_gui::{sprite_cell, Square};
+use ggez::graphics::{
+    self, Canvas, DrawMode, DrawParam, Image, ImageFormat, Mesh, Rect, Transform,
+};
+use ggez::Context;
+use mint::{Point2, Vector2};
+
+static PIECES_IMAGE_BYTES: &[u8] = include_bytes!("Pieces.png");
+// How far outlines reach past the pieces, in squares
+const OUTLINE_WIDTH: f32 = 1.0 / 24.0;
+// The badge's radius and how far its centre is from the square's top right corner, in squares
+const BADGE_RADIUS: f32 = 0.09;
+const BADGE_INSET: f32 = 0.14;
+
+/// The embedded sprite sheet unless the theme has one that loads
+fn pieces_image(ctx: &Context, theme: &Theme) -> Image {
+    if let Some(path) = &theme.pieces {
+        match std::fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| Image::from_bytes(ctx, &bytes).map_err(|err| err.to_string()))
+        {
+            Ok(image) => return image,
+            Err(err) => println!("Can't load pieces from {}: {}", path.display(), err),
+        }
+    }
+    Image::from_bytes(ctx, PIECES_IMAGE_BYTES).unwrap()
+}
+
+/// The silhouettes of the pieces in `image`, white so they can be drawn in any color
+fn outlines(ctx: &Context, image: &Image) -> Option<Image> {
+    let (width, height) = (image.width(), image.height());
+    let pixels = image
+        .to_pixels(ctx)
+        .map_err(|err| println!("Can't outline the pieces: {}", err))
+        .ok()?;
+    let radius = (width as f32 / 6.0 * OUTLINE_WIDTH).ceil() as usize;
+    let Some(silhouettes) =
+        piece_style::silhouettes(&pixels, (width as usize, height as usize), (6, 2), radius)
+    else {
+        println!("The pieces are too big to outline");
+        return None;
+    };
+    Some(Image::from_pixels(
+        ctx,
+        &silhouettes,
+        ImageFormat::Rgba8UnormSrgb,
+        width,
+        height,
+    ))
+}
+
+#[inline]
+fn solid(Rgb(r, g, b): Rgb, alpha: f32) -> graphics::Color {
+    graphics::Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, alpha)
+}
+
+/// The piece sprites and how each side's are styled. Nothing else can get at the sprite
+/// sheet, so the board, the animations, the promotion choices, the captured pieces and the
+/// legend all draw their pieces through `draw`, with the same styles.
+pub(crate) struct Sprites {
+    image: Image,
+    // Made from the sprite sheet when a side has an outline, and again with the theme
+    outlines: Option<Image>,
+    // A white circle of radius 1 around the origin
+    badge: Mesh,
+    styles: PieceStyles,
+}
+
+impl Sprites {
+    pub(crate) fn new(ctx: &Context, theme: &Theme, styles: PieceStyles) -> Self {
+        let image = pieces_image(ctx, theme);
+        Self {
+            outlines: styles.outlined().then(|| outlines(ctx, &image)).flatten(),
+            image,
+            badge: Mesh::new_circle(
+                ctx,
+                DrawMode::fill(),
+                Point2 { x: 0.0, y: 0.0 },
+                1.0,
+                0.01,
+                graphics::Color::WHITE,
+            )
+            .unwrap(),
+            styles,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn styles(&self) -> PieceStyles {
+        self.styles
+    }
+
+    /// Draw the sprite of a piece scaled to fill `dest`, in `color` as well as the side's tint
+    pub(crate) fn draw(
+        &self,
+        canvas: &mut Canvas,
+        piece: &Square,
+        dest: Rect,
+        color: graphics::Color,
+    ) {
+        let (Some((sprite_col, sprite_row)), Some(side)) = (sprite_cell(piece), piece.color())
+        else {
+            return;
+        };
+        let style = self.styles.side(side);
+        let param = |image: &Image, color: graphics::Color| DrawParam {
+            src: Rect::new(
+                sprite_col as f32 / 6.0,
+                sprite_row as f32 / 2.0,
+                1.0 / 6.0,
+                1.0 / 2.0,
+            ),
+            color,
+            transform: Transform::Values {
+                dest: Point2 {
+                    x: dest.x,
+                    y: dest.y,
+                },
+                rotation: 0.0,
+                scale: Vector2 {
+                    x: (dest.w * 6.0) / image.width() as f32,
+                    y: (dest.h * 2.0) / image.height() as f32,
+                },
+                offset: Point2 { x: 0.0, y: 0.0 },
+            },
+            z: 0,
+        };
+
+        // Behind the piece, faded along with it
+        if let (Some(outlines), Some(outline)) = (&self.outlines, style.outline) {
+            canvas.draw(outlines, param(outlines, solid(outline, color.a)));
+        }
+        canvas.draw(
+            &self.image,
+            param(
+                &self.image,
+                piece_style::tinted(color.into(), style.tint).into(),
+            ),
+        );
+        if let Some(badge) = style.badge {
+            canvas.draw(
+                &self.badge,
+                DrawParam::default()
+                    .dest(Point2 {
+                        x: dest.x + dest.w * (1.0 - BADGE_INSET),
+                        y: dest.y + dest.h * BADGE_INSET,
+                    })
+                    .scale(Vector2 {
+                        x: dest.w * BADGE_RADIUS,
+                        y: dest.h * BADGE_RADIUS,
+                    })
+                    .color(solid(badge, color.a)),
+            );
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+/// How long after going past the cap the opponent still has before the win can be claimed
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(5 * 60);
+
+/// How long the opponent has been thinking about its move, as far as the cap is concerned
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Stall {
+    // It isn't the opponent's turn, the game is over or the connection is lost
+    Idle,
+    // Within the cap
+    Thinking,
+    // Past the cap by this much, still within the grace period
+    Over(Duration),
+    // Past the cap and the grace period, without a move for this long
+    Claimable(Duration),
+}
+
+/// Times the opponent's moves in an untimed networked game against a hard cap per move.
+/// The peer never agreed to the cap, so all it can lead to is a claim made on this side.
+pub struct StallWatch {
+    cap: Duration,
+    grace: Duration,
+    // The ply the opponent is thinking about and when its turn began
+    turn: Option<(usize, Duration)>,
+}
+
+impl StallWatch {
+    pub fn new(cap: Duration, grace: Duration) -> Self {
+        Self {
+            cap,
+            grace,
+            turn: None,
+        }
+    }
+
+    #[inline]
+    pub fn cap(&self) -> Duration {
+        self.cap
+    }
+
+    /// Where the opponent stands at `now`. `waiting` is whether it is the opponent's turn
+    /// in a running game, and `connected` whether the peer is still there, a lost connection
+    /// is never counted as stalling. The turn is timed from the first call with both at this
+    /// `ply`, which is when the move that handed it over arrived.
+    pub fn status(&mut self, now: Duration, ply: usize, waiting: bool, connected: bool) -> Stall {
+        if !waiting || !connected {
+            self.turn = None;
+            return Stall::Idle;
+        }
+        let started = match self.turn {
+            Some((turn, started)) if turn == ply => started,
+            _ => {
+                self.turn = Some((ply, now));
+                now
+            }
+        };
+        let thinking = now.saturating_sub(started);
+        match thinking.checked_sub(self.cap) {
+            None => Stall::Thinking,
+            Some(over) if over < self.grace => Stall::Over(over),
+            Some(_) => Stall::Claimable(thinking),
+        }
+    }
+}
+
+/// A duration like "10m", "90s", "1h" or "45", which is seconds
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.char_indices().last()? {
+        (at, 's') => (&text[..at], 1.0),
+        (at, 'm') => (&text[..at], 60.0),
+        (at, 'h') => (&text[..at], 3600.0),
+        _ => (text, 1.0),
+    };
+    let seconds = number.parse::<f64>().ok()? * unit;
+    (seconds.is_finite() && seconds > 0.0).then(|| Duration::from_secs_f64(seconds))
+}
+
+/// A whole number of minutes or seconds, like "25m" in "claimed after 25m without a move"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        _ if seconds >= 60 && seconds.is_multiple_of(60) => format!("{}m", seconds / 60),
+        _ if seconds >= 60 => format!("{}m{}s", seconds / 60, seconds % 60),
+        _ => format!("{}s", seconds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn watch() -> StallWatch {
+        StallWatch::new(10 * MINUTE, 15 * MINUTE)
+    }
+
+    #[test]
+    fn the_cap_warns_and_the_grace_period_allows_a_claim() {
+        let mut watch = watch();
+        let start = 3 * MINUTE;
+        assert_eq!(watch.status(start, 4, true, true), Stall::Thinking);
+        assert_eq!(
+            watch.status(start + 10 * MINUTE - Duration::from_secs(1), 4, true, true),
+            Stall::Thinking
+        );
+        assert_eq!(
+            watch.status(start + 12 * MINUTE, 4, true, true),
+            Stall::Over(2 * MINUTE)
+        );
+        assert_eq!(
+            watch.status(start + 25 * MINUTE, 4, true, true),
+            Stall::Claimable(25 * MINUTE)
+        );
+    }
+
+    #[test]
+    fn every_turn_is_timed_from_its_own_start() {
+        let mut watch = watch();
+        assert_eq!(watch.status(Duration::ZERO, 1, true, true), Stall::Thinking);
+        // Our own turn in between
+        assert_eq!(watch.status(9 * MINUTE, 2, false, true), Stall::Idle);
+        // The opponent's next turn began when our move went out at 20 minutes
+        assert_eq!(watch.status(20 * MINUTE, 3, true, true), Stall::Thinking);
+        assert_eq!(watch.status(29 * MINUTE, 3, true, true), Stall::Thinking);
+        assert_eq!(
+            watch.status(31 * MINUTE, 3, true, true),
+            Stall::Over(MINUTE)
+        );
+        // A new ply without our turn in between, like a move the server took back
+        assert_eq!(watch.status(32 * MINUTE, 5, true, true), Stall::Thinking);
+    }
+
+    #[test]
+    fn a_lost_connection_never_stalls() {
+        let mut watch = watch();
+        watch.status(Duration::ZERO, 1, true, true);
+        for minutes in [5, 12, 30, 60] {
+            assert_eq!(watch.status(minutes * MINUTE, 1, true, false), Stall::Idle);
+        }
+        // Back again, the time without a connection isn't held against the opponent
+        assert_eq!(watch.status(61 * MINUTE, 1, true, true), Stall::Thinking);
+        assert_eq!(
+            watch.status(72 * MINUTE, 1, true, true),
+            Stall::Over(MINUTE)
+        );
+    }
+
+    #[test]
+    fn durations_parse_and_format() {
+        assert_eq!(parse_duration("10m"), Some(10 * MINUTE));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h"), Some(60 * MINUTE));
+        assert_eq!(parse_duration("45"), Some(Duration::from_secs(45)));
+        assert_eq!(parse_duration("1.5m"), Some(Duration::from_secs(90)));
+        for invalid in ["", "m", "0m", "-1m", "10x", "inf"] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+        assert_eq!(format_duration(25 * MINUTE), "25m");
+        assert_eq!(format_duration(Duration::from_secs(90)), "1m30s");
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+    }
+}
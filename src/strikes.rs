@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+
+/// Rejected moves in a row after which the server gives the game to the other side
+pub const DEFAULT_MAX_STRIKES: u32 = 5;
+
+/// Why the server rejected a move from the client, each kind is counted on its own
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub enum Strike {
+    // A move that isn't legal in the position
+    Illegal,
+    // A move sent out of turn or after the game ended
+    Protocol,
+}
+
+/// What a rejected move counts for
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Verdict {
+    // The same move was already rejected this turn, it was only sent again
+    Repeated,
+    // How many strikes of its kind there are now
+    Counted(u32),
+    // That was one too many, the client loses the game
+    Forfeit,
+}
+
+/// Counts the moves the server rejects from the client since the last one it played,
+/// for each kind of strike against its own limit. A limit of 0 never forfeits.
+pub struct Strikes {
+    max_illegal: u32,
+    max_protocol: u32,
+    illegal: u32,
+    protocol: u32,
+    // The ply the rejected moves were sent at and the moves as they arrived
+    turn: usize,
+    rejected: HashSet<(Strike, String)>,
+}
+
+impl Strikes {
+    pub fn new(max_illegal: u32, max_protocol: u32) -> Self {
+        Self {
+            max_illegal,
+            max_protocol,
+            illegal: 0,
+            protocol: 0,
+            turn: 0,
+            rejected: HashSet::new(),
+        }
+    }
+
+    /// Count a move rejected at `ply`, `raw` is the move as it arrived so a resent one is known
+    pub fn reject(&mut self, strike: Strike, ply: usize, raw: &str) -> Verdict {
+        if ply != self.turn {
+            self.turn = ply;
+            self.rejected.clear();
+        }
+        if !self.rejected.insert((strike, raw.to_owned())) {
+            return Verdict::Repeated;
+        }
+        let (count, max) = match strike {
+            Strike::Illegal => (&mut self.illegal, self.max_illegal),
+            Strike::Protocol => (&mut self.protocol, self.max_protocol),
+        };
+        *count += 1;
+        match max {
+            0 => Verdict::Counted(*count),
+            max if *count >= max => Verdict::Forfeit,
+            _ => Verdict::Counted(*count),
+        }
+    }
+
+    /// A move was played, the client starts over with no strikes
+    #[inline]
+    pub fn accept(&mut self) {
+        self.illegal = 0;
+        self.protocol = 0;
+        self.rejected.clear();
+    }
+
+    #[inline]
+    pub fn count(&self, strike: Strike) -> u32 {
+        match strike {
+            Strike::Illegal => self.illegal,
+            Strike::Protocol => self.protocol,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_strike_forfeits() {
+        let mut strikes = Strikes::new(3, 2);
+        assert_eq!(strikes.reject(Strike::Illegal, 0, "a"), Verdict::Counted(1));
+        assert_eq!(strikes.reject(Strike::Illegal, 0, "b"), Verdict::Counted(2));
+        // Protocol strikes have their own count and limit
+        assert_eq!(
+            strikes.reject(Strike::Protocol, 0, "a"),
+            Verdict::Counted(1)
+        );
+        assert_eq!(strikes.count(Strike::Illegal), 2);
+        assert_eq!(strikes.reject(Strike::Illegal, 0, "c"), Verdict::Forfeit);
+        assert_eq!(strikes.reject(Strike::Protocol, 0, "b"), Verdict::Forfeit);
+    }
+
+    #[test]
+    fn a_played_move_clears_the_strikes() {
+        let mut strikes = Strikes::new(2, 2);
+        assert_eq!(strikes.reject(Strike::Illegal, 0, "a"), Verdict::Counted(1));
+        assert_eq!(
+            strikes.reject(Strike::Protocol, 0, "b"),
+            Verdict::Counted(1)
+        );
+        strikes.accept();
+        assert_eq!(strikes.count(Strike::Illegal), 0);
+        assert_eq!(strikes.count(Strike::Protocol), 0);
+        assert_eq!(strikes.reject(Strike::Illegal, 2, "a"), Verdict::Counted(1));
+    }
+
+    #[test]
+    fn a_resent_move_counts_once_a_turn() {
+        let mut strikes = Strikes::new(2, 0);
+        assert_eq!(strikes.reject(Strike::Illegal, 4, "a"), Verdict::Counted(1));
+        assert_eq!(strikes.reject(Strike::Illegal, 4, "a"), Verdict::Repeated);
+        assert_eq!(strikes.count(Strike::Illegal), 1);
+        // The same move sent at another point of the game is a new mistake
+        assert_eq!(strikes.reject(Strike::Illegal, 6, "a"), Verdict::Forfeit);
+    }
+
+    #[test]
+    fn a_limit_of_zero_never_forfeits() {
+        let mut strikes = Strikes::new(0, 0);
+        for i in 0..100 {
+            let verdict = strikes.reject(Strike::Illegal, 0, &i.to_string());
+            assert_eq!(verdict, Verdict::Counted(i + 1));
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// Enough for the move list, the clocks and every label on screen at once
+pub const CAPACITY: usize = 256;
+
+struct Entry<V> {
+    text: String,
+    scale: u32,
+    value: V,
+    // When it was last looked up, by the cache's own count
+    used: u64,
+}
+
+/// Laid out texts by their string and scale, so the same text isn't laid out again every frame.
+/// The least recently used one is dropped once it holds `CAPACITY` of them.
+/// Looking up doesn't allocate, entries are found by a hash of the string and scale.
+pub struct TextCache<V> {
+    entries: HashMap<u64, Entry<V>>,
+    uses: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl<V> Default for TextCache<V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            uses: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+#[inline]
+fn key(text: &str, scale: u32) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    scale.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<V> TextCache<V> {
+    /// The cached value for the text at this scale, made by `make` the first time
+    pub fn get(&mut self, text: &str, scale: f32, make: impl FnOnce() -> V) -> &mut V {
+        self.uses += 1;
+        let scale = scale.to_bits();
+        let key = key(text, scale);
+        let cached = self
+            .entries
+            .get(&key)
+            .is_some_and(|entry| entry.text == text && entry.scale == scale);
+        if cached {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            if self.entries.len() >= CAPACITY && !self.entries.contains_key(&key) {
+                self.evict();
+            }
+            // A different text with the same hash is simply replaced
+            self.entries.insert(
+                key,
+                Entry {
+                    text: text.to_owned(),
+                    scale,
+                    value: make(),
+                    used: 0,
+                },
+            );
+        }
+        let entry = self.entries.get_mut(&key).unwrap();
+        entry.used = self.uses;
+        &mut entry.value
+    }
+
+    fn evict(&mut self) {
+        if let Some(oldest) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.used)
+            .map(|(key, _)| *key)
+        {
+            self.entries.remove(&oldest);
+        }
+    }
+
+    /// Forget every text, for when what they were laid out with changes
+    pub fn flush(&mut self) {
+        self.entries.clear();
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups that found their text and ones that had to make it since the last call
+    pub fn take_counts(&mut self) -> (usize, usize) {
+        (
+            std::mem::take(&mut self.hits),
+            std::mem::take(&mut self.misses),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texts_are_made_once_per_scale() {
+        let mut cache = TextCache::default();
+        let mut made = 0;
+        for _ in 0..3 {
+            cache.get("1. e4 e5", 20.0, || made += 1);
+        }
+        cache.get("1. e4 e5", 24.0, || made += 1);
+        assert_eq!(made, 2);
+        assert_eq!(cache.take_counts(), (2, 2));
+        assert_eq!(cache.take_counts(), (0, 0));
+
+        cache.flush();
+        assert!(cache.is_empty());
+        cache.get("1. e4 e5", 20.0, || made += 1);
+        assert_eq!(made, 3);
+    }
+
+    #[test]
+    fn the_least_recently_used_text_goes_first() {
+        let mut cache = TextCache::default();
+        for i in 0..CAPACITY {
+            cache.get(&i.to_string(), 10.0, || i);
+        }
+        // Using the oldest one again keeps it, the second oldest goes instead
+        cache.get("0", 10.0, || unreachable!());
+        cache.get("new", 10.0, || CAPACITY);
+        assert_eq!(cache.len(), CAPACITY);
+        assert_eq!(*cache.get("0", 10.0, || unreachable!()), 0);
+        assert_eq!(*cache.get("1", 10.0, || 1000), 1000);
+    }
+}
@@ -0,0 +1,134 @@
+use ggez::graphics::Color;
+use std::path::{Path, PathBuf};
+
+// Cycled through in this order at runtime
+const BUILT_IN: [&str; 3] = ["default", "blue", "wood"];
+
+/// Colors of the board and the piece sprite sheet, chosen with --theme
+#[derive(Clone)]
+pub(crate) struct Theme {
+    pub(crate) name: String,
+    pub(crate) white_square: Color,
+    pub(crate) black_square: Color,
+    // The selected piece and the moves it can make
+    pub(crate) highlight: Color,
+    pub(crate) check: Color,
+    // Sprite sheet with the same 6x2 layout as Pieces.png, the embedded one if None
+    pub(crate) pieces: Option<PathBuf>,
+}
+
+impl Theme {
+    pub(crate) fn built_in(name: &str) -> Option<Self> {
+        let (white_square, black_square, highlight) = match name {
+            "default" => (
+                Color::new(1.0, 0.9, 0.9, 1.0),
+                Color::new(0.9, 0.7, 0.7, 1.0),
+                Color::new(0.0, 0.5, 0.0, 0.75),
+            ),
+            "blue" => (
+                Color::new(0.87, 0.89, 0.93, 1.0),
+                Color::new(0.45, 0.56, 0.69, 1.0),
+                Color::new(0.0, 0.5, 0.0, 0.75),
+            ),
+            "wood" => (
+                Color::new(0.94, 0.85, 0.71, 1.0),
+                Color::new(0.71, 0.53, 0.39, 1.0),
+                Color::new(0.6, 0.7, 0.1, 0.75),
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_owned(),
+            white_square,
+            black_square,
+            highlight,
+            check: Color::new(0.8, 0.0, 0.0, 0.5),
+            pieces: None,
+        })
+    }
+
+    /// A built-in theme by name, or a theme file if the name ends in .json
+    pub(crate) fn load(name: &str) -> Result<Self, String> {
+        if name.ends_with(".json") {
+            return Self::from_file(Path::new(name));
+        }
+        Self::built_in(name).ok_or_else(|| {
+            format!(
+                "Unknown theme \"{}\", choose one of {} or a .json file.",
+                name,
+                BUILT_IN.join(", ")
+            )
+        })
+    }
+
+    /// Read a JSON object of "#rrggbb" or "#rrggbbaa" colors and an optional "pieces" path,
+    /// anything left out keeps the default theme's value
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| format!("{} must contain a JSON object.", path.display()))?;
+
+        let mut theme = Self {
+            name: path.display().to_string(),
+            ..Self::default()
+        };
+        for (key, value) in object {
+            let value = value
+                .as_str()
+                .ok_or_else(|| format!("Theme value \"{}\" must be a string.", key))?;
+            match key.as_str() {
+                "white_square" => theme.white_square = parse_color(value)?,
+                "black_square" => theme.black_square = parse_color(value)?,
+                "highlight" => theme.highlight = parse_color(value)?,
+                "check" => theme.check = parse_color(value)?,
+                // Relative to the theme file so themes can be moved around with their sprites
+                "pieces" => {
+                    theme.pieces = Some(match path.parent() {
+                        Some(dir) => dir.join(value),
+                        None => PathBuf::from(value),
+                    })
+                }
+                _ => return Err(format!("Unknown theme key \"{}\".", key)),
+            }
+        }
+        Ok(theme)
+    }
+
+    /// The built-in theme after this one, the first one after a theme file
+    pub(crate) fn next(&self) -> Self {
+        let index = BUILT_IN
+            .iter()
+            .position(|name| *name == self.name)
+            .map_or(0, |index| (index + 1) % BUILT_IN.len());
+        Self::built_in(BUILT_IN[index]).unwrap()
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::built_in(BUILT_IN[0]).unwrap()
+    }
+}
+
+fn parse_color(text: &str) -> Result<Color, String> {
+    let invalid = || format!("\"{}\" is not a #rrggbb or #rrggbbaa color.", text);
+    let hex = text.strip_prefix('#').ok_or_else(invalid)?;
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel = |index: usize| {
+        hex.get(index * 2..index * 2 + 2)
+            .map_or(Ok(255), |pair| u8::from_str_radix(pair, 16))
+            .map_err(|_| invalid())
+    };
+    Ok(Color::from_rgba(
+        channel(0)?,
+        channel(1)?,
+        channel(2)?,
+        channel(3)?,
+    ))
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Toasts waiting past this are dropped, oldest first, they would be stale by their turn
+const MAX_WAITING: usize = 4;
+
+struct Toast {
+    text: String,
+    duration: Duration,
+    // When it came up, None while it waits its turn
+    shown: Option<Duration>,
+}
+
+/// Short messages shown over the board one after another, each for its own time. They share
+/// one line, so a new one waits until the ones before it are done.
+#[derive(Default)]
+pub struct Toasts {
+    queue: VecDeque<Toast>,
+}
+
+impl Toasts {
+    /// Show `text` for `duration` once the toasts before it are done. The same text as the
+    /// last one in line only shows once.
+    pub fn push(&mut self, text: impl Into<String>, duration: Duration) {
+        let text = text.into();
+        if self.queue.back().is_some_and(|last| last.text == text) {
+            return;
+        }
+        if self.queue.len() > MAX_WAITING {
+            // Never the one showing
+            self.queue.remove(1);
+        }
+        self.queue.push_back(Toast {
+            text,
+            duration,
+            shown: None,
+        });
+    }
+
+    /// Drop the toast showing once its time is up, and start the next one's
+    pub fn advance(&mut self, now: Duration) {
+        while let Some(first) = self.queue.front_mut() {
+            let shown = *first.shown.get_or_insert(now);
+            if now.saturating_sub(shown) < first.duration {
+                return;
+            }
+            self.queue.pop_front();
+        }
+    }
+
+    /// The toast showing since the last `advance`
+    #[inline]
+    pub fn current(&self) -> Option<&str> {
+        self.queue
+            .front()
+            .filter(|toast| toast.shown.is_some())
+            .map(|toast| toast.text.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn toasts_take_turns_instead_of_overlapping() {
+        let mut toasts = Toasts::default();
+        toasts.push("The hook vetoed the move", secs(4));
+        toasts.push("The opponent moved out of turn", secs(3));
+        assert_eq!(toasts.current(), None);
+
+        toasts.advance(secs(10));
+        assert_eq!(toasts.current(), Some("The hook vetoed the move"));
+        toasts.advance(secs(13));
+        assert_eq!(toasts.current(), Some("The hook vetoed the move"));
+        // The second one's time starts once it shows
+        toasts.advance(secs(14));
+        assert_eq!(toasts.current(), Some("The opponent moved out of turn"));
+        toasts.advance(secs(16));
+        assert_eq!(toasts.current(), Some("The opponent moved out of turn"));
+        toasts.advance(secs(17));
+        assert_eq!(toasts.current(), None);
+    }
+
+    #[test]
+    fn repeats_show_once_and_old_toasts_make_way() {
+        let mut toasts = Toasts::default();
+        toasts.push("Bundle written", secs(8));
+        toasts.push("Bundle written", secs(8));
+        toasts.advance(Duration::ZERO);
+        toasts.advance(secs(8));
+        assert_eq!(toasts.current(), None);
+
+        for n in 0..10 {
+            toasts.push(format!("Toast {}", n), secs(1));
+        }
+        toasts.advance(secs(20));
+        assert_eq!(toasts.current(), Some("Toast 0"));
+        let mut shown = Vec::new();
+        for second in 21..40 {
+            if let Some(toast) = toasts.current() {
+                if shown.last().map(String::as_str) != Some(toast) {
+                    shown.push(toast.to_owned());
+                }
+            }
+            toasts.advance(secs(second));
+        }
+        assert_eq!(
+            shown,
+            ["Toast 0", "Toast 6", "Toast 7", "Toast 8", "Toast 9"]
+        );
+    }
+}
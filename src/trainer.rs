@@ -0,0 +1,553 @@
+use crate::pgn::{file_name, rank_name};
+use crate::Random;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Kept in the profile directory
+pub const FILE_NAME: &str = "trainer.json";
+/// How long a round of the square trainer lasts
+pub const ROUND: Duration = Duration::from_secs(30);
+/// The names offered for the highlighted square when naming squares
+pub const CHOICES: usize = 4;
+// What a right answer scores, and what each right answer in a row before it adds up to
+// the most a streak counts for
+const POINTS: u32 = 10;
+const STREAK_BONUS: u32 = 5;
+const MAX_BONUS_STREAK: u32 = 5;
+// How much more often a square comes up for every time it was missed, up to how many misses
+const MISS_WEIGHT: u32 = 3;
+const MAX_MISSES: u32 = 5;
+
+type Coord = (usize, usize);
+
+/// What the trainer asks
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Mode {
+    // A square is named and is clicked on the board
+    FindSquare,
+    // A square is highlighted and its name is picked from a few
+    NameSquare,
+}
+
+impl Mode {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::FindSquare => "Find the square",
+            Mode::NameSquare => "Name the square",
+        }
+    }
+
+    #[inline]
+    fn key(self) -> &'static str {
+        match self {
+            Mode::FindSquare => "find",
+            Mode::NameSquare => "name",
+        }
+    }
+
+    #[inline]
+    pub fn next(self) -> Self {
+        match self {
+            Mode::FindSquare => Mode::NameSquare,
+            Mode::NameSquare => Mode::FindSquare,
+        }
+    }
+}
+
+/// How hard the trainer makes it
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+impl Difficulty {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    #[inline]
+    fn key(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    #[inline]
+    pub fn next(self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Medium,
+            Difficulty::Medium => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    /// Whether the file letters and rank numbers are drawn on the board
+    #[inline]
+    pub fn coordinates(self) -> bool {
+        self == Difficulty::Easy
+    }
+
+    /// Whether every prompt turns the board either way, for learning the board from Black's side
+    #[inline]
+    pub fn random_orientation(self) -> bool {
+        self == Difficulty::Hard
+    }
+
+    /// How long a prompt waits for its answer before it counts as missed
+    #[inline]
+    pub fn time_limit(self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_secs(6),
+            Difficulty::Medium => Duration::from_secs(4),
+            Difficulty::Hard => Duration::from_secs(3),
+        }
+    }
+}
+
+/// What a right answer scores after `streak` right answers in a row
+#[inline]
+pub fn points(streak: u32) -> u32 {
+    POINTS + STREAK_BONUS * streak.min(MAX_BONUS_STREAK)
+}
+
+/// Picks the squares asked for, seeded so the same seed asks the same squares. A square
+/// never comes up twice in a row, and the ones missed come up more often until they are
+/// answered right again.
+pub struct Sampler {
+    random: Random,
+    misses: [[u32; 8]; 8],
+    last: Option<Coord>,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            random: Random::new(seed),
+            misses: [[0; 8]; 8],
+            last: None,
+        }
+    }
+
+    #[inline]
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.random.chance(percent)
+    }
+
+    /// How likely `square` is to come up next, against the others
+    #[inline]
+    pub fn weight(&self, (row, col): Coord) -> u32 {
+        1 + MISS_WEIGHT * self.misses[row][col].min(MAX_MISSES)
+    }
+
+    /// The square to ask for next
+    pub fn pick(&mut self) -> Coord {
+        let squares = || (0..8).flat_map(|row| (0..8).map(move |col| (row, col)));
+        let last = self.last;
+        let total: u32 = squares()
+            .filter(|square| Some(*square) != last)
+            .map(|square| self.weight(square))
+            .sum();
+        let mut pick = self.random.below(total as usize) as u32;
+        for square in squares().filter(|square| Some(*square) != last) {
+            let weight = self.weight(square);
+            if pick < weight {
+                self.last = Some(square);
+                return square;
+            }
+            pick -= weight;
+        }
+        unreachable!("the weights add up to the total")
+    }
+
+    /// `square` was asked for and answered, right or wrong
+    pub fn answered(&mut self, (row, col): Coord, right: bool) {
+        let misses = &mut self.misses[row][col];
+        *misses = match right {
+            true => misses.saturating_sub(1),
+            false => *misses + 1,
+        };
+    }
+
+    /// `target` and other squares to pick its name from, in no order
+    pub fn choices(&mut self, target: Coord) -> [Coord; CHOICES] {
+        let mut choices = [target; CHOICES];
+        let mut filled = 1;
+        while filled < CHOICES {
+            let square = (self.random.below(8), self.random.below(8));
+            if !choices[..filled].contains(&square) {
+                choices[filled] = square;
+                filled += 1;
+            }
+        }
+        let at = self.random.below(CHOICES);
+        choices.swap(0, at);
+        choices
+    }
+}
+
+/// One prompt of a round
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Answer {
+    pub asked: Coord,
+    pub right: bool,
+    // How long it took, None when the time ran out
+    pub response: Option<Duration>,
+}
+
+/// A round of prompts against the clock, only ever counted, nothing of a game is touched
+#[derive(Clone, PartialEq, Debug)]
+pub struct Round {
+    pub mode: Mode,
+    pub difficulty: Difficulty,
+    // Since the start of the program
+    pub started: Duration,
+    pub answers: Vec<Answer>,
+    pub score: u32,
+    // Right answers in a row up to the latest one
+    pub streak: u32,
+    pub best_streak: u32,
+}
+
+impl Round {
+    pub fn new(mode: Mode, difficulty: Difficulty, started: Duration) -> Self {
+        Self {
+            mode,
+            difficulty,
+            started,
+            answers: Vec::new(),
+            score: 0,
+            streak: 0,
+            best_streak: 0,
+        }
+    }
+
+    /// What is left of the round at `now`
+    #[inline]
+    pub fn left(&self, now: Duration) -> Duration {
+        ROUND.saturating_sub(now.saturating_sub(self.started))
+    }
+
+    #[inline]
+    pub fn is_over(&self, now: Duration) -> bool {
+        self.left(now).is_zero()
+    }
+
+    /// Count the answer to a prompt, and what it scored
+    pub fn answer(&mut self, answer: Answer) -> u32 {
+        self.answers.push(answer);
+        if !answer.right {
+            self.streak = 0;
+            return 0;
+        }
+        let points = points(self.streak);
+        self.score += points;
+        self.streak += 1;
+        self.best_streak = self.best_streak.max(self.streak);
+        points
+    }
+}
+
+/// How a round went
+#[derive(Clone, PartialEq, Debug)]
+pub struct Summary {
+    pub score: u32,
+    pub asked: usize,
+    pub right: usize,
+    // Over the prompts answered before their time ran out
+    pub mean_response: Option<Duration>,
+    pub best_streak: u32,
+    // The files and ranks missed most, all of them on a tie and none without a miss
+    pub weakest_files: Vec<char>,
+    pub weakest_ranks: Vec<char>,
+}
+
+impl Summary {
+    /// Right answers out of every prompt, in percent
+    #[inline]
+    pub fn accuracy(&self) -> usize {
+        match self.asked {
+            0 => 0,
+            asked => (self.right * 100 + asked / 2) / asked,
+        }
+    }
+}
+
+/// The names of the lines of the board missed most often out of `misses`, one count per line
+fn weakest(misses: [u32; 8], name: fn(usize) -> char) -> Vec<char> {
+    let most = misses.iter().copied().max().unwrap_or(0);
+    match most {
+        0 => Vec::new(),
+        most => (0..8)
+            .filter(|line| misses[*line] == most)
+            .map(name)
+            .collect(),
+    }
+}
+
+pub fn summarize(round: &Round) -> Summary {
+    let mut files = [0; 8];
+    let mut ranks = [0; 8];
+    for answer in round.answers.iter().filter(|answer| !answer.right) {
+        let (row, col) = answer.asked;
+        ranks[row] += 1;
+        files[col] += 1;
+    }
+    let responses: Vec<Duration> = round
+        .answers
+        .iter()
+        .filter_map(|answer| answer.response)
+        .collect();
+    Summary {
+        score: round.score,
+        asked: round.answers.len(),
+        right: round.answers.iter().filter(|answer| answer.right).count(),
+        mean_response: (!responses.is_empty())
+            .then(|| responses.iter().sum::<Duration>() / responses.len() as u32),
+        best_streak: round.best_streak,
+        weakest_files: weakest(files, file_name),
+        // From the first rank up, though the rows run from the eighth
+        weakest_ranks: weakest(ranks, rank_name).into_iter().rev().collect(),
+    }
+}
+
+/// The best score of every mode and difficulty played, kept per profile
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Bests {
+    scores: Vec<(Mode, Difficulty, u32)>,
+}
+
+impl Bests {
+    pub fn best(&self, mode: Mode, difficulty: Difficulty) -> Option<u32> {
+        self.scores
+            .iter()
+            .find(|(m, d, _)| (*m, *d) == (mode, difficulty))
+            .map(|(_, _, score)| *score)
+    }
+
+    /// Count a finished round's score, whether it is a new best
+    pub fn record(&mut self, mode: Mode, difficulty: Difficulty, score: u32) -> bool {
+        match self
+            .scores
+            .iter_mut()
+            .find(|(m, d, _)| (*m, *d) == (mode, difficulty))
+        {
+            Some((_, _, best)) if *best >= score => false,
+            Some((_, _, best)) => {
+                *best = score;
+                true
+            }
+            None => {
+                self.scores.push((mode, difficulty, score));
+                score > 0
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        for (mode, difficulty, score) in &self.scores {
+            object.insert(
+                format!("{} {}", mode.key(), difficulty.key()),
+                Value::Number((*score).into()),
+            );
+        }
+        Value::Object(object)
+    }
+
+    /// Scores of modes or difficulties this version doesn't have are dropped
+    pub fn from_json(value: &Value) -> Self {
+        let mut scores = Vec::new();
+        for mode in [Mode::FindSquare, Mode::NameSquare] {
+            for difficulty in DIFFICULTIES {
+                let key = format!("{} {}", mode.key(), difficulty.key());
+                if let Some(score) = value.get(key.as_str()).and_then(Value::as_u64) {
+                    scores.push((mode, difficulty, score.min(u32::MAX as u64) as u32));
+                }
+            }
+        }
+        Self { scores }
+    }
+
+    pub fn read(path: &Path) -> Self {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => Self::from_json(&value),
+            Err(err) => {
+                println!(
+                    "Ignoring the damaged trainer scores {}: {}",
+                    path.display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Can't save the trainer scores: {}", err))?;
+        }
+        serde_json::to_string(&self.to_json())
+            .map_err(std::io::Error::from)
+            .and_then(|text| fs::write(path, text))
+            .map_err(|err| format!("Can't save the trainer scores: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::square_name;
+
+    const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+    fn answer(asked: &str, right: bool, millis: Option<u64>) -> Answer {
+        let asked = asked.as_bytes();
+        Answer {
+            asked: ((b'8' - asked[1]) as usize, (asked[0] - b'a') as usize),
+            right,
+            response: millis.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn streaks_score_more_until_a_miss() {
+        let mut round = Round::new(Mode::FindSquare, Difficulty::Easy, Duration::ZERO);
+        let scored: Vec<u32> = [true, true, true, false, true]
+            .into_iter()
+            .map(|right| round.answer(answer("d5", right, Some(900))))
+            .collect();
+        assert_eq!(scored, [10, 15, 20, 0, 10]);
+        assert_eq!(round.score, 55);
+        assert_eq!((round.streak, round.best_streak), (1, 3));
+
+        // The bonus stops growing
+        assert_eq!(points(MAX_BONUS_STREAK), points(MAX_BONUS_STREAK + 10));
+
+        assert!(!round.is_over(Duration::from_secs(29)));
+        assert!(round.is_over(ROUND));
+        assert_eq!(round.left(Duration::from_secs(40)), Duration::ZERO);
+    }
+
+    #[test]
+    fn missed_squares_come_up_more_and_never_twice_in_a_row() {
+        let mut sampler = Sampler::new(SEED);
+        let d5 = (3, 3);
+        for _ in 0..3 {
+            sampler.answered(d5, false);
+        }
+        let mut counts = [[0; 8]; 8];
+        let mut last = None;
+        for _ in 0..6400 {
+            let square = sampler.pick();
+            assert_ne!(
+                Some(square),
+                last,
+                "{} twice",
+                square_name(square.0, square.1)
+            );
+            last = Some(square);
+            counts[square.0][square.1] += 1;
+        }
+        // Ten times as likely as any other square, apart from never following itself
+        let others = (6400 - counts[3][3]) / 63;
+        assert!(counts[3][3] > 5 * others, "{} and {}", counts[3][3], others);
+
+        // Answering it right brings it back down
+        for _ in 0..3 {
+            sampler.answered(d5, true);
+        }
+        assert_eq!(sampler.weight(d5), sampler.weight((0, 0)));
+
+        // The same seed asks the same squares
+        let mut again = Sampler::new(SEED);
+        let mut other = Sampler::new(SEED);
+        assert!((0..100).all(|_| again.pick() == other.pick()));
+    }
+
+    #[test]
+    fn the_choices_hold_the_square_once() {
+        let mut sampler = Sampler::new(SEED);
+        let mut first = [0; CHOICES];
+        for _ in 0..200 {
+            let target = sampler.pick();
+            let choices = sampler.choices(target);
+            assert_eq!(
+                choices.iter().filter(|square| **square == target).count(),
+                1
+            );
+            for (index, square) in choices.iter().enumerate() {
+                assert!(!choices[index + 1..].contains(square));
+            }
+            first[choices.iter().position(|square| *square == target).unwrap()] += 1;
+        }
+        // The right one isn't always in the same place
+        assert!(first.iter().all(|count| *count > 0), "{:?}", first);
+    }
+
+    #[test]
+    fn a_summary_finds_the_weakest_files_and_ranks() {
+        let mut round = Round::new(Mode::NameSquare, Difficulty::Medium, Duration::ZERO);
+        for (asked, right, millis) in [
+            ("c3", false, None),
+            ("c6", false, Some(2000)),
+            ("f3", false, Some(1000)),
+            ("e4", true, Some(600)),
+            ("a1", true, Some(400)),
+        ] {
+            round.answer(answer(asked, right, millis));
+        }
+        let summary = summarize(&round);
+        assert_eq!(
+            (summary.asked, summary.right, summary.accuracy()),
+            (5, 2, 40)
+        );
+        assert_eq!(summary.mean_response, Some(Duration::from_millis(1000)));
+        assert_eq!(summary.weakest_files, ['c']);
+        assert_eq!(summary.weakest_ranks, ['3']);
+        assert_eq!(summary.best_streak, 2);
+
+        // Ties are all named, from the first rank up
+        round.answer(answer("h6", false, None));
+        let summary = summarize(&round);
+        assert_eq!(summary.weakest_ranks, ['3', '6']);
+
+        let empty = summarize(&Round::new(
+            Mode::NameSquare,
+            Difficulty::Hard,
+            Duration::ZERO,
+        ));
+        assert_eq!((empty.accuracy(), empty.mean_response), (0, None));
+        assert!(empty.weakest_files.is_empty() && empty.weakest_ranks.is_empty());
+    }
+
+    #[test]
+    fn only_a_higher_score_is_a_new_best() {
+        let mut bests = Bests::default();
+        assert!(bests.record(Mode::FindSquare, Difficulty::Easy, 120));
+        assert!(!bests.record(Mode::FindSquare, Difficulty::Easy, 90));
+        assert!(bests.record(Mode::FindSquare, Difficulty::Easy, 150));
+        assert!(bests.record(Mode::NameSquare, Difficulty::Hard, 40));
+        assert_eq!(bests.best(Mode::FindSquare, Difficulty::Easy), Some(150));
+        assert_eq!(bests.best(Mode::FindSquare, Difficulty::Hard), None);
+
+        assert_eq!(Bests::from_json(&bests.to_json()), bests);
+    }
+}
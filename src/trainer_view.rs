@@ -0,0 +1,503 @@
+use crate::menu::grown;
+use crate::theme::Theme;
+use crate::{rect, CanvasSink, Graphics, Render, Restore, TextLine};
+use chess_gui::layout::Area;
+use chess_gui::pgn;
+use chess_gui::piece_style::PieceStyles;
+use chess_gui::scene::{self, BoardScene, Overlay};
+use chess_gui::trainer::{self, Answer, Bests, Difficulty, Mode, Round, Sampler, Summary, CHOICES};
+use chess_gui::Square;
+use ggez::graphics::{self, Canvas, Rect};
+use ggez::input::keyboard::KeyCode;
+use ggez::{Context, GameResult};
+use mint::Point2;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const RIGHT_TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.45, 0.0, 1.0);
+const WRONG_TEXT_COLOR: graphics::Color = graphics::Color::new(0.7, 0.0, 0.0, 1.0);
+// How long a missed square stays marked on the board
+const MISS_FLASH: Duration = Duration::from_millis(600);
+const EMPTY_BOARD: [[Square; 8]; 8] = [[Square::Empty; 8]; 8];
+
+/// The buttons under the board
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Button {
+    Mode,
+    Difficulty,
+    Start,
+    Back,
+}
+
+/// Where everything in the square trainer is drawn, relative to the window size
+struct TrainerLayout {
+    board: Area,
+    // The square asked for, or the question when naming squares
+    prompt: Rect,
+    // The time left, the score, and which way round the board is
+    status: Rect,
+    // How the last answer went
+    feedback: Rect,
+    // The names to pick from, and the summary of a round in the same place
+    choices: [Rect; CHOICES],
+    summary: Rect,
+    buttons: [(Button, Rect); 4],
+}
+
+impl TrainerLayout {
+    fn new(width: f32, height: f32) -> Self {
+        let rect = |x: f32, y: f32, w: f32, h: f32| {
+            Rect::new(x * width, y * height, w * width, h * height)
+        };
+        let side = (0.75 * height).min(0.55 * width);
+        let board = Area::new(0.05 * width, 0.05 * height, side, side);
+        let panel = board.x + board.w + 0.05 * width;
+        let panel = |y: f32, h: f32| Rect::new(panel, y * height, 0.95 * width - panel, h * height);
+        Self {
+            board,
+            prompt: panel(0.05, 0.2),
+            status: panel(0.27, 0.05),
+            feedback: panel(0.33, 0.05),
+            choices: std::array::from_fn(|index| panel(0.42 + 0.1 * index as f32, 0.08)),
+            summary: panel(0.42, 0.4),
+            buttons: [
+                (Button::Mode, rect(0.05, 0.88, 0.21, 0.07)),
+                (Button::Difficulty, rect(0.28, 0.88, 0.21, 0.07)),
+                (Button::Start, rect(0.51, 0.88, 0.21, 0.07)),
+                (Button::Back, rect(0.74, 0.88, 0.21, 0.07)),
+            ],
+        }
+    }
+}
+
+/// What the player did in the square trainer
+pub(crate) enum TrainerAction {
+    Back,
+}
+
+/// The square asked for and how the board is turned for it
+struct Prompt {
+    target: (usize, usize),
+    flipped: bool,
+    // The names offered when naming squares
+    choices: [(usize, usize); CHOICES],
+    asked_at: Duration,
+}
+
+/// How the last answer went, and when
+struct Feedback {
+    text: String,
+    right: bool,
+    // What was asked, marked on the board a moment after a miss
+    missed: Option<((usize, usize), bool)>,
+    at: Duration,
+}
+
+/// A drill of the squares' names on an empty board, in rounds against the clock. Reached from
+/// the start screen, nothing of a game is read or changed.
+pub(crate) struct TrainerView {
+    mode: Mode,
+    difficulty: Difficulty,
+    sampler: Sampler,
+    bests: Bests,
+    file: PathBuf,
+    // None between rounds
+    round: Option<(Round, Prompt)>,
+    feedback: Option<Feedback>,
+    // How the last round went, and whether it was a new best
+    last: Option<(Summary, bool)>,
+    // Why the best scores couldn't be saved
+    error: Option<String>,
+    render: Render,
+    theme: Theme,
+}
+
+impl Restore for TrainerView {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::Render(&mut self.render, &self.theme)
+    }
+}
+
+impl TrainerView {
+    /// With the best scores kept at `file`, drawn like the game's board
+    pub(crate) fn new(ctx: &Context, theme: Theme, styles: PieceStyles, file: PathBuf) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |since| since.as_nanos() as u64);
+        Self {
+            mode: Mode::FindSquare,
+            difficulty: Difficulty::Easy,
+            sampler: Sampler::new(seed),
+            bests: Bests::read(&file),
+            file,
+            round: None,
+            feedback: None,
+            last: None,
+            error: None,
+            render: Render::new(ctx, &theme, styles),
+            theme,
+        }
+    }
+
+    fn prompt(&mut self, now: Duration) -> Prompt {
+        let target = self.sampler.pick();
+        Prompt {
+            target,
+            flipped: self.difficulty.random_orientation() && self.sampler.chance(50),
+            choices: self.sampler.choices(target),
+            asked_at: now,
+        }
+    }
+
+    fn start(&mut self, now: Duration) {
+        let prompt = self.prompt(now);
+        self.round = Some((Round::new(self.mode, self.difficulty, now), prompt));
+        self.feedback = None;
+        self.last = None;
+    }
+
+    /// Count the answer to the prompt, `answer` None when its time ran out, and ask the next
+    fn answer(&mut self, answer: Option<(usize, usize)>, now: Duration) {
+        let Some((round, prompt)) = &mut self.round else {
+            return;
+        };
+        let right = answer == Some(prompt.target);
+        let points = round.answer(Answer {
+            asked: prompt.target,
+            right,
+            response: answer.map(|_| now.saturating_sub(prompt.asked_at)),
+        });
+        self.sampler.answered(prompt.target, right);
+        let name = pgn::square_name(prompt.target.0, prompt.target.1);
+        self.feedback = Some(Feedback {
+            text: match (right, answer) {
+                (true, _) if round.streak > 1 => {
+                    format!("+{}, {} in a row", points, round.streak)
+                }
+                (true, _) => format!("+{}", points),
+                (false, Some(_)) => format!("That was {}", name),
+                (false, None) => format!("Too slow, that was {}", name),
+            },
+            right,
+            missed: (!right).then_some((prompt.target, prompt.flipped)),
+            at: now,
+        });
+        let next = self.prompt(now);
+        if let Some((_, prompt)) = &mut self.round {
+            *prompt = next;
+        }
+    }
+
+    /// Sum up the round that is over and keep its score if it is the best yet
+    fn finish(&mut self) {
+        let Some((round, _)) = self.round.take() else {
+            return;
+        };
+        let summary = trainer::summarize(&round);
+        let best = self
+            .bests
+            .record(round.mode, round.difficulty, summary.score);
+        if best {
+            self.error = self.bests.write(&self.file).err();
+        }
+        self.feedback = None;
+        self.last = Some((summary, best));
+    }
+
+    pub(crate) fn update(&mut self, now: Duration) {
+        let Some((round, prompt)) = &self.round else {
+            return;
+        };
+        if round.is_over(now) {
+            self.finish();
+        } else if now.saturating_sub(prompt.asked_at) >= round.difficulty.time_limit() {
+            self.answer(None, now);
+        }
+    }
+
+    fn press(&mut self, button: Button, now: Duration) -> Option<TrainerAction> {
+        match button {
+            // Both only change between rounds
+            Button::Mode if self.round.is_none() => self.mode = self.mode.next(),
+            Button::Difficulty if self.round.is_none() => self.difficulty = self.difficulty.next(),
+            Button::Mode | Button::Difficulty => {}
+            Button::Start => self.start(now),
+            Button::Back => return Some(TrainerAction::Back),
+        }
+        None
+    }
+
+    fn button_label(&self, button: Button) -> String {
+        match button {
+            Button::Mode => self.mode.name().to_owned(),
+            Button::Difficulty => self.difficulty.name().to_owned(),
+            Button::Start if self.round.is_some() => "Restart".to_owned(),
+            Button::Start => "Start".to_owned(),
+            Button::Back => "Back".to_owned(),
+        }
+    }
+
+    /// A line of text centred in `area`, `scale` of its height
+    fn line(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        area: Rect,
+        text: &str,
+        scale: f32,
+        color: graphics::Color,
+    ) {
+        self.render.draw_text(
+            ctx,
+            canvas,
+            area,
+            text,
+            TextLine {
+                y: 0.5,
+                scale,
+                color,
+            },
+        );
+    }
+
+    fn draw_button(&self, ctx: &Context, canvas: &mut Canvas, area: Rect, label: &str) {
+        canvas.draw(
+            &self.render.bar_mesh,
+            graphics::DrawParam::default()
+                .dest_rect(area)
+                .color(BUTTON_COLOR),
+        );
+        self.line(ctx, canvas, area, label, 0.5, TEXT_COLOR);
+    }
+
+    /// What is said about the difficulty between rounds
+    fn describe(&self) -> String {
+        let difficulty = self.difficulty;
+        format!(
+            "{}, {}, {} s a square",
+            match difficulty.coordinates() {
+                true => "coordinates shown",
+                false => "no coordinates",
+            },
+            match difficulty.random_orientation() {
+                true => "either side at the bottom",
+                false => "White at the bottom",
+            },
+            difficulty.time_limit().as_secs()
+        )
+    }
+
+    /// How the last round went, or the best score before there was one
+    fn summary_lines(&self) -> Vec<String> {
+        let best = self.bests.best(self.mode, self.difficulty);
+        let Some((summary, new_best)) = &self.last else {
+            return vec![
+                self.describe(),
+                match best {
+                    Some(best) => format!("Best score {}", best),
+                    None => "No round played yet".to_owned(),
+                },
+            ];
+        };
+        let mut lines = vec![match new_best {
+            true => format!("Score {}, a new best", summary.score),
+            false => format!("Score {}, best {}", summary.score, best.unwrap_or(0)),
+        }];
+        lines.push(format!(
+            "{} of {} right ({}%)",
+            summary.right,
+            summary.asked,
+            summary.accuracy()
+        ));
+        if let Some(mean) = summary.mean_response {
+            lines.push(format!("{:.1} s an answer on average", mean.as_secs_f32()));
+        }
+        lines.push(format!("Longest streak {}", summary.best_streak));
+        let names = |lines: &[char]| {
+            lines
+                .iter()
+                .map(char::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (&summary.weakest_files[..], &summary.weakest_ranks[..]) {
+            ([], []) => lines.push("Nothing missed".to_owned()),
+            (files, ranks) => {
+                lines.push(format!("Weakest files: {}", names(files)));
+                lines.push(format!("Weakest ranks: {}", names(ranks)));
+            }
+        }
+        lines
+    }
+
+    pub(crate) fn draw(&self, ctx: &Context, canvas: &mut Canvas, now: Duration) -> GameResult {
+        let (width, height) = {
+            let cords = canvas.screen_coordinates().unwrap();
+            (cords.w, cords.h)
+        };
+        let layout = TrainerLayout::new(width, height);
+
+        let mut board = BoardScene::new(layout.board, &EMPTY_BOARD);
+        board.coordinates = self.difficulty.coordinates();
+        let prompt = self.round.as_ref().map(|(_, prompt)| prompt);
+        if let Some(prompt) = prompt {
+            board.flipped = prompt.flipped;
+            if self.mode == Mode::NameSquare {
+                board.overlay = Overlay::Selection {
+                    from: prompt.target,
+                    destinations: &[],
+                };
+            }
+        }
+        // The missed square fades, unless the board has been turned around since
+        board.flash = self.feedback.as_ref().and_then(|feedback| {
+            let (square, flipped) = feedback.missed?;
+            let since = now.saturating_sub(feedback.at);
+            (since < MISS_FLASH && flipped == board.flipped)
+                .then(|| (square, 1.0 - since.as_secs_f32() / MISS_FLASH.as_secs_f32()))
+        });
+        let mut sink = CanvasSink {
+            render: &self.render,
+            theme: &self.theme,
+            ctx,
+            canvas,
+            board: rect(layout.board),
+            result: Ok(()),
+        };
+        scene::compose(&board, &mut sink);
+        sink.result?;
+
+        match (&self.round, prompt) {
+            (Some((round, _)), Some(prompt)) => {
+                let text = match self.mode {
+                    Mode::FindSquare => pgn::square_name(prompt.target.0, prompt.target.1),
+                    Mode::NameSquare => "Which square?".to_owned(),
+                };
+                let scale = match self.mode {
+                    Mode::FindSquare => 0.8,
+                    Mode::NameSquare => 0.35,
+                };
+                self.line(ctx, canvas, layout.prompt, &text, scale, TEXT_COLOR);
+                let status = format!(
+                    "{} s left, score {}, {} at the bottom",
+                    round.left(now).as_secs_f32().ceil(),
+                    round.score,
+                    match prompt.flipped {
+                        true => "Black",
+                        false => "White",
+                    }
+                );
+                self.line(ctx, canvas, layout.status, &status, 0.6, TEXT_COLOR);
+                if let Some(feedback) = &self.feedback {
+                    let color = match feedback.right {
+                        true => RIGHT_TEXT_COLOR,
+                        false => WRONG_TEXT_COLOR,
+                    };
+                    self.line(ctx, canvas, layout.feedback, &feedback.text, 0.6, color);
+                }
+                if self.mode == Mode::NameSquare {
+                    for (area, (row, col)) in layout.choices.iter().zip(prompt.choices) {
+                        self.draw_button(ctx, canvas, *area, &pgn::square_name(row, col));
+                    }
+                }
+            }
+            _ => {
+                let title = format!("{}, {}", self.mode.name(), self.difficulty.name());
+                self.line(ctx, canvas, layout.prompt, &title, 0.25, TEXT_COLOR);
+                let row = layout.summary.h / 8.0;
+                for (index, text) in self.summary_lines().iter().enumerate() {
+                    let area = Rect::new(
+                        layout.summary.x,
+                        layout.summary.y + index as f32 * row,
+                        layout.summary.w,
+                        row,
+                    );
+                    self.line(ctx, canvas, area, text, 0.7, TEXT_COLOR);
+                }
+                if let Some(error) = &self.error {
+                    self.line(ctx, canvas, layout.feedback, error, 0.6, WRONG_TEXT_COLOR);
+                }
+            }
+        }
+
+        for (button, area) in layout.buttons {
+            self.draw_button(ctx, canvas, area, &self.button_label(button));
+        }
+        Ok(())
+    }
+
+    /// Handle a click. The buttons take clicks up to `slop` pixels past their edges, the
+    /// squares are too close together for it.
+    pub(crate) fn click(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        slop: f32,
+        now: Duration,
+    ) -> Option<TrainerAction> {
+        let layout = TrainerLayout::new(width, height);
+        let point = Point2 { x, y };
+        if let Some((button, _)) = layout
+            .buttons
+            .iter()
+            .find(|(_, area)| grown(*area, slop).contains(point))
+        {
+            return self.press(*button, now);
+        }
+        let (_, prompt) = self.round.as_ref()?;
+        match self.mode {
+            Mode::FindSquare => {
+                let mut board = BoardScene::new(layout.board, &EMPTY_BOARD);
+                board.flipped = prompt.flipped;
+                if let Some(square) = board.square_at(x, y) {
+                    self.answer(Some(square), now);
+                }
+            }
+            Mode::NameSquare => {
+                if let Some(index) = layout
+                    .choices
+                    .iter()
+                    .position(|area| grown(*area, slop).contains(point))
+                {
+                    let square = prompt.choices[index];
+                    self.answer(Some(square), now);
+                }
+            }
+        }
+        None
+    }
+
+    pub(crate) fn key(&mut self, keycode: KeyCode, now: Duration) -> Option<TrainerAction> {
+        let choice = match keycode {
+            KeyCode::Key1 => Some(0),
+            KeyCode::Key2 => Some(1),
+            KeyCode::Key3 => Some(2),
+            KeyCode::Key4 => Some(3),
+            _ => None,
+        };
+        match (keycode, &self.round) {
+            // Escape gives up on a round before it leaves
+            (KeyCode::Escape, Some(_)) => {
+                self.round = None;
+                self.feedback = None;
+            }
+            (KeyCode::Escape, None) => return Some(TrainerAction::Back),
+            (KeyCode::Space | KeyCode::Return, _) => self.start(now),
+            (KeyCode::M, None) => self.mode = self.mode.next(),
+            (KeyCode::D, None) => self.difficulty = self.difficulty.next(),
+            (_, Some((_, prompt))) if self.mode == Mode::NameSquare => {
+                if let Some(index) = choice {
+                    let square = prompt.choices[index];
+                    self.answer(Some(square), now);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
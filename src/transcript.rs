@@ -0,0 +1,455 @@
+use crate::network::network_piece_letter;
+use chess_network_protocol::{
+    ClientToServer, ClientToServerHandshake, Features, Joever, Move, Piece, ServerToClient,
+    ServerToClientHandshake,
+};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Instant;
+
+type NetworkBoard = [[Piece; 8]; 8];
+
+/// Every message of a connection as it went over the wire with what the GUI decided about
+/// them, a line "<milliseconds> <sent|received|note> <message>" each
+pub struct WireLog {
+    started: Instant,
+    entries: Mutex<Vec<String>>,
+}
+
+impl Default for WireLog {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl WireLog {
+    pub fn sent<T: Serialize>(&self, message: &T) {
+        self.push("sent", serde_json::to_string(message).unwrap_or_default());
+    }
+
+    pub fn received<T: Serialize>(&self, message: &T) {
+        self.push(
+            "received",
+            serde_json::to_string(message).unwrap_or_default(),
+        );
+    }
+
+    /// Something the GUI decided, like a move it rejected or a board it took over
+    pub fn note(&self, text: &str) {
+        self.push("note", text.replace('\n', " "));
+    }
+
+    pub fn entries(&self) -> Vec<String> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    fn push(&self, direction: &str, message: String) {
+        let millis = self.started.elapsed().as_millis();
+        self.entries
+            .lock()
+            .unwrap()
+            .push(format!("{} {} {}", millis, direction, message));
+    }
+}
+
+/// What the transcript says about the game above the messages
+pub struct Header {
+    pub is_server: bool,
+    // What this GUI supports, and what both sides do once the handshake is done
+    pub ours: Vec<Features>,
+    pub negotiated: Vec<Features>,
+    pub result: String,
+}
+
+/// A square as the protocol indexes it, like "e4"
+pub fn square_name(x: usize, y: usize) -> String {
+    match x < 8 && y < 8 {
+        true => format!("{}{}", (b'a' + x as u8) as char, y + 1),
+        false => format!("({}, {})", x, y),
+    }
+}
+
+/// A move in long algebraic notation like "Ng1→f3" or "e7→e8=Q", by the piece on its
+/// starting square in `board`
+pub fn render_move(mv: &Move, board: &NetworkBoard) -> String {
+    let piece = board
+        .get(mv.start_y)
+        .and_then(|rank| rank.get(mv.start_x))
+        .and_then(|piece| network_piece_letter(*piece))
+        .map(|letter| letter.to_ascii_uppercase())
+        .filter(|letter| *letter != 'P');
+    let promotion = network_piece_letter(mv.promotion)
+        .map(|letter| format!("={}", letter.to_ascii_uppercase()))
+        .unwrap_or_default();
+    format!(
+        "{}{}→{}{}",
+        piece.map(String::from).unwrap_or_default(),
+        square_name(mv.start_x, mv.start_y),
+        square_name(mv.end_x, mv.end_y),
+        promotion
+    )
+}
+
+/// What changed between two boards, a piece that moved as "e2→e4" and any other change as
+/// the square with what is on it now, like "e5 empty" after en passant or "e8 Q"
+pub fn board_diff(before: &NetworkBoard, after: &NetworkBoard) -> Vec<String> {
+    let squares = || (0..8).flat_map(|y| (0..8).map(move |x| (x, y)));
+    let mut vacated: Vec<(usize, usize)> = squares()
+        .filter(|&(x, y)| before[y][x] != Piece::None && after[y][x] == Piece::None)
+        .collect();
+    let mut changes = Vec::new();
+    let mut left = Vec::new();
+    for (x, y) in squares().filter(|&(x, y)| before[y][x] != after[y][x]) {
+        if after[y][x] == Piece::None {
+            continue;
+        }
+        // The piece came from a square it left, if any
+        match vacated
+            .iter()
+            .position(|&(from_x, from_y)| before[from_y][from_x] == after[y][x])
+        {
+            Some(at) => {
+                let (from_x, from_y) = vacated.remove(at);
+                changes.push(format!(
+                    "{}→{}",
+                    square_name(from_x, from_y),
+                    square_name(x, y)
+                ));
+            }
+            None => left.push((x, y)),
+        }
+    }
+    for (x, y) in vacated.into_iter().chain(left) {
+        let piece = network_piece_letter(after[y][x]).map_or("empty".to_owned(), String::from);
+        changes.push(format!("{} {}", square_name(x, y), piece));
+    }
+    changes
+}
+
+fn joever_name(joever: Joever) -> &'static str {
+    match joever {
+        Joever::White => "White won",
+        Joever::Black => "Black won",
+        Joever::Draw => "draw",
+        Joever::Indeterminate => "indeterminate",
+        Joever::Ongoing => "ongoing",
+    }
+}
+
+fn feature_names(features: &[Features]) -> String {
+    let names: Vec<&str> = features
+        .iter()
+        .map(|feature| match feature {
+            Features::Castling => "castling",
+            Features::EnPassant => "en passant",
+            Features::Promotion => "promotion",
+            Features::Stalemate => "stalemate",
+            _ => "other",
+        })
+        .collect();
+    match names.is_empty() {
+        true => "none".to_owned(),
+        false => names.join(", "),
+    }
+}
+
+/// Follows the board through the messages, the moves are rendered by what stood on their squares
+struct Reader {
+    board: NetworkBoard,
+    joever: Joever,
+}
+
+impl Reader {
+    /// A board the server sent, with what changed since the last one and a changed result
+    fn board(&mut self, board: &NetworkBoard, joever: Option<Joever>) -> String {
+        let mut text = String::new();
+        let changes = board_diff(&self.board, board);
+        if !changes.is_empty() {
+            let _ = write!(text, ", board {}", changes.join(" "));
+        }
+        self.board = *board;
+        if let Some(joever) = joever.filter(|joever| *joever != self.joever) {
+            let _ = write!(text, ", now {}", joever_name(joever));
+            self.joever = joever;
+        }
+        text
+    }
+
+    fn server_message(&mut self, message: &str) -> Option<String> {
+        if let Ok(message) = serde_json::from_str::<ServerToClient>(message) {
+            return Some(match message {
+                ServerToClient::State {
+                    board,
+                    moves,
+                    joever,
+                    move_made,
+                } => {
+                    let played = render_move(&move_made, &self.board);
+                    let board = self.board(&board, Some(joever));
+                    format!("state: {}{}, {} legal moves", played, board, moves.len())
+                }
+                ServerToClient::Error {
+                    board,
+                    joever,
+                    message,
+                    ..
+                } => format!("error \"{}\"{}", message, self.board(&board, Some(joever))),
+                ServerToClient::Resigned { board, joever } => {
+                    format!("resigned{}", self.board(&board, Some(joever)))
+                }
+                ServerToClient::Draw { board, .. } => format!("draw{}", self.board(&board, None)),
+            });
+        }
+        let handshake = serde_json::from_str::<ServerToClientHandshake>(message).ok()?;
+        self.board = handshake.board;
+        self.joever = handshake.joever;
+        Some(format!(
+            "handshake: features {}, {} legal moves, {}",
+            feature_names(&handshake.features),
+            handshake.moves.len(),
+            joever_name(handshake.joever)
+        ))
+    }
+
+    fn client_message(&self, message: &str) -> Option<String> {
+        if let Ok(message) = serde_json::from_str::<ClientToServer>(message) {
+            return Some(match message {
+                ClientToServer::Move(mv) => format!("move {}", render_move(&mv, &self.board)),
+                ClientToServer::Resign => "resign".to_owned(),
+                ClientToServer::Draw => "draw".to_owned(),
+            });
+        }
+        let handshake = serde_json::from_str::<ClientToServerHandshake>(message).ok()?;
+        Some(format!(
+            "handshake: the server plays {}",
+            match handshake.server_color {
+                chess_network_protocol::Color::White => "White",
+                chess_network_protocol::Color::Black => "Black",
+            }
+        ))
+    }
+}
+
+/// The wire log as a Markdown document for both players to agree on what happened. Entries
+/// that can't be read are noted and skipped, the rest is still rendered.
+pub fn document(entries: &[String], header: &Header) -> String {
+    let mut out = String::from("# Transcript of a networked game\n\n");
+    let _ = writeln!(
+        out,
+        "- We were the {}",
+        match header.is_server {
+            true => "server",
+            false => "client",
+        }
+    );
+    let _ = writeln!(out, "- Our features: {}", feature_names(&header.ours));
+    let _ = writeln!(
+        out,
+        "- Features both sides use: {}",
+        feature_names(&header.negotiated)
+    );
+    let _ = writeln!(out, "- Result: {}", header.result);
+    out.push_str("\n## Messages\n\n");
+
+    let mut reader = Reader {
+        board: [[Piece::None; 8]; 8],
+        joever: Joever::Ongoing,
+    };
+    for (number, entry) in entries.iter().enumerate() {
+        let mut fields = entry.splitn(3, ' ');
+        let rendered = match (
+            fields.next().and_then(|millis| millis.parse::<u64>().ok()),
+            fields.next(),
+            fields.next(),
+        ) {
+            (Some(millis), Some(direction), Some(message)) => {
+                let from_server = match direction {
+                    "sent" => Some(header.is_server),
+                    "received" => Some(!header.is_server),
+                    _ => None,
+                };
+                let text = match (direction, from_server) {
+                    ("note", _) => Some(format!("*GUI* {}", message)),
+                    (_, Some(true)) => reader.server_message(message),
+                    (_, Some(false)) => reader.client_message(message),
+                    _ => None,
+                };
+                text.map(|text| match direction {
+                    "note" => format!("`{:.3}s` {}", millis as f64 / 1000.0, text),
+                    _ => format!(
+                        "`{:.3}s` **{}** {}",
+                        millis as f64 / 1000.0,
+                        direction,
+                        text
+                    ),
+                })
+            }
+            _ => None,
+        };
+        let line = rendered
+            .unwrap_or_else(|| format!("*entry {} can't be read and is skipped*", number + 1));
+        let _ = writeln!(out, "{}. {}", number + 1, line);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::internal_to_network_board;
+    use crate::session::tests::play;
+    use crate::session::Session;
+    use crate::strikes::Strikes;
+
+    fn network_move(from: (usize, usize), to: (usize, usize), promotion: Piece) -> Move {
+        Move {
+            start_x: from.0,
+            start_y: from.1,
+            end_x: to.0,
+            end_y: to.1,
+            promotion,
+        }
+    }
+
+    fn starting_board() -> NetworkBoard {
+        internal_to_network_board(
+            &crate::parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        )
+    }
+
+    #[test]
+    fn moves_render_in_long_algebraic() {
+        let board = starting_board();
+        assert_eq!(
+            render_move(&network_move((6, 0), (5, 2), Piece::None), &board),
+            "Ng1→f3"
+        );
+        assert_eq!(
+            render_move(&network_move((4, 1), (4, 3), Piece::None), &board),
+            "e2→e4"
+        );
+        // A pawn on the seventh rank promoting, whatever color the peer gave the piece
+        assert_eq!(
+            render_move(&network_move((0, 6), (0, 7), Piece::BlackQueen), &board),
+            "a7→a8=Q"
+        );
+        assert_eq!(
+            render_move(&network_move((9, 0), (4, 3), Piece::None), &board),
+            "(9, 0)→e4"
+        );
+    }
+
+    #[test]
+    fn board_changes_pair_up_moved_pieces() {
+        let before = starting_board();
+        let mut after = before;
+        after[1][4] = Piece::None;
+        after[3][4] = Piece::WhitePawn;
+        assert_eq!(board_diff(&before, &after), ["e2→e4"]);
+
+        // Castling moves two pieces, en passant clears a square the pawn didn't land on
+        let mut castled = before;
+        castled[0][5] = Piece::None;
+        castled[0][6] = Piece::None;
+        let mut after = castled;
+        after[0][4] = Piece::None;
+        after[0][7] = Piece::None;
+        after[0][6] = Piece::WhiteKing;
+        after[0][5] = Piece::WhiteRook;
+        assert_eq!(board_diff(&castled, &after), ["h1→f1", "e1→g1"]);
+
+        let mut after = before;
+        after[6][3] = Piece::None;
+        after[7][3] = Piece::WhiteQueen;
+        assert_eq!(board_diff(&before, &after), ["d7 empty", "d8 Q"]);
+        assert!(board_diff(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn unreadable_entries_are_skipped_and_noted() {
+        let entries = [
+            format!(
+                "0 sent {}",
+                serde_json::to_string(&ClientToServer::Move(network_move(
+                    (4, 1),
+                    (4, 3),
+                    Piece::None
+                )))
+                .unwrap()
+            ),
+            "12 received {\"State\": {\"board\"".to_owned(),
+            "nonsense".to_owned(),
+            "30 note The server doesn't support castling".to_owned(),
+        ];
+        let header = Header {
+            is_server: false,
+            ours: vec![Features::Castling],
+            negotiated: Vec::new(),
+            result: "ongoing".to_owned(),
+        };
+        let document = document(&entries, &header);
+        assert!(document.contains("1. `0.000s` **sent** move e2→e4\n"));
+        assert!(document.contains("2. *entry 2 can't be read and is skipped*\n"));
+        assert!(document.contains("3. *entry 3 can't be read and is skipped*\n"));
+        assert!(document.contains("4. `0.030s` *GUI* The server doesn't support castling\n"));
+        assert!(document.contains("- Features both sides use: none\n"));
+    }
+
+    #[test]
+    fn a_scripted_game_reads_in_order() {
+        let log = WireLog::default();
+        let mut session = Session::new(None).unwrap();
+        let mut strikes = Strikes::new(5, 5);
+        log.sent(&crate::network::internal_to_server_handshake(&session));
+
+        // The server plays Black, the client tries a knight move that isn't legal first
+        let rejected = network_move((6, 0), (6, 2), Piece::None);
+        log.received(&ClientToServer::Move(rejected));
+        let (message, _) = session
+            .judge_client_move(
+                &rejected,
+                jonathan_hallstrom_chess::Color::Black,
+                &mut strikes,
+            )
+            .err()
+            .unwrap();
+        log.note(&format!("Rejected client move: {}", message));
+        log.sent(&crate::network::error_state(&session, message));
+
+        let e4 = network_move((4, 1), (4, 3), Piece::None);
+        log.received(&ClientToServer::Move(e4));
+        play(&mut session, "e2e4");
+        let played = *session.played_moves.last().unwrap();
+        log.sent(&crate::network::board_state(&session, &played).unwrap());
+
+        let header = Header {
+            is_server: true,
+            ours: crate::network::supported_features(),
+            negotiated: crate::network::supported_features(),
+            result: "ongoing".to_owned(),
+        };
+        let document = document(&log.entries(), &header);
+        let expected = [
+            "# Transcript of a networked game",
+            "- We were the server",
+            "## Messages",
+            "**sent** handshake: features castling, en passant, promotion, stalemate, 20 legal moves, ongoing",
+            "**received** move Ng1→g3",
+            "*GUI* Rejected client move",
+            "**sent** error",
+            "**received** move e2→e4",
+            "**sent** state: e2→e4, board e2→e4",
+        ];
+        let mut rest = document.as_str();
+        for section in expected {
+            let at = rest
+                .find(section)
+                .unwrap_or_else(|| panic!("{} missing or out of order in\n{}", section, document));
+            rest = &rest[at + section.len()..];
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use crate::{attackers, to_cordinate, Square};
+use jonathan_hallstrom_chess::Color;
+use std::sync::OnceLock;
+
+// The traps shipped with the game, see the file for the format
+const BUNDLED: &str = include_str!("traps.txt");
+// Plies after a warning the trap can still go off in, after that it was avoided
+pub const SPRING_PLIES: usize = 4;
+
+type Coord = (usize, usize);
+
+/// One check on the position, all of a trap's have to hold for it to match
+#[derive(PartialEq)]
+enum Condition {
+    ToMove(Color),
+    On(Coord, Square),
+    // The piece on the first square attacks the second
+    Attacks(Coord, Coord),
+    // At most this many pieces of the color defend the square
+    DefendedAtMost(Coord, Color, usize),
+}
+
+impl Condition {
+    fn holds(&self, squares: &[[Square; 8]; 8], to_move: Color) -> bool {
+        match *self {
+            Condition::ToMove(color) => to_move == color,
+            Condition::On((row, col), piece) => squares[row][col] == piece,
+            Condition::Attacks(from, to) => squares[from.0][from.1]
+                .color()
+                .is_some_and(|color| attackers(squares, to, color).contains(&from)),
+            Condition::DefendedAtMost(square, color, most) => {
+                attackers(squares, square, color).len() <= most
+            }
+        }
+    }
+}
+
+/// A trap a beginner falls for, known by the position right before it
+pub struct Trap {
+    pub name: String,
+    pub warning: String,
+    // How to stay out of it, without giving away a move to play
+    pub idea: String,
+    signature: Vec<Condition>,
+    // The position once it went off
+    sprung: Vec<Condition>,
+}
+
+impl Trap {
+    /// Whether the side to move is about to fall for it
+    pub fn matches(&self, squares: &[[Square; 8]; 8], to_move: Color) -> bool {
+        self.signature
+            .iter()
+            .all(|condition| condition.holds(squares, to_move))
+    }
+
+    /// Whether it went off in this position
+    pub fn sprung(&self, squares: &[[Square; 8]; 8], to_move: Color) -> bool {
+        !self.sprung.is_empty()
+            && self
+                .sprung
+                .iter()
+                .all(|condition| condition.holds(squares, to_move))
+    }
+
+    /// The text of the toast shown when it matches
+    #[inline]
+    pub fn toast(&self) -> String {
+        format!("{}, {}", self.warning, self.idea)
+    }
+}
+
+fn parse_color(word: &str) -> Result<Color, String> {
+    match word {
+        "white" => Ok(Color::White),
+        "black" => Ok(Color::Black),
+        _ => Err(format!("\"{}\" isn't white or black", word)),
+    }
+}
+
+fn parse_square(word: &str) -> Result<Coord, String> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(file), Some(rank), None) => to_cordinate(file, rank).map_err(|err| err.to_string()),
+        _ => Err(format!("\"{}\" isn't a square", word)),
+    }
+}
+
+fn parse_piece(word: &str) -> Result<Square, String> {
+    let mut chars = word.chars();
+    let (Some(letter), None) = (chars.next(), chars.next()) else {
+        return Err(format!("\"{}\" isn't a piece letter", word));
+    };
+    let color = match letter.is_ascii_uppercase() {
+        true => Color::White,
+        false => Color::Black,
+    };
+    Ok(match letter.to_ascii_lowercase() {
+        'p' => Square::Pawn(color),
+        'n' => Square::Knight(color),
+        'b' => Square::Bishop(color),
+        'r' => Square::Rook(color),
+        'q' => Square::Queen(color),
+        'k' => Square::King(color),
+        _ => return Err(format!("\"{}\" isn't a piece letter", word)),
+    })
+}
+
+fn parse_condition(keyword: &str, words: &[&str]) -> Result<Condition, String> {
+    match (keyword, words) {
+        ("to-move", [color]) => Ok(Condition::ToMove(parse_color(color)?)),
+        ("on", [square, piece]) => Ok(Condition::On(parse_square(square)?, parse_piece(piece)?)),
+        ("attacks", [from, to]) => Ok(Condition::Attacks(parse_square(from)?, parse_square(to)?)),
+        ("defended", [square, color, most]) => Ok(Condition::DefendedAtMost(
+            parse_square(square)?,
+            parse_color(color)?,
+            most.parse()
+                .map_err(|_| format!("\"{}\" isn't a number", most))?,
+        )),
+        _ => Err(format!("Can't read \"{} {}\"", keyword, words.join(" "))),
+    }
+}
+
+/// Read traps in the format of traps.txt
+pub fn parse(text: &str) -> Result<Vec<Trap>, String> {
+    let mut traps: Vec<Trap> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let at_line = |message: String| format!("Line {}: {}", number + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        if keyword == "trap" {
+            traps.push(Trap {
+                name: rest.to_owned(),
+                warning: String::new(),
+                idea: String::new(),
+                signature: Vec::new(),
+                sprung: Vec::new(),
+            });
+            continue;
+        }
+        let trap = traps
+            .last_mut()
+            .ok_or_else(|| at_line("Expected \"trap <name>\" first".to_owned()))?;
+        match keyword {
+            "warn" => trap.warning = rest.to_owned(),
+            "idea" => trap.idea = rest.to_owned(),
+            "sprung" => {
+                let words: Vec<&str> = rest.split_whitespace().collect();
+                let (keyword, words) = words
+                    .split_first()
+                    .ok_or_else(|| at_line("\"sprung\" needs a condition".to_owned()))?;
+                trap.sprung
+                    .push(parse_condition(keyword, words).map_err(at_line)?);
+            }
+            _ => {
+                let words: Vec<&str> = rest.split_whitespace().collect();
+                trap.signature
+                    .push(parse_condition(keyword, &words).map_err(at_line)?);
+            }
+        }
+    }
+    match traps
+        .iter()
+        .find(|trap| trap.warning.is_empty() || trap.signature.is_empty())
+    {
+        Some(trap) => Err(format!("{} needs a warning and conditions", trap.name)),
+        None => Ok(traps),
+    }
+}
+
+/// The traps shipped with the game
+pub fn bundled() -> &'static [Trap] {
+    static TRAPS: OnceLock<Vec<Trap>> = OnceLock::new();
+    TRAPS.get_or_init(|| parse(BUNDLED).expect("the bundled traps to parse"))
+}
+
+/// The first bundled trap the side to move is about to fall for
+#[inline]
+pub fn warning_for(squares: &[[Square; 8]; 8], to_move: Color) -> Option<&'static Trap> {
+    bundled().iter().find(|trap| trap.matches(squares, to_move))
+}
+
+/// A warning shown during a game, kept so the review can show it at its move
+#[derive(Copy, Clone)]
+pub struct TrapNote {
+    pub trap: &'static Trap,
+    // The ply of the position it was shown in
+    pub ply: usize,
+    // None while the trap can still go off
+    pub avoided: Option<bool>,
+}
+
+impl TrapNote {
+    #[inline]
+    pub fn new(trap: &'static Trap, ply: usize) -> Self {
+        Self {
+            trap,
+            ply,
+            avoided: None,
+        }
+    }
+
+    /// Look at the position after `ply` for whether the trap went off after all
+    pub fn follow(&mut self, squares: &[[Square; 8]; 8], to_move: Color, ply: usize) {
+        if self.avoided.is_some() || ply <= self.ply {
+            return;
+        }
+        if self.trap.sprung(squares, to_move) {
+            self.avoided = Some(false);
+        } else if ply >= self.ply + SPRING_PLIES {
+            self.avoided = Some(true);
+        }
+    }
+
+    /// How it went, for the review and the saved game
+    pub fn describe(&self) -> String {
+        format!(
+            "{} warned at ply {}{}",
+            self.trap.name,
+            self.ply,
+            match self.avoided {
+                Some(true) => ", avoided",
+                Some(false) => ", fallen for",
+                None => "",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opposite_color, parse_fen, parse_move};
+
+    // A trap's name, a line into it and lines that only look alike
+    type Case = (
+        &'static str,
+        &'static [&'static str],
+        &'static [&'static [&'static str]],
+    );
+
+    /// The position after these moves and the side to move in it. None of the lines castle,
+    /// take en passant or promote, so moving the pieces is enough.
+    fn after(moves: &[&str]) -> ([[Square; 8]; 8], Color) {
+        let mut squares =
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut to_move = Color::White;
+        for mv in moves {
+            let (from, to) = parse_move(mv).unwrap();
+            assert!(squares[from.0][from.1].color() == Some(to_move), "{}", mv);
+            squares[to.0][to.1] = squares[from.0][from.1];
+            squares[from.0][from.1] = Square::Empty;
+            to_move = opposite_color(to_move);
+        }
+        (squares, to_move)
+    }
+
+    fn trap(name: &str) -> &'static Trap {
+        bundled()
+            .iter()
+            .find(|trap| trap.name == name)
+            .unwrap_or_else(|| panic!("{} isn't bundled", name))
+    }
+
+    /// Every bundled trap
+    const CASES: [Case; 5] = [
+        (
+            "Scholar's mate",
+            &["e2e4", "e7e5", "f1c4", "b8c6", "d1h5"],
+            &[
+                &["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g7g6"],
+                &["e2e4", "e7e5", "f1c4", "d8e7", "d1h5"],
+                &["e2e4", "e7e5", "d1h5"],
+            ],
+        ),
+        (
+            "Scholar's mate with the queen on f3",
+            &["e2e4", "e7e5", "f1c4", "b8c6", "d1f3"],
+            &[
+                &["e2e4", "e7e5", "f1c4", "g8f6", "d1f3"],
+                &["e2e4", "f7f5", "f1c4", "b8c6", "d1f3"],
+            ],
+        ),
+        (
+            "Fried liver",
+            &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "f3g5"],
+            &[
+                &[
+                    "e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "f3g5", "d7d5",
+                ],
+                &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "g8f6", "f3g5"],
+            ],
+        ),
+        (
+            "Englund gambit queen trap",
+            &[
+                "d2d4", "e7e5", "d4e5", "b8c6", "g1f3", "d8e7", "c1f4", "e7b4",
+            ],
+            &[
+                &["d2d4", "e7e5", "d4e5", "b8c6", "g1f3", "d8e7", "c1f4"],
+                &[
+                    "d2d4", "e7e5", "d4e5", "b8c6", "g1f3", "d8e7", "c1f4", "e7b4", "b1d2",
+                ],
+            ],
+        ),
+        (
+            "Blackburne Shilling gambit",
+            &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "c6d4"],
+            &[
+                &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "f8c5"],
+                &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "c6d4", "f3d4"],
+            ],
+        ),
+    ];
+
+    #[test]
+    fn the_bundled_traps_parse() {
+        assert_eq!(bundled().len(), CASES.len());
+        for trap in bundled() {
+            assert!(!trap.idea.is_empty(), "{}", trap.name);
+            assert!(!trap.sprung.is_empty(), "{}", trap.name);
+        }
+    }
+
+    #[test]
+    fn every_trap_matches_its_line_and_nothing_like_it() {
+        for (name, line, lookalikes) in CASES {
+            let (squares, to_move) = after(line);
+            assert!(trap(name).matches(&squares, to_move), "{}", name);
+            assert_eq!(warning_for(&squares, to_move).unwrap().name, name);
+            for lookalike in lookalikes {
+                let (squares, to_move) = after(lookalike);
+                assert!(!trap(name).matches(&squares, to_move), "{:?}", lookalike);
+            }
+        }
+        let (squares, to_move) = after(&[]);
+        assert!(warning_for(&squares, to_move).is_none());
+    }
+
+    #[test]
+    fn a_trap_is_avoided_or_fallen_for() {
+        let line = ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5"];
+        let follow = |replies: &[&str]| {
+            let mut note = TrapNote::new(trap("Scholar's mate"), line.len());
+            let mut moves = line.to_vec();
+            for reply in replies {
+                moves.push(reply);
+                let (squares, to_move) = after(&moves);
+                note.follow(&squares, to_move, moves.len());
+            }
+            note.avoided
+        };
+        assert_eq!(follow(&["g8f6", "h5f7"]), Some(false));
+        assert_eq!(follow(&["g7g6", "h5f3"]), None);
+        assert_eq!(follow(&["g7g6", "h5f3", "g8f6", "d2d3"]), Some(true));
+        let mut note = TrapNote::new(trap("Scholar's mate"), 5);
+        note.avoided = Some(true);
+        assert_eq!(note.describe(), "Scholar's mate warned at ply 5, avoided");
+    }
+
+    #[test]
+    fn mistakes_in_a_trap_file_are_reported() {
+        assert!(parse("warn No trap yet")
+            .err()
+            .unwrap()
+            .starts_with("Line 1"));
+        assert!(parse("trap T\nwarn W\non z9 Q")
+            .err()
+            .unwrap()
+            .starts_with("Line 3"));
+        assert!(parse("trap T\nwarn W\non e4 X").is_err());
+        assert!(parse("trap T\nwarn W\ndefended e4 white many").is_err());
+        assert!(parse("trap T\nwarn W").is_err());
+        assert!(
+            parse("trap T\nwarn W\nto-move white\nsprung on e4 P").unwrap()[0].sprung
+                == vec![Condition::On((4, 4), Square::Pawn(Color::White))]
+        );
+    }
+}
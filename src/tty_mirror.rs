@@ -0,0 +1,231 @@
+use crate::clock::format_time;
+use crate::{color_name, Square};
+use jonathan_hallstrom_chess::Color;
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Rapid position changes within this window only print the last one
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+const ANSI_WHITE_PIECE: &str = "\x1b[1;37m";
+const ANSI_BLACK_PIECE: &str = "\x1b[1;34m";
+const ANSI_EMPTY: &str = "\x1b[2m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum TtyOrientation {
+    White,
+    Black,
+    // Use the same orientation as the window
+    Follow,
+}
+
+impl TtyOrientation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "white" => Some(TtyOrientation::White),
+            "black" => Some(TtyOrientation::Black),
+            "follow" => Some(TtyOrientation::Follow),
+            _ => None,
+        }
+    }
+}
+
+/// Mirrors every position to stdout from a printer thread so the render thread never blocks on it
+pub struct TtyMirror {
+    latest: Arc<(Mutex<Option<String>>, Condvar)>,
+    orientation: TtyOrientation,
+    colored: bool,
+}
+
+impl TtyMirror {
+    pub fn start(orientation: TtyOrientation) -> Self {
+        let latest = Arc::new((Mutex::new(None::<String>), Condvar::new()));
+
+        let printer_latest = Arc::clone(&latest);
+        thread::spawn(move || {
+            let (lock, condvar) = &*printer_latest;
+            loop {
+                {
+                    let mut block = lock.lock().unwrap();
+                    while block.is_none() {
+                        block = condvar.wait(block).unwrap();
+                    }
+                }
+                // Let the position settle, then print only the newest one
+                thread::sleep(DEBOUNCE);
+                if let Some(block) = lock.lock().unwrap().take() {
+                    let mut stdout = std::io::stdout().lock();
+                    let _ = stdout.write_all(block.as_bytes());
+                    let _ = stdout.flush();
+                }
+            }
+        });
+
+        Self {
+            latest,
+            orientation,
+            colored: std::io::stdout().is_terminal(),
+        }
+    }
+
+    /// Queues a position for printing, replacing any position that hasn't been printed yet
+    pub fn show(
+        &self,
+        squares: &[[Square; 8]; 8],
+        window_flipped: bool,
+        last_move: Option<&str>,
+        clocks: Option<(Duration, Duration)>,
+        status: &str,
+    ) {
+        let flipped = match self.orientation {
+            TtyOrientation::White => false,
+            TtyOrientation::Black => true,
+            TtyOrientation::Follow => window_flipped,
+        };
+
+        let mut block = render_board(squares, flipped, self.colored);
+        if let Some(last_move) = last_move {
+            block.push_str(&format!("Last move: {}\n", last_move));
+        }
+        if let Some((white, black)) = clocks {
+            block.push_str(&clock_line(white, black));
+            block.push('\n');
+        }
+        block.push_str(status);
+        block.push_str("\n\n");
+
+        let (lock, condvar) = &*self.latest;
+        *lock.lock().unwrap() = Some(block);
+        condvar.notify_one();
+    }
+}
+
+#[inline]
+fn piece_letter(square: &Square) -> char {
+    let letter = match square {
+        Square::Empty => return '.',
+        Square::Pawn(_) => 'p',
+        Square::Rook(_) => 'r',
+        Square::Bishop(_) => 'b',
+        Square::Knight(_) => 'n',
+        Square::King(_) => 'k',
+        Square::Queen(_) => 'q',
+    };
+    match square.color() {
+        Some(Color::White) => letter.to_ascii_uppercase(),
+        _ => letter,
+    }
+}
+
+/// Ranks top to bottom with a file footer, White at the bottom unless flipped
+pub fn render_board(squares: &[[Square; 8]; 8], flipped: bool, colored: bool) -> String {
+    let order: [usize; 8] = match flipped {
+        true => [7, 6, 5, 4, 3, 2, 1, 0],
+        false => [0, 1, 2, 3, 4, 5, 6, 7],
+    };
+
+    let mut text = String::new();
+    for row in order {
+        // Row 0 is the eighth rank
+        text.push_str(&format!("{} ", 8 - row));
+        for col in order {
+            let square = &squares[row][col];
+            if colored {
+                text.push_str(match square.color() {
+                    Some(Color::White) => ANSI_WHITE_PIECE,
+                    Some(Color::Black) => ANSI_BLACK_PIECE,
+                    None => ANSI_EMPTY,
+                });
+            }
+            text.push(' ');
+            text.push(piece_letter(square));
+            if colored {
+                text.push_str(ANSI_RESET);
+            }
+        }
+        text.push('\n');
+    }
+
+    text.push_str("  ");
+    for col in order {
+        text.push(' ');
+        text.push((b'a' + col as u8) as char);
+    }
+    text.push('\n');
+    text
+}
+
+/// Both clocks on one line, White first
+pub fn clock_line(white: Duration, black: Duration) -> String {
+    format!("White {}  Black {}", format_time(white), format_time(black))
+}
+
+/// One line describing whose turn it is, or the result once the game is over
+pub fn status_line(to_move: Color, result: Option<String>) -> String {
+    result.unwrap_or_else(|| format!("{} to move", color_name(to_move)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_fen;
+
+    // After 1. e4 e5 2. Nf3
+    const FEN: &str = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+
+    const WHITE_VIEW: &str = "\
+8  r n b q k b n r
+7  p p p p . p p p
+6  . . . . . . . .
+5  . . . . p . . .
+4  . . . . P . . .
+3  . . . . . N . .
+2  P P P P . P P P
+1  R N B Q K B . R
+   a b c d e f g h
+";
+
+    const BLACK_VIEW: &str = "\
+1  R . B K Q B N R
+2  P P P . P P P P
+3  . . N . . . . .
+4  . . . P . . . .
+5  . . . p . . . .
+6  . . . . . . . .
+7  p p p . p p p p
+8  r n b k q b n r
+   h g f e d c b a
+";
+
+    #[test]
+    fn plain_boards_in_both_orientations() {
+        let squares = parse_fen(FEN).unwrap();
+        assert_eq!(render_board(&squares, false, false), WHITE_VIEW);
+        assert_eq!(render_board(&squares, true, false), BLACK_VIEW);
+    }
+
+    #[test]
+    fn colored_boards_only_add_escape_codes() {
+        let squares = parse_fen(FEN).unwrap();
+        for flipped in [false, true] {
+            let colored = render_board(&squares, flipped, true);
+            let stripped = [ANSI_WHITE_PIECE, ANSI_BLACK_PIECE, ANSI_EMPTY, ANSI_RESET]
+                .iter()
+                .fold(colored, |text, code| text.replace(code, ""));
+            assert_eq!(stripped, render_board(&squares, flipped, false));
+        }
+        let first_rank = render_board(&squares, false, true);
+        assert!(first_rank.starts_with(&format!("8 {} r{}", ANSI_BLACK_PIECE, ANSI_RESET)));
+    }
+
+    #[test]
+    fn clocks_are_listed_white_first() {
+        assert_eq!(
+            clock_line(Duration::from_secs(299), Duration::from_millis(9_400)),
+            "White 4:59  Black 0:09.4"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{opposite_color, Square};
+use jonathan_hallstrom_chess::Color;
+use std::time::Duration;
+
+/// How long a piece clicked out of turn shakes
+pub const SHAKE_DURATION: Duration = Duration::from_millis(320);
+// Times the piece swings either way while it shakes
+const SHAKE_SWINGS: f32 = 3.0;
+// Farthest it swings, as a part of the square's width
+const SHAKE_AMPLITUDE: f32 = 0.08;
+// How long each dot of the waiting indicator takes to come up
+const DOT_DURATION: Duration = Duration::from_millis(400);
+
+/// Who plays at this window
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum Seat {
+    // Both sides, or the side the computer doesn't play
+    Local {
+        computer: Option<Color>,
+    },
+    // One side of a networked game. A client waits for the server to confirm its move, and
+    // nobody moves before the peer is there.
+    Network {
+        color: Color,
+        spectating: bool,
+        connected: bool,
+        pending: bool,
+    },
+}
+
+impl Seat {
+    /// The side played here, None when it is both or neither
+    #[inline]
+    pub fn local_color(&self) -> Option<Color> {
+        match *self {
+            Seat::Local { computer } => computer.map(opposite_color),
+            Seat::Network { spectating, .. } if spectating => None,
+            Seat::Network { color, .. } => Some(color),
+        }
+    }
+}
+
+/// Whether whoever sits here may move with `to_move` to move. Input and the waiting treatment
+/// both go by it, so what is shown can't disagree with what a click does.
+pub fn is_local_turn(seat: Seat, to_move: Color) -> bool {
+    match seat {
+        Seat::Local { computer } => Some(to_move) != computer,
+        Seat::Network {
+            color,
+            spectating,
+            connected,
+            pending,
+        } => !spectating && connected && !pending && to_move == color,
+    }
+}
+
+/// The side whose pieces are dimmed while it waits for the other one, premoves or not
+#[inline]
+pub fn dimmed(seat: Seat, to_move: Color) -> Option<Color> {
+    seat.local_color().filter(|_| !is_local_turn(seat, to_move))
+}
+
+/// What a click on the board does while it is the other side's turn
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum OutOfTurn {
+    // Nothing, the click wasn't on one of our pieces
+    Ignore,
+    // The piece shakes to say it can't move yet
+    Shake,
+    // The piece is picked up for a premove
+    Premove,
+}
+
+/// What clicking `clicked` does while waiting as `seat`, with premoves on or off
+pub fn out_of_turn_click(
+    seat: Seat,
+    squares: &[[Square; 8]; 8],
+    clicked: (usize, usize),
+    premoves: bool,
+) -> OutOfTurn {
+    let Some(own) = seat.local_color() else {
+        return OutOfTurn::Ignore;
+    };
+    match (squares[clicked.0][clicked.1].color() == Some(own), premoves) {
+        (false, _) => OutOfTurn::Ignore,
+        (true, false) => OutOfTurn::Shake,
+        (true, true) => OutOfTurn::Premove,
+    }
+}
+
+/// A piece shaking side to side after it was clicked out of turn
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Shake {
+    pub square: (usize, usize),
+    pub start: Duration,
+}
+
+impl Shake {
+    /// How far the piece is off its square at `now`, as a part of the square's width. It
+    /// swings less and less and is None once the shake is over.
+    pub fn offset(&self, now: Duration) -> Option<f32> {
+        let elapsed = now.saturating_sub(self.start);
+        if elapsed >= SHAKE_DURATION {
+            return None;
+        }
+        let t = elapsed.as_secs_f32() / SHAKE_DURATION.as_secs_f32();
+        let swing = (t * SHAKE_SWINGS * std::f32::consts::TAU).sin();
+        Some(SHAKE_AMPLITUDE * (1.0 - t) * swing)
+    }
+}
+
+/// The piece picked up while waiting and where it goes, played once the turn comes if it is
+/// legal by then
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Premove {
+    pub from: (usize, usize),
+    pub to: Option<(usize, usize)>,
+}
+
+/// The dots after "waiting for the opponent" at `now`, one more every little while and none
+/// again after three
+#[inline]
+pub fn waiting_dots(now: Duration) -> &'static str {
+    let dots = now.as_millis() / DOT_DURATION.as_millis();
+    ["", ".", "..", "..."][dots as usize % 4]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_fen;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn network(color: Color) -> Seat {
+        Seat::Network {
+            color,
+            spectating: false,
+            connected: true,
+            pending: false,
+        }
+    }
+
+    #[test]
+    fn whose_turn_it_is_in_every_mode() {
+        for to_move in [Color::White, Color::Black] {
+            // A local game is always someone's turn here
+            assert!(is_local_turn(Seat::Local { computer: None }, to_move));
+            assert!(dimmed(Seat::Local { computer: None }, to_move).is_none());
+
+            for other in [Color::White, Color::Black] {
+                let computer = Seat::Local {
+                    computer: Some(other),
+                };
+                assert_eq!(is_local_turn(computer, to_move), to_move != other);
+                let network = network(opposite_color(other));
+                assert_eq!(is_local_turn(network, to_move), to_move != other);
+                // Dimmed exactly while waiting
+                for seat in [computer, network] {
+                    let waiting = dimmed(seat, to_move) == Some(opposite_color(other));
+                    assert_eq!(waiting, to_move == other);
+                }
+            }
+        }
+        let waiting_on = |change: fn(&mut Seat)| {
+            let mut seat = network(Color::White);
+            change(&mut seat);
+            is_local_turn(seat, Color::White)
+        };
+        assert!(waiting_on(|_| {}));
+        assert!(!waiting_on(|seat| {
+            if let Seat::Network { pending, .. } = seat {
+                *pending = true
+            }
+        }));
+        assert!(!waiting_on(|seat| {
+            if let Seat::Network { connected, .. } = seat {
+                *connected = false
+            }
+        }));
+        assert!(!waiting_on(|seat| {
+            if let Seat::Network { spectating, .. } = seat {
+                *spectating = true
+            }
+        }));
+    }
+
+    #[test]
+    fn a_shake_settles_within_its_duration() {
+        let shake = Shake {
+            square: (6, 4),
+            start: Duration::from_secs(2),
+        };
+        assert_eq!(shake.offset(Duration::from_secs(2)), Some(0.0));
+        let mut now = Duration::from_secs(2);
+        while let Some(offset) = shake.offset(now) {
+            assert!(offset.abs() <= SHAKE_AMPLITUDE, "{}", offset);
+            now += Duration::from_millis(5);
+        }
+        assert!(now.saturating_sub(shake.start) <= SHAKE_DURATION + Duration::from_millis(5));
+        assert_eq!(shake.offset(Duration::from_secs(60)), None);
+        // Before it started it is as good as over, the clock never goes back
+        assert_eq!(shake.offset(Duration::ZERO), Some(0.0));
+    }
+
+    #[test]
+    fn premoves_change_what_a_click_does_but_not_the_dimming() {
+        let squares = parse_fen(START).unwrap();
+        let seat = network(Color::White);
+        let (pawn, theirs, empty) = ((6, 4), (1, 4), (4, 4));
+        assert_eq!(
+            out_of_turn_click(seat, &squares, pawn, false),
+            OutOfTurn::Shake
+        );
+        assert_eq!(
+            out_of_turn_click(seat, &squares, pawn, true),
+            OutOfTurn::Premove
+        );
+        for premoves in [false, true] {
+            assert_eq!(
+                out_of_turn_click(seat, &squares, theirs, premoves),
+                OutOfTurn::Ignore
+            );
+            assert_eq!(
+                out_of_turn_click(seat, &squares, empty, premoves),
+                OutOfTurn::Ignore
+            );
+        }
+        // Dimming never asks about premoves
+        assert!(dimmed(seat, Color::Black) == Some(Color::White));
+
+        assert_eq!(waiting_dots(Duration::ZERO), "");
+        assert_eq!(waiting_dots(Duration::from_millis(1250)), "...");
+        assert_eq!(waiting_dots(Duration::from_millis(1600)), "");
+    }
+}
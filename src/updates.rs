@@ -0,0 +1,502 @@
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Kept in the user data directory, shared by every profile
+pub const FILE_NAME: &str = "updates.json";
+// A check is made at startup at most this often
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// The whole fetch gives up after this, it runs beside the game and never holds it up
+pub const TIMEOUT: Duration = Duration::from_secs(3);
+/// Printed when the check is turned on, so it is clear what it does
+pub const EXPLANATION: &str = "Once a day at startup a single request goes to the releases \
+    URL to see whether a newer version is out, nothing is sent about you or your games. \
+    --check-updates off stops it and --offline skips it for one run.";
+
+/// Whether --check-updates turned the check on, with the URL of the releases document, or off
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum UpdateSwitch {
+    On(String),
+    Off,
+}
+
+impl UpdateSwitch {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "off" => Some(UpdateSwitch::Off),
+            url if url.starts_with("https://") || url.starts_with("http://") => {
+                Some(UpdateSwitch::On(url.to_owned()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+enum Identifier {
+    // Numeric identifiers sort before alphanumeric ones
+    Numeric(u64),
+    Alphanumeric(String),
+}
+
+/// A semantic version like "0.4.0-rc.1+build.5", build metadata is left out of comparisons
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Version {
+    major: u64,
+    minor: u64,
+    patch: u64,
+    pre_release: Vec<Identifier>,
+}
+
+impl Version {
+    /// A version with or without a leading v
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let text = text.strip_prefix('v').unwrap_or(text);
+        let text = text.split_once('+').map_or(text, |(version, _)| version);
+        let (core, pre_release) = match text.split_once('-') {
+            Some((core, pre_release)) => (core, Some(pre_release)),
+            None => (text, None),
+        };
+        let numbers: Vec<u64> = core
+            .split('.')
+            .map(|number| number.parse().ok())
+            .collect::<Option<_>>()?;
+        let [major, minor, patch] = numbers[..] else {
+            return None;
+        };
+        let pre_release = match pre_release {
+            None => Vec::new(),
+            Some(pre_release) => pre_release
+                .split('.')
+                .map(|identifier| match identifier.parse() {
+                    Ok(number) => Some(Identifier::Numeric(number)),
+                    Err(_) if identifier.is_empty() => None,
+                    Err(_) => Some(Identifier::Alphanumeric(identifier.to_owned())),
+                })
+                .collect::<Option<_>>()?,
+        };
+        Some(Self {
+            major,
+            minor,
+            patch,
+            pre_release,
+        })
+    }
+
+    /// The version this was built as
+    pub fn current() -> Self {
+        Self::parse(env!("CARGO_PKG_VERSION")).unwrap()
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            // A pre-release comes before the release itself
+            .then_with(
+                || match (self.pre_release.is_empty(), other.pre_release.is_empty()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => self.pre_release.cmp(&other.pre_release),
+                },
+            )
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The latest release as the releases document describes it, like
+/// {"version": "0.4.0", "note": "protocol fixes for castling interop", "url": "https://..."}
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Release {
+    pub version: String,
+    // What changed, like the oldest version it still plays over the network with
+    pub note: Option<String>,
+    pub url: String,
+}
+
+impl Release {
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_owned);
+        Ok(Self {
+            version: field("version").ok_or("no version")?,
+            note: field("note"),
+            url: field("url").ok_or("no url")?,
+        })
+    }
+
+    /// What the start screen says about it, like "v0.4.0 available — protocol fixes"
+    pub fn banner(&self) -> String {
+        let version = self.version.trim_start_matches('v');
+        match &self.note {
+            Some(note) => format!("v{} available — {}", version, note),
+            None => format!("v{} available", version),
+        }
+    }
+}
+
+/// What is kept between runs in `updates.json`
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct UpdateSettings {
+    // The releases document, None while the check is off
+    pub url: Option<String>,
+    // When it was last checked, in seconds since the epoch
+    pub last_checked: Option<u64>,
+    // A version the player doesn't want to hear about again
+    pub skipped: Option<String>,
+}
+
+impl UpdateSettings {
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        let text = |text: &Option<String>| text.clone().map_or(Value::Null, Value::String);
+        object.insert("url".to_owned(), text(&self.url));
+        object.insert(
+            "last_checked".to_owned(),
+            self.last_checked
+                .map_or(Value::Null, |seconds| Value::Number(seconds.into())),
+        );
+        object.insert("skipped".to_owned(), text(&self.skipped));
+        Value::Object(object)
+    }
+
+    fn from_json(value: &Value) -> Self {
+        let text = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_owned);
+        Self {
+            url: text("url"),
+            last_checked: value.get("last_checked").and_then(Value::as_u64),
+            skipped: text("skipped"),
+        }
+    }
+
+    /// The settings in `path`, off if there are none or they are damaged
+    pub fn read(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+            .map_or_else(Self::default, |value| Self::from_json(&value))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| serde_json::to_string(&self.to_json()).map_err(std::io::Error::from))
+            .and_then(|text| fs::write(path, text))
+            .map_err(|err| format!("Can't save the update settings: {}", err))
+    }
+
+    /// Whether a check is due at `now`, in seconds since the epoch
+    pub fn due(&self, now: u64) -> bool {
+        self.url.is_some()
+            && self
+                .last_checked
+                .is_none_or(|last| now.saturating_sub(last) >= CHECK_INTERVAL.as_secs())
+    }
+
+    /// Whether the start screen should tell of `release` to someone running `current`
+    pub fn offers(&self, release: &Release, current: &Version) -> bool {
+        let Some(version) = Version::parse(&release.version) else {
+            return false;
+        };
+        let skipped = self.skipped.as_deref().and_then(Version::parse);
+        version > *current && skipped != Some(version)
+    }
+}
+
+#[inline]
+pub fn seconds_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// A single GET, so tests can answer it without a network
+pub trait Fetch: Send {
+    fn get(&self, url: &str, timeout: Duration) -> Result<String, String>;
+}
+
+/// Fetches with the curl program, which speaks HTTPS without another dependency
+pub struct Curl;
+
+impl Fetch for Curl {
+    fn get(&self, url: &str, timeout: Duration) -> Result<String, String> {
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location"])
+            .args(["--proto", "=https,http", "--max-time"])
+            .arg(timeout.as_secs().to_string())
+            .arg(url)
+            .output()
+            .map_err(|err| format!("can't run curl: {}", err))?;
+        match output.status.success() {
+            true => String::from_utf8(output.stdout).map_err(|err| err.to_string()),
+            false => Err(String::from_utf8_lossy(&output.stderr).trim().to_owned()),
+        }
+    }
+}
+
+/// The newer release to tell of, if there is one
+pub fn check(
+    fetch: &dyn Fetch,
+    settings: &UpdateSettings,
+    current: &Version,
+) -> Result<Option<Release>, String> {
+    let url = settings.url.as_deref().ok_or("the check is off")?;
+    let release = Release::from_json(&fetch.get(url, TIMEOUT)?)?;
+    release_page(&release.url, url)?;
+    Ok(settings.offers(&release, current).then_some(release))
+}
+
+/// Check in the background, the release comes through the receiver if there is one to tell
+/// of. Failures are only printed in debug builds, nobody needs to hear the network is down.
+pub fn check_in_background(fetch: Box<dyn Fetch>, settings: UpdateSettings) -> Receiver<Release> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(
+        move || match check(fetch.as_ref(), &settings, &Version::current()) {
+            Ok(Some(release)) => {
+                let _ = sender.send(release);
+            }
+            Ok(None) => {}
+            Err(message) => {
+                if cfg!(debug_assertions) {
+                    println!("Update check failed: {}", message);
+                }
+            }
+        },
+    );
+    receiver
+}
+
+// Characters a shell or the Windows command line would read as more than part of a URL
+const SHELL_CHARACTERS: &[char] = &[
+    '&', '|', ';', '<', '>', '^', '"', '\'', '`', '$', '\\', '(', ')',
+];
+
+/// Whether `url` is https with nothing a shell would read into
+fn plain_https(url: &str) -> bool {
+    url.starts_with("https://")
+        && !url
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || SHELL_CHARACTERS.contains(&c))
+}
+
+/// The host of an http or https URL, lowercase, None for anything else
+fn host(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Checks the page a release points to before anything opens it: an https URL on the same
+/// host as the releases document `releases`, with nothing a shell would read into
+pub fn release_page<'a>(url: &'a str, releases: &str) -> Result<&'a str, String> {
+    if !plain_https(url) {
+        return Err(format!("the release page {} isn't a plain https URL", url));
+    }
+    match host(url).is_some_and(|host| Some(host) == self::host(releases)) {
+        true => Ok(url),
+        false => Err(format!(
+            "the release page {} isn't on the releases host",
+            url
+        )),
+    }
+}
+
+/// Open the release page in the default browser. Only an https URL with nothing a shell would
+/// read into is handed over, and never through a shell.
+pub fn open_in_browser(url: &str) -> std::io::Result<()> {
+    if !plain_https(url) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} isn't a page to open", url),
+        ));
+    }
+    let mut command = match std::env::consts::OS {
+        "windows" => {
+            let mut command = Command::new("rundll32");
+            command.arg("url.dll,FileProtocolHandler");
+            command
+        }
+        "macos" => Command::new("open"),
+        _ => Command::new("xdg-open"),
+    };
+    command.arg(url).spawn().map(|_| ())
+}
+
+/// Open a folder in the file manager. Only a folder that exists is opened, by its absolute
+/// path, so it can't be taken for a URL.
+pub fn open_folder(dir: &Path) -> std::io::Result<()> {
+    let dir = fs::canonicalize(dir)?;
+    if !dir.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} isn't a folder", dir.display()),
+        ));
+    }
+    let program = match std::env::consts::OS {
+        "windows" => "explorer",
+        "macos" => "open",
+        _ => "xdg-open",
+    };
+    Command::new(program).arg(dir).spawn().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    struct Canned(Result<&'static str, &'static str>);
+
+    impl Fetch for Canned {
+        fn get(&self, _url: &str, _timeout: Duration) -> Result<String, String> {
+            self.0.map(str::to_owned).map_err(str::to_owned)
+        }
+    }
+
+    fn version(text: &str) -> Version {
+        Version::parse(text).unwrap()
+    }
+
+    fn checking() -> UpdateSettings {
+        UpdateSettings {
+            url: Some("https://example.com/latest.json".to_owned()),
+            ..UpdateSettings::default()
+        }
+    }
+
+    #[test]
+    fn versions_compare_by_semver_precedence() {
+        let ordered = [
+            "0.3.9",
+            "0.4.0-alpha",
+            "0.4.0-alpha.1",
+            "0.4.0-alpha.beta",
+            "0.4.0-beta.2",
+            "0.4.0-beta.11",
+            "0.4.0-rc.1",
+            "0.4.0",
+            "0.10.0",
+            "1.0.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{:?}", pair);
+        }
+        // Build metadata doesn't count, and a v in front doesn't matter
+        assert_eq!(version("v0.4.0+build.5"), version("0.4.0"));
+        for broken in ["0.4", "0.4.0.1", "0.x.0", "0.4.0-", "0.4.0-rc..1", ""] {
+            assert_eq!(Version::parse(broken), None, "{}", broken);
+        }
+    }
+
+    #[test]
+    fn only_newer_versions_not_skipped_are_offered() {
+        let release = |version: &str| Release {
+            version: version.to_owned(),
+            note: None,
+            url: "https://example.com/release".to_owned(),
+        };
+        let mut settings = checking();
+        let current = version("0.3.0");
+        assert!(settings.offers(&release("0.4.0"), &current));
+        assert!(settings.offers(&release("0.3.1-rc.1"), &current));
+        assert!(!settings.offers(&release("0.3.0+build.7"), &current));
+        assert!(!settings.offers(&release("0.3.0-rc.1"), &current));
+        assert!(!settings.offers(&release("later"), &current));
+
+        settings.skipped = Some("0.4.0".to_owned());
+        assert!(!settings.offers(&release("v0.4.0+build.2"), &current));
+        assert!(settings.offers(&release("0.4.1"), &current));
+    }
+
+    #[test]
+    fn checks_are_made_once_a_day_and_only_when_on() {
+        let day = CHECK_INTERVAL.as_secs();
+        assert!(!UpdateSettings::default().due(day));
+        let mut settings = checking();
+        assert!(settings.due(day));
+        settings.last_checked = Some(day);
+        assert!(!settings.due(day + day - 1));
+        assert!(settings.due(day + day));
+    }
+
+    #[test]
+    fn fetched_releases_are_offered_and_failures_are_errors() {
+        let current = version("0.3.0");
+        let newer = Canned(Ok(
+            r#"{"version": "0.4.0", "note": "protocol fixes for castling interop", "url": "https://example.com/v0.4.0"}"#,
+        ));
+        let release = check(&newer, &checking(), &current).unwrap().unwrap();
+        assert_eq!(
+            release.banner(),
+            "v0.4.0 available — protocol fixes for castling interop"
+        );
+        let same = Canned(Ok(r#"{"version": "0.3.0", "url": "https://example.com"}"#));
+        assert_eq!(check(&same, &checking(), &current), Ok(None));
+
+        for broken in [
+            Ok("<html>"),
+            Ok(r#"{"version": "0.4.0"}"#),
+            Err("timed out"),
+        ] {
+            assert!(check(&Canned(broken), &checking(), &current).is_err());
+        }
+        // Nothing is fetched while the check is off
+        assert!(check(&newer, &UpdateSettings::default(), &current).is_err());
+    }
+
+    #[test]
+    fn only_https_pages_on_the_releases_host_are_opened() {
+        let releases = "https://example.com/latest.json";
+        assert!(release_page("https://example.com/v0.4.0", releases).is_ok());
+        assert!(release_page("https://EXAMPLE.com", "http://example.com/latest.json").is_ok());
+        for page in [
+            "http://example.com/v0.4.0",
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+            "https://example.org/v0.4.0",
+            "https://example.com@example.org/",
+            "https://example.com/v0.4.0&calc.exe",
+            "https://example.com/$(reboot)",
+            "https://example.com/a b",
+            "https://example.com/\n",
+        ] {
+            assert!(release_page(page, releases).is_err(), "{}", page);
+        }
+        // A release document pointing elsewhere is turned down with the check
+        let elsewhere = Canned(Ok(
+            r#"{"version": "0.4.0", "url": "https://example.org/x"}"#,
+        ));
+        assert!(check(&elsewhere, &checking(), &version("0.3.0")).is_err());
+        assert!(open_in_browser("file:///etc/passwd").is_err());
+    }
+
+    #[test]
+    fn skipped_versions_are_remembered() {
+        let scratch = Scratch::new("updates");
+        let path = scratch.join(FILE_NAME);
+        let settings = UpdateSettings {
+            skipped: Some("0.4.0".to_owned()),
+            last_checked: Some(1_700_000_000),
+            ..checking()
+        };
+        settings.write(&path).unwrap();
+        assert_eq!(UpdateSettings::read(&path), settings);
+        let _ = fs::remove_file(&path);
+        assert_eq!(UpdateSettings::read(&path), UpdateSettings::default());
+    }
+}
@@ -0,0 +1,904 @@
+use crate::config::Validation;
+use crate::network::{
+    compute_joever, internal_to_network_board, negotiated_features, network_fen,
+    network_piece_letter, network_to_internal_board, network_to_internal_move,
+    network_to_internal_piece, Handshake, NetworkCoord, ILLEGAL_MOVES_FORFEIT, OUT_OF_TIME,
+};
+use crate::session::{Rehandshake, Session};
+use crate::transcript::{board_diff, square_name};
+use crate::{opposite_color, parse_move, ExcludedMoves, Square};
+use chess_network_protocol::{ClientToServer, Features, Joever, Move, Piece, ServerToClient};
+use jonathan_hallstrom_chess::Color;
+use serde_json::{Map, Value};
+use std::io::Read;
+
+type NetworkBoard = [[Piece; 8]; 8];
+
+/// Which of the protocol's messages a message is read as
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Direction {
+    ServerToClient,
+    ClientToServer,
+    HandshakeServerToClient,
+    HandshakeClientToServer,
+}
+
+// In the order a message of unknown direction is tried as
+const DIRECTIONS: [Direction; 4] = [
+    Direction::ServerToClient,
+    Direction::ClientToServer,
+    Direction::HandshakeServerToClient,
+    Direction::HandshakeClientToServer,
+];
+
+impl Direction {
+    /// The name `--type` takes
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::ServerToClient => "server-to-client",
+            Direction::ClientToServer => "client-to-server",
+            Direction::HandshakeServerToClient => "handshake-s2c",
+            Direction::HandshakeClientToServer => "handshake-c2s",
+        }
+    }
+
+    #[inline]
+    pub fn parse(name: &str) -> Option<Self> {
+        DIRECTIONS
+            .into_iter()
+            .find(|direction| direction.name() == name)
+    }
+}
+
+/// A message as the protocol's types read it
+pub enum Message {
+    ServerToClient(ServerToClient),
+    ClientToServer(ClientToServer),
+    Handshake(Handshake),
+}
+
+impl Message {
+    fn read(value: &Value, direction: Direction) -> Result<Self, serde_json::Error> {
+        let value = value.clone();
+        Ok(match direction {
+            Direction::ServerToClient => Message::ServerToClient(serde_json::from_value(value)?),
+            Direction::ClientToServer => Message::ClientToServer(serde_json::from_value(value)?),
+            Direction::HandshakeServerToClient => {
+                Message::Handshake(Handshake::ServerToClient(serde_json::from_value(value)?))
+            }
+            Direction::HandshakeClientToServer => {
+                Message::Handshake(Handshake::ClientToServer(serde_json::from_value(value)?))
+            }
+        })
+    }
+}
+
+/// One thing checked about a message
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    // What was wrong, empty when there is nothing to say
+    pub detail: String,
+}
+
+impl Check {
+    #[inline]
+    fn pass(name: &'static str) -> Self {
+        Self {
+            name,
+            passed: true,
+            detail: String::new(),
+        }
+    }
+
+    #[inline]
+    fn fail(name: &'static str, detail: String) -> Self {
+        Self {
+            name,
+            passed: false,
+            detail,
+        }
+    }
+
+    /// Passed without any of `problems`, which the detail lists
+    #[inline]
+    fn of(name: &'static str, problems: Vec<String>) -> Self {
+        Self {
+            name,
+            passed: problems.is_empty(),
+            detail: problems.join("; "),
+        }
+    }
+}
+
+/// Everything checked about one message
+pub struct Report {
+    // Counted from 1
+    pub index: usize,
+    // None for a message that is none of them
+    pub direction: Option<Direction>,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    #[inline]
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    /// "Message 2, server-to-client: invalid" and a line for every check
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "Message {}, {}: {}",
+            self.index,
+            self.direction.map_or("unknown", Direction::name),
+            match self.passed() {
+                true => "valid",
+                false => "invalid",
+            }
+        );
+        for check in &self.checks {
+            text.push_str(&format!(
+                "\n    {:<5} {}",
+                match check.passed {
+                    true => "ok",
+                    false => "FAIL",
+                },
+                check.name
+            ));
+            if !check.detail.is_empty() {
+                text.push_str(&format!(": {}", check.detail));
+            }
+        }
+        text
+    }
+
+    pub fn to_json(&self) -> Value {
+        let checks = self
+            .checks
+            .iter()
+            .map(|check| {
+                let mut object = Map::new();
+                object.insert("check".to_owned(), Value::String(check.name.to_owned()));
+                object.insert("passed".to_owned(), Value::Bool(check.passed));
+                object.insert("detail".to_owned(), Value::String(check.detail.clone()));
+                Value::Object(object)
+            })
+            .collect();
+        let mut object = Map::new();
+        object.insert(
+            "index".to_owned(),
+            Value::Number((self.index as u64).into()),
+        );
+        object.insert(
+            "type".to_owned(),
+            self.direction.map_or(Value::Null, |direction| {
+                Value::String(direction.name().to_owned())
+            }),
+        );
+        object.insert("valid".to_owned(), Value::Bool(self.passed()));
+        object.insert("checks".to_owned(), Value::Array(checks));
+        Value::Object(object)
+    }
+}
+
+/// The report of a whole run, valid when every message is
+pub fn report_json(reports: &[Report]) -> Value {
+    let mut object = Map::new();
+    object.insert(
+        "valid".to_owned(),
+        Value::Bool(reports.iter().all(Report::passed)),
+    );
+    object.insert(
+        "messages".to_owned(),
+        Value::Array(reports.iter().map(Report::to_json).collect()),
+    );
+    Value::Object(object)
+}
+
+/// Read a message as `expected`, or as whichever message it is without. Why it isn't one
+/// says where the JSON went wrong, as serde tells it.
+fn read(
+    value: &Value,
+    expected: Option<Direction>,
+) -> (Option<Direction>, Result<Message, String>) {
+    let Some(expected) = expected else {
+        let mut reasons = Vec::new();
+        for direction in DIRECTIONS {
+            match Message::read(value, direction) {
+                Ok(message) => return (Some(direction), Ok(message)),
+                Err(err) => reasons.push(format!("as {}, {}", direction.name(), err)),
+            }
+        }
+        return (
+            None,
+            Err(format!(
+                "None of the protocol's messages: {}",
+                reasons.join("; ")
+            )),
+        );
+    };
+    let reason = match Message::read(value, expected) {
+        Ok(message) => return (Some(expected), Ok(message)),
+        Err(err) => err.to_string(),
+    };
+    let reason = match DIRECTIONS
+        .into_iter()
+        .find(|direction| Message::read(value, *direction).is_ok())
+    {
+        Some(direction) => format!("{}, it reads as {} though", reason, direction.name()),
+        None => reason,
+    };
+    (Some(expected), Err(reason))
+}
+
+/// A protocol move like "e7→e8=Q", off-board squares as their coordinates
+fn move_name(mv: &Move) -> String {
+    let promotion =
+        network_piece_letter(mv.promotion).map_or(String::new(), |letter| format!("={}", letter));
+    format!(
+        "{}→{}{}",
+        square_name(mv.start_x, mv.start_y),
+        square_name(mv.end_x, mv.end_y),
+        promotion
+    )
+}
+
+/// The piece on a square of the board, None off it
+#[inline]
+fn piece_at(board: &NetworkBoard, x: usize, y: usize) -> Option<Piece> {
+    board.get(y)?.get(x).copied()
+}
+
+#[inline]
+fn on_board(x: usize, y: usize) -> bool {
+    NetworkCoord { x, y }.to_board().is_some()
+}
+
+fn squares_check<'a>(moves: impl Iterator<Item = &'a Move>) -> Check {
+    let mut problems = Vec::new();
+    for mv in moves {
+        if !on_board(mv.start_x, mv.start_y) || !on_board(mv.end_x, mv.end_y) {
+            problems.push(format!("{} leaves the board", move_name(mv)));
+        } else if (mv.start_x, mv.start_y) == (mv.end_x, mv.end_y) {
+            problems.push(format!("{} doesn't go anywhere", move_name(mv)));
+        }
+    }
+    Check::of("squares on the board", problems)
+}
+
+/// Promotions to what a pawn can become on the last rank, by the pawns of `board` if the
+/// moves start there
+fn promotions_check<'a>(
+    moves: impl Iterator<Item = &'a Move>,
+    board: Option<&NetworkBoard>,
+) -> Check {
+    let mut problems = Vec::new();
+    for mv in moves {
+        let last_rank = mv.end_y == 0 || mv.end_y == 7;
+        let pawn = board
+            .and_then(|board| piece_at(board, mv.start_x, mv.start_y))
+            .map(|piece| matches!(network_to_internal_piece(&piece), Square::Pawn(_)));
+        match mv.promotion {
+            Piece::None if last_rank && pawn == Some(true) => problems.push(format!(
+                "{} reaches the last rank without a promotion",
+                move_name(mv)
+            )),
+            Piece::None => {}
+            Piece::WhiteKing | Piece::BlackKing | Piece::WhitePawn | Piece::BlackPawn => problems
+                .push(format!(
+                    "{} promotes to a {:?}",
+                    move_name(mv),
+                    mv.promotion
+                )),
+            _ if !last_rank => problems.push(format!(
+                "{} promotes away from the last rank",
+                move_name(mv)
+            )),
+            _ if pawn == Some(false) => problems.push(format!(
+                "{} promotes a piece that isn't a pawn",
+                move_name(mv)
+            )),
+            _ => {}
+        }
+    }
+    Check::of("promotions", problems)
+}
+
+fn kings_check(board: &NetworkBoard) -> Check {
+    let count = |king: Piece| {
+        board
+            .iter()
+            .flatten()
+            .filter(|piece| **piece == king)
+            .count()
+    };
+    let mut problems = Vec::new();
+    for (side, king) in [("White", Piece::WhiteKing), ("Black", Piece::BlackKing)] {
+        match count(king) {
+            1 => {}
+            kings => problems.push(format!("{} has {} kings", side, kings)),
+        }
+    }
+    Check::of("one king each", problems)
+}
+
+fn listed_once_check(moves: &[Move]) -> Check {
+    let names: Vec<String> = moves.iter().map(move_name).collect();
+    let mut problems = Vec::new();
+    for (at, name) in names.iter().enumerate() {
+        let times = names.iter().filter(|other| *other == name).count();
+        if times > 1 && !names[..at].contains(name) {
+            problems.push(format!("{} is listed {} times", name, times));
+        }
+    }
+    Check::of("moves listed once", problems)
+}
+
+/// The moves start on pieces of one side, not `moved` if that side just played
+fn one_side_check(moves: &[Move], board: &NetworkBoard, moved: Option<Color>) -> Check {
+    let mut problems = Vec::new();
+    let mut sides = Vec::new();
+    for mv in moves {
+        match piece_at(board, mv.start_x, mv.start_y)
+            .and_then(|piece| network_to_internal_piece(&piece).color())
+        {
+            None => problems.push(format!("{} starts on an empty square", move_name(mv))),
+            Some(side) if moved == Some(side) => {
+                problems.push(format!("{} moves the side that just played", move_name(mv)))
+            }
+            Some(side) if !sides.contains(&side) => sides.push(side),
+            Some(_) => {}
+        }
+    }
+    if sides.len() > 1 {
+        problems.push("The moves are for both sides".to_owned());
+    }
+    Check::of("moves for the side to move", problems)
+}
+
+/// The board and moves a message lists as legal, if it lists any
+fn listed(message: &Message) -> Option<(&NetworkBoard, &[Move])> {
+    match message {
+        Message::ServerToClient(
+            ServerToClient::State { board, moves, .. }
+            | ServerToClient::Error { board, moves, .. }
+            | ServerToClient::Draw { board, moves },
+        ) => Some((board, moves)),
+        Message::Handshake(Handshake::ServerToClient(handshake)) => {
+            Some((&handshake.board, &handshake.moves))
+        }
+        _ => None,
+    }
+}
+
+/// What can be checked of a message on its own
+pub fn structure(message: &Message) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if let Some((board, moves)) = listed(message) {
+        let moved = match message {
+            Message::ServerToClient(ServerToClient::State { move_made, .. }) => {
+                piece_at(board, move_made.end_x, move_made.end_y)
+                    .and_then(|piece| network_to_internal_piece(&piece).color())
+            }
+            _ => None,
+        };
+        checks.push(squares_check(moves.iter()));
+        checks.push(promotions_check(moves.iter(), Some(board)));
+        checks.push(listed_once_check(moves));
+        checks.push(one_side_check(moves, board, moved));
+    }
+    match message {
+        Message::ServerToClient(ServerToClient::State { move_made, .. })
+        | Message::ClientToServer(ClientToServer::Move(move_made)) => {
+            checks.push(squares_check(std::iter::once(move_made)));
+            checks.push(promotions_check(std::iter::once(move_made), None));
+        }
+        _ => {}
+    }
+    match message {
+        Message::ServerToClient(
+            ServerToClient::State { board, .. }
+            | ServerToClient::Error { board, .. }
+            | ServerToClient::Resigned { board, .. }
+            | ServerToClient::Draw { board, .. },
+        ) => checks.push(kings_check(board)),
+        Message::Handshake(Handshake::ServerToClient(handshake)) => {
+            checks.push(kings_check(&handshake.board))
+        }
+        _ => {}
+    }
+    if let Message::ServerToClient(ServerToClient::Resigned { joever, .. }) = message {
+        checks.push(match joever {
+            Joever::White | Joever::Black => Check::pass("winner"),
+            _ => Check::fail("winner", format!("A resignation that {:?} won", joever)),
+        });
+    }
+    checks
+}
+
+/// The board of `session` as the protocol has it, or what `board` has different
+fn board_check(name: &'static str, board: &NetworkBoard, session: &Session) -> Check {
+    if network_to_internal_board(board) == session.board_repr.squares {
+        return Check::pass(name);
+    }
+    let expected = internal_to_network_board(&session.board_repr.squares);
+    Check::fail(
+        name,
+        format!(
+            "Expected {}, differs by {}",
+            session.board.to_fen(),
+            board_diff(&expected, board).join(", ")
+        ),
+    )
+}
+
+/// The moves listed against the legal moves of `session`, leaving out those its peer didn't
+/// advertise
+fn moves_check(moves: &[Move], session: &Session) -> Check {
+    let legal = &session.board_repr.moves;
+    let mut problems: Vec<String> = moves
+        .iter()
+        .filter(|mv| network_to_internal_move(mv, legal).is_none())
+        .map(|mv| format!("{} isn't legal", move_name(mv)))
+        .collect();
+    let listed: Vec<String> = moves
+        .iter()
+        .filter_map(|mv| network_to_internal_move(mv, legal))
+        .map(|mv| mv.to_algebraic_notation())
+        .collect();
+    for mv in legal {
+        let notation = mv.to_algebraic_notation();
+        let Ok((from, to)) = parse_move(&notation) else {
+            continue;
+        };
+        let excluded =
+            session
+                .board_repr
+                .excluded
+                .excludes(&session.board_repr.squares, mv, from, to);
+        if !excluded && !listed.contains(&notation) {
+            problems.push(format!("{} is missing", notation));
+        }
+    }
+    Check::of("legal moves listed", problems)
+}
+
+/// What can be checked of a message against the game so far, which a reported move advances.
+/// `server_color` is whoever isn't to move when unknown.
+pub fn in_context(
+    message: &Message,
+    session: &mut Session,
+    server_color: Option<Color>,
+) -> Vec<Check> {
+    match message {
+        Message::ServerToClient(ServerToClient::State {
+            board,
+            moves,
+            joever,
+            move_made,
+        }) => {
+            let Some(mv) = network_to_internal_move(move_made, &session.board_repr.moves) else {
+                return vec![Check::fail(
+                    "legal in context",
+                    format!(
+                        "{} isn't legal in {}",
+                        move_name(move_made),
+                        session.board.to_fen()
+                    ),
+                )];
+            };
+            session.apply_move(mv);
+            let result = compute_joever(&session.game_state);
+            let result_check = match (joever, result) {
+                (Joever::Indeterminate, _)
+                | (Joever::Ongoing, Joever::Ongoing)
+                | (Joever::White, Joever::White)
+                | (Joever::Black, Joever::Black)
+                | (Joever::Draw, Joever::Draw) => Check::pass("result"),
+                _ => Check::fail(
+                    "result",
+                    format!("Reported as {:?}, the game is {:?}", joever, result),
+                ),
+            };
+            vec![
+                Check::pass("legal in context"),
+                board_check("board after the move", board, session),
+                moves_check(moves, session),
+                result_check,
+            ]
+        }
+        Message::ServerToClient(
+            ServerToClient::Error { board, moves, .. } | ServerToClient::Draw { board, moves },
+        ) => vec![
+            board_check("board unchanged", board, session),
+            moves_check(moves, session),
+        ],
+        Message::ServerToClient(ServerToClient::Resigned { board, .. }) => {
+            vec![board_check("board unchanged", board, session)]
+        }
+        Message::ClientToServer(ClientToServer::Move(mv)) => {
+            let server_color =
+                server_color.unwrap_or_else(|| opposite_color(session.board.get_curr_player()));
+            vec![match session.validate_client_move(mv, server_color) {
+                Ok(_) => Check::pass("legal in context"),
+                Err((_, why)) => Check::fail("legal in context", why),
+            }]
+        }
+        Message::Handshake(Handshake::ServerToClient(handshake)) => vec![
+            board_check("board", &handshake.board, session),
+            moves_check(&handshake.moves, session),
+        ],
+        Message::ClientToServer(_) | Message::Handshake(Handshake::ClientToServer(_)) => Vec::new(),
+    }
+}
+
+/// The game a sequence of messages plays, once both handshakes are in
+struct Exchange {
+    session: Session,
+    server_color: Color,
+    features: Vec<Features>,
+    // The client's move the server hasn't answered yet
+    pending: Option<String>,
+    // Whoever offered a draw that still stands, true for the server
+    draw_offer: Option<bool>,
+    over: bool,
+}
+
+impl Exchange {
+    fn follow(&mut self, message: &Message) -> Vec<Check> {
+        if self.over && !matches!(message, Message::Handshake(_)) {
+            return vec![Check::fail("order", "The game was already over".to_owned())];
+        }
+        let mut checks = vec![Check::pass("order")];
+        match message {
+            Message::Handshake(handshake) => {
+                // Each side only takes the other's handshake
+                let is_server = matches!(handshake, Handshake::ClientToServer(_));
+                let judged = self.session.judge_rehandshake(
+                    handshake,
+                    is_server,
+                    self.server_color,
+                    &self.features,
+                    false,
+                );
+                checks.push(match judged {
+                    Rehandshake::Duplicate => Check::pass("repeated handshake"),
+                    _ => Check::fail(
+                        "repeated handshake",
+                        "It disagrees with the game so far".to_owned(),
+                    ),
+                });
+                return checks;
+            }
+            Message::ClientToServer(ClientToServer::Move(mv)) => {
+                if let Some(pending) = &self.pending {
+                    checks[0] = Check::fail(
+                        "order",
+                        format!(
+                            "The client moved again before the server answered {}",
+                            pending
+                        ),
+                    );
+                }
+                self.pending = Some(move_name(mv));
+            }
+            Message::ClientToServer(ClientToServer::Resign)
+            | Message::ServerToClient(ServerToClient::Resigned { .. }) => self.over = true,
+            Message::ClientToServer(ClientToServer::Draw) => self.offer_draw(false),
+            Message::ServerToClient(ServerToClient::State { move_made, .. }) => {
+                checks[0] = match self.pending.take() {
+                    Some(pending) if pending != move_name(move_made) => Check::fail(
+                        "order",
+                        format!(
+                            "The server reported {} for the client's {}",
+                            move_name(move_made),
+                            pending
+                        ),
+                    ),
+                    None if self.session.board.get_curr_player() != self.server_color => {
+                        Check::fail("order", "The server moved on the client's turn".to_owned())
+                    }
+                    _ => Check::pass("order"),
+                };
+                // Answering a draw offer with a move declines it
+                self.draw_offer = None;
+            }
+            Message::ServerToClient(ServerToClient::Error { message, .. }) => {
+                self.pending = None;
+                self.over = message == OUT_OF_TIME || message == ILLEGAL_MOVES_FORFEIT;
+            }
+            Message::ServerToClient(ServerToClient::Draw { .. }) => self.offer_draw(true),
+        }
+        checks.extend(in_context(
+            message,
+            &mut self.session,
+            Some(self.server_color),
+        ));
+        if let Message::ServerToClient(ServerToClient::State { joever, .. }) = message {
+            self.over |= !matches!(joever, Joever::Ongoing);
+        }
+        checks
+    }
+
+    /// A draw offer by the server or the client, which ends the game if the other side had
+    /// offered one
+    #[inline]
+    fn offer_draw(&mut self, by_server: bool) {
+        match self.draw_offer {
+            Some(offered_by_server) if offered_by_server != by_server => self.over = true,
+            _ => self.draw_offer = Some(by_server),
+        }
+    }
+}
+
+/// Check messages as one connection sends them both ways: the client's handshake, then the
+/// server's, then the game, with every move in turn and answered before the next one. The
+/// position comes from the server's handshake and the moves left out from its features.
+pub fn validate_sequence(values: &[Value]) -> Vec<Report> {
+    let mut server_color = None;
+    let mut exchange: Option<Exchange> = None;
+    let mut reports = Vec::new();
+    for (at, value) in values.iter().enumerate() {
+        let (direction, message) = read(value, None);
+        let mut report = Report {
+            index: at + 1,
+            direction,
+            checks: Vec::new(),
+        };
+        let message = match message {
+            Ok(message) => message,
+            Err(reason) => {
+                report.checks.push(Check::fail("parses", reason));
+                reports.push(report);
+                continue;
+            }
+        };
+        report.checks.push(Check::pass("parses"));
+        report.checks.extend(structure(&message));
+        match (&mut exchange, &message) {
+            (Some(exchange), message) => report.checks.extend(exchange.follow(message)),
+            (None, Message::Handshake(Handshake::ClientToServer(handshake)))
+                if server_color.is_none() =>
+            {
+                server_color = Some(match handshake.server_color {
+                    chess_network_protocol::Color::White => Color::White,
+                    chess_network_protocol::Color::Black => Color::Black,
+                });
+                report.checks.push(Check::pass("order"));
+            }
+            (None, Message::Handshake(Handshake::ServerToClient(handshake))) => {
+                let Some(server_color) = server_color else {
+                    report.checks.push(Check::fail(
+                        "order",
+                        "The client's handshake comes first".to_owned(),
+                    ));
+                    reports.push(report);
+                    continue;
+                };
+                report.checks.push(Check::pass("order"));
+                let fen = network_fen(&handshake.board, &handshake.moves, Color::White);
+                match Session::new(Some(fen)) {
+                    Ok(mut session) => {
+                        let features = negotiated_features(handshake);
+                        session.board_repr.excluded = ExcludedMoves::for_features(&features);
+                        report.checks.push(moves_check(&handshake.moves, &session));
+                        exchange = Some(Exchange {
+                            session,
+                            server_color,
+                            features,
+                            pending: None,
+                            draw_offer: None,
+                            over: false,
+                        });
+                    }
+                    Err(err) => report.checks.push(Check::fail(
+                        "board",
+                        format!("No game can be played from it: {}", err),
+                    )),
+                }
+            }
+            (None, _) => report.checks.push(Check::fail(
+                "order",
+                match server_color {
+                    None => "The client's handshake comes first",
+                    Some(_) => "The server's handshake comes after the client's",
+                }
+                .to_owned(),
+            )),
+        }
+        reports.push(report);
+    }
+    reports
+}
+
+/// Check every message on its own, against `context_fen` if there is one
+pub fn validate_messages(
+    values: &[Value],
+    expected: Option<Direction>,
+    context_fen: Option<&str>,
+) -> Result<Vec<Report>, String> {
+    let mut reports = Vec::new();
+    for (at, value) in values.iter().enumerate() {
+        let (direction, message) = read(value, expected);
+        let mut report = Report {
+            index: at + 1,
+            direction,
+            checks: Vec::new(),
+        };
+        match message {
+            Ok(message) => {
+                report.checks.push(Check::pass("parses"));
+                report.checks.extend(structure(&message));
+                if let Some(fen) = context_fen {
+                    let mut session = Session::new(Some(fen.to_owned()))
+                        .map_err(|err| format!("Invalid --context-fen: {}", err))?;
+                    report
+                        .checks
+                        .extend(in_context(&message, &mut session, None));
+                }
+            }
+            Err(reason) => report.checks.push(Check::fail("parses", reason)),
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// The JSON values of `input` one after another, however they are spaced
+pub fn read_values(input: &[u8]) -> Result<Vec<Value>, String> {
+    serde_json::Deserializer::from_slice(input)
+        .into_iter::<Value>()
+        .collect::<Result<_, _>>()
+        .map_err(|err| format!("Not JSON: {}", err))
+}
+
+/// Check the messages `validation` asks for and print the reports, the exit code is 0 when
+/// every one is valid, 1 when any isn't and 2 when they couldn't be checked at all
+pub fn run(validation: &Validation) -> i32 {
+    let input = match &validation.input {
+        Some(path) => {
+            std::fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))
+        }
+        None => {
+            let mut input = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut input)
+                .map(|_| input)
+                .map_err(|err| format!("Could not read the standard input: {}", err))
+        }
+    };
+    let reports = input
+        .and_then(|input| read_values(&input))
+        .and_then(|values| match values.is_empty() {
+            true => Err("No message to validate.".to_owned()),
+            false if validation.sequence => Ok(validate_sequence(&values)),
+            false => validate_messages(
+                &values,
+                validation.direction,
+                validation.context_fen.as_deref(),
+            ),
+        });
+    let reports = match reports {
+        Ok(reports) => reports,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 2;
+        }
+    };
+
+    match validation.json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&report_json(&reports)).unwrap_or_default()
+        ),
+        false => {
+            for report in &reports {
+                println!("{}", report.describe());
+            }
+        }
+    }
+    match reports.iter().all(Report::passed) {
+        true => 0,
+        false => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn values(text: &str) -> Vec<Value> {
+        read_values(text.as_bytes()).unwrap()
+    }
+
+    /// The sample messages of one folder by file name, a .jsonl sample is a sequence
+    fn samples(folder: &str) -> Vec<(String, Vec<Report>)> {
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/samples")
+            .join(folder);
+        let mut samples = Vec::new();
+        for entry in std::fs::read_dir(folder).unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let reports = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("jsonl") => validate_sequence(&values(&text)),
+                _ => validate_messages(&values(&text), None, Some(START)).unwrap(),
+            };
+            samples.push((path.display().to_string(), reports));
+        }
+        samples
+    }
+
+    #[test]
+    fn good_samples_pass_and_bad_ones_fail() {
+        for (name, reports) in samples("good") {
+            for report in &reports {
+                assert!(report.passed(), "{}: {}", name, report.describe());
+            }
+        }
+        let bad = samples("bad");
+        assert!(!bad.is_empty());
+        for (name, reports) in bad {
+            assert!(!reports.iter().all(Report::passed), "{} passed", name);
+        }
+    }
+
+    #[test]
+    fn a_message_of_the_wrong_direction_says_what_it_is() {
+        let move_message = r#"{"Move": {"start_x": 4, "start_y": 1, "end_x": 4, "end_y": 3, "promotion": "None"}}"#;
+        let reports =
+            validate_messages(&values(move_message), Some(Direction::ServerToClient), None)
+                .unwrap();
+        assert!(!reports[0].passed());
+        assert!(reports[0].checks[0]
+            .detail
+            .ends_with("it reads as client-to-server though"));
+
+        let auto = validate_messages(&values(move_message), None, Some(START)).unwrap();
+        assert!(auto[0].direction == Some(Direction::ClientToServer));
+        assert!(auto[0].passed(), "{}", auto[0].describe());
+    }
+
+    #[test]
+    fn a_move_out_of_turn_fails_in_context() {
+        let black_move = r#"{"Move": {"start_x": 4, "start_y": 6, "end_x": 4, "end_y": 4, "promotion": "None"}}"#;
+        let reports = validate_messages(&values(black_move), None, None).unwrap();
+        assert!(reports[0].passed());
+        let reports = validate_messages(&values(black_move), None, Some(START)).unwrap();
+        assert!(reports[0]
+            .checks
+            .iter()
+            .any(|check| check.name == "legal in context" && !check.passed));
+    }
+
+    #[test]
+    fn promotions_are_checked_on_their_own() {
+        let mv = |end_y, promotion| Move {
+            start_x: 0,
+            start_y: 6,
+            end_x: 0,
+            end_y,
+            promotion,
+        };
+        assert!(promotions_check([mv(7, Piece::WhiteQueen)].iter(), None).passed);
+        assert!(!promotions_check([mv(7, Piece::WhiteKing)].iter(), None).passed);
+        assert!(!promotions_check([mv(5, Piece::WhiteRook)].iter(), None).passed);
+        let mut board = [[Piece::None; 8]; 8];
+        board[6][0] = Piece::WhitePawn;
+        assert!(!promotions_check([mv(7, Piece::None)].iter(), Some(&board)).passed);
+        board[6][0] = Piece::WhiteRook;
+        assert!(!promotions_check([mv(7, Piece::WhiteQueen)].iter(), Some(&board)).passed);
+    }
+
+    #[test]
+    fn directions_read_back() {
+        for direction in DIRECTIONS {
+            assert!(Direction::parse(direction.name()) == Some(direction));
+        }
+        assert!(Direction::parse("auto").is_none());
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{metrics, ordering};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the window may go without updating while focused before a hang is reported
+pub const DEFAULT_HANG_AFTER: Duration = Duration::from_secs(5);
+/// After this long the next startup is told the last run froze
+pub const STUCK_AFTER: Duration = Duration::from_secs(30);
+/// Written in the user data directory
+pub const REPORT_FILE: &str = "hang-report.txt";
+// Left for the next startup once the window was stuck for good
+const MARKER_FILE: &str = "froze";
+// How often the monitor looks for the heartbeat
+const POLL: Duration = Duration::from_millis(250);
+// The end of the connection timeline that goes into a report
+const TIMELINE_TAIL: usize = 20;
+
+/// What stopped heartbeats call for
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Alarm {
+    // No heartbeat for this long, past the first threshold
+    Hang(Duration),
+    // Past the second threshold too, it may never come back
+    Stuck(Duration),
+}
+
+/// Whether the heartbeats of the main loop stopped, on a clock given to it so it can be tested.
+/// Each alarm is raised once for every hang.
+pub struct Heartbeats {
+    hang_after: Duration,
+    stuck_after: Duration,
+    last: Duration,
+    hang_raised: bool,
+    stuck_raised: bool,
+}
+
+impl Heartbeats {
+    pub fn new(hang_after: Duration, stuck_after: Duration, now: Duration) -> Self {
+        Self {
+            hang_after,
+            stuck_after: stuck_after.max(hang_after),
+            last: now,
+            hang_raised: false,
+            stuck_raised: false,
+        }
+    }
+
+    #[inline]
+    pub fn beat(&mut self, now: Duration) {
+        self.last = now;
+        self.hang_raised = false;
+        self.stuck_raised = false;
+    }
+
+    /// The alarm to raise at `now`. An unfocused window may be updated less often, so time
+    /// spent without focus is never counted.
+    pub fn check(&mut self, now: Duration, focused: bool) -> Option<Alarm> {
+        if !focused {
+            self.last = now;
+            return None;
+        }
+        let silent = now.saturating_sub(self.last);
+        if silent >= self.stuck_after && !self.stuck_raised {
+            self.stuck_raised = true;
+            self.hang_raised = true;
+            Some(Alarm::Stuck(silent))
+        } else if silent >= self.hang_after && !self.hang_raised {
+            self.hang_raised = true;
+            Some(Alarm::Hang(silent))
+        } else {
+            None
+        }
+    }
+}
+
+/// Watches the main loop from a thread of its own. The loop beats at the top of every update
+/// and names the stage it is in with checkpoints, so a report of a hang says where it stuck.
+/// Nothing is ever killed, the hang may pass.
+pub struct Watchdog {
+    started: Instant,
+    hang_after: Duration,
+    // Since `started`
+    last_beat: AtomicU64,
+    focused: AtomicBool,
+    // The stages the main loop is in, innermost last
+    stages: Mutex<Vec<&'static str>>,
+    fen: Mutex<Option<String>>,
+    dir: PathBuf,
+}
+
+static WATCHDOG: OnceLock<Watchdog> = OnceLock::new();
+
+/// The watchdog once `enable` was called, the checkpoints do nothing before that
+#[inline]
+pub fn global() -> Option<&'static Watchdog> {
+    WATCHDOG.get()
+}
+
+/// Start watching for hangs of `hang_after`, reported in `dir`
+pub fn enable(hang_after: Duration, dir: PathBuf) -> &'static Watchdog {
+    let mut started = false;
+    let watchdog = WATCHDOG.get_or_init(|| {
+        started = true;
+        Watchdog::new(hang_after, dir)
+    });
+    if started {
+        watchdog.watch();
+    }
+    watchdog
+}
+
+/// Name the stage the main loop is in until the guard is dropped
+#[inline]
+pub fn checkpoint(stage: &'static str) -> Checkpoint {
+    match global() {
+        Some(watchdog) => watchdog.checkpoint(stage),
+        None => Checkpoint { watchdog: None },
+    }
+}
+
+/// Leaves its stage when dropped
+#[must_use = "the stage is left as soon as the guard is dropped"]
+pub struct Checkpoint {
+    watchdog: Option<&'static Watchdog>,
+}
+
+impl Drop for Checkpoint {
+    fn drop(&mut self) {
+        if let Some(watchdog) = self.watchdog {
+            watchdog.lock_stages().pop();
+        }
+    }
+}
+
+/// The hang report the last run left if it was stuck for good, the marker is taken away so it
+/// is only told once
+pub fn froze(dir: &Path) -> Option<PathBuf> {
+    fs::remove_file(dir.join(MARKER_FILE)).ok()?;
+    Some(dir.join(REPORT_FILE))
+}
+
+impl Watchdog {
+    pub fn new(hang_after: Duration, dir: PathBuf) -> Self {
+        Self {
+            started: Instant::now(),
+            hang_after,
+            last_beat: AtomicU64::new(0),
+            focused: AtomicBool::new(true),
+            stages: Mutex::new(Vec::new()),
+            fen: Mutex::new(None),
+            dir,
+        }
+    }
+
+    #[inline]
+    fn now(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Called at the top of every update
+    #[inline]
+    pub fn beat(&self) {
+        self.last_beat
+            .store(self.now().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn focus(&self, focused: bool) {
+        self.focused.store(focused, Ordering::Relaxed);
+    }
+
+    /// The position on the board, for the report
+    pub fn position(&self, fen: String) {
+        *self.fen.lock().unwrap_or_else(|err| err.into_inner()) = Some(fen);
+    }
+
+    // A panic mid-stage leaves the stack as it was, which is still the best guess
+    fn lock_stages(&self) -> std::sync::MutexGuard<'_, Vec<&'static str>> {
+        self.stages.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Name the stage the main loop is in until the guard is dropped
+    pub fn checkpoint(&'static self, stage: &'static str) -> Checkpoint {
+        self.lock_stages().push(stage);
+        Checkpoint {
+            watchdog: Some(self),
+        }
+    }
+
+    /// What is known about the hang after `silent` without a heartbeat
+    pub fn report(&self, silent: Duration) -> String {
+        let mut report = format!("No update for {:.1}s\n\n", silent.as_secs_f64());
+        let stages = self.lock_stages().clone();
+        let _ = writeln!(
+            report,
+            "Stuck in: {}",
+            match stages.is_empty() {
+                true => "no named stage".to_owned(),
+                false => stages.join(" > "),
+            }
+        );
+        // Stable Rust can only take the backtrace of the thread it runs on
+        let _ = writeln!(
+            report,
+            "No backtrace of the main thread can be taken from another thread, the stages above \
+             are where it was"
+        );
+        let fen = self
+            .fen
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone();
+        let _ = writeln!(
+            report,
+            "Position: {}",
+            fen.as_deref().unwrap_or("no game yet")
+        );
+        if let Some(audit) = ordering::global() {
+            let timeline = audit.render();
+            let lines: Vec<&str> = timeline.lines().collect();
+            let _ = writeln!(report, "\nThe end of the connection timeline:");
+            for line in &lines[lines.len().saturating_sub(TIMELINE_TAIL)..] {
+                let _ = writeln!(report, "{}", line);
+            }
+        }
+        if let Some(metrics) = metrics::global() {
+            let _ = write!(report, "\nMetrics:\n{}", metrics.render());
+        }
+        report
+    }
+
+    fn raise(&self, alarm: Alarm) -> Result<PathBuf, Error> {
+        let path = self.dir.join(REPORT_FILE);
+        match alarm {
+            Alarm::Hang(silent) => fs::write(&path, self.report(silent))?,
+            Alarm::Stuck(_) => fs::write(self.dir.join(MARKER_FILE), "")?,
+        }
+        Ok(path)
+    }
+
+    /// Start the monitor thread
+    pub fn watch(&'static self) {
+        let mut heartbeats = Heartbeats::new(self.hang_after, STUCK_AFTER, self.now());
+        let mut last_beat = 0;
+        let mut stuck = false;
+        thread::spawn(move || loop {
+            thread::sleep(POLL);
+            let beat = self.last_beat.load(Ordering::Relaxed);
+            if beat != last_beat {
+                last_beat = beat;
+                heartbeats.beat(Duration::from_nanos(beat));
+                // It came back after all, there is nothing to recover from
+                if std::mem::take(&mut stuck) {
+                    let _ = fs::remove_file(self.dir.join(MARKER_FILE));
+                }
+            }
+            let Some(alarm) = heartbeats.check(self.now(), self.focused.load(Ordering::Relaxed))
+            else {
+                continue;
+            };
+            match (self.raise(alarm), alarm) {
+                (Ok(path), Alarm::Hang(silent)) => eprintln!(
+                    "=== The window hasn't updated for {:.1}s, hang report written to {} ===",
+                    silent.as_secs_f64(),
+                    path.display()
+                ),
+                (Ok(_), Alarm::Stuck(silent)) => {
+                    stuck = true;
+                    eprintln!(
+                        "=== Still stuck after {:.0}s, the next start will offer to resume ===",
+                        silent.as_secs_f64()
+                    );
+                }
+                (Err(err), _) => {
+                    eprintln!("=== The window hung, can't write a report: {} ===", err)
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn alarms_are_raised_once_for_each_hang() {
+        let mut heartbeats = Heartbeats::new(5 * SECOND, 30 * SECOND, 100 * SECOND);
+        assert_eq!(heartbeats.check(104 * SECOND, true), None);
+        assert_eq!(
+            heartbeats.check(105 * SECOND, true),
+            Some(Alarm::Hang(5 * SECOND))
+        );
+        assert_eq!(heartbeats.check(110 * SECOND, true), None);
+        assert_eq!(
+            heartbeats.check(131 * SECOND, true),
+            Some(Alarm::Stuck(31 * SECOND))
+        );
+        assert_eq!(heartbeats.check(200 * SECOND, true), None);
+        // It came back, the next hang is a hang of its own
+        heartbeats.beat(201 * SECOND);
+        assert_eq!(
+            heartbeats.check(207 * SECOND, true),
+            Some(Alarm::Hang(6 * SECOND))
+        );
+    }
+
+    #[test]
+    fn time_without_focus_isnt_counted() {
+        let mut heartbeats = Heartbeats::new(5 * SECOND, 30 * SECOND, Duration::ZERO);
+        assert_eq!(heartbeats.check(60 * SECOND, false), None);
+        assert_eq!(heartbeats.check(63 * SECOND, true), None);
+        assert_eq!(
+            heartbeats.check(65 * SECOND, true),
+            Some(Alarm::Hang(5 * SECOND))
+        );
+    }
+
+    /// A stage that blocks is named in the report, and the marker is only found once
+    #[test]
+    fn a_blocked_stage_is_reported() {
+        let scratch = Scratch::new("watchdog");
+        let dir = scratch.path().to_owned();
+        let watchdog: &'static Watchdog = Box::leak(Box::new(Watchdog::new(
+            Duration::from_millis(300),
+            dir.clone(),
+        )));
+        watchdog.position("8/8/8/8/8/8/8/K6k w - - 0 1".to_owned());
+        watchdog.watch();
+        watchdog.beat();
+        {
+            let _frame = watchdog.checkpoint("building frame");
+            let _stage = watchdog.checkpoint("applying move");
+            thread::sleep(Duration::from_millis(1000));
+        }
+        let report = fs::read_to_string(dir.join(REPORT_FILE)).unwrap();
+        assert!(report.contains("Stuck in: building frame > applying move"));
+        assert!(report.contains("8/8/8/8/8/8/8/K6k w - - 0 1"));
+        assert!(watchdog.lock_stages().is_empty());
+        assert_eq!(froze(&dir), None, "not stuck long enough");
+        fs::write(dir.join(MARKER_FILE), "").unwrap();
+        assert_eq!(froze(&dir), Some(dir.join(REPORT_FILE)));
+        assert_eq!(froze(&dir), None);
+    }
+}
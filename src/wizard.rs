@@ -0,0 +1,421 @@
+use crate::menu::grown;
+use crate::{unit_mesh, Graphics, Restore};
+use chess_gui::config::{NetworkConfig, DEFAULT_CONNECT_ATTEMPTS};
+use chess_gui::connectivity::{self, Responder, Verdict};
+use ggez::graphics::{self, Canvas, Drawable, Mesh, Rect, Text};
+use ggez::input::keyboard::KeyCode;
+use ggez::Context;
+use mint::Point2;
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+const BUTTON_COLOR: graphics::Color = graphics::Color::new(0.9, 0.7, 0.7, 1.0);
+const CHOSEN_BUTTON_COLOR: graphics::Color = graphics::Color::new(0.0, 0.5, 0.0, 0.75);
+const FIELD_COLOR: graphics::Color = graphics::Color::new(1.0, 0.9, 0.9, 1.0);
+const TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+const ERROR_TEXT_COLOR: graphics::Color = graphics::Color::new(0.7, 0.0, 0.0, 1.0);
+const READY_TEXT_COLOR: graphics::Color = graphics::Color::new(0.0, 0.45, 0.0, 1.0);
+
+/// Which side of the connection test this computer is, chosen on the start screen
+pub(crate) enum Role {
+    // Listening on this port, on every address
+    Host(u16),
+    // Joining a server that plays this color
+    Guest(chess_network_protocol::Color),
+}
+
+/// Where everything on the connection test is drawn, relative to the window size
+struct WizardLayout {
+    title: Rect,
+    // What the host tells the guest, or the guest types
+    lines: [Rect; 2],
+    field: Rect,
+    verdict: Rect,
+    test: Rect,
+    play: Rect,
+    back: Rect,
+}
+
+impl WizardLayout {
+    fn new(width: f32, height: f32) -> Self {
+        let rect = |x: f32, y: f32, w: f32, h: f32| {
+            Rect::new(x * width, y * height, w * width, h * height)
+        };
+        Self {
+            title: rect(0.0, 0.1, 1.0, 0.1),
+            lines: [rect(0.1, 0.28, 0.8, 0.08), rect(0.1, 0.38, 0.8, 0.06)],
+            field: rect(0.25, 0.38, 0.5, 0.08),
+            verdict: rect(0.05, 0.55, 0.9, 0.05),
+            test: rect(0.06, 0.78, 0.27, 0.1),
+            play: rect(0.37, 0.78, 0.27, 0.1),
+            back: rect(0.68, 0.78, 0.26, 0.1),
+        }
+    }
+}
+
+/// What was done on the connection test
+pub(crate) enum WizardAction {
+    // Back to the start screen, the port is let go
+    Back,
+    // Start the game, the host's on the port it tested
+    Play(NetworkConfig),
+}
+
+enum Side {
+    Host {
+        // None when the port couldn't be listened on
+        responder: Option<Responder>,
+        // The guest who tested last
+        reached: Option<SocketAddr>,
+    },
+    Guest {
+        server_color: chess_network_protocol::Color,
+        typed: String,
+        // The address being tested and the test's outcome once it is done
+        testing: Option<(String, Receiver<Verdict>)>,
+        tested: Option<(String, Verdict)>,
+    },
+}
+
+/// Tests that the two computers reach each other before anyone waits for a game that won't
+/// connect. The host answers tests on the game's port and shows a pairing code for it, the
+/// guest types the code and times a few pings.
+pub(crate) struct Wizard {
+    side: Side,
+    // Why something didn't work, shown until the next try
+    message: Option<String>,
+    // Unit square tinted and stretched into every button
+    button_mesh: Mesh,
+}
+
+impl Restore for Wizard {
+    #[inline]
+    fn graphics(&mut self) -> Graphics<'_> {
+        Graphics::UnitMesh(&mut self.button_mesh)
+    }
+}
+
+impl Wizard {
+    pub(crate) fn new(ctx: &Context, role: Role) -> Self {
+        let mut message = None;
+        let side = match role {
+            Role::Host(port) => Side::Host {
+                responder: Responder::start(&port.to_string())
+                    .map_err(|err| {
+                        message = Some(format!("Can't listen on port {}: {}", port, err))
+                    })
+                    .ok(),
+                reached: None,
+            },
+            Role::Guest(server_color) => Side::Guest {
+                server_color,
+                typed: String::new(),
+                testing: None,
+                tested: None,
+            },
+        };
+        Self {
+            side,
+            message,
+            button_mesh: unit_mesh(ctx),
+        }
+    }
+
+    /// Look for tests that finished, on either side
+    pub(crate) fn update(&mut self) {
+        match &mut self.side {
+            Side::Host {
+                responder: Some(responder),
+                reached,
+            } => {
+                while let Some(peer) = responder.tested() {
+                    *reached = Some(peer);
+                }
+            }
+            Side::Host { .. } => {}
+            Side::Guest {
+                testing, tested, ..
+            } => {
+                let Some((address, receiver)) = testing else {
+                    return;
+                };
+                if let Ok(verdict) = receiver.try_recv() {
+                    *tested = Some((address.clone(), verdict));
+                    *testing = None;
+                }
+            }
+        }
+    }
+
+    /// The guest's typed code or address, tested on a thread of its own
+    fn test(&mut self) {
+        let Side::Guest {
+            typed,
+            testing,
+            tested,
+            ..
+        } = &mut self.side
+        else {
+            return;
+        };
+        if testing.is_some() {
+            return;
+        }
+        *tested = None;
+        match connectivity::host_address(typed, connectivity::lan_address()) {
+            Ok(address) => {
+                self.message = None;
+                let (sender, receiver) = channel();
+                let thread_address = address.clone();
+                thread::spawn(move || {
+                    let _ = sender.send(connectivity::test_reach(&thread_address));
+                });
+                *testing = Some((address, receiver));
+            }
+            Err(message) => self.message = Some(message),
+        }
+    }
+
+    /// The game to start, None until there is one to start
+    fn play(&mut self) -> Option<WizardAction> {
+        match &mut self.side {
+            Side::Host { responder, .. } => {
+                let responder = responder.take()?;
+                let port = responder.listening.port();
+                match responder.into_hosting() {
+                    Ok(hosting) => Some(WizardAction::Play(NetworkConfig {
+                        is_server: true,
+                        address: port.to_string(),
+                        strict_port: true,
+                        connect_attempts: DEFAULT_CONNECT_ATTEMPTS,
+                        server_color: None,
+                        spectate: false,
+                        hosting: Some(hosting),
+                    })),
+                    Err(err) => {
+                        self.message = Some(format!("Can't keep port {}: {}", port, err));
+                        None
+                    }
+                }
+            }
+            Side::Guest {
+                server_color,
+                tested: Some((address, Verdict::Reachable(_))),
+                ..
+            } => Some(WizardAction::Play(NetworkConfig {
+                is_server: false,
+                address: address.clone(),
+                strict_port: false,
+                connect_attempts: DEFAULT_CONNECT_ATTEMPTS,
+                server_color: Some(*server_color),
+                spectate: false,
+                hosting: None,
+            })),
+            Side::Guest { .. } => None,
+        }
+    }
+
+    /// Whether Play would start the game
+    fn ready(&self) -> bool {
+        match &self.side {
+            Side::Host { responder, .. } => responder.is_some(),
+            Side::Guest { tested, .. } => matches!(tested, Some((_, Verdict::Reachable(_)))),
+        }
+    }
+
+    /// What the host tells the guest to type: the pairing code, and the full address for a
+    /// guest on another network
+    fn host_lines(responder: &Responder) -> [String; 2] {
+        let port = responder.listening.port();
+        let Some(ip) = connectivity::lan_address() else {
+            return [
+                format!("Port {}", port),
+                "This computer isn't on a network, the guest types its address".to_owned(),
+            ];
+        };
+        let address = std::net::SocketAddrV4::new(ip, port);
+        match connectivity::pairing_code(address) {
+            Some(code) => [
+                format!("Pairing code {}", connectivity::grouped(&code)),
+                format!("Or the full address {}", address),
+            ],
+            None => [
+                format!("Address {}", address),
+                "Not on a private network, so there is no pairing code".to_owned(),
+            ],
+        }
+    }
+
+    fn draw_button(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        rect: Rect,
+        label: &str,
+        color: graphics::Color,
+    ) {
+        canvas.draw(
+            &self.button_mesh,
+            graphics::DrawParam::default().dest_rect(rect).color(color),
+        );
+        let mut text = Text::new(label);
+        text.set_scale(rect.h / 2.0);
+        let text_width = text.dimensions(ctx).map_or(0.0, |dims| dims.w);
+        canvas.draw(
+            &text,
+            graphics::DrawParam::default()
+                .dest(Point2 {
+                    x: rect.x + (rect.w - text_width) / 2.0,
+                    y: rect.y + rect.h / 4.0,
+                })
+                .color(TEXT_COLOR),
+        );
+    }
+
+    pub(crate) fn draw(&self, ctx: &Context, canvas: &mut Canvas) {
+        let (width, height) = {
+            let cords = canvas.screen_coordinates().unwrap();
+            (cords.w, cords.h)
+        };
+        let layout = WizardLayout::new(width, height);
+        let white = graphics::Color::WHITE;
+
+        let (title, status) = match &self.side {
+            Side::Host { responder, reached } => {
+                if let Some(responder) = responder {
+                    for (rect, line) in layout.lines.iter().zip(Self::host_lines(responder)) {
+                        self.draw_button(ctx, canvas, *rect, &line, white);
+                    }
+                }
+                let status = match reached {
+                    Some(peer) => Some((
+                        format!("Reached by {}, ready to play", peer.ip()),
+                        READY_TEXT_COLOR,
+                    )),
+                    None if responder.is_some() => Some((
+                        "Waiting for the guest to test the connection".to_owned(),
+                        TEXT_COLOR,
+                    )),
+                    None => None,
+                };
+                ("Test the connection: host", status)
+            }
+            Side::Guest {
+                typed,
+                testing,
+                tested,
+                ..
+            } => {
+                self.draw_button(
+                    ctx,
+                    canvas,
+                    layout.lines[0],
+                    "Type the host's pairing code or address",
+                    white,
+                );
+                self.draw_button(ctx, canvas, layout.field, typed, FIELD_COLOR);
+                let status = match (testing, tested) {
+                    (Some((address, _)), _) => {
+                        Some((format!("Testing {}...", address), TEXT_COLOR))
+                    }
+                    (None, Some((_, verdict @ Verdict::Reachable(_)))) => {
+                        Some((verdict.describe(), READY_TEXT_COLOR))
+                    }
+                    (None, Some((_, verdict))) => Some((verdict.describe(), ERROR_TEXT_COLOR)),
+                    (None, None) => None,
+                };
+                ("Test the connection: guest", status)
+            }
+        };
+        self.draw_button(ctx, canvas, layout.title, title, white);
+
+        if let Some((status, color)) = self
+            .message
+            .clone()
+            .map(|message| (message, ERROR_TEXT_COLOR))
+            .or(status)
+        {
+            let mut text = Text::new(status);
+            text.set_scale(layout.verdict.h * 0.8);
+            let text_width = text.dimensions(ctx).map_or(0.0, |dims| dims.w);
+            canvas.draw(
+                &text,
+                graphics::DrawParam::default()
+                    .dest(Point2 {
+                        x: layout.verdict.x + (layout.verdict.w - text_width).max(0.0) / 2.0,
+                        y: layout.verdict.y,
+                    })
+                    .color(color),
+            );
+        }
+
+        if let Side::Guest { .. } = self.side {
+            self.draw_button(ctx, canvas, layout.test, "Test", BUTTON_COLOR);
+        }
+        let (play, play_color) = match (&self.side, self.ready()) {
+            (Side::Host { .. }, true) => ("Play", CHOSEN_BUTTON_COLOR),
+            (Side::Host { .. }, false) => ("Play", BUTTON_COLOR),
+            (Side::Guest { .. }, true) => ("Join", CHOSEN_BUTTON_COLOR),
+            (Side::Guest { .. }, false) => ("Join", BUTTON_COLOR),
+        };
+        self.draw_button(ctx, canvas, layout.play, play, play_color);
+        self.draw_button(ctx, canvas, layout.back, "Back", BUTTON_COLOR);
+    }
+
+    /// Handle a click, what to do next once Play, Join or Back is pressed. The buttons take
+    /// clicks up to `slop` pixels past their edges.
+    pub(crate) fn click(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        slop: f32,
+    ) -> Option<WizardAction> {
+        let layout = WizardLayout::new(width, height);
+        let point = Point2 { x, y };
+        let hit = |rect: &Rect| grown(*rect, slop).contains(point);
+        if hit(&layout.back) {
+            return Some(WizardAction::Back);
+        } else if hit(&layout.play) {
+            return self.play();
+        } else if hit(&layout.test) {
+            self.test();
+        }
+        None
+    }
+
+    /// Handle a key press: Enter tests, then joins once the test passed, and Escape goes back
+    pub(crate) fn key(&mut self, keycode: KeyCode) -> Option<WizardAction> {
+        match keycode {
+            KeyCode::Escape => Some(WizardAction::Back),
+            KeyCode::Back => {
+                if let Side::Guest { typed, tested, .. } = &mut self.side {
+                    typed.pop();
+                    *tested = None;
+                }
+                None
+            }
+            KeyCode::Return | KeyCode::NumpadEnter => match (&self.side, self.ready()) {
+                (Side::Guest { .. }, false) => {
+                    self.test();
+                    None
+                }
+                _ => self.play(),
+            },
+            _ => None,
+        }
+    }
+
+    /// Typed characters go into the guest's field, a changed code needs testing again.
+    /// Spaces are kept, so the code can be typed grouped like the host shows it.
+    pub(crate) fn text_input(&mut self, character: char) {
+        if let Side::Guest { typed, tested, .. } = &mut self.side {
+            if !character.is_control() {
+                typed.push(character);
+                *tested = None;
+            }
+        }
+    }
+}